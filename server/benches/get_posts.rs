@@ -0,0 +1,114 @@
+//! Benchmarks for the `GetPosts` hot path: the front page query, and serializing its response.
+//!
+//! Needs the same database as the tests, ie `cargo bench --bench get_posts`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use lemmy_server::api::post::{GetPosts, GetPostsResponse};
+use lemmy_server::api::{Oper, Perform};
+use lemmy_server::db::community::{Community, CommunityForm};
+use lemmy_server::db::post::{Post, PostForm};
+use lemmy_server::db::user::{UserForm, User_};
+use lemmy_server::db::{establish_unpooled_connection, Crud, ListingType, SortType};
+use lemmy_server::routes::api::to_json_body;
+
+const PAGE_SIZE: i64 = 20;
+
+fn front_page() -> GetPosts {
+  serde_json::from_value(serde_json::json!({
+    "type_": "All",
+    "sort": "Hot",
+    "page": 1,
+    "limit": PAGE_SIZE,
+  }))
+  .unwrap()
+}
+
+fn get_posts(c: &mut Criterion) {
+  let conn = establish_unpooled_connection();
+
+  let new_user = UserForm {
+    name: "bench_get_posts".into(),
+    fedi_name: "rrf".into(),
+    preferred_username: None,
+    password_encrypted: "nope".into(),
+    email: None,
+    matrix_user_id: None,
+    avatar: None,
+    updated: None,
+    admin: false,
+    banned: false,
+    show_nsfw: false,
+    theme: "darkly".into(),
+    default_sort_type: SortType::Hot as i16,
+    default_listing_type: ListingType::Subscribed as i16,
+    lang: "browser".into(),
+    show_avatars: true,
+    send_notifications_to_email: false,
+  };
+  let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+  let new_community = CommunityForm {
+    name: "bench_get_posts".into(),
+    title: "GetPosts benchmark".into(),
+    description: None,
+    creator_id: inserted_user.id,
+    category_id: 1,
+    removed: None,
+    deleted: None,
+    updated: None,
+    nsfw: false,
+  };
+  let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+  // A full page of posts, so the serialization numbers reflect a real front page
+  for i in 0..PAGE_SIZE {
+    let new_post = PostForm {
+      name: format!(
+        "A benchmark post with a reasonably long title, number {}",
+        i
+      ),
+      url: Some("https://example.com/some/linked/article".into()),
+      body: Some("Some markdown body text, as most text posts have. ".repeat(10)),
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+    };
+    Post::create(&conn, &new_post).unwrap();
+  }
+
+  c.bench_function("get_posts_perform", |b| {
+    b.iter(|| {
+      let response: GetPostsResponse = Oper::new(front_page()).perform(&conn).unwrap();
+      response
+    })
+  });
+
+  let response: GetPostsResponse = Oper::new(front_page()).perform(&conn).unwrap();
+
+  c.bench_function("get_posts_serialize_to_vec", |b| {
+    b.iter(|| serde_json::to_vec(&response).unwrap())
+  });
+
+  c.bench_function("get_posts_serialize_presized", |b| {
+    b.iter(|| to_json_body(&response).unwrap())
+  });
+
+  // Deleting the community cascades to its posts
+  Community::delete(&conn, inserted_community.id).unwrap();
+  User_::delete(&conn, inserted_user.id).unwrap();
+}
+
+criterion_group!(benches, get_posts);
+criterion_main!(benches);
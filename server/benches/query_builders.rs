@@ -0,0 +1,217 @@
+//! Benchmarks for `PostQueryBuilder` and `CommentQueryBuilder`, across sorts and filters.
+//!
+//! These run against seeded data, so point `LEMMY_DATABASE_URL` at a database filled with
+//! `lemmy_server admin seed` (the default seed, `--seed 0`). If it hasn't been seeded yet, a small
+//! data set is seeded first, which takes a few minutes.
+//!
+//! Besides criterion's own comparison against the last run, every query has to stay within a
+//! budget, so a plan regression fails the run instead of only showing up as a slower number.
+//! `LEMMY_BENCH_BUDGET_FACTOR` scales the budgets, for slower machines.
+//!
+//! `cargo bench --bench query_builders`
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use diesel::PgConnection;
+use lemmy_server::admin::seed::{seed, SeedConfig};
+use lemmy_server::db::comment_view::CommentQueryBuilder;
+use lemmy_server::db::community::Community;
+use lemmy_server::db::establish_unpooled_connection;
+use lemmy_server::db::ids::{CommunityId, PostId, UserId};
+use lemmy_server::db::post_view::PostQueryBuilder;
+use lemmy_server::db::user::User_;
+use lemmy_server::db::{ListingQueryBuilder, ListingType, SortType, ViewerQueryBuilder};
+use std::env;
+use std::time::{Duration, Instant};
+
+/// The longest a page of posts may take, at the median.
+const POST_LIST_BUDGET: Duration = Duration::from_millis(50);
+/// The longest a page of comments may take, at the median.
+const COMMENT_LIST_BUDGET: Duration = Duration::from_millis(100);
+
+const PAGE_SIZE: i64 = 20;
+
+/// The seeded rows the queries are filtered by.
+struct Seeded {
+  user_id: UserId,
+  community_id: CommunityId,
+  post_id: PostId,
+}
+
+fn seeded(conn: &PgConnection) -> Seeded {
+  let user = match User_::read_from_name(conn, "seed0_user_0".into()) {
+    Ok(user) => user,
+    Err(_) => {
+      seed(
+        conn,
+        &SeedConfig {
+          users: 200,
+          communities: 5,
+          posts: 2000,
+          days: 30,
+          seed: 0,
+        },
+      )
+      .unwrap();
+      User_::read_from_name(conn, "seed0_user_0".into()).unwrap()
+    }
+  };
+  let community = Community::read_from_name(conn, "seed0_community_0".into()).unwrap();
+
+  // The post with the most votes, which also tends to have the biggest comment tree
+  let post = PostQueryBuilder::create(conn)
+    .sort(&SortType::TopAll)
+    .for_community_id(CommunityId(community.id))
+    .limit(1)
+    .list()
+    .unwrap()
+    .remove(0);
+
+  Seeded {
+    user_id: UserId(user.id),
+    community_id: CommunityId(community.id),
+    post_id: PostId(post.id),
+  }
+}
+
+/// Panics if the median of a few runs of `query` is over `budget`.
+fn assert_within_budget<F: FnMut()>(name: &str, budget: Duration, mut query: F) {
+  let factor: f64 = env::var("LEMMY_BENCH_BUDGET_FACTOR")
+    .ok()
+    .and_then(|f| f.parse().ok())
+    .unwrap_or(1.0);
+  let budget = budget.mul_f64(factor);
+
+  let mut times: Vec<Duration> = (0..11)
+    .map(|_| {
+      let start = Instant::now();
+      query();
+      start.elapsed()
+    })
+    .collect();
+  times.sort();
+  let median = times[times.len() / 2];
+
+  assert!(
+    median <= budget,
+    "{} took {:?} at the median, over its budget of {:?}",
+    name,
+    median,
+    budget
+  );
+}
+
+fn sorts() -> Vec<SortType> {
+  vec![
+    SortType::Hot,
+    SortType::New,
+    SortType::TopDay,
+    SortType::TopWeek,
+    SortType::TopAll,
+  ]
+}
+
+fn post_list(c: &mut Criterion) {
+  let conn = establish_unpooled_connection();
+  let seeded = seeded(&conn);
+  let mut group = c.benchmark_group("post_list");
+
+  for sort in sorts() {
+    let all = || {
+      PostQueryBuilder::create(&conn)
+        .listing_type(ListingType::All)
+        .sort(&sort)
+        .limit(PAGE_SIZE)
+        .list()
+        .unwrap()
+    };
+    let subscribed = || {
+      PostQueryBuilder::create(&conn)
+        .listing_type(ListingType::Subscribed)
+        .sort(&sort)
+        .my_user_id(seeded.user_id)
+        .limit(PAGE_SIZE)
+        .list()
+        .unwrap()
+    };
+    let community = || {
+      PostQueryBuilder::create(&conn)
+        .listing_type(ListingType::Community)
+        .sort(&sort)
+        .for_community_id(seeded.community_id)
+        .my_user_id(seeded.user_id)
+        .limit(PAGE_SIZE)
+        .list()
+        .unwrap()
+    };
+
+    for (filter, query) in &[
+      ("all", &all as &dyn Fn() -> _),
+      ("subscribed", &subscribed),
+      ("community", &community),
+    ] {
+      let name = format!("{}/{:?}", filter, sort);
+      group.bench_function(&name, |b| b.iter(query));
+      assert_within_budget(&format!("post_list/{}", name), POST_LIST_BUDGET, || {
+        query();
+      });
+    }
+  }
+
+  group.finish();
+}
+
+fn comment_list(c: &mut Criterion) {
+  let conn = establish_unpooled_connection();
+  let seeded = seeded(&conn);
+  let mut group = c.benchmark_group("comment_list");
+
+  for sort in sorts() {
+    // A whole post's comments, like GetPost loads them
+    let post = || {
+      CommentQueryBuilder::create(&conn)
+        .for_post_id(seeded.post_id)
+        .my_user_id(seeded.user_id)
+        .sort(&sort)
+        .limit(9999)
+        .list()
+        .unwrap()
+    };
+    let all = || {
+      CommentQueryBuilder::create(&conn)
+        .listing_type(ListingType::All)
+        .sort(&sort)
+        .limit(PAGE_SIZE)
+        .list()
+        .unwrap()
+    };
+    let creator = || {
+      CommentQueryBuilder::create(&conn)
+        .for_creator_id(seeded.user_id)
+        .sort(&sort)
+        .limit(PAGE_SIZE)
+        .list()
+        .unwrap()
+    };
+
+    for (filter, query) in &[
+      ("post", &post as &dyn Fn() -> _),
+      ("all", &all),
+      ("creator", &creator),
+    ] {
+      let name = format!("{}/{:?}", filter, sort);
+      group.bench_function(&name, |b| b.iter(query));
+      assert_within_budget(
+        &format!("comment_list/{}", name),
+        COMMENT_LIST_BUDGET,
+        || {
+          query();
+        },
+      );
+    }
+  }
+
+  group.finish();
+}
+
+criterion_group!(benches, post_list, comment_list);
+criterion_main!(benches);
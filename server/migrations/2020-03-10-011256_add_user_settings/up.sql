@@ -0,0 +1,2 @@
+-- Client preferences that don't warrant their own column
+alter table user_ add column settings jsonb not null default '{}'::jsonb;
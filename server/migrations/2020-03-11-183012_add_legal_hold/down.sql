@@ -0,0 +1,3 @@
+drop table mod_legal_hold;
+alter table post drop column legal_hold;
+alter table user_ drop column legal_hold;
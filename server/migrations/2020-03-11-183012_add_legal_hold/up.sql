@@ -0,0 +1,13 @@
+-- Content on legal hold can't be overwritten or purged until the hold is released
+alter table user_ add column legal_hold boolean default false not null;
+alter table post add column legal_hold boolean default false not null;
+
+create table mod_legal_hold (
+  id serial primary key,
+  mod_user_id int references user_ on update cascade on delete cascade not null,
+  other_user_id int references user_ on update cascade on delete cascade,
+  post_id int references post on update cascade on delete cascade,
+  reason text,
+  held boolean default true,
+  when_ timestamp not null default now()
+);
@@ -0,0 +1 @@
+drop table ip_block;
@@ -0,0 +1,16 @@
+-- Admin managed blocklists of networks and autonomous systems, checked at registration and
+-- optionally when posting
+create table ip_block (
+  id serial primary key,
+  cidr text unique,
+  asn int unique,
+  reason text,
+  block_posting boolean default false not null,
+  hits int default 0 not null,
+  last_hit timestamp,
+  creator_id int references user_ on update cascade on delete cascade not null,
+  published timestamp not null default now(),
+  check ((cidr is null) <> (asn is null))
+);
+
+create index idx_ip_block_cidr on ip_block using gist ((cidr::cidr) inet_ops);
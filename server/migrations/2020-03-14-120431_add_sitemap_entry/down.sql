@@ -0,0 +1 @@
+drop view sitemap_entry;
@@ -0,0 +1,19 @@
+-- The public posts and communities, for the sitemap
+create view sitemap_entry as
+select
+  'community'::text as kind,
+  c.id,
+  c.name,
+  coalesce(c.updated, c.published) as lastmod
+from community c
+where not c.removed and not c.deleted and not c.nsfw
+union all
+select
+  'post'::text as kind,
+  p.id,
+  null as name,
+  coalesce(p.updated, p.published) as lastmod
+from post p
+join community c on p.community_id = c.id
+where not p.removed and not p.deleted and not p.nsfw
+  and not c.removed and not c.deleted and not c.nsfw;
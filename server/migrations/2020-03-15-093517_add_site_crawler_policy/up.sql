@@ -0,0 +1,15 @@
+-- Crawler policy: a custom robots.txt, and a noindex mode which keeps search engines out entirely
+alter table site add column noindex boolean default false not null;
+alter table site add column robots_txt text;
+
+drop view site_view;
+
+create view site_view as 
+select *,
+(select name from user_ u where s.creator_id = u.id) as creator_name,
+(select avatar from user_ u where s.creator_id = u.id) as creator_avatar,
+(select count(*) from user_) as number_of_users,
+(select count(*) from post) as number_of_posts,
+(select count(*) from comment) as number_of_comments,
+(select count(*) from community) as number_of_communities
+from site s;
@@ -0,0 +1,2 @@
+-- Profiles which only logged in users can see
+alter table user_ add column hide_profile boolean default false not null;
@@ -0,0 +1,22 @@
+drop view sitemap_entry;
+create view sitemap_entry as
+select
+  'community'::text as kind,
+  c.id,
+  c.name,
+  coalesce(c.updated, c.published) as lastmod
+from community c
+where not c.removed and not c.deleted and not c.nsfw
+union all
+select
+  'post'::text as kind,
+  p.id,
+  null as name,
+  coalesce(p.updated, p.published) as lastmod
+from post p
+join community c on p.community_id = c.id
+where not p.removed and not p.deleted and not p.nsfw
+  and not c.removed and not c.deleted and not c.nsfw;
+
+drop table post_slug_history;
+alter table post drop column slug;
@@ -0,0 +1,34 @@
+-- The readable part of post urls, ie /post/1234/my-post-title
+alter table post add column slug text default '' not null;
+
+update post
+set slug = left(trim(both '-' from regexp_replace(lower(name), '[^[:alnum:]]+', '-', 'g')), 60);
+
+-- The slugs a post had before its title was edited, so that links using them still redirect
+create table post_slug_history (
+  id serial primary key,
+  post_id int references post on update cascade on delete cascade not null,
+  slug text not null,
+  published timestamp not null default now(),
+  unique (post_id, slug)
+);
+
+drop view sitemap_entry;
+create view sitemap_entry as
+select
+  'community'::text as kind,
+  c.id,
+  c.name::text,
+  coalesce(c.updated, c.published) as lastmod
+from community c
+where not c.removed and not c.deleted and not c.nsfw
+union all
+select
+  'post'::text as kind,
+  p.id,
+  p.slug as name,
+  coalesce(p.updated, p.published) as lastmod
+from post p
+join community c on p.community_id = c.id
+where not p.removed and not p.deleted and not p.nsfw
+  and not c.removed and not c.deleted and not c.nsfw;
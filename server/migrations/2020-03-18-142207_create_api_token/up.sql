@@ -0,0 +1,13 @@
+-- Personal access tokens, for bots and scripts. The token itself is a jwt naming its row, so
+-- deleting the row revokes it.
+create table api_token (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  name varchar(100) not null,
+  scopes text[] not null,
+  rate_limit_multiplier real default 1 not null,
+  last_used timestamp,
+  published timestamp not null default now()
+);
+
+create index idx_api_token_user on api_token (user_id);
@@ -0,0 +1,3 @@
+alter table api_token drop column oauth_client_id;
+drop table oauth_code;
+drop table oauth_client;
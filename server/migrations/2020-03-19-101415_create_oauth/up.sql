@@ -0,0 +1,26 @@
+-- Third party apps, which get api tokens through the oauth2 authorization code flow
+create table oauth_client (
+  id serial primary key,
+  client_id text unique not null,
+  client_secret_hash text not null,
+  name varchar(100) not null,
+  redirect_uris text[] not null,
+  creator_id int references user_ on update cascade on delete cascade not null,
+  published timestamp not null default now()
+);
+
+-- Codes a user has approved, until the app exchanges them for a token
+create table oauth_code (
+  id serial primary key,
+  code_hash text unique not null,
+  oauth_client_id int references oauth_client on update cascade on delete cascade not null,
+  user_id int references user_ on update cascade on delete cascade not null,
+  scopes text[] not null,
+  redirect_uri text not null,
+  code_challenge text,
+  expires timestamp not null,
+  published timestamp not null default now()
+);
+
+-- Deleting an app revokes its tokens
+alter table api_token add column oauth_client_id int references oauth_client on update cascade on delete cascade;
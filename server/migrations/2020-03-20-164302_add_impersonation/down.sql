@@ -0,0 +1,3 @@
+drop table mod_impersonate;
+alter table api_token drop column impersonator_id;
+alter table api_token drop column expires;
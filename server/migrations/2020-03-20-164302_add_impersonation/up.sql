@@ -0,0 +1,12 @@
+-- Read only tokens admins use to see the site as a user does
+alter table api_token add column impersonator_id int references user_ on update cascade on delete cascade;
+alter table api_token add column expires timestamp;
+
+create table mod_impersonate (
+  id serial primary key,
+  mod_user_id int references user_ on update cascade on delete cascade not null,
+  other_user_id int references user_ on update cascade on delete cascade not null,
+  reason text,
+  revoked boolean default false not null,
+  when_ timestamp not null default now()
+);
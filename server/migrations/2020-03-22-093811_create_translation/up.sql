@@ -0,0 +1,14 @@
+-- Cached translations of posts and comments, one per object and language
+create table translation (
+  id serial primary key,
+  post_id int references post on update cascade on delete cascade,
+  comment_id int references comment on update cascade on delete cascade,
+  lang varchar(20) not null,
+  name text,
+  content text,
+  published timestamp not null default now(),
+  check ((post_id is null) <> (comment_id is null))
+);
+
+create unique index idx_translation_post_lang on translation (post_id, lang) where post_id is not null;
+create unique index idx_translation_comment_lang on translation (comment_id, lang) where comment_id is not null;
@@ -0,0 +1,9 @@
+-- Activities the shared inbox rejected, counted by instance and reason
+create table inbox_rejection (
+  id serial primary key,
+  domain text not null,
+  reason text not null,
+  count int default 1 not null,
+  last_rejected timestamp not null default now(),
+  unique (domain, reason)
+);
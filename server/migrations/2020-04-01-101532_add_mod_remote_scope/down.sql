@@ -0,0 +1,33 @@
+drop view mod_remove_post_view;
+drop view mod_remove_comment_view;
+drop view mod_ban_view;
+
+alter table mod_remove_post drop column remote_scope;
+alter table mod_remove_comment drop column remote_scope;
+alter table mod_ban drop column remote_scope;
+
+create view mod_remove_post_view as 
+select mrp.*,
+(select name from user_ u where mrp.mod_user_id = u.id) as mod_user_name,
+(select name from post p where mrp.post_id = p.id) as post_name,
+(select c.id from post p, community c where mrp.post_id = p.id and p.community_id = c.id) as community_id,
+(select c.name from post p, community c where mrp.post_id = p.id and p.community_id = c.id) as community_name
+from mod_remove_post mrp;
+
+create view mod_remove_comment_view as 
+select mrc.*,
+(select name from user_ u where mrc.mod_user_id = u.id) as mod_user_name,
+(select c.id from comment c where mrc.comment_id = c.id) as comment_user_id,
+(select name from user_ u, comment c where mrc.comment_id = c.id and u.id = c.creator_id) as comment_user_name,
+(select content from comment c where mrc.comment_id = c.id) as comment_content,
+(select p.id from post p, comment c where mrc.comment_id = c.id and c.post_id = p.id) as post_id,
+(select p.name from post p, comment c where mrc.comment_id = c.id and c.post_id = p.id) as post_name,
+(select co.id from comment c, post p, community co where mrc.comment_id = c.id and c.post_id = p.id and p.community_id = co.id) as community_id, 
+(select co.name from comment c, post p, community co where mrc.comment_id = c.id and c.post_id = p.id and p.community_id = co.id) as community_name
+from mod_remove_comment mrc;
+
+create view mod_ban_view as 
+select mb.*,
+(select name from user_ u where mb.mod_user_id = u.id) as mod_user_name,
+(select name from user_ u where mb.other_user_id = u.id) as other_user_name
+from mod_ban mb;
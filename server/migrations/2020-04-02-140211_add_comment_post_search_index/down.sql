@@ -0,0 +1,4 @@
+drop index idx_comment_post_content_trgm;
+
+drop extension if exists btree_gin;
+drop extension if exists pg_trgm;
@@ -0,0 +1,6 @@
+-- Finding text in the comments of a single post, so that a search on a megathread only looks at
+-- that post's comments. btree_gin lets the post id go in the same gin index as the trigrams.
+create extension if not exists pg_trgm;
+create extension if not exists btree_gin;
+
+create index idx_comment_post_content_trgm on comment using gin (post_id, content gin_trgm_ops);
@@ -0,0 +1,8 @@
+alter table user_ drop column hide_activity;
+
+drop trigger user_activity_day_comment on comment;
+drop function user_activity_day_comment();
+drop trigger user_activity_day_post on post;
+drop function user_activity_day_post();
+
+drop table user_activity_day;
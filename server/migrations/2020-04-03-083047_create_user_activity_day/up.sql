@@ -0,0 +1,64 @@
+-- Posts and comments per user and day, for the activity heatmaps on profiles. Kept up to date by
+-- triggers, so the heatmap doesn't have to count a year of posts and comments each time.
+create table user_activity_day (
+  user_id int references user_ on update cascade on delete cascade not null,
+  day date not null,
+  posts int default 0 not null,
+  comments int default 0 not null,
+  primary key (user_id, day)
+);
+
+insert into user_activity_day (user_id, day, posts, comments)
+select creator_id, day, sum(posts), sum(comments)
+from (
+  select creator_id, published::date as day, 1 as posts, 0 as comments from post
+  union all
+  select creator_id, published::date as day, 0 as posts, 1 as comments from comment
+) activity
+where day > now() - interval '1 year'
+group by creator_id, day;
+
+create or replace function user_activity_day_post()
+returns trigger language plpgsql
+as $$
+begin
+  if (TG_OP = 'INSERT') then
+    insert into user_activity_day (user_id, day, posts)
+    values (NEW.creator_id, NEW.published::date, 1)
+    on conflict (user_id, day) do update set posts = user_activity_day.posts + 1;
+  elsif (TG_OP = 'DELETE') then
+    update user_activity_day set posts = posts - 1
+    where user_id = OLD.creator_id and day = OLD.published::date;
+  end if;
+  return null;
+end $$;
+
+create trigger user_activity_day_post
+after insert or delete
+on post
+for each row
+execute procedure user_activity_day_post();
+
+create or replace function user_activity_day_comment()
+returns trigger language plpgsql
+as $$
+begin
+  if (TG_OP = 'INSERT') then
+    insert into user_activity_day (user_id, day, comments)
+    values (NEW.creator_id, NEW.published::date, 1)
+    on conflict (user_id, day) do update set comments = user_activity_day.comments + 1;
+  elsif (TG_OP = 'DELETE') then
+    update user_activity_day set comments = comments - 1
+    where user_id = OLD.creator_id and day = OLD.published::date;
+  end if;
+  return null;
+end $$;
+
+create trigger user_activity_day_comment
+after insert or delete
+on comment
+for each row
+execute procedure user_activity_day_comment();
+
+-- Users who'd rather not show their heatmap
+alter table user_ add column hide_activity boolean default false not null;
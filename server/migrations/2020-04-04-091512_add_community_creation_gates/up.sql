@@ -0,0 +1,27 @@
+-- Who can create communities: only admins, or users whose accounts are old enough and who have
+-- enough karma. 0 turns a requirement off.
+alter table site add column community_creation_admin_only boolean default false not null;
+alter table site add column community_creation_min_account_age_days int default 0 not null;
+alter table site add column community_creation_min_karma int default 0 not null;
+
+-- Names that only admins can create communities with. They're stored lowercase.
+create table reserved_community_name (
+  id serial primary key,
+  name varchar(20) not null unique,
+  reason text,
+  creator_id int references user_ on update cascade on delete cascade not null,
+  published timestamp not null default now()
+);
+
+-- The view is recreated so that it picks up the new columns
+drop view site_view;
+
+create view site_view as 
+select *,
+(select name from user_ u where s.creator_id = u.id) as creator_name,
+(select avatar from user_ u where s.creator_id = u.id) as creator_avatar,
+(select count(*) from user_) as number_of_users,
+(select count(*) from post) as number_of_posts,
+(select count(*) from comment) as number_of_comments,
+(select count(*) from community) as number_of_communities
+from site s;
@@ -0,0 +1 @@
+drop table consistency_check;
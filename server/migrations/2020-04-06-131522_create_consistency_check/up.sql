@@ -0,0 +1,11 @@
+-- The results of the consistency audits, one row per check and audit, with all the rows of an
+-- audit sharing its published time
+create table consistency_check (
+  id serial primary key,
+  name text not null,
+  found bigint not null,
+  repaired boolean not null,
+  published timestamp not null default now()
+);
+
+create index idx_consistency_check_published on consistency_check (published);
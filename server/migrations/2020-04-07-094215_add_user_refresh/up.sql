@@ -0,0 +1,35 @@
+-- When remote users were last fetched from their instance, and how refreshing them is failing,
+-- so that a copy that can't be refreshed is still served while the refresh is retried
+alter table user_ add column last_refreshed timestamp;
+alter table user_ add column refresh_failures int default 0 not null;
+alter table user_ add column refresh_retry_at timestamp;
+
+update user_ set last_refreshed = published where actor_id is not null;
+
+create index idx_user_refresh_retry_at on user_ (refresh_retry_at) where refresh_retry_at is not null;
+
+drop view user_view cascade;
+create view user_view as 
+select 
+u.id,
+u.name,
+u.avatar,
+u.email,
+u.matrix_user_id,
+u.fedi_name,
+u.admin,
+u.banned,
+u.show_avatars,
+u.send_notifications_to_email,
+u.published,
+u.last_refreshed,
+u.refresh_failures,
+(select count(*) from post p where p.creator_id = u.id) as number_of_posts,
+(select coalesce(sum(score), 0) from post p, post_like pl where u.id = p.creator_id and p.id = pl.post_id) as post_score,
+(select count(*) from comment c where c.creator_id = u.id) as number_of_comments,
+(select coalesce(sum(score), 0) from comment c, comment_like cl where u.id = c.creator_id and c.id = cl.comment_id) as comment_score
+from user_ u;
+
+create materialized view user_mview as select * from user_view;
+
+create unique index idx_user_mview_id on user_mview (id);
@@ -0,0 +1,6 @@
+drop function rising_rank(int);
+drop trigger post_activity_hour_comment on comment;
+drop function post_activity_hour_comment();
+drop trigger post_activity_hour_like on post_like;
+drop function post_activity_hour_like();
+drop table post_activity_hour;
@@ -0,0 +1,72 @@
+-- The votes and comments posts got per hour, for the Rising sort. Kept up to date by triggers, and
+-- only kept for a day.
+create table post_activity_hour (
+  post_id int references post on update cascade on delete cascade not null,
+  hour timestamp not null,
+  score int default 0 not null,
+  comments int default 0 not null,
+  primary key (post_id, hour)
+);
+
+create index idx_post_activity_hour_hour on post_activity_hour (hour);
+
+insert into post_activity_hour (post_id, hour, score, comments)
+select post_id, hour, sum(score), sum(comments)
+from (
+  select post_id, date_trunc('hour', published) as hour, score, 0 as comments from post_like
+  union all
+  select post_id, date_trunc('hour', published) as hour, 0 as score, 1 as comments from comment
+) activity
+where hour > now() - interval '1 day'
+group by post_id, hour;
+
+create or replace function post_activity_hour_like()
+returns trigger language plpgsql
+as $$
+begin
+  if (TG_OP = 'INSERT') then
+    insert into post_activity_hour (post_id, hour, score)
+    values (NEW.post_id, date_trunc('hour', now()::timestamp), NEW.score)
+    on conflict (post_id, hour) do update set score = post_activity_hour.score + NEW.score;
+  elsif (TG_OP = 'DELETE') then
+    -- A vote being taken back or changed counts against the current hour, where the new one goes
+    insert into post_activity_hour (post_id, hour, score)
+    values (OLD.post_id, date_trunc('hour', now()::timestamp), -OLD.score)
+    on conflict (post_id, hour) do update set score = post_activity_hour.score - OLD.score;
+  end if;
+  return null;
+end $$;
+
+create trigger post_activity_hour_like
+after insert or delete
+on post_like
+for each row
+execute procedure post_activity_hour_like();
+
+create or replace function post_activity_hour_comment()
+returns trigger language plpgsql
+as $$
+begin
+  insert into post_activity_hour (post_id, hour, comments)
+  values (NEW.post_id, date_trunc('hour', now()::timestamp), 1)
+  on conflict (post_id, hour) do update set comments = post_activity_hour.comments + 1;
+  return null;
+end $$;
+
+create trigger post_activity_hour_comment
+after insert
+on comment
+for each row
+execute procedure post_activity_hour_comment();
+
+-- How fast a post is picking up votes and comments: those of the last 6 hours, with each hour
+-- counting for less the longer ago it was
+create or replace function rising_rank(for_post_id int)
+returns float8 language sql stable as $$
+  select coalesce(sum(
+    (pah.score + pah.comments)::float8
+    / (extract(epoch from (now()::timestamp - pah.hour)) / 3600 + 1)
+  ), 0)
+  from post_activity_hour pah
+  where pah.post_id = for_post_id and pah.hour > now()::timestamp - interval '6 hours'
+$$;
@@ -0,0 +1,2 @@
+alter table user_ drop column blended_discovery_percent;
+alter table user_ drop column blended_discovery_local;
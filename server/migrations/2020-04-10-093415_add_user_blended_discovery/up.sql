@@ -0,0 +1,4 @@
+-- How the Blended listing mixes posts from outside a user's subscriptions in with theirs: the
+-- percentage of the listing they make up, and whether they're only local posts
+alter table user_ add column blended_discovery_percent smallint default 20 not null;
+alter table user_ add column blended_discovery_local boolean default false not null;
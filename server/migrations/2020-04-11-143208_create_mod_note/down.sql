@@ -0,0 +1,4 @@
+drop view mod_note_revision_view;
+drop view mod_note_view;
+drop table mod_note_revision;
+drop table mod_note;
@@ -0,0 +1,37 @@
+-- Notes a community's moderators keep about users, which only they and the admins can see
+create table mod_note (
+  id serial primary key,
+  community_id int references community on update cascade on delete cascade not null,
+  user_id int references user_ on update cascade on delete cascade not null,
+  creator_id int references user_ on update cascade on delete cascade not null,
+  content text not null,
+  deleted boolean default false not null,
+  published timestamp not null default now(),
+  updated timestamp
+);
+
+create index idx_mod_note_user_community on mod_note (user_id, community_id);
+
+-- Every version of every note, including deleting it, and who wrote it
+create table mod_note_revision (
+  id serial primary key,
+  mod_note_id int references mod_note on update cascade on delete cascade not null,
+  editor_id int references user_ on update cascade on delete cascade not null,
+  content text not null,
+  deleted boolean not null,
+  published timestamp not null default now()
+);
+
+create index idx_mod_note_revision_mod_note on mod_note_revision (mod_note_id);
+
+create view mod_note_view as
+select mn.*,
+(select name from user_ u where mn.user_id = u.id) as user_name,
+(select name from user_ u where mn.creator_id = u.id) as creator_name,
+(select name from community c where mn.community_id = c.id) as community_name
+from mod_note mn;
+
+create view mod_note_revision_view as
+select mnr.*,
+(select name from user_ u where mnr.editor_id = u.id) as editor_name
+from mod_note_revision mnr;
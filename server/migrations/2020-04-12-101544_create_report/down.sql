@@ -0,0 +1,4 @@
+alter table community drop column report_auto_remove_threshold;
+
+drop view report_view;
+drop table report;
@@ -0,0 +1,33 @@
+-- Users reporting posts and comments to the community's moderators. Someone can only have one
+-- open report of something, and resolving one resolves all of them.
+create table report (
+  id serial primary key,
+  creator_id int references user_ on update cascade on delete cascade not null,
+  community_id int references community on update cascade on delete cascade not null,
+  post_id int references post on update cascade on delete cascade,
+  comment_id int references comment on update cascade on delete cascade,
+  reason text not null,
+  resolved boolean default false not null,
+  resolver_id int references user_ on update cascade on delete set null,
+  published timestamp not null default now(),
+  updated timestamp,
+  check ((post_id is null) <> (comment_id is null))
+);
+
+create unique index idx_report_creator_post on report (creator_id, post_id)
+where post_id is not null and not resolved;
+create unique index idx_report_creator_comment on report (creator_id, comment_id)
+where comment_id is not null and not resolved;
+create index idx_report_community_unresolved on report (community_id) where not resolved;
+
+create view report_view as
+select r.*,
+(select name from user_ u where r.creator_id = u.id) as creator_name,
+(select name from community c where r.community_id = c.id) as community_name,
+(select name from post p where r.post_id = p.id) as post_name,
+(select content from comment co where r.comment_id = co.id) as comment_content
+from report r;
+
+-- How many users reporting a post or comment removes it, until a moderator looks at it. Null to
+-- never remove them automatically.
+alter table community add column report_auto_remove_threshold int;
@@ -0,0 +1,38 @@
+-- Notices telling users that a moderator removed their post or comment, or banned them, with the
+-- reason. They're kept apart from private messages so that they can't be replied to.
+create table moderation_notice (
+  id serial primary key,
+  recipient_id int references user_ on update cascade on delete cascade not null,
+  mod_user_id int references user_ on update cascade on delete cascade not null,
+  kind smallint not null,
+  community_id int references community on update cascade on delete cascade,
+  post_id int references post on update cascade on delete cascade,
+  comment_id int references comment on update cascade on delete cascade,
+  reason text,
+  content text not null,
+  read boolean default false not null,
+  published timestamp not null default now()
+);
+
+create index idx_moderation_notice_recipient on moderation_notice (recipient_id, published);
+
+-- Whether users get the notices, whether they also get them as a private message from the
+-- moderator, the text of them with {action}, {reason} and {appeal_url} filled in, and where to
+-- appeal
+alter table site add column moderation_notices boolean default true not null;
+alter table site add column moderation_notice_pm boolean default false not null;
+alter table site add column moderation_notice_template text;
+alter table site add column moderation_appeal_url text;
+
+-- The view is recreated so that it picks up the new columns
+drop view site_view;
+
+create view site_view as 
+select *,
+(select name from user_ u where s.creator_id = u.id) as creator_name,
+(select avatar from user_ u where s.creator_id = u.id) as creator_avatar,
+(select count(*) from user_) as number_of_users,
+(select count(*) from post) as number_of_posts,
+(select count(*) from comment) as number_of_comments,
+(select count(*) from community) as number_of_communities
+from site s;
@@ -0,0 +1,87 @@
+-- How users' karma, their post and comment scores, is counted: without votes on their own posts
+-- and comments, with no post or comment counting for more than the cap either way (0 for no cap),
+-- and with votes counting half as much every so many days (0 for no decay)
+alter table site add column karma_exclude_self_votes boolean default false not null;
+alter table site add column karma_cap_per_item int default 0 not null;
+alter table site add column karma_decay_half_life_days int default 0 not null;
+
+-- The view is recreated so that it picks up the new columns
+drop view site_view;
+
+create view site_view as 
+select *,
+(select name from user_ u where s.creator_id = u.id) as creator_name,
+(select avatar from user_ u where s.creator_id = u.id) as creator_avatar,
+(select count(*) from user_) as number_of_users,
+(select count(*) from post) as number_of_posts,
+(select count(*) from comment) as number_of_comments,
+(select count(*) from community) as number_of_communities
+from site s;
+
+-- How much a vote counts towards its creator's karma. Decay goes by whole days, so that karma
+-- only changes once a day without new votes.
+create or replace function karma_vote_weight(voter_id int, voted_user_id int, voted timestamp)
+returns numeric language sql stable as $$
+  select coalesce((
+    select case
+      when s.karma_exclude_self_votes and voter_id = voted_user_id then 0
+      when s.karma_decay_half_life_days > 0
+        then power(0.5, (current_date - voted::date)::numeric / s.karma_decay_half_life_days)
+      else 1
+    end
+    from site s
+  ), 1)
+$$;
+
+-- The karma of a post or comment, capped
+create or replace function karma_capped(karma numeric)
+returns numeric language sql stable as $$
+  select coalesce((
+    select case
+      when s.karma_cap_per_item > 0
+        then greatest(-s.karma_cap_per_item, least(s.karma_cap_per_item, karma))
+      else karma
+    end
+    from site s
+  ), karma)
+$$;
+
+drop view user_view cascade;
+create view user_view as 
+select 
+u.id,
+u.name,
+u.avatar,
+u.email,
+u.matrix_user_id,
+u.fedi_name,
+u.admin,
+u.banned,
+u.show_avatars,
+u.send_notifications_to_email,
+u.published,
+u.last_refreshed,
+u.refresh_failures,
+(select count(*) from post p where p.creator_id = u.id) as number_of_posts,
+(
+  select coalesce(round(sum(karma_capped(pk.karma))), 0)::bigint from (
+    select sum(pl.score * karma_vote_weight(pl.user_id, u.id, pl.published)) as karma
+    from post p, post_like pl
+    where u.id = p.creator_id and p.id = pl.post_id
+    group by p.id
+  ) pk
+) as post_score,
+(select count(*) from comment c where c.creator_id = u.id) as number_of_comments,
+(
+  select coalesce(round(sum(karma_capped(ck.karma))), 0)::bigint from (
+    select sum(cl.score * karma_vote_weight(cl.user_id, u.id, cl.published)) as karma
+    from comment c, comment_like cl
+    where u.id = c.creator_id and c.id = cl.comment_id
+    group by c.id
+  ) ck
+) as comment_score
+from user_ u;
+
+create materialized view user_mview as select * from user_view;
+
+create unique index idx_user_mview_id on user_mview (id);
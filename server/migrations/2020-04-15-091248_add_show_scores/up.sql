@@ -0,0 +1,18 @@
+-- Whether users see the vote scores of posts, comments and users, and whether the site leaves
+-- that up to them (0), shows them to everyone (1), or hides them from everyone (2). Users still
+-- see their own votes.
+alter table user_ add column show_scores boolean default true not null;
+alter table site add column score_visibility smallint default 0 not null;
+
+-- The view is recreated so that it picks up the new column
+drop view site_view;
+
+create view site_view as 
+select *,
+(select name from user_ u where s.creator_id = u.id) as creator_name,
+(select avatar from user_ u where s.creator_id = u.id) as creator_avatar,
+(select count(*) from user_) as number_of_users,
+(select count(*) from post) as number_of_posts,
+(select count(*) from comment) as number_of_comments,
+(select count(*) from community) as number_of_communities
+from site s;
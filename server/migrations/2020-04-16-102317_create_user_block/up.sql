@@ -0,0 +1,8 @@
+-- The users a user blocked, whose posts, comments, mentions and private messages they don't see
+create table user_block (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  target_id int references user_ on update cascade on delete cascade not null,
+  published timestamp not null default now(),
+  unique (user_id, target_id)
+);
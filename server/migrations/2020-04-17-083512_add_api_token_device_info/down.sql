@@ -0,0 +1,3 @@
+alter table api_token drop column device_name;
+alter table api_token drop column client_app;
+alter table api_token drop column last_ip_network;
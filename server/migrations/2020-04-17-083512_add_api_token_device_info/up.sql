@@ -0,0 +1,5 @@
+-- Which device and app a token is for, so users signed in on several can tell their sessions apart.
+-- Only the network of the last IP a token was used from is kept, never the whole address.
+alter table api_token add column device_name varchar(100);
+alter table api_token add column client_app varchar(100);
+alter table api_token add column last_ip_network text;
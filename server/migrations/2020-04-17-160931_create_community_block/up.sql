@@ -0,0 +1,8 @@
+-- The communities a user blocked, whose posts and comments they don't see in their listings
+create table community_block (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  community_id int references community on update cascade on delete cascade not null,
+  published timestamp not null default now(),
+  unique (user_id, community_id)
+);
@@ -0,0 +1,2 @@
+drop function instance_domain(text);
+drop table user_instance_block;
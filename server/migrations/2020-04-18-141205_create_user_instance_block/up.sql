@@ -0,0 +1,14 @@
+-- The remote instances a user blocked, whose posts, comments and communities they don't see
+create table user_instance_block (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  domain text not null,
+  published timestamp not null default now(),
+  unique (user_id, domain)
+);
+
+-- The lowercased host of an activitypub id, or '' for local objects, which have none
+create or replace function instance_domain(url text)
+returns text language sql immutable as $$
+  select coalesce(lower(substring(url from '^https://(?:[^/?#@]*@)?([^/?#:]+)')), '')
+$$;
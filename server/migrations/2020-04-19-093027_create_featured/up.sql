@@ -0,0 +1,12 @@
+-- The posts and comments users pinned to their profiles, in the order they're shown in
+create table featured (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  post_id int references post on update cascade on delete cascade,
+  comment_id int references comment on update cascade on delete cascade,
+  position int not null,
+  published timestamp not null default now(),
+  check ((post_id is null) <> (comment_id is null)),
+  unique (user_id, post_id),
+  unique (user_id, comment_id)
+);
@@ -0,0 +1,8 @@
+drop index idx_post_search_vector;
+drop index idx_comment_search_vector;
+drop trigger post_search_vector on post;
+drop trigger comment_search_vector on comment;
+drop function post_search_vector();
+drop function comment_search_vector();
+alter table post drop column search_vector;
+alter table comment drop column search_vector;
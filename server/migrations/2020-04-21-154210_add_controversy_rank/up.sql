@@ -0,0 +1,12 @@
+-- Rank = (Upvotes + Downvotes) ^ (Fewer / More), so a lot of votes split close to evenly ranks
+-- highest, and anything with no votes on one side ranks 0
+create or replace function controversy_rank(upvotes bigint, downvotes bigint)
+returns float8 language sql immutable as $$
+  select case
+    when coalesce(upvotes, 0) <= 0 or coalesce(downvotes, 0) <= 0 then 0
+    else power(
+      (upvotes + downvotes)::float8,
+      least(upvotes, downvotes)::float8 / greatest(upvotes, downvotes)
+    )
+  end
+$$;
@@ -0,0 +1,20 @@
+-- Posts a community's moderators have made every week or every day, like a weekly discussion thread
+create table scheduled_post (
+  id serial primary key,
+  community_id int references community on update cascade on delete cascade not null,
+  creator_id int references user_ on update cascade on delete cascade not null,
+  name varchar(200) not null,
+  body text,
+  -- 0 is Monday, and null is every day
+  weekday smallint check (weekday between 0 and 6),
+  -- In UTC
+  hour smallint not null check (hour between 0 and 23),
+  -- The last post it made, which gets unstickied when the next one is made
+  last_post_id int references post on update cascade on delete set null,
+  next_run timestamp not null,
+  published timestamp not null default now(),
+  updated timestamp
+);
+
+create index idx_scheduled_post_next_run on scheduled_post (next_run);
+create index idx_scheduled_post_community on scheduled_post (community_id);
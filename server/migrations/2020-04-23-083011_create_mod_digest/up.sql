@@ -0,0 +1,12 @@
+-- Moderators who get a digest of their community's most reported and fastest growing posts
+create table mod_digest (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  community_id int references community on update cascade on delete cascade not null,
+  interval_days smallint not null check (interval_days between 1 and 7),
+  next_send timestamp not null,
+  published timestamp not null default now(),
+  unique (user_id, community_id)
+);
+
+create index idx_mod_digest_next_send on mod_digest (next_send);
@@ -0,0 +1 @@
+drop view modlog_view;
@@ -0,0 +1,48 @@
+-- Every moderator action in one feed. `kind` is which of the mod tables it's from, and
+-- `other_user_id` the user it was done to: the creator of the post, comment or community, or the
+-- user who was banned or added. `applied` is false when the action was undone, like a post being
+-- restored or a mod being removed.
+create view modlog_view as
+select m.*,
+(select name from user_ u where m.mod_user_id = u.id) as mod_user_name,
+(select name from user_ u where m.other_user_id = u.id) as other_user_name,
+(select name from community c where m.community_id = c.id) as community_name,
+(select name from post p where m.post_id = p.id) as post_name
+from (
+  select 0::smallint as kind, mrp.id, mrp.mod_user_id, p.creator_id as other_user_id,
+  p.community_id, mrp.post_id, null::int as comment_id, mrp.reason,
+  coalesce(mrp.removed, true) as applied, null::timestamp as expires, mrp.remote_scope, mrp.when_
+  from mod_remove_post mrp join post p on mrp.post_id = p.id
+  union all
+  select 1::smallint, mlp.id, mlp.mod_user_id, p.creator_id, p.community_id, mlp.post_id, null,
+  null, coalesce(mlp.locked, true), null, null, mlp.when_
+  from mod_lock_post mlp join post p on mlp.post_id = p.id
+  union all
+  select 2::smallint, msp.id, msp.mod_user_id, p.creator_id, p.community_id, msp.post_id, null,
+  null, coalesce(msp.stickied, true), null, null, msp.when_
+  from mod_sticky_post msp join post p on msp.post_id = p.id
+  union all
+  select 3::smallint, mrc.id, mrc.mod_user_id, c.creator_id, p.community_id, c.post_id,
+  mrc.comment_id, mrc.reason, coalesce(mrc.removed, true), null, mrc.remote_scope, mrc.when_
+  from mod_remove_comment mrc join comment c on mrc.comment_id = c.id join post p on c.post_id = p.id
+  union all
+  select 4::smallint, mrco.id, mrco.mod_user_id, co.creator_id, mrco.community_id, null, null,
+  mrco.reason, coalesce(mrco.removed, true), mrco.expires, null, mrco.when_
+  from mod_remove_community mrco join community co on mrco.community_id = co.id
+  union all
+  select 5::smallint, mbc.id, mbc.mod_user_id, mbc.other_user_id, mbc.community_id, null, null,
+  mbc.reason, coalesce(mbc.banned, true), mbc.expires, null, mbc.when_
+  from mod_ban_from_community mbc
+  union all
+  select 6::smallint, mb.id, mb.mod_user_id, mb.other_user_id, null, null, null, mb.reason,
+  coalesce(mb.banned, true), mb.expires, mb.remote_scope, mb.when_
+  from mod_ban mb
+  union all
+  select 7::smallint, mac.id, mac.mod_user_id, mac.other_user_id, mac.community_id, null, null,
+  null, not coalesce(mac.removed, false), null, null, mac.when_
+  from mod_add_community mac
+  union all
+  select 8::smallint, ma.id, ma.mod_user_id, ma.other_user_id, null, null, null, null,
+  not coalesce(ma.removed, false), null, null, ma.when_
+  from mod_add ma
+) m;
@@ -0,0 +1,7 @@
+drop index idx_post_generated;
+drop index idx_comment_generated;
+
+alter table post drop column generated;
+alter table comment drop column generated;
+alter table user_ drop column bot_account;
+alter table user_ drop column show_generated;
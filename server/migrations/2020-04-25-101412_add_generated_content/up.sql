@@ -0,0 +1,10 @@
+-- Posts and comments made by bots or generated by AI, which users can hide. Accounts flagged as
+-- bots have to mark everything they make as generated.
+alter table post add column generated boolean default false not null;
+alter table comment add column generated boolean default false not null;
+alter table user_ add column bot_account boolean default false not null;
+alter table user_ add column show_generated boolean default true not null;
+
+-- Hiding them looks up the few generated ones
+create index idx_post_generated on post (id) where generated;
+create index idx_comment_generated on comment (id) where generated;
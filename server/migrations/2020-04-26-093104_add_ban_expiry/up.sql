@@ -0,0 +1,118 @@
+-- Bans that are lifted at a time, rather than by a mod. Expired bans count as lifted until the
+-- server gets to lifting them.
+alter table community_user_ban add column expires timestamp;
+alter table user_ add column ban_expires timestamp;
+
+drop view community_user_ban_view;
+
+create view community_user_ban_view as 
+select cm.id,
+cm.community_id,
+cm.user_id,
+cm.published,
+cm.expires,
+(select name from user_ u where cm.user_id = u.id) as user_name,
+(select avatar from user_ u where cm.user_id = u.id),
+(select name from community c where cm.community_id = c.id) as community_name
+from community_user_ban cm
+where cm.expires is null or cm.expires > now()::timestamp;
+
+-- The post and comment columns are listed, since the ones added since aren't in the views
+create or replace view post_aggregates_view as
+select        
+p.id,
+p.name,
+p.url,
+p.body,
+p.creator_id,
+p.community_id,
+p.removed,
+p.locked,
+p.published,
+p.updated,
+p.deleted,
+p.nsfw,
+p.stickied,
+p.embed_title,
+p.embed_description,
+p.embed_html,
+p.thumbnail_url,
+p.legal_hold,
+p.slug,
+p.excerpt,
+p.alt_text,
+p.visibility,
+p.ap_id,
+(select u.banned and (u.ban_expires is null or u.ban_expires > now()::timestamp) from user_ u where p.creator_id = u.id) as banned,
+(select cb.id::bool from community_user_ban cb where p.creator_id = cb.user_id and p.community_id = cb.community_id and (cb.expires is null or cb.expires > now()::timestamp)) as banned_from_community,
+(select name from user_ where p.creator_id = user_.id) as creator_name,
+(select avatar from user_ where p.creator_id = user_.id) as creator_avatar,
+(select name from community where p.community_id = community.id) as community_name,
+(select removed from community c where p.community_id = c.id) as community_removed,
+(select deleted from community c where p.community_id = c.id) as community_deleted,
+(select nsfw from community c where p.community_id = c.id) as community_nsfw,
+(select hidden from community c where p.community_id = c.id) as community_hidden,
+coalesce(
+  (
+    select jsonb_agg(jsonb_build_object(
+      'kind', pa.kind,
+      'url', pa.url,
+      'mime_type', pa.mime_type,
+      'name', pa.name,
+      'duration', pa.duration
+    ) order by pa.position)
+    from post_attachment pa where pa.post_id = p.id
+  ),
+  '[]'::jsonb
+) as attachments,
+(select count(*) from comment where comment.post_id = p.id) as number_of_comments,
+coalesce(sum(pl.score), 0) as score,
+count (case when pl.score = 1 then 1 else null end) as upvotes,
+count (case when pl.score = -1 then 1 else null end) as downvotes,
+hot_rank(coalesce(sum(pl.score * vote_weight(pl.user_id)), 0), 
+  (
+    case when (p.published < ('now'::timestamp - '1 month'::interval)) then p.published -- Prevents necro-bumps
+    else greatest(c.recent_comment_time, p.published)
+    end
+  )
+) as hot_rank,
+(
+  case when (p.published < ('now'::timestamp - '1 month'::interval)) then p.published -- Prevents necro-bumps
+  else greatest(c.recent_comment_time, p.published)
+  end
+) as newest_activity_time
+from post p
+left join post_like pl on p.id = pl.post_id
+left join (
+  select post_id, 
+  max(published) as recent_comment_time
+  from comment
+  group by 1
+) c on p.id = c.post_id
+group by p.id, c.recent_comment_time;
+
+create or replace view comment_aggregates_view as
+select        
+c.id,
+c.creator_id,
+c.post_id,
+c.parent_id,
+c.content,
+c.removed,
+c.read,
+c.published,
+c.updated,
+c.deleted,
+(select community_id from post p where p.id = c.post_id),
+(select co.name from post p, community co where p.id = c.post_id and p.community_id = co.id) as community_name,
+(select u.banned and (u.ban_expires is null or u.ban_expires > now()::timestamp) from user_ u where c.creator_id = u.id) as banned,
+(select cb.id::bool from community_user_ban cb, post p where c.creator_id = cb.user_id and p.id = c.post_id and p.community_id = cb.community_id and (cb.expires is null or cb.expires > now()::timestamp)) as banned_from_community,
+(select name from user_ where c.creator_id = user_.id) as creator_name,
+(select avatar from user_ where c.creator_id = user_.id) as creator_avatar,
+coalesce(sum(cl.score), 0) as score,
+count (case when cl.score = 1 then 1 else null end) as upvotes,
+count (case when cl.score = -1 then 1 else null end) as downvotes,
+hot_rank(coalesce(sum(cl.score * vote_weight(cl.user_id)), 0), c.published) as hot_rank
+from comment c
+left join comment_like cl on c.id = cl.comment_id
+group by c.id;
@@ -0,0 +1 @@
+drop view inbox_combined_view;
@@ -0,0 +1,27 @@
+-- A user's replies, mentions and private messages in one feed. `kind` is which of them it is, and
+-- `id` its id in its own table: the reply's comment, the user_mention, or the private_message.
+-- `read` is the reply's, mention's or message's own. Private message content is still encrypted.
+create view inbox_combined_view as
+select i.*,
+u.name as creator_name,
+u.avatar as creator_avatar,
+(select name from post p where i.post_id = p.id) as post_name
+from (
+  select 0::smallint as kind, c.id, c.creator_id, pc.creator_id as recipient_id, c.post_id,
+  c.id as comment_id, c.content, c.read, c.deleted or c.removed as deleted, c.published, c.updated
+  from comment c join comment pc on c.parent_id = pc.id
+  where c.creator_id != pc.creator_id
+  union all
+  select 0::smallint, c.id, c.creator_id, p.creator_id, c.post_id, c.id, c.content, c.read,
+  c.deleted or c.removed, c.published, c.updated
+  from comment c join post p on c.post_id = p.id
+  where c.parent_id is null and c.creator_id != p.creator_id
+  union all
+  select 1::smallint, um.id, c.creator_id, um.recipient_id, c.post_id, c.id, c.content, um.read,
+  c.deleted or c.removed, c.published, c.updated
+  from user_mention um join comment c on um.comment_id = c.id
+  union all
+  select 2::smallint, pm.id, pm.creator_id, pm.recipient_id, null, null, pm.content, pm.read,
+  pm.deleted, pm.published, pm.updated
+  from private_message pm
+) i join user_ u on i.creator_id = u.id;
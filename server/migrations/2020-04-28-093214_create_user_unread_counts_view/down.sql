@@ -0,0 +1 @@
+drop view user_unread_counts_view;
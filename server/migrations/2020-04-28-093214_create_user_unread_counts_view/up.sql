@@ -0,0 +1,17 @@
+-- How many unread replies, mentions and private messages each user has, for the inbox badge. Users
+-- without any don't have a row. It counts what the inbox lists, so it leaves out deleted and removed
+-- items, and the ones from users the recipient blocked.
+create view user_unread_counts_view as
+select
+i.recipient_id as user_id,
+count(*) filter (where i.kind = 0) as unread_replies,
+count(*) filter (where i.kind = 1) as unread_mentions,
+count(*) filter (where i.kind = 2) as unread_private_messages
+from inbox_combined_view i
+where not i.read
+and not i.deleted
+and not exists (
+  select 1 from user_block ub
+  where ub.user_id = i.recipient_id and ub.target_id = i.creator_id
+)
+group by i.recipient_id;
@@ -0,0 +1,4 @@
+drop view saved_items_view;
+alter table post_saved drop column folder_id;
+alter table comment_saved drop column folder_id;
+drop table saved_folder;
@@ -0,0 +1,56 @@
+-- Folders that users sort their saved posts and comments into
+create table saved_folder (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  name varchar(50) not null,
+  published timestamp not null default now(),
+  unique(user_id, name)
+);
+
+-- Deleting a folder leaves its items saved, without a folder
+alter table post_saved add column folder_id int references saved_folder on update cascade on delete set null;
+alter table comment_saved add column folder_id int references saved_folder on update cascade on delete set null;
+
+-- A user's saved posts (kind 0) and comments (kind 1) together, so they sort and page by when they
+-- were saved. The id is the id of the post_saved or comment_saved row.
+create view saved_items_view as
+select
+0::smallint as kind,
+ps.id,
+ps.user_id,
+ps.folder_id,
+ps.published as saved,
+p.id as post_id,
+null::int as comment_id,
+p.name as post_name,
+p.url as post_url,
+null::text as content,
+p.creator_id,
+(select name from user_ u where p.creator_id = u.id) as creator_name,
+p.community_id,
+(select name from community c where p.community_id = c.id) as community_name,
+p.published,
+(p.deleted or p.removed) as deleted
+from post_saved ps
+join post p on ps.post_id = p.id
+union all
+select
+1::smallint as kind,
+cs.id,
+cs.user_id,
+cs.folder_id,
+cs.published as saved,
+p.id as post_id,
+c.id as comment_id,
+p.name as post_name,
+p.url as post_url,
+c.content,
+c.creator_id,
+(select name from user_ u where c.creator_id = u.id) as creator_name,
+p.community_id,
+(select name from community co where p.community_id = co.id) as community_name,
+c.published,
+(c.deleted or c.removed) as deleted
+from comment_saved cs
+join comment c on cs.comment_id = c.id
+join post p on c.post_id = p.id;
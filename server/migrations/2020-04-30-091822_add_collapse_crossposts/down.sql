@@ -0,0 +1,3 @@
+drop index idx_post_normalized_url;
+drop function normalize_url(text);
+alter table user_ drop column collapse_crossposts;
@@ -0,0 +1,24 @@
+-- Whether the All and Subscribed listings collapse the posts of the same link into one
+alter table user_ add column collapse_crossposts boolean default false not null;
+
+-- A link without what doesn't change where it goes, so that the posts of it match: the scheme, a
+-- www., the case of the host, utm_ tracking parameters, the fragment and a trailing slash
+create or replace function normalize_url(url text)
+returns text language sql immutable as $$
+  select coalesce(
+    regexp_replace(
+      rtrim(
+        regexp_replace(
+          lower(substring(url from '^[a-zA-Z][a-zA-Z0-9+.-]*://(?:www\.)?([^/?#]*)'))
+          || coalesce(substring(url from '^[a-zA-Z][a-zA-Z0-9+.-]*://[^/?#]*([^#]*)'), ''),
+          '(?<=[?&])utm_[^&]*&?', '', 'g'
+        ),
+        '?&'
+      ),
+      '/+(?=\?|$)', ''
+    ),
+    url
+  )
+$$;
+
+create index idx_post_normalized_url on post (normalize_url(url)) where url is not null;
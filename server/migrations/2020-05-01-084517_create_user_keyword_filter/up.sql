@@ -0,0 +1,15 @@
+-- The words and regular expressions users muted. The posts and comments with them are left out of
+-- what they see.
+create table user_keyword_filter (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  keyword varchar(100) not null,
+  regex boolean default false not null,
+  -- Words are matched against the posts' and comments' search vectors, so they match the other
+  -- forms of them, without scanning their text
+  query tsquery generated always as (
+    case when regex then null else phraseto_tsquery('english'::regconfig, keyword) end
+  ) stored,
+  published timestamp not null default now(),
+  unique (user_id, keyword)
+);
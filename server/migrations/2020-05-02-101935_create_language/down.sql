@@ -0,0 +1,4 @@
+drop table user_language;
+alter table comment drop column language_id;
+alter table post drop column language_id;
+drop table language;
@@ -0,0 +1,59 @@
+-- The languages posts and comments can be in, by their ISO 639 code
+create table language (
+  id serial primary key,
+  code varchar(3) not null unique,
+  name varchar(30) not null
+);
+
+insert into language (code, name) values
+('en', 'English'),
+('ar', 'Arabic'),
+('bg', 'Bulgarian'),
+('ca', 'Catalan'),
+('cs', 'Czech'),
+('da', 'Danish'),
+('de', 'German'),
+('el', 'Greek'),
+('eo', 'Esperanto'),
+('es', 'Spanish'),
+('eu', 'Basque'),
+('fa', 'Persian'),
+('fi', 'Finnish'),
+('fr', 'French'),
+('ga', 'Irish'),
+('gl', 'Galician'),
+('he', 'Hebrew'),
+('hi', 'Hindi'),
+('hu', 'Hungarian'),
+('id', 'Indonesian'),
+('it', 'Italian'),
+('ja', 'Japanese'),
+('ko', 'Korean'),
+('nl', 'Dutch'),
+('no', 'Norwegian'),
+('pl', 'Polish'),
+('pt', 'Portuguese'),
+('ro', 'Romanian'),
+('ru', 'Russian'),
+('sk', 'Slovak'),
+('sv', 'Swedish'),
+('th', 'Thai'),
+('tr', 'Turkish'),
+('uk', 'Ukrainian'),
+('vi', 'Vietnamese'),
+('zh', 'Chinese');
+
+-- Posts and comments without one are in every listing
+alter table post add column language_id int references language on update cascade on delete set null;
+alter table comment add column language_id int references language on update cascade on delete set null;
+
+create index idx_post_language on post (language_id);
+create index idx_comment_language on comment (language_id);
+
+-- The languages a user reads. Users who didn't pick any see all of them.
+create table user_language (
+  id serial primary key,
+  user_id int references user_ on update cascade on delete cascade not null,
+  language_id int references language on update cascade on delete cascade not null,
+  unique (user_id, language_id)
+);
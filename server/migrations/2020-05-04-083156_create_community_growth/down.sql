@@ -0,0 +1,2 @@
+drop index idx_community_follower_community_published;
+drop table community_growth;
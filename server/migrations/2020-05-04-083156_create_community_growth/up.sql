@@ -0,0 +1,10 @@
+-- The last milestones of each community that its moderators were told about, and when they were
+-- last told about a spike in subscriptions
+create table community_growth (
+  community_id int primary key references community on update cascade on delete cascade,
+  subscriber_milestone bigint default 0 not null,
+  activity_milestone bigint default 0 not null,
+  spike_notified timestamp
+);
+
+create index idx_community_follower_community_published on community_follower (community_id, published);
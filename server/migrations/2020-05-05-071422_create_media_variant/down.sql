@@ -0,0 +1 @@
+drop table media_variant;
@@ -0,0 +1,8 @@
+-- The image that's served for an uploaded one, which pictshare resized and re-encoded, and the
+-- uploaded original, if the instance keeps them
+create table media_variant (
+  id serial primary key,
+  url text not null unique,
+  original_url text,
+  published timestamp not null default now()
+);
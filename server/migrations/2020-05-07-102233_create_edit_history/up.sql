@@ -0,0 +1,39 @@
+-- The content that posts and comments had before each edit, with who edited them, so that edits
+-- can't hide what was said
+create table post_edit (
+  id serial primary key,
+  post_id int references post on update cascade on delete cascade not null,
+  editor_id int references user_ on update cascade on delete cascade not null,
+  name varchar(200) not null,
+  url text,
+  body text,
+  published timestamp not null default now()
+);
+
+create index idx_post_edit_post on post_edit (post_id);
+
+create table comment_edit (
+  id serial primary key,
+  comment_id int references comment on update cascade on delete cascade not null,
+  editor_id int references user_ on update cascade on delete cascade not null,
+  content text not null,
+  published timestamp not null default now()
+);
+
+create index idx_comment_edit_comment on comment_edit (comment_id);
+
+-- Whether everyone can see the edit history, or only mods and admins
+alter table site add column public_edit_history boolean default false not null;
+
+-- The view is recreated so that it picks up the new column
+drop view site_view;
+
+create view site_view as 
+select *,
+(select name from user_ u where s.creator_id = u.id) as creator_name,
+(select avatar from user_ u where s.creator_id = u.id) as creator_avatar,
+(select count(*) from user_) as number_of_users,
+(select count(*) from post) as number_of_posts,
+(select count(*) from comment) as number_of_comments,
+(select count(*) from community) as number_of_communities
+from site s;
@@ -0,0 +1,61 @@
+drop view user_unread_counts_view;
+drop view inbox_combined_view;
+drop materialized view private_message_mview;
+drop view private_message_view;
+
+alter table private_message drop column encrypted;
+
+create view private_message_view as 
+select        
+pm.*,
+u.name as creator_name,
+u.avatar as creator_avatar,
+u2.name as recipient_name,
+u2.avatar as recipient_avatar
+from private_message pm
+inner join user_ u on u.id = pm.creator_id
+inner join user_ u2 on u2.id = pm.recipient_id;
+
+create materialized view private_message_mview as select * from private_message_view;
+
+create unique index idx_private_message_mview_id on private_message_mview (id);
+
+create view inbox_combined_view as
+select i.*,
+u.name as creator_name,
+u.avatar as creator_avatar,
+(select name from post p where i.post_id = p.id) as post_name
+from (
+  select 0::smallint as kind, c.id, c.creator_id, pc.creator_id as recipient_id, c.post_id,
+  c.id as comment_id, c.content, c.read, c.deleted or c.removed as deleted, c.published, c.updated
+  from comment c join comment pc on c.parent_id = pc.id
+  where c.creator_id != pc.creator_id
+  union all
+  select 0::smallint, c.id, c.creator_id, p.creator_id, c.post_id, c.id, c.content, c.read,
+  c.deleted or c.removed, c.published, c.updated
+  from comment c join post p on c.post_id = p.id
+  where c.parent_id is null and c.creator_id != p.creator_id
+  union all
+  select 1::smallint, um.id, c.creator_id, um.recipient_id, c.post_id, c.id, c.content, um.read,
+  c.deleted or c.removed, c.published, c.updated
+  from user_mention um join comment c on um.comment_id = c.id
+  union all
+  select 2::smallint, pm.id, pm.creator_id, pm.recipient_id, null, null, pm.content, pm.read,
+  pm.deleted, pm.published, pm.updated
+  from private_message pm
+) i join user_ u on i.creator_id = u.id;
+
+create view user_unread_counts_view as
+select
+i.recipient_id as user_id,
+count(*) filter (where i.kind = 0) as unread_replies,
+count(*) filter (where i.kind = 1) as unread_mentions,
+count(*) filter (where i.kind = 2) as unread_private_messages
+from inbox_combined_view i
+where not i.read
+and not i.deleted
+and not exists (
+  select 1 from user_block ub
+  where ub.user_id = i.recipient_id and ub.target_id = i.creator_id
+)
+group by i.recipient_id;
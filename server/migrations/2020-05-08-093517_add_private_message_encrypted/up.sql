@@ -0,0 +1,69 @@
+-- Whether a private message's content is encrypted, so plaintext that looks like ciphertext is
+-- left alone. The messages from before this are marked by their prefix, which is all there was to
+-- go by.
+alter table private_message add column encrypted boolean default false not null;
+
+update private_message set encrypted = true where content ~ '^enc:v1:[^:]*:';
+
+drop materialized view private_message_mview;
+
+create or replace view private_message_view as 
+select        
+pm.id,
+pm.creator_id,
+pm.recipient_id,
+pm.content,
+pm.deleted,
+pm.read,
+pm.published,
+pm.updated,
+u.name as creator_name,
+u.avatar as creator_avatar,
+u2.name as recipient_name,
+u2.avatar as recipient_avatar,
+pm.encrypted
+from private_message pm
+inner join user_ u on u.id = pm.creator_id
+inner join user_ u2 on u2.id = pm.recipient_id;
+
+create materialized view private_message_mview as select * from private_message_view;
+
+create unique index idx_private_message_mview_id on private_message_mview (id);
+
+create or replace view inbox_combined_view as
+select
+i.kind,
+i.id,
+i.creator_id,
+i.recipient_id,
+i.post_id,
+i.comment_id,
+i.content,
+i.read,
+i.deleted,
+i.published,
+i.updated,
+u.name as creator_name,
+u.avatar as creator_avatar,
+(select name from post p where i.post_id = p.id) as post_name,
+i.encrypted
+from (
+  select 0::smallint as kind, c.id, c.creator_id, pc.creator_id as recipient_id, c.post_id,
+  c.id as comment_id, c.content, c.read, c.deleted or c.removed as deleted, c.published, c.updated,
+  false as encrypted
+  from comment c join comment pc on c.parent_id = pc.id
+  where c.creator_id != pc.creator_id
+  union all
+  select 0::smallint, c.id, c.creator_id, p.creator_id, c.post_id, c.id, c.content, c.read,
+  c.deleted or c.removed, c.published, c.updated, false
+  from comment c join post p on c.post_id = p.id
+  where c.parent_id is null and c.creator_id != p.creator_id
+  union all
+  select 1::smallint, um.id, c.creator_id, um.recipient_id, c.post_id, c.id, c.content, um.read,
+  c.deleted or c.removed, c.published, c.updated, false
+  from user_mention um join comment c on um.comment_id = c.id
+  union all
+  select 2::smallint, pm.id, pm.creator_id, pm.recipient_id, null, null, pm.content, pm.read,
+  pm.deleted, pm.published, pm.updated, pm.encrypted
+  from private_message pm
+) i join user_ u on i.creator_id = u.id;
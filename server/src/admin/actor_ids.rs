@@ -0,0 +1,114 @@
+//! `lemmy_server admin actor-ids [--fix true]`
+//!
+//! Checks the activitypub ids stored for local actors against the current `hostname` and
+//! `federation.actor_domain`, since they go stale when the domain changes or tls is turned on: the
+//! `fedi_name` of local users, and the ids that renamed communities keep. It lists the ones that
+//! don't match, and with `--fix true` moves them onto the current domain, all in one transaction.
+
+use super::Flags;
+use crate::schema::{community, user_};
+use crate::Settings;
+use diesel::*;
+use failure::Error;
+
+/// A local actor whose stored id isn't on the current domain.
+pub struct StaleActor {
+  pub kind: &'static str,
+  pub id: i32,
+  pub name: String,
+  pub stored: String,
+  pub expected: String,
+}
+
+pub fn run(conn: &PgConnection, flags: &Flags) -> Result<(), Error> {
+  let fix = flags.get("fix", false)?;
+  let actor_domain = Settings::get().actor_domain();
+
+  let stale = stale_actors(conn, &actor_domain)?;
+  for actor in &stale {
+    println!(
+      "{} {} ({}): {} -> {}",
+      actor.kind, actor.name, actor.id, actor.stored, actor.expected
+    );
+  }
+  println!("{} local actors aren't on {}", stale.len(), actor_domain);
+  if stale.is_empty() {
+    return Ok(());
+  }
+  if !fix {
+    println!("Nothing was changed, --fix true rewrites them");
+    return Ok(());
+  }
+
+  conn.transaction::<_, Error, _>(|| {
+    for actor in &stale {
+      match actor.kind {
+        "user" => {
+          diesel::update(user_::table.find(actor.id))
+            .set(user_::fedi_name.eq(&actor.expected))
+            .execute(conn)?;
+        }
+        _ => {
+          diesel::update(community::table.find(actor.id))
+            .set(community::actor_id.eq(&actor.expected))
+            .execute(conn)?;
+        }
+      }
+    }
+    Ok(())
+  })?;
+  println!("Rewrote {} actors", stale.len());
+  Ok(())
+}
+
+pub fn stale_actors(conn: &PgConnection, actor_domain: &str) -> Result<Vec<StaleActor>, Error> {
+  // Local users are the ones without the id of a remote actor
+  let users = user_::table
+    .filter(user_::actor_id.is_null())
+    .filter(user_::fedi_name.ne(actor_domain))
+    .select((user_::id, user_::name, user_::fedi_name))
+    .order_by(user_::id)
+    .load::<(i32, String, String)>(conn)?
+    .into_iter()
+    .map(|(id, name, fedi_name)| StaleActor {
+      kind: "user",
+      id,
+      name,
+      stored: fedi_name,
+      expected: actor_domain.to_owned(),
+    });
+
+  let communities = community::table
+    .filter(community::actor_id.is_not_null())
+    .select((community::id, community::name, community::actor_id))
+    .order_by(community::id)
+    .load::<(i32, String, Option<String>)>(conn)?
+    .into_iter()
+    .filter_map(|(id, name, actor_id)| {
+      let stored = actor_id?;
+      let expected = rehost(&stored, actor_domain)?;
+      Some(StaleActor {
+        kind: "community",
+        id,
+        name,
+        stored,
+        expected,
+      })
+    });
+
+  Ok(users.chain(communities).collect())
+}
+
+/// The url on `https://{actor_domain}` with the same path, if that's not already where it is.
+fn rehost(url: &str, actor_domain: &str) -> Option<String> {
+  let without_scheme = url.splitn(2, "://").nth(1).unwrap_or(url);
+  let path = without_scheme
+    .find('/')
+    .map_or("", |start| &without_scheme[start..]);
+  let expected = format!("https://{}{}", actor_domain, path);
+  if expected == url {
+    None
+  } else {
+    Some(expected)
+  }
+}
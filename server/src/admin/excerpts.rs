@@ -0,0 +1,65 @@
+//! `lemmy_server admin excerpts [--batch-size 1000] [--all true]`
+//!
+//! Generates the excerpts of the posts from before they were added, or regenerates all of them
+//! with `--all true` after the way they're made changes.
+
+use super::Flags;
+use crate::db::post::post_excerpt;
+use crate::schema::post::dsl::*;
+use diesel::sql_types::{Array, Integer, Nullable, Text};
+use diesel::*;
+use failure::Error;
+
+pub fn run(conn: &PgConnection, flags: &Flags) -> Result<(), Error> {
+  let batch_size = flags.get("batch-size", 1000)?;
+  if batch_size < 1 {
+    return Err(format_err!("--batch-size must be at least 1"));
+  }
+  let all = flags.get("all", false)?;
+
+  let changed = generate_excerpts(conn, batch_size, all)?;
+  println!("Generated {} post excerpts", changed);
+  Ok(())
+}
+
+/// Fills in the missing excerpts, or all of them, a batch at a time. Returns how many were set.
+pub fn generate_excerpts(conn: &PgConnection, batch_size: i64, all: bool) -> Result<usize, Error> {
+  let mut changed = 0;
+  let mut last_id = 0;
+
+  loop {
+    let mut query = post
+      .select((id, body))
+      .filter(id.gt(last_id))
+      .filter(body.is_not_null())
+      .order_by(id)
+      .limit(batch_size)
+      .into_boxed();
+    if !all {
+      query = query.filter(excerpt.is_null());
+    }
+    let batch = query.load::<(i32, Option<String>)>(conn)?;
+
+    let last = match batch.last() {
+      Some((batch_last_id, _)) => *batch_last_id,
+      None => return Ok(changed),
+    };
+
+    let (ids, excerpts): (Vec<i32>, Vec<Option<String>>) = batch
+      .into_iter()
+      .map(|(post_id, post_body)| (post_id, post_excerpt(&post_body)))
+      .unzip();
+
+    // One statement per batch, since every statement refreshes the materialized views
+    changed += sql_query(
+      "update post set excerpt = data.excerpt \
+       from (select unnest($1) as id, unnest($2) as excerpt) data \
+       where post.id = data.id",
+    )
+    .bind::<Array<Integer>, _>(ids)
+    .bind::<Array<Nullable<Text>>, _>(excerpts)
+    .execute(conn)?;
+
+    last_id = last;
+  }
+}
@@ -0,0 +1,82 @@
+//! `lemmy_server admin instance-cleanup --domain example.com [--dry-run true] [--ban-users true]
+//! [--batch-size 1000]`
+//!
+//! Removes everything that came from an instance after blocking it: the posts and comments of its
+//! users, and with `--ban-users true` bans them too. `--dry-run true` only counts what would be
+//! removed. It works a batch at a time, printing its progress, so it can run alongside the server
+//! and be stopped and started again.
+
+use super::Flags;
+use crate::db::instance_impact::InstanceImpact;
+use crate::Settings;
+use diesel::sql_types::{BigInt, Text};
+use diesel::*;
+use failure::Error;
+
+pub fn run(conn: &PgConnection, flags: &Flags) -> Result<(), Error> {
+  let domain = flags.get("domain", String::new())?.trim().to_lowercase();
+  if domain.is_empty() {
+    return Err(format_err!("--domain is required"));
+  }
+  if domain == Settings::get().actor_domain() {
+    return Err(format_err!("--domain can't be this instance's own"));
+  }
+  let batch_size = flags.get("batch-size", 1000)?;
+  if batch_size < 1 {
+    return Err(format_err!("--batch-size must be at least 1"));
+  }
+  let dry_run = flags.get("dry-run", false)?;
+  let ban_users = flags.get("ban-users", false)?;
+
+  let impact = InstanceImpact::for_domain(conn, &domain)?;
+  println!(
+    "{} has {} users, {} posts and {} comments",
+    domain, impact.users, impact.posts, impact.comments
+  );
+  if dry_run {
+    println!("Dry run, nothing was removed");
+    return Ok(());
+  }
+
+  let posts = remove_content(conn, "post", &domain, batch_size, impact.posts)?;
+  println!("Removed {} posts", posts);
+  let comments = remove_content(conn, "comment", &domain, batch_size, impact.comments)?;
+  println!("Removed {} comments", comments);
+
+  if ban_users {
+    let banned = sql_query("update user_ set banned = true where fedi_name = $1 and not banned")
+      .bind::<Text, _>(&domain)
+      .execute(conn)?;
+    println!("Banned {} users", banned);
+  }
+  Ok(())
+}
+
+/// Removes the not yet removed posts or comments of the domain's users, a batch at a time. Returns
+/// how many were removed.
+fn remove_content(
+  conn: &PgConnection,
+  table: &str,
+  domain: &str,
+  batch_size: i64,
+  total: i64,
+) -> Result<usize, Error> {
+  let mut removed = 0;
+  loop {
+    // One statement per batch, since every statement refreshes the materialized views
+    let batch = sql_query(format!(
+      "update {table} set removed = true where id in ( \
+       select t.id from {table} t join user_ u on t.creator_id = u.id \
+       where u.fedi_name = $1 and not t.removed order by t.id limit $2)",
+      table = table
+    ))
+    .bind::<Text, _>(domain)
+    .bind::<BigInt, _>(batch_size)
+    .execute(conn)?;
+    if batch == 0 {
+      return Ok(removed);
+    }
+    removed += batch;
+    println!("  {}: {}/{}", table, removed, total);
+  }
+}
@@ -0,0 +1,66 @@
+//! Maintenance commands, run as `lemmy_server admin <command> [--flag value]...` instead of starting
+//! the server.
+
+pub mod actor_ids;
+pub mod excerpts;
+pub mod instance_cleanup;
+pub mod recount_aggregates;
+pub mod reencrypt;
+pub mod seed;
+
+use diesel::PgConnection;
+use failure::Error;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// The `--flag value` pairs following a command.
+pub struct Flags(HashMap<String, String>);
+
+impl Flags {
+  pub fn parse(args: &[String]) -> Result<Self, Error> {
+    let mut flags = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+      if !arg.starts_with("--") {
+        return Err(format_err!("unexpected argument: {}", arg));
+      }
+      let name = &arg[2..];
+      let value = match args.next() {
+        Some(value) => value,
+        None => return Err(format_err!("missing value for --{}", name)),
+      };
+      flags.insert(name.to_owned(), value.to_owned());
+    }
+    Ok(Flags(flags))
+  }
+
+  /// The value of `--name`, or `default` if it wasn't given.
+  pub fn get<T: FromStr>(&self, name: &str, default: T) -> Result<T, Error> {
+    match self.0.get(name) {
+      Some(value) => value
+        .parse::<T>()
+        .map_err(|_| format_err!("invalid value for --{}: {}", name, value)),
+      None => Ok(default),
+    }
+  }
+}
+
+/// Runs the admin command named by `args[0]`.
+pub fn run(conn: &PgConnection, args: &[String]) -> Result<(), Error> {
+  let command = args.first().map(String::as_str).unwrap_or_default();
+  let flags = Flags::parse(args.get(1..).unwrap_or_default())?;
+
+  match command {
+    "actor-ids" => actor_ids::run(conn, &flags),
+    "excerpts" => excerpts::run(conn, &flags),
+    "instance-cleanup" => instance_cleanup::run(conn, &flags),
+    "recount-aggregates" => recount_aggregates::run(conn, &flags),
+    "reencrypt" => reencrypt::run(conn, &flags),
+    "seed" => seed::run(conn, &flags),
+    _ => Err(format_err!(
+      "unknown admin command: {:?}, expected one of: actor-ids, excerpts, instance-cleanup, \
+       recount-aggregates, reencrypt, seed",
+      command
+    )),
+  }
+}
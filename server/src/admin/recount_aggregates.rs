@@ -0,0 +1,78 @@
+//! `lemmy_server admin recount-aggregates [--community name] [--batch-size 1000]`
+//!
+//! Counts the aggregates again from the tables they're of, for after a trigger bug or manual
+//! changes to the database. The comment, post, community and user aggregates are materialized
+//! views, so they're refreshed whole, and the site's are counted each time they're read. The
+//! activity heatmaps of users are recounted a batch of users at a time, and with `--community`
+//! only those of the users who posted or commented in it.
+
+use super::Flags;
+use crate::db::community::Community;
+use crate::db::user_activity::UserActivityDay;
+use crate::schema::{comment, post, user_};
+use diesel::*;
+use failure::Error;
+
+const AGGREGATE_MVIEWS: [&str; 4] = [
+  "comment_aggregates_mview",
+  "post_aggregates_mview",
+  "community_aggregates_mview",
+  "user_mview",
+];
+
+pub fn run(conn: &PgConnection, flags: &Flags) -> Result<(), Error> {
+  let batch_size = flags.get("batch-size", 1000)?;
+  if batch_size < 1 {
+    return Err(format_err!("--batch-size must be at least 1"));
+  }
+  let community_name = flags.get("community", String::new())?;
+  let community = if community_name.is_empty() {
+    None
+  } else {
+    Some(
+      Community::read_from_name(conn, community_name.to_owned())
+        .map_err(|_| format_err!("no community is named {}", community_name))?,
+    )
+  };
+
+  for mview in AGGREGATE_MVIEWS.iter() {
+    sql_query(format!("refresh materialized view concurrently {}", mview)).execute(conn)?;
+    println!("Refreshed {}", mview);
+  }
+
+  let user_ids = match &community {
+    Some(community) => community_user_ids(conn, community.id)?,
+    None => user_::table
+      .select(user_::id)
+      .order_by(user_::id)
+      .load::<i32>(conn)?,
+  };
+  let mut recounted = 0;
+  for batch in user_ids.chunks(batch_size) {
+    UserActivityDay::recount(conn, batch)?;
+    recounted += batch.len();
+    println!("  users: {}/{}", recounted, user_ids.len());
+  }
+  println!("Recounted the activity of {} users", recounted);
+  Ok(())
+}
+
+/// The users who posted or commented in the community.
+fn community_user_ids(conn: &PgConnection, community_id: i32) -> Result<Vec<i32>, Error> {
+  let mut user_ids = post::table
+    .filter(post::community_id.eq(community_id))
+    .select(post::creator_id)
+    .distinct()
+    .load::<i32>(conn)?;
+  user_ids.extend(
+    comment::table
+      .inner_join(post::table)
+      .filter(post::community_id.eq(community_id))
+      .select(comment::creator_id)
+      .distinct()
+      .load::<i32>(conn)?,
+  );
+  user_ids.sort();
+  user_ids.dedup();
+  Ok(user_ids)
+}
@@ -0,0 +1,72 @@
+//! `lemmy_server admin reencrypt [--batch-size 1000]`
+//!
+//! Re-encrypts the private messages which aren't encrypted with the current key from
+//! `private_message_encryption`, after it's turned on or its key is rotated. If it's turned off,
+//! this decrypts them instead.
+
+use super::Flags;
+use crate::db::encryption::{keyring, Keyring};
+use crate::schema::private_message::dsl::*;
+use diesel::sql_types::{Array, Bool, Integer, Text};
+use diesel::*;
+use failure::Error;
+
+pub fn run(conn: &PgConnection, flags: &Flags) -> Result<(), Error> {
+  let batch_size = flags.get("batch-size", 1000)?;
+  if batch_size < 1 {
+    return Err(format_err!("--batch-size must be at least 1"));
+  }
+
+  let changed = reencrypt(conn, keyring(), batch_size)?;
+  println!("Re-encrypted {} private messages", changed);
+  Ok(())
+}
+
+/// Brings every private message up to `keyring`'s current key, a batch at a time. Returns how
+/// many were changed.
+pub fn reencrypt(conn: &PgConnection, keyring: &Keyring, batch_size: i64) -> Result<usize, Error> {
+  let mut changed = 0;
+  let mut last_id = 0;
+
+  loop {
+    let batch = private_message
+      .select((id, content, encrypted))
+      .filter(id.gt(last_id))
+      .order_by(id)
+      .limit(batch_size)
+      .load::<(i32, String, bool)>(conn)?;
+
+    let last = match batch.last() {
+      Some((batch_last_id, _, _)) => *batch_last_id,
+      None => return Ok(changed),
+    };
+
+    let mut ids = Vec::new();
+    let mut contents = Vec::new();
+    let mut encrypteds = Vec::new();
+    for (message_id, stored, stored_encrypted) in batch {
+      if keyring.needs_reencryption(&stored, stored_encrypted) {
+        let (new_content, new_encrypted) =
+          keyring.encrypt(&keyring.decrypt(&stored, stored_encrypted)?)?;
+        ids.push(message_id);
+        contents.push(new_content);
+        encrypteds.push(new_encrypted);
+      }
+    }
+
+    // One statement per batch, since every statement refreshes the materialized view
+    if !ids.is_empty() {
+      changed += sql_query(
+        "update private_message set content = data.content, encrypted = data.encrypted \
+         from (select unnest($1) as id, unnest($2) as content, unnest($3) as encrypted) data \
+         where private_message.id = data.id",
+      )
+      .bind::<Array<Integer>, _>(ids)
+      .bind::<Array<Text>, _>(contents)
+      .bind::<Array<Bool>, _>(encrypteds)
+      .execute(conn)?;
+    }
+
+    last_id = last;
+  }
+}
@@ -0,0 +1,376 @@
+//! `lemmy_server admin seed --users N --posts M`
+//!
+//! Fills the database with synthetic users, communities, posts, comments and votes, for
+//! benchmarking the view queries. The same flags on an empty database always produce the same
+//! rows, only the timestamps move along with the time of the run (they're spread over the
+//! `--days` before it, so the hot ranks look like a live site's).
+//!
+//! Activity follows power laws, like on a real site: a few users write most of the posts, a few
+//! communities get most of them, and a few posts get most of the votes and comments. Comment
+//! trees are nested up to `MAX_COMMENT_DEPTH`.
+//!
+//! Rows are inserted a batch per statement, since each statement refreshes the materialized
+//! views.
+
+use super::Flags;
+use crate::db::comment::{Comment, CommentForm, CommentLikeForm};
+use crate::db::community::{Community, CommunityFollowerForm, CommunityForm};
+use crate::db::post::{Post, PostForm, PostLikeForm};
+use crate::db::user::{UserForm, User_};
+use crate::db::{ListingType, SortType};
+use crate::naive_now;
+use crate::schema::{comment, comment_like, community, community_follower, post, post_like, user_};
+use chrono::{Duration, NaiveDateTime};
+use diesel::sql_types::{Array, Integer, Timestamp};
+use diesel::*;
+use failure::Error;
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+use rand::{Rng, SeedableRng};
+
+const BATCH_SIZE: usize = 1000;
+const MAX_COMMENT_DEPTH: usize = 8;
+
+/// Everyone gets the same password, so that the seeded users can log in.
+const SEED_PASSWORD: &str = "lemmy_seed";
+
+pub struct SeedConfig {
+  pub users: usize,
+  pub communities: usize,
+  pub posts: usize,
+  pub days: i64,
+  pub seed: u64,
+}
+
+pub fn run(conn: &PgConnection, flags: &Flags) -> Result<(), Error> {
+  let users = flags.get("users", 100)?;
+  let config = SeedConfig {
+    users,
+    communities: flags.get("communities", (users / 100).max(1))?,
+    posts: flags.get("posts", 1000)?,
+    days: flags.get("days", 30)?,
+    seed: flags.get("seed", 0)?,
+  };
+
+  if config.users == 0 || config.communities == 0 {
+    return Err(format_err!("--users and --communities must be at least 1"));
+  }
+
+  seed(conn, &config)
+}
+
+/// A sample from a pareto distribution with minimum 1, ie mostly small numbers with a long tail.
+/// Lower `alpha`s make for a longer tail.
+fn pareto(rng: &mut StdRng, alpha: f64) -> f64 {
+  let u: f64 = rng.gen_range(f64::EPSILON, 1.0);
+  1.0 / u.powf(1.0 / alpha)
+}
+
+/// An index in `0..len`, skewed towards the start, so that the first rows are the popular ones.
+fn skewed_index(rng: &mut StdRng, len: usize, skew: i32) -> usize {
+  let u: f64 = rng.gen();
+  ((len as f64) * u.powi(skew)) as usize % len
+}
+
+/// Picks `amount` distinct users, ie voters.
+fn sample_users(rng: &mut StdRng, users: &[User_], amount: usize) -> Vec<i32> {
+  sample(rng, users.len(), amount.min(users.len()))
+    .into_iter()
+    .map(|i| users[i].id)
+    .collect()
+}
+
+fn published_before(rng: &mut StdRng, now: NaiveDateTime, days: i64) -> NaiveDateTime {
+  now - Duration::seconds(rng.gen_range(0, days.max(1) * 24 * 60 * 60))
+}
+
+/// Sets the published times of freshly inserted rows, all in one statement.
+fn set_published(
+  conn: &PgConnection,
+  table: &str,
+  ids: Vec<i32>,
+  published: Vec<NaiveDateTime>,
+) -> Result<(), Error> {
+  sql_query(format!(
+    "update {table} set published = data.published \
+     from (select unnest($1) as id, unnest($2) as published) data \
+     where {table}.id = data.id",
+    table = table
+  ))
+  .bind::<Array<Integer>, _>(ids)
+  .bind::<Array<Timestamp>, _>(published)
+  .execute(conn)?;
+  Ok(())
+}
+
+pub fn seed(conn: &PgConnection, config: &SeedConfig) -> Result<(), Error> {
+  let mut rng = StdRng::seed_from_u64(config.seed);
+  let now = naive_now();
+  let prefix = format!("seed{}", config.seed);
+  let password_encrypted = bcrypt::hash(SEED_PASSWORD, bcrypt::DEFAULT_COST)?;
+
+  // Users
+  let user_forms = (0..config.users)
+    .map(|i| UserForm {
+      name: format!("{}_user_{}", prefix, i),
+      fedi_name: "seed".into(),
+      preferred_username: None,
+      password_encrypted: password_encrypted.to_owned(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      updated: None,
+      admin: false,
+      banned: false,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    })
+    .collect::<Vec<UserForm>>();
+  let mut users: Vec<User_> = Vec::with_capacity(config.users);
+  for batch in user_forms.chunks(BATCH_SIZE) {
+    users.extend(
+      insert_into(user_::table)
+        .values(batch)
+        .get_results::<User_>(conn)?,
+    );
+  }
+  println!("Seeded {} users", users.len());
+
+  // Communities, and who follows them
+  let community_forms = (0..config.communities)
+    .map(|i| CommunityForm {
+      name: format!("{}_community_{}", prefix, i),
+      title: format!("Seeded community {}", i),
+      description: Some(format!("Community number {} of the seeded data.", i)),
+      category_id: 1 + (i % 20) as i32,
+      creator_id: users[skewed_index(&mut rng, users.len(), 3)].id,
+      removed: None,
+      updated: None,
+      deleted: None,
+      nsfw: false,
+    })
+    .collect::<Vec<CommunityForm>>();
+  let mut communities: Vec<Community> = Vec::with_capacity(config.communities);
+  for batch in community_forms.chunks(BATCH_SIZE) {
+    communities.extend(
+      insert_into(community::table)
+        .values(batch)
+        .get_results::<Community>(conn)?,
+    );
+  }
+
+  let mut follower_forms = Vec::new();
+  for user in &users {
+    let follows = (pareto(&mut rng, 1.5) as usize).min(communities.len());
+    let mut followed: Vec<usize> = (0..follows)
+      .map(|_| skewed_index(&mut rng, communities.len(), 2))
+      .collect();
+    followed.sort_unstable();
+    followed.dedup();
+    follower_forms.extend(followed.into_iter().map(|c| CommunityFollowerForm {
+      community_id: communities[c].id,
+      user_id: user.id,
+    }));
+  }
+  for batch in follower_forms.chunks(BATCH_SIZE) {
+    insert_into(community_follower::table)
+      .values(batch)
+      .execute(conn)?;
+  }
+  println!(
+    "Seeded {} communities, with {} followers",
+    communities.len(),
+    follower_forms.len()
+  );
+
+  // Posts
+  let post_forms = (0..config.posts)
+    .map(|i| {
+      let has_url = rng.gen_bool(0.4);
+      PostForm {
+        name: format!("Seeded post number {}", i),
+        url: if has_url {
+          Some(format!("https://example.com/articles/{}", i))
+        } else {
+          None
+        },
+        body: if has_url {
+          None
+        } else {
+          Some("A seeded post body. ".repeat(rng.gen_range(1, 50)))
+        },
+        creator_id: users[skewed_index(&mut rng, users.len(), 3)].id,
+        community_id: communities[skewed_index(&mut rng, communities.len(), 2)].id,
+        removed: None,
+        locked: None,
+        updated: None,
+        deleted: None,
+        nsfw: false,
+        stickied: None,
+        embed_title: None,
+        embed_description: None,
+        embed_html: None,
+        thumbnail_url: None,
+        alt_text: None,
+        visibility: None,
+        generated: None,
+        language_id: None,
+        scheduled_publish_time: None,
+      }
+    })
+    .collect::<Vec<PostForm>>();
+  let mut posts: Vec<Post> = Vec::with_capacity(config.posts);
+  for batch in post_forms.chunks(BATCH_SIZE) {
+    let inserted = insert_into(post::table)
+      .values(batch)
+      .get_results::<Post>(conn)?;
+    let published = inserted
+      .iter()
+      .map(|_| published_before(&mut rng, now, config.days))
+      .collect::<Vec<NaiveDateTime>>();
+    set_published(
+      conn,
+      "post",
+      inserted.iter().map(|p| p.id).collect(),
+      published.to_owned(),
+    )?;
+    posts.extend(
+      inserted
+        .into_iter()
+        .zip(published)
+        .map(|(p, published)| Post { published, ..p }),
+    );
+  }
+  println!("Seeded {} posts", posts.len());
+
+  // Post votes
+  let mut post_like_forms = Vec::new();
+  for p in &posts {
+    let votes = pareto(&mut rng, 1.1) as usize - 1;
+    for user_id in sample_users(&mut rng, &users, votes) {
+      post_like_forms.push(PostLikeForm {
+        post_id: p.id,
+        user_id,
+        score: if rng.gen_bool(0.85) { 1 } else { -1 },
+      });
+    }
+  }
+  for batch in post_like_forms.chunks(BATCH_SIZE) {
+    insert_into(post_like::table).values(batch).execute(conn)?;
+  }
+  println!("Seeded {} post votes", post_like_forms.len());
+
+  // Comment trees. Each planned comment knows its parent's index in the plan, and they're
+  // inserted a depth at a time, so that the parents' ids are known.
+  struct PlannedComment {
+    post: usize,
+    parent: Option<usize>,
+    depth: usize,
+  }
+  let mut planned: Vec<PlannedComment> = Vec::new();
+  for (post_index, _) in posts.iter().enumerate() {
+    let first = planned.len();
+    let count = (pareto(&mut rng, 1.2) as usize - 1).min(500);
+    for _ in 0..count {
+      let parent = if planned.len() > first && rng.gen_bool(0.6) {
+        Some(rng.gen_range(first, planned.len()))
+      } else {
+        None
+      };
+      let depth = match parent {
+        Some(parent) if planned[parent].depth < MAX_COMMENT_DEPTH => planned[parent].depth + 1,
+        _ => 0,
+      };
+      planned.push(PlannedComment {
+        post: post_index,
+        parent: if depth == 0 { None } else { parent },
+        depth,
+      });
+    }
+  }
+
+  let mut comment_ids: Vec<i32> = vec![0; planned.len()];
+  let mut comment_published: Vec<NaiveDateTime> = vec![now; planned.len()];
+  let mut comments_seeded = 0;
+  for depth in 0..=MAX_COMMENT_DEPTH {
+    let at_depth = planned
+      .iter()
+      .enumerate()
+      .filter(|(_, c)| c.depth == depth)
+      .map(|(i, _)| i)
+      .collect::<Vec<usize>>();
+
+    for batch in at_depth.chunks(BATCH_SIZE) {
+      let forms = batch
+        .iter()
+        .map(|&i| CommentForm {
+          creator_id: users[skewed_index(&mut rng, users.len(), 2)].id,
+          post_id: posts[planned[i].post].id,
+          parent_id: planned[i].parent.map(|parent| comment_ids[parent]),
+          content: "A seeded comment. ".repeat(rng.gen_range(1, 20)),
+          removed: None,
+          read: None,
+          updated: None,
+          deleted: None,
+          generated: None,
+          language_id: None,
+        })
+        .collect::<Vec<CommentForm>>();
+      let inserted = insert_into(comment::table)
+        .values(&forms)
+        .get_results::<Comment>(conn)?;
+
+      // Comments come after their post, and replies after their parent
+      let published = batch
+        .iter()
+        .map(|&i| {
+          let after = match planned[i].parent {
+            Some(parent) => comment_published[parent],
+            None => posts[planned[i].post].published,
+          };
+          (after + Duration::seconds(rng.gen_range(60, 24 * 60 * 60))).min(now)
+        })
+        .collect::<Vec<NaiveDateTime>>();
+      set_published(
+        conn,
+        "comment",
+        inserted.iter().map(|c| c.id).collect(),
+        published.to_owned(),
+      )?;
+
+      for ((&i, c), published) in batch.iter().zip(inserted.iter()).zip(published) {
+        comment_ids[i] = c.id;
+        comment_published[i] = published;
+      }
+      comments_seeded += inserted.len();
+    }
+  }
+  println!("Seeded {} comments", comments_seeded);
+
+  // Comment votes
+  let mut comment_like_forms = Vec::new();
+  for (i, c) in planned.iter().enumerate() {
+    let votes = pareto(&mut rng, 1.3) as usize - 1;
+    for user_id in sample_users(&mut rng, &users, votes) {
+      comment_like_forms.push(CommentLikeForm {
+        user_id,
+        comment_id: comment_ids[i],
+        post_id: posts[c.post].id,
+        score: if rng.gen_bool(0.8) { 1 } else { -1 },
+      });
+    }
+  }
+  for batch in comment_like_forms.chunks(BATCH_SIZE) {
+    insert_into(comment_like::table)
+      .values(batch)
+      .execute(conn)?;
+  }
+  println!("Seeded {} comment votes", comment_like_forms.len());
+
+  Ok(())
+}
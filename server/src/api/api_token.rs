@@ -0,0 +1,606 @@
+use super::*;
+use crate::db::api_token::*;
+use crate::ip_privacy;
+use log::info;
+use serde_json::Value;
+use std::str::FromStr;
+
+/// The most tokens a user can have at once.
+const MAX_API_TOKENS: usize = 20;
+/// The most an admin can raise a token's rate limits by.
+const MAX_RATE_LIMIT_MULTIPLIER: f32 = 100.0;
+/// How long an admin can view the site as a user with one token.
+const IMPERSONATION_MINUTES: i64 = 60;
+
+/// The fields which make an edit a mod action.
+const MOD_FIELDS: &[&str] = &[
+  "removed",
+  "locked",
+  "stickied",
+  "hidden",
+  "report_auto_remove_threshold",
+];
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateApiToken {
+  name: String,
+  scopes: Vec<String>,
+  /// For clients signed in to several accounts, to show which device and app each session is on
+  device_name: Option<String>,
+  client_app: Option<String>,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateApiTokenResponse {
+  api_token: ApiToken,
+  /// The token to send as `auth`, which is only shown this once
+  token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListApiTokens {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListApiTokensResponse {
+  api_tokens: Vec<ApiToken>,
+}
+
+/// Lists the user's tokens, with which device and app each is used on, for managing their
+/// sessions.
+#[derive(Serialize, Deserialize)]
+pub struct ListMyTokens {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MyToken {
+  api_token: ApiToken,
+  /// Whether it's the token the request was made with
+  current: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListMyTokensResponse {
+  tokens: Vec<MyToken>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeleteApiToken {
+  id: i32,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SetApiTokenRateLimit {
+  id: i32,
+  rate_limit_multiplier: f32,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiTokenResponse {
+  api_token: ApiToken,
+}
+
+/// Gets a read only token for viewing the site as a user, to look into their reports.
+#[derive(Serialize, Deserialize)]
+pub struct ImpersonateUser {
+  user_id: i32,
+  reason: String,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListImpersonations {
+  page: Option<i64>,
+  limit: Option<i64>,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RevokeImpersonation {
+  id: i32,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListImpersonationsResponse {
+  /// The unexpired tokens
+  active: Vec<ApiToken>,
+  log: Vec<ModImpersonate>,
+}
+
+/// The scope a token needs for an operation, or `None` if it needs a login.
+fn required_scope(op: &UserOperation, data: &Value) -> Option<TokenScope> {
+  use UserOperation::*;
+  match op {
+    Login
+    | Register
+    | PasswordReset
+    | GetSite
+    | ListCategories
+    | ListLanguages
+    | ListCommunities
+    | GetPost
+    | GetPosts
+    | GetComments
+    | GetCommentContext
+    | GetCommentTree
+    | GetPostEdits
+    | GetCommentEdits
+    | GetCommunity
+    | GetUserDetails
+    | GetUserActivity
+    | GetFollowedCommunities
+    | GetBlockedCommunities
+    | GetReplies
+    | GetUserMentions
+    | GetInbox
+    | GetUnreadCount
+    | GetSavedFolders
+    | GetSavedItems
+    | GetKeywordFilters
+    | GetBlockedUsers
+    | GetBlockedInstances
+    | GetMyContent
+    | ListMyTokens
+    | GetModerationNotices
+    | GetPrivateMessages
+    | GetModlog
+    | GetModlogFeed
+    | Search
+    | ResolveObject
+    | PrefetchActors
+    | GetApubId
+    | TranslateContent
+    | UserJoin => Some(TokenScope::Read),
+    EditPost | EditComment | EditCommunity => {
+      let moderates = MOD_FIELDS
+        .iter()
+        .any(|field| data.get(field).map_or(false, |v| !v.is_null()));
+      if moderates {
+        Some(TokenScope::Moderate)
+      } else {
+        Some(TokenScope::Write)
+      }
+    }
+    CreateCommunity | CreatePost | CreateComment | CreatePostLike | CreateCommentLike
+    | SavePost | SaveMediaAltText | SaveComment | FollowCommunity | EditUserMention
+    | MarkAllAsRead | MarkAsRead | BlockUser | BlockInstance | BlockCommunity | PinToProfile
+    | ReorderProfilePins | CreatePrivateMessage | EditPrivateMessage | CreateReport
+    | CreateSavedFolder | EditSavedFolder | DeleteSavedFolder | CreateKeywordFilter
+    | DeleteKeywordFilter => Some(TokenScope::Write),
+    BanFromCommunity | AddModToCommunity | TransferCommunity | BanUser | CreateModNote
+    | EditModNote | GetModNotes | CreateScheduledPost | DeleteScheduledPost | GetScheduledPosts
+    | SaveModDigest | GetModDigests | ListReports | ResolveReport => Some(TokenScope::Moderate),
+    SaveUserSettings
+    | DeleteAccount
+    | PasswordChange
+    | CreateSite
+    | EditSite
+    | TransferSite
+    | AddAdmin
+    | GetSiteConfig
+    | SaveSiteConfig
+    | LegalHold
+    | ImportIpBlocks
+    | ListIpBlocks
+    | DeleteIpBlock
+    | CreateApiToken
+    | ListApiTokens
+    | DeleteApiToken
+    | SetApiTokenRateLimit
+    | RegisterOAuthClient
+    | ListOAuthClients
+    | DeleteOAuthClient
+    | GetOAuthConsent
+    | AuthorizeOAuthClient
+    | ImpersonateUser
+    | ListImpersonations
+    | RevokeImpersonation
+    | GetInstanceImpact
+    | ListInboxRejections
+    | GetFederationMetrics
+    | GetOperationMetrics
+    | ListReservedCommunityNames
+    | ReserveCommunityName
+    | DeleteReservedCommunityName
+    | GetConsistencyReport
+    | RunConsistencyCheck => None,
+  }
+}
+
+/// Checks a request made with an api token: that the token hasn't been deleted, and has the
+/// scope the operation needs. Returns the token, or `None` for requests without one. The token is
+/// marked used from the network of `ip`.
+pub fn check_api_token(
+  conn: &PgConnection,
+  op: &UserOperation,
+  data: &Value,
+  ip: Option<&str>,
+) -> Result<Option<ApiToken>, Error> {
+  // Bad jwts are left for the operation itself to refuse
+  let claims = match data
+    .get("auth")
+    .and_then(Value::as_str)
+    .and_then(|auth| Claims::decode(auth).ok())
+  {
+    Some(claims) => claims.claims,
+    None => return Ok(None),
+  };
+  let token_id = match claims.token_id {
+    Some(token_id) => token_id,
+    None => return Ok(None),
+  };
+
+  let token = match ApiToken::read(conn, token_id) {
+    Ok(token) if token.user_id == claims.id && !token.is_expired() => token,
+    _ => return Err(APIError::err("invalid_api_token").into()),
+  };
+
+  match required_scope(op, data) {
+    Some(scope) if token.has_scope(scope) => (),
+    _ => return Err(APIError::err("api_token_missing_scope").into()),
+  }
+
+  // Impersonation tokens stop working if the admin is removed, and never read private messages
+  if let Some(impersonator_id) = token.impersonator_id {
+    if !User_::read(conn, impersonator_id)?.admin {
+      return Err(APIError::err("invalid_api_token").into());
+    }
+    match op {
+      UserOperation::GetPrivateMessages | UserOperation::GetInbox => {
+        return Err(APIError::err("cant_impersonate_private_messages").into())
+      }
+      _ => (),
+    }
+    info!(
+      "Admin {} viewing as user {}: {}",
+      impersonator_id,
+      token.user_id,
+      op.to_string()
+    );
+  }
+
+  ApiToken::mark_used(conn, token.id, ip.and_then(ip_privacy::network))?;
+  Ok(Some(token))
+}
+
+impl Perform<CreateApiTokenResponse> for Oper<CreateApiToken> {
+  fn perform(&self, conn: &PgConnection) -> Result<CreateApiTokenResponse, Error> {
+    let data: &CreateApiToken = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    if data.name.trim().is_empty() || data.name.chars().count() > 100 {
+      return Err(APIError::err("invalid_api_token_name").into());
+    }
+
+    let device_name = token_label(&data.device_name, "invalid_device_name")?;
+    let client_app = token_label(&data.client_app, "invalid_client_app")?;
+
+    let mut scopes = Vec::new();
+    for scope in &data.scopes {
+      match TokenScope::from_str(scope) {
+        Ok(scope) if !scopes.contains(&scope.to_string()) => scopes.push(scope.to_string()),
+        Ok(_) => (),
+        Err(_) => return Err(APIError::err("invalid_api_token_scope").into()),
+      }
+    }
+    if scopes.is_empty() {
+      return Err(APIError::err("invalid_api_token_scope").into());
+    }
+
+    // Apps' tokens don't count, since the user didn't make those
+    let personal_tokens = ApiToken::list_for_user(&conn, user_id)?
+      .iter()
+      .filter(|t| t.oauth_client_id.is_none())
+      .count();
+    if personal_tokens >= MAX_API_TOKENS {
+      return Err(APIError::err("too_many_api_tokens").into());
+    }
+
+    let form = ApiTokenForm {
+      user_id,
+      name: data.name.trim().to_owned(),
+      scopes,
+      rate_limit_multiplier: 1.0,
+      oauth_client_id: None,
+      impersonator_id: None,
+      expires: None,
+      device_name,
+      client_app,
+    };
+
+    let api_token = match ApiToken::create(&conn, &form) {
+      Ok(api_token) => api_token,
+      Err(_e) => return Err(APIError::err("couldnt_create_api_token").into()),
+    };
+
+    let token = User_::read(&conn, user_id)?.api_token_jwt(api_token.id);
+
+    Ok(CreateApiTokenResponse { api_token, token })
+  }
+}
+
+impl Perform<ListApiTokensResponse> for Oper<ListApiTokens> {
+  fn perform(&self, conn: &PgConnection) -> Result<ListApiTokensResponse, Error> {
+    let data: &ListApiTokens = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let api_tokens = ApiToken::list_for_user(&conn, claims.id)?;
+
+    Ok(ListApiTokensResponse { api_tokens })
+  }
+}
+
+impl Perform<ListMyTokensResponse> for Oper<ListMyTokens> {
+  fn perform(&self, conn: &PgConnection) -> Result<ListMyTokensResponse, Error> {
+    let data: &ListMyTokens = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let tokens = ApiToken::list_for_user(&conn, claims.id)?
+      .into_iter()
+      .map(|api_token| MyToken {
+        current: claims.token_id == Some(api_token.id),
+        api_token,
+      })
+      .collect();
+
+    Ok(ListMyTokensResponse { tokens })
+  }
+}
+
+impl Perform<ListApiTokensResponse> for Oper<DeleteApiToken> {
+  fn perform(&self, conn: &PgConnection) -> Result<ListApiTokensResponse, Error> {
+    let data: &DeleteApiToken = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    // Only your own tokens
+    match ApiToken::read(&conn, data.id) {
+      Ok(api_token) if api_token.user_id == user_id => (),
+      _ => return Err(APIError::err("couldnt_find_api_token").into()),
+    }
+
+    ApiToken::delete(&conn, data.id)?;
+
+    let api_tokens = ApiToken::list_for_user(&conn, user_id)?;
+
+    Ok(ListApiTokensResponse { api_tokens })
+  }
+}
+
+impl Perform<ApiTokenResponse> for Oper<SetApiTokenRateLimit> {
+  fn perform(&self, conn: &PgConnection) -> Result<ApiTokenResponse, Error> {
+    let data: &SetApiTokenRateLimit = &self.data;
+
+    admin_user_id(conn, &data.auth)?;
+
+    let multiplier = data.rate_limit_multiplier;
+    if !(multiplier > 0.0 && multiplier <= MAX_RATE_LIMIT_MULTIPLIER) {
+      return Err(APIError::err("invalid_rate_limit_multiplier").into());
+    }
+
+    let api_token = match ApiToken::update_rate_limit_multiplier(&conn, data.id, multiplier) {
+      Ok(api_token) => api_token,
+      Err(_e) => return Err(APIError::err("couldnt_find_api_token").into()),
+    };
+
+    Ok(ApiTokenResponse { api_token })
+  }
+}
+
+impl Perform<CreateApiTokenResponse> for Oper<ImpersonateUser> {
+  fn perform(&self, conn: &PgConnection) -> Result<CreateApiTokenResponse, Error> {
+    let data: &ImpersonateUser = &self.data;
+
+    let admin_id = admin_user_id(conn, &data.auth)?;
+
+    // Every impersonation needs a reason in the log
+    if data.reason.trim().is_empty() {
+      return Err(APIError::err("impersonation_reason_required").into());
+    }
+
+    let user = match User_::read(&conn, data.user_id) {
+      Ok(user) => user,
+      Err(_e) => return Err(APIError::err("couldnt_find_that_username_or_email").into()),
+    };
+    if user.admin {
+      return Err(APIError::err("cant_impersonate_admin").into());
+    }
+
+    let api_token = transaction_with_retry(conn, || {
+      ApiToken::delete_expired(&conn)?;
+
+      let form = ApiTokenForm {
+        user_id: user.id,
+        name: format!("Impersonation by admin {}", admin_id),
+        scopes: vec![TokenScope::Read.to_string()],
+        rate_limit_multiplier: 1.0,
+        oauth_client_id: None,
+        impersonator_id: Some(admin_id),
+        expires: Some(naive_now() + chrono::Duration::minutes(IMPERSONATION_MINUTES)),
+        device_name: None,
+        client_app: None,
+      };
+      let api_token = match ApiToken::create(&conn, &form) {
+        Ok(api_token) => api_token,
+        Err(e) => return Err(APIError::from_db("couldnt_create_api_token", e)),
+      };
+
+      // Mod tables
+      let form = ModImpersonateForm {
+        mod_user_id: admin_id,
+        other_user_id: user.id,
+        reason: Some(data.reason.trim().to_owned()),
+        revoked: false,
+      };
+      ModImpersonate::create(&conn, &form)?;
+
+      Ok(api_token)
+    })?;
+
+    let token = user.api_token_jwt(api_token.id);
+
+    Ok(CreateApiTokenResponse { api_token, token })
+  }
+}
+
+impl Perform<ListImpersonationsResponse> for Oper<ListImpersonations> {
+  fn perform(&self, conn: &PgConnection) -> Result<ListImpersonationsResponse, Error> {
+    let data: &ListImpersonations = &self.data;
+
+    admin_user_id(conn, &data.auth)?;
+
+    let active = ApiToken::list_impersonations(&conn)?;
+    let log = ModImpersonate::list(&conn, data.page, data.limit)?;
+
+    Ok(ListImpersonationsResponse { active, log })
+  }
+}
+
+impl Perform<ListImpersonationsResponse> for Oper<RevokeImpersonation> {
+  fn perform(&self, conn: &PgConnection) -> Result<ListImpersonationsResponse, Error> {
+    let data: &RevokeImpersonation = &self.data;
+
+    let admin_id = admin_user_id(conn, &data.auth)?;
+
+    // Any admin can revoke any impersonation token, but only those
+    let api_token = match ApiToken::read(&conn, data.id) {
+      Ok(api_token) if api_token.impersonator_id.is_some() => api_token,
+      _ => return Err(APIError::err("couldnt_find_api_token").into()),
+    };
+
+    transaction_with_retry(conn, || {
+      if let Err(e) = ApiToken::delete(&conn, api_token.id) {
+        return Err(APIError::from_db("couldnt_find_api_token", e));
+      }
+
+      // Mod tables
+      let form = ModImpersonateForm {
+        mod_user_id: admin_id,
+        other_user_id: api_token.user_id,
+        reason: None,
+        revoked: true,
+      };
+      ModImpersonate::create(&conn, &form)?;
+
+      Ok(())
+    })?;
+
+    let active = ApiToken::list_impersonations(&conn)?;
+    let log = ModImpersonate::list(&conn, None, None)?;
+
+    Ok(ListImpersonationsResponse { active, log })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::db::establish_unpooled_connection;
+  use serde_json::json;
+
+  #[test]
+  fn test_required_scope() {
+    let none = json!({});
+    assert_eq!(
+      Some(TokenScope::Read),
+      required_scope(&UserOperation::GetPosts, &none)
+    );
+    assert_eq!(
+      Some(TokenScope::Write),
+      required_scope(&UserOperation::CreateComment, &none)
+    );
+    assert_eq!(
+      Some(TokenScope::Write),
+      required_scope(
+        &UserOperation::EditPost,
+        &json!({ "name": "A post", "removed": null })
+      )
+    );
+    assert_eq!(
+      Some(TokenScope::Moderate),
+      required_scope(&UserOperation::EditPost, &json!({ "locked": true }))
+    );
+    assert_eq!(
+      Some(TokenScope::Moderate),
+      required_scope(&UserOperation::BanFromCommunity, &none)
+    );
+    assert_eq!(
+      None,
+      required_scope(&UserOperation::SaveUserSettings, &none)
+    );
+    assert_eq!(None, required_scope(&UserOperation::CreateApiToken, &none));
+    assert_eq!(None, required_scope(&UserOperation::ImpersonateUser, &none));
+  }
+
+  #[test]
+  fn test_impersonation_private_messages() {
+    let conn = establish_unpooled_connection();
+    let admin_form = UserForm {
+      admin: true,
+      ..user_form("impersonating_admin")
+    };
+    let admin = User_::create(&conn, &admin_form).unwrap();
+    let user = User_::create(&conn, &user_form("impersonated_user")).unwrap();
+
+    let form = ApiTokenForm {
+      user_id: user.id,
+      name: "Impersonation".into(),
+      scopes: vec![TokenScope::Read.to_string()],
+      rate_limit_multiplier: 1.0,
+      oauth_client_id: None,
+      impersonator_id: Some(admin.id),
+      expires: Some(naive_now() + chrono::Duration::minutes(IMPERSONATION_MINUTES)),
+      device_name: None,
+      client_app: None,
+    };
+    let api_token = ApiToken::create(&conn, &form).unwrap();
+    let data = json!({ "auth": user.api_token_jwt(api_token.id) });
+
+    let posts = check_api_token(&conn, &UserOperation::GetPosts, &data, None);
+    let private_messages = check_api_token(&conn, &UserOperation::GetPrivateMessages, &data, None);
+    let inbox = check_api_token(&conn, &UserOperation::GetInbox, &data, None);
+
+    User_::delete(&conn, user.id).unwrap();
+    User_::delete(&conn, admin.id).unwrap();
+
+    assert_eq!(Some(api_token.id), posts.unwrap().map(|token| token.id));
+    assert!(private_messages.is_err());
+    assert!(inbox.is_err());
+  }
+}
+
+/// A device name or client app, which can be left out, but not be blank or too long.
+fn token_label(label: &Option<String>, error: &str) -> Result<Option<String>, Error> {
+  match label.as_ref().map(|label| label.trim()) {
+    None => Ok(None),
+    Some(label) if label.is_empty() || label.chars().count() > 100 => {
+      Err(APIError::err(error).into())
+    }
+    Some(label) => Ok(Some(label.to_owned())),
+  }
+}
@@ -1,8 +1,11 @@
 use super::*;
-use crate::send_email;
-use crate::settings::Settings;
+use crate::api::post::can_view_post;
+use crate::apub::cache::evict_apub_object;
+use crate::apub::flag::send_flag;
+use crate::apub::make_apub_endpoint;
 use diesel::PgConnection;
 use log::error;
+use std::collections::HashMap;
 use std::str::FromStr;
 
 #[derive(Serialize, Deserialize)]
@@ -11,6 +14,12 @@ pub struct CreateComment {
   parent_id: Option<i32>,
   edit_id: Option<i32>, // TODO this isn't used
   pub post_id: i32,
+  /// Agrees to the community's rules, if it requires that
+  acknowledge_rules: Option<bool>,
+  /// Made by a bot or generated by AI, which bot accounts have to set
+  generated: Option<bool>,
+  /// The `Language` it's in
+  language_id: Option<i32>,
   auth: String,
 }
 
@@ -24,7 +33,11 @@ pub struct EditComment {
   removed: Option<bool>,
   deleted: Option<bool>,
   reason: Option<String>,
+  /// A `RemoteModerationScope`, required to remove a remote comment
+  remote_scope: Option<String>,
   read: Option<bool>,
+  /// The `Language` it's in, left as it is if not given
+  language_id: Option<i32>,
   auth: String,
 }
 
@@ -32,6 +45,7 @@ pub struct EditComment {
 pub struct SaveComment {
   comment_id: i32,
   save: bool,
+  folder_id: Option<i32>,
   auth: String,
 }
 
@@ -56,6 +70,12 @@ pub struct GetComments {
   page: Option<i64>,
   limit: Option<i64>,
   pub community_id: Option<i32>,
+  /// Only the comments of this post
+  post_id: Option<i32>,
+  /// Only the comments containing this
+  search_term: Option<String>,
+  /// Only the comments of the users the user blocked, which are otherwise left out
+  blocked_only: Option<bool>,
   auth: Option<String>,
 }
 
@@ -64,6 +84,80 @@ pub struct GetCommentsResponse {
   comments: Vec<CommentView>,
 }
 
+/// A comment with the comments it's a reply to, and all of its replies. Clients load the replies
+/// of a megathread's top level comments with this.
+#[derive(Serialize, Deserialize)]
+pub struct GetCommentContext {
+  comment_id: i32,
+  auth: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetCommentContextResponse {
+  comments: Vec<CommentView>,
+}
+
+/// The earlier versions of a comment.
+#[derive(Serialize, Deserialize)]
+pub struct GetCommentEdits {
+  comment_id: CommentId,
+  auth: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetCommentEditsResponse {
+  /// The latest first
+  edits: Vec<CommentEdit>,
+}
+
+/// A subtree of a post's comments: the replies to `parent_id`, or the top level comments without
+/// it, `max_depth` levels deep, with at most `max_children` of each comment's replies.
+#[derive(Serialize, Deserialize)]
+pub struct GetCommentTree {
+  post_id: i32,
+  parent_id: Option<i32>,
+  max_depth: Option<i32>,
+  max_children: Option<i64>,
+  auth: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetCommentTreeResponse {
+  comments: Vec<CommentTreeView>,
+}
+
+const DEFAULT_TREE_DEPTH: i32 = 6;
+const MAX_TREE_DEPTH: i32 = 20;
+const DEFAULT_TREE_CHILDREN: i64 = 20;
+const MAX_TREE_CHILDREN: i64 = 100;
+
+/// Fingerprints a comment listing from its newest edit, its size and its vote totals.
+pub fn comments_fingerprint(comments: &[CommentView]) -> String {
+  let newest = comments
+    .iter()
+    .map(|c| c.updated.unwrap_or(c.published))
+    .max();
+  let upvotes: i64 = comments.iter().filter_map(|c| c.upvotes).sum();
+  let downvotes: i64 = comments.iter().filter_map(|c| c.downvotes).sum();
+  let my_votes: i32 = comments.iter().filter_map(|c| c.my_vote).sum();
+  let saved = comments.iter().filter(|c| c.saved == Some(true)).count();
+  format!(
+    "{}:{:?}:{}:{}:{}:{}",
+    comments.len(),
+    newest,
+    upvotes,
+    downvotes,
+    my_votes,
+    saved
+  )
+}
+
+impl ETag for GetCommentsResponse {
+  fn etag(&self) -> String {
+    weak_etag(&comments_fingerprint(&self.comments))
+  }
+}
+
 impl Perform<CommentResponse> for Oper<CreateComment> {
   fn perform(&self, conn: &PgConnection) -> Result<CommentResponse, Error> {
     let data: &CreateComment = &self.data;
@@ -75,7 +169,7 @@ impl Perform<CommentResponse> for Oper<CreateComment> {
 
     let user_id = claims.id;
 
-    let hostname = &format!("https://{}", Settings::get().hostname);
+    ip_blocklist::check(&conn, self.ip.as_deref(), true)?;
 
     // Check for a community ban
     let post = Post::read(&conn, data.post_id)?;
@@ -83,11 +177,22 @@ impl Perform<CommentResponse> for Oper<CreateComment> {
       return Err(APIError::err("community_ban").into());
     }
 
+    check_rules_ack(
+      &conn,
+      CommunityId(post.community_id),
+      UserId(user_id),
+      data.acknowledge_rules,
+    )?;
+
     // Check for a site ban
     if UserView::read(&conn, user_id)?.banned {
       return Err(APIError::err("site_ban").into());
     }
 
+    let generated = data.generated.unwrap_or(false);
+    check_generated(&conn, user_id, generated)?;
+    check_language(&conn, data.language_id)?;
+
     let content_slurs_removed = remove_slurs(&data.content.to_owned());
 
     let comment_form = CommentForm {
@@ -99,129 +204,88 @@ impl Perform<CommentResponse> for Oper<CreateComment> {
       deleted: None,
       read: None,
       updated: None,
+      generated: Some(generated),
+      language_id: data.language_id,
     };
 
-    let inserted_comment = match Comment::create(&conn, &comment_form) {
-      Ok(comment) => comment,
-      Err(_e) => return Err(APIError::err("couldnt_create_comment").into()),
-    };
-
-    let mut recipient_ids = Vec::new();
-
-    // Scan the comment for user mentions, add those rows
+    // Scan the comment for user mentions
     let extracted_usernames = extract_usernames(&comment_form.content);
 
-    for username_mention in &extracted_usernames {
-      if let Ok(mention_user) = User_::read_from_name(&conn, (*username_mention).to_string()) {
-        // You can't mention yourself
-        // At some point, make it so you can't tag the parent creator either
-        // This can cause two notifications, one for reply and the other for mention
-        if mention_user.id != user_id {
-          recipient_ids.push(mention_user.id);
-
-          let user_mention_form = UserMentionForm {
-            recipient_id: mention_user.id,
-            comment_id: inserted_comment.id,
-            read: None,
-          };
-
-          // Allow this to fail softly, since comment edits might re-update or replace it
-          // Let the uniqueness handle this fail
-          match UserMention::create(&conn, &user_mention_form) {
-            Ok(_mention) => (),
-            Err(_e) => error!("{}", &_e),
-          };
-
-          // Send an email to those users that have notifications on
-          if mention_user.send_notifications_to_email {
-            if let Some(mention_email) = mention_user.email {
-              let subject = &format!(
-                "{} - Mentioned by {}",
-                Settings::get().hostname,
-                claims.username
-              );
-              let html = &format!(
-                "<h1>User Mention</h1><br><div>{} - {}</div><br><a href={}/inbox>inbox</a>",
-                claims.username, comment_form.content, hostname
-              );
-              match send_email(subject, &mention_email, &mention_user.name, html) {
-                Ok(_o) => _o,
-                Err(e) => error!("{}", e),
-              };
-            }
+    let (inserted_comment, mention_users) = transaction_with_retry(conn, || {
+      let inserted_comment = match Comment::create(&conn, &comment_form) {
+        Ok(comment) => comment,
+        Err(e) => return Err(APIError::from_db("couldnt_create_comment", e)),
+      };
+
+      // Add the user mention rows
+      let mut mention_users = Vec::new();
+      for username_mention in &extracted_usernames {
+        if let Ok(mention_user) = User_::read_from_name(&conn, (*username_mention).to_string()) {
+          // You can't mention yourself
+          // At some point, make it so you can't tag the parent creator either
+          // This can cause two notifications, one for reply and the other for mention
+          if mention_user.id != user_id {
+            let user_mention_form = UserMentionForm {
+              recipient_id: mention_user.id,
+              comment_id: inserted_comment.id,
+              read: None,
+            };
+
+            // Allow this to fail softly, since comment edits might re-update or replace it
+            // Let the uniqueness handle this fail, in a savepoint so it doesn't abort the rest
+            match conn.transaction(|| UserMention::create(&conn, &user_mention_form)) {
+              Ok(_mention) => (),
+              Err(_e) => error!("{}", &_e),
+            };
+
+            mention_users.push(mention_user);
           }
         }
       }
-    }
 
-    // Send notifs to the parent commenter / poster
-    match data.parent_id {
-      Some(parent_id) => {
-        let parent_comment = Comment::read(&conn, parent_id)?;
-        if parent_comment.creator_id != user_id {
-          let parent_user = User_::read(&conn, parent_comment.creator_id)?;
-          recipient_ids.push(parent_user.id);
+      // You like your own comment by default
+      let like_form = CommentLikeForm {
+        comment_id: inserted_comment.id,
+        post_id: data.post_id,
+        user_id,
+        score: 1,
+      };
 
-          if parent_user.send_notifications_to_email {
-            if let Some(comment_reply_email) = parent_user.email {
-              let subject = &format!(
-                "{} - Reply from {}",
-                Settings::get().hostname,
-                claims.username
-              );
-              let html = &format!(
-                "<h1>Comment Reply</h1><br><div>{} - {}</div><br><a href={}/inbox>inbox</a>",
-                claims.username, comment_form.content, hostname
-              );
-              match send_email(subject, &comment_reply_email, &parent_user.name, html) {
-                Ok(_o) => _o,
-                Err(e) => error!("{}", e),
-              };
-            }
-          }
-        }
-      }
-      // Its a post
-      None => {
-        if post.creator_id != user_id {
-          let parent_user = User_::read(&conn, post.creator_id)?;
-          recipient_ids.push(parent_user.id);
+      let _inserted_like = match CommentLike::like(&conn, &like_form) {
+        Ok(like) => like,
+        Err(e) => return Err(APIError::from_db("couldnt_like_comment", e)),
+      };
 
-          if parent_user.send_notifications_to_email {
-            if let Some(post_reply_email) = parent_user.email {
-              let subject = &format!(
-                "{} - Reply from {}",
-                Settings::get().hostname,
-                claims.username
-              );
-              let html = &format!(
-                "<h1>Post Reply</h1><br><div>{} - {}</div><br><a href={}/inbox>inbox</a>",
-                claims.username, comment_form.content, hostname
-              );
-              match send_email(subject, &post_reply_email, &parent_user.name, html) {
-                Ok(_o) => _o,
-                Err(e) => error!("{}", e),
-              };
-            }
-          }
-        }
-      }
-    };
+      Ok((inserted_comment, mention_users))
+    })?;
 
-    // You like your own comment by default
-    let like_form = CommentLikeForm {
-      comment_id: inserted_comment.id,
-      post_id: data.post_id,
-      user_id,
-      score: 1,
+    // The parent commenter / poster
+    let parent_creator_id = match data.parent_id {
+      Some(parent_id) => Comment::read(&conn, parent_id)?.creator_id,
+      // Its a post
+      None => post.creator_id,
     };
-
-    let _inserted_like = match CommentLike::like(&conn, &like_form) {
-      Ok(like) => like,
-      Err(_e) => return Err(APIError::err("couldnt_like_comment").into()),
+    let replied_to = if parent_creator_id != user_id {
+      Some(User_::read(&conn, parent_creator_id)?)
+    } else {
+      None
     };
 
-    let comment_view = CommentView::read(&conn, inserted_comment.id, Some(user_id))?;
+    let mut recipient_ids: Vec<i32> = mention_users.iter().map(|u| u.id).collect();
+    recipient_ids.extend(replied_to.iter().map(|u| u.id));
+
+    let mut comment_view =
+      CommentView::read(&conn, CommentId(inserted_comment.id), Some(UserId(user_id)))?;
+
+    events::publish(Event::CommentCreated {
+      comment: comment_view.clone(),
+      mentioned: mention_users,
+      replied_to,
+    });
+
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      comment_view.hide_scores();
+    }
 
     Ok(CommentResponse {
       comment: comment_view,
@@ -240,8 +304,9 @@ impl Perform<CommentResponse> for Oper<EditComment> {
     };
 
     let user_id = claims.id;
+    check_language(&conn, data.language_id)?;
 
-    let orig_comment = CommentView::read(&conn, data.edit_id, None)?;
+    let orig_comment = CommentView::read(&conn, CommentId(data.edit_id), None)?;
 
     // You are allowed to mark the comment as read even if you're banned.
     if data.read.is_none() {
@@ -270,6 +335,14 @@ impl Perform<CommentResponse> for Oper<EditComment> {
       }
     }
 
+    let creator = User_::read(&conn, orig_comment.creator_id)?;
+    let remote_scope = match data.removed {
+      Some(removed) => {
+        remote_moderation_scope(&data.remote_scope, creator.actor_id.is_some(), removed)?
+      }
+      None => None,
+    };
+
     let content_slurs_removed = remove_slurs(&data.content.to_owned());
 
     let comment_form = CommentForm {
@@ -285,44 +358,83 @@ impl Perform<CommentResponse> for Oper<EditComment> {
       } else {
         Some(naive_now())
       },
+      generated: None,
+      language_id: data.language_id,
     };
 
-    let _updated_comment = match Comment::update(&conn, data.edit_id, &comment_form) {
-      Ok(comment) => comment,
-      Err(_e) => return Err(APIError::err("couldnt_update_comment").into()),
-    };
+    // Scan the comment for user mentions
+    let extracted_usernames = extract_usernames(&comment_form.content);
 
-    let mut recipient_ids = Vec::new();
+    let (mut recipient_ids, removal) = transaction_with_retry(conn, || {
+      // What it was is kept if the content changes
+      if orig_comment.content != comment_form.content {
+        let edit_form = CommentEditForm {
+          comment_id: data.edit_id,
+          editor_id: user_id,
+          content: orig_comment.content.to_owned(),
+        };
+        CommentEdit::create(&conn, &edit_form)?;
+      }
 
-    // Scan the comment for user mentions, add those rows
-    let extracted_usernames = extract_usernames(&comment_form.content);
+      let _updated_comment = match Comment::update(&conn, data.edit_id, &comment_form) {
+        Ok(comment) => comment,
+        Err(e) => return Err(APIError::from_db("couldnt_update_comment", e)),
+      };
 
-    for username_mention in &extracted_usernames {
-      let mention_user = User_::read_from_name(&conn, (*username_mention).to_string());
-
-      if mention_user.is_ok() {
-        let mention_user_id = mention_user?.id;
-
-        // You can't mention yourself
-        // At some point, make it so you can't tag the parent creator either
-        // This can cause two notifications, one for reply and the other for mention
-        if mention_user_id != user_id {
-          recipient_ids.push(mention_user_id);
-
-          let user_mention_form = UserMentionForm {
-            recipient_id: mention_user_id,
-            comment_id: data.edit_id,
-            read: None,
-          };
-
-          // Allow this to fail softly, since comment edits might re-update or replace it
-          // Let the uniqueness handle this fail
-          match UserMention::create(&conn, &user_mention_form) {
-            Ok(_mention) => (),
-            Err(_e) => error!("{}", &_e),
+      // Add the user mention rows
+      let mut mention_user_ids = Vec::new();
+      for username_mention in &extracted_usernames {
+        if let Ok(mention_user) = User_::read_from_name(&conn, (*username_mention).to_string()) {
+          // You can't mention yourself
+          // At some point, make it so you can't tag the parent creator either
+          // This can cause two notifications, one for reply and the other for mention
+          if mention_user.id != user_id {
+            mention_user_ids.push(mention_user.id);
+
+            let user_mention_form = UserMentionForm {
+              recipient_id: mention_user.id,
+              comment_id: data.edit_id,
+              read: None,
+            };
+
+            // Allow this to fail softly, since comment edits might re-update or replace it
+            // Let the uniqueness handle this fail, in a savepoint so it doesn't abort the rest
+            match conn.transaction(|| UserMention::create(&conn, &user_mention_form)) {
+              Ok(_mention) => (),
+              Err(_e) => error!("{}", &_e),
+            }
           }
         }
       }
+
+      // Mod tables
+      let mut removal = None;
+      if let Some(removed) = data.removed.to_owned() {
+        let form = ModRemoveCommentForm {
+          mod_user_id: user_id,
+          comment_id: data.edit_id,
+          removed: Some(removed),
+          reason: data.reason.to_owned(),
+          remote_scope: remote_scope.map(|scope| scope as i16),
+        };
+        removal = Some(ModRemoveComment::create(&conn, &form)?);
+      }
+
+      Ok((mention_user_ids, removal))
+    })?;
+    evict_apub_object("comment", data.edit_id);
+
+    if let (Some(RemoteModerationScope::Flag), Some(removal)) = (remote_scope, removal) {
+      send_flag(
+        &User_::read(&conn, user_id)?,
+        &creator.apub_id(),
+        make_apub_endpoint("mod_remove_comment", removal.id),
+        vec![
+          creator.apub_id(),
+          make_apub_endpoint("comment", data.edit_id),
+        ],
+        data.reason.to_owned(),
+      );
     }
 
     // Add to recipient ids
@@ -340,18 +452,23 @@ impl Perform<CommentResponse> for Oper<EditComment> {
       }
     }
 
-    // Mod tables
-    if let Some(removed) = data.removed.to_owned() {
-      let form = ModRemoveCommentForm {
-        mod_user_id: user_id,
-        comment_id: data.edit_id,
-        removed: Some(removed),
-        reason: data.reason.to_owned(),
-      };
-      ModRemoveComment::create(&conn, &form)?;
+    let mut comment_view =
+      CommentView::read(&conn, CommentId(data.edit_id), Some(UserId(user_id)))?;
+
+    if data.removed == Some(true) && !orig_comment.removed {
+      send_moderation_notice(
+        &conn,
+        orig_comment.creator_id,
+        user_id,
+        ModerationNoticeKind::CommentRemoved,
+        Some(data.edit_id),
+        &data.reason,
+      );
     }
 
-    let comment_view = CommentView::read(&conn, data.edit_id, Some(user_id))?;
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      comment_view.hide_scores();
+    }
 
     Ok(CommentResponse {
       comment: comment_view,
@@ -371,9 +488,12 @@ impl Perform<CommentResponse> for Oper<SaveComment> {
 
     let user_id = claims.id;
 
+    check_saved_folder(&conn, data.folder_id, user_id)?;
+
     let comment_saved_form = CommentSavedForm {
       comment_id: data.comment_id,
       user_id,
+      folder_id: data.folder_id,
     };
 
     if data.save {
@@ -388,7 +508,12 @@ impl Perform<CommentResponse> for Oper<SaveComment> {
       };
     }
 
-    let comment_view = CommentView::read(&conn, data.comment_id, Some(user_id))?;
+    let mut comment_view =
+      CommentView::read(&conn, CommentId(data.comment_id), Some(UserId(user_id)))?;
+
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      comment_view.hide_scores();
+    }
 
     Ok(CommentResponse {
       comment: comment_view,
@@ -465,7 +590,12 @@ impl Perform<CommentResponse> for Oper<CreateCommentLike> {
     }
 
     // Have to refetch the comment to get the current state
-    let liked_comment = CommentView::read(&conn, data.comment_id, Some(user_id))?;
+    let mut liked_comment =
+      CommentView::read(&conn, CommentId(data.comment_id), Some(UserId(user_id)))?;
+
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      liked_comment.hide_scores();
+    }
 
     Ok(CommentResponse {
       comment: liked_comment,
@@ -491,14 +621,30 @@ impl Perform<GetCommentsResponse> for Oper<GetComments> {
       None => None,
     };
 
+    check_private_instance(user_id)?;
+
     let type_ = ListingType::from_str(&data.type_)?;
     let sort = SortType::from_str(&data.sort)?;
 
-    let comments = match CommentQueryBuilder::create(&conn)
+    if let Some(post_id) = data.post_id {
+      let post_view = match PostView::read(&conn, PostId(post_id), user_id.map(UserId)) {
+        Ok(post) => post,
+        Err(_e) => return Err(APIError::err("couldnt_find_post").into()),
+      };
+      if !can_view_post(&conn, &post_view, user_id)? {
+        return Err(APIError::err("couldnt_find_post").into());
+      }
+    }
+
+    let mut comments = match CommentQueryBuilder::create(&conn)
       .listing_type(type_)
       .sort(&sort)
-      .for_community_id(data.community_id)
-      .my_user_id(user_id)
+      .for_community_id(data.community_id.map(CommunityId))
+      .for_post_id(data.post_id.map(PostId))
+      .search_term(non_empty(&data.search_term))
+      .hide_generated(User_::hides_generated(&conn, user_id)?)
+      .my_user_id(user_id.map(UserId))
+      .blocked_only(data.blocked_only.unwrap_or(false))
       .page(data.page)
       .limit(data.limit)
       .list()
@@ -507,6 +653,152 @@ impl Perform<GetCommentsResponse> for Oper<GetComments> {
       Err(_e) => return Err(APIError::err("couldnt_get_comments").into()),
     };
 
+    if Site::hides_scores_from(&conn, user_id)? {
+      comments.hide_scores();
+    }
+
     Ok(GetCommentsResponse { comments })
   }
 }
+
+impl Perform<GetCommentContextResponse> for Oper<GetCommentContext> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetCommentContextResponse, Error> {
+    let data: &GetCommentContext = &self.data;
+
+    let user_id: Option<i32> = match &data.auth {
+      Some(auth) => match Claims::decode(&auth) {
+        Ok(claims) => Some(claims.claims.id),
+        Err(_e) => None,
+      },
+      None => None,
+    };
+
+    check_private_instance(user_id)?;
+
+    let comment = match Comment::read(&conn, data.comment_id) {
+      Ok(comment) => comment,
+      Err(_e) => return Err(APIError::err("couldnt_find_comment").into()),
+    };
+    let post_view = PostView::read(&conn, PostId(comment.post_id), user_id.map(UserId))?;
+    if !can_view_post(&conn, &post_view, user_id)? {
+      return Err(APIError::err("couldnt_find_comment").into());
+    }
+
+    let comment_ids = Comment::context_ids(&conn, data.comment_id)?;
+    let mut comments = CommentQueryBuilder::create(&conn)
+      .for_post_id(PostId(comment.post_id))
+      .for_comment_ids(comment_ids)
+      .my_user_id(user_id.map(UserId))
+      .limit(9999)
+      .list()?;
+
+    if Site::hides_scores_from(&conn, user_id)? {
+      comments.hide_scores();
+    }
+
+    Ok(GetCommentContextResponse { comments })
+  }
+}
+
+impl Perform<GetCommentEditsResponse> for Oper<GetCommentEdits> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetCommentEditsResponse, Error> {
+    let data: &GetCommentEdits = &self.data;
+
+    let user_id: Option<i32> = match &data.auth {
+      Some(auth) => match Claims::decode(&auth) {
+        Ok(claims) => Some(claims.claims.id),
+        Err(_e) => None,
+      },
+      None => None,
+    };
+
+    check_private_instance(user_id)?;
+
+    let comment = match Comment::read(&conn, data.comment_id.0) {
+      Ok(comment) => comment,
+      Err(_e) => return Err(APIError::err("couldnt_find_comment").into()),
+    };
+    let post_view = PostView::read(&conn, PostId(comment.post_id), user_id.map(UserId))?;
+    if !can_view_post(&conn, &post_view, user_id)? {
+      return Err(APIError::err("couldnt_find_comment").into());
+    }
+    check_edit_history_access(
+      &conn,
+      CommunityId(post_view.community_id),
+      user_id.map(UserId),
+    )?;
+
+    let edits = CommentEdit::list_for_comment(&conn, data.comment_id.0)?;
+
+    Ok(GetCommentEditsResponse { edits })
+  }
+}
+
+impl Perform<GetCommentTreeResponse> for Oper<GetCommentTree> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetCommentTreeResponse, Error> {
+    let data: &GetCommentTree = &self.data;
+
+    let user_id: Option<i32> = match &data.auth {
+      Some(auth) => match Claims::decode(&auth) {
+        Ok(claims) => Some(claims.claims.id),
+        Err(_e) => None,
+      },
+      None => None,
+    };
+
+    check_private_instance(user_id)?;
+
+    let max_depth = data.max_depth.unwrap_or(DEFAULT_TREE_DEPTH);
+    let max_children = data.max_children.unwrap_or(DEFAULT_TREE_CHILDREN);
+    if max_depth < 1 || max_depth > MAX_TREE_DEPTH {
+      return Err(APIError::err("invalid_max_depth").into());
+    }
+    if max_children < 1 || max_children > MAX_TREE_CHILDREN {
+      return Err(APIError::err("invalid_max_children").into());
+    }
+
+    let post_view = match PostView::read(&conn, PostId(data.post_id), user_id.map(UserId)) {
+      Ok(post) => post,
+      Err(_e) => return Err(APIError::err("couldnt_find_post").into()),
+    };
+    if !can_view_post(&conn, &post_view, user_id)? {
+      return Err(APIError::err("couldnt_find_post").into());
+    }
+
+    if let Some(parent_id) = data.parent_id {
+      match Comment::read(&conn, parent_id) {
+        Ok(parent) if parent.post_id == data.post_id => (),
+        _ => return Err(APIError::err("couldnt_find_comment").into()),
+      }
+    }
+
+    let nodes = Comment::tree(&conn, data.post_id, data.parent_id, max_depth, max_children)?;
+    let mut views = CommentQueryBuilder::create(&conn)
+      .for_post_id(PostId(data.post_id))
+      .for_comment_ids(nodes.iter().map(|n| n.id).collect())
+      .my_user_id(user_id.map(UserId))
+      .limit(9999)
+      .list()?
+      .into_iter()
+      .map(|c| (c.id, c))
+      .collect::<HashMap<i32, CommentView>>();
+
+    // In the tree's order, which the listing doesn't keep
+    let mut comments = nodes
+      .into_iter()
+      .filter_map(|node| {
+        views.remove(&node.id).map(|comment| CommentTreeView {
+          comment,
+          depth: node.depth,
+          has_more_children: node.has_more_children,
+        })
+      })
+      .collect::<Vec<CommentTreeView>>();
+
+    if Site::hides_scores_from(&conn, user_id)? {
+      comments.hide_scores();
+    }
+
+    Ok(GetCommentTreeResponse { comments })
+  }
+}
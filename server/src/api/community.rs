@@ -1,5 +1,13 @@
 use super::*;
-use diesel::PgConnection;
+use crate::apub::cache::evict_apub_object;
+use crate::apub::community::send_update_group;
+use crate::apub::is_federated;
+use crate::db::community_growth::{
+  is_subscription_spike, milestone, CommunityGrowth, CommunityGrowthCounts,
+};
+use crate::db::post_activity_hour::PostActivityHour;
+use crate::db::report::{ReportGroup, ReportQueryBuilder};
+use diesel::{OptionalExtension, PgConnection};
 use std::str::FromStr;
 
 #[derive(Serialize, Deserialize)]
@@ -24,6 +32,11 @@ pub struct CreateCommunity {
   description: Option<String>,
   category_id: i32,
   nsfw: bool,
+  rules: Option<String>,
+  require_rules_ack: Option<bool>,
+  require_alt_text: Option<bool>,
+  /// Keeps the community, and everything in it, off of activitypub
+  local_only: Option<bool>,
   auth: String,
 }
 
@@ -77,6 +90,7 @@ pub struct AddModToCommunityResponse {
 #[derive(Serialize, Deserialize)]
 pub struct EditCommunity {
   pub edit_id: i32,
+  /// Only admins can rename a community. Its old names keep working.
   name: String,
   title: String,
   description: Option<String>,
@@ -84,6 +98,18 @@ pub struct EditCommunity {
   removed: Option<bool>,
   deleted: Option<bool>,
   nsfw: bool,
+  /// Left alone if neither of the rules fields are sent, and removed if sent empty
+  rules: Option<String>,
+  require_rules_ack: Option<bool>,
+  /// Left as it is if not given
+  require_alt_text: Option<bool>,
+  /// Left as it is if not given
+  local_only: Option<bool>,
+  /// Only for admins, left as it is if not given
+  hidden: Option<bool>,
+  /// How many users reporting a post or comment removes it. Left as it is if not given, and
+  /// turned off with 0.
+  report_auto_remove_threshold: Option<i32>,
   reason: Option<String>,
   expires: Option<i64>,
   auth: String,
@@ -106,6 +132,30 @@ pub struct GetFollowedCommunitiesResponse {
   communities: Vec<CommunityFollowerView>,
 }
 
+/// Blocks or unblocks a community. Blocking it also unsubscribes from it.
+#[derive(Serialize, Deserialize)]
+pub struct BlockCommunity {
+  community_id: i32,
+  block: bool,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BlockCommunityResponse {
+  community: CommunityView,
+  blocked: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetBlockedCommunities {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetBlockedCommunitiesResponse {
+  communities: Vec<CommunityView>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct TransferCommunity {
   community_id: i32,
@@ -113,6 +163,132 @@ pub struct TransferCommunity {
   auth: String,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct CreateModNote {
+  community_id: i32,
+  /// The user the note is about
+  user_id: i32,
+  content: String,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EditModNote {
+  edit_id: i32,
+  /// Left as it is if not given
+  content: Option<String>,
+  /// Left as it is if not given
+  deleted: Option<bool>,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ModNoteResponse {
+  note: ModNoteView,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetModNotes {
+  community_id: i32,
+  user_id: Option<i32>,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetModNotesResponse {
+  notes: Vec<ModNoteView>,
+  /// Every version of the notes, and of the deleted ones, oldest first
+  revisions: Vec<ModNoteRevisionView>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateScheduledPost {
+  community_id: i32,
+  /// The title of the posts, where `{date}` is the day it's made
+  name: String,
+  body: Option<String>,
+  /// 0 is Monday, every day if not given
+  weekday: Option<i16>,
+  /// The hour of the day, in UTC
+  hour: i16,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeleteScheduledPost {
+  scheduled_post_id: i32,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetScheduledPosts {
+  community_id: i32,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetScheduledPostsResponse {
+  /// The community's scheduled posts, the next to be made first
+  scheduled_posts: Vec<ScheduledPost>,
+}
+
+/// Subscribes to a digest of a community's most reported and fastest growing posts, or
+/// unsubscribes if `interval_days` isn't given.
+#[derive(Serialize, Deserialize)]
+pub struct SaveModDigest {
+  community_id: i32,
+  /// How many days apart the digests are, from 1 to 7
+  interval_days: Option<i16>,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetModDigests {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetModDigestsResponse {
+  digests: Vec<ModDigest>,
+}
+
+/// How many of the most reported, and of the fastest growing, posts a digest has.
+const MOD_DIGEST_ITEMS: i64 = 5;
+
+/// How many of the oldest open reports a digest's most reported are counted from.
+const MOD_DIGEST_REPORTS: i64 = 500;
+
+/// The length the reported comments are cut to in a digest.
+const MOD_DIGEST_EXCERPT_LENGTH: usize = 100;
+
+/// The most scheduled posts a community can have.
+const MAX_SCHEDULED_POSTS: usize = 10;
+
+/// Fingerprints a community from its last edit, its counts and the viewer's subscription.
+pub fn community_fingerprint(community: &CommunityView) -> String {
+  format!(
+    "{}:{:?}:{}:{}:{}:{:?}",
+    community.id,
+    community.updated.unwrap_or(community.published),
+    community.number_of_subscribers,
+    community.number_of_posts,
+    community.number_of_comments,
+    community.subscribed
+  )
+}
+
+impl ETag for GetCommunityResponse {
+  fn etag(&self) -> String {
+    let fingerprint = format!(
+      "{}|{}:{}",
+      community_fingerprint(&self.community),
+      self.moderators.len(),
+      self.admins.len(),
+    );
+    weak_etag(&fingerprint)
+  }
+}
+
 impl Perform<GetCommunityResponse> for Oper<GetCommunity> {
   fn perform(&self, conn: &PgConnection) -> Result<GetCommunityResponse, Error> {
     let data: &GetCommunity = &self.data;
@@ -128,6 +304,8 @@ impl Perform<GetCommunityResponse> for Oper<GetCommunity> {
       None => None,
     };
 
+    check_private_instance(user_id)?;
+
     let community_id = match data.id {
       Some(id) => id,
       None => {
@@ -167,6 +345,55 @@ impl Perform<GetCommunityResponse> for Oper<GetCommunity> {
   }
 }
 
+/// Checks the site's restrictions on who can create communities, and the reserved names. Admins
+/// can create any community.
+fn check_community_creation(
+  conn: &PgConnection,
+  user: &UserView,
+  community_name: &str,
+) -> Result<(), Error> {
+  if user.admin {
+    return Ok(());
+  }
+
+  if ReservedCommunityName::is_reserved(&conn, community_name)? {
+    return Err(APIError::err("community_name_reserved").into());
+  }
+
+  // Before the site is set up, there's nothing to enforce
+  let site = match Site::read(&conn, 1) {
+    Ok(site) => site,
+    Err(_e) => return Ok(()),
+  };
+
+  if site.community_creation_admin_only {
+    return Err(CommunityCreationRestricted::AdminOnly.into());
+  }
+
+  let min_account_age = chrono::Duration::days(site.community_creation_min_account_age_days.into());
+  if naive_now() - user.published < min_account_age {
+    return Err(
+      CommunityCreationRestricted::AccountTooNew {
+        min_account_age_days: site.community_creation_min_account_age_days,
+      }
+      .into(),
+    );
+  }
+
+  let karma = user.post_score.unwrap_or(0) + user.comment_score.unwrap_or(0);
+  if karma < site.community_creation_min_karma.into() {
+    return Err(
+      CommunityCreationRestricted::NotEnoughKarma {
+        min_karma: site.community_creation_min_karma,
+        karma,
+      }
+      .into(),
+    );
+  }
+
+  Ok(())
+}
+
 impl Perform<CommunityResponse> for Oper<CreateCommunity> {
   fn perform(&self, conn: &PgConnection) -> Result<CommunityResponse, Error> {
     let data: &CreateCommunity = &self.data;
@@ -190,13 +417,27 @@ impl Perform<CommunityResponse> for Oper<CreateCommunity> {
       }
     }
 
+    if let Some(rules) = &data.rules {
+      if let Err(slurs) = slur_check(rules) {
+        return Err(APIError::err(&slurs_vec_to_str(slurs)).into());
+      }
+    }
+
     let user_id = claims.id;
 
     // Check for a site ban
-    if UserView::read(&conn, user_id)?.banned {
+    let user = UserView::read(&conn, user_id)?;
+    if user.banned {
       return Err(APIError::err("site_ban").into());
     }
 
+    check_community_creation(&conn, &user, &data.name)?;
+
+    // Nor can it take the old name of a renamed community
+    if Community::read_from_name(&conn, data.name.to_owned()).is_ok() {
+      return Err(APIError::err("community_already_exists").into());
+    }
+
     // When you create a community, make sure the user becomes a moderator and a follower
     let community_form = CommunityForm {
       name: data.name.to_owned(),
@@ -210,33 +451,58 @@ impl Perform<CommunityResponse> for Oper<CreateCommunity> {
       updated: None,
     };
 
-    let inserted_community = match Community::create(&conn, &community_form) {
-      Ok(community) => community,
-      Err(_e) => return Err(APIError::err("community_already_exists").into()),
-    };
-
-    let community_moderator_form = CommunityModeratorForm {
-      community_id: inserted_community.id,
-      user_id,
-    };
+    let inserted_community = transaction_with_retry(conn, || {
+      let inserted_community = match Community::create(&conn, &community_form) {
+        Ok(community) => community,
+        Err(e) => return Err(APIError::from_db("community_already_exists", e)),
+      };
 
-    let _inserted_community_moderator =
-      match CommunityModerator::join(&conn, &community_moderator_form) {
-        Ok(user) => user,
-        Err(_e) => return Err(APIError::err("community_moderator_already_exists").into()),
+      let community_moderator_form = CommunityModeratorForm {
+        community_id: inserted_community.id,
+        user_id,
       };
 
-    let community_follower_form = CommunityFollowerForm {
-      community_id: inserted_community.id,
-      user_id,
-    };
+      let _inserted_community_moderator =
+        match CommunityModerator::join(&conn, &community_moderator_form) {
+          Ok(user) => user,
+          Err(e) => return Err(APIError::from_db("community_moderator_already_exists", e)),
+        };
 
-    let _inserted_community_follower =
-      match CommunityFollower::follow(&conn, &community_follower_form) {
-        Ok(user) => user,
-        Err(_e) => return Err(APIError::err("community_follower_already_exists").into()),
+      let community_follower_form = CommunityFollowerForm {
+        community_id: inserted_community.id,
+        user_id,
       };
 
+      let _inserted_community_follower =
+        match CommunityFollower::follow(&conn, &community_follower_form) {
+          Ok(user) => user,
+          Err(e) => return Err(APIError::from_db("community_follower_already_exists", e)),
+        };
+
+      if let Err(e) = Community::update_rules(
+        &conn,
+        inserted_community.id,
+        non_empty(&data.rules),
+        data.require_rules_ack.unwrap_or(false),
+      ) {
+        return Err(APIError::from_db("couldnt_update_community", e));
+      }
+
+      if data.require_alt_text == Some(true) {
+        if let Err(e) = Community::update_require_alt_text(&conn, inserted_community.id, true) {
+          return Err(APIError::from_db("couldnt_update_community", e));
+        }
+      }
+
+      if data.local_only == Some(true) {
+        if let Err(e) = Community::update_local_only(&conn, inserted_community.id, true) {
+          return Err(APIError::from_db("couldnt_update_community", e));
+        }
+      }
+
+      Ok(inserted_community)
+    })?;
+
     let community_view = CommunityView::read(&conn, inserted_community.id, Some(user_id))?;
 
     Ok(CommunityResponse {
@@ -263,6 +529,12 @@ impl Perform<CommunityResponse> for Oper<EditCommunity> {
       }
     }
 
+    if let Some(rules) = &data.rules {
+      if let Err(slurs) = slur_check(rules) {
+        return Err(APIError::err(&slurs_vec_to_str(slurs)).into());
+      }
+    }
+
     let claims = match Claims::decode(&data.auth) {
       Ok(claims) => claims.claims,
       Err(_e) => return Err(APIError::err("not_logged_in").into()),
@@ -288,8 +560,28 @@ impl Perform<CommunityResponse> for Oper<EditCommunity> {
       return Err(APIError::err("no_community_edit_allowed").into());
     }
 
+    // Only admins can hide or rename a community
+    let is_admin = UserView::read(&conn, user_id)?.admin;
+    if data.hidden.is_some() && !is_admin {
+      return Err(APIError::err("not_an_admin").into());
+    }
+
+    let read_community = Community::read(&conn, data.edit_id)?;
+    let renamed = data.name != read_community.name;
+    if renamed {
+      if !is_admin {
+        return Err(APIError::err("not_an_admin").into());
+      }
+      match Community::read_from_name(&conn, data.name.to_owned()) {
+        Ok(community) if community.id != data.edit_id => {
+          return Err(APIError::err("community_already_exists").into())
+        }
+        _ => (),
+      }
+    }
+
     let community_form = CommunityForm {
-      name: data.name.to_owned(),
+      name: read_community.name.to_owned(),
       title: data.title.to_owned(),
       description: data.description.to_owned(),
       category_id: data.category_id.to_owned(),
@@ -300,25 +592,87 @@ impl Perform<CommunityResponse> for Oper<EditCommunity> {
       updated: Some(naive_now()),
     };
 
-    let _updated_community = match Community::update(&conn, data.edit_id, &community_form) {
-      Ok(community) => community,
-      Err(_e) => return Err(APIError::err("couldnt_update_community").into()),
-    };
-
-    // Mod tables
-    if let Some(removed) = data.removed.to_owned() {
-      let expires = match data.expires {
-        Some(time) => Some(naive_from_unix(time)),
-        None => None,
+    transaction_with_retry(conn, || {
+      let updated_community = match Community::update(&conn, data.edit_id, &community_form) {
+        Ok(community) => community,
+        Err(e) => return Err(APIError::from_db("couldnt_update_community", e)),
       };
-      let form = ModRemoveCommunityForm {
-        mod_user_id: user_id,
-        community_id: data.edit_id,
-        removed: Some(removed),
-        reason: data.reason.to_owned(),
-        expires,
-      };
-      ModRemoveCommunity::create(&conn, &form)?;
+
+      if data.rules.is_some() || data.require_rules_ack.is_some() {
+        let rules = match &data.rules {
+          Some(_) => non_empty(&data.rules),
+          None => updated_community.rules,
+        };
+        let require_rules_ack = data
+          .require_rules_ack
+          .unwrap_or(updated_community.require_rules_ack);
+        if let Err(e) = Community::update_rules(&conn, data.edit_id, rules, require_rules_ack) {
+          return Err(APIError::from_db("couldnt_update_community", e));
+        }
+      }
+
+      if let Some(require_alt_text) = data.require_alt_text {
+        if let Err(e) = Community::update_require_alt_text(&conn, data.edit_id, require_alt_text) {
+          return Err(APIError::from_db("couldnt_update_community", e));
+        }
+      }
+
+      if let Some(local_only) = data.local_only {
+        if let Err(e) = Community::update_local_only(&conn, data.edit_id, local_only) {
+          return Err(APIError::from_db("couldnt_update_community", e));
+        }
+      }
+
+      if let Some(hidden) = data.hidden {
+        if let Err(e) = Community::update_hidden(&conn, data.edit_id, hidden) {
+          return Err(APIError::from_db("couldnt_update_community", e));
+        }
+      }
+
+      if let Some(threshold) = data.report_auto_remove_threshold {
+        let threshold = if threshold > 0 { Some(threshold) } else { None };
+        if let Err(e) =
+          Community::update_report_auto_remove_threshold(&conn, data.edit_id, threshold)
+        {
+          return Err(APIError::from_db("couldnt_update_community", e));
+        }
+      }
+
+      if renamed {
+        let actor_id = read_community.apub_id();
+        if let Err(e) = Community::rename(&conn, data.edit_id, &data.name, &actor_id) {
+          return Err(APIError::from_db("community_already_exists", e));
+        }
+      }
+
+      // Mod tables
+      if let Some(removed) = data.removed.to_owned() {
+        let expires = match data.expires {
+          Some(time) => Some(naive_from_unix(time)),
+          None => None,
+        };
+        let form = ModRemoveCommunityForm {
+          mod_user_id: user_id,
+          community_id: data.edit_id,
+          removed: Some(removed),
+          reason: data.reason.to_owned(),
+          expires,
+        };
+        ModRemoveCommunity::create(&conn, &form)?;
+      }
+
+      Ok(())
+    })?;
+    evict_apub_object("c", &read_community.name);
+    if renamed {
+      evict_apub_object("c", &data.name);
+    }
+
+    if renamed {
+      let community = Community::read(&conn, data.edit_id)?;
+      if is_federated(&community) {
+        send_update_group(&conn, &community);
+      }
     }
 
     let community_view = CommunityView::read(&conn, data.edit_id, Some(user_id))?;
@@ -346,6 +700,8 @@ impl Perform<ListCommunitiesResponse> for Oper<ListCommunities> {
       None => None,
     };
 
+    check_private_instance(user_id)?;
+
     let show_nsfw = match &user_claims {
       Some(claims) => claims.show_nsfw,
       None => false,
@@ -355,7 +711,7 @@ impl Perform<ListCommunitiesResponse> for Oper<ListCommunities> {
 
     let communities = CommunityQueryBuilder::create(&conn)
       .sort(&sort)
-      .for_user(user_id)
+      .for_user(user_id.map(UserId))
       .show_nsfw(show_nsfw)
       .page(data.page)
       .limit(data.limit)
@@ -424,6 +780,65 @@ impl Perform<GetFollowedCommunitiesResponse> for Oper<GetFollowedCommunities> {
   }
 }
 
+impl Perform<BlockCommunityResponse> for Oper<BlockCommunity> {
+  fn perform(&self, conn: &PgConnection) -> Result<BlockCommunityResponse, Error> {
+    let data: &BlockCommunity = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    if Community::read(&conn, data.community_id).is_err() {
+      return Err(APIError::err("couldnt_find_community").into());
+    }
+
+    let form = CommunityBlockForm {
+      community_id: data.community_id,
+      user_id,
+    };
+    if data.block {
+      if let Err(e) = CommunityBlock::block(&conn, &form) {
+        return Err(APIError::from_db("couldnt_block_community", e));
+      }
+      let community_follower_form = CommunityFollowerForm {
+        community_id: data.community_id,
+        user_id,
+      };
+      CommunityFollower::ignore(&conn, &community_follower_form)?;
+    } else {
+      CommunityBlock::unblock(&conn, &form)?;
+    }
+
+    let community = CommunityView::read(&conn, data.community_id, Some(user_id))?;
+
+    Ok(BlockCommunityResponse {
+      community,
+      blocked: data.block,
+    })
+  }
+}
+
+impl Perform<GetBlockedCommunitiesResponse> for Oper<GetBlockedCommunities> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetBlockedCommunitiesResponse, Error> {
+    let data: &GetBlockedCommunities = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    let community_ids = CommunityBlock::community_ids_for_user(&conn, user_id)?;
+    let communities = CommunityView::read_many(&conn, &community_ids, Some(user_id))?;
+
+    Ok(GetBlockedCommunitiesResponse { communities })
+  }
+}
+
 impl Perform<BanFromCommunityResponse> for Oper<BanFromCommunity> {
   fn perform(&self, conn: &PgConnection) -> Result<BanFromCommunityResponse, Error> {
     let data: &BanFromCommunity = &self.data;
@@ -435,9 +850,16 @@ impl Perform<BanFromCommunityResponse> for Oper<BanFromCommunity> {
 
     let user_id = claims.id;
 
+    // Temporary bans are lifted once they expire
+    let expires = match data.expires {
+      Some(time) => Some(naive_from_unix(time)),
+      None => None,
+    };
+
     let community_user_ban_form = CommunityUserBanForm {
       community_id: data.community_id,
       user_id: data.user_id,
+      expires,
     };
 
     if data.ban {
@@ -453,11 +875,6 @@ impl Perform<BanFromCommunityResponse> for Oper<BanFromCommunity> {
     }
 
     // Mod tables
-    let expires = match data.expires {
-      Some(time) => Some(naive_from_unix(time)),
-      None => None,
-    };
-
     let form = ModBanFromCommunityForm {
       mod_user_id: user_id,
       other_user_id: data.user_id,
@@ -468,6 +885,17 @@ impl Perform<BanFromCommunityResponse> for Oper<BanFromCommunity> {
     };
     ModBanFromCommunity::create(&conn, &form)?;
 
+    if data.ban {
+      send_moderation_notice(
+        &conn,
+        data.user_id,
+        user_id,
+        ModerationNoticeKind::BannedFromCommunity,
+        Some(data.community_id),
+        &data.reason,
+      );
+    }
+
     let user_view = UserView::read(&conn, data.user_id)?;
 
     Ok(BanFromCommunityResponse {
@@ -560,6 +988,7 @@ impl Perform<GetCommunityResponse> for Oper<TransferCommunity> {
       Ok(community) => community,
       Err(_e) => return Err(APIError::err("couldnt_update_community").into()),
     };
+    evict_apub_object("c", &community_form.name);
 
     // You also have to re-do the community_moderator table, reordering it.
     let mut community_mods = CommunityModeratorView::for_community(&conn, data.community_id)?;
@@ -613,3 +1042,606 @@ impl Perform<GetCommunityResponse> for Oper<TransferCommunity> {
     })
   }
 }
+
+impl Perform<ModNoteResponse> for Oper<CreateModNote> {
+  fn perform(&self, conn: &PgConnection) -> Result<ModNoteResponse, Error> {
+    let data: &CreateModNote = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    check_mod_or_admin(&conn, CommunityId(data.community_id), UserId(user_id))?;
+
+    let content = match non_empty(&Some(data.content.to_owned())) {
+      Some(content) => content,
+      None => return Err(APIError::err("mod_note_empty").into()),
+    };
+
+    let form = ModNoteForm {
+      community_id: data.community_id,
+      user_id: data.user_id,
+      creator_id: user_id,
+      content,
+      deleted: None,
+      updated: None,
+    };
+
+    let note = match ModNote::save(&conn, None, &form, user_id) {
+      Ok(note) => note,
+      Err(_e) => return Err(APIError::err("couldnt_create_mod_note").into()),
+    };
+
+    Ok(ModNoteResponse {
+      note: ModNoteView::read(&conn, note.id)?,
+    })
+  }
+}
+
+impl Perform<ModNoteResponse> for Oper<EditModNote> {
+  fn perform(&self, conn: &PgConnection) -> Result<ModNoteResponse, Error> {
+    let data: &EditModNote = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    let read_note = ModNote::read(&conn, data.edit_id)?;
+
+    // The whole mod team shares the notes, so any of them can edit one
+    check_mod_or_admin(&conn, CommunityId(read_note.community_id), UserId(user_id))?;
+
+    let content = match &data.content {
+      Some(content) => match non_empty(&Some(content.to_owned())) {
+        Some(content) => content,
+        None => return Err(APIError::err("mod_note_empty").into()),
+      },
+      None => read_note.content,
+    };
+
+    let form = ModNoteForm {
+      community_id: read_note.community_id,
+      user_id: read_note.user_id,
+      creator_id: read_note.creator_id,
+      content,
+      deleted: data.deleted,
+      updated: Some(naive_now()),
+    };
+
+    let note = match ModNote::save(&conn, Some(data.edit_id), &form, user_id) {
+      Ok(note) => note,
+      Err(_e) => return Err(APIError::err("couldnt_update_mod_note").into()),
+    };
+
+    Ok(ModNoteResponse {
+      note: ModNoteView::read(&conn, note.id)?,
+    })
+  }
+}
+
+impl Perform<GetModNotesResponse> for Oper<GetModNotes> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetModNotesResponse, Error> {
+    let data: &GetModNotes = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    check_mod_or_admin(&conn, CommunityId(data.community_id), UserId(user_id))?;
+
+    // The revisions of deleted notes are kept in the audit trail too
+    let all_notes = ModNoteView::list(&conn, Some(vec![data.community_id]), data.user_id, true)?;
+    let revisions =
+      ModNoteRevisionView::for_notes(&conn, all_notes.iter().map(|n| n.id).collect())?;
+    let notes = all_notes.into_iter().filter(|n| !n.deleted).collect();
+
+    Ok(GetModNotesResponse { notes, revisions })
+  }
+}
+
+impl Perform<GetScheduledPostsResponse> for Oper<CreateScheduledPost> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetScheduledPostsResponse, Error> {
+    let data: &CreateScheduledPost = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    check_mod_or_admin(&conn, CommunityId(data.community_id), UserId(user_id))?;
+
+    let name = match non_empty(&Some(data.name.to_owned())) {
+      Some(name) => name,
+      None => return Err(APIError::err("invalid_post_title").into()),
+    };
+
+    if let Err(slurs) = slur_check(&name) {
+      return Err(APIError::err(&slurs_vec_to_str(slurs)).into());
+    }
+
+    if let Some(body) = &data.body {
+      if let Err(slurs) = slur_check(body) {
+        return Err(APIError::err(&slurs_vec_to_str(slurs)).into());
+      }
+    }
+
+    let valid_weekday = data
+      .weekday
+      .map_or(true, |weekday| weekday >= 0 && weekday <= 6);
+    if !valid_weekday || data.hour < 0 || data.hour > 23 {
+      return Err(APIError::err("invalid_schedule").into());
+    }
+
+    if ScheduledPost::list_for_community(&conn, data.community_id)?.len() >= MAX_SCHEDULED_POSTS {
+      return Err(APIError::err("too_many_scheduled_posts").into());
+    }
+
+    let form = ScheduledPostForm {
+      community_id: data.community_id,
+      creator_id: user_id,
+      name,
+      body: data.body.to_owned(),
+      weekday: data.weekday,
+      hour: data.hour,
+      next_run: next_run_after(data.weekday, data.hour, naive_now()),
+      updated: None,
+    };
+
+    if let Err(e) = ScheduledPost::create(&conn, &form) {
+      let err_type = if e.to_string() == "value too long for type character varying(200)" {
+        "post_title_too_long"
+      } else {
+        "couldnt_create_scheduled_post"
+      };
+      return Err(APIError::from_db(err_type, e));
+    }
+
+    let scheduled_posts = ScheduledPost::list_for_community(&conn, data.community_id)?;
+
+    Ok(GetScheduledPostsResponse { scheduled_posts })
+  }
+}
+
+impl Perform<GetScheduledPostsResponse> for Oper<DeleteScheduledPost> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetScheduledPostsResponse, Error> {
+    let data: &DeleteScheduledPost = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    let scheduled = match ScheduledPost::read(&conn, data.scheduled_post_id) {
+      Ok(scheduled) => scheduled,
+      Err(_e) => return Err(APIError::err("couldnt_find_scheduled_post").into()),
+    };
+
+    // Any of the mods can stop one, not only the one who made it
+    check_mod_or_admin(&conn, CommunityId(scheduled.community_id), UserId(user_id))?;
+
+    ScheduledPost::delete(&conn, scheduled.id)?;
+
+    let scheduled_posts = ScheduledPost::list_for_community(&conn, scheduled.community_id)?;
+
+    Ok(GetScheduledPostsResponse { scheduled_posts })
+  }
+}
+
+impl Perform<GetScheduledPostsResponse> for Oper<GetScheduledPosts> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetScheduledPostsResponse, Error> {
+    let data: &GetScheduledPosts = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    check_mod_or_admin(&conn, CommunityId(data.community_id), UserId(user_id))?;
+
+    let scheduled_posts = ScheduledPost::list_for_community(&conn, data.community_id)?;
+
+    Ok(GetScheduledPostsResponse { scheduled_posts })
+  }
+}
+
+/// Makes a scheduled post, stickies it and unstickies the one it made before, recording when
+/// the next one is due. Nothing is made if its creator isn't a moderator of the community any
+/// more, or the community was removed or deleted. Returns the new post's id.
+fn make_scheduled_post(conn: &PgConnection, scheduled_post_id: i32) -> Result<Option<i32>, Error> {
+  let now = naive_now();
+
+  transaction_with_retry(conn, || {
+    let scheduled = match ScheduledPost::lock_if_due(&conn, scheduled_post_id)? {
+      Some(scheduled) => scheduled,
+      None => return Ok(None),
+    };
+    let next_run = next_run_after(scheduled.weekday, scheduled.hour, now);
+
+    let community = Community::read(&conn, scheduled.community_id)?;
+    let can_post = !community.removed
+      && !community.deleted
+      && !UserView::read(&conn, scheduled.creator_id)?.banned
+      && check_mod_or_admin(
+        &conn,
+        CommunityId(scheduled.community_id),
+        UserId(scheduled.creator_id),
+      )
+      .is_ok();
+    if !can_post {
+      ScheduledPost::record_run(&conn, scheduled.id, None, next_run)?;
+      return Ok(None);
+    }
+
+    let post_form = PostForm {
+      name: scheduled.title(now.date()),
+      url: None,
+      body: scheduled.body.to_owned(),
+      community_id: scheduled.community_id,
+      creator_id: scheduled.creator_id,
+      removed: None,
+      deleted: None,
+      nsfw: community.nsfw,
+      locked: None,
+      stickied: Some(true),
+      updated: None,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
+    };
+    let inserted_post = Post::create(&conn, &post_form)?;
+
+    let like_form = PostLikeForm {
+      post_id: inserted_post.id,
+      user_id: scheduled.creator_id,
+      score: 1,
+    };
+    PostLike::like(&conn, &like_form)?;
+
+    ModStickyPost::create(
+      &conn,
+      &ModStickyPostForm {
+        mod_user_id: scheduled.creator_id,
+        post_id: inserted_post.id,
+        stickied: Some(true),
+      },
+    )?;
+
+    if let Some(last_post_id) = scheduled.last_post_id {
+      if Post::read(&conn, last_post_id)?.stickied {
+        Post::update_stickied(&conn, last_post_id, false)?;
+        ModStickyPost::create(
+          &conn,
+          &ModStickyPostForm {
+            mod_user_id: scheduled.creator_id,
+            post_id: last_post_id,
+            stickied: Some(false),
+          },
+        )?;
+      }
+    }
+
+    ScheduledPost::record_run(&conn, scheduled.id, Some(inserted_post.id), next_run)?;
+    evict_apub_object("c", format!("{}/featured", community.name));
+    if let Some(last_post_id) = scheduled.last_post_id {
+      evict_apub_object("post", last_post_id);
+    }
+    Ok(Some(inserted_post.id))
+  })
+}
+
+/// Makes the scheduled posts that are due. One that fails is logged and skipped until its next
+/// time, so it doesn't hold up the others. Returns how many were made.
+pub fn run_scheduled_posts(conn: &PgConnection) -> Result<usize, Error> {
+  let mut made = 0;
+  for scheduled in ScheduledPost::list_due(&conn)? {
+    match make_scheduled_post(&conn, scheduled.id) {
+      Ok(Some(_)) => made += 1,
+      Ok(None) => {}
+      Err(e) => {
+        error!("Making scheduled post {} failed: {}", scheduled.id, e);
+        let next_run = next_run_after(scheduled.weekday, scheduled.hour, naive_now());
+        ScheduledPost::record_run(&conn, scheduled.id, None, next_run)?;
+      }
+    }
+  }
+  Ok(made)
+}
+
+impl Perform<GetModDigestsResponse> for Oper<SaveModDigest> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetModDigestsResponse, Error> {
+    let data: &SaveModDigest = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    match data.interval_days {
+      Some(interval_days) => {
+        check_mod_or_admin(&conn, CommunityId(data.community_id), UserId(user_id))?;
+
+        if interval_days < 1 || interval_days > 7 {
+          return Err(APIError::err("invalid_digest_interval").into());
+        }
+
+        let form = ModDigestForm {
+          user_id,
+          community_id: data.community_id,
+          interval_days,
+          next_send: naive_now() + chrono::Duration::days(interval_days.into()),
+        };
+
+        if let Err(e) = ModDigest::save(&conn, &form) {
+          return Err(APIError::from_db("couldnt_save_mod_digest", e));
+        }
+      }
+      // Anyone can unsubscribe, even once they aren't a moderator any more
+      None => {
+        ModDigest::remove(&conn, user_id, data.community_id)?;
+      }
+    }
+
+    let digests = ModDigest::list_for_user(&conn, user_id)?;
+
+    Ok(GetModDigestsResponse { digests })
+  }
+}
+
+impl Perform<GetModDigestsResponse> for Oper<GetModDigests> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetModDigestsResponse, Error> {
+    let data: &GetModDigests = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    let digests = ModDigest::list_for_user(&conn, user_id)?;
+
+    Ok(GetModDigestsResponse { digests })
+  }
+}
+
+/// The digest of a community, with its most reported posts and comments from the open reports,
+/// and its fastest growing posts ranked like the Rising sort. `None` if there's nothing in it.
+fn mod_digest_content(conn: &PgConnection, community: &Community) -> Result<Option<String>, Error> {
+  let reported = ReportGroup::group(
+    ReportQueryBuilder::create(&conn)
+      .for_community_ids(vec![community.id])
+      .limit(MOD_DIGEST_REPORTS)
+      .list()?,
+  );
+  let rising = PostActivityHour::rising_in_community(&conn, community.id, MOD_DIGEST_ITEMS)?;
+  if reported.is_empty() && rising.is_empty() {
+    return Ok(None);
+  }
+
+  let hostname = &Settings::get().hostname;
+  let mut content = format!("Digest of /c/{}", community.name);
+
+  if !reported.is_empty() {
+    content.push_str("\n\nMost reported:\n");
+    for group in reported.iter().take(MOD_DIGEST_ITEMS as usize) {
+      let line = match (group.post_id, &group.post_name, &group.comment_content) {
+        (Some(post_id), Some(post_name), _) => format!(
+          "\n- [{}](https://{}/post/{}), {} reports",
+          post_name, hostname, post_id, group.count
+        ),
+        (_, _, Some(comment_content)) => format!(
+          "\n- The comment \"{}\", {} reports",
+          crate::excerpt(comment_content, MOD_DIGEST_EXCERPT_LENGTH).unwrap_or_default(),
+          group.count
+        ),
+        _ => continue,
+      };
+      content.push_str(&line);
+    }
+  }
+
+  if !rising.is_empty() {
+    content.push_str("\n\nFastest growing:\n");
+    for (post_id, post_name, _rank) in rising {
+      content.push_str(&format!(
+        "\n- [{}](https://{}/post/{})",
+        post_name, hostname, post_id
+      ));
+    }
+  }
+
+  Ok(Some(content))
+}
+
+/// Sends a digest as a moderation notice, which emails it to moderators with email notifications
+/// on. It's dropped if the user isn't a moderator of the community any more.
+fn send_mod_digest(conn: &PgConnection, digest: &ModDigest) -> Result<bool, Error> {
+  if check_mod_or_admin(
+    &conn,
+    CommunityId(digest.community_id),
+    UserId(digest.user_id),
+  )
+  .is_err()
+  {
+    ModDigest::remove(&conn, digest.user_id, digest.community_id)?;
+    return Ok(false);
+  }
+
+  ModDigest::record_sent(&conn, digest)?;
+
+  let community = Community::read(&conn, digest.community_id)?;
+  let content = match mod_digest_content(&conn, &community)? {
+    Some(content) => content,
+    None => return Ok(false),
+  };
+
+  let form = ModerationNoticeForm {
+    recipient_id: digest.user_id,
+    mod_user_id: digest.user_id,
+    kind: ModerationNoticeKind::ModDigest as i16,
+    community_id: Some(community.id),
+    post_id: None,
+    comment_id: None,
+    reason: None,
+    content,
+  };
+  let notice = ModerationNotice::create(&conn, &form)?;
+
+  events::publish(Event::ModerationNoticeSent {
+    notice,
+    recipient: User_::read(&conn, digest.user_id)?,
+    mod_name: Site::read(&conn, 1)?.name,
+  });
+
+  Ok(true)
+}
+
+/// Sends the moderators' digests that are due. One that fails is logged and skipped until its
+/// next time. Returns how many were sent.
+pub fn send_mod_digests(conn: &PgConnection) -> Result<usize, Error> {
+  let mut sent = 0;
+  for digest in ModDigest::list_due(&conn)? {
+    match send_mod_digest(&conn, &digest) {
+      Ok(true) => sent += 1,
+      Ok(false) => {}
+      Err(e) => {
+        error!("Sending mod digest {} failed: {}", digest.id, e);
+        ModDigest::record_sent(&conn, &digest)?;
+      }
+    }
+  }
+  Ok(sent)
+}
+
+/// Tells the moderators of the local communities about the subscriber and activity milestones
+/// they reached, and about spikes in subscriptions, which could be a brigade. The milestones a
+/// community already had when it was first checked aren't sent, and each is only sent once. A
+/// community that fails is logged and skipped. Returns how many notices were sent.
+pub fn send_growth_notices(conn: &PgConnection) -> Result<usize, Error> {
+  let mut sent = 0;
+  for counts in CommunityGrowth::counts(&conn)? {
+    match send_community_growth_notices(&conn, &counts) {
+      Ok(community_sent) => sent += community_sent,
+      Err(e) => error!(
+        "Sending the growth notices of community {} failed: {}",
+        counts.community_id, e
+      ),
+    }
+  }
+  Ok(sent)
+}
+
+fn send_community_growth_notices(
+  conn: &PgConnection,
+  counts: &CommunityGrowthCounts,
+) -> Result<usize, Error> {
+  let previous = CommunityGrowth::read(&conn, counts.community_id).optional()?;
+  let mut growth = CommunityGrowth {
+    community_id: counts.community_id,
+    subscriber_milestone: milestone(counts.subscribers),
+    activity_milestone: milestone(counts.activity),
+    spike_notified: None,
+  };
+
+  let mut notices = Vec::new();
+  if let Some(previous) = &previous {
+    if growth.subscriber_milestone > previous.subscriber_milestone {
+      notices.push((
+        ModerationNoticeKind::CommunityMilestone,
+        format!(
+          "/c/{} reached {} subscribers.",
+          counts.community_name, growth.subscriber_milestone
+        ),
+      ));
+    }
+    if growth.activity_milestone > previous.activity_milestone {
+      notices.push((
+        ModerationNoticeKind::CommunityMilestone,
+        format!(
+          "/c/{} reached {} posts and comments.",
+          counts.community_name, growth.activity_milestone
+        ),
+      ));
+    }
+    // Dropping back below a milestone doesn't send it again
+    growth.subscriber_milestone = growth
+      .subscriber_milestone
+      .max(previous.subscriber_milestone);
+    growth.activity_milestone = growth.activity_milestone.max(previous.activity_milestone);
+  }
+
+  let spike_sent_lately = previous
+    .as_ref()
+    .and_then(|previous| previous.spike_notified)
+    .map_or(false, |notified| {
+      notified > naive_now() - chrono::Duration::days(1)
+    });
+  if is_subscription_spike(counts.new_subscriptions, counts.earlier_subscriptions)
+    && !spike_sent_lately
+  {
+    notices.push((
+      ModerationNoticeKind::SubscriptionSpike,
+      format!(
+        "/c/{} got {} subscriptions in the last day, many more than usual. It could be a brigade.",
+        counts.community_name, counts.new_subscriptions
+      ),
+    ));
+    growth.spike_notified = Some(naive_now());
+  }
+
+  if previous.is_some() && notices.is_empty() {
+    return Ok(0);
+  }
+  CommunityGrowth::save(&conn, &growth)?;
+
+  let mut sent = 0;
+  let site_name = Site::read(&conn, 1)?.name;
+  for moderator in CommunityModeratorView::for_community(&conn, counts.community_id)? {
+    if User_::read(&conn, moderator.user_id)?.actor_id.is_some() {
+      continue;
+    }
+    for (kind, content) in &notices {
+      let form = ModerationNoticeForm {
+        recipient_id: moderator.user_id,
+        mod_user_id: moderator.user_id,
+        kind: *kind as i16,
+        community_id: Some(counts.community_id),
+        post_id: None,
+        comment_id: None,
+        reason: None,
+        content: content.to_owned(),
+      };
+      let notice = ModerationNotice::create(&conn, &form)?;
+      events::publish(Event::ModerationNoticeSent {
+        notice,
+        recipient: User_::read(&conn, moderator.user_id)?,
+        mod_name: site_name.to_owned(),
+      });
+      sent += 1;
+    }
+  }
+  Ok(sent)
+}
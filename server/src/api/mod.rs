@@ -2,33 +2,69 @@ use crate::db::category::*;
 use crate::db::comment::*;
 use crate::db::comment_view::*;
 use crate::db::community::*;
+use crate::db::community_block::*;
 use crate::db::community_view::*;
+use crate::db::consistency::*;
+use crate::db::edit_history::*;
+use crate::db::featured::*;
+use crate::db::ids::{CommentId, CommunityId, PostId, UserId};
+use crate::db::inbox_combined_view::*;
+use crate::db::inbox_rejection::*;
+use crate::db::instance_impact::*;
+use crate::db::ip_block::*;
+use crate::db::language::*;
+use crate::db::media_alt_text::*;
+use crate::db::mod_digest::*;
+use crate::db::mod_note::*;
+use crate::db::moderation_notice::*;
 use crate::db::moderator::*;
 use crate::db::moderator_views::*;
 use crate::db::password_reset_request::*;
 use crate::db::post::*;
+use crate::db::post_attachment::*;
 use crate::db::post_view::*;
 use crate::db::private_message::*;
 use crate::db::private_message_view::*;
+use crate::db::reserved_community_name::*;
+use crate::db::saved_folder::*;
+use crate::db::saved_items_view::*;
+use crate::db::scheduled_post::*;
 use crate::db::site::*;
 use crate::db::site_view::*;
 use crate::db::user::*;
+use crate::db::user_activity::*;
+use crate::db::user_block::*;
+use crate::db::user_instance_block::*;
+use crate::db::user_keyword_filter::*;
 use crate::db::user_mention::*;
 use crate::db::user_mention_view::*;
+use crate::db::user_unread_counts_view::*;
 use crate::db::user_view::*;
 use crate::db::*;
+use crate::events::{self, Event};
+use crate::ip_blocklist::{self, Network};
+use crate::settings::Settings;
+use crate::websocket::UserOperation;
 use crate::{
-  extract_usernames, fetch_iframely_and_pictshare_data, naive_from_unix, naive_now, remove_slurs,
-  slur_check, slurs_vec_to_str,
+  extract_usernames, fetch_iframely_and_pictshare_data, is_image_url, naive_from_unix, naive_now,
+  remove_slurs, slur_check, slurs_vec_to_str,
 };
-use diesel::PgConnection;
+use diesel::{Connection, PgConnection};
 use failure::Error;
+use log::error;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
 
+pub mod api_token;
 pub mod comment;
 pub mod community;
+pub mod oauth;
 pub mod post;
+pub mod report;
 pub mod site;
+pub mod translation;
 pub mod user;
 
 #[derive(Fail, Debug)]
@@ -43,16 +79,366 @@ impl APIError {
       message: msg.to_string(),
     }
   }
+
+  /// The error for a failed write: `msg`, unless the write only lost a race with another
+  /// transaction, in which case the database error is kept so `transaction_with_retry` retries it.
+  pub fn from_db(msg: &str, e: diesel::result::Error) -> Error {
+    if e.is_retryable() {
+      e.into()
+    } else {
+      APIError::err(msg).into()
+    }
+  }
+}
+
+/// The error for a first post or comment in a community whose rules the user hasn't agreed to.
+/// It carries the rules, so that clients can show them and retry with `acknowledge_rules`.
+#[derive(Fail, Debug)]
+pub struct RulesAckRequired {
+  pub community_id: i32,
+  pub rules: String,
+}
+
+impl fmt::Display for RulesAckRequired {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let error = serde_json::json!({
+      "error": "rules_ack_required",
+      "community_id": self.community_id,
+      "rules": self.rules,
+    });
+    write!(f, "{}", error)
+  }
+}
+
+/// The error for a user the site doesn't let create communities. It carries the requirement they
+/// don't meet, so that clients can explain it.
+#[derive(Fail, Debug)]
+pub enum CommunityCreationRestricted {
+  AdminOnly,
+  AccountTooNew { min_account_age_days: i32 },
+  NotEnoughKarma { min_karma: i32, karma: i64 },
+}
+
+impl fmt::Display for CommunityCreationRestricted {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let error = match self {
+      CommunityCreationRestricted::AdminOnly => serde_json::json!({
+        "error": "community_creation_admin_only",
+      }),
+      CommunityCreationRestricted::AccountTooNew {
+        min_account_age_days,
+      } => serde_json::json!({
+        "error": "account_too_new",
+        "min_account_age_days": min_account_age_days,
+      }),
+      CommunityCreationRestricted::NotEnoughKarma { min_karma, karma } => serde_json::json!({
+        "error": "not_enough_karma",
+        "min_karma": min_karma,
+        "karma": karma,
+      }),
+    };
+    write!(f, "{}", error)
+  }
+}
+
+/// Checks that a user posting or commenting in a community has agreed to its rules, if it
+/// requires that. `acknowledge` records the user agreeing now. Mods and admins are exempt.
+pub fn check_rules_ack(
+  conn: &PgConnection,
+  CommunityId(community_id): CommunityId,
+  UserId(user_id): UserId,
+  acknowledge: Option<bool>,
+) -> Result<(), Error> {
+  let community = Community::read(&conn, community_id)?;
+  let rules = match community.rules {
+    Some(rules) if community.require_rules_ack && !rules.trim().is_empty() => rules,
+    _ => return Ok(()),
+  };
+
+  if CommunityRulesAck::has_acknowledged(&conn, community_id, user_id)? {
+    return Ok(());
+  }
+
+  if acknowledge == Some(true) {
+    let form = CommunityRulesAckForm {
+      community_id,
+      user_id,
+    };
+    CommunityRulesAck::acknowledge(&conn, &form)?;
+    return Ok(());
+  }
+
+  let is_mod = CommunityModeratorView::for_community(&conn, community_id)?
+    .iter()
+    .any(|m| m.user_id == user_id);
+  if is_mod || UserView::read(&conn, user_id)?.admin {
+    return Ok(());
+  }
+
+  Err(
+    RulesAckRequired {
+      community_id,
+      rules,
+    }
+    .into(),
+  )
+}
+
+/// Errors with `not_a_moderator` unless the user moderates the community or is an admin.
+pub fn check_mod_or_admin(
+  conn: &PgConnection,
+  CommunityId(community_id): CommunityId,
+  UserId(user_id): UserId,
+) -> Result<(), Error> {
+  let is_mod = CommunityModeratorView::for_community(&conn, community_id)?
+    .iter()
+    .any(|m| m.user_id == user_id);
+  if is_mod || UserView::read(&conn, user_id)?.admin {
+    Ok(())
+  } else {
+    Err(APIError::err("not_a_moderator").into())
+  }
+}
+
+/// Whether a user can see the edit history of posts and comments in a community: anyone if the
+/// site has it public, and otherwise its mods and the admins.
+pub fn check_edit_history_access(
+  conn: &PgConnection,
+  community_id: CommunityId,
+  user_id: Option<UserId>,
+) -> Result<(), Error> {
+  if Site::read(&conn, 1)?.public_edit_history {
+    return Ok(());
+  }
+  match user_id {
+    Some(user_id) => check_mod_or_admin(&conn, community_id, user_id),
+    None => Err(APIError::err("not_logged_in").into()),
+  }
+}
+
+/// Tells a user that a moderator removed their post or comment, or banned them, if the site has
+/// moderation notices on. `object_id` is the post, comment or community the notice is about.
+/// Remote users and moderators acting on their own content don't get one. The action stands
+/// either way, so failing to send it is only logged.
+pub fn send_moderation_notice(
+  conn: &PgConnection,
+  recipient_id: i32,
+  mod_user_id: i32,
+  kind: ModerationNoticeKind,
+  object_id: Option<i32>,
+  reason: &Option<String>,
+) {
+  if let Err(e) = create_moderation_notice(conn, recipient_id, mod_user_id, kind, object_id, reason)
+  {
+    error!(
+      "Couldn't send a moderation notice to {}: {}",
+      recipient_id, e
+    );
+  }
+}
+
+fn create_moderation_notice(
+  conn: &PgConnection,
+  recipient_id: i32,
+  mod_user_id: i32,
+  kind: ModerationNoticeKind,
+  object_id: Option<i32>,
+  reason: &Option<String>,
+) -> Result<(), Error> {
+  let site = Site::read(&conn, 1)?;
+  let recipient = User_::read(&conn, recipient_id)?;
+  if !site.moderation_notices || recipient_id == mod_user_id || recipient.actor_id.is_some() {
+    return Ok(());
+  }
+
+  let (action, community_id, post_id, comment_id) = match (kind, object_id) {
+    (ModerationNoticeKind::PostRemoved, Some(post_id)) => {
+      let post = Post::read(&conn, post_id)?;
+      let community = Community::read(&conn, post.community_id)?;
+      let action = format!(
+        "Your post \"{}\" was removed from {}",
+        post.name, community.name
+      );
+      (action, Some(community.id), Some(post.id), None)
+    }
+    (ModerationNoticeKind::CommentRemoved, Some(comment_id)) => {
+      let comment = Comment::read(&conn, comment_id)?;
+      let post = Post::read(&conn, comment.post_id)?;
+      let community = Community::read(&conn, post.community_id)?;
+      let action = format!(
+        "Your comment on \"{}\" was removed from {}",
+        post.name, community.name
+      );
+      (action, Some(community.id), Some(post.id), Some(comment.id))
+    }
+    (ModerationNoticeKind::BannedFromCommunity, Some(community_id)) => {
+      let community = Community::read(&conn, community_id)?;
+      let action = format!("You were banned from {}", community.name);
+      (action, Some(community.id), None, None)
+    }
+    (ModerationNoticeKind::Banned, _) => (
+      format!("You were banned from {}", site.name),
+      None,
+      None,
+      None,
+    ),
+    _ => return Ok(()),
+  };
+
+  let reason = non_empty(reason);
+  let content = render_moderation_notice(
+    site.moderation_notice_template.as_deref(),
+    &action,
+    reason.as_deref(),
+    site.moderation_appeal_url.as_deref(),
+  );
+
+  let form = ModerationNoticeForm {
+    recipient_id,
+    mod_user_id,
+    kind: kind as i16,
+    community_id,
+    post_id,
+    comment_id,
+    reason,
+    content: content.to_owned(),
+  };
+  let notice = ModerationNotice::create(&conn, &form)?;
+
+  if site.moderation_notice_pm {
+    let private_message_form = PrivateMessageForm {
+      content: Some(content),
+      creator_id: mod_user_id,
+      recipient_id,
+      deleted: None,
+      read: None,
+      updated: None,
+    };
+    PrivateMessage::create(&conn, &private_message_form)?;
+  }
+
+  events::publish(Event::ModerationNoticeSent {
+    notice,
+    recipient,
+    mod_name: User_::read(&conn, mod_user_id)?.name,
+  });
+
+  Ok(())
+}
+
+/// Errors with `bot_content_must_be_generated` when a bot account makes a post or comment without
+/// marking it generated.
+pub fn check_generated(conn: &PgConnection, user_id: i32, generated: bool) -> Result<(), Error> {
+  if !generated && User_::read(&conn, user_id)?.bot_account {
+    return Err(APIError::err("bot_content_must_be_generated").into());
+  }
+  Ok(())
+}
+
+/// Errors with `couldnt_find_saved_folder` unless the folder, if there is one, is the user's.
+pub fn check_saved_folder(
+  conn: &PgConnection,
+  folder_id: Option<i32>,
+  user_id: i32,
+) -> Result<(), Error> {
+  if let Some(folder_id) = folder_id {
+    match SavedFolder::read(&conn, folder_id) {
+      Ok(folder) if folder.user_id == user_id => {}
+      _ => return Err(APIError::err("couldnt_find_saved_folder").into()),
+    }
+  }
+  Ok(())
+}
+
+/// Errors with `invalid_language` unless the language, if there is one, exists.
+pub fn check_language(conn: &PgConnection, language_id: Option<i32>) -> Result<(), Error> {
+  if let Some(language_id) = language_id {
+    if Language::read(&conn, language_id).is_err() {
+      return Err(APIError::err("invalid_language").into());
+    }
+  }
+  Ok(())
+}
+
+/// Blank text as `None`.
+pub fn non_empty(text: &Option<String>) -> Option<String> {
+  text
+    .as_ref()
+    .map(|t| t.trim())
+    .filter(|t| !t.is_empty())
+    .map(String::from)
+}
+
+/// Errors with `not_logged_in` on a private instance, for the read endpoints which are otherwise
+/// open to anyone.
+pub fn check_private_instance(user_id: Option<i32>) -> Result<(), Error> {
+  if Settings::get().private_instance && user_id.is_none() {
+    return Err(APIError::err("not_logged_in").into());
+  }
+  Ok(())
+}
+
+/// The modlog scope of a removal of remote content, or a ban of a remote user, which has to be
+/// given for those. Undoing one is always local. `None` for local content.
+pub fn remote_moderation_scope(
+  scope: &Option<String>,
+  is_remote: bool,
+  applying: bool,
+) -> Result<Option<RemoteModerationScope>, Error> {
+  if !is_remote {
+    return Ok(None);
+  }
+  let scope = match scope {
+    Some(scope) => {
+      RemoteModerationScope::from_str(scope).map_err(|_| APIError::err("invalid_remote_scope"))?
+    }
+    None if applying => return Err(APIError::err("remote_scope_required").into()),
+    None => RemoteModerationScope::Local,
+  };
+  Ok(Some(if applying {
+    scope
+  } else {
+    RemoteModerationScope::Local
+  }))
+}
+
+/// The id of the logged in admin.
+pub fn admin_user_id(conn: &PgConnection, auth: &str) -> Result<i32, Error> {
+  let claims = match Claims::decode(auth) {
+    Ok(claims) => claims.claims,
+    Err(_e) => return Err(APIError::err("not_logged_in").into()),
+  };
+
+  let user_id = claims.id;
+
+  // Make sure user is an admin
+  if !UserView::read(&conn, user_id)?.admin {
+    return Err(APIError::err("not_an_admin").into());
+  }
+
+  Ok(user_id)
 }
 
 pub struct Oper<T> {
   data: T,
+  /// The client's IP, for the handlers checking the blocklist
+  ip: Option<String>,
 }
 
 impl<T> Oper<T> {
   pub fn new(data: T) -> Oper<T> {
-    Oper { data }
+    Oper { data, ip: None }
   }
+
+  pub fn with_ip(mut self, ip: String) -> Oper<T> {
+    self.ip = Some(ip);
+    self
+  }
+}
+
+/// Names the operation a request is for, which the http routes need to check api token scopes.
+pub trait Operation {
+  const OP: UserOperation;
 }
 
 pub trait Perform<T> {
@@ -60,3 +446,42 @@ pub trait Perform<T> {
   where
     T: Sized;
 }
+
+/// Read responses which can be answered with a `304 Not Modified`, so that polling clients don't
+/// re-download identical payloads.
+pub trait ETag {
+  /// A weak entity tag, built from the newest edit times and the counts in the response.
+  fn etag(&self) -> String;
+}
+
+/// Hashes a response fingerprint into a weak entity tag, ie `W/"1f2e3d4c5b6a7988"`.
+pub fn weak_etag(fingerprint: &str) -> String {
+  let mut hasher = Sha256::new();
+  hasher.input(fingerprint);
+  let hash = format!("{:x}", hasher.result());
+  format!("W/\"{}\"", &hash[..16])
+}
+
+/// Weak comparison of an `If-None-Match` header value against an entity tag.
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+  let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_owned();
+  let etag = opaque(etag);
+  if_none_match
+    .split(',')
+    .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::etag_matches;
+
+  #[test]
+  fn test_etag_matches() {
+    let etag = "W/\"1f2e3d4c5b6a7988\"";
+    assert!(etag_matches(etag, etag));
+    assert!(etag_matches("\"1f2e3d4c5b6a7988\"", etag));
+    assert!(etag_matches("W/\"aaaa\", W/\"1f2e3d4c5b6a7988\"", etag));
+    assert!(etag_matches("*", etag));
+    assert!(!etag_matches("W/\"aaaa\"", etag));
+  }
+}
@@ -0,0 +1,426 @@
+//! The api side of the oauth2 authorization code flow: apps are registered, and the front end's
+//! consent screen approves them. The token endpoint itself is in `routes::oauth`.
+//!
+//! The tokens apps get are api tokens, limited to the scopes the user approved.
+
+use super::*;
+use crate::db::api_token::*;
+use crate::db::oauth::*;
+use crate::generate_random_string;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::str::FromStr;
+
+/// How long an app has to exchange a code for a token.
+const CODE_LIFETIME_MINUTES: i64 = 10;
+const MAX_REDIRECT_URIS: usize = 10;
+const MAX_REDIRECT_URI_LENGTH: usize = 500;
+
+/// Everything but the unreserved characters, for query values.
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+  .remove(b'-')
+  .remove(b'.')
+  .remove(b'_')
+  .remove(b'~');
+
+#[derive(Serialize, Deserialize)]
+pub struct RegisterOAuthClient {
+  name: String,
+  redirect_uris: Vec<String>,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RegisterOAuthClientResponse {
+  oauth_client: OAuthClient,
+  /// Only shown this once
+  client_secret: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListOAuthClients {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeleteOAuthClient {
+  id: i32,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListOAuthClientsResponse {
+  oauth_clients: Vec<OAuthClient>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetOAuthConsent {
+  client_id: String,
+  redirect_uri: String,
+  scope: Option<String>,
+  auth: String,
+}
+
+/// What the consent screen shows.
+#[derive(Serialize, Deserialize)]
+pub struct GetOAuthConsentResponse {
+  client_name: String,
+  redirect_uri: String,
+  scopes: Vec<TokenScope>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AuthorizeOAuthClient {
+  client_id: String,
+  redirect_uri: String,
+  scope: Option<String>,
+  state: Option<String>,
+  code_challenge: Option<String>,
+  code_challenge_method: Option<String>,
+  approve: bool,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AuthorizeOAuthClientResponse {
+  /// Where to send the user back to the app, with either a code or an error
+  redirect: String,
+}
+
+/// Secrets and codes are only stored hashed.
+pub fn hash_secret(secret: &str) -> String {
+  let mut hasher = Sha256::new();
+  hasher.input(secret);
+  format!("{:x}", hasher.result())
+}
+
+/// Whether a PKCE verifier matches its `S256` challenge.
+pub fn pkce_matches(code_challenge: &str, code_verifier: &str) -> bool {
+  let mut hasher = Sha256::new();
+  hasher.input(code_verifier);
+  base64::encode_config(hasher.result(), base64::URL_SAFE_NO_PAD) == code_challenge
+}
+
+/// Parses a space separated oauth `scope`, which is `read` if left out.
+fn parse_scopes(scope: Option<&str>) -> Result<Vec<TokenScope>, Error> {
+  let mut scopes = Vec::new();
+  for s in scope.unwrap_or("read").split_whitespace() {
+    match TokenScope::from_str(s) {
+      Ok(scope) if !scopes.contains(&scope) => scopes.push(scope),
+      Ok(_) => (),
+      Err(_) => return Err(APIError::err("invalid_scope").into()),
+    }
+  }
+  if scopes.is_empty() {
+    return Err(APIError::err("invalid_scope").into());
+  }
+  Ok(scopes)
+}
+
+/// Apps can use any scheme, like `myapp://` for mobile apps, but no fragments.
+fn valid_redirect_uri(uri: &str) -> bool {
+  let scheme_end = match uri.find("://") {
+    Some(scheme_end) => scheme_end,
+    None => return false,
+  };
+  scheme_end > 0
+    && uri.len() <= MAX_REDIRECT_URI_LENGTH
+    && !uri.contains('#')
+    && !uri.chars().any(char::is_whitespace)
+}
+
+/// The app, if the redirect uri is one it registered.
+fn read_client(
+  conn: &PgConnection,
+  client_id: &str,
+  redirect_uri: &str,
+) -> Result<OAuthClient, Error> {
+  let client = match OAuthClient::read_from_client_id(&conn, client_id) {
+    Ok(client) => client,
+    Err(_e) => return Err(APIError::err("invalid_client").into()),
+  };
+  if !client.redirect_uris.iter().any(|uri| uri == redirect_uri) {
+    return Err(APIError::err("invalid_redirect_uri").into());
+  }
+  Ok(client)
+}
+
+fn with_query(uri: &str, params: &[(&str, &str)]) -> String {
+  let query = params
+    .iter()
+    .map(|(key, value)| format!("{}={}", key, utf8_percent_encode(value, QUERY_VALUE)))
+    .collect::<Vec<_>>()
+    .join("&");
+  let separator = if uri.contains('?') { '&' } else { '?' };
+  format!("{}{}{}", uri, separator, query)
+}
+
+fn logged_in_user_id(auth: &str) -> Result<i32, Error> {
+  match Claims::decode(auth) {
+    Ok(claims) => Ok(claims.claims.id),
+    Err(_e) => Err(APIError::err("not_logged_in").into()),
+  }
+}
+
+impl Perform<RegisterOAuthClientResponse> for Oper<RegisterOAuthClient> {
+  fn perform(&self, conn: &PgConnection) -> Result<RegisterOAuthClientResponse, Error> {
+    let data: &RegisterOAuthClient = &self.data;
+
+    let user_id = logged_in_user_id(&data.auth)?;
+
+    if data.name.trim().is_empty() || data.name.chars().count() > 100 {
+      return Err(APIError::err("invalid_oauth_client_name").into());
+    }
+
+    if data.redirect_uris.is_empty()
+      || data.redirect_uris.len() > MAX_REDIRECT_URIS
+      || !data.redirect_uris.iter().all(|uri| valid_redirect_uri(uri))
+    {
+      return Err(APIError::err("invalid_redirect_uri").into());
+    }
+
+    let client_secret = generate_random_string();
+    let form = OAuthClientForm {
+      client_id: generate_random_string(),
+      client_secret_hash: hash_secret(&client_secret),
+      name: data.name.trim().to_owned(),
+      redirect_uris: data.redirect_uris.to_owned(),
+      creator_id: user_id,
+    };
+
+    let oauth_client = match OAuthClient::create(&conn, &form) {
+      Ok(oauth_client) => oauth_client,
+      Err(_e) => return Err(APIError::err("couldnt_create_oauth_client").into()),
+    };
+
+    Ok(RegisterOAuthClientResponse {
+      oauth_client,
+      client_secret,
+    })
+  }
+}
+
+impl Perform<ListOAuthClientsResponse> for Oper<ListOAuthClients> {
+  fn perform(&self, conn: &PgConnection) -> Result<ListOAuthClientsResponse, Error> {
+    let data: &ListOAuthClients = &self.data;
+
+    let user_id = logged_in_user_id(&data.auth)?;
+
+    let oauth_clients = OAuthClient::list_for_creator(&conn, user_id)?;
+
+    Ok(ListOAuthClientsResponse { oauth_clients })
+  }
+}
+
+impl Perform<ListOAuthClientsResponse> for Oper<DeleteOAuthClient> {
+  fn perform(&self, conn: &PgConnection) -> Result<ListOAuthClientsResponse, Error> {
+    let data: &DeleteOAuthClient = &self.data;
+
+    let user_id = logged_in_user_id(&data.auth)?;
+
+    // Only your own apps. Their tokens go with them.
+    match OAuthClient::read(&conn, data.id) {
+      Ok(oauth_client) if oauth_client.creator_id == user_id => (),
+      _ => return Err(APIError::err("couldnt_find_oauth_client").into()),
+    }
+
+    OAuthClient::delete(&conn, data.id)?;
+
+    let oauth_clients = OAuthClient::list_for_creator(&conn, user_id)?;
+
+    Ok(ListOAuthClientsResponse { oauth_clients })
+  }
+}
+
+impl Perform<GetOAuthConsentResponse> for Oper<GetOAuthConsent> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetOAuthConsentResponse, Error> {
+    let data: &GetOAuthConsent = &self.data;
+
+    logged_in_user_id(&data.auth)?;
+
+    let client = read_client(&conn, &data.client_id, &data.redirect_uri)?;
+    let scopes = parse_scopes(data.scope.as_deref())?;
+
+    Ok(GetOAuthConsentResponse {
+      client_name: client.name,
+      redirect_uri: data.redirect_uri.to_owned(),
+      scopes,
+    })
+  }
+}
+
+impl Perform<AuthorizeOAuthClientResponse> for Oper<AuthorizeOAuthClient> {
+  fn perform(&self, conn: &PgConnection) -> Result<AuthorizeOAuthClientResponse, Error> {
+    let data: &AuthorizeOAuthClient = &self.data;
+
+    let user_id = logged_in_user_id(&data.auth)?;
+
+    // Only a known redirect uri gets the user sent back to it, even with an error
+    let client = read_client(&conn, &data.client_id, &data.redirect_uri)?;
+    let redirect_with = |params: &[(&str, &str)]| {
+      let mut params = params.to_vec();
+      if let Some(state) = &data.state {
+        params.push(("state", state));
+      }
+      AuthorizeOAuthClientResponse {
+        redirect: with_query(&data.redirect_uri, &params),
+      }
+    };
+
+    if !data.approve {
+      return Ok(redirect_with(&[("error", "access_denied")]));
+    }
+
+    let scopes = match parse_scopes(data.scope.as_deref()) {
+      Ok(scopes) => scopes,
+      Err(_e) => return Ok(redirect_with(&[("error", "invalid_scope")])),
+    };
+
+    // Only the S256 PKCE method, since the plain one doesn't protect anything
+    match (&data.code_challenge, data.code_challenge_method.as_deref()) {
+      (None, None) | (Some(_), Some("S256")) => (),
+      _ => return Ok(redirect_with(&[("error", "invalid_request")])),
+    }
+
+    OAuthCode::delete_expired(&conn)?;
+
+    let code = generate_random_string();
+    let form = OAuthCodeForm {
+      code_hash: hash_secret(&code),
+      oauth_client_id: client.id,
+      user_id,
+      scopes: scopes.iter().map(ToString::to_string).collect(),
+      redirect_uri: data.redirect_uri.to_owned(),
+      code_challenge: data.code_challenge.to_owned(),
+      expires: naive_now() + chrono::Duration::minutes(CODE_LIFETIME_MINUTES),
+    };
+    if OAuthCode::create(&conn, &form).is_err() {
+      return Ok(redirect_with(&[("error", "server_error")]));
+    }
+
+    Ok(redirect_with(&[("code", &code)]))
+  }
+}
+
+/// Exchanges an authorization code for an api token, for the token endpoint. The app
+/// authenticates with its secret, or with the PKCE verifier if it sent a challenge. Errors are
+/// the oauth2 error codes.
+pub fn exchange_code(
+  conn: &PgConnection,
+  code: &str,
+  client_id: &str,
+  client_secret: Option<&str>,
+  redirect_uri: &str,
+  code_verifier: Option<&str>,
+) -> Result<(String, Vec<String>), Error> {
+  let client = match OAuthClient::read_from_client_id(&conn, client_id) {
+    Ok(client) => client,
+    Err(_e) => return Err(APIError::err("invalid_client").into()),
+  };
+  let secret_matches = client_secret.map(|secret| hash_secret(secret) == client.client_secret_hash);
+  if secret_matches == Some(false) {
+    return Err(APIError::err("invalid_client").into());
+  }
+
+  let code = match OAuthCode::take(&conn, &hash_secret(code)) {
+    Ok(code) => code,
+    Err(_e) => return Err(APIError::err("invalid_grant").into()),
+  };
+  if code.oauth_client_id != client.id || code.redirect_uri != redirect_uri {
+    return Err(APIError::err("invalid_grant").into());
+  }
+
+  let authenticated = match (&code.code_challenge, code_verifier) {
+    (Some(challenge), Some(verifier)) => pkce_matches(challenge, verifier),
+    (Some(_), None) => false,
+    (None, _) => secret_matches == Some(true),
+  };
+  if !authenticated {
+    return Err(APIError::err("invalid_grant").into());
+  }
+
+  let form = ApiTokenForm {
+    user_id: code.user_id,
+    name: client.name.to_owned(),
+    scopes: code.scopes.to_owned(),
+    rate_limit_multiplier: 1.0,
+    oauth_client_id: Some(client.id),
+    impersonator_id: None,
+    expires: None,
+    device_name: None,
+    client_app: Some(client.name.to_owned()),
+  };
+  let api_token = ApiToken::create(&conn, &form)?;
+  let token = User_::read(&conn, code.user_id)?.api_token_jwt(api_token.id);
+
+  Ok((token, api_token.scopes))
+}
+
+/// Revokes one of an app's tokens, for the revocation endpoint. Tokens which aren't the app's,
+/// or aren't tokens at all, are left alone.
+pub fn revoke_token(
+  conn: &PgConnection,
+  token: &str,
+  client_id: &str,
+  client_secret: Option<&str>,
+) -> Result<(), Error> {
+  let client = match OAuthClient::read_from_client_id(&conn, client_id) {
+    Ok(client) => client,
+    Err(_e) => return Err(APIError::err("invalid_client").into()),
+  };
+  if let Some(secret) = client_secret {
+    if hash_secret(secret) != client.client_secret_hash {
+      return Err(APIError::err("invalid_client").into());
+    }
+  }
+
+  let token_id = match Claims::decode(token).ok().and_then(|c| c.claims.token_id) {
+    Some(token_id) => token_id,
+    None => return Ok(()),
+  };
+  if let Ok(api_token) = ApiToken::read(&conn, token_id) {
+    if api_token.oauth_client_id == Some(client.id) {
+      ApiToken::delete(&conn, api_token.id)?;
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_oauth_helpers() {
+    // The example from RFC 7636
+    assert!(pkce_matches(
+      "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM",
+      "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"
+    ));
+    assert!(!pkce_matches(
+      "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM",
+      "wrong"
+    ));
+
+    assert_eq!(vec![TokenScope::Read], parse_scopes(None).unwrap());
+    assert_eq!(
+      vec![TokenScope::Read, TokenScope::Write],
+      parse_scopes(Some("read write read")).unwrap()
+    );
+    assert!(parse_scopes(Some("read admin")).is_err());
+    assert!(parse_scopes(Some(" ")).is_err());
+
+    assert!(valid_redirect_uri("https://app.example.com/callback"));
+    assert!(valid_redirect_uri("myapp://oauth"));
+    assert!(!valid_redirect_uri("https://app.example.com/#callback"));
+    assert!(!valid_redirect_uri("/callback"));
+
+    assert_eq!(
+      "myapp://oauth?code=abc&state=a%20b",
+      with_query("myapp://oauth", &[("code", "abc"), ("state", "a b")])
+    );
+    assert_eq!(
+      "https://a.example/cb?x=1&error=access_denied",
+      with_query("https://a.example/cb?x=1", &[("error", "access_denied")])
+    );
+  }
+}
@@ -1,5 +1,15 @@
 use super::*;
+use crate::api::comment::comments_fingerprint;
+use crate::api::community::community_fingerprint;
+use crate::apub::cache::evict_apub_object;
+use crate::apub::featured::send_featured;
+use crate::apub::flag::send_flag;
+use crate::apub::make_apub_endpoint;
+use crate::media::{ingest, is_too_large};
+use crate::post_views::record_view;
 use diesel::PgConnection;
+use log::error;
+use std::collections::HashMap;
 use std::str::FromStr;
 
 #[derive(Serialize, Deserialize)]
@@ -9,6 +19,20 @@ pub struct CreatePost {
   body: Option<String>,
   nsfw: bool,
   pub community_id: i32,
+  /// Describes the image of an image post, defaulting to the alt text saved when it was uploaded
+  alt_text: Option<String>,
+  /// A `PostVisibility`, defaulting to `Public`
+  visibility: Option<String>,
+  /// The images of a gallery post, in order
+  images: Option<Vec<GalleryImage>>,
+  /// Agrees to the community's rules, if it requires that
+  acknowledge_rules: Option<bool>,
+  /// Made by a bot or generated by AI, which bot accounts have to set
+  generated: Option<bool>,
+  /// The `Language` it's in
+  language_id: Option<i32>,
+  /// When to publish it, as a unix timestamp. Nobody but its creator sees it until then.
+  scheduled_publish_time: Option<i64>,
   auth: String,
 }
 
@@ -20,6 +44,8 @@ pub struct PostResponse {
 #[derive(Serialize, Deserialize)]
 pub struct GetPost {
   pub id: i32,
+  /// The page of top level comments, for megathreads
+  page: Option<i64>,
   auth: Option<String>,
 }
 
@@ -27,6 +53,10 @@ pub struct GetPost {
 pub struct GetPostResponse {
   post: PostView,
   comments: Vec<CommentView>,
+  /// Whether the post has so many comments that only a page of the top level ones is here
+  megathread: bool,
+  /// How many replies the top level comments have, for megathreads
+  child_counts: HashMap<i32, i64>,
   community: CommunityView,
   moderators: Vec<CommunityModeratorView>,
   admins: Vec<UserView>,
@@ -41,11 +71,18 @@ pub struct GetPosts {
   limit: Option<i64>,
   pub community_id: Option<i32>,
   auth: Option<String>,
+  /// Whether to return the full post bodies, defaulting to true. Listings can show the
+  /// `excerpt` instead.
+  include_body: Option<bool>,
+  /// Only the posts of the users the user blocked, which are otherwise left out
+  blocked_only: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct GetPostsResponse {
   posts: Vec<PostView>,
+  /// The other posts of the same links as the posts, when the user collapses them
+  crossposts: Vec<Crosspost>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -68,17 +105,226 @@ pub struct EditPost {
   nsfw: bool,
   locked: Option<bool>,
   stickied: Option<bool>,
+  /// Left as it is if not given, and removed if empty
+  alt_text: Option<String>,
+  /// A `PostVisibility`, left as it is if not given
+  visibility: Option<String>,
+  /// Left as they are if not given, and removed if empty
+  images: Option<Vec<GalleryImage>>,
   reason: Option<String>,
+  /// A `RemoteModerationScope`, required to remove a remote post
+  remote_scope: Option<String>,
+  /// The `Language` it's in, left as it is if not given
+  language_id: Option<i32>,
+  auth: String,
+}
+
+/// The earlier versions of a post.
+#[derive(Serialize, Deserialize)]
+pub struct GetPostEdits {
+  post_id: PostId,
+  auth: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetPostEditsResponse {
+  /// The latest first
+  edits: Vec<PostEdit>,
+}
+
+/// An image of a gallery post, which has to have been uploaded here.
+#[derive(Serialize, Deserialize)]
+pub struct GalleryImage {
+  url: String,
+  /// Defaults to the alt text saved when it was uploaded
+  alt_text: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveMediaAltText {
+  url: String,
+  alt_text: String,
   auth: String,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct MediaAltTextResponse {
+  media: MediaAltText,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SavePost {
   post_id: i32,
   save: bool,
+  folder_id: Option<i32>,
   auth: String,
 }
 
+impl ETag for GetPostResponse {
+  fn etag(&self) -> String {
+    let post = &self.post;
+    let fingerprint = format!(
+      "{}:{:?}:{}:{:?}:{:?}:{}:{:?}:{:?}:{:?}|{}|{}|{}:{}",
+      post.id,
+      post.updated.unwrap_or(post.published),
+      post.newest_activity_time,
+      post.upvotes,
+      post.downvotes,
+      post.number_of_comments,
+      post.my_vote,
+      post.saved,
+      post.read,
+      comments_fingerprint(&self.comments),
+      community_fingerprint(&self.community),
+      self.moderators.len(),
+      self.admins.len(),
+    );
+    weak_etag(&fingerprint)
+  }
+}
+
+const MAX_ALT_TEXT_LENGTH: usize = 1500;
+
+fn check_alt_text(alt_text: &Option<String>) -> Result<(), Error> {
+  if let Some(alt_text) = alt_text {
+    if alt_text.chars().count() > MAX_ALT_TEXT_LENGTH {
+      return Err(APIError::err("alt_text_too_long").into());
+    }
+    if let Err(slurs) = slur_check(alt_text) {
+      return Err(APIError::err(&slurs_vec_to_str(slurs)).into());
+    }
+  }
+  Ok(())
+}
+
+/// The alt text given for a post's image, or else the one saved when the image was uploaded.
+/// `None` for posts which aren't of an image.
+fn post_alt_text(
+  conn: &PgConnection,
+  url: &Option<String>,
+  alt_text: &Option<String>,
+) -> Option<String> {
+  let url = url.as_ref().filter(|url| is_image_url(url))?;
+  non_empty(alt_text).or_else(|| {
+    MediaAltText::read_for_url(&conn, url)
+      .ok()
+      .map(|media| media.alt_text)
+  })
+}
+
+/// Whether the site or the community requires image posts to have alt text.
+fn alt_text_required(conn: &PgConnection, community_id: i32) -> Result<bool, Error> {
+  Ok(
+    Site::read(&conn, 1)?.require_alt_text
+      || Community::read(&conn, community_id)?.require_alt_text,
+  )
+}
+
+/// The url a post serves for an image, which fails if the image can't be made safe to serve.
+fn served_image(conn: &PgConnection, url: &str) -> Result<String, Error> {
+  ingest(&conn, url).map_err(|e| {
+    error!("Processing image {} failed: {}", url, e);
+    APIError::err("couldnt_process_image").into()
+  })
+}
+
+/// Checks the images of a gallery post, returning the urls they're served at and their alt texts.
+/// They have to be uploaded here, and fit in the gallery and in the poster's images for the day,
+/// which don't count the post being edited.
+fn check_gallery(
+  conn: &PgConnection,
+  images: &[GalleryImage],
+  creator_id: i32,
+  community_id: i32,
+  edit_post_id: Option<i32>,
+) -> Result<Vec<(String, Option<String>)>, Error> {
+  if images.is_empty() {
+    return Ok(vec![]);
+  }
+  let gallery = Settings::get().gallery;
+  if images.len() > gallery.max_images {
+    return Err(APIError::err("too_many_images").into());
+  }
+  let pictshare = format!("https://{}/pictshare/", Settings::get().hostname);
+  if images
+    .iter()
+    .any(|image| !image.url.starts_with(&pictshare))
+  {
+    return Err(APIError::err("image_not_uploaded").into());
+  }
+  if images.iter().any(|image| is_too_large(&image.url)) {
+    return Err(APIError::err("image_too_large").into());
+  }
+
+  let day_ago = naive_now() - chrono::Duration::days(1);
+  let posted_today =
+    PostAttachment::count_for_creator_since(&conn, creator_id, day_ago, edit_post_id)?;
+  if posted_today + images.len() as i64 > gallery.images_per_day {
+    return Err(APIError::err("image_quota_exceeded").into());
+  }
+
+  let required = alt_text_required(&conn, community_id)?;
+  let mut checked = Vec::with_capacity(images.len());
+  for image in images {
+    check_alt_text(&image.alt_text)?;
+    let alt_text = post_alt_text(&conn, &Some(image.url.to_owned()), &image.alt_text)
+      .or_else(|| non_empty(&image.alt_text));
+    if required && alt_text.is_none() {
+      return Err(APIError::err("alt_text_required").into());
+    }
+    checked.push((served_image(&conn, &image.url)?, alt_text));
+  }
+  Ok(checked)
+}
+
+fn gallery_forms(post_id: i32, images: &[(String, Option<String>)]) -> Vec<PostAttachmentForm> {
+  images
+    .iter()
+    .enumerate()
+    .map(|(position, (url, alt_text))| PostAttachmentForm {
+      post_id,
+      position: position as i32,
+      kind: PostAttachmentKind::Image as i16,
+      url: url.to_owned(),
+      mime_type: None,
+      name: alt_text.to_owned(),
+      duration: None,
+    })
+    .collect()
+}
+
+fn parse_visibility(visibility: &Option<String>) -> Result<Option<i16>, Error> {
+  match visibility {
+    Some(visibility) => Ok(Some(PostVisibility::from_str(visibility)? as i16)),
+    None => Ok(None),
+  }
+}
+
+/// Whether someone can see a post by its link. Followers only posts are for the community's
+/// subscribers, and the people who can moderate it.
+pub fn can_view_post(
+  conn: &PgConnection,
+  post: &PostView,
+  user_id: Option<i32>,
+) -> Result<bool, Error> {
+  if PostVisibility::from_i16(post.visibility) != PostVisibility::FollowersOnly
+    || post.subscribed == Some(true)
+  {
+    return Ok(true);
+  }
+  let user_id = match user_id {
+    Some(user_id) => user_id,
+    None => return Ok(false),
+  };
+  if user_id == post.creator_id {
+    return Ok(true);
+  }
+  let moderates = CommunityModeratorView::for_community(&conn, post.community_id)?
+    .iter()
+    .any(|m| m.user_id == user_id);
+  Ok(moderates || UserView::read(&conn, user_id)?.admin)
+}
+
 impl Perform<PostResponse> for Oper<CreatePost> {
   fn perform(&self, conn: &PgConnection) -> Result<PostResponse, Error> {
     let data: &CreatePost = &self.data;
@@ -100,23 +346,67 @@ impl Perform<PostResponse> for Oper<CreatePost> {
 
     let user_id = claims.id;
 
+    ip_blocklist::check(&conn, self.ip.as_deref(), true)?;
+
     // Check for a community ban
     if CommunityUserBanView::get(&conn, user_id, data.community_id).is_ok() {
       return Err(APIError::err("community_ban").into());
     }
 
+    check_rules_ack(
+      &conn,
+      CommunityId(data.community_id),
+      UserId(user_id),
+      data.acknowledge_rules,
+    )?;
+
     // Check for a site ban
     if UserView::read(&conn, user_id)?.banned {
       return Err(APIError::err("site_ban").into());
     }
 
+    let generated = data.generated.unwrap_or(false);
+    check_generated(&conn, user_id, generated)?;
+    check_language(&conn, data.language_id)?;
+
+    let scheduled_publish_time = match data.scheduled_publish_time {
+      Some(time) if naive_from_unix(time) <= naive_now() => {
+        return Err(APIError::err("scheduled_publish_time_in_past").into())
+      }
+      Some(time) => Some(naive_from_unix(time)),
+      None => None,
+    };
+
+    check_alt_text(&data.alt_text)?;
+    let alt_text = post_alt_text(&conn, &data.url, &data.alt_text);
+    let is_image = data.url.as_ref().map_or(false, |url| is_image_url(url));
+    if is_image && alt_text.is_none() && alt_text_required(&conn, data.community_id)? {
+      return Err(APIError::err("alt_text_required").into());
+    }
+    let images = match &data.images {
+      Some(images) => check_gallery(&conn, images, user_id, data.community_id, None)?,
+      None => vec![],
+    };
+
+    if data.url.as_ref().map_or(false, |url| is_too_large(url)) {
+      return Err(APIError::err("image_too_large").into());
+    }
+
     // Fetch Iframely and Pictshare cached image
     let (iframely_title, iframely_description, iframely_html, pictshare_thumbnail) =
       fetch_iframely_and_pictshare_data(data.url.to_owned());
+    let url = match &data.url {
+      Some(url) => Some(served_image(&conn, url)?),
+      None => None,
+    };
+    let thumbnail_url = match pictshare_thumbnail {
+      Some(thumbnail) => Some(served_image(&conn, &thumbnail)?),
+      None => None,
+    };
 
     let post_form = PostForm {
       name: data.name.to_owned(),
-      url: data.url.to_owned(),
+      url,
       body: data.body.to_owned(),
       community_id: data.community_id,
       creator_id: user_id,
@@ -129,45 +419,78 @@ impl Perform<PostResponse> for Oper<CreatePost> {
       embed_title: iframely_title,
       embed_description: iframely_description,
       embed_html: iframely_html,
-      thumbnail_url: pictshare_thumbnail,
+      thumbnail_url,
+      alt_text,
+      visibility: parse_visibility(&data.visibility)?,
+      generated: Some(generated),
+      language_id: data.language_id,
+      scheduled_publish_time,
     };
 
-    let inserted_post = match Post::create(&conn, &post_form) {
-      Ok(post) => post,
-      Err(e) => {
-        let err_type = if e.to_string() == "value too long for type character varying(200)" {
-          "post_title_too_long"
-        } else {
-          "couldnt_create_post"
-        };
+    let inserted_post = transaction_with_retry(conn, || {
+      let inserted_post = match Post::create(&conn, &post_form) {
+        Ok(post) => post,
+        Err(e) => {
+          let err_type = if e.to_string() == "value too long for type character varying(200)" {
+            "post_title_too_long"
+          } else {
+            "couldnt_create_post"
+          };
+
+          return Err(APIError::from_db(err_type, e));
+        }
+      };
 
-        return Err(APIError::err(err_type).into());
-      }
-    };
+      // They like their own post by default
+      let like_form = PostLikeForm {
+        post_id: inserted_post.id,
+        user_id,
+        score: 1,
+      };
 
-    // They like their own post by default
-    let like_form = PostLikeForm {
-      post_id: inserted_post.id,
-      user_id,
-      score: 1,
-    };
+      // Only add the like if the score isnt 0
+      let _inserted_like = match PostLike::like(&conn, &like_form) {
+        Ok(like) => like,
+        Err(e) => return Err(APIError::from_db("couldnt_like_post", e)),
+      };
 
-    // Only add the like if the score isnt 0
-    let _inserted_like = match PostLike::like(&conn, &like_form) {
-      Ok(like) => like,
-      Err(_e) => return Err(APIError::err("couldnt_like_post").into()),
-    };
+      if !images.is_empty() {
+        let forms = gallery_forms(inserted_post.id, &images);
+        if let Err(e) = PostAttachment::replace_for_post(&conn, inserted_post.id, &forms) {
+          return Err(APIError::from_db("couldnt_create_post", e));
+        }
+      }
+
+      Ok(inserted_post)
+    })?;
 
     // Refetch the view
-    let post_view = match PostView::read(&conn, inserted_post.id, Some(user_id)) {
+    let mut post_view = match PostView::read(&conn, PostId(inserted_post.id), Some(UserId(user_id)))
+    {
       Ok(post) => post,
       Err(_e) => return Err(APIError::err("couldnt_find_post").into()),
     };
 
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      post_view.hide_scores();
+    }
+
     Ok(PostResponse { post: post_view })
   }
 }
 
+/// Publishes the scheduled posts that are due, which other instances can fetch from then on.
+/// Returns how many were published.
+pub fn publish_scheduled_posts(conn: &PgConnection) -> Result<usize, Error> {
+  let mut published = 0;
+  for scheduled in Post::list_due_scheduled(&conn)? {
+    if Post::publish_scheduled(&conn, scheduled.id)?.is_some() {
+      published += 1;
+    }
+  }
+  Ok(published)
+}
+
 impl Perform<GetPostResponse> for Oper<GetPost> {
   fn perform(&self, conn: &PgConnection) -> Result<GetPostResponse, Error> {
     let data: &GetPost = &self.data;
@@ -183,16 +506,49 @@ impl Perform<GetPostResponse> for Oper<GetPost> {
       None => None,
     };
 
-    let post_view = match PostView::read(&conn, data.id, user_id) {
+    check_private_instance(user_id)?;
+
+    let mut post_view = match PostView::read(&conn, PostId(data.id), user_id.map(UserId)) {
       Ok(post) => post,
       Err(_e) => return Err(APIError::err("couldnt_find_post").into()),
     };
 
-    let comments = CommentQueryBuilder::create(&conn)
-      .for_post_id(data.id)
-      .my_user_id(user_id)
-      .limit(9999)
-      .list()?;
+    if !can_view_post(&conn, &post_view, user_id)? {
+      return Err(APIError::err("couldnt_find_post").into());
+    }
+
+    // The further pages of a megathread's comments are the same view
+    if data.page.unwrap_or(1) <= 1 {
+      record_view(post_view.id, user_id.is_some());
+    }
+
+    let hide_generated = User_::hides_generated(&conn, user_id)?;
+    let megathread = Settings::get().megathread;
+    let is_megathread = post_view.number_of_comments > megathread.comment_threshold;
+    let (mut comments, child_counts) = if is_megathread {
+      let comments = CommentQueryBuilder::create(&conn)
+        .for_post_id(PostId(data.id))
+        .top_level_only(true)
+        .hide_generated(hide_generated)
+        .my_user_id(user_id.map(UserId))
+        .page(data.page)
+        .limit(megathread.page_size)
+        .list()?;
+      let comment_ids = comments.iter().map(|c| c.id).collect::<Vec<i32>>();
+      let child_counts = Comment::child_counts(&conn, &comment_ids)?
+        .into_iter()
+        .map(|c| (c.comment_id, c.child_count))
+        .collect();
+      (comments, child_counts)
+    } else {
+      let comments = CommentQueryBuilder::create(&conn)
+        .for_post_id(PostId(data.id))
+        .hide_generated(hide_generated)
+        .my_user_id(user_id.map(UserId))
+        .limit(9999)
+        .list()?;
+      (comments, HashMap::new())
+    };
 
     let community = CommunityView::read(&conn, post_view.community_id, user_id)?;
 
@@ -204,10 +560,18 @@ impl Perform<GetPostResponse> for Oper<GetPost> {
     let creator_user = admins.remove(creator_index);
     admins.insert(0, creator_user);
 
+    if Site::hides_scores_from(&conn, user_id)? {
+      post_view.hide_scores();
+      comments.hide_scores();
+      admins.hide_scores();
+    }
+
     // Return the jwt
     Ok(GetPostResponse {
       post: post_view,
       comments,
+      megathread: is_megathread,
+      child_counts,
       community,
       moderators,
       admins,
@@ -233,6 +597,8 @@ impl Perform<GetPostsResponse> for Oper<GetPosts> {
       None => None,
     };
 
+    check_private_instance(user_id)?;
+
     let show_nsfw = match &user_claims {
       Some(claims) => claims.show_nsfw,
       None => false,
@@ -241,12 +607,26 @@ impl Perform<GetPostsResponse> for Oper<GetPosts> {
     let type_ = ListingType::from_str(&data.type_)?;
     let sort = SortType::from_str(&data.sort)?;
 
-    let posts = match PostQueryBuilder::create(&conn)
+    let mut query = PostQueryBuilder::create(&conn);
+    let mut collapse_crossposts = false;
+    if let Some(user_id) = user_id {
+      let user = User_::read(&conn, user_id)?;
+      query = query.blended_discovery(user.blended_discovery_percent, user.blended_discovery_local);
+      collapse_crossposts = match type_ {
+        ListingType::All | ListingType::Subscribed => user.collapse_crossposts,
+        _ => false,
+      } && data.community_id.is_none();
+    }
+
+    let mut posts = match query
       .listing_type(type_)
       .sort(&sort)
       .show_nsfw(show_nsfw)
-      .for_community_id(data.community_id)
-      .my_user_id(user_id)
+      .hide_generated(User_::hides_generated(&conn, user_id)?)
+      .for_community_id(data.community_id.map(CommunityId))
+      .my_user_id(user_id.map(UserId))
+      .blocked_only(data.blocked_only.unwrap_or(false))
+      .collapse_crossposts(collapse_crossposts)
       .page(data.page)
       .limit(data.limit)
       .list()
@@ -255,7 +635,24 @@ impl Perform<GetPostsResponse> for Oper<GetPosts> {
       Err(_e) => return Err(APIError::err("couldnt_get_posts").into()),
     };
 
-    Ok(GetPostsResponse { posts })
+    let crossposts = if collapse_crossposts {
+      let post_ids: Vec<i32> = posts.iter().map(|p| p.id).collect();
+      Post::crossposts(&conn, &post_ids)?
+    } else {
+      Vec::new()
+    };
+
+    if data.include_body == Some(false) {
+      for post in posts.iter_mut() {
+        post.body = None;
+      }
+    }
+
+    if Site::hides_scores_from(&conn, user_id)? {
+      posts.hide_scores();
+    }
+
+    Ok(GetPostsResponse { posts, crossposts })
   }
 }
 
@@ -307,11 +704,15 @@ impl Perform<PostResponse> for Oper<CreatePostLike> {
       };
     }
 
-    let post_view = match PostView::read(&conn, data.post_id, Some(user_id)) {
+    let mut post_view = match PostView::read(&conn, PostId(data.post_id), Some(UserId(user_id))) {
       Ok(post) => post,
       Err(_e) => return Err(APIError::err("couldnt_find_post").into()),
     };
 
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      post_view.hide_scores();
+    }
+
     // just output the score
     Ok(PostResponse { post: post_view })
   }
@@ -337,6 +738,7 @@ impl Perform<PostResponse> for Oper<EditPost> {
     };
 
     let user_id = claims.id;
+    check_language(&conn, data.language_id)?;
 
     // Verify its the creator or a mod or admin
     let mut editors: Vec<i32> = vec![data.creator_id];
@@ -361,13 +763,50 @@ impl Perform<PostResponse> for Oper<EditPost> {
       return Err(APIError::err("site_ban").into());
     }
 
+    let orig_post = Post::read(&conn, data.edit_id)?;
+    let creator = User_::read(&conn, orig_post.creator_id)?;
+    let remote_scope = match data.removed {
+      Some(removed) => {
+        remote_moderation_scope(&data.remote_scope, creator.actor_id.is_some(), removed)?
+      }
+      None => None,
+    };
+
+    check_alt_text(&data.alt_text)?;
+    let alt_text = match &data.alt_text {
+      Some(_) => post_alt_text(&conn, &data.url, &data.alt_text),
+      None => post_alt_text(&conn, &data.url, &orig_post.alt_text),
+    };
+    let images = match &data.images {
+      Some(images) => Some(check_gallery(
+        &conn,
+        images,
+        data.creator_id,
+        data.community_id,
+        Some(data.edit_id),
+      )?),
+      None => None,
+    };
+
+    if data.url.as_ref().map_or(false, |url| is_too_large(url)) {
+      return Err(APIError::err("image_too_large").into());
+    }
+
     // Fetch Iframely and Pictshare cached image
     let (iframely_title, iframely_description, iframely_html, pictshare_thumbnail) =
       fetch_iframely_and_pictshare_data(data.url.to_owned());
+    let url = match &data.url {
+      Some(url) => Some(served_image(&conn, url)?),
+      None => None,
+    };
+    let thumbnail_url = match pictshare_thumbnail {
+      Some(thumbnail) => Some(served_image(&conn, &thumbnail)?),
+      None => None,
+    };
 
     let post_form = PostForm {
       name: data.name.to_owned(),
-      url: data.url.to_owned(),
+      url,
       body: data.body.to_owned(),
       creator_id: data.creator_id.to_owned(),
       community_id: data.community_id,
@@ -380,52 +819,120 @@ impl Perform<PostResponse> for Oper<EditPost> {
       embed_title: iframely_title,
       embed_description: iframely_description,
       embed_html: iframely_html,
-      thumbnail_url: pictshare_thumbnail,
+      thumbnail_url,
+      alt_text,
+      visibility: parse_visibility(&data.visibility)?,
+      generated: None,
+      language_id: data.language_id,
+      scheduled_publish_time: None,
     };
 
-    let _updated_post = match Post::update(&conn, data.edit_id, &post_form) {
-      Ok(post) => post,
-      Err(e) => {
-        let err_type = if e.to_string() == "value too long for type character varying(200)" {
-          "post_title_too_long"
-        } else {
-          "couldnt_update_post"
+    let removal = transaction_with_retry(conn, || {
+      // What it was is kept if the content changes
+      if orig_post.name != post_form.name
+        || orig_post.url != post_form.url
+        || orig_post.body != post_form.body
+      {
+        let edit_form = PostEditForm {
+          post_id: data.edit_id,
+          editor_id: user_id,
+          name: orig_post.name.to_owned(),
+          url: orig_post.url.to_owned(),
+          body: orig_post.body.to_owned(),
         };
-
-        return Err(APIError::err(err_type).into());
+        PostEdit::create(&conn, &edit_form)?;
       }
-    };
 
-    // Mod tables
-    if let Some(removed) = data.removed.to_owned() {
-      let form = ModRemovePostForm {
-        mod_user_id: user_id,
-        post_id: data.edit_id,
-        removed: Some(removed),
-        reason: data.reason.to_owned(),
+      let _updated_post = match Post::update(&conn, data.edit_id, &post_form) {
+        Ok(post) => post,
+        Err(e) => {
+          let err_type = if e.to_string() == "value too long for type character varying(200)" {
+            "post_title_too_long"
+          } else {
+            "couldnt_update_post"
+          };
+
+          return Err(APIError::from_db(err_type, e));
+        }
       };
-      ModRemovePost::create(&conn, &form)?;
-    }
 
-    if let Some(locked) = data.locked.to_owned() {
-      let form = ModLockPostForm {
-        mod_user_id: user_id,
-        post_id: data.edit_id,
-        locked: Some(locked),
-      };
-      ModLockPost::create(&conn, &form)?;
+      // Mod tables
+      let mut removal = None;
+      if let Some(removed) = data.removed.to_owned() {
+        let form = ModRemovePostForm {
+          mod_user_id: user_id,
+          post_id: data.edit_id,
+          removed: Some(removed),
+          reason: data.reason.to_owned(),
+          remote_scope: remote_scope.map(|scope| scope as i16),
+        };
+        removal = Some(ModRemovePost::create(&conn, &form)?);
+      }
+
+      if let Some(locked) = data.locked.to_owned() {
+        let form = ModLockPostForm {
+          mod_user_id: user_id,
+          post_id: data.edit_id,
+          locked: Some(locked),
+        };
+        ModLockPost::create(&conn, &form)?;
+      }
+
+      if let Some(stickied) = data.stickied.to_owned() {
+        let form = ModStickyPostForm {
+          mod_user_id: user_id,
+          post_id: data.edit_id,
+          stickied: Some(stickied),
+        };
+        ModStickyPost::create(&conn, &form)?;
+      }
+
+      if let Some(images) = &images {
+        let forms = gallery_forms(data.edit_id, images);
+        if let Err(e) = PostAttachment::replace_for_post(&conn, data.edit_id, &forms) {
+          return Err(APIError::from_db("couldnt_update_post", e));
+        }
+      }
+
+      Ok(removal)
+    })?;
+
+    if let (Some(RemoteModerationScope::Flag), Some(removal)) = (remote_scope, removal) {
+      let post_id = orig_post.apub_id();
+      send_flag(
+        &User_::read(&conn, user_id)?,
+        &creator.apub_id(),
+        make_apub_endpoint("mod_remove_post", removal.id),
+        vec![creator.apub_id(), post_id],
+        data.reason.to_owned(),
+      );
     }
 
-    if let Some(stickied) = data.stickied.to_owned() {
-      let form = ModStickyPostForm {
-        mod_user_id: user_id,
-        post_id: data.edit_id,
-        stickied: Some(stickied),
-      };
-      ModStickyPost::create(&conn, &form)?;
+    if data.removed == Some(true) && !orig_post.removed {
+      send_moderation_notice(
+        &conn,
+        orig_post.creator_id,
+        user_id,
+        ModerationNoticeKind::PostRemoved,
+        Some(data.edit_id),
+        &data.reason,
+      );
     }
 
-    let post_view = PostView::read(&conn, data.edit_id, Some(user_id))?;
+    let updated_post = Post::read(&conn, data.edit_id)?;
+    evict_apub_object("post", data.edit_id);
+    send_featured(
+      &conn,
+      &Community::read(&conn, updated_post.community_id)?,
+      &orig_post,
+      &updated_post,
+    );
+
+    let mut post_view = PostView::read(&conn, PostId(data.edit_id), Some(UserId(user_id)))?;
+
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      post_view.hide_scores();
+    }
 
     Ok(PostResponse { post: post_view })
   }
@@ -442,9 +949,12 @@ impl Perform<PostResponse> for Oper<SavePost> {
 
     let user_id = claims.id;
 
+    check_saved_folder(&conn, data.folder_id, user_id)?;
+
     let post_saved_form = PostSavedForm {
       post_id: data.post_id,
       user_id,
+      folder_id: data.folder_id,
     };
 
     if data.save {
@@ -459,8 +969,88 @@ impl Perform<PostResponse> for Oper<SavePost> {
       };
     }
 
-    let post_view = PostView::read(&conn, data.post_id, Some(user_id))?;
+    let mut post_view = PostView::read(&conn, PostId(data.post_id), Some(UserId(user_id)))?;
+
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      post_view.hide_scores();
+    }
 
     Ok(PostResponse { post: post_view })
   }
 }
+
+impl Perform<GetPostEditsResponse> for Oper<GetPostEdits> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetPostEditsResponse, Error> {
+    let data: &GetPostEdits = &self.data;
+
+    let user_id: Option<i32> = match &data.auth {
+      Some(auth) => match Claims::decode(&auth) {
+        Ok(claims) => Some(claims.claims.id),
+        Err(_e) => None,
+      },
+      None => None,
+    };
+
+    check_private_instance(user_id)?;
+
+    let post_view = match PostView::read(&conn, data.post_id, user_id.map(UserId)) {
+      Ok(post) => post,
+      Err(_e) => return Err(APIError::err("couldnt_find_post").into()),
+    };
+    if !can_view_post(&conn, &post_view, user_id)? {
+      return Err(APIError::err("couldnt_find_post").into());
+    }
+    check_edit_history_access(
+      &conn,
+      CommunityId(post_view.community_id),
+      user_id.map(UserId),
+    )?;
+
+    let edits = PostEdit::list_for_post(&conn, data.post_id.0)?;
+
+    Ok(GetPostEditsResponse { edits })
+  }
+}
+
+impl Perform<MediaAltTextResponse> for Oper<SaveMediaAltText> {
+  fn perform(&self, conn: &PgConnection) -> Result<MediaAltTextResponse, Error> {
+    let data: &SaveMediaAltText = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    let alt_text = match non_empty(&Some(data.alt_text.to_owned())) {
+      Some(alt_text) => alt_text,
+      None => return Err(APIError::err("alt_text_required").into()),
+    };
+    check_alt_text(&Some(alt_text.to_owned()))?;
+
+    if !is_image_url(&data.url) {
+      return Err(APIError::err("not_an_image").into());
+    }
+
+    // Only whoever first described an image can change its description
+    if let Ok(media) = MediaAltText::read_for_url(&conn, &data.url) {
+      if media.creator_id != user_id {
+        return Err(APIError::err("no_media_edit_allowed").into());
+      }
+    }
+
+    let form = MediaAltTextForm {
+      url: data.url.to_owned(),
+      alt_text,
+      creator_id: user_id,
+    };
+
+    let media = match MediaAltText::save(&conn, &form) {
+      Ok(media) => media,
+      Err(_e) => return Err(APIError::err("couldnt_save_alt_text").into()),
+    };
+
+    Ok(MediaAltTextResponse { media })
+  }
+}
@@ -0,0 +1,277 @@
+use super::*;
+use crate::apub::cache::evict_apub_object;
+use crate::db::report::*;
+use log::info;
+
+/// The longest a report's reason can be.
+const MAX_REPORT_REASON_LENGTH: usize = 1000;
+
+/// Reports a post or a comment to the community's moderators.
+#[derive(Serialize, Deserialize)]
+pub struct CreateReport {
+  post_id: Option<i32>,
+  comment_id: Option<i32>,
+  reason: String,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReportResponse {
+  report: ReportView,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListReports {
+  /// The reports of all the communities the user moderates, or all of them for admins, if not
+  /// given
+  community_id: Option<i32>,
+  /// One of `All, Posts, Comments`, `All` if not given
+  type_: Option<String>,
+  /// The resolved reports instead of the open ones
+  resolved: Option<bool>,
+  /// Only the reports this user made
+  creator_id: Option<i32>,
+  page: Option<i64>,
+  limit: Option<i64>,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListReportsResponse {
+  reports: Vec<ReportGroup>,
+}
+
+/// Resolves a report, and with it every other open report of the same post or comment.
+#[derive(Serialize, Deserialize)]
+pub struct ResolveReport {
+  report_id: i32,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ResolveReportResponse {
+  resolved: usize,
+}
+
+/// Removes a reported post or comment once as many users as the community's threshold have an
+/// open report of it. The removal, and the notice its creator gets, are the community's creator's.
+fn auto_remove(
+  conn: &PgConnection,
+  community: &Community,
+  object: ReportedObject,
+) -> Result<(), Error> {
+  let threshold = match community.report_auto_remove_threshold {
+    Some(threshold) => i64::from(threshold),
+    None => return Ok(()),
+  };
+  let reports = Report::count_open(&conn, object)?;
+  if reports < threshold {
+    return Ok(());
+  }
+
+  let reason = Some(format!("Removed automatically after {} reports", reports));
+  let removed = transaction_with_retry(conn, || -> Result<_, diesel::result::Error> {
+    match object {
+      ReportedObject::Post(post_id) => {
+        let post = Post::read(&conn, post_id)?;
+        if post.removed {
+          return Ok(None);
+        }
+        Post::update_removed(&conn, post_id, true)?;
+        let form = ModRemovePostForm {
+          mod_user_id: community.creator_id,
+          post_id,
+          reason: reason.to_owned(),
+          removed: Some(true),
+          remote_scope: None,
+        };
+        ModRemovePost::create(&conn, &form)?;
+        Ok(Some((
+          post.creator_id,
+          ModerationNoticeKind::PostRemoved,
+          post_id,
+        )))
+      }
+      ReportedObject::Comment(comment_id) => {
+        let comment = Comment::read(&conn, comment_id)?;
+        if comment.removed {
+          return Ok(None);
+        }
+        Comment::update_removed(&conn, comment_id, true)?;
+        let form = ModRemoveCommentForm {
+          mod_user_id: community.creator_id,
+          comment_id,
+          reason: reason.to_owned(),
+          removed: Some(true),
+          remote_scope: None,
+        };
+        ModRemoveComment::create(&conn, &form)?;
+        Ok(Some((
+          comment.creator_id,
+          ModerationNoticeKind::CommentRemoved,
+          comment_id,
+        )))
+      }
+    }
+  })?;
+
+  if let Some((creator_id, kind, object_id)) = removed {
+    info!("Removed {:?} after {} reports", object, reports);
+    match object {
+      ReportedObject::Post(post_id) => evict_apub_object("post", post_id),
+      ReportedObject::Comment(comment_id) => evict_apub_object("comment", comment_id),
+    }
+    send_moderation_notice(
+      &conn,
+      creator_id,
+      community.creator_id,
+      kind,
+      Some(object_id),
+      &reason,
+    );
+  }
+  Ok(())
+}
+
+impl Perform<ReportResponse> for Oper<CreateReport> {
+  fn perform(&self, conn: &PgConnection) -> Result<ReportResponse, Error> {
+    let data: &CreateReport = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    // Check for a site ban
+    if UserView::read(&conn, user_id)?.banned {
+      return Err(APIError::err("site_ban").into());
+    }
+
+    let reason = match non_empty(&Some(data.reason.to_owned())) {
+      Some(reason) if reason.len() <= MAX_REPORT_REASON_LENGTH => reason,
+      _ => return Err(APIError::err("invalid_report_reason").into()),
+    };
+
+    let object = match ReportedObject::of(data.post_id, data.comment_id) {
+      Some(object) => object,
+      None => return Err(APIError::err("no_report_target").into()),
+    };
+
+    let community_id = match object {
+      ReportedObject::Post(post_id) => match Post::read(&conn, post_id) {
+        Ok(post) => post.community_id,
+        Err(_e) => return Err(APIError::err("couldnt_find_post").into()),
+      },
+      ReportedObject::Comment(comment_id) => match Comment::read(&conn, comment_id) {
+        Ok(comment) => Post::read(&conn, comment.post_id)?.community_id,
+        Err(_e) => return Err(APIError::err("couldnt_find_comment").into()),
+      },
+    };
+
+    let form = ReportForm {
+      creator_id: user_id,
+      community_id,
+      post_id: data.post_id,
+      comment_id: data.comment_id,
+      reason,
+    };
+
+    let inserted_report = match Report::create(&conn, &form) {
+      Ok(report) => report,
+      Err(e) => return Err(APIError::from_db("already_reported", e)),
+    };
+
+    let community = Community::read(&conn, community_id)?;
+    auto_remove(&conn, &community, object)?;
+
+    Ok(ReportResponse {
+      report: ReportView::read(&conn, inserted_report.id)?,
+    })
+  }
+}
+
+impl Perform<ListReportsResponse> for Oper<ListReports> {
+  fn perform(&self, conn: &PgConnection) -> Result<ListReportsResponse, Error> {
+    let data: &ListReports = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    let community_ids = match data.community_id {
+      Some(community_id) => {
+        check_mod_or_admin(&conn, CommunityId(community_id), UserId(user_id))?;
+        Some(vec![community_id])
+      }
+      None if UserView::read(&conn, user_id)?.admin => None,
+      None => {
+        let moderated: Vec<i32> = CommunityModeratorView::for_user(&conn, user_id)?
+          .into_iter()
+          .map(|m| m.community_id)
+          .collect();
+        if moderated.is_empty() {
+          return Err(APIError::err("not_a_moderator").into());
+        }
+        Some(moderated)
+      }
+    };
+
+    let type_ = match &data.type_ {
+      Some(type_) => {
+        ReportType::from_str(type_).map_err(|_| APIError::err("invalid_report_type"))?
+      }
+      None => ReportType::All,
+    };
+
+    let reports = ReportQueryBuilder::create(&conn)
+      .for_community_ids(community_ids)
+      .for_creator_id(data.creator_id)
+      .type_(&type_)
+      .resolved(data.resolved.unwrap_or(false))
+      .page(data.page)
+      .limit(data.limit)
+      .list()?;
+
+    let reports = ReportGroup::group(reports);
+
+    Ok(ListReportsResponse { reports })
+  }
+}
+
+impl Perform<ResolveReportResponse> for Oper<ResolveReport> {
+  fn perform(&self, conn: &PgConnection) -> Result<ResolveReportResponse, Error> {
+    let data: &ResolveReport = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    let read_report = match Report::read(&conn, data.report_id) {
+      Ok(report) => report,
+      Err(_e) => return Err(APIError::err("couldnt_find_report").into()),
+    };
+
+    check_mod_or_admin(
+      &conn,
+      CommunityId(read_report.community_id),
+      UserId(user_id),
+    )?;
+
+    let object = match ReportedObject::of(read_report.post_id, read_report.comment_id) {
+      Some(object) => object,
+      None => return Err(APIError::err("couldnt_find_report").into()),
+    };
+
+    let resolved = Report::resolve_all(&conn, object, user_id)?;
+
+    Ok(ResolveReportResponse { resolved })
+  }
+}
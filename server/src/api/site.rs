@@ -1,7 +1,13 @@
 use super::*;
-use crate::api::user::Register;
+use crate::api::post::can_view_post;
+use crate::api::user::{csv_line, export_chunk, ExportFormat, Register};
 use crate::api::{Oper, Perform};
+use crate::apub::prefetch::{prefetch_actors, ResolvedActor, MAX_PREFETCH_ACTORS};
+use crate::apub::throttle::{federation_metrics, FederationMetrics};
+use crate::apub::{federated_community, local_object, make_apub_endpoint, LocalObject};
+use crate::routes::index::post_path;
 use crate::settings::Settings;
+use crate::websocket::server::{operation_metrics, OperationMetrics};
 use diesel::PgConnection;
 use log::info;
 use std::str::FromStr;
@@ -14,6 +20,14 @@ pub struct ListCategoriesResponse {
   categories: Vec<Category>,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct ListLanguages {}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListLanguagesResponse {
+  languages: Vec<Language>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Search {
   q: String,
@@ -25,6 +39,56 @@ pub struct Search {
   auth: Option<String>,
 }
 
+/// Finds the local post or comment for a url, which can be its activitypub id or its page.
+#[derive(Serialize, Deserialize)]
+pub struct ResolveObject {
+  q: String,
+  auth: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ResolveObjectResponse {
+  post: Option<PostView>,
+  comment: Option<CommentView>,
+}
+
+/// Resolves users and communities by their activitypub ids or handles, fetching the remote ones
+/// from their instances, so clients can have them all here before showing remote content.
+#[derive(Serialize, Deserialize)]
+pub struct PrefetchActors {
+  actors: Vec<String>,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PrefetchActorsResponse {
+  actors: Vec<PrefetchedActor>,
+}
+
+/// One of the actors of a `PrefetchActors`: the user or community it resolved to, or why it
+/// didn't.
+#[derive(Serialize, Deserialize)]
+pub struct PrefetchedActor {
+  q: String,
+  user: Option<UserView>,
+  community: Option<CommunityView>,
+  error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetApubId {
+  post_id: Option<PostId>,
+  comment_id: Option<CommentId>,
+  auth: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetApubIdResponse {
+  /// Not set for the posts and comments of local only communities
+  ap_id: Option<String>,
+  url: String,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SearchResponse {
   type_: String,
@@ -40,6 +104,7 @@ pub struct GetModlog {
   community_id: Option<i32>,
   page: Option<i64>,
   limit: Option<i64>,
+  auth: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -55,6 +120,34 @@ pub struct GetModlogResponse {
   added: Vec<ModAddView>,
 }
 
+/// The modlog as one feed, newest first, instead of by what the actions were.
+#[derive(Serialize, Deserialize)]
+pub struct GetModlogFeed {
+  mod_user_id: Option<i32>,
+  community_id: Option<i32>,
+  /// The user the actions were done to, or to whose posts, comments and communities
+  other_user_id: Option<i32>,
+  /// One of the `ModlogActionKind`s, all of them if not given
+  kind: Option<String>,
+  after: Option<chrono::NaiveDateTime>,
+  before: Option<chrono::NaiveDateTime>,
+  page: Option<i64>,
+  limit: Option<i64>,
+  /// `csv`, `json` or `jsonl`, for the export route, which has every action rather than a page
+  export: Option<String>,
+  auth: Option<String>,
+}
+
+/// How many actions a modlog export reads at a time.
+const MODLOG_EXPORT_PAGE_SIZE: i64 = 500;
+
+const MODLOG_EXPORT_CSV_HEADER: &str = "kind,id,when_,mod_user_id,mod_user_name,other_user_id,other_user_name,community_id,community_name,post_id,post_name,comment_id,reason,applied,expires\n";
+
+#[derive(Serialize, Deserialize)]
+pub struct GetModlogFeedResponse {
+  actions: Vec<ModlogView>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct CreateSite {
   pub name: String,
@@ -62,6 +155,29 @@ pub struct CreateSite {
   pub enable_downvotes: bool,
   pub open_registration: bool,
   pub enable_nsfw: bool,
+  pub noindex: Option<bool>,
+  pub robots_txt: Option<String>,
+  pub require_alt_text: Option<bool>,
+  pub community_creation_admin_only: Option<bool>,
+  pub community_creation_min_account_age_days: Option<i32>,
+  pub community_creation_min_karma: Option<i32>,
+  pub vote_weighting: Option<bool>,
+  pub vote_weighting_min_account_age_days: Option<i32>,
+  pub vote_weighting_min_karma: Option<i32>,
+  pub vote_weighting_weight: Option<f32>,
+  pub moderation_notices: Option<bool>,
+  pub moderation_notice_pm: Option<bool>,
+  pub moderation_notice_template: Option<String>,
+  pub moderation_appeal_url: Option<String>,
+  pub karma_exclude_self_votes: Option<bool>,
+  pub karma_cap_per_item: Option<i32>,
+  pub karma_decay_half_life_days: Option<i32>,
+  /// A `ScoreVisibility`, defaulting to `UserChoice`
+  pub score_visibility: Option<i16>,
+  /// Defaults to true
+  pub track_post_views: Option<bool>,
+  /// Defaults to false
+  pub public_edit_history: Option<bool>,
   pub auth: String,
 }
 
@@ -72,12 +188,192 @@ pub struct EditSite {
   enable_downvotes: bool,
   open_registration: bool,
   enable_nsfw: bool,
+  /// Left as it is if not given
+  noindex: Option<bool>,
+  /// Left as it is if not given, and back to the default rules if empty
+  robots_txt: Option<String>,
+  /// Left as it is if not given
+  require_alt_text: Option<bool>,
+  /// Left as it is if not given
+  community_creation_admin_only: Option<bool>,
+  /// Left as it is if not given
+  community_creation_min_account_age_days: Option<i32>,
+  /// Left as it is if not given
+  community_creation_min_karma: Option<i32>,
+  /// Left as it is if not given
+  vote_weighting: Option<bool>,
+  /// Left as it is if not given
+  vote_weighting_min_account_age_days: Option<i32>,
+  /// Left as it is if not given
+  vote_weighting_min_karma: Option<i32>,
+  /// Left as it is if not given
+  vote_weighting_weight: Option<f32>,
+  /// Left as it is if not given
+  moderation_notices: Option<bool>,
+  /// Left as it is if not given
+  moderation_notice_pm: Option<bool>,
+  /// Left as it is if not given, and back to the default text if empty
+  moderation_notice_template: Option<String>,
+  /// Left as it is if not given, and removed if empty
+  moderation_appeal_url: Option<String>,
+  /// Left as it is if not given
+  karma_exclude_self_votes: Option<bool>,
+  /// Left as it is if not given
+  karma_cap_per_item: Option<i32>,
+  /// Left as it is if not given
+  karma_decay_half_life_days: Option<i32>,
+  /// A `ScoreVisibility`, left as it is if not given
+  score_visibility: Option<i16>,
+  /// Left as it is if not given
+  track_post_views: Option<bool>,
+  /// Left as it is if not given
+  public_edit_history: Option<bool>,
   auth: String,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct GetSite {}
 
+/// Puts a user's content, or a single post, on legal hold, or releases it.
+#[derive(Serialize, Deserialize)]
+pub struct LegalHold {
+  user_id: Option<i32>,
+  post_id: Option<i32>,
+  hold: bool,
+  reason: Option<String>,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LegalHoldResponse {
+  held: bool,
+}
+
+/// What blocking an instance would cut off, for admins.
+#[derive(Serialize, Deserialize)]
+pub struct GetInstanceImpact {
+  domain: String,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetInstanceImpactResponse {
+  domain: String,
+  impact: InstanceImpact,
+}
+
+/// The activities the shared inbox rejected, by instance and reason, for admins.
+#[derive(Serialize, Deserialize)]
+pub struct ListInboxRejections {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListInboxRejectionsResponse {
+  rejections: Vec<InboxRejection>,
+}
+
+/// What the limits on federation requests to other instances are doing, for admins.
+#[derive(Serialize, Deserialize)]
+pub struct GetFederationMetrics {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetFederationMetricsResponse {
+  metrics: FederationMetrics,
+}
+
+/// How many requests of each operation have been made, and rate limited, for admins.
+#[derive(Serialize, Deserialize)]
+pub struct GetOperationMetrics {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetOperationMetricsResponse {
+  operations: Vec<OperationMetrics>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ImportIpBlocks {
+  list: String,
+  reason: Option<String>,
+  block_posting: bool,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ImportIpBlocksResponse {
+  added: usize,
+  invalid: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListIpBlocks {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListIpBlocksResponse {
+  ip_blocks: Vec<IpBlock>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeleteIpBlock {
+  id: i32,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IpBlockResponse {
+  ip_block: IpBlock,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListReservedCommunityNames {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListReservedCommunityNamesResponse {
+  reserved_names: Vec<ReservedCommunityName>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReserveCommunityName {
+  name: String,
+  reason: Option<String>,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeleteReservedCommunityName {
+  id: i32,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReservedCommunityNameResponse {
+  reserved_name: ReservedCommunityName,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetConsistencyReport {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RunConsistencyCheck {
+  repair: bool,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ConsistencyReportResponse {
+  checks: Vec<ConsistencyCheck>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SiteResponse {
   site: SiteView,
@@ -89,6 +385,8 @@ pub struct GetSiteResponse {
   admins: Vec<UserView>,
   banned: Vec<UserView>,
   pub online: usize,
+  /// Whether posts and comments can be translated
+  translation_enabled: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -113,6 +411,83 @@ pub struct SaveSiteConfig {
   auth: String,
 }
 
+/// The longest a custom robots.txt can be
+const MAX_ROBOTS_TXT_LENGTH: usize = 10_000;
+
+/// A custom robots.txt to save, where an empty one means the default rules.
+fn robots_txt(robots_txt: &Option<String>) -> Result<Option<String>, Error> {
+  match robots_txt {
+    Some(robots_txt) if robots_txt.len() > MAX_ROBOTS_TXT_LENGTH => {
+      Err(APIError::err("robots_txt_too_long").into())
+    }
+    Some(robots_txt) if !robots_txt.trim().is_empty() => Ok(Some(robots_txt.to_owned())),
+    _ => Ok(None),
+  }
+}
+
+/// A karma cap or half life, where 0 turns it off, or `current` if not given.
+fn karma_option(option: Option<i32>, current: i32) -> Result<i32, Error> {
+  match option {
+    Some(option) if option < 0 => Err(APIError::err("invalid_karma_option").into()),
+    Some(option) => Ok(option),
+    None => Ok(current),
+  }
+}
+
+/// Checks a `ScoreVisibility`, or gives `current` if not given.
+fn score_visibility(option: Option<i16>, current: i16) -> Result<i16, Error> {
+  match option {
+    Some(option) if ScoreVisibility::from_i16(option).is_none() => {
+      Err(APIError::err("invalid_score_visibility").into())
+    }
+    Some(option) => Ok(option),
+    None => Ok(current),
+  }
+}
+
+/// The longest the text of moderation notices can be
+const MAX_MODERATION_NOTICE_TEMPLATE_LENGTH: usize = 2000;
+
+/// The text of moderation notices, `None` for the default one if it's blank.
+fn moderation_notice_template(template: &Option<String>) -> Result<Option<String>, Error> {
+  match non_empty(template) {
+    Some(template) if template.len() > MAX_MODERATION_NOTICE_TEMPLATE_LENGTH => {
+      Err(APIError::err("moderation_notice_template_too_long").into())
+    }
+    template => Ok(template),
+  }
+}
+
+/// A minimum account age or karma to create communities, or `current` if not given.
+fn community_creation_minimum(minimum: Option<i32>, current: i32) -> Result<i32, Error> {
+  match minimum {
+    Some(minimum) if minimum < 0 => Err(APIError::err("invalid_community_creation_minimum").into()),
+    Some(minimum) => Ok(minimum),
+    None => Ok(current),
+  }
+}
+
+/// A minimum account age or karma for full vote weight, or `current` if not given.
+fn vote_weighting_minimum(minimum: Option<i32>, current: i32) -> Result<i32, Error> {
+  match minimum {
+    Some(minimum) if minimum < 0 => Err(APIError::err("invalid_vote_weighting_minimum").into()),
+    Some(minimum) => Ok(minimum),
+    None => Ok(current),
+  }
+}
+
+/// The weight of votes from accounts below the minimums, between 0 and 1, or `current` if not
+/// given.
+fn vote_weighting_weight(weight: Option<f32>, current: f32) -> Result<f32, Error> {
+  match weight {
+    Some(weight) if !(0.0..=1.0).contains(&weight) => {
+      Err(APIError::err("invalid_vote_weighting_weight").into())
+    }
+    Some(weight) => Ok(weight),
+    None => Ok(current),
+  }
+}
+
 impl Perform<ListCategoriesResponse> for Oper<ListCategories> {
   fn perform(&self, conn: &PgConnection) -> Result<ListCategoriesResponse, Error> {
     let _data: &ListCategories = &self.data;
@@ -124,10 +499,27 @@ impl Perform<ListCategoriesResponse> for Oper<ListCategories> {
   }
 }
 
+impl Perform<ListLanguagesResponse> for Oper<ListLanguages> {
+  fn perform(&self, conn: &PgConnection) -> Result<ListLanguagesResponse, Error> {
+    let _data: &ListLanguages = &self.data;
+
+    let languages = Language::list_all(&conn)?;
+
+    Ok(ListLanguagesResponse { languages })
+  }
+}
+
 impl Perform<GetModlogResponse> for Oper<GetModlog> {
   fn perform(&self, conn: &PgConnection) -> Result<GetModlogResponse, Error> {
     let data: &GetModlog = &self.data;
 
+    let user_id = match &data.auth {
+      Some(auth) => Claims::decode(&auth).ok().map(|claims| claims.claims.id),
+      None => None,
+    };
+
+    check_private_instance(user_id)?;
+
     let removed_posts = ModRemovePostView::list(
       &conn,
       data.community_id,
@@ -197,6 +589,118 @@ impl Perform<GetModlogResponse> for Oper<GetModlog> {
   }
 }
 
+impl Perform<GetModlogFeedResponse> for Oper<GetModlogFeed> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetModlogFeedResponse, Error> {
+    let data: &GetModlogFeed = &self.data;
+
+    let user_id = match &data.auth {
+      Some(auth) => Claims::decode(&auth).ok().map(|claims| claims.claims.id),
+      None => None,
+    };
+
+    check_private_instance(user_id)?;
+
+    let actions = data.actions(conn, data.page, data.limit)?;
+
+    Ok(GetModlogFeedResponse { actions })
+  }
+}
+
+impl GetModlogFeed {
+  /// The format of an export, checking that the request can make one: only admins can, unless
+  /// `public_modlog_export` is set. The end of the export is fixed to now, so the actions made
+  /// while it's streamed don't move the pages.
+  pub fn export_format(&mut self, conn: &PgConnection) -> Result<ExportFormat, Error> {
+    if Settings::get().public_modlog_export {
+      let user_id = match &self.auth {
+        Some(auth) => Claims::decode(&auth).ok().map(|claims| claims.claims.id),
+        None => None,
+      };
+      check_private_instance(user_id)?;
+    } else {
+      match &self.auth {
+        Some(auth) => admin_user_id(conn, auth)?,
+        None => return Err(APIError::err("not_logged_in").into()),
+      };
+    }
+
+    let format = ExportFormat::from_name(self.export.as_deref())?;
+    let now = naive_now();
+    self.before = Some(self.before.map_or(now, |before| before.min(now)));
+    Ok(format)
+  }
+
+  /// A page of an export, like `GetMyContent::export_page`. Returns whether it was the end.
+  pub fn export_page(
+    &self,
+    conn: &PgConnection,
+    format: ExportFormat,
+    page: i64,
+  ) -> Result<(Vec<u8>, bool), Error> {
+    let actions = self.actions(conn, Some(page), Some(MODLOG_EXPORT_PAGE_SIZE))?;
+    let out = export_chunk(
+      &actions,
+      format,
+      page,
+      MODLOG_EXPORT_CSV_HEADER,
+      modlog_csv_row,
+    )?;
+    Ok((out, actions.is_empty()))
+  }
+
+  fn actions(
+    &self,
+    conn: &PgConnection,
+    page: Option<i64>,
+    limit: Option<i64>,
+  ) -> Result<Vec<ModlogView>, Error> {
+    let kind = match &self.kind {
+      Some(kind) => Some(ModlogActionKind::from_str(kind)?),
+      None => None,
+    };
+
+    let actions = ModlogQueryBuilder::create(&conn)
+      .for_community_id(self.community_id)
+      .for_mod_user_id(self.mod_user_id)
+      .for_other_user_id(self.other_user_id)
+      .kind(kind)
+      .after(self.after)
+      .before(self.before)
+      .page(page)
+      .limit(limit)
+      .list()?;
+
+    Ok(actions)
+  }
+}
+
+/// A modlog action as a line of a csv export.
+fn modlog_csv_row(a: &ModlogView) -> String {
+  fn or_empty<T: ToString>(field: &Option<T>) -> String {
+    field.as_ref().map(T::to_string).unwrap_or_default()
+  }
+
+  csv_line(&[
+    ModlogActionKind::from_i16(a.kind)
+      .map(|kind| kind.to_string())
+      .unwrap_or_default(),
+    a.id.to_string(),
+    a.when_.to_string(),
+    a.mod_user_id.to_string(),
+    a.mod_user_name.to_owned(),
+    or_empty(&a.other_user_id),
+    or_empty(&a.other_user_name),
+    or_empty(&a.community_id),
+    or_empty(&a.community_name),
+    or_empty(&a.post_id),
+    or_empty(&a.post_name),
+    or_empty(&a.comment_id),
+    or_empty(&a.reason),
+    a.applied.to_string(),
+    or_empty(&a.expires),
+  ])
+}
+
 impl Perform<SiteResponse> for Oper<CreateSite> {
   fn perform(&self, conn: &PgConnection) -> Result<SiteResponse, Error> {
     let data: &CreateSite = &self.data;
@@ -230,6 +734,35 @@ impl Perform<SiteResponse> for Oper<CreateSite> {
       enable_downvotes: data.enable_downvotes,
       open_registration: data.open_registration,
       enable_nsfw: data.enable_nsfw,
+      noindex: data.noindex.unwrap_or(false),
+      robots_txt: robots_txt(&data.robots_txt)?,
+      require_alt_text: data.require_alt_text.unwrap_or(false),
+      community_creation_admin_only: data.community_creation_admin_only.unwrap_or(false),
+      community_creation_min_account_age_days: community_creation_minimum(
+        data.community_creation_min_account_age_days,
+        0,
+      )?,
+      community_creation_min_karma: community_creation_minimum(
+        data.community_creation_min_karma,
+        0,
+      )?,
+      vote_weighting: data.vote_weighting.unwrap_or(false),
+      vote_weighting_min_account_age_days: vote_weighting_minimum(
+        data.vote_weighting_min_account_age_days,
+        7,
+      )?,
+      vote_weighting_min_karma: vote_weighting_minimum(data.vote_weighting_min_karma, 0)?,
+      vote_weighting_weight: vote_weighting_weight(data.vote_weighting_weight, 0.5)?,
+      moderation_notices: data.moderation_notices.unwrap_or(true),
+      moderation_notice_pm: data.moderation_notice_pm.unwrap_or(false),
+      moderation_notice_template: moderation_notice_template(&data.moderation_notice_template)?,
+      moderation_appeal_url: non_empty(&data.moderation_appeal_url),
+      karma_exclude_self_votes: data.karma_exclude_self_votes.unwrap_or(false),
+      karma_cap_per_item: karma_option(data.karma_cap_per_item, 0)?,
+      karma_decay_half_life_days: karma_option(data.karma_decay_half_life_days, 0)?,
+      score_visibility: score_visibility(data.score_visibility, 0)?,
+      track_post_views: data.track_post_views.unwrap_or(true),
+      public_edit_history: data.public_edit_history.unwrap_or(false),
       updated: None,
     };
 
@@ -280,13 +813,83 @@ impl Perform<SiteResponse> for Oper<EditSite> {
       enable_downvotes: data.enable_downvotes,
       open_registration: data.open_registration,
       enable_nsfw: data.enable_nsfw,
+      noindex: data.noindex.unwrap_or(found_site.noindex),
+      robots_txt: match &data.robots_txt {
+        Some(_) => robots_txt(&data.robots_txt)?,
+        None => found_site.robots_txt,
+      },
+      require_alt_text: data.require_alt_text.unwrap_or(found_site.require_alt_text),
+      community_creation_admin_only: data
+        .community_creation_admin_only
+        .unwrap_or(found_site.community_creation_admin_only),
+      community_creation_min_account_age_days: community_creation_minimum(
+        data.community_creation_min_account_age_days,
+        found_site.community_creation_min_account_age_days,
+      )?,
+      community_creation_min_karma: community_creation_minimum(
+        data.community_creation_min_karma,
+        found_site.community_creation_min_karma,
+      )?,
+      vote_weighting: data.vote_weighting.unwrap_or(found_site.vote_weighting),
+      vote_weighting_min_account_age_days: vote_weighting_minimum(
+        data.vote_weighting_min_account_age_days,
+        found_site.vote_weighting_min_account_age_days,
+      )?,
+      vote_weighting_min_karma: vote_weighting_minimum(
+        data.vote_weighting_min_karma,
+        found_site.vote_weighting_min_karma,
+      )?,
+      vote_weighting_weight: vote_weighting_weight(
+        data.vote_weighting_weight,
+        found_site.vote_weighting_weight,
+      )?,
+      moderation_notices: data
+        .moderation_notices
+        .unwrap_or(found_site.moderation_notices),
+      moderation_notice_pm: data
+        .moderation_notice_pm
+        .unwrap_or(found_site.moderation_notice_pm),
+      moderation_notice_template: match &data.moderation_notice_template {
+        Some(_) => moderation_notice_template(&data.moderation_notice_template)?,
+        None => found_site.moderation_notice_template,
+      },
+      moderation_appeal_url: match &data.moderation_appeal_url {
+        Some(_) => non_empty(&data.moderation_appeal_url),
+        None => found_site.moderation_appeal_url,
+      },
+      karma_exclude_self_votes: data
+        .karma_exclude_self_votes
+        .unwrap_or(found_site.karma_exclude_self_votes),
+      karma_cap_per_item: karma_option(data.karma_cap_per_item, found_site.karma_cap_per_item)?,
+      karma_decay_half_life_days: karma_option(
+        data.karma_decay_half_life_days,
+        found_site.karma_decay_half_life_days,
+      )?,
+      score_visibility: score_visibility(data.score_visibility, found_site.score_visibility)?,
+      track_post_views: data.track_post_views.unwrap_or(found_site.track_post_views),
+      public_edit_history: data
+        .public_edit_history
+        .unwrap_or(found_site.public_edit_history),
     };
 
-    match Site::update(&conn, 1, &site_form) {
+    let updated_site = match Site::update(&conn, 1, &site_form) {
       Ok(site) => site,
       Err(_e) => return Err(APIError::err("couldnt_update_site").into()),
     };
 
+    // Karma is counted again right away with the new options
+    if (
+      updated_site.karma_exclude_self_votes,
+      updated_site.karma_cap_per_item,
+      updated_site.karma_decay_half_life_days,
+    ) != (
+      found_site.karma_exclude_self_votes,
+      found_site.karma_cap_per_item,
+      found_site.karma_decay_half_life_days,
+    ) {
+      UserView::refresh(&conn)?;
+    }
+
     let site_view = SiteView::read(&conn)?;
 
     Ok(SiteResponse { site: site_view })
@@ -318,6 +921,26 @@ impl Perform<GetSiteResponse> for Oper<GetSite> {
         enable_downvotes: false,
         open_registration: false,
         enable_nsfw: false,
+        noindex: None,
+        robots_txt: None,
+        require_alt_text: None,
+        community_creation_admin_only: None,
+        community_creation_min_account_age_days: None,
+        community_creation_min_karma: None,
+        vote_weighting: None,
+        vote_weighting_min_account_age_days: None,
+        vote_weighting_min_karma: None,
+        vote_weighting_weight: None,
+        moderation_notices: None,
+        moderation_notice_pm: None,
+        moderation_notice_template: None,
+        moderation_appeal_url: None,
+        karma_exclude_self_votes: None,
+        karma_cap_per_item: None,
+        karma_decay_half_life_days: None,
+        score_visibility: None,
+        track_post_views: None,
+        public_edit_history: None,
         auth: login_response.jwt,
       };
       Oper::new(create_site).perform(&conn)?;
@@ -335,13 +958,19 @@ impl Perform<GetSiteResponse> for Oper<GetSite> {
       admins.insert(0, creator_user);
     }
 
-    let banned = UserView::banned(&conn)?;
+    let mut banned = UserView::banned(&conn)?;
+
+    if Site::hides_scores_from(&conn, None)? {
+      admins.hide_scores();
+      banned.hide_scores();
+    }
 
     Ok(GetSiteResponse {
       site: site_view,
       admins,
       banned,
       online: 0,
+      translation_enabled: Settings::get().translation.is_some(),
     })
   }
 }
@@ -361,6 +990,8 @@ impl Perform<SearchResponse> for Oper<Search> {
       None => None,
     };
 
+    check_private_instance(user_id)?;
+
     let sort = SortType::from_str(&data.sort)?;
     let type_ = SearchType::from_str(&data.type_)?;
 
@@ -370,15 +1001,17 @@ impl Perform<SearchResponse> for Oper<Search> {
     let mut users = Vec::new();
 
     // TODO no clean / non-nsfw searching rn
+    let hide_generated = User_::hides_generated(&conn, user_id)?;
 
     match type_ {
       SearchType::Posts => {
         posts = PostQueryBuilder::create(&conn)
           .sort(&sort)
           .show_nsfw(true)
-          .for_community_id(data.community_id)
+          .hide_generated(hide_generated)
+          .for_community_id(data.community_id.map(CommunityId))
           .search_term(data.q.to_owned())
-          .my_user_id(user_id)
+          .my_user_id(user_id.map(UserId))
           .page(data.page)
           .limit(data.limit)
           .list()?;
@@ -387,7 +1020,8 @@ impl Perform<SearchResponse> for Oper<Search> {
         comments = CommentQueryBuilder::create(&conn)
           .sort(&sort)
           .search_term(data.q.to_owned())
-          .my_user_id(user_id)
+          .hide_generated(hide_generated)
+          .my_user_id(user_id.map(UserId))
           .page(data.page)
           .limit(data.limit)
           .list()?;
@@ -396,6 +1030,7 @@ impl Perform<SearchResponse> for Oper<Search> {
         communities = CommunityQueryBuilder::create(&conn)
           .sort(&sort)
           .search_term(data.q.to_owned())
+          .for_user(user_id.map(UserId))
           .page(data.page)
           .limit(data.limit)
           .list()?;
@@ -404,6 +1039,8 @@ impl Perform<SearchResponse> for Oper<Search> {
         users = UserQueryBuilder::create(&conn)
           .sort(&sort)
           .search_term(data.q.to_owned())
+          .logged_in(user_id.is_some())
+          .for_user(user_id.map(UserId))
           .page(data.page)
           .limit(data.limit)
           .list()?;
@@ -412,9 +1049,10 @@ impl Perform<SearchResponse> for Oper<Search> {
         posts = PostQueryBuilder::create(&conn)
           .sort(&sort)
           .show_nsfw(true)
-          .for_community_id(data.community_id)
+          .hide_generated(hide_generated)
+          .for_community_id(data.community_id.map(CommunityId))
           .search_term(data.q.to_owned())
-          .my_user_id(user_id)
+          .my_user_id(user_id.map(UserId))
           .page(data.page)
           .limit(data.limit)
           .list()?;
@@ -422,7 +1060,8 @@ impl Perform<SearchResponse> for Oper<Search> {
         comments = CommentQueryBuilder::create(&conn)
           .sort(&sort)
           .search_term(data.q.to_owned())
-          .my_user_id(user_id)
+          .hide_generated(hide_generated)
+          .my_user_id(user_id.map(UserId))
           .page(data.page)
           .limit(data.limit)
           .list()?;
@@ -430,6 +1069,7 @@ impl Perform<SearchResponse> for Oper<Search> {
         communities = CommunityQueryBuilder::create(&conn)
           .sort(&sort)
           .search_term(data.q.to_owned())
+          .for_user(user_id.map(UserId))
           .page(data.page)
           .limit(data.limit)
           .list()?;
@@ -437,6 +1077,8 @@ impl Perform<SearchResponse> for Oper<Search> {
         users = UserQueryBuilder::create(&conn)
           .sort(&sort)
           .search_term(data.q.to_owned())
+          .logged_in(user_id.is_some())
+          .for_user(user_id.map(UserId))
           .page(data.page)
           .limit(data.limit)
           .list()?;
@@ -445,7 +1087,8 @@ impl Perform<SearchResponse> for Oper<Search> {
         posts = PostQueryBuilder::create(&conn)
           .sort(&sort)
           .show_nsfw(true)
-          .for_community_id(data.community_id)
+          .hide_generated(hide_generated)
+          .for_community_id(data.community_id.map(CommunityId))
           .url_search(data.q.to_owned())
           .page(data.page)
           .limit(data.limit)
@@ -453,6 +1096,12 @@ impl Perform<SearchResponse> for Oper<Search> {
       }
     };
 
+    if Site::hides_scores_from(&conn, user_id)? {
+      posts.hide_scores();
+      comments.hide_scores();
+      users.hide_scores();
+    }
+
     // Return the jwt
     Ok(SearchResponse {
       type_: data.type_.to_owned(),
@@ -490,6 +1139,26 @@ impl Perform<GetSiteResponse> for Oper<TransferSite> {
       enable_downvotes: read_site.enable_downvotes,
       open_registration: read_site.open_registration,
       enable_nsfw: read_site.enable_nsfw,
+      noindex: read_site.noindex,
+      robots_txt: read_site.robots_txt,
+      require_alt_text: read_site.require_alt_text,
+      community_creation_admin_only: read_site.community_creation_admin_only,
+      community_creation_min_account_age_days: read_site.community_creation_min_account_age_days,
+      community_creation_min_karma: read_site.community_creation_min_karma,
+      vote_weighting: read_site.vote_weighting,
+      vote_weighting_min_account_age_days: read_site.vote_weighting_min_account_age_days,
+      vote_weighting_min_karma: read_site.vote_weighting_min_karma,
+      vote_weighting_weight: read_site.vote_weighting_weight,
+      moderation_notices: read_site.moderation_notices,
+      moderation_notice_pm: read_site.moderation_notice_pm,
+      moderation_notice_template: read_site.moderation_notice_template,
+      moderation_appeal_url: read_site.moderation_appeal_url,
+      karma_exclude_self_votes: read_site.karma_exclude_self_votes,
+      karma_cap_per_item: read_site.karma_cap_per_item,
+      karma_decay_half_life_days: read_site.karma_decay_half_life_days,
+      score_visibility: read_site.score_visibility,
+      track_post_views: read_site.track_post_views,
+      public_edit_history: read_site.public_edit_history,
     };
 
     match Site::update(&conn, 1, &site_form) {
@@ -516,17 +1185,74 @@ impl Perform<GetSiteResponse> for Oper<TransferSite> {
     let creator_user = admins.remove(creator_index);
     admins.insert(0, creator_user);
 
-    let banned = UserView::banned(&conn)?;
+    let mut banned = UserView::banned(&conn)?;
+
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      admins.hide_scores();
+      banned.hide_scores();
+    }
 
     Ok(GetSiteResponse {
       site: Some(site_view),
       admins,
       banned,
       online: 0,
+      translation_enabled: Settings::get().translation.is_some(),
     })
   }
 }
 
+impl Perform<LegalHoldResponse> for Oper<LegalHold> {
+  fn perform(&self, conn: &PgConnection) -> Result<LegalHoldResponse, Error> {
+    let data: &LegalHold = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    // Make sure user is an admin
+    if !UserView::read(&conn, user_id)?.admin {
+      return Err(APIError::err("not_an_admin").into());
+    }
+
+    // Holds are on one user or one post at a time
+    if data.user_id.is_some() == data.post_id.is_some() {
+      return Err(APIError::err("no_legal_hold_target").into());
+    }
+
+    transaction_with_retry(conn, || {
+      if let Some(other_user_id) = data.user_id {
+        if let Err(e) = User_::update_legal_hold(&conn, other_user_id, data.hold) {
+          return Err(APIError::from_db("couldnt_update_user", e));
+        }
+      }
+
+      if let Some(post_id) = data.post_id {
+        if let Err(e) = Post::update_legal_hold(&conn, post_id, data.hold) {
+          return Err(APIError::from_db("couldnt_update_post", e));
+        }
+      }
+
+      // Mod tables
+      let form = ModLegalHoldForm {
+        mod_user_id: user_id,
+        other_user_id: data.user_id,
+        post_id: data.post_id,
+        reason: data.reason.to_owned(),
+        held: Some(data.hold),
+      };
+      ModLegalHold::create(&conn, &form)?;
+
+      Ok(())
+    })?;
+
+    Ok(LegalHoldResponse { held: data.hold })
+  }
+}
+
 impl Perform<GetSiteConfigResponse> for Oper<GetSiteConfig> {
   fn perform(&self, conn: &PgConnection) -> Result<GetSiteConfigResponse, Error> {
     let data: &GetSiteConfig = &self.data;
@@ -580,3 +1306,348 @@ impl Perform<GetSiteConfigResponse> for Oper<SaveSiteConfig> {
     Ok(GetSiteConfigResponse { config_hjson })
   }
 }
+
+impl Perform<ImportIpBlocksResponse> for Oper<ImportIpBlocks> {
+  fn perform(&self, conn: &PgConnection) -> Result<ImportIpBlocksResponse, Error> {
+    let data: &ImportIpBlocks = &self.data;
+
+    let user_id = admin_user_id(conn, &data.auth)?;
+
+    let (networks, invalid) = ip_blocklist::parse_list(&data.list);
+    let forms = networks
+      .into_iter()
+      .map(|network| {
+        let (cidr, asn) = match network {
+          Network::Cidr(cidr) => (Some(cidr), None),
+          Network::Asn(asn) => (None, Some(asn)),
+        };
+        IpBlockForm {
+          cidr,
+          asn,
+          reason: data.reason.to_owned(),
+          block_posting: data.block_posting,
+          creator_id: user_id,
+        }
+      })
+      .collect::<Vec<IpBlockForm>>();
+
+    let added = match IpBlock::create_many(&conn, &forms) {
+      Ok(added) => added,
+      Err(_e) => return Err(APIError::err("couldnt_create_ip_block").into()),
+    };
+
+    Ok(ImportIpBlocksResponse { added, invalid })
+  }
+}
+
+impl Perform<ListIpBlocksResponse> for Oper<ListIpBlocks> {
+  fn perform(&self, conn: &PgConnection) -> Result<ListIpBlocksResponse, Error> {
+    let data: &ListIpBlocks = &self.data;
+
+    admin_user_id(conn, &data.auth)?;
+
+    let ip_blocks = IpBlock::list_all(&conn)?;
+
+    Ok(ListIpBlocksResponse { ip_blocks })
+  }
+}
+
+impl Perform<IpBlockResponse> for Oper<DeleteIpBlock> {
+  fn perform(&self, conn: &PgConnection) -> Result<IpBlockResponse, Error> {
+    let data: &DeleteIpBlock = &self.data;
+
+    admin_user_id(conn, &data.auth)?;
+
+    let ip_block = IpBlock::read(&conn, data.id)?;
+    if IpBlock::delete(&conn, data.id).is_err() {
+      return Err(APIError::err("couldnt_delete_ip_block").into());
+    }
+
+    Ok(IpBlockResponse { ip_block })
+  }
+}
+
+impl Perform<ResolveObjectResponse> for Oper<ResolveObject> {
+  fn perform(&self, conn: &PgConnection) -> Result<ResolveObjectResponse, Error> {
+    let data: &ResolveObject = &self.data;
+
+    let user_id: Option<i32> = match &data.auth {
+      Some(auth) => match Claims::decode(&auth) {
+        Ok(claims) => Some(claims.claims.id),
+        Err(_e) => None,
+      },
+      None => None,
+    };
+
+    check_private_instance(user_id)?;
+
+    // Remote objects aren't stored here, so only urls of this instance can be resolved
+    match local_object(data.q.trim()) {
+      Some(LocalObject::Post(post_id)) => {
+        let post = match PostView::read(&conn, PostId(post_id), user_id.map(UserId)) {
+          Ok(post) => post,
+          Err(_e) => return Err(APIError::err("couldnt_find_post").into()),
+        };
+        if !can_view_post(&conn, &post, user_id)? {
+          return Err(APIError::err("couldnt_find_post").into());
+        }
+        Ok(ResolveObjectResponse {
+          post: Some(post),
+          comment: None,
+        })
+      }
+      Some(LocalObject::Comment(comment_id)) => {
+        let comment = match CommentView::read(&conn, CommentId(comment_id), user_id.map(UserId)) {
+          Ok(comment) => comment,
+          Err(_e) => return Err(APIError::err("couldnt_find_comment").into()),
+        };
+        let post = PostView::read(&conn, PostId(comment.post_id), user_id.map(UserId))?;
+        if !can_view_post(&conn, &post, user_id)? {
+          return Err(APIError::err("couldnt_find_comment").into());
+        }
+        Ok(ResolveObjectResponse {
+          post: None,
+          comment: Some(comment),
+        })
+      }
+      None => Err(APIError::err("couldnt_find_object").into()),
+    }
+  }
+}
+
+impl Perform<PrefetchActorsResponse> for Oper<PrefetchActors> {
+  fn perform(&self, conn: &PgConnection) -> Result<PrefetchActorsResponse, Error> {
+    let data: &PrefetchActors = &self.data;
+
+    let user_id = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims.id,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    if data.actors.len() > MAX_PREFETCH_ACTORS {
+      return Err(APIError::err("too_many_actors").into());
+    }
+
+    let hide_scores = Site::hides_scores_from(&conn, Some(user_id))?;
+    let mut actors = Vec::new();
+    for (q, resolved) in data.actors.iter().zip(prefetch_actors(&conn, &data.actors)) {
+      let mut actor = PrefetchedActor {
+        q: q.to_owned(),
+        user: None,
+        community: None,
+        error: None,
+      };
+      match resolved {
+        Ok(ResolvedActor::User(user)) => {
+          let mut user_view = UserView::read(&conn, user.id)?;
+          if hide_scores {
+            user_view.hide_scores();
+          }
+          actor.user = Some(user_view);
+        }
+        Ok(ResolvedActor::Community(community)) => {
+          actor.community = Some(CommunityView::read(&conn, community.id, Some(user_id))?);
+        }
+        Err(error) => actor.error = Some(error),
+      }
+      actors.push(actor);
+    }
+
+    Ok(PrefetchActorsResponse { actors })
+  }
+}
+
+impl Perform<GetApubIdResponse> for Oper<GetApubId> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetApubIdResponse, Error> {
+    let data: &GetApubId = &self.data;
+
+    let user_id: Option<i32> = match &data.auth {
+      Some(auth) => match Claims::decode(&auth) {
+        Ok(claims) => Some(claims.claims.id),
+        Err(_e) => None,
+      },
+      None => None,
+    };
+
+    check_private_instance(user_id)?;
+
+    // The url has the post's title in it, so only posts the user can see are found
+    let hostname = Settings::get().hostname;
+    match (data.post_id, data.comment_id) {
+      (Some(post_id), None) => {
+        let post = match PostView::read(&conn, post_id, user_id.map(UserId)) {
+          Ok(post) => post,
+          Err(_e) => return Err(APIError::err("couldnt_find_post").into()),
+        };
+        if !can_view_post(&conn, &post, user_id)? || is_gone(&post) {
+          return Err(APIError::err("couldnt_find_post").into());
+        }
+        let federated = federated_community(&conn, post.community_id).is_some();
+        Ok(GetApubIdResponse {
+          ap_id: Some(make_apub_endpoint("post", post.id)).filter(|_| federated),
+          url: format!("https://{}/{}", hostname, post_path(post.id, &post.slug)),
+        })
+      }
+      (None, Some(comment_id)) => {
+        let comment = match CommentView::read(&conn, comment_id, user_id.map(UserId)) {
+          Ok(comment) => comment,
+          Err(_e) => return Err(APIError::err("couldnt_find_comment").into()),
+        };
+        let post = match PostView::read(&conn, PostId(comment.post_id), user_id.map(UserId)) {
+          Ok(post) => post,
+          Err(_e) => return Err(APIError::err("couldnt_find_comment").into()),
+        };
+        if !can_view_post(&conn, &post, user_id)? || is_gone(&post) {
+          return Err(APIError::err("couldnt_find_comment").into());
+        }
+        let federated = federated_community(&conn, post.community_id).is_some();
+        Ok(GetApubIdResponse {
+          ap_id: Some(make_apub_endpoint("comment", comment.id)).filter(|_| federated),
+          url: format!(
+            "https://{}/post/{}/comment/{}",
+            hostname, comment.post_id, comment.id
+          ),
+        })
+      }
+      _ => Err(APIError::err("couldnt_find_object").into()),
+    }
+  }
+}
+
+/// Whether a post, or its community, was removed or deleted.
+fn is_gone(post: &PostView) -> bool {
+  post.removed || post.deleted || post.community_removed || post.community_deleted
+}
+
+impl Perform<GetInstanceImpactResponse> for Oper<GetInstanceImpact> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetInstanceImpactResponse, Error> {
+    let data: &GetInstanceImpact = &self.data;
+
+    admin_user_id(conn, &data.auth)?;
+
+    let domain = data.domain.trim().to_lowercase();
+    if domain.is_empty() || domain == Settings::get().actor_domain() {
+      return Err(APIError::err("invalid_domain").into());
+    }
+
+    let impact = InstanceImpact::for_domain(&conn, &domain)?;
+
+    Ok(GetInstanceImpactResponse { domain, impact })
+  }
+}
+
+impl Perform<ListInboxRejectionsResponse> for Oper<ListInboxRejections> {
+  fn perform(&self, conn: &PgConnection) -> Result<ListInboxRejectionsResponse, Error> {
+    let data: &ListInboxRejections = &self.data;
+
+    admin_user_id(conn, &data.auth)?;
+
+    let rejections = InboxRejection::list(&conn)?;
+
+    Ok(ListInboxRejectionsResponse { rejections })
+  }
+}
+
+impl Perform<GetFederationMetricsResponse> for Oper<GetFederationMetrics> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetFederationMetricsResponse, Error> {
+    let data: &GetFederationMetrics = &self.data;
+
+    admin_user_id(conn, &data.auth)?;
+
+    Ok(GetFederationMetricsResponse {
+      metrics: federation_metrics(),
+    })
+  }
+}
+
+impl Perform<GetOperationMetricsResponse> for Oper<GetOperationMetrics> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetOperationMetricsResponse, Error> {
+    let data: &GetOperationMetrics = &self.data;
+
+    admin_user_id(conn, &data.auth)?;
+
+    Ok(GetOperationMetricsResponse {
+      operations: operation_metrics(),
+    })
+  }
+}
+
+impl Perform<ListReservedCommunityNamesResponse> for Oper<ListReservedCommunityNames> {
+  fn perform(&self, conn: &PgConnection) -> Result<ListReservedCommunityNamesResponse, Error> {
+    let data: &ListReservedCommunityNames = &self.data;
+
+    admin_user_id(conn, &data.auth)?;
+
+    let reserved_names = ReservedCommunityName::list_all(&conn)?;
+
+    Ok(ListReservedCommunityNamesResponse { reserved_names })
+  }
+}
+
+impl Perform<ReservedCommunityNameResponse> for Oper<ReserveCommunityName> {
+  fn perform(&self, conn: &PgConnection) -> Result<ReservedCommunityNameResponse, Error> {
+    let data: &ReserveCommunityName = &self.data;
+
+    let user_id = admin_user_id(conn, &data.auth)?;
+
+    let form = ReservedCommunityNameForm {
+      name: data.name.trim().to_owned(),
+      reason: non_empty(&data.reason),
+      creator_id: user_id,
+    };
+    if form.name.is_empty() {
+      return Err(APIError::err("invalid_community_name").into());
+    }
+
+    let reserved_name = match ReservedCommunityName::create(&conn, &form) {
+      Ok(reserved_name) => reserved_name,
+      Err(_e) => return Err(APIError::err("community_name_already_reserved").into()),
+    };
+
+    Ok(ReservedCommunityNameResponse { reserved_name })
+  }
+}
+
+impl Perform<ReservedCommunityNameResponse> for Oper<DeleteReservedCommunityName> {
+  fn perform(&self, conn: &PgConnection) -> Result<ReservedCommunityNameResponse, Error> {
+    let data: &DeleteReservedCommunityName = &self.data;
+
+    admin_user_id(conn, &data.auth)?;
+
+    let reserved_name = ReservedCommunityName::read(&conn, data.id)?;
+    if ReservedCommunityName::delete(&conn, data.id).is_err() {
+      return Err(APIError::err("couldnt_delete_reserved_community_name").into());
+    }
+
+    Ok(ReservedCommunityNameResponse { reserved_name })
+  }
+}
+
+impl Perform<ConsistencyReportResponse> for Oper<GetConsistencyReport> {
+  fn perform(&self, conn: &PgConnection) -> Result<ConsistencyReportResponse, Error> {
+    let data: &GetConsistencyReport = &self.data;
+
+    admin_user_id(conn, &data.auth)?;
+
+    let checks = ConsistencyCheck::latest(&conn)?;
+
+    Ok(ConsistencyReportResponse { checks })
+  }
+}
+
+impl Perform<ConsistencyReportResponse> for Oper<RunConsistencyCheck> {
+  fn perform(&self, conn: &PgConnection) -> Result<ConsistencyReportResponse, Error> {
+    let data: &RunConsistencyCheck = &self.data;
+
+    let user_id = admin_user_id(conn, &data.auth)?;
+
+    let checks = ConsistencyCheck::run_all(&conn, data.repair)?;
+    let found: i64 = checks.iter().map(|check| check.found).sum();
+    info!(
+      "Admin {} ran a consistency check, which found {} inconsistent rows",
+      user_id, found
+    );
+
+    Ok(ConsistencyReportResponse { checks })
+  }
+}
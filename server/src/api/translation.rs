@@ -0,0 +1,99 @@
+use super::*;
+use crate::db::translation::*;
+use crate::translation::{translate, valid_lang};
+use log::error;
+
+/// Translates a post or a comment, with the translation server set in the settings.
+#[derive(Serialize, Deserialize)]
+pub struct TranslateContent {
+  post_id: Option<i32>,
+  comment_id: Option<i32>,
+  lang: String,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TranslateContentResponse {
+  translation: Translation,
+}
+
+/// Whether a cached translation is still of the current text.
+fn is_fresh(translation: &Translation, updated: Option<chrono::NaiveDateTime>) -> bool {
+  updated.map_or(true, |updated| updated <= translation.published)
+}
+
+impl Perform<TranslateContentResponse> for Oper<TranslateContent> {
+  fn perform(&self, conn: &PgConnection) -> Result<TranslateContentResponse, Error> {
+    let data: &TranslateContent = &self.data;
+
+    // Only for logged in users, since translation servers are usually paid for
+    if Claims::decode(&data.auth).is_err() {
+      return Err(APIError::err("not_logged_in").into());
+    }
+
+    let config = match Settings::get().translation {
+      Some(config) => config,
+      None => return Err(APIError::err("translation_not_enabled").into()),
+    };
+
+    if !valid_lang(&data.lang) {
+      return Err(APIError::err("invalid_language").into());
+    }
+    let lang = data.lang.to_lowercase();
+
+    let mut form = TranslationForm {
+      post_id: data.post_id,
+      comment_id: data.comment_id,
+      lang: lang.to_owned(),
+      name: None,
+      content: None,
+    };
+
+    let translate_text = |text: &str| match translate(&config, text, &lang) {
+      Ok(translated) => Ok(translated),
+      Err(e) => {
+        error!("Couldn't translate to {}: {}", lang, e);
+        Err(APIError::err("couldnt_translate"))
+      }
+    };
+
+    match (data.post_id, data.comment_id) {
+      (Some(post_id), None) => {
+        let post = match Post::read(&conn, post_id) {
+          Ok(post) if !post.deleted && !post.removed => post,
+          _ => return Err(APIError::err("couldnt_find_post").into()),
+        };
+        if let Ok(translation) = Translation::read_for_post(&conn, post_id, &lang) {
+          if is_fresh(&translation, post.updated) {
+            return Ok(TranslateContentResponse { translation });
+          }
+        }
+        form.name = Some(translate_text(&post.name)?);
+        form.content = match &post.body {
+          Some(body) => Some(translate_text(body)?),
+          None => None,
+        };
+      }
+      (None, Some(comment_id)) => {
+        let comment = match Comment::read(&conn, comment_id) {
+          Ok(comment) if !comment.deleted && !comment.removed => comment,
+          _ => return Err(APIError::err("couldnt_find_comment").into()),
+        };
+        if let Ok(translation) = Translation::read_for_comment(&conn, comment_id, &lang) {
+          if is_fresh(&translation, comment.updated) {
+            return Ok(TranslateContentResponse { translation });
+          }
+        }
+        form.content = Some(translate_text(&comment.content)?);
+      }
+      _ => return Err(APIError::err("no_translation_target").into()),
+    }
+
+    let translation = match Translation::replace(&conn, &form) {
+      Ok(translation) => translation,
+      Err(_e) => return Err(APIError::err("couldnt_translate").into()),
+    };
+
+    Ok(TranslateContentResponse { translation })
+  }
+}
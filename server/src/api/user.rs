@@ -1,4 +1,8 @@
 use super::*;
+use crate::apub::cache::evict_apub_object;
+use crate::apub::flag::send_flag;
+use crate::apub::user::refresh_remote_user;
+use crate::apub::{make_apub_endpoint, url_host};
 use crate::settings::Settings;
 use crate::{generate_random_string, send_email};
 use bcrypt::verify;
@@ -37,6 +41,26 @@ pub struct SaveUserSettings {
   old_password: Option<String>,
   show_avatars: bool,
   send_notifications_to_email: bool,
+  settings: Option<UserSettings>,
+  /// Only lets logged in users see the profile, and keeps search engines off it
+  hide_profile: Option<bool>,
+  /// Hides the activity heatmap from everyone else
+  hide_activity: Option<bool>,
+  /// The percentage of the Blended listing made up of posts from outside their subscriptions
+  blended_discovery_percent: Option<i16>,
+  /// Only mixes local posts into the Blended listing
+  blended_discovery_local: Option<bool>,
+  /// Shows vote scores, unless the site hides them from everyone
+  show_scores: Option<bool>,
+  /// Flags the account as run by a bot, which has to mark its posts and comments generated
+  bot_account: Option<bool>,
+  /// Shows the posts and comments made by bots or generated by AI
+  show_generated: Option<bool>,
+  /// Collapses the posts of the same link into one in the All and Subscribed listings
+  collapse_crossposts: Option<bool>,
+  /// The `Language`s to see posts and comments in, as well as those without one. All of them if
+  /// it's empty.
+  language_ids: Option<Vec<i32>>,
   auth: String,
 }
 
@@ -65,6 +89,42 @@ pub struct GetUserDetailsResponse {
   comments: Vec<CommentView>,
   posts: Vec<PostView>,
   admins: Vec<UserView>,
+  /// Only returned to the user themself.
+  settings: Option<UserSettings>,
+  /// Only returned to the user themself.
+  hide_profile: Option<bool>,
+  /// Only returned to the user themself.
+  hide_activity: Option<bool>,
+  /// Only returned to the user themself.
+  blended_discovery_percent: Option<i16>,
+  /// Only returned to the user themself.
+  blended_discovery_local: Option<bool>,
+  /// Only returned to the user themself.
+  show_scores: Option<bool>,
+  /// Whether the account is run by a bot
+  bot_account: bool,
+  /// Only returned to the user themself.
+  show_generated: Option<bool>,
+  /// Only returned to the user themself.
+  collapse_crossposts: Option<bool>,
+  /// Only returned to the user themself.
+  language_ids: Option<Vec<i32>>,
+  /// The notes about the user of the communities the viewer moderates, or of all of them for
+  /// admins. Only returned to moderators and admins.
+  mod_notes: Option<Vec<ModNoteView>>,
+  /// The posts and comments the user pinned, which are first on the first page of theirs.
+  pins: Vec<Featured>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetUserActivity {
+  user_id: i32,
+  auth: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetUserActivityResponse {
+  days: Vec<UserActivityDay>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -77,11 +137,38 @@ pub struct GetUserMentionsResponse {
   mentions: Vec<UserMentionView>,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct GetInboxResponse {
+  inbox: Vec<InboxCombinedView>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct MarkAllAsRead {
   auth: String,
 }
 
+/// Marks replies, mentions and private messages to the user as read, or unread, all at once. The
+/// ids are the ones `GetInbox` gives.
+#[derive(Serialize, Deserialize)]
+pub struct MarkAsRead {
+  reply_ids: Option<Vec<i32>>,
+  user_mention_ids: Option<Vec<i32>>,
+  private_message_ids: Option<Vec<i32>>,
+  read: bool,
+  auth: String,
+}
+
+/// How many of each were changed.
+#[derive(Serialize, Deserialize)]
+pub struct MarkAsReadResponse {
+  replies: usize,
+  user_mentions: usize,
+  private_messages: usize,
+}
+
+/// The most ids `MarkAsRead` takes of each kind.
+const MAX_MARK_AS_READ_IDS: usize = 1000;
+
 #[derive(Serialize, Deserialize)]
 pub struct AddAdmin {
   user_id: i32,
@@ -100,6 +187,8 @@ pub struct BanUser {
   ban: bool,
   reason: Option<String>,
   expires: Option<i64>,
+  /// A `RemoteModerationScope`, required to ban a remote user
+  remote_scope: Option<String>,
   auth: String,
 }
 
@@ -109,6 +198,142 @@ pub struct BanUserResponse {
   banned: bool,
 }
 
+/// Blocks or unblocks a user. Their posts, comments, mentions and private messages are left out of
+/// everything the blocking user sees.
+#[derive(Serialize, Deserialize)]
+pub struct BlockUser {
+  user_id: i32,
+  block: bool,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BlockUserResponse {
+  user: UserView,
+  blocked: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetBlockedUsers {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetBlockedUsersResponse {
+  users: Vec<UserView>,
+}
+
+/// Pins one of the user's own posts or comments to their profile, after the ones already pinned,
+/// or unpins it.
+#[derive(Serialize, Deserialize)]
+pub struct PinToProfile {
+  post_id: Option<i32>,
+  comment_id: Option<i32>,
+  pin: bool,
+  auth: String,
+}
+
+/// Puts all of the user's pins in a new order.
+#[derive(Serialize, Deserialize)]
+pub struct ReorderProfilePins {
+  pin_ids: Vec<i32>,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProfilePinsResponse {
+  pins: Vec<Featured>,
+}
+
+/// The most posts and comments a user can pin to their profile.
+const MAX_PROFILE_PINS: usize = 5;
+
+/// Blocks or unblocks a remote instance, by its domain or an activitypub id from it.
+#[derive(Serialize, Deserialize)]
+pub struct BlockInstance {
+  domain: String,
+  block: bool,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BlockInstanceResponse {
+  domain: String,
+  blocked: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetBlockedInstances {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetBlockedInstancesResponse {
+  instances: Vec<UserInstanceBlock>,
+}
+
+/// The user's own comments, with the posts they're on, newest first.
+#[derive(Serialize, Deserialize)]
+pub struct GetMyContent {
+  community_id: Option<i32>,
+  published_after: Option<chrono::NaiveDateTime>,
+  published_before: Option<chrono::NaiveDateTime>,
+  removed_only: Option<bool>,
+  page: Option<i64>,
+  limit: Option<i64>,
+  /// `csv`, `json` or `jsonl`, for the export route, which has every comment rather than a page
+  export: Option<String>,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetMyContentResponse {
+  comments: Vec<CommentWithPostView>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+  Csv,
+  /// An array of the items
+  Json,
+  /// An item on each line
+  JsonLines,
+}
+
+impl ExportFormat {
+  /// The format named by an `export` field.
+  pub fn from_name(name: Option<&str>) -> Result<Self, Error> {
+    match name {
+      Some("csv") => Ok(ExportFormat::Csv),
+      Some("json") => Ok(ExportFormat::Json),
+      Some("jsonl") => Ok(ExportFormat::JsonLines),
+      _ => Err(APIError::err("invalid_export_format").into()),
+    }
+  }
+
+  pub fn content_type(self) -> &'static str {
+    match self {
+      ExportFormat::Csv => "text/csv; charset=utf-8",
+      ExportFormat::Json => "application/json",
+      ExportFormat::JsonLines => "application/x-ndjson",
+    }
+  }
+
+  pub fn extension(self) -> &'static str {
+    match self {
+      ExportFormat::Csv => "csv",
+      ExportFormat::Json => "json",
+      ExportFormat::JsonLines => "jsonl",
+    }
+  }
+}
+
+/// How many comments an export reads at a time.
+const EXPORT_PAGE_SIZE: i64 = 200;
+
+const EXPORT_CSV_HEADER: &str =
+  "id,published,updated,community_name,post_id,post_name,post_url,parent_id,content,score,removed,deleted\n";
+
 #[derive(Serialize, Deserialize)]
 pub struct GetReplies {
   sort: String,
@@ -127,6 +352,112 @@ pub struct GetUserMentions {
   auth: String,
 }
 
+/// The replies, mentions and private messages to a user as one feed, newest first.
+#[derive(Serialize, Deserialize)]
+pub struct GetInbox {
+  page: Option<i64>,
+  limit: Option<i64>,
+  unread_only: bool,
+  auth: String,
+}
+
+/// How many unread replies, mentions and private messages there are, for the inbox badge.
+#[derive(Serialize, Deserialize)]
+pub struct GetUnreadCount {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetUnreadCountResponse {
+  replies: i64,
+  mentions: i64,
+  private_messages: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateSavedFolder {
+  name: String,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EditSavedFolder {
+  folder_id: i32,
+  name: String,
+  auth: String,
+}
+
+/// The folder's items stay saved, without a folder.
+#[derive(Serialize, Deserialize)]
+pub struct DeleteSavedFolder {
+  folder_id: i32,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetSavedFolders {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetSavedFoldersResponse {
+  folders: Vec<SavedFolder>,
+}
+
+/// A user's saved posts and comments as one list, the last saved first.
+#[derive(Serialize, Deserialize)]
+pub struct GetSavedItems {
+  folder_id: Option<i32>,
+  page: Option<i64>,
+  limit: Option<i64>,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetSavedItemsResponse {
+  items: Vec<SavedItemsView>,
+}
+
+/// Mutes a word or phrase, or a regular expression with `regex`.
+#[derive(Serialize, Deserialize)]
+pub struct CreateKeywordFilter {
+  keyword: String,
+  regex: Option<bool>,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeleteKeywordFilter {
+  keyword_filter_id: i32,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetKeywordFilters {
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetKeywordFiltersResponse {
+  keyword_filters: Vec<UserKeywordFilter>,
+}
+
+/// The most words a user can mute.
+const MAX_KEYWORD_FILTERS: usize = 100;
+
+#[derive(Serialize, Deserialize)]
+pub struct GetModerationNotices {
+  page: Option<i64>,
+  limit: Option<i64>,
+  unread_only: bool,
+  auth: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetModerationNoticesResponse {
+  notices: Vec<ModerationNotice>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct EditUserMention {
   user_mention_id: i32,
@@ -236,6 +567,8 @@ impl Perform<LoginResponse> for Oper<Register> {
       }
     }
 
+    ip_blocklist::check(&conn, self.ip.as_deref(), false)?;
+
     // Make sure passwords match
     if data.password != data.password_verify {
       return Err(APIError::err("passwords_dont_match").into());
@@ -253,7 +586,7 @@ impl Perform<LoginResponse> for Oper<Register> {
     // Register the new user
     let user_form = UserForm {
       name: data.username.to_owned(),
-      fedi_name: Settings::get().hostname,
+      fedi_name: Settings::get().actor_domain(),
       email: data.email.to_owned(),
       matrix_user_id: None,
       avatar: None,
@@ -339,6 +672,37 @@ impl Perform<LoginResponse> for Oper<Register> {
   }
 }
 
+/// The most client hints a user can store.
+const MAX_CLIENT_HINTS: usize = 50;
+/// The longest a client hint's key or value can be.
+const MAX_CLIENT_HINT_LENGTH: usize = 500;
+
+fn validate_settings(conn: &PgConnection, settings: &UserSettings) -> Result<(), &'static str> {
+  let category_ids: Vec<i32> = Category::list_all(conn)
+    .map_err(|_| "couldnt_find_category")?
+    .into_iter()
+    .map(|c| c.id)
+    .collect();
+  if settings
+    .collapsed_categories
+    .iter()
+    .any(|id| !category_ids.contains(id))
+  {
+    return Err("couldnt_find_category");
+  }
+
+  if settings.client_hints.len() > MAX_CLIENT_HINTS
+    || settings
+      .client_hints
+      .iter()
+      .any(|(k, v)| k.len() > MAX_CLIENT_HINT_LENGTH || v.len() > MAX_CLIENT_HINT_LENGTH)
+  {
+    return Err("invalid_client_hints");
+  }
+
+  Ok(())
+}
+
 impl Perform<LoginResponse> for Oper<SaveUserSettings> {
   fn perform(&self, conn: &PgConnection) -> Result<LoginResponse, Error> {
     let data: &SaveUserSettings = &self.data;
@@ -350,6 +714,27 @@ impl Perform<LoginResponse> for Oper<SaveUserSettings> {
 
     let user_id = claims.id;
 
+    if let Some(settings) = &data.settings {
+      if let Err(e) = validate_settings(&conn, settings) {
+        return Err(APIError::err(e).into());
+      }
+    }
+
+    if let Some(percent) = data.blended_discovery_percent {
+      if percent < 0 || percent > 100 {
+        return Err(APIError::err("invalid_blended_discovery_percent").into());
+      }
+    }
+
+    if let Some(language_ids) = &data.language_ids {
+      let mut language_ids = language_ids.to_owned();
+      language_ids.sort();
+      language_ids.dedup();
+      if Language::count_existing(&conn, &language_ids)? != language_ids.len() as i64 {
+        return Err(APIError::err("invalid_language").into());
+      }
+    }
+
     let read_user = User_::read(&conn, user_id)?;
 
     let email = match &data.email {
@@ -419,6 +804,53 @@ impl Perform<LoginResponse> for Oper<SaveUserSettings> {
         return Err(APIError::err(err_type).into());
       }
     };
+    evict_apub_object("u", &updated_user.name);
+
+    if let Some(settings) = &data.settings {
+      User_::update_settings(&conn, user_id, settings)?;
+    }
+
+    if let Some(hide_profile) = data.hide_profile {
+      User_::update_hide_profile(&conn, user_id, hide_profile)?;
+    }
+
+    if let Some(hide_activity) = data.hide_activity {
+      User_::update_hide_activity(&conn, user_id, hide_activity)?;
+    }
+
+    if data.blended_discovery_percent.is_some() || data.blended_discovery_local.is_some() {
+      User_::update_blended_discovery(
+        &conn,
+        user_id,
+        data
+          .blended_discovery_percent
+          .unwrap_or(updated_user.blended_discovery_percent),
+        data
+          .blended_discovery_local
+          .unwrap_or(updated_user.blended_discovery_local),
+      )?;
+    }
+
+    if let Some(show_scores) = data.show_scores {
+      User_::update_show_scores(&conn, user_id, show_scores)?;
+    }
+
+    if data.bot_account.is_some() || data.show_generated.is_some() {
+      User_::update_generated_settings(
+        &conn,
+        user_id,
+        data.bot_account.unwrap_or(updated_user.bot_account),
+        data.show_generated.unwrap_or(updated_user.show_generated),
+      )?;
+    }
+
+    if let Some(collapse_crossposts) = data.collapse_crossposts {
+      User_::update_collapse_crossposts(&conn, user_id, collapse_crossposts)?;
+    }
+
+    if let Some(language_ids) = &data.language_ids {
+      Language::replace_for_user(&conn, user_id, language_ids)?;
+    }
 
     // Return the jwt
     Ok(LoginResponse {
@@ -444,6 +876,8 @@ impl Perform<GetUserDetailsResponse> for Oper<GetUserDetails> {
       None => None,
     };
 
+    check_private_instance(user_id)?;
+
     let show_nsfw = match &user_claims {
       Some(claims) => claims.show_nsfw,
       None => false,
@@ -467,33 +901,78 @@ impl Perform<GetUserDetailsResponse> for Oper<GetUserDetails> {
       }
     };
 
+    // Hidden profiles are only for logged in users
+    let user_details = User_::read(&conn, user_details_id)?;
+    if user_details.hide_profile && user_id.is_none() {
+      return Err(APIError::err("profile_hidden").into());
+    }
+    // Remote users are fetched again once they're stale, and shown as they are if that fails
+    let user_details = refresh_remote_user(&conn, user_details);
+
     let mut user_view = UserView::read(&conn, user_details_id)?;
 
     let mut posts_query = PostQueryBuilder::create(&conn)
       .sort(&sort)
       .show_nsfw(show_nsfw)
       .saved_only(data.saved_only)
-      .for_community_id(data.community_id)
-      .my_user_id(user_id)
+      .for_community_id(data.community_id.map(CommunityId))
+      .my_user_id(user_id.map(UserId))
       .page(data.page)
       .limit(data.limit);
 
     let mut comments_query = CommentQueryBuilder::create(&conn)
       .sort(&sort)
       .saved_only(data.saved_only)
-      .my_user_id(user_id)
+      .my_user_id(user_id.map(UserId))
       .page(data.page)
       .limit(data.limit);
 
     // If its saved only, you don't care what creator it was
     // Or, if its not saved, then you only want it for that specific creator
     if !data.saved_only {
-      posts_query = posts_query.for_creator_id(user_details_id);
-      comments_query = comments_query.for_creator_id(user_details_id);
+      posts_query = posts_query.for_creator_id(UserId(user_details_id));
+      comments_query = comments_query.for_creator_id(UserId(user_details_id));
     }
 
-    let posts = posts_query.list()?;
-    let comments = comments_query.list()?;
+    let mut posts = posts_query.list()?;
+    let mut comments = comments_query.list()?;
+
+    // Pins go before the rest of the user's own posts and comments, on the first page
+    let pins = Featured::list_for_user(&conn, user_details_id)?;
+    if !data.saved_only && data.page.unwrap_or(1) <= 1 {
+      let pinned_post_ids = pins.iter().filter_map(|p| p.post_id).collect::<Vec<i32>>();
+      if !pinned_post_ids.is_empty() {
+        let mut pinned_posts = PostQueryBuilder::create(&conn)
+          .show_nsfw(show_nsfw)
+          .for_community_id(data.community_id.map(CommunityId))
+          .my_user_id(user_id.map(UserId))
+          .for_creator_id(UserId(user_details_id))
+          .for_post_ids(pinned_post_ids.to_owned())
+          .list()?;
+        pinned_posts.retain(|p| !p.removed && !p.deleted);
+        pinned_posts.sort_by_key(|p| pinned_post_ids.iter().position(|id| *id == p.id));
+        posts.retain(|p| !pinned_post_ids.contains(&p.id));
+        pinned_posts.append(&mut posts);
+        posts = pinned_posts;
+      }
+
+      let pinned_comment_ids = pins
+        .iter()
+        .filter_map(|p| p.comment_id)
+        .collect::<Vec<i32>>();
+      if !pinned_comment_ids.is_empty() {
+        let mut pinned_comments = CommentQueryBuilder::create(&conn)
+          .my_user_id(user_id.map(UserId))
+          .for_creator_id(UserId(user_details_id))
+          .for_comment_ids(pinned_comment_ids.to_owned())
+          .list()?;
+        pinned_comments.retain(|c| !c.removed && !c.deleted);
+        pinned_comments.sort_by_key(|c| pinned_comment_ids.iter().position(|id| *id == c.id));
+        comments.retain(|c| !pinned_comment_ids.contains(&c.id));
+        pinned_comments.append(&mut comments);
+        comments = pinned_comments;
+      }
+    }
 
     let follows = CommunityFollowerView::for_user(&conn, user_details_id)?;
     let moderates = CommunityModeratorView::for_user(&conn, user_details_id)?;
@@ -503,15 +982,67 @@ impl Perform<GetUserDetailsResponse> for Oper<GetUserDetails> {
     let creator_user = admins.remove(creator_index);
     admins.insert(0, creator_user);
 
-    // If its not the same user, remove the email
+    // If its not the same user, remove the email and settings
+    let mut settings = None;
+    let mut hide_profile = None;
+    let mut hide_activity = None;
+    let mut blended_discovery_percent = None;
+    let mut blended_discovery_local = None;
+    let mut show_scores = None;
+    let mut show_generated = None;
+    let mut collapse_crossposts = None;
+    let mut language_ids = None;
     if let Some(user_id) = user_id {
       if user_details_id != user_id {
         user_view.email = None;
+      } else {
+        settings = Some(user_details.settings());
+        hide_profile = Some(user_details.hide_profile);
+        hide_activity = Some(user_details.hide_activity);
+        blended_discovery_percent = Some(user_details.blended_discovery_percent);
+        blended_discovery_local = Some(user_details.blended_discovery_local);
+        show_scores = Some(user_details.show_scores);
+        show_generated = Some(user_details.show_generated);
+        collapse_crossposts = Some(user_details.collapse_crossposts);
+        language_ids = Some(Language::ids_for_user(&conn, user_id)?);
       }
     } else {
       user_view.email = None;
     }
 
+    let mod_notes = match user_id {
+      Some(user_id) if admins.iter().any(|a| a.id == user_id) => Some(ModNoteView::list(
+        &conn,
+        None,
+        Some(user_details_id),
+        false,
+      )?),
+      Some(user_id) => {
+        let moderated: Vec<i32> = CommunityModeratorView::for_user(&conn, user_id)?
+          .into_iter()
+          .map(|m| m.community_id)
+          .collect();
+        if moderated.is_empty() {
+          None
+        } else {
+          Some(ModNoteView::list(
+            &conn,
+            Some(moderated),
+            Some(user_details_id),
+            false,
+          )?)
+        }
+      }
+      None => None,
+    };
+
+    if Site::hides_scores_from(&conn, user_id)? {
+      user_view.hide_scores();
+      posts.hide_scores();
+      comments.hide_scores();
+      admins.hide_scores();
+    }
+
     // Return the jwt
     Ok(GetUserDetailsResponse {
       user: user_view,
@@ -520,10 +1051,54 @@ impl Perform<GetUserDetailsResponse> for Oper<GetUserDetails> {
       comments,
       posts,
       admins,
+      settings,
+      hide_profile,
+      hide_activity,
+      blended_discovery_percent,
+      blended_discovery_local,
+      show_scores,
+      bot_account: user_details.bot_account,
+      show_generated,
+      collapse_crossposts,
+      language_ids,
+      mod_notes,
+      pins,
     })
   }
 }
 
+impl Perform<GetUserActivityResponse> for Oper<GetUserActivity> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetUserActivityResponse, Error> {
+    let data: &GetUserActivity = &self.data;
+
+    let user_id = match &data.auth {
+      Some(auth) => match Claims::decode(&auth) {
+        Ok(claims) => Some(claims.claims.id),
+        Err(_e) => None,
+      },
+      None => None,
+    };
+
+    check_private_instance(user_id)?;
+
+    let user = match User_::read(&conn, data.user_id) {
+      Ok(user) => user,
+      Err(_e) => return Err(APIError::err("couldnt_find_that_username_or_email").into()),
+    };
+    if user.hide_profile && user_id.is_none() {
+      return Err(APIError::err("profile_hidden").into());
+    }
+    if user.hide_activity && user_id != Some(user.id) {
+      return Err(APIError::err("activity_hidden").into());
+    }
+
+    let since = (naive_now() - chrono::Duration::days(365)).date();
+    let days = UserActivityDay::for_user(&conn, user.id, since)?;
+
+    Ok(GetUserActivityResponse { days })
+  }
+}
+
 impl Perform<AddAdminResponse> for Oper<AddAdmin> {
   fn perform(&self, conn: &PgConnection) -> Result<AddAdminResponse, Error> {
     let data: &AddAdmin = &self.data;
@@ -604,6 +1179,9 @@ impl Perform<BanUserResponse> for Oper<BanUser> {
     }
 
     let read_user = User_::read(&conn, data.user_id)?;
+    let remote_scope =
+      remote_moderation_scope(&data.remote_scope, read_user.actor_id.is_some(), data.ban)?;
+    let apub_id = read_user.apub_id();
 
     // TODO make bans and addadmins easier
     let user_form = UserForm {
@@ -631,11 +1209,14 @@ impl Perform<BanUserResponse> for Oper<BanUser> {
       Err(_e) => return Err(APIError::err("couldnt_update_user").into()),
     };
 
-    // Mod tables
+    // Temporary bans are lifted once they expire
     let expires = match data.expires {
       Some(time) => Some(naive_from_unix(time)),
       None => None,
     };
+    User_::update_ban_expires(&conn, data.user_id, expires.filter(|_| data.ban))?;
+
+    // Mod tables
 
     let form = ModBanForm {
       mod_user_id: user_id,
@@ -643,9 +1224,31 @@ impl Perform<BanUserResponse> for Oper<BanUser> {
       reason: data.reason.to_owned(),
       banned: Some(data.ban),
       expires,
+      remote_scope: remote_scope.map(|scope| scope as i16),
     };
 
-    ModBan::create(&conn, &form)?;
+    let ban = ModBan::create(&conn, &form)?;
+
+    if remote_scope == Some(RemoteModerationScope::Flag) {
+      send_flag(
+        &User_::read(&conn, user_id)?,
+        &apub_id,
+        make_apub_endpoint("mod_ban", ban.id),
+        vec![apub_id.to_owned()],
+        data.reason.to_owned(),
+      );
+    }
+
+    if data.ban {
+      send_moderation_notice(
+        &conn,
+        data.user_id,
+        user_id,
+        ModerationNoticeKind::Banned,
+        None,
+        &data.reason,
+      );
+    }
 
     let user_view = UserView::read(&conn, data.user_id)?;
 
@@ -656,9 +1259,9 @@ impl Perform<BanUserResponse> for Oper<BanUser> {
   }
 }
 
-impl Perform<GetRepliesResponse> for Oper<GetReplies> {
-  fn perform(&self, conn: &PgConnection) -> Result<GetRepliesResponse, Error> {
-    let data: &GetReplies = &self.data;
+impl Perform<BlockUserResponse> for Oper<BlockUser> {
+  fn perform(&self, conn: &PgConnection) -> Result<BlockUserResponse, Error> {
+    let data: &BlockUser = &self.data;
 
     let claims = match Claims::decode(&data.auth) {
       Ok(claims) => claims.claims,
@@ -667,22 +1270,41 @@ impl Perform<GetRepliesResponse> for Oper<GetReplies> {
 
     let user_id = claims.id;
 
-    let sort = SortType::from_str(&data.sort)?;
+    if data.user_id == user_id {
+      return Err(APIError::err("cant_block_yourself").into());
+    }
 
-    let replies = ReplyQueryBuilder::create(&conn, user_id)
-      .sort(&sort)
-      .unread_only(data.unread_only)
-      .page(data.page)
-      .limit(data.limit)
-      .list()?;
+    let mut user_view = match UserView::read(&conn, data.user_id) {
+      Ok(user_view) => user_view,
+      Err(_e) => return Err(APIError::err("couldnt_find_user").into()),
+    };
 
-    Ok(GetRepliesResponse { replies })
+    let form = UserBlockForm {
+      user_id,
+      target_id: data.user_id,
+    };
+    if data.block {
+      if let Err(e) = UserBlock::block(&conn, &form) {
+        return Err(APIError::from_db("couldnt_block_user", e));
+      }
+    } else {
+      UserBlock::unblock(&conn, &form)?;
+    }
+
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      user_view.hide_scores();
+    }
+
+    Ok(BlockUserResponse {
+      user: user_view,
+      blocked: data.block,
+    })
   }
 }
 
-impl Perform<GetUserMentionsResponse> for Oper<GetUserMentions> {
-  fn perform(&self, conn: &PgConnection) -> Result<GetUserMentionsResponse, Error> {
-    let data: &GetUserMentions = &self.data;
+impl Perform<GetBlockedUsersResponse> for Oper<GetBlockedUsers> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetBlockedUsersResponse, Error> {
+    let data: &GetBlockedUsers = &self.data;
 
     let claims = match Claims::decode(&data.auth) {
       Ok(claims) => claims.claims,
@@ -691,19 +1313,659 @@ impl Perform<GetUserMentionsResponse> for Oper<GetUserMentions> {
 
     let user_id = claims.id;
 
-    let sort = SortType::from_str(&data.sort)?;
+    let mut users = UserView::blocked_by(&conn, user_id)?;
 
-    let mentions = UserMentionQueryBuilder::create(&conn, user_id)
-      .sort(&sort)
-      .unread_only(data.unread_only)
-      .page(data.page)
-      .limit(data.limit)
-      .list()?;
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      users.hide_scores();
+    }
+
+    Ok(GetBlockedUsersResponse { users })
+  }
+}
+
+impl Perform<ProfilePinsResponse> for Oper<PinToProfile> {
+  fn perform(&self, conn: &PgConnection) -> Result<ProfilePinsResponse, Error> {
+    let data: &PinToProfile = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    // Only your own, and only what others can still see
+    let (creator_id, gone) = match (data.post_id, data.comment_id) {
+      (Some(post_id), None) => match Post::read(&conn, post_id) {
+        Ok(post) => (post.creator_id, post.removed || post.deleted),
+        Err(_e) => return Err(APIError::err("couldnt_find_post").into()),
+      },
+      (None, Some(comment_id)) => match Comment::read(&conn, comment_id) {
+        Ok(comment) => (comment.creator_id, comment.removed || comment.deleted),
+        Err(_e) => return Err(APIError::err("couldnt_find_comment").into()),
+      },
+      _ => return Err(APIError::err("invalid_pin").into()),
+    };
+    if creator_id != user_id {
+      return Err(APIError::err("cant_pin_others_content").into());
+    }
+
+    let pins = Featured::list_for_user(&conn, user_id)?;
+    let existing = pins
+      .iter()
+      .find(|p| p.post_id == data.post_id && p.comment_id == data.comment_id);
+
+    match (data.pin, existing) {
+      (true, None) => {
+        if gone {
+          return Err(APIError::err("cant_pin_removed_content").into());
+        }
+        if pins.len() >= MAX_PROFILE_PINS {
+          return Err(APIError::err("too_many_pins").into());
+        }
+        let form = FeaturedForm {
+          user_id,
+          post_id: data.post_id,
+          comment_id: data.comment_id,
+          position: pins.last().map_or(0, |p| p.position + 1),
+        };
+        if let Err(e) = Featured::pin(&conn, &form) {
+          return Err(APIError::from_db("couldnt_pin", e));
+        }
+      }
+      (false, Some(pin)) => {
+        Featured::unpin(&conn, pin.id)?;
+      }
+      _ => (),
+    }
+
+    let pins = Featured::list_for_user(&conn, user_id)?;
+
+    Ok(ProfilePinsResponse { pins })
+  }
+}
+
+impl Perform<ProfilePinsResponse> for Oper<ReorderProfilePins> {
+  fn perform(&self, conn: &PgConnection) -> Result<ProfilePinsResponse, Error> {
+    let data: &ReorderProfilePins = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    // Every pin, once
+    let mut pin_ids = Featured::list_for_user(&conn, user_id)?
+      .iter()
+      .map(|p| p.id)
+      .collect::<Vec<i32>>();
+    let mut new_order = data.pin_ids.to_owned();
+    pin_ids.sort();
+    new_order.sort();
+    if pin_ids != new_order {
+      return Err(APIError::err("invalid_pin_order").into());
+    }
+
+    let pins = Featured::reorder(&conn, user_id, &data.pin_ids)?;
+
+    Ok(ProfilePinsResponse { pins })
+  }
+}
+
+impl Perform<BlockInstanceResponse> for Oper<BlockInstance> {
+  fn perform(&self, conn: &PgConnection) -> Result<BlockInstanceResponse, Error> {
+    let data: &BlockInstance = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    let domain = match parse_instance_domain(&data.domain) {
+      Some(domain) => domain,
+      None => return Err(APIError::err("invalid_instance_domain").into()),
+    };
+    let settings = Settings::get();
+    if domain == settings.hostname || domain == settings.actor_domain() {
+      return Err(APIError::err("cant_block_local_instance").into());
+    }
+
+    let form = UserInstanceBlockForm {
+      user_id,
+      domain: domain.to_owned(),
+    };
+    if data.block {
+      if let Err(e) = UserInstanceBlock::block(&conn, &form) {
+        return Err(APIError::from_db("couldnt_block_instance", e));
+      }
+    } else {
+      UserInstanceBlock::unblock(&conn, &form)?;
+    }
+
+    Ok(BlockInstanceResponse {
+      domain,
+      blocked: data.block,
+    })
+  }
+}
+
+impl Perform<GetBlockedInstancesResponse> for Oper<GetBlockedInstances> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetBlockedInstancesResponse, Error> {
+    let data: &GetBlockedInstances = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let instances = UserInstanceBlock::list_for_user(&conn, claims.id)?;
+
+    Ok(GetBlockedInstancesResponse { instances })
+  }
+}
+
+/// The lowercased domain of an instance, from the domain or an activitypub id from it.
+fn parse_instance_domain(q: &str) -> Option<String> {
+  let q = q.trim();
+  if q.starts_with("https://") {
+    return url_host(q);
+  }
+  let domain = q.to_lowercase();
+  let valid = domain.contains('.')
+    && !domain.starts_with('.')
+    && domain
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+  if valid {
+    Some(domain)
+  } else {
+    None
+  }
+}
+
+impl Perform<GetMyContentResponse> for Oper<GetMyContent> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetMyContentResponse, Error> {
+    let data: &GetMyContent = &self.data;
+
+    let comments = data.comments(conn, data.page, data.limit)?;
+
+    Ok(GetMyContentResponse { comments })
+  }
+}
+
+impl GetMyContent {
+  /// The format of an export, checking that the request can make one.
+  pub fn export_format(&self) -> Result<ExportFormat, Error> {
+    if Claims::decode(&self.auth).is_err() {
+      return Err(APIError::err("not_logged_in").into());
+    }
+    ExportFormat::from_name(self.export.as_deref())
+  }
+
+  /// A page of an export, which starts the file on the first page and ends it on the one after
+  /// the last comment. Returns whether it was the end.
+  pub fn export_page(
+    &self,
+    conn: &PgConnection,
+    format: ExportFormat,
+    page: i64,
+  ) -> Result<(Vec<u8>, bool), Error> {
+    let comments = self.comments(conn, Some(page), Some(EXPORT_PAGE_SIZE))?;
+    let out = export_chunk(&comments, format, page, EXPORT_CSV_HEADER, csv_row)?;
+    Ok((out, comments.is_empty()))
+  }
+
+  fn comments(
+    &self,
+    conn: &PgConnection,
+    page: Option<i64>,
+    limit: Option<i64>,
+  ) -> Result<Vec<CommentWithPostView>, Error> {
+    let claims = match Claims::decode(&self.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    let comments = CommentQueryBuilder::create(&conn)
+      .sort(&SortType::New)
+      .for_creator_id(UserId(user_id))
+      .for_community_id(self.community_id.map(CommunityId))
+      .published_after(self.published_after)
+      .published_before(self.published_before)
+      .removed_only(self.removed_only.unwrap_or(false))
+      .page(page)
+      .limit(limit)
+      .list()?;
+
+    let mut comments = CommentWithPostView::from_comments(&conn, comments)?;
+
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      comments.hide_scores();
+    }
+
+    Ok(comments)
+  }
+}
+
+/// A page of items of an export. The first page starts the file, and the empty page after the
+/// last item ends it.
+pub fn export_chunk<T: Serialize>(
+  items: &[T],
+  format: ExportFormat,
+  page: i64,
+  csv_header: &str,
+  csv_row: fn(&T) -> String,
+) -> Result<Vec<u8>, Error> {
+  let mut out = Vec::new();
+  match format {
+    ExportFormat::Csv => {
+      if page == 1 {
+        out.extend_from_slice(csv_header.as_bytes());
+      }
+      for item in items {
+        out.extend_from_slice(csv_row(item).as_bytes());
+      }
+    }
+    ExportFormat::Json => {
+      if page == 1 {
+        out.push(b'[');
+      }
+      for (i, item) in items.iter().enumerate() {
+        if page > 1 || i > 0 {
+          out.push(b',');
+        }
+        serde_json::to_writer(&mut out, item)?;
+      }
+      if items.is_empty() {
+        out.push(b']');
+      }
+    }
+    ExportFormat::JsonLines => {
+      for item in items {
+        serde_json::to_writer(&mut out, item)?;
+        out.push(b'\n');
+      }
+    }
+  }
+  Ok(out)
+}
+
+/// A comment as a line of a csv export.
+fn csv_row(c: &CommentWithPostView) -> String {
+  csv_line(&[
+    c.comment.id.to_string(),
+    c.comment.published.to_string(),
+    c.comment.updated.map(|u| u.to_string()).unwrap_or_default(),
+    c.comment.community_name.to_owned(),
+    c.comment.post_id.to_string(),
+    c.post_name.to_owned(),
+    c.post_url.to_owned().unwrap_or_default(),
+    c.comment
+      .parent_id
+      .map(|p| p.to_string())
+      .unwrap_or_default(),
+    c.comment.content.to_owned(),
+    c.comment.score.map(|s| s.to_string()).unwrap_or_default(),
+    c.comment.removed.to_string(),
+    c.comment.deleted.to_string(),
+  ])
+}
+
+/// Fields as a line of a csv file.
+pub fn csv_line(fields: &[String]) -> String {
+  let mut line = fields
+    .iter()
+    .map(|field| csv_field(field))
+    .collect::<Vec<String>>()
+    .join(",");
+  line.push('\n');
+  line
+}
+
+/// Quotes a csv field if it needs it.
+fn csv_field(field: &str) -> String {
+  if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+    format!("\"{}\"", field.replace('"', "\"\""))
+  } else {
+    field.to_owned()
+  }
+}
+
+impl Perform<GetRepliesResponse> for Oper<GetReplies> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetRepliesResponse, Error> {
+    let data: &GetReplies = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    let sort = SortType::from_str(&data.sort)?;
+
+    let mut replies = ReplyQueryBuilder::create(&conn, user_id)
+      .sort(&sort)
+      .unread_only(data.unread_only)
+      .page(data.page)
+      .limit(data.limit)
+      .list()?;
+
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      replies.hide_scores();
+    }
+
+    Ok(GetRepliesResponse { replies })
+  }
+}
+
+impl Perform<GetInboxResponse> for Oper<GetInbox> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetInboxResponse, Error> {
+    let data: &GetInbox = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    let inbox = InboxQueryBuilder::create(&conn, user_id)
+      .unread_only(data.unread_only)
+      .page(data.page)
+      .limit(data.limit)
+      .list()?;
+
+    Ok(GetInboxResponse { inbox })
+  }
+}
+
+impl Perform<GetUnreadCountResponse> for Oper<GetUnreadCount> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetUnreadCountResponse, Error> {
+    let data: &GetUnreadCount = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let counts = UserUnreadCountsView::read(&conn, claims.id)?;
+
+    Ok(GetUnreadCountResponse {
+      replies: counts.unread_replies,
+      mentions: counts.unread_mentions,
+      private_messages: counts.unread_private_messages,
+    })
+  }
+}
+
+/// Errors with `invalid_folder_name` unless it has 1 to 50 characters.
+fn folder_name(name: &str) -> Result<String, Error> {
+  match non_empty(&Some(name.to_owned())) {
+    Some(name) if name.chars().count() <= 50 => Ok(name),
+    _ => Err(APIError::err("invalid_folder_name").into()),
+  }
+}
+
+/// Creating or renaming a folder, which errors with `folder_already_exists` when the user already
+/// has one by that name.
+fn save_folder(
+  conn: &PgConnection,
+  folder_id: Option<i32>,
+  form: &SavedFolderForm,
+) -> Result<GetSavedFoldersResponse, Error> {
+  let saved = match folder_id {
+    Some(folder_id) => SavedFolder::update(&conn, folder_id, form),
+    None => SavedFolder::create(&conn, form),
+  };
+
+  if let Err(e) = saved {
+    let err_type = if e.to_string().contains("saved_folder_user_id_name_key") {
+      "folder_already_exists"
+    } else {
+      "couldnt_save_folder"
+    };
+    return Err(APIError::from_db(err_type, e));
+  }
+
+  let folders = SavedFolder::list_for_user(&conn, form.user_id)?;
+
+  Ok(GetSavedFoldersResponse { folders })
+}
+
+impl Perform<GetSavedFoldersResponse> for Oper<CreateSavedFolder> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetSavedFoldersResponse, Error> {
+    let data: &CreateSavedFolder = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let form = SavedFolderForm {
+      user_id: claims.id,
+      name: folder_name(&data.name)?,
+    };
+
+    save_folder(&conn, None, &form)
+  }
+}
+
+impl Perform<GetSavedFoldersResponse> for Oper<EditSavedFolder> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetSavedFoldersResponse, Error> {
+    let data: &EditSavedFolder = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    check_saved_folder(&conn, Some(data.folder_id), user_id)?;
+
+    let form = SavedFolderForm {
+      user_id,
+      name: folder_name(&data.name)?,
+    };
+
+    save_folder(&conn, Some(data.folder_id), &form)
+  }
+}
+
+impl Perform<GetSavedFoldersResponse> for Oper<DeleteSavedFolder> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetSavedFoldersResponse, Error> {
+    let data: &DeleteSavedFolder = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    check_saved_folder(&conn, Some(data.folder_id), user_id)?;
+
+    SavedFolder::delete(&conn, data.folder_id)?;
+
+    let folders = SavedFolder::list_for_user(&conn, user_id)?;
+
+    Ok(GetSavedFoldersResponse { folders })
+  }
+}
+
+impl Perform<GetSavedFoldersResponse> for Oper<GetSavedFolders> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetSavedFoldersResponse, Error> {
+    let data: &GetSavedFolders = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let folders = SavedFolder::list_for_user(&conn, claims.id)?;
+
+    Ok(GetSavedFoldersResponse { folders })
+  }
+}
+
+impl Perform<GetSavedItemsResponse> for Oper<GetSavedItems> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetSavedItemsResponse, Error> {
+    let data: &GetSavedItems = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    check_saved_folder(&conn, data.folder_id, user_id)?;
+
+    let items = SavedItemsQueryBuilder::create(&conn, user_id)
+      .folder_id(data.folder_id)
+      .page(data.page)
+      .limit(data.limit)
+      .list()?;
+
+    Ok(GetSavedItemsResponse { items })
+  }
+}
+
+impl Perform<GetKeywordFiltersResponse> for Oper<CreateKeywordFilter> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetKeywordFiltersResponse, Error> {
+    let data: &CreateKeywordFilter = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    let keyword = match non_empty(&Some(data.keyword.to_owned())) {
+      Some(keyword) if keyword.chars().count() <= 100 => keyword,
+      _ => return Err(APIError::err("invalid_keyword").into()),
+    };
+
+    let regex = data.regex.unwrap_or(false);
+    if regex
+      && !(UserKeywordFilter::is_simple_regex(&keyword)
+        && UserKeywordFilter::is_valid_regex(&conn, &keyword))
+    {
+      return Err(APIError::err("invalid_keyword_regex").into());
+    }
+
+    if UserKeywordFilter::list_for_user(&conn, user_id)?.len() >= MAX_KEYWORD_FILTERS {
+      return Err(APIError::err("too_many_keyword_filters").into());
+    }
+
+    let form = UserKeywordFilterForm {
+      user_id,
+      keyword,
+      regex,
+    };
+
+    if let Err(e) = UserKeywordFilter::create(&conn, &form) {
+      return Err(APIError::from_db("keyword_already_muted", e));
+    }
+
+    let keyword_filters = UserKeywordFilter::list_for_user(&conn, user_id)?;
+
+    Ok(GetKeywordFiltersResponse { keyword_filters })
+  }
+}
+
+impl Perform<GetKeywordFiltersResponse> for Oper<DeleteKeywordFilter> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetKeywordFiltersResponse, Error> {
+    let data: &DeleteKeywordFilter = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    match UserKeywordFilter::read(&conn, data.keyword_filter_id) {
+      Ok(filter) if filter.user_id == user_id => {}
+      _ => return Err(APIError::err("couldnt_find_keyword_filter").into()),
+    }
+
+    UserKeywordFilter::delete(&conn, data.keyword_filter_id)?;
+
+    let keyword_filters = UserKeywordFilter::list_for_user(&conn, user_id)?;
+
+    Ok(GetKeywordFiltersResponse { keyword_filters })
+  }
+}
+
+impl Perform<GetKeywordFiltersResponse> for Oper<GetKeywordFilters> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetKeywordFiltersResponse, Error> {
+    let data: &GetKeywordFilters = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let keyword_filters = UserKeywordFilter::list_for_user(&conn, claims.id)?;
+
+    Ok(GetKeywordFiltersResponse { keyword_filters })
+  }
+}
+
+impl Perform<GetUserMentionsResponse> for Oper<GetUserMentions> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetUserMentionsResponse, Error> {
+    let data: &GetUserMentions = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    let sort = SortType::from_str(&data.sort)?;
+
+    let mut mentions = UserMentionQueryBuilder::create(&conn, user_id)
+      .sort(&sort)
+      .unread_only(data.unread_only)
+      .page(data.page)
+      .limit(data.limit)
+      .list()?;
+
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      mentions.hide_scores();
+    }
 
     Ok(GetUserMentionsResponse { mentions })
   }
 }
 
+impl Perform<GetModerationNoticesResponse> for Oper<GetModerationNotices> {
+  fn perform(&self, conn: &PgConnection) -> Result<GetModerationNoticesResponse, Error> {
+    let data: &GetModerationNotices = &self.data;
+
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
+
+    let user_id = claims.id;
+
+    let notices = ModerationNotice::list(&conn, user_id, data.unread_only, data.page, data.limit)?;
+
+    Ok(GetModerationNoticesResponse { notices })
+  }
+}
+
 impl Perform<UserMentionResponse> for Oper<EditUserMention> {
   fn perform(&self, conn: &PgConnection) -> Result<UserMentionResponse, Error> {
     let data: &EditUserMention = &self.data;
@@ -729,7 +1991,11 @@ impl Perform<UserMentionResponse> for Oper<EditUserMention> {
         Err(_e) => return Err(APIError::err("couldnt_update_comment").into()),
       };
 
-    let user_mention_view = UserMentionView::read(&conn, user_mention.id, user_id)?;
+    let mut user_mention_view = UserMentionView::read(&conn, user_mention.id, user_id)?;
+
+    if Site::hides_scores_from(&conn, Some(user_id))? {
+      user_mention_view.hide_scores();
+    }
 
     Ok(UserMentionResponse {
       mention: user_mention_view,
@@ -748,76 +2014,73 @@ impl Perform<GetRepliesResponse> for Oper<MarkAllAsRead> {
 
     let user_id = claims.id;
 
-    let replies = ReplyQueryBuilder::create(&conn, user_id)
-      .unread_only(true)
-      .page(1)
-      .limit(999)
-      .list()?;
+    transaction_with_retry(conn, || -> Result<(), Error> {
+      Comment::update_replies_read(&conn, user_id, None, true)
+        .map_err(|e| APIError::from_db("couldnt_update_comment", e))?;
+      UserMention::update_read(&conn, user_id, None, true)
+        .map_err(|e| APIError::from_db("couldnt_update_comment", e))?;
+      PrivateMessage::update_read(&conn, user_id, None, true)
+        .map_err(|e| APIError::from_db("couldnt_update_private_message", e))?;
+      ModerationNotice::mark_all_as_read(&conn, user_id)?;
+      Ok(())
+    })?;
 
-    for reply in &replies {
-      let comment_form = CommentForm {
-        content: reply.to_owned().content,
-        parent_id: reply.to_owned().parent_id,
-        post_id: reply.to_owned().post_id,
-        creator_id: reply.to_owned().creator_id,
-        removed: None,
-        deleted: None,
-        read: Some(true),
-        updated: reply.to_owned().updated,
-      };
+    Ok(GetRepliesResponse { replies: vec![] })
+  }
+}
 
-      let _updated_comment = match Comment::update(&conn, reply.id, &comment_form) {
-        Ok(comment) => comment,
-        Err(_e) => return Err(APIError::err("couldnt_update_comment").into()),
-      };
-    }
+impl Perform<MarkAsReadResponse> for Oper<MarkAsRead> {
+  fn perform(&self, conn: &PgConnection) -> Result<MarkAsReadResponse, Error> {
+    let data: &MarkAsRead = &self.data;
 
-    // Mentions
-    let mentions = UserMentionQueryBuilder::create(&conn, user_id)
-      .unread_only(true)
-      .page(1)
-      .limit(999)
-      .list()?;
+    let claims = match Claims::decode(&data.auth) {
+      Ok(claims) => claims.claims,
+      Err(_e) => return Err(APIError::err("not_logged_in").into()),
+    };
 
-    for mention in &mentions {
-      let mention_form = UserMentionForm {
-        recipient_id: mention.to_owned().recipient_id,
-        comment_id: mention.to_owned().id,
-        read: Some(true),
-      };
+    let user_id = claims.id;
 
-      let _updated_mention =
-        match UserMention::update(&conn, mention.user_mention_id, &mention_form) {
-          Ok(mention) => mention,
-          Err(_e) => return Err(APIError::err("couldnt_update_comment").into()),
-        };
+    let ids = [
+      &data.reply_ids,
+      &data.user_mention_ids,
+      &data.private_message_ids,
+    ];
+    if ids
+      .iter()
+      .any(|ids| ids.as_ref().map_or(0, Vec::len) > MAX_MARK_AS_READ_IDS)
+    {
+      return Err(APIError::err("too_many_ids").into());
     }
 
-    // messages
-    let messages = PrivateMessageQueryBuilder::create(&conn, user_id)
-      .page(1)
-      .limit(999)
-      .unread_only(true)
-      .list()?;
-
-    for message in &messages {
-      let private_message_form = PrivateMessageForm {
-        content: None,
-        creator_id: message.to_owned().creator_id,
-        recipient_id: message.to_owned().recipient_id,
-        deleted: None,
-        read: Some(true),
-        updated: None,
+    transaction_with_retry(conn, || -> Result<MarkAsReadResponse, Error> {
+      let replies = match &data.reply_ids {
+        Some(reply_ids) => {
+          Comment::update_replies_read(&conn, user_id, Some(&reply_ids[..]), data.read)
+            .map_err(|e| APIError::from_db("couldnt_update_comment", e))?
+        }
+        None => 0,
       };
-
-      let _updated_message = match PrivateMessage::update(&conn, message.id, &private_message_form)
-      {
-        Ok(message) => message,
-        Err(_e) => return Err(APIError::err("couldnt_update_private_message").into()),
+      let user_mentions = match &data.user_mention_ids {
+        Some(user_mention_ids) => {
+          UserMention::update_read(&conn, user_id, Some(&user_mention_ids[..]), data.read)
+            .map_err(|e| APIError::from_db("couldnt_update_comment", e))?
+        }
+        None => 0,
+      };
+      let private_messages = match &data.private_message_ids {
+        Some(private_message_ids) => {
+          PrivateMessage::update_read(&conn, user_id, Some(&private_message_ids[..]), data.read)
+            .map_err(|e| APIError::from_db("couldnt_update_private_message", e))?
+        }
+        None => 0,
       };
-    }
 
-    Ok(GetRepliesResponse { replies: vec![] })
+      Ok(MarkAsReadResponse {
+        replies,
+        user_mentions,
+        private_messages,
+      })
+    })
   }
 }
 
@@ -842,12 +2105,12 @@ impl Perform<LoginResponse> for Oper<DeleteAccount> {
 
     // Comments
     let comments = CommentQueryBuilder::create(&conn)
-      .for_creator_id(user_id)
+      .for_creator_id(UserId(user_id))
       .limit(std::i64::MAX)
       .list()?;
 
     for comment in &comments {
-      let comment_form = CommentForm {
+      let mut comment_form = CommentForm {
         content: "*Permananently Deleted*".to_string(),
         parent_id: comment.to_owned().parent_id,
         post_id: comment.to_owned().post_id,
@@ -856,23 +2119,36 @@ impl Perform<LoginResponse> for Oper<DeleteAccount> {
         deleted: Some(true),
         read: None,
         updated: Some(naive_now()),
+        generated: None,
+        language_id: None,
       };
 
+      // Content on legal hold is only marked as deleted, so that it can still be produced
+      if user.legal_hold {
+        comment_form.content = comment.content.to_owned();
+      }
+
       let _updated_comment = match Comment::update(&conn, comment.id, &comment_form) {
         Ok(comment) => comment,
         Err(_e) => return Err(APIError::err("couldnt_update_comment").into()),
       };
+      evict_apub_object("comment", comment.id);
+
+      // The earlier versions would still have what was deleted
+      if !user.legal_hold {
+        CommentEdit::delete_for_comment(&conn, comment.id)?;
+      }
     }
 
     // Posts
     let posts = PostQueryBuilder::create(&conn)
       .sort(&SortType::New)
-      .for_creator_id(user_id)
+      .for_creator_id(UserId(user_id))
       .limit(std::i64::MAX)
       .list()?;
 
     for post in &posts {
-      let post_form = PostForm {
+      let mut post_form = PostForm {
         name: "*Permananently Deleted*".to_string(),
         url: Some("https://deleted.com".to_string()),
         body: Some("*Permananently Deleted*".to_string()),
@@ -888,12 +2164,36 @@ impl Perform<LoginResponse> for Oper<DeleteAccount> {
         embed_description: None,
         embed_html: None,
         thumbnail_url: None,
+        alt_text: None,
+        visibility: None,
+        generated: None,
+        language_id: None,
+        scheduled_publish_time: None,
       };
 
+      let held = user.legal_hold || Post::read(&conn, post.id)?.legal_hold;
+      if held {
+        post_form.name = post.name.to_owned();
+        post_form.url = post.url.to_owned();
+        post_form.body = post.body.to_owned();
+        post_form.embed_title = post.embed_title.to_owned();
+        post_form.embed_description = post.embed_description.to_owned();
+        post_form.embed_html = post.embed_html.to_owned();
+        post_form.thumbnail_url = post.thumbnail_url.to_owned();
+        post_form.alt_text = post.alt_text.to_owned();
+      }
+
       let _updated_post = match Post::update(&conn, post.id, &post_form) {
         Ok(post) => post,
         Err(_e) => return Err(APIError::err("couldnt_update_post").into()),
       };
+      evict_apub_object("post", post.id);
+
+      // The old slugs are made from the title, and the earlier versions have it too
+      if !held {
+        Post::forget_slug_history(&conn, post.id)?;
+        PostEdit::delete_for_post(&conn, post.id)?;
+      }
     }
 
     Ok(LoginResponse {
@@ -1109,3 +2409,53 @@ impl Perform<UserJoinResponse> for Oper<UserJoin> {
     Ok(UserJoinResponse { user_id })
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{csv_field, export_chunk, parse_instance_domain, ExportFormat};
+
+  #[test]
+  fn test_parse_instance_domain() {
+    assert_eq!(
+      Some("remote.example".to_string()),
+      parse_instance_domain(" Remote.Example ")
+    );
+    assert_eq!(
+      Some("remote.example".to_string()),
+      parse_instance_domain("https://remote.example/u/thom")
+    );
+    assert_eq!(None, parse_instance_domain("remote"));
+    assert_eq!(None, parse_instance_domain("remote.example/u/thom"));
+    assert_eq!(None, parse_instance_domain("thom@remote.example"));
+  }
+
+  #[test]
+  fn test_csv_field() {
+    assert_eq!("plain", csv_field("plain"));
+    assert_eq!("\"a, b\"", csv_field("a, b"));
+    assert_eq!("\"say \"\"hi\"\"\"", csv_field("say \"hi\""));
+    assert_eq!("\"two\nlines\"", csv_field("two\nlines"));
+  }
+
+  #[test]
+  fn test_export_chunk() {
+    fn row(n: &i32) -> String {
+      format!("{}\n", n)
+    }
+    let chunk = |items: &[i32], format, page| {
+      String::from_utf8(export_chunk(items, format, page, "n\n", row).unwrap()).unwrap()
+    };
+
+    assert_eq!("n\n1\n2\n", chunk(&[1, 2], ExportFormat::Csv, 1));
+    assert_eq!("3\n", chunk(&[3], ExportFormat::Csv, 2));
+
+    // The pages of a json export add up to one array
+    let json = chunk(&[1, 2], ExportFormat::Json, 1)
+      + &chunk(&[3], ExportFormat::Json, 2)
+      + &chunk(&[], ExportFormat::Json, 3);
+    assert_eq!("[1,2,3]", json);
+
+    assert_eq!("1\n2\n", chunk(&[1, 2], ExportFormat::JsonLines, 1));
+    assert_eq!("", chunk(&[], ExportFormat::JsonLines, 2));
+  }
+}
@@ -0,0 +1,238 @@
+use crate::api::{etag_matches, weak_etag};
+use crate::Settings;
+use actix_web::body::Body;
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A serialized activitypub object, as it was last sent out.
+#[derive(Clone)]
+pub struct CachedObject {
+  pub body: String,
+  pub etag: String,
+  fetched: Instant,
+}
+
+/// A small least-recently-used cache of serialized actors and objects, keyed by hostname and
+/// request path.
+///
+/// Remote instances all fetch the same few actors when a post gets popular, so this keeps those
+/// fetches from turning into one database read each.
+pub struct ApubCache {
+  capacity: usize,
+  max_age: Duration,
+  entries: BTreeMap<String, (CachedObject, u64)>,
+  /// The keys by when they were last used, least recently used first
+  recency: BTreeMap<u64, String>,
+  clock: u64,
+}
+
+impl ApubCache {
+  pub fn new(capacity: usize, max_age: Duration) -> Self {
+    ApubCache {
+      capacity,
+      max_age,
+      entries: BTreeMap::new(),
+      recency: BTreeMap::new(),
+      clock: 0,
+    }
+  }
+
+  /// Returns the object if it was cached less than `max_age` ago.
+  pub fn get(&mut self, key: &str) -> Option<CachedObject> {
+    let clock = self.clock + 1;
+    let (object, last_used) = self.entries.get_mut(key)?;
+    if object.fetched.elapsed() >= self.max_age {
+      self.remove(key);
+      return None;
+    }
+    let object = object.to_owned();
+    let previous = std::mem::replace(last_used, clock);
+    self.clock = clock;
+    self.recency.remove(&previous);
+    self.recency.insert(clock, key.to_owned());
+    Some(object)
+  }
+
+  pub fn insert(&mut self, key: &str, body: String) -> CachedObject {
+    let object = CachedObject {
+      etag: weak_etag(&body),
+      body,
+      fetched: Instant::now(),
+    };
+    if self.capacity == 0 {
+      return object;
+    }
+
+    if !self.remove(key) && self.entries.len() >= self.capacity {
+      let least_recent = self.recency.values().next().cloned();
+      if let Some(k) = least_recent {
+        self.remove(&k);
+      }
+    }
+
+    self.clock += 1;
+    self.recency.insert(self.clock, key.to_owned());
+    self
+      .entries
+      .insert(key.to_owned(), (object.to_owned(), self.clock));
+    object
+  }
+
+  /// Removes an entry, and whether there was one.
+  pub fn remove(&mut self, key: &str) -> bool {
+    match self.entries.remove(key) {
+      Some((_, last_used)) => {
+        self.recency.remove(&last_used);
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Removes an entry along with the ones under it, like a post's along with its `/likes`.
+  pub fn remove_with_children(&mut self, key: &str) {
+    let children = format!("{}/", key);
+    let keys = self
+      .entries
+      .range(key.to_owned()..)
+      .map(|(k, _)| k)
+      .take_while(|k| k.starts_with(key))
+      .filter(|k| *k == key || k.starts_with(&children))
+      .cloned()
+      .collect::<Vec<String>>();
+    for k in keys {
+      self.remove(&k);
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+}
+
+lazy_static! {
+  static ref APUB_CACHE: Mutex<ApubCache> = {
+    let federation = Settings::get().federation;
+    Mutex::new(ApubCache::new(
+      federation.apub_cache_size,
+      Duration::from_secs(federation.apub_cache_max_age),
+    ))
+  };
+}
+
+/// Keyed by the configured hostname too, since one cache can be serving several instances' routes.
+/// The `Host` header is left out of it, so requests can't fill the cache with copies of an object.
+fn cache_key(path: &str) -> String {
+  format!("{}{}", Settings::get().hostname, path)
+}
+
+/// Drops what's cached of a local object and its collections, like a post's `/likes`, so the next
+/// fetch reads it again. Called when it's edited, deleted or removed. `point` and `value` are the
+/// ones of its `make_apub_endpoint`.
+pub fn evict_apub_object<T: Display>(point: &str, value: T) {
+  let key = cache_key(&format!("/federation/{}/{}", point, value));
+  APUB_CACHE.lock().unwrap().remove_with_children(&key);
+}
+
+/// Serves an activitypub object from the cache, only calling `fetch` (which does the database
+/// read and serialization) on a miss. `fetch` returning `None` means a 404, which isn't cached.
+///
+/// The response carries `Cache-Control` and `ETag` headers, and is a `304 Not Modified` if the
+/// remote instance already has this version.
+///
+/// A private instance only serves signed fetches, and since signatures aren't verified yet, that
+/// means none at all.
+pub fn cached_apub_response<F>(req: &HttpRequest, fetch: F) -> HttpResponse<Body>
+where
+  F: FnOnce() -> Option<String>,
+{
+  if Settings::get().private_instance {
+    return HttpResponse::Unauthorized().finish();
+  }
+
+  let key = cache_key(req.path());
+  let cached = APUB_CACHE.lock().unwrap().get(&key);
+  let object = match cached {
+    Some(object) => object,
+    None => match fetch() {
+      Some(body) => APUB_CACHE.lock().unwrap().insert(&key, body),
+      None => return HttpResponse::NotFound().finish(),
+    },
+  };
+
+  let cache_control = format!(
+    "public, max-age={}",
+    Settings::get().federation.apub_cache_max_age
+  );
+
+  let if_none_match = req
+    .headers()
+    .get(header::IF_NONE_MATCH)
+    .and_then(|h| h.to_str().ok());
+
+  match if_none_match {
+    Some(tags) if etag_matches(tags, &object.etag) => HttpResponse::NotModified()
+      .header(header::ETAG, object.etag)
+      .header(header::CACHE_CONTROL, cache_control)
+      .finish(),
+    _ => HttpResponse::Ok()
+      .header(header::ETAG, object.etag)
+      .header(header::CACHE_CONTROL, cache_control)
+      .content_type("application/activity+json")
+      .body(object.body),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::ApubCache;
+  use std::time::Duration;
+
+  #[test]
+  fn test_apub_cache() {
+    let mut cache = ApubCache::new(2, Duration::from_secs(60));
+    cache.insert("/federation/c/main", "main".into());
+    cache.insert("/federation/u/thom", "thom".into());
+
+    // Touch the community, so the user is the least recently used
+    assert_eq!("main", cache.get("/federation/c/main").unwrap().body);
+
+    cache.insert("/federation/post/1", "post".into());
+    assert_eq!(2, cache.len());
+    assert!(cache.get("/federation/u/thom").is_none());
+    assert!(cache.get("/federation/c/main").is_some());
+
+    // Replacing an entry doesn't evict anything
+    cache.insert("/federation/c/main", "renamed".into());
+    assert_eq!(2, cache.len());
+    assert_eq!("renamed", cache.get("/federation/c/main").unwrap().body);
+
+    let mut objects = ApubCache::new(10, Duration::from_secs(60));
+    for key in &[
+      "/federation/post/1",
+      "/federation/post/1/likes",
+      "/federation/post/12",
+      "/federation/post/2",
+    ] {
+      objects.insert(key, "object".into());
+    }
+    objects.remove_with_children("/federation/post/1");
+    assert_eq!(2, objects.len());
+    assert!(objects.get("/federation/post/1/likes").is_none());
+    assert!(objects.get("/federation/post/12").is_some());
+    assert!(objects.get("/federation/post/2").is_some());
+
+    let mut expired = ApubCache::new(2, Duration::from_secs(0));
+    expired.insert("/federation/c/main", "main".into());
+    assert!(expired.get("/federation/c/main").is_none());
+    assert!(expired.is_empty());
+  }
+}
@@ -0,0 +1,95 @@
+use crate::apub::cache::cached_apub_response;
+use crate::apub::vote::{comment_votes, with_vote_collections};
+use crate::apub::{fetchable_post, make_apub_endpoint, PUBLIC};
+use crate::db::comment::Comment;
+use crate::db::post::Post;
+use crate::db::Crud;
+use crate::to_datetime_utc;
+use activitypub::{context, object::Note};
+use actix_web::body::Body;
+use actix_web::web;
+use actix_web::web::Path;
+use actix_web::{HttpRequest, HttpResponse};
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use serde::Deserialize;
+
+impl Comment {
+  /// Top-level comments reply to the post, or to the video of a post received from PeerTube.
+  pub fn as_note(&self, post: &Post) -> Note {
+    let base_url = make_apub_endpoint("comment", self.id);
+    let mut note = Note::default();
+
+    note.object_props.set_context_object(context()).ok();
+    note.object_props.set_id_string(base_url).ok();
+
+    // Removed and deleted comments keep their place in the thread, but not their content
+    if !self.removed && !self.deleted {
+      note
+        .object_props
+        .set_content_string(self.content.to_owned())
+        .ok();
+    }
+
+    let in_reply_to = match self.parent_id {
+      Some(parent_id) => make_apub_endpoint("comment", parent_id),
+      None => post
+        .ap_id
+        .to_owned()
+        .unwrap_or_else(|| make_apub_endpoint("post", self.post_id)),
+    };
+    note.object_props.set_in_reply_to_string(in_reply_to).ok();
+    note.object_props.set_to_string(PUBLIC.to_string()).ok();
+
+    note
+      .object_props
+      .set_published_utctime(to_datetime_utc(self.published))
+      .ok();
+    if let Some(updated) = self.updated {
+      note
+        .object_props
+        .set_updated_utctime(to_datetime_utc(updated))
+        .ok();
+    }
+
+    note
+  }
+}
+
+#[derive(Deserialize)]
+pub struct CommentQuery {
+  comment_id: i32,
+}
+
+pub async fn get_apub_comment(
+  req: HttpRequest,
+  info: Path<CommentQuery>,
+  db: web::Data<Pool<ConnectionManager<PgConnection>>>,
+) -> HttpResponse<Body> {
+  cached_apub_response(&req, || {
+    let conn = db.get().ok()?;
+    let comment = Comment::read(&conn, info.comment_id).ok()?;
+    let (post, _) = fetchable_post(&conn, comment.post_id)?;
+    let note = serde_json::to_value(comment.as_note(&post)).unwrap();
+    Some(with_vote_collections(note).to_string())
+  })
+}
+
+#[derive(Deserialize)]
+pub struct CommentVotesQuery {
+  comment_id: i32,
+  collection: String,
+}
+
+/// The `likes` or `dislikes` of a comment.
+pub async fn get_apub_comment_votes(
+  req: HttpRequest,
+  info: Path<CommentVotesQuery>,
+  db: web::Data<Pool<ConnectionManager<PgConnection>>>,
+) -> HttpResponse<Body> {
+  cached_apub_response(&req, || {
+    let conn = db.get().ok()?;
+    let votes = comment_votes(&conn, info.comment_id, &info.collection)?;
+    Some(serde_json::to_string(&votes).unwrap())
+  })
+}
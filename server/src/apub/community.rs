@@ -1,17 +1,37 @@
-use crate::apub::make_apub_endpoint;
+use crate::apub::cache::cached_apub_response;
+use crate::apub::{actor_inbox, is_federated, make_apub_endpoint, post_activity, PUBLIC};
 use crate::db::community::Community;
-use crate::db::community_view::CommunityFollowerView;
-use crate::db::establish_unpooled_connection;
-use crate::to_datetime_utc;
-use activitypub::{actor::Group, collection::UnorderedCollection, context};
+use crate::db::community_view::{CommunityFollowerView, CommunityModeratorView};
+use crate::db::post::Post;
+use crate::db::user::User_;
+use crate::db::Crud;
+use crate::{naive_now, to_datetime_utc};
+use activitypub::collection::{OrderedCollection, UnorderedCollection};
+use activitypub::{actor::Group, context};
 use actix_web::body::Body;
+use actix_web::web;
 use actix_web::web::Path;
-use actix_web::HttpResponse;
+use actix_web::{HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use log::{info, warn};
 use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::thread;
 
 impl Community {
+  /// The activitypub id of the community, which renamed communities keep from before.
+  pub fn apub_id(&self) -> String {
+    self
+      .actor_id
+      .to_owned()
+      .unwrap_or_else(|| make_apub_endpoint("c", &self.name))
+  }
+
   pub fn as_group(&self) -> Group {
-    let base_url = make_apub_endpoint("c", &self.name);
+    let base_url = self.apub_id();
 
     let mut group = Group::default();
 
@@ -21,6 +41,10 @@ impl Community {
       .object_props
       .set_name_string(self.name.to_owned())
       .ok();
+    group
+      .ap_actor_props
+      .set_preferred_username_string(self.name.to_owned())
+      .ok();
     group
       .object_props
       .set_published_utctime(to_datetime_utc(self.published))
@@ -51,59 +75,208 @@ impl Community {
       .ap_actor_props
       .set_followers_string(format!("{}/followers", &base_url))
       .ok();
+    // Other instances find the moderators through here
+    group
+      .object_props
+      .set_attributed_to_string(format!("{}/moderators", &base_url))
+      .ok();
 
     group
   }
 
-  pub fn followers_as_collection(&self) -> UnorderedCollection {
-    let base_url = make_apub_endpoint("c", &self.name);
+  /// The group with its featured posts collection, which the activitypub crate doesn't know about.
+  pub fn as_group_json(&self) -> Value {
+    let mut group = serde_json::to_value(self.as_group()).unwrap();
+    group["featured"] = Value::String(format!("{}/featured", self.apub_id()));
+    group
+  }
 
+  /// The followers, by their activitypub ids.
+  pub fn followers_as_collection(&self, followers: Vec<String>) -> UnorderedCollection {
     let mut collection = UnorderedCollection::default();
     collection.object_props.set_context_object(context()).ok();
-    collection.object_props.set_id_string(base_url).ok();
-
-    let connection = establish_unpooled_connection();
-    //As we are an object, we validated that the community id was valid
-    let community_followers = CommunityFollowerView::for_community(&connection, self.id).unwrap();
+    collection
+      .object_props
+      .set_id_string(format!("{}/followers", self.apub_id()))
+      .ok();
+    collection
+      .collection_props
+      .set_items_string_vec(followers)
+      .unwrap();
+    collection
+  }
 
-    let ap_followers = community_followers
-      .iter()
-      .map(|follower| make_apub_endpoint("u", &follower.user_name))
-      .collect();
+  /// The moderators, by their activitypub ids. Remote instances add and remove them with `Add` and
+  /// `Remove` activities that target this collection.
+  pub fn moderators_as_collection(&self, moderators: &[User_]) -> UnorderedCollection {
+    let mut collection = UnorderedCollection::default();
+    collection.object_props.set_context_object(context()).ok();
+    collection
+      .object_props
+      .set_id_string(format!("{}/moderators", self.apub_id()))
+      .ok();
+    collection
+      .collection_props
+      .set_items_string_vec(moderators.iter().map(User_::apub_id).collect())
+      .unwrap();
+    collection
+  }
 
+  /// The stickied posts, by their activitypub ids, newest first. Other instances follow it with the
+  /// `Add` and `Remove` activities sent when posts are stickied and unstickied.
+  pub fn featured_as_collection(&self, featured: &[Post]) -> OrderedCollection {
+    let mut collection = OrderedCollection::default();
+    collection.object_props.set_context_object(context()).ok();
+    collection
+      .object_props
+      .set_id_string(format!("{}/featured", self.apub_id()))
+      .ok();
     collection
       .collection_props
-      .set_items_string_vec(ap_followers)
+      .set_items_string_vec(featured.iter().map(Post::apub_id).collect())
       .unwrap();
     collection
   }
 }
 
+/// Sends an `Update` of the community's group to the instances of its remote followers and
+/// moderators, which is how they find out that it was renamed.
+pub fn send_update_group(conn: &PgConnection, community: &Community) {
+  send_to_remote_members(
+    conn,
+    community,
+    update_group_activity(community, naive_now()),
+  );
+}
+
+pub fn update_group_activity(community: &Community, sent: NaiveDateTime) -> Value {
+  let actor_id = community.apub_id();
+  json!({
+    "@context": "https://www.w3.org/ns/activitystreams",
+    "id": format!("{}#update-{}", actor_id, sent.timestamp()),
+    "type": "Update",
+    "actor": actor_id,
+    "to": [PUBLIC],
+    "cc": [format!("{}/followers", actor_id)],
+    "object": community.as_group_json(),
+  })
+}
+
+/// Sends an activity of the community to the instances of its remote followers and moderators. It's
+/// sent in the background, and failures are only logged.
+pub fn send_to_remote_members(conn: &PgConnection, community: &Community, activity: Value) {
+  let mut user_ids = CommunityFollowerView::for_community(conn, community.id)
+    .unwrap_or_default()
+    .iter()
+    .map(|follower| follower.user_id)
+    .collect::<Vec<i32>>();
+  user_ids.extend(
+    CommunityModeratorView::for_community(conn, community.id)
+      .unwrap_or_default()
+      .iter()
+      .map(|moderator| moderator.user_id),
+  );
+  let remote_actor_ids = user_ids
+    .into_iter()
+    .filter_map(|user_id| User_::read(conn, user_id).ok()?.actor_id)
+    .collect::<BTreeSet<String>>();
+  if remote_actor_ids.is_empty() {
+    return;
+  }
+
+  thread::spawn(move || {
+    let inboxes = remote_actor_ids
+      .iter()
+      .filter_map(|actor_id| match actor_inbox(actor_id) {
+        Ok(inbox) => Some(inbox),
+        Err(e) => {
+          warn!("Couldn't find the inbox of {}: {}", actor_id, e);
+          None
+        }
+      })
+      .collect::<BTreeSet<String>>();
+    for inbox in inboxes {
+      match post_activity(&inbox, &activity) {
+        Ok(()) => info!("Sent {} {} to {}", activity["type"], activity["id"], inbox),
+        Err(e) => warn!(
+          "Couldn't send {} {}: {}",
+          activity["type"], activity["id"], e
+        ),
+      }
+    }
+  });
+}
+
 #[derive(Deserialize)]
 pub struct CommunityQuery {
   community_name: String,
 }
 
-pub async fn get_apub_community(info: Path<CommunityQuery>) -> HttpResponse<Body> {
-  let connection = establish_unpooled_connection();
+pub async fn get_apub_community(
+  req: HttpRequest,
+  info: Path<CommunityQuery>,
+  db: web::Data<Pool<ConnectionManager<PgConnection>>>,
+) -> HttpResponse<Body> {
+  cached_apub_response(&req, || {
+    let conn = db.get().ok()?;
+    let community = Community::read_from_name(&conn, info.community_name.to_owned())
+      .ok()
+      .filter(is_federated)?;
+    Some(serde_json::to_string(&community.as_group_json()).unwrap())
+  })
+}
 
-  if let Ok(community) = Community::read_from_name(&connection, info.community_name.to_owned()) {
-    HttpResponse::Ok()
-      .content_type("application/activity+json")
-      .body(serde_json::to_string(&community.as_group()).unwrap())
-  } else {
-    HttpResponse::NotFound().finish()
-  }
+pub async fn get_apub_community_followers(
+  req: HttpRequest,
+  info: Path<CommunityQuery>,
+  db: web::Data<Pool<ConnectionManager<PgConnection>>>,
+) -> HttpResponse<Body> {
+  cached_apub_response(&req, || {
+    let conn = db.get().ok()?;
+    let community = Community::read_from_name(&conn, info.community_name.to_owned())
+      .ok()
+      .filter(is_federated)?;
+    // Remote followers are listed by their ids on their own instance
+    let followers = CommunityFollowerView::for_community(&conn, community.id)
+      .ok()?
+      .iter()
+      .filter_map(|follower| User_::read(&conn, follower.user_id).ok())
+      .map(|follower| follower.apub_id())
+      .collect();
+    Some(serde_json::to_string(&community.followers_as_collection(followers)).unwrap())
+  })
 }
 
-pub async fn get_apub_community_followers(info: Path<CommunityQuery>) -> HttpResponse<Body> {
-  let connection = establish_unpooled_connection();
+pub async fn get_apub_community_moderators(
+  req: HttpRequest,
+  info: Path<CommunityQuery>,
+  db: web::Data<Pool<ConnectionManager<PgConnection>>>,
+) -> HttpResponse<Body> {
+  cached_apub_response(&req, || {
+    let conn = db.get().ok()?;
+    let community = Community::read_from_name(&conn, info.community_name.to_owned())
+      .ok()
+      .filter(is_federated)?;
+    let moderators = CommunityModeratorView::for_community(&conn, community.id)
+      .ok()?
+      .iter()
+      .filter_map(|moderator| User_::read(&conn, moderator.user_id).ok())
+      .collect::<Vec<User_>>();
+    Some(serde_json::to_string(&community.moderators_as_collection(&moderators)).unwrap())
+  })
+}
 
-  if let Ok(community) = Community::read_from_name(&connection, info.community_name.to_owned()) {
-    HttpResponse::Ok()
-      .content_type("application/activity+json")
-      .body(serde_json::to_string(&community.followers_as_collection()).unwrap())
-  } else {
-    HttpResponse::NotFound().finish()
-  }
+pub async fn get_apub_community_featured(
+  req: HttpRequest,
+  info: Path<CommunityQuery>,
+  db: web::Data<Pool<ConnectionManager<PgConnection>>>,
+) -> HttpResponse<Body> {
+  cached_apub_response(&req, || {
+    let conn = db.get().ok()?;
+    let community = Community::read_from_name(&conn, info.community_name.to_owned())
+      .ok()
+      .filter(is_federated)?;
+    let featured = Post::list_featured(&conn, community.id).ok()?;
+    Some(serde_json::to_string(&community.featured_as_collection(&featured)).unwrap())
+  })
 }
@@ -0,0 +1,194 @@
+//! Stickied posts, which federate as the `featured` collection of a community's group. When a post
+//! is stickied or unstickied, the community sends an `Add` or `Remove` of it targeting the
+//! collection to the instances of its remote followers and moderators.
+//!
+//! Remote communities send the same for the posts they have that are also here. The activities
+//! aren't verified, so the collection is fetched from the community's instance instead, and whether
+//! the post is in it is what decides if it's stickied here. Only posts from the community's own
+//! instance are changed, and without a modlog entry, since there's no moderator here to log.
+//! Remote moderators stickying posts of local communities is moderation, and left to
+//! `moderation`.
+
+use crate::apub::cache::evict_apub_object;
+use crate::apub::community::send_to_remote_members;
+use crate::apub::PUBLIC;
+use crate::apub::{fetch_remote_object, is_federated, make_apub_endpoint, object_id, url_host};
+use crate::db::community::Community;
+use crate::db::post::Post;
+use crate::db::PostVisibility;
+use crate::naive_now;
+use chrono::NaiveDateTime;
+use diesel::PgConnection;
+use failure::Error;
+use log::info;
+use serde_json::{json, Value};
+
+/// Whether a post is in its community's featured collection: it's stickied, and other instances
+/// can see it.
+pub fn is_featured(post: &Post) -> bool {
+  post.stickied
+    && !post.removed
+    && !post.deleted
+    && PostVisibility::from_i16(post.visibility) != PostVisibility::FollowersOnly
+    && post.scheduled_publish_time.is_none()
+}
+
+/// Sends an `Add` or `Remove` of a post to or from its community's featured collection when an edit
+/// changed whether it's in it, if the community is federated.
+pub fn send_featured(conn: &PgConnection, community: &Community, before: &Post, after: &Post) {
+  let featured = is_featured(after);
+  if !is_federated(community) || is_featured(before) == featured {
+    return;
+  }
+  evict_apub_object("c", format!("{}/featured", community.name));
+  send_to_remote_members(
+    conn,
+    community,
+    featured_activity(community, after, featured, naive_now()),
+  );
+}
+
+pub fn featured_activity(
+  community: &Community,
+  post: &Post,
+  featured: bool,
+  sent: NaiveDateTime,
+) -> Value {
+  let actor_id = community.apub_id();
+  json!({
+    "@context": "https://www.w3.org/ns/activitystreams",
+    "id": format!(
+      "{}#{}-{}-{}",
+      actor_id,
+      if featured { "add" } else { "remove" },
+      post.id,
+      sent.timestamp()
+    ),
+    "type": if featured { "Add" } else { "Remove" },
+    "actor": actor_id,
+    "to": [PUBLIC],
+    "cc": [format!("{}/followers", actor_id)],
+    "object": post.apub_id(),
+    "target": format!("{}/featured", actor_id),
+  })
+}
+
+/// Whether an activity adds or removes a post to or from a remote community's featured
+/// collection.
+pub fn is_remote_featured(activity: &Value) -> bool {
+  let is_add_or_remove = match activity["type"].as_str() {
+    Some("Add") | Some("Remove") => true,
+    _ => false,
+  };
+  let target = match object_id(&activity["target"]) {
+    Some(target) => target,
+    None => return false,
+  };
+  is_add_or_remove
+    && target.ends_with("/featured")
+    && !target.starts_with(&make_apub_endpoint("c", ""))
+}
+
+/// Stickies or unstickies the local copy of a remote community's post, to match its featured
+/// collection.
+pub fn receive_featured(conn: &PgConnection, activity: &Value) -> Result<(), Error> {
+  let actor_id = object_id(&activity["actor"]).ok_or_else(|| format_err!("No actor"))?;
+  let object = object_id(&activity["object"]).ok_or_else(|| format_err!("No object"))?;
+  let target = object_id(&activity["target"]).ok_or_else(|| format_err!("No target"))?;
+
+  let post = match Post::read_from_ap_id(conn, object) {
+    Ok(post) => post,
+    Err(_e) => return Ok(()),
+  };
+  if url_host(object) != url_host(actor_id) {
+    return Err(format_err!("{} isn't from {}", object, actor_id));
+  }
+
+  let group = fetch_remote_object(actor_id)?;
+  if group["featured"].as_str() != Some(target) {
+    return Err(format_err!(
+      "{} isn't the featured posts of {}",
+      target,
+      actor_id
+    ));
+  }
+  let stickied = featured_items(&fetch_remote_object(target)?)
+    .iter()
+    .any(|id| *id == object);
+  if post.stickied == stickied {
+    return Ok(());
+  }
+
+  Post::update_stickied(conn, post.id, stickied)?;
+  info!(
+    "{} {} post {}",
+    actor_id,
+    if stickied { "stickied" } else { "unstickied" },
+    post.id
+  );
+  Ok(())
+}
+
+/// The ids of the posts in a featured collection, which can be ordered or not.
+pub fn featured_items(collection: &Value) -> Vec<&str> {
+  let items = match (&collection["orderedItems"], &collection["items"]) {
+    (Value::Array(items), _) | (_, Value::Array(items)) => items.iter().collect::<Vec<_>>(),
+    _ => vec![],
+  };
+  items.into_iter().filter_map(object_id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_is_remote_featured() {
+    let activity = |kind: &str, target: &str| json!({"type": kind, "target": target});
+    assert!(is_remote_featured(&activity(
+      "Add",
+      "https://remote.example/c/main/featured"
+    )));
+    assert!(is_remote_featured(&json!({
+      "type": "Remove",
+      "target": {"id": "https://remote.example/c/main/featured"},
+    })));
+    assert!(!is_remote_featured(&activity(
+      "Add",
+      "https://remote.example/c/main/moderators"
+    )));
+    assert!(!is_remote_featured(&activity(
+      "Create",
+      "https://remote.example/c/main/featured"
+    )));
+    // Our own communities' are moderation
+    assert!(!is_remote_featured(&activity(
+      "Add",
+      &format!("{}/featured", make_apub_endpoint("c", "main"))
+    )));
+  }
+
+  #[test]
+  fn test_featured_items() {
+    let ordered = json!({
+      "type": "OrderedCollection",
+      "orderedItems": [
+        "https://remote.example/post/1",
+        {"type": "Page", "id": "https://remote.example/post/2"},
+      ],
+    });
+    assert_eq!(
+      vec![
+        "https://remote.example/post/1",
+        "https://remote.example/post/2"
+      ],
+      featured_items(&ordered)
+    );
+    let unordered = json!({"type": "Collection", "items": ["https://remote.example/post/3"]});
+    assert_eq!(
+      vec!["https://remote.example/post/3"],
+      featured_items(&unordered)
+    );
+    assert!(featured_items(&json!({"type": "OrderedCollection"})).is_empty());
+  }
+}
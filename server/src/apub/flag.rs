@@ -0,0 +1,49 @@
+//! `Flag`s, which report remote content or users to their own instance when they're removed or
+//! banned here with the `Flag` scope, so that its moderators can act on them too.
+
+use crate::apub::{actor_inbox, post_activity};
+use crate::db::user::User_;
+use failure::Error;
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::thread;
+
+/// Sends a `Flag` of the objects to the instance of `origin_actor_id`, which is who they're by. It
+/// runs in the background, since it's a couple of requests to another server, and failures are
+/// only logged.
+pub fn send_flag(
+  moderator: &User_,
+  origin_actor_id: &str,
+  flag_id: String,
+  objects: Vec<String>,
+  reason: Option<String>,
+) {
+  let flag = flag_activity(moderator, flag_id, objects, reason);
+  let origin_actor_id = origin_actor_id.to_owned();
+  thread::spawn(move || match deliver(&origin_actor_id, &flag) {
+    Ok(inbox) => info!("Sent flag {} to {}", flag["id"], inbox),
+    Err(e) => warn!("Couldn't send flag {}: {}", flag["id"], e),
+  });
+}
+
+pub fn flag_activity(
+  moderator: &User_,
+  flag_id: String,
+  objects: Vec<String>,
+  reason: Option<String>,
+) -> Value {
+  json!({
+    "@context": "https://www.w3.org/ns/activitystreams",
+    "id": flag_id,
+    "type": "Flag",
+    "actor": moderator.apub_id(),
+    "object": objects,
+    "content": reason.unwrap_or_default(),
+  })
+}
+
+fn deliver(actor_id: &str, activity: &Value) -> Result<String, Error> {
+  let inbox = actor_inbox(actor_id)?;
+  post_activity(&inbox, activity)?;
+  Ok(inbox)
+}
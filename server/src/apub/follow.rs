@@ -0,0 +1,125 @@
+//! Remote users following local communities. A `Follow` of a community's group makes its actor a
+//! follower, and is answered with an `Accept` of it. An `Undo` of the follow takes it back.
+//!
+//! The activities aren't verified, so each one is fetched again from its actor's instance.
+
+use crate::apub::user::apub_user;
+use crate::apub::{actor_inbox, fetch_remote_object, is_federated, make_apub_endpoint};
+use crate::apub::{object_id, post_activity, url_host};
+use crate::db::community::{Community, CommunityFollower, CommunityFollowerForm};
+use crate::db::community_view::{CommunityFollowerView, CommunityUserBanView};
+use crate::db::Followable;
+use crate::naive_now;
+use chrono::NaiveDateTime;
+use diesel::PgConnection;
+use failure::Error;
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::thread;
+
+/// Whether an activity is a follow, or the `Undo` of one, from what it looks like before it's
+/// fetched again.
+pub fn is_follow(activity: &Value) -> bool {
+  match activity["type"].as_str() {
+    Some("Follow") => true,
+    Some("Undo") => activity["object"]["type"] == "Follow",
+    _ => false,
+  }
+}
+
+pub fn receive_follow(conn: &PgConnection, activity: &Value) -> Result<(), Error> {
+  let activity_id = activity["id"]
+    .as_str()
+    .ok_or_else(|| format_err!("No activity id"))?;
+  let activity = fetch_remote_object(activity_id)?;
+  let undo = activity["type"] == "Undo";
+  let follow = if undo { &activity["object"] } else { &activity };
+  if follow["type"] != "Follow" {
+    return Err(format_err!("{} isn't a follow", activity_id));
+  }
+  let actor_id = object_id(&activity["actor"]).ok_or_else(|| format_err!("No actor"))?;
+  if url_host(actor_id) != url_host(activity_id) || object_id(&follow["actor"]) != Some(actor_id) {
+    return Err(format_err!("{} isn't from {}", activity_id, actor_id));
+  }
+  let object = object_id(&follow["object"]).ok_or_else(|| format_err!("No object"))?;
+  let community = followed_community(conn, object)
+    .ok_or_else(|| format_err!("{} isn't a community here", object))?;
+
+  let follower = apub_user(conn, actor_id)?;
+  let form = CommunityFollowerForm {
+    community_id: community.id,
+    user_id: follower.id,
+  };
+  let is_following = CommunityFollowerView::for_community(conn, community.id)?
+    .iter()
+    .any(|f| f.user_id == follower.id);
+  if undo {
+    if is_following {
+      CommunityFollower::ignore(conn, &form)?;
+      info!("{} unfollowed {}", follower.name, community.name);
+    }
+    return Ok(());
+  }
+  if follower.banned || CommunityUserBanView::get(conn, follower.id, community.id).is_ok() {
+    return Err(format_err!(
+      "{} can't follow {}",
+      follower.name,
+      community.name
+    ));
+  }
+  if !is_following {
+    CommunityFollower::follow(conn, &form)?;
+    info!("{} followed {}", follower.name, community.name);
+  }
+
+  let accept = accept_follow_activity(&community, follow, naive_now());
+  let actor_id = actor_id.to_owned();
+  thread::spawn(move || {
+    match actor_inbox(&actor_id).and_then(|inbox| post_activity(&inbox, &accept)) {
+      Ok(()) => info!("Sent {} to {}", accept["id"], actor_id),
+      Err(e) => warn!("Couldn't send {}: {}", accept["id"], e),
+    }
+  });
+  Ok(())
+}
+
+/// The `Accept` of a follow of a community, with the follow in it.
+pub fn accept_follow_activity(community: &Community, follow: &Value, sent: NaiveDateTime) -> Value {
+  let actor_id = community.apub_id();
+  json!({
+    "@context": "https://www.w3.org/ns/activitystreams",
+    "id": format!("{}#accept-{}", actor_id, sent.timestamp()),
+    "type": "Accept",
+    "actor": actor_id,
+    "to": [follow["actor"]],
+    "object": follow,
+  })
+}
+
+/// The federated local community that a follow is of, by its group's id.
+fn followed_community(conn: &PgConnection, object: &str) -> Option<Community> {
+  let community_prefix = make_apub_endpoint("c", "");
+  if !object.starts_with(&community_prefix) {
+    return None;
+  }
+  Community::read_from_name(conn, object[community_prefix.len()..].to_owned())
+    .ok()
+    .filter(|community| is_federated(community) && !community.removed && !community.deleted)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_is_follow() {
+    assert!(is_follow(&json!({"type": "Follow"})));
+    assert!(is_follow(
+      &json!({"type": "Undo", "object": {"type": "Follow"}})
+    ));
+    assert!(!is_follow(
+      &json!({"type": "Undo", "object": {"type": "Like"}})
+    ));
+    assert!(!is_follow(&json!({"type": "Accept"})));
+  }
+}
@@ -0,0 +1,311 @@
+//! The shared inbox at `/federation/inbox`. Activities are checked against the `federation.inbox`
+//! settings before anything else looks at them: their size, how deeply they're nested, their json-ld
+//! shape and their type. Rejections are answered with a json error, and counted per instance for the
+//! admin api.
+//!
+//! Of the accepted activities, the `Create`s of PeerTube videos, the stickies of remote communities,
+//! the moderation activities of remote moderators, and the follows and votes of remote users are
+//! processed. The rest are only logged.
+
+use crate::apub::{featured, follow, moderation, url_host, video, vote};
+use crate::db::inbox_rejection::{InboxRejection, InboxRejectionForm};
+use crate::settings::InboxConfig;
+use crate::Settings;
+use actix_web::body::Body;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+
+const ACTIVITYSTREAMS_CONTEXT: &str = "https://www.w3.org/ns/activitystreams";
+
+/// Why an activity was rejected. `limit` is the setting it went over, for the size and depth limits.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct InboxRejectionResponse {
+  pub error: &'static str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub limit: Option<usize>,
+}
+
+impl InboxRejectionResponse {
+  fn new(error: &'static str) -> Self {
+    InboxRejectionResponse { error, limit: None }
+  }
+}
+
+pub async fn shared_inbox(
+  req: HttpRequest,
+  body: Result<web::Bytes, actix_web::Error>,
+  db: web::Data<Pool<ConnectionManager<PgConnection>>>,
+) -> HttpResponse<Body> {
+  let config = Settings::get().federation.inbox;
+  let mut domain = sender_domain(&req);
+  let validated = match body {
+    Ok(body) => {
+      if domain.is_none() {
+        domain = serde_json::from_slice::<Value>(&body)
+          .ok()
+          .and_then(|activity| activity.get("actor").and_then(actor_id).and_then(url_host));
+      }
+      validate_activity(&body, &config)
+    }
+    Err(e) => {
+      let payload_too_large = e.as_response_error().status_code() == StatusCode::PAYLOAD_TOO_LARGE;
+      Err(if payload_too_large {
+        InboxRejectionResponse {
+          error: "payload_too_large",
+          limit: Some(config.max_payload_size),
+        }
+      } else {
+        InboxRejectionResponse::new("invalid_payload")
+      })
+    }
+  };
+
+  match validated {
+    Ok(activity) => {
+      info!(
+        "Accepted {} activity {} from {}",
+        activity["type"], activity["id"], activity["actor"]
+      );
+      if is_create_video(&activity)
+        || featured::is_remote_featured(&activity)
+        || moderation::is_moderation(&activity)
+        || follow::is_follow(&activity)
+        || vote::is_vote(&activity)
+      {
+        let received = web::block(move || receive(&db.get()?, &activity)).await;
+        if let Err(e) = received {
+          warn!("Couldn't receive activity: {}", e);
+        }
+      }
+      HttpResponse::Accepted().finish()
+    }
+    Err(rejection) => {
+      if let Some(domain) = domain {
+        let form = InboxRejectionForm {
+          domain,
+          reason: rejection.error.to_string(),
+        };
+        let recorded = web::block(move || -> Result<(), failure::Error> {
+          InboxRejection::record(&db.get()?, &form)?;
+          Ok(())
+        })
+        .await;
+        if let Err(e) = recorded {
+          warn!("Couldn't record inbox rejection: {}", e);
+        }
+      }
+      let status = if rejection.error == "payload_too_large" {
+        StatusCode::PAYLOAD_TOO_LARGE
+      } else {
+        StatusCode::BAD_REQUEST
+      };
+      HttpResponse::build(status).json(rejection)
+    }
+  }
+}
+
+/// Checks an activity, returning it parsed if it's accepted.
+pub fn validate_activity(
+  body: &[u8],
+  config: &InboxConfig,
+) -> Result<Value, InboxRejectionResponse> {
+  let activity: Value =
+    serde_json::from_slice(body).map_err(|_| InboxRejectionResponse::new("invalid_json"))?;
+  let object = activity
+    .as_object()
+    .ok_or_else(|| InboxRejectionResponse::new("not_an_object"))?;
+
+  if depth(&activity) > config.max_depth {
+    return Err(InboxRejectionResponse {
+      error: "too_deep",
+      limit: Some(config.max_depth),
+    });
+  }
+
+  let has_context = match object.get("@context") {
+    Some(Value::String(context)) => context == ACTIVITYSTREAMS_CONTEXT,
+    Some(Value::Array(contexts)) => contexts
+      .iter()
+      .any(|c| c.as_str() == Some(ACTIVITYSTREAMS_CONTEXT)),
+    _ => false,
+  };
+  if !has_context {
+    return Err(InboxRejectionResponse::new("invalid_context"));
+  }
+
+  let kind = object
+    .get("type")
+    .and_then(Value::as_str)
+    .ok_or_else(|| InboxRejectionResponse::new("missing_type"))?;
+  if !config.accepted_activity_types.iter().any(|t| t == kind) {
+    return Err(InboxRejectionResponse::new("activity_type_not_accepted"));
+  }
+
+  let id_host = object
+    .get("id")
+    .and_then(Value::as_str)
+    .and_then(url_host)
+    .ok_or_else(|| InboxRejectionResponse::new("invalid_id"))?;
+  let actor_host = object
+    .get("actor")
+    .and_then(actor_id)
+    .and_then(url_host)
+    .ok_or_else(|| InboxRejectionResponse::new("invalid_actor"))?;
+  if id_host != actor_host {
+    return Err(InboxRejectionResponse::new("id_actor_mismatch"));
+  }
+
+  if object.get("object").map_or(true, Value::is_null) {
+    return Err(InboxRejectionResponse::new("missing_object"));
+  }
+
+  Ok(activity)
+}
+
+fn receive(conn: &PgConnection, activity: &Value) -> Result<(), failure::Error> {
+  if is_create_video(activity) {
+    video::receive_create_video(conn, activity)?;
+  } else if featured::is_remote_featured(activity) {
+    featured::receive_featured(conn, activity)?;
+  } else if follow::is_follow(activity) {
+    follow::receive_follow(conn, activity)?;
+  } else if vote::is_vote(activity) {
+    vote::receive_vote(conn, activity)?;
+  } else {
+    moderation::receive_moderation(conn, activity)?;
+  }
+  Ok(())
+}
+
+/// Whether an activity creates a video. The object can be the video or its id, which is checked
+/// when it's fetched.
+pub fn is_create_video(activity: &Value) -> bool {
+  activity["type"] == "Create"
+    && (activity["object"].is_string() || activity["object"]["type"] == "Video")
+}
+
+/// How deeply objects and arrays are nested, where a bare value is 0.
+fn depth(value: &Value) -> usize {
+  match value {
+    Value::Array(values) => 1 + values.iter().map(depth).max().unwrap_or(0),
+    Value::Object(values) => 1 + values.values().map(depth).max().unwrap_or(0),
+    _ => 0,
+  }
+}
+
+/// An actor is either its id, or an object with one.
+fn actor_id(actor: &Value) -> Option<&str> {
+  match actor {
+    Value::String(id) => Some(id),
+    Value::Object(actor) => actor.get("id").and_then(Value::as_str),
+    _ => None,
+  }
+}
+
+/// Which instance sent a request, from the signature's key id. Unsigned requests are counted by
+/// their actor instead, when the body can be parsed that far.
+fn sender_domain(req: &HttpRequest) -> Option<String> {
+  let signature = req.headers().get("Signature")?.to_str().ok()?;
+  let key_id = signature
+    .split(',')
+    .filter_map(|param| {
+      let mut kv = param.trim().splitn(2, '=');
+      Some((kv.next()?, kv.next()?))
+    })
+    .find(|(k, _)| *k == "keyId")?
+    .1
+    .trim_matches('"');
+  url_host(key_id)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn config() -> InboxConfig {
+    InboxConfig {
+      max_payload_size: 1024,
+      max_depth: 4,
+      accepted_activity_types: vec!["Create".into(), "Follow".into()],
+    }
+  }
+
+  fn rejection(activity: Value) -> &'static str {
+    validate_activity(activity.to_string().as_bytes(), &config())
+      .unwrap_err()
+      .error
+  }
+
+  #[test]
+  fn test_validate_activity() {
+    let activity = json!({
+      "@context": [ACTIVITYSTREAMS_CONTEXT, {"sensitive": "as:sensitive"}],
+      "id": "https://remote.example/activities/1",
+      "type": "Create",
+      "actor": {"id": "https://Remote.example:8443/u/thom"},
+      "object": {"type": "Note", "content": "hi"},
+    });
+    assert!(validate_activity(activity.to_string().as_bytes(), &config()).is_ok());
+
+    let with = |key: &str, value: Value| {
+      let mut activity = activity.clone();
+      activity[key] = value;
+      activity
+    };
+    assert_eq!(
+      "invalid_json",
+      validate_activity(b"{\"type\":", &config())
+        .unwrap_err()
+        .error
+    );
+    assert_eq!("not_an_object", rejection(json!(["Create"])));
+    assert_eq!(
+      InboxRejectionResponse {
+        error: "too_deep",
+        limit: Some(4)
+      },
+      validate_activity(
+        with("object", json!({"a": {"b": {"c": {}}}}))
+          .to_string()
+          .as_bytes(),
+        &config()
+      )
+      .unwrap_err()
+    );
+    assert_eq!(
+      "invalid_context",
+      rejection(with("@context", json!("https://example.com/ns")))
+    );
+    assert_eq!("missing_type", rejection(with("type", json!(1))));
+    assert_eq!(
+      "activity_type_not_accepted",
+      rejection(with("type", json!("Block")))
+    );
+    assert_eq!(
+      "invalid_id",
+      rejection(with("id", json!("http://remote.example/activities/1")))
+    );
+    assert_eq!("invalid_actor", rejection(with("actor", json!({}))));
+    assert_eq!(
+      "id_actor_mismatch",
+      rejection(with("actor", json!("https://other.example/u/thom")))
+    );
+    assert_eq!("missing_object", rejection(with("object", Value::Null)));
+  }
+
+  #[test]
+  fn test_url_host() {
+    assert_eq!(
+      Some("remote.example".into()),
+      url_host("https://user@Remote.example:443/u/thom#main-key")
+    );
+    assert_eq!(None, url_host("http://remote.example/u/thom"));
+    assert_eq!(None, url_host("https:///u/thom"));
+  }
+}
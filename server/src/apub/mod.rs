@@ -1,16 +1,41 @@
+pub mod cache;
+pub mod comment;
 pub mod community;
+pub mod featured;
+pub mod flag;
+pub mod follow;
+pub mod inbox;
+pub mod moderation;
 pub mod post;
+pub mod prefetch;
+pub mod throttle;
 pub mod user;
+pub mod video;
+pub mod vote;
+use crate::apub::throttle::{throttled, Traffic};
+use crate::db::community::Community;
+use crate::db::post::Post;
+use crate::db::{Crud, PostVisibility};
 use crate::Settings;
-
+use diesel::PgConnection;
+use failure::Error;
+use isahc::prelude::*;
+use serde_json::Value;
 use std::fmt::Display;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[cfg(test)]
 mod tests {
+  use super::post::attachments_from_object;
+  use super::{local_object, make_apub_endpoint, LocalObject, PUBLIC};
   use crate::db::community::Community;
   use crate::db::post::Post;
+  use crate::db::post_attachment::{PostAttachment, PostAttachmentForm};
   use crate::db::user::User_;
-  use crate::db::{ListingType, SortType};
+  use crate::db::{ListingType, PostAttachmentKind, PostVisibility, SortType};
   use crate::{naive_now, Settings};
 
   #[test]
@@ -35,11 +60,29 @@ mod tests {
       lang: "browser".into(),
       show_avatars: true,
       send_notifications_to_email: false,
+      settings: serde_json::json!({}),
+      legal_hold: false,
+      hide_profile: false,
+      actor_id: None,
+      hide_activity: false,
+      last_refreshed: None,
+      refresh_failures: 0,
+      refresh_retry_at: None,
+      blended_discovery_percent: 20,
+      blended_discovery_local: false,
+      show_scores: true,
+      bot_account: false,
+      show_generated: true,
+      ban_expires: None,
+      collapse_crossposts: false,
     };
 
     let person = user.as_person();
     assert_eq!(
-      format!("https://{}/federation/u/thom", Settings::get().hostname),
+      format!(
+        "https://{}/federation/u/thom",
+        Settings::get().actor_domain()
+      ),
       person.object_props.id_string().unwrap()
     );
   }
@@ -58,17 +101,65 @@ mod tests {
       updated: Some(naive_now()),
       deleted: false,
       nsfw: false,
+      rules: None,
+      require_rules_ack: false,
+      require_alt_text: false,
+      local_only: false,
+      hidden: false,
+      actor_id: None,
+      report_auto_remove_threshold: None,
     };
 
     let group = community.as_group();
     assert_eq!(
-      format!("https://{}/federation/c/Test", Settings::get().hostname),
+      format!(
+        "https://{}/federation/c/Test",
+        Settings::get().actor_domain()
+      ),
       group.object_props.id_string().unwrap()
     );
+    assert_eq!(
+      format!("{}/moderators", make_apub_endpoint("c", "Test")),
+      serde_json::to_value(&group).unwrap()["attributedTo"]
+    );
+    assert_eq!(
+      format!("{}/featured", make_apub_endpoint("c", "Test")),
+      community.as_group_json()["featured"]
+    );
+
+    // A renamed community keeps its id, with its new name
+    let renamed = Community {
+      name: "Renamed".into(),
+      actor_id: Some(make_apub_endpoint("c", "Test")),
+      ..community
+    };
+    let group = serde_json::to_value(renamed.as_group()).unwrap();
+    assert_eq!(make_apub_endpoint("c", "Test"), group["id"]);
+    assert_eq!("Renamed", group["preferredUsername"]);
   }
 
   #[test]
   fn test_post() {
+    let community = Community {
+      id: 42,
+      name: "Test".into(),
+      title: "Test Title".into(),
+      description: Some("Test community".into()),
+      category_id: 32,
+      creator_id: 52,
+      removed: false,
+      published: naive_now(),
+      updated: Some(naive_now()),
+      deleted: false,
+      nsfw: false,
+      rules: None,
+      require_rules_ack: false,
+      require_alt_text: false,
+      local_only: false,
+      hidden: false,
+      actor_id: None,
+      report_auto_remove_threshold: None,
+    };
     let post = Post {
       id: 62,
       name: "A test post".into(),
@@ -87,21 +178,365 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      legal_hold: false,
+      slug: "a-test-post".into(),
+      excerpt: None,
+      alt_text: None,
+      visibility: PostVisibility::Public as i16,
+      ap_id: None,
+      generated: false,
+      language_id: None,
+      scheduled_publish_time: None,
     };
 
-    let page = post.as_page();
+    let page = post.as_page(&community, &[]);
     assert_eq!(
-      format!("https://{}/federation/post/62", Settings::get().hostname),
+      format!(
+        "https://{}/federation/post/62",
+        Settings::get().actor_domain()
+      ),
       page.object_props.id_string().unwrap()
     );
+
+    let image_post = Post {
+      url: Some("https://example.com/pictshare/cat.png".into()),
+      alt_text: Some("A sleeping cat".into()),
+      ..post
+    };
+    let page = serde_json::to_value(image_post.as_page(&community, &[])).unwrap();
+    assert_eq!("Image", page["attachment"]["type"]);
+    assert_eq!("A sleeping cat", page["attachment"]["name"]);
+    assert_eq!(serde_json::json!([PUBLIC]), page["to"]);
+
+    let followers = format!("{}/followers", make_apub_endpoint("c", "Test"));
+    let unlisted_post = Post {
+      visibility: PostVisibility::Unlisted as i16,
+      ..image_post
+    };
+    let page = serde_json::to_value(unlisted_post.as_page(&community, &[])).unwrap();
+    assert_eq!(serde_json::json!([followers]), page["to"]);
+    assert_eq!(serde_json::json!([PUBLIC]), page["cc"]);
+
+    let followers_only_post = Post {
+      visibility: PostVisibility::FollowersOnly as i16,
+      ..unlisted_post
+    };
+    let page = serde_json::to_value(followers_only_post.as_page(&community, &[])).unwrap();
+    assert_eq!(serde_json::json!([followers]), page["to"]);
+    assert!(page.get("cc").is_none());
+
+    // Attachments replace the image of the url
+    let attachments = vec![
+      PostAttachment {
+        id: 1,
+        post_id: 62,
+        position: 0,
+        kind: PostAttachmentKind::Video as i16,
+        url: "https://example.com/cat.mp4".into(),
+        mime_type: Some("video/mp4".into()),
+        name: None,
+        published: naive_now(),
+        duration: Some(93),
+      },
+      PostAttachment {
+        id: 2,
+        post_id: 62,
+        position: 1,
+        kind: PostAttachmentKind::Image as i16,
+        url: "https://example.com/pictshare/dog.png".into(),
+        mime_type: None,
+        name: Some("A dog".into()),
+        published: naive_now(),
+        duration: None,
+      },
+    ];
+    let page = serde_json::to_value(followers_only_post.as_page(&community, &attachments)).unwrap();
+    assert_eq!(
+      serde_json::json!([
+        {"type": "Video", "url": "https://example.com/cat.mp4", "mediaType": "video/mp4", "duration": "PT93S"},
+        {"type": "Image", "url": "https://example.com/pictshare/dog.png", "name": "A dog"},
+      ]),
+      page["attachment"]
+    );
+  }
+
+  #[test]
+  fn test_attachments_from_object() {
+    let page = serde_json::json!({
+      "type": "Page",
+      "attachment": [
+        {"type": "Document", "url": "https://remote.example/file.pdf"},
+        {"type": "Image", "url": "https://remote.example/cat.png", "name": "A cat", "mediaType": "image/png"},
+        {"type": "Video", "url": [{"type": "Link", "href": "https://remote.example/cat.mp4"}]},
+        {"type": "Image"},
+      ],
+    });
+    let attachments = attachments_from_object(62, &page);
+    assert_eq!(2, attachments.len());
+    assert_eq!(
+      PostAttachmentForm {
+        post_id: 62,
+        position: 0,
+        kind: PostAttachmentKind::Image as i16,
+        url: "https://remote.example/cat.png".into(),
+        mime_type: Some("image/png".into()),
+        name: Some("A cat".into()),
+        duration: None,
+      },
+      attachments[0]
+    );
+    assert_eq!(1, attachments[1].position);
+    assert_eq!("https://remote.example/cat.mp4", attachments[1].url);
+
+    let single =
+      serde_json::json!({"attachment": {"type": "Image", "url": "https://remote.example/a.png"}});
+    assert_eq!(1, attachments_from_object(62, &single).len());
+    assert!(attachments_from_object(62, &serde_json::json!({})).is_empty());
+
+    // A Funkwhale track is an Audio itself, with its duration in seconds
+    let track = serde_json::json!({
+      "type": "Audio",
+      "name": "A song",
+      "duration": 229,
+      "url": [{"type": "Link", "href": "https://funkwhale.example/track.mp3", "mediaType": "audio/mpeg"}],
+    });
+    let attachments = attachments_from_object(62, &track);
+    assert_eq!(PostAttachmentKind::Audio as i16, attachments[0].kind);
+    assert_eq!(Some("audio/mpeg".into()), attachments[0].mime_type);
+    assert_eq!(Some(229), attachments[0].duration);
+
+    // A Castopod episode has its audio apart from the attachments
+    let episode = serde_json::json!({
+      "type": "PodcastEpisode",
+      "audio": {
+        "type": "Audio",
+        "url": {"type": "Link", "href": "https://castopod.example/episode.mp3"},
+        "duration": "PT1H2M3.4S",
+      },
+      "attachment": {"type": "Image", "url": "https://castopod.example/cover.png"},
+    });
+    let attachments = attachments_from_object(62, &episode);
+    assert_eq!(2, attachments.len());
+    assert_eq!("https://castopod.example/episode.mp3", attachments[0].url);
+    assert_eq!(Some(3723), attachments[0].duration);
+    assert_eq!(PostAttachmentKind::Image as i16, attachments[1].kind);
+  }
+
+  #[test]
+  fn test_local_object() {
+    let settings = Settings::get();
+    assert_eq!(
+      Some(LocalObject::Post(62)),
+      local_object(&make_apub_endpoint("post", 62))
+    );
+    assert_eq!(
+      Some(LocalObject::Comment(7)),
+      local_object(&make_apub_endpoint("comment", 7))
+    );
+    assert_eq!(
+      Some(LocalObject::Post(62)),
+      local_object(&format!(
+        "https://{}/post/62/a-test-post",
+        settings.hostname
+      ))
+    );
+    assert_eq!(
+      Some(LocalObject::Comment(7)),
+      local_object(&format!(
+        "https://{}/post/62/comment/7#top",
+        settings.hostname
+      ))
+    );
+    assert_eq!(
+      None,
+      local_object("https://remote.example/federation/post/62")
+    );
+    assert_eq!(None, local_object(&make_apub_endpoint("c", "main")));
+  }
+}
+
+/// Whether a community is served over activitypub, along with the posts and comments in it. Local
+/// only ones aren't, and can't be found with webfinger either.
+pub fn is_federated(community: &Community) -> bool {
+  !community.local_only
+}
+
+/// Reads the community of a post or comment, if it's federated.
+pub fn federated_community(conn: &PgConnection, community_id: i32) -> Option<Community> {
+  Community::read(conn, community_id)
+    .ok()
+    .filter(is_federated)
+}
+
+/// Reads a post that other instances can fetch, along with its comments and votes, and its
+/// community. Followers only posts are only delivered to the followers, scheduled ones aren't out
+/// yet, and the ones in local only communities aren't served at all.
+pub fn fetchable_post(conn: &PgConnection, post_id: i32) -> Option<(Post, Community)> {
+  let post = Post::read(conn, post_id).ok()?;
+  if PostVisibility::from_i16(post.visibility) == PostVisibility::FollowersOnly
+    || post.scheduled_publish_time.is_some()
+  {
+    return None;
   }
+  let community = federated_community(conn, post.community_id)?;
+  Some((post, community))
 }
 
+/// The collection that addresses an object to everyone.
+pub const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
 pub fn make_apub_endpoint<S: Display, T: Display>(point: S, value: T) -> String {
   format!(
     "https://{}/federation/{}/{}",
-    Settings::get().hostname,
+    Settings::get().actor_domain(),
     point,
     value
   )
 }
+
+/// Where other instances deliver activities to this one.
+pub fn shared_inbox() -> String {
+  format!("https://{}/federation/inbox", Settings::get().hostname)
+}
+
+/// An object is either its id, or an object with one.
+pub fn object_id(object: &Value) -> Option<&str> {
+  object.as_str().or_else(|| object["id"].as_str())
+}
+
+/// The lowercased host of an https url.
+pub fn url_host(url: &str) -> Option<String> {
+  if !url.starts_with("https://") {
+    return None;
+  }
+  let authority = url["https://".len()..]
+    .split(|c| c == '/' || c == '?' || c == '#')
+    .next()?;
+  let host = authority.rsplit('@').next()?.split(':').next()?;
+  if host.is_empty() {
+    None
+  } else {
+    Some(host.to_lowercase())
+  }
+}
+
+/// How federation requests reach other instances.
+pub trait Transport: Send + Sync {
+  /// The body of an object, which has to be answered with a success.
+  fn get(&self, url: &str) -> Result<String, Error>;
+  /// Posts an activity to an inbox, which has to answer with a success.
+  fn post(&self, inbox: &str, activity: &Value) -> Result<(), Error>;
+}
+
+/// Requests over https, which is what's used unless another transport is set.
+struct HttpTransport;
+
+impl Transport for HttpTransport {
+  fn get(&self, url: &str) -> Result<String, Error> {
+    let mut response = Request::get(url)
+      .header("Accept", "application/activity+json")
+      .timeout(FETCH_TIMEOUT)
+      .body(())?
+      .send()?;
+    if !response.status().is_success() {
+      return Err(format_err!(
+        "Fetching {} failed with {}",
+        url,
+        response.status()
+      ));
+    }
+    Ok(response.text()?)
+  }
+
+  fn post(&self, inbox: &str, activity: &Value) -> Result<(), Error> {
+    let response = Request::post(inbox)
+      .header("Content-Type", "application/activity+json")
+      .timeout(FETCH_TIMEOUT)
+      .body(activity.to_string())?
+      .send()?;
+    if !response.status().is_success() {
+      return Err(format_err!("{} answered with {}", inbox, response.status()));
+    }
+    Ok(())
+  }
+}
+
+lazy_static! {
+  static ref TRANSPORT: RwLock<Arc<dyn Transport>> = RwLock::new(Arc::new(HttpTransport));
+}
+
+/// Sends the requests to other instances through another transport from now on. The integration
+/// tests use it to connect their in-process instances.
+pub fn set_transport(transport: Arc<dyn Transport>) {
+  *TRANSPORT.write().unwrap() = transport;
+}
+
+fn transport() -> Arc<dyn Transport> {
+  TRANSPORT.read().unwrap().to_owned()
+}
+
+/// Fetches a remote object by its id. Objects are only trusted from where their id says they're
+/// from, since the copies in incoming activities aren't verified.
+pub fn fetch_remote_object(url: &str) -> Result<Value, Error> {
+  let text = throttled(Traffic::Fetch, url, || transport().get(url))?;
+  if text.len() > Settings::get().federation.inbox.max_payload_size {
+    return Err(format_err!("{} is too large", url));
+  }
+  let object: Value = serde_json::from_str(&text)?;
+  let host = url_host(url);
+  if host.is_none() || object["id"].as_str().and_then(url_host) != host {
+    return Err(format_err!("{} isn't from its own host", url));
+  }
+  Ok(object)
+}
+
+/// The inbox to deliver activities for an actor to: the shared inbox of their instance, or their
+/// own.
+pub fn actor_inbox(actor_id: &str) -> Result<String, Error> {
+  let actor = fetch_remote_object(actor_id)?;
+  actor["endpoints"]["sharedInbox"]
+    .as_str()
+    .or_else(|| actor["inbox"].as_str())
+    .map(str::to_owned)
+    .ok_or_else(|| format_err!("{} has no inbox", actor_id))
+}
+
+pub fn post_activity(inbox: &str, activity: &Value) -> Result<(), Error> {
+  throttled(Traffic::Delivery, inbox, || {
+    transport().post(inbox, activity)
+  })
+}
+
+/// A local post or comment, found from a url.
+#[derive(Debug, PartialEq)]
+pub enum LocalObject {
+  Post(i32),
+  Comment(i32),
+}
+
+/// The local post or comment that a url is the activitypub id or page of, if it's one of ours.
+pub fn local_object(url: &str) -> Option<LocalObject> {
+  let settings = Settings::get();
+  let url = url.split(|c| c == '?' || c == '#').next()?;
+  if !url.starts_with("https://") {
+    return None;
+  }
+  let rest = &url["https://".len()..];
+  let (host, path) = rest.split_at(rest.find('/')?);
+  let segments = path.trim_matches('/').split('/').collect::<Vec<&str>>();
+
+  let is_actor_domain = host == settings.actor_domain();
+  let is_hostname = host == settings.hostname;
+  let (kind, id) = match segments.as_slice() {
+    ["federation", kind, id] if is_actor_domain => (*kind, *id),
+    ["post", _, "comment", id] if is_hostname => ("comment", *id),
+    ["post", id] | ["post", id, _] if is_hostname => ("post", *id),
+    _ => return None,
+  };
+  let id = id.parse::<i32>().ok()?;
+  match kind {
+    "post" => Some(LocalObject::Post(id)),
+    "comment" => Some(LocalObject::Comment(id)),
+    _ => None,
+  }
+}
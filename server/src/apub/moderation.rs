@@ -0,0 +1,290 @@
+//! Moderation of local communities by their remote moderators. Other instances add and remove
+//! moderators with `Add` and `Remove` activities targeting a community's moderators collection,
+//! sticky and unsticky posts with ones targeting its featured collection, remove posts and comments
+//! with `Remove`, and lock posts with an `Update` of the page that has `commentsEnabled`.
+//!
+//! The activities aren't verified, so each one is fetched again from its actor's instance, and only
+//! acted on if its actor is a remote moderator of the community, who isn't banned from it.
+
+use crate::apub::cache::evict_apub_object;
+use crate::apub::featured::send_featured;
+use crate::apub::user::apub_user;
+use crate::apub::{fetch_remote_object, is_federated, local_object, make_apub_endpoint};
+use crate::apub::{object_id, url_host, LocalObject};
+use crate::db::comment::Comment;
+use crate::db::community::{Community, CommunityModerator, CommunityModeratorForm};
+use crate::db::community_view::{CommunityModeratorView, CommunityUserBanView};
+use crate::db::moderator::*;
+use crate::db::post::Post;
+use crate::db::user::User_;
+use crate::db::{transaction_with_retry, Crud, Joinable};
+use diesel::PgConnection;
+use failure::Error;
+use log::info;
+use serde_json::Value;
+
+/// Whether an activity is one of the moderation activities, from what it looks like before it's
+/// fetched again.
+pub fn is_moderation(activity: &Value) -> bool {
+  match activity["type"].as_str() {
+    Some("Add") | Some("Remove") => true,
+    Some("Update") => !activity["object"]["commentsEnabled"].is_null(),
+    _ => false,
+  }
+}
+
+pub fn receive_moderation(conn: &PgConnection, activity: &Value) -> Result<(), Error> {
+  let activity_id = activity["id"]
+    .as_str()
+    .ok_or_else(|| format_err!("No activity id"))?;
+  let activity = fetch_remote_object(activity_id)?;
+  let actor_id = object_id(&activity["actor"]).ok_or_else(|| format_err!("No actor"))?;
+  if url_host(actor_id) != url_host(activity_id) {
+    return Err(format_err!("{} isn't from {}", activity_id, actor_id));
+  }
+  let actor = User_::read_from_actor_id(conn, actor_id)
+    .map_err(|_| format_err!("{} doesn't moderate anything here", actor_id))?;
+
+  let object = object_id(&activity["object"]).ok_or_else(|| format_err!("No object"))?;
+  let reason = activity["summary"].as_str().map(str::to_owned);
+  let target = &activity["target"];
+  match (
+    activity["type"].as_str(),
+    target_community(conn, target, "/moderators"),
+    target_community(conn, target, "/featured"),
+  ) {
+    (Some("Add"), Some(community), _) => update_moderator(conn, &actor, &community, object, true),
+    (Some("Remove"), Some(community), _) => {
+      update_moderator(conn, &actor, &community, object, false)
+    }
+    (Some("Add"), None, Some(community)) => sticky_post(conn, &actor, &community, object, true),
+    (Some("Remove"), None, Some(community)) => sticky_post(conn, &actor, &community, object, false),
+    (Some("Remove"), None, None) => remove_object(conn, &actor, object, reason),
+    (Some("Update"), _, _) => match activity["object"]["commentsEnabled"].as_bool() {
+      Some(comments_enabled) => lock_post(conn, &actor, object, !comments_enabled),
+      None => Ok(()),
+    },
+    _ => Ok(()),
+  }
+}
+
+/// Adds or removes a moderator of a community. The community's creator can't be removed.
+fn update_moderator(
+  conn: &PgConnection,
+  actor: &User_,
+  community: &Community,
+  user_id: &str,
+  added: bool,
+) -> Result<(), Error> {
+  check_moderator(conn, actor, community.id)?;
+  let user = apub_user(conn, user_id)?;
+  if !added && user.id == community.creator_id {
+    return Err(format_err!(
+      "The creator of {} can't be removed",
+      community.name
+    ));
+  }
+  let is_moderator = CommunityModeratorView::for_community(conn, community.id)?
+    .iter()
+    .any(|m| m.user_id == user.id);
+  if is_moderator == added {
+    return Ok(());
+  }
+
+  let form = CommunityModeratorForm {
+    community_id: community.id,
+    user_id: user.id,
+  };
+  transaction_with_retry(conn, || -> Result<(), diesel::result::Error> {
+    if added {
+      CommunityModerator::join(conn, &form)?;
+    } else {
+      CommunityModerator::leave(conn, &form)?;
+    }
+    let form = ModAddCommunityForm {
+      mod_user_id: actor.id,
+      other_user_id: user.id,
+      community_id: community.id,
+      removed: Some(!added),
+    };
+    ModAddCommunity::create(conn, &form)?;
+    Ok(())
+  })?;
+  info!(
+    "{} {} {} as a moderator of {}",
+    actor.name,
+    if added { "added" } else { "removed" },
+    user.name,
+    community.name
+  );
+  Ok(())
+}
+
+/// Removes a post or comment, which is either a local one or a post received from elsewhere.
+fn remove_object(
+  conn: &PgConnection,
+  actor: &User_,
+  object: &str,
+  reason: Option<String>,
+) -> Result<(), Error> {
+  match local_object(object) {
+    Some(LocalObject::Comment(comment_id)) => {
+      let comment = Comment::read(conn, comment_id)?;
+      let post = Post::read(conn, comment.post_id)?;
+      check_moderator(conn, actor, post.community_id)?;
+      transaction_with_retry(conn, || -> Result<(), diesel::result::Error> {
+        Comment::update_removed(conn, comment_id, true)?;
+        let form = ModRemoveCommentForm {
+          mod_user_id: actor.id,
+          comment_id,
+          reason: reason.to_owned(),
+          removed: Some(true),
+          remote_scope: None,
+        };
+        ModRemoveComment::create(conn, &form)?;
+        Ok(())
+      })?;
+      evict_apub_object("comment", comment_id);
+      info!("{} removed comment {}", actor.name, comment_id);
+    }
+    _ => {
+      let post = read_post(conn, object)?;
+      check_moderator(conn, actor, post.community_id)?;
+      transaction_with_retry(conn, || -> Result<(), diesel::result::Error> {
+        Post::update_removed(conn, post.id, true)?;
+        let form = ModRemovePostForm {
+          mod_user_id: actor.id,
+          post_id: post.id,
+          reason: reason.to_owned(),
+          removed: Some(true),
+          remote_scope: None,
+        };
+        ModRemovePost::create(conn, &form)?;
+        Ok(())
+      })?;
+      evict_apub_object("post", post.id);
+      info!("{} removed post {}", actor.name, post.id);
+    }
+  }
+  Ok(())
+}
+
+fn lock_post(conn: &PgConnection, actor: &User_, object: &str, locked: bool) -> Result<(), Error> {
+  let post = read_post(conn, object)?;
+  check_moderator(conn, actor, post.community_id)?;
+  transaction_with_retry(conn, || -> Result<(), diesel::result::Error> {
+    Post::update_locked(conn, post.id, locked)?;
+    let form = ModLockPostForm {
+      mod_user_id: actor.id,
+      post_id: post.id,
+      locked: Some(locked),
+    };
+    ModLockPost::create(conn, &form)?;
+    Ok(())
+  })?;
+  evict_apub_object("post", post.id);
+  info!(
+    "{} {} post {}",
+    actor.name,
+    if locked { "locked" } else { "unlocked" },
+    post.id
+  );
+  Ok(())
+}
+
+fn sticky_post(
+  conn: &PgConnection,
+  actor: &User_,
+  community: &Community,
+  object: &str,
+  stickied: bool,
+) -> Result<(), Error> {
+  let post = read_post(conn, object)?;
+  if post.community_id != community.id {
+    return Err(format_err!("{} isn't in {}", object, community.name));
+  }
+  check_moderator(conn, actor, community.id)?;
+  if post.stickied == stickied {
+    return Ok(());
+  }
+  let updated_post = transaction_with_retry(conn, || -> Result<Post, diesel::result::Error> {
+    let updated_post = Post::update_stickied(conn, post.id, stickied)?;
+    let form = ModStickyPostForm {
+      mod_user_id: actor.id,
+      post_id: post.id,
+      stickied: Some(stickied),
+    };
+    ModStickyPost::create(conn, &form)?;
+    Ok(updated_post)
+  })?;
+  evict_apub_object("post", post.id);
+  info!(
+    "{} {} post {}",
+    actor.name,
+    if stickied { "stickied" } else { "unstickied" },
+    post.id
+  );
+  send_featured(conn, community, &post, &updated_post);
+  Ok(())
+}
+
+/// Only moderators of a community can moderate it, and not while they're banned, from it or the
+/// site.
+fn check_moderator(conn: &PgConnection, actor: &User_, community_id: i32) -> Result<(), Error> {
+  let is_moderator = CommunityModeratorView::for_community(conn, community_id)?
+    .iter()
+    .any(|m| m.user_id == actor.id);
+  if !is_moderator
+    || actor.banned
+    || CommunityUserBanView::get(conn, actor.id, community_id).is_ok()
+  {
+    return Err(format_err!(
+      "{} can't moderate community {}",
+      actor.name,
+      community_id
+    ));
+  }
+  Ok(())
+}
+
+fn read_post(conn: &PgConnection, object: &str) -> Result<Post, Error> {
+  Ok(match local_object(object) {
+    Some(LocalObject::Post(post_id)) => Post::read(conn, post_id)?,
+    _ => Post::read_from_ap_id(conn, object)?,
+  })
+}
+
+/// The federated local community whose collection, such as its `/moderators`, is the target.
+fn target_community(conn: &PgConnection, target: &Value, collection: &str) -> Option<Community> {
+  let community_prefix = make_apub_endpoint("c", "");
+  let target = object_id(target)?;
+  if !target.starts_with(&community_prefix)
+    || !target.ends_with(collection)
+    || target.len() < community_prefix.len() + collection.len()
+  {
+    return None;
+  }
+  let name = &target[community_prefix.len()..target.len() - collection.len()];
+  Community::read_from_name(conn, name.to_owned())
+    .ok()
+    .filter(is_federated)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn test_is_moderation() {
+    assert!(is_moderation(&json!({"type": "Add"})));
+    assert!(is_moderation(&json!({"type": "Remove"})));
+    assert!(is_moderation(
+      &json!({"type": "Update", "object": {"type": "Page", "commentsEnabled": false}})
+    ));
+    assert!(!is_moderation(
+      &json!({"type": "Update", "object": {"type": "Page", "name": "An edit"}})
+    ));
+    assert!(!is_moderation(&json!({"type": "Create"})));
+  }
+}
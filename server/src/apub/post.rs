@@ -1,10 +1,34 @@
-use crate::apub::make_apub_endpoint;
+use crate::apub::cache::cached_apub_response;
+use crate::apub::vote::{post_votes, with_vote_collections};
+use crate::apub::{fetchable_post, make_apub_endpoint, PUBLIC};
+use crate::db::community::Community;
 use crate::db::post::Post;
-use crate::to_datetime_utc;
-use activitypub::{context, object::Page};
+use crate::db::post_attachment::{PostAttachment, PostAttachmentForm};
+use crate::db::{PostAttachmentKind, PostVisibility};
+use crate::{is_image_url, to_datetime_utc};
+use activitypub::{
+  context,
+  object::{properties::ObjectProperties, Audio, Image, Page, Video},
+};
+use actix_web::body::Body;
+use actix_web::web;
+use actix_web::web::Path;
+use actix_web::{HttpRequest, HttpResponse};
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use serde::Deserialize;
+use serde_json::Value;
 
 impl Post {
-  pub fn as_page(&self) -> Page {
+  /// The activitypub id of the post, which is its original one if it was received from elsewhere.
+  pub fn apub_id(&self) -> String {
+    self
+      .ap_id
+      .to_owned()
+      .unwrap_or_else(|| make_apub_endpoint("post", self.id))
+  }
+
+  pub fn as_page(&self, community: &Community, attachments: &[PostAttachment]) -> Page {
     let base_url = make_apub_endpoint("post", self.id);
     let mut page = Page::default();
 
@@ -12,6 +36,18 @@ impl Post {
     page.object_props.set_id_string(base_url).ok();
     page.object_props.set_name_string(self.name.to_owned()).ok();
 
+    // Unlisted posts are public, but not addressed to it, so they stay out of public timelines
+    let followers = format!("{}/followers", community.apub_id());
+    let (to, cc) = match PostVisibility::from_i16(self.visibility) {
+      PostVisibility::Public => (vec![PUBLIC.to_string()], vec![followers]),
+      PostVisibility::Unlisted => (vec![followers], vec![PUBLIC.to_string()]),
+      PostVisibility::FollowersOnly => (vec![followers], vec![]),
+    };
+    page.object_props.set_to_string_vec(to).ok();
+    if !cc.is_empty() {
+      page.object_props.set_cc_string_vec(cc).ok();
+    }
+
     if let Some(body) = &self.body {
       page.object_props.set_content_string(body.to_owned()).ok();
     }
@@ -20,6 +56,23 @@ impl Post {
       page.object_props.set_url_string(url.to_owned()).ok();
     }
 
+    if !attachments.is_empty() {
+      // Different types of objects can't be set together with the typed setters
+      page.object_props.attachment = Some(Value::Array(
+        attachments.iter().map(PostAttachment::as_object).collect(),
+      ));
+    } else if let Some(url) = &self.url {
+      // The image of an image post, with its alt text as the name
+      if is_image_url(url) {
+        let mut image = Image::default();
+        image.object_props.set_url_string(url.to_owned()).ok();
+        if let Some(alt_text) = &self.alt_text {
+          image.object_props.set_name_string(alt_text.to_owned()).ok();
+        }
+        page.object_props.set_attachment_object(image).ok();
+      }
+    }
+
     //page.object_props.set_attributed_to_string
 
     page
@@ -36,3 +89,173 @@ impl Post {
     page
   }
 }
+
+impl PostAttachment {
+  pub fn as_object(&self) -> Value {
+    let object = match PostAttachmentKind::from_i16(self.kind) {
+      PostAttachmentKind::Image => {
+        let mut image = Image::default();
+        self.set_object_props(&mut image.object_props);
+        serde_json::to_value(image)
+      }
+      PostAttachmentKind::Video => {
+        let mut video = Video::default();
+        self.set_object_props(&mut video.object_props);
+        serde_json::to_value(video)
+      }
+      PostAttachmentKind::Audio => {
+        let mut audio = Audio::default();
+        self.set_object_props(&mut audio.object_props);
+        serde_json::to_value(audio)
+      }
+    };
+    object.unwrap()
+  }
+
+  fn set_object_props(&self, props: &mut ObjectProperties) {
+    props.set_url_string(self.url.to_owned()).ok();
+    if let Some(mime_type) = &self.mime_type {
+      props.set_media_type_string(mime_type.to_owned()).ok();
+    }
+    if let Some(name) = &self.name {
+      props.set_name_string(name.to_owned()).ok();
+    }
+    if let Some(duration) = self.duration {
+      props.set_duration_string(format!("PT{}S", duration)).ok();
+    }
+  }
+}
+
+/// The images, videos and audio of a remote object, in order. Those are its attachments, plus the
+/// object itself if it's an `Audio` (a Funkwhale track), or its `audio` (a Castopod episode).
+/// Attachments of other types, Documents that aren't any of those, and ones without a url are left
+/// out.
+pub fn attachments_from_object(post_id: i32, object: &Value) -> Vec<PostAttachmentForm> {
+  let mut attachments = match &object["attachment"] {
+    Value::Array(attachments) => attachments.iter().collect::<Vec<_>>(),
+    Value::Null => vec![],
+    attachment => vec![attachment],
+  };
+  if object["type"] == "Audio" {
+    attachments.insert(0, object);
+  } else if object["audio"].is_object() {
+    attachments.insert(0, &object["audio"]);
+  }
+  attachments
+    .into_iter()
+    .filter_map(|attachment| {
+      let kind = match attachment["type"].as_str()? {
+        "Image" => PostAttachmentKind::Image,
+        "Video" => PostAttachmentKind::Video,
+        "Audio" => PostAttachmentKind::Audio,
+        // Mastodon's media are all Documents, of the kind their media type says
+        "Document" => match attachment["mediaType"].as_str()?.split('/').next()? {
+          "image" => PostAttachmentKind::Image,
+          "video" => PostAttachmentKind::Video,
+          "audio" => PostAttachmentKind::Audio,
+          _ => return None,
+        },
+        _ => return None,
+      };
+      // A url is either the link itself, or a Link object with an href and maybe the media type
+      let link = match &attachment["url"] {
+        Value::Array(links) => links.first()?,
+        link => link,
+      };
+      let url = match link {
+        Value::String(url) => url.to_owned(),
+        link => link.get("href")?.as_str()?.to_owned(),
+      };
+      let mime_type = attachment["mediaType"]
+        .as_str()
+        .or_else(|| link["mediaType"].as_str())
+        .map(str::to_owned);
+      Some((kind, url, mime_type, attachment))
+    })
+    .enumerate()
+    .map(
+      |(position, (kind, url, mime_type, attachment))| PostAttachmentForm {
+        post_id,
+        position: position as i32,
+        kind: kind as i16,
+        url,
+        mime_type,
+        name: attachment["name"].as_str().map(str::to_owned),
+        duration: parse_duration(&attachment["duration"]),
+      },
+    )
+    .collect()
+}
+
+/// A duration in seconds, from an xsd:duration like `PT1H2M3S`. Some instances send the seconds as
+/// a number instead.
+pub fn parse_duration(duration: &Value) -> Option<i32> {
+  if let Some(seconds) = duration.as_f64() {
+    return Some(seconds.round() as i32);
+  }
+  let duration = duration.as_str()?;
+  if !duration.starts_with("PT") {
+    return None;
+  }
+  let time = &duration[2..];
+  let mut seconds = 0.0;
+  let mut number = String::new();
+  for c in time.chars() {
+    match c {
+      '0'..='9' | '.' => number.push(c),
+      'H' | 'M' | 'S' => {
+        let unit = match c {
+          'H' => 3600.0,
+          'M' => 60.0,
+          _ => 1.0,
+        };
+        seconds += number.parse::<f64>().ok()? * unit;
+        number.clear();
+      }
+      _ => return None,
+    }
+  }
+  if number.is_empty() {
+    Some(seconds.round() as i32)
+  } else {
+    None
+  }
+}
+
+#[derive(Deserialize)]
+pub struct PostQuery {
+  post_id: i32,
+}
+
+pub async fn get_apub_post(
+  req: HttpRequest,
+  info: Path<PostQuery>,
+  db: web::Data<Pool<ConnectionManager<PgConnection>>>,
+) -> HttpResponse<Body> {
+  cached_apub_response(&req, || {
+    let conn = db.get().ok()?;
+    let (post, community) = fetchable_post(&conn, info.post_id)?;
+    let attachments = PostAttachment::list_for_post(&conn, post.id).ok()?;
+    let page = serde_json::to_value(post.as_page(&community, &attachments)).unwrap();
+    Some(with_vote_collections(page).to_string())
+  })
+}
+
+#[derive(Deserialize)]
+pub struct PostVotesQuery {
+  post_id: i32,
+  collection: String,
+}
+
+/// The `likes` or `dislikes` of a post.
+pub async fn get_apub_post_votes(
+  req: HttpRequest,
+  info: Path<PostVotesQuery>,
+  db: web::Data<Pool<ConnectionManager<PgConnection>>>,
+) -> HttpResponse<Body> {
+  cached_apub_response(&req, || {
+    let conn = db.get().ok()?;
+    let votes = post_votes(&conn, info.post_id, &info.collection)?;
+    Some(serde_json::to_string(&votes).unwrap())
+  })
+}
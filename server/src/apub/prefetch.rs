@@ -0,0 +1,283 @@
+//! Resolving many users and communities at once, by their activitypub ids or `@name@domain` and
+//! `!name@domain` handles, so clients showing remote content can have them all here in one
+//! request. The remote ones are fetched from their instances at the same time, on their own
+//! threads, and the users among them get their local copies.
+//!
+//! Remote communities aren't stored here, so they can't be resolved.
+
+use crate::apub::throttle::{throttled, Traffic};
+use crate::apub::user::remote_user_from_actor;
+use crate::apub::{fetch_remote_object, make_apub_endpoint, url_host};
+use crate::db::community::Community;
+use crate::db::user::User_;
+use crate::Settings;
+use diesel::PgConnection;
+use failure::Error;
+use isahc::prelude::*;
+use log::warn;
+use serde_json::Value;
+use std::thread;
+use std::time::Duration;
+
+/// The most actors one request can resolve.
+pub const MAX_PREFETCH_ACTORS: usize = 50;
+
+const WEBFINGER_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub enum ResolvedActor {
+  User(User_),
+  Community(Community),
+}
+
+/// A `name@domain` handle, of a community if it starts with `!`, or of a user.
+#[derive(Debug, PartialEq)]
+pub struct Handle {
+  pub name: String,
+  pub domain: String,
+  pub community: bool,
+}
+
+impl Handle {
+  pub fn parse(q: &str) -> Option<Handle> {
+    let (community, rest) = if q.starts_with('!') {
+      (true, &q[1..])
+    } else {
+      (false, q.trim_start_matches('@'))
+    };
+    let mut parts = rest.splitn(2, '@');
+    let name = parts.next()?;
+    let domain = parts.next()?;
+    if name.is_empty() || domain.is_empty() || domain.contains(|c| c == '@' || c == '/') {
+      return None;
+    }
+    Some(Handle {
+      name: name.to_owned(),
+      domain: domain.to_lowercase(),
+      community,
+    })
+  }
+
+  fn is_local(&self) -> bool {
+    let settings = Settings::get();
+    self.domain == settings.actor_domain() || self.domain == settings.hostname
+  }
+}
+
+/// What's left to do for an actor, after looking for it here.
+enum Lookup {
+  Found(ResolvedActor),
+  Fetch(String),
+  Webfinger(Handle),
+  Failed(&'static str),
+}
+
+/// Resolves each of the ids and handles to a user or a community, or to the error of the
+/// api that it failed with. The results are in the same order.
+pub fn prefetch_actors(conn: &PgConnection, qs: &[String]) -> Vec<Result<ResolvedActor, String>> {
+  let lookups = qs
+    .iter()
+    .map(|q| lookup(conn, q.trim()))
+    .collect::<Vec<Lookup>>();
+
+  // Only the fetching happens on the other threads, since the connection can't be shared
+  let mut fetches = Vec::new();
+  let mut results = Vec::new();
+  for lookup in lookups {
+    match lookup {
+      Lookup::Found(actor) => results.push(Some(Ok(actor))),
+      Lookup::Failed(error) => results.push(Some(Err(error.to_owned()))),
+      Lookup::Fetch(actor_id) => {
+        fetches.push((results.len(), thread::spawn(move || fetch_actor(actor_id))));
+        results.push(None);
+      }
+      Lookup::Webfinger(handle) => {
+        fetches.push((
+          results.len(),
+          thread::spawn(move || {
+            let actor_id = webfinger_actor_id(&handle)?;
+            fetch_actor(actor_id)
+          }),
+        ));
+        results.push(None);
+      }
+    }
+  }
+
+  for (index, fetch) in fetches {
+    let fetched = fetch
+      .join()
+      .unwrap_or_else(|_| Err(format_err!("The fetch panicked")));
+    results[index] = Some(match fetched {
+      Ok((actor_id, actor)) => store_actor(conn, &actor_id, &actor),
+      Err(e) => {
+        warn!("Couldn't prefetch an actor: {}", e);
+        Err("couldnt_resolve_actor".to_owned())
+      }
+    });
+  }
+
+  results.into_iter().flatten().collect()
+}
+
+fn lookup(conn: &PgConnection, q: &str) -> Lookup {
+  if q.starts_with("https://") {
+    let user_prefix = make_apub_endpoint("u", "");
+    let community_prefix = make_apub_endpoint("c", "");
+    if q.starts_with(&user_prefix) {
+      return local_user(conn, &q[user_prefix.len()..]);
+    }
+    if q.starts_with(&community_prefix) {
+      return local_community(conn, &q[community_prefix.len()..]);
+    }
+    return match User_::read_from_actor_id(conn, q) {
+      Ok(user) => Lookup::Found(ResolvedActor::User(user)),
+      Err(_e) => Lookup::Fetch(q.to_owned()),
+    };
+  }
+
+  match Handle::parse(q) {
+    Some(handle) if handle.is_local() && handle.community => local_community(conn, &handle.name),
+    Some(handle) if handle.is_local() => local_user(conn, &handle.name),
+    Some(handle) if handle.community => Lookup::Failed("remote_communities_not_supported"),
+    Some(handle) => Lookup::Webfinger(handle),
+    None => Lookup::Failed("invalid_actor"),
+  }
+}
+
+fn local_user(conn: &PgConnection, name: &str) -> Lookup {
+  match User_::read_from_name(conn, name.to_owned()) {
+    Ok(user) => Lookup::Found(ResolvedActor::User(user)),
+    Err(_e) => Lookup::Failed("couldnt_find_user"),
+  }
+}
+
+fn local_community(conn: &PgConnection, name: &str) -> Lookup {
+  match Community::read_from_name(conn, name.to_owned()) {
+    Ok(community) => Lookup::Found(ResolvedActor::Community(community)),
+    Err(_e) => Lookup::Failed("couldnt_find_community"),
+  }
+}
+
+fn fetch_actor(actor_id: String) -> Result<(String, Value), Error> {
+  let actor = fetch_remote_object(&actor_id)?;
+  Ok((actor_id, actor))
+}
+
+fn store_actor(
+  conn: &PgConnection,
+  actor_id: &str,
+  actor: &Value,
+) -> Result<ResolvedActor, String> {
+  // A handle's actor can be one that's already here
+  if let Ok(user) = User_::read_from_actor_id(conn, actor_id) {
+    return Ok(ResolvedActor::User(user));
+  }
+  match actor["type"].as_str() {
+    Some("Person") | Some("Service") | Some("Application") => {
+      match remote_user_from_actor(conn, actor_id, actor) {
+        Ok(user) => Ok(ResolvedActor::User(user)),
+        Err(e) => {
+          warn!("Couldn't create the remote user {}: {}", actor_id, e);
+          Err("couldnt_resolve_actor".to_owned())
+        }
+      }
+    }
+    Some("Group") => Err("remote_communities_not_supported".to_owned()),
+    _ => Err("couldnt_resolve_actor".to_owned()),
+  }
+}
+
+/// The activitypub id of a remote handle, from its instance's webfinger.
+fn webfinger_actor_id(handle: &Handle) -> Result<String, Error> {
+  let url = format!(
+    "https://{}/.well-known/webfinger?resource=acct:{}@{}",
+    handle.domain, handle.name, handle.domain
+  );
+  let text = throttled(Traffic::Fetch, &url, || {
+    let mut response = Request::get(&url)
+      .header("Accept", "application/jrd+json")
+      .timeout(WEBFINGER_TIMEOUT)
+      .body(())?
+      .send()?;
+    if !response.status().is_success() {
+      return Err(format_err!(
+        "Fetching {} failed with {}",
+        url,
+        response.status()
+      ));
+    }
+    Ok(response.text()?)
+  })?;
+  let webfinger: Value = serde_json::from_str(&text)?;
+  self_link(&webfinger)
+    .filter(|href| url_host(href).is_some())
+    .map(str::to_owned)
+    .ok_or_else(|| format_err!("{} has no activitypub actor", url))
+}
+
+/// The activitypub actor link of a webfinger response.
+fn self_link(webfinger: &Value) -> Option<&str> {
+  webfinger["links"]
+    .as_array()?
+    .iter()
+    .find(|link| {
+      link["rel"].as_str() == Some("self")
+        && link["type"].as_str().map_or(false, |t| {
+          t.starts_with("application/activity+json") || t.contains("activitystreams")
+        })
+    })
+    .and_then(|link| link["href"].as_str())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn test_parse_handle() {
+    assert_eq!(
+      Some(Handle {
+        name: "thom".into(),
+        domain: "remote.example".into(),
+        community: false,
+      }),
+      Handle::parse("@thom@Remote.Example")
+    );
+    assert_eq!(
+      Some(Handle {
+        name: "main".into(),
+        domain: "remote.example".into(),
+        community: true,
+      }),
+      Handle::parse("!main@remote.example")
+    );
+    assert_eq!(
+      Some(Handle {
+        name: "thom".into(),
+        domain: "remote.example".into(),
+        community: false,
+      }),
+      Handle::parse("thom@remote.example")
+    );
+    assert_eq!(None, Handle::parse("thom"));
+    assert_eq!(None, Handle::parse("@thom@"));
+    assert_eq!(None, Handle::parse("thom@remote.example/u/thom"));
+  }
+
+  #[test]
+  fn test_self_link() {
+    let webfinger = json!({
+      "subject": "acct:thom@remote.example",
+      "links": [
+        {"rel": "http://webfinger.net/rel/profile-page", "type": "text/html", "href": "https://remote.example/@thom"},
+        {"rel": "self", "type": "application/activity+json", "href": "https://remote.example/users/thom"},
+      ],
+    });
+    assert_eq!(
+      Some("https://remote.example/users/thom"),
+      self_link(&webfinger)
+    );
+    assert_eq!(None, self_link(&json!({"links": []})));
+  }
+}
@@ -0,0 +1,241 @@
+//! Limits on the requests federation makes to other instances: how many deliveries of activities
+//! and fetches of remote objects run at once, and how many requests a second any one instance
+//! gets. Requests over a limit wait for their turn, on the thread that makes them.
+//!
+//! What the limits are doing is counted, for the admin api.
+
+use crate::apub::url_host;
+use crate::Settings;
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The kinds of requests that are limited separately.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Traffic {
+  /// Activities posted to remote inboxes
+  Delivery,
+  /// Remote objects fetched, mostly to verify incoming activities
+  Fetch,
+}
+
+/// How much of a kind of request can run at once. A limit of 0 is no limit.
+struct Slots {
+  limit: usize,
+  used: Mutex<usize>,
+  freed: Condvar,
+}
+
+impl Slots {
+  fn new(limit: usize) -> Self {
+    Slots {
+      limit,
+      used: Mutex::new(0),
+      freed: Condvar::new(),
+    }
+  }
+
+  fn acquire(&self) -> SlotGuard {
+    let mut used = self.used.lock().unwrap();
+    while self.limit > 0 && *used >= self.limit {
+      used = self.freed.wait(used).unwrap();
+    }
+    *used += 1;
+    SlotGuard { slots: self }
+  }
+}
+
+struct SlotGuard<'a> {
+  slots: &'a Slots,
+}
+
+impl<'a> Drop for SlotGuard<'a> {
+  fn drop(&mut self) {
+    *self.slots.used.lock().unwrap() -= 1;
+    self.slots.freed.notify_one();
+  }
+}
+
+/// Spaces out the requests to each instance, so none gets more than `requests_per_second`. A rate
+/// of 0 is no limit.
+pub struct InstanceRateLimiter {
+  interval: Option<Duration>,
+  next_request: HashMap<String, Instant>,
+}
+
+impl InstanceRateLimiter {
+  pub fn new(requests_per_second: f64) -> Self {
+    let interval = if requests_per_second > 0.0 {
+      Some(Duration::from_micros(
+        (1_000_000.0 / requests_per_second) as u64,
+      ))
+    } else {
+      None
+    };
+    InstanceRateLimiter {
+      interval,
+      next_request: HashMap::new(),
+    }
+  }
+
+  /// Takes the next turn to send a request to a host, returning how long to wait for it.
+  pub fn reserve(&mut self, host: &str, now: Instant) -> Duration {
+    let interval = match self.interval {
+      Some(interval) => interval,
+      None => return Duration::from_secs(0),
+    };
+    // Turns long past don't count, so a quiet instance doesn't get a burst of them
+    self.next_request.retain(|_, next| *next > now);
+    let turn = match self.next_request.get(host) {
+      Some(next) => *next,
+      None => now,
+    };
+    self.next_request.insert(host.to_owned(), turn + interval);
+    turn - now
+  }
+}
+
+/// What the limits are doing right now, and since the server started.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct FederationMetrics {
+  pub max_concurrent_deliveries: usize,
+  pub max_concurrent_fetches: usize,
+  pub instance_requests_per_second: f64,
+  pub deliveries_in_flight: usize,
+  pub deliveries_waiting: usize,
+  pub deliveries_sent: usize,
+  pub deliveries_failed: usize,
+  pub fetches_in_flight: usize,
+  pub fetches_waiting: usize,
+  pub fetches_done: usize,
+  pub fetches_failed: usize,
+  /// The requests that had to wait for their instance's turn
+  pub instance_waits: usize,
+}
+
+#[derive(Default)]
+struct Counters {
+  in_flight: AtomicUsize,
+  waiting: AtomicUsize,
+  succeeded: AtomicUsize,
+  failed: AtomicUsize,
+}
+
+struct Throttle {
+  deliveries: Slots,
+  fetches: Slots,
+  instances: Mutex<InstanceRateLimiter>,
+  instance_requests_per_second: f64,
+  delivery_counters: Counters,
+  fetch_counters: Counters,
+  instance_waits: AtomicUsize,
+}
+
+lazy_static! {
+  static ref THROTTLE: Throttle = {
+    let limits = Settings::get().federation.limits;
+    Throttle {
+      deliveries: Slots::new(limits.max_concurrent_deliveries),
+      fetches: Slots::new(limits.max_concurrent_fetches),
+      instances: Mutex::new(InstanceRateLimiter::new(
+        limits.instance_requests_per_second,
+      )),
+      instance_requests_per_second: limits.instance_requests_per_second,
+      delivery_counters: Counters::default(),
+      fetch_counters: Counters::default(),
+      instance_waits: AtomicUsize::new(0),
+    }
+  };
+}
+
+/// Makes a request to `url` once there's room for it under the limits.
+pub fn throttled<T, F>(traffic: Traffic, url: &str, request: F) -> Result<T, Error>
+where
+  F: FnOnce() -> Result<T, Error>,
+{
+  let throttle = &*THROTTLE;
+  let (slots, counters) = match traffic {
+    Traffic::Delivery => (&throttle.deliveries, &throttle.delivery_counters),
+    Traffic::Fetch => (&throttle.fetches, &throttle.fetch_counters),
+  };
+
+  counters.waiting.fetch_add(1, Ordering::SeqCst);
+  let _slot = slots.acquire();
+  let wait = match url_host(url) {
+    Some(host) => throttle
+      .instances
+      .lock()
+      .unwrap()
+      .reserve(&host, Instant::now()),
+    None => Duration::from_secs(0),
+  };
+  if wait > Duration::from_secs(0) {
+    throttle.instance_waits.fetch_add(1, Ordering::SeqCst);
+    thread::sleep(wait);
+  }
+  counters.waiting.fetch_sub(1, Ordering::SeqCst);
+
+  counters.in_flight.fetch_add(1, Ordering::SeqCst);
+  let result = request();
+  counters.in_flight.fetch_sub(1, Ordering::SeqCst);
+  match result {
+    Ok(_) => counters.succeeded.fetch_add(1, Ordering::SeqCst),
+    Err(_) => counters.failed.fetch_add(1, Ordering::SeqCst),
+  };
+  result
+}
+
+pub fn federation_metrics() -> FederationMetrics {
+  let throttle = &*THROTTLE;
+  let deliveries = &throttle.delivery_counters;
+  let fetches = &throttle.fetch_counters;
+  FederationMetrics {
+    max_concurrent_deliveries: throttle.deliveries.limit,
+    max_concurrent_fetches: throttle.fetches.limit,
+    instance_requests_per_second: throttle.instance_requests_per_second,
+    deliveries_in_flight: deliveries.in_flight.load(Ordering::SeqCst),
+    deliveries_waiting: deliveries.waiting.load(Ordering::SeqCst),
+    deliveries_sent: deliveries.succeeded.load(Ordering::SeqCst),
+    deliveries_failed: deliveries.failed.load(Ordering::SeqCst),
+    fetches_in_flight: fetches.in_flight.load(Ordering::SeqCst),
+    fetches_waiting: fetches.waiting.load(Ordering::SeqCst),
+    fetches_done: fetches.succeeded.load(Ordering::SeqCst),
+    fetches_failed: fetches.failed.load(Ordering::SeqCst),
+    instance_waits: throttle.instance_waits.load(Ordering::SeqCst),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::InstanceRateLimiter;
+  use std::time::{Duration, Instant};
+
+  #[test]
+  fn test_instance_rate_limiter() {
+    let now = Instant::now();
+    let mut limiter = InstanceRateLimiter::new(2.0);
+    assert_eq!(Duration::from_secs(0), limiter.reserve("a.example", now));
+    assert_eq!(
+      Duration::from_millis(500),
+      limiter.reserve("a.example", now)
+    );
+    assert_eq!(
+      Duration::from_millis(1000),
+      limiter.reserve("a.example", now)
+    );
+    // Other instances have their own turns
+    assert_eq!(Duration::from_secs(0), limiter.reserve("b.example", now));
+
+    // Once the turns have passed, the next request doesn't wait
+    let later = now + Duration::from_secs(2);
+    assert_eq!(Duration::from_secs(0), limiter.reserve("a.example", later));
+
+    let mut unlimited = InstanceRateLimiter::new(0.0);
+    assert_eq!(Duration::from_secs(0), unlimited.reserve("a.example", now));
+    assert_eq!(Duration::from_secs(0), unlimited.reserve("a.example", now));
+  }
+}
@@ -1,14 +1,29 @@
-use crate::apub::make_apub_endpoint;
-use crate::db::establish_unpooled_connection;
+use crate::apub::cache::cached_apub_response;
+use crate::apub::{fetch_remote_object, make_apub_endpoint, shared_inbox, url_host};
 use crate::db::user::User_;
-use crate::to_datetime_utc;
+use crate::{naive_now, to_datetime_utc, Settings};
 use activitypub::{actor::Person, context};
 use actix_web::body::Body;
+use actix_web::web;
 use actix_web::web::Path;
-use actix_web::HttpResponse;
+use actix_web::{HttpRequest, HttpResponse};
+use chrono::Duration;
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use failure::Error;
+use log::{info, warn};
 use serde::Deserialize;
+use serde_json::{json, Value};
 
 impl User_ {
+  /// The activitypub id of the user, which for remote users is the one on their own instance.
+  pub fn apub_id(&self) -> String {
+    self
+      .actor_id
+      .to_owned()
+      .unwrap_or_else(|| make_apub_endpoint("u", &self.name))
+  }
+
   pub fn as_person(&self) -> Person {
     let base_url = make_apub_endpoint("u", &self.name);
     let mut person = Person::default();
@@ -54,6 +69,108 @@ impl User_ {
 
     person
   }
+
+  /// The person with the shared inbox, which the activitypub crate doesn't know about, so other
+  /// instances can deliver to this one.
+  pub fn as_person_json(&self) -> Value {
+    let mut person = serde_json::to_value(self.as_person()).unwrap();
+    if Settings::get().federation_enabled {
+      person["endpoints"] = json!({ "sharedInbox": shared_inbox() });
+    }
+    person
+  }
+}
+
+/// The user an actor id belongs to. Local users are found by name, and remote ones are created the
+/// first time they're seen, from the actor on their own instance.
+pub fn apub_user(conn: &PgConnection, actor_id: &str) -> Result<User_, Error> {
+  let local_prefix = make_apub_endpoint("u", "");
+  if actor_id.starts_with(&local_prefix) {
+    return Ok(User_::read_from_name(
+      conn,
+      actor_id[local_prefix.len()..].to_owned(),
+    )?);
+  }
+  if let Ok(user) = User_::read_from_actor_id(conn, actor_id) {
+    return Ok(user);
+  }
+  remote_user_from_actor(conn, actor_id, &fetch_remote_object(actor_id)?)
+}
+
+/// Creates the local copy of a remote user from their fetched actor.
+pub fn remote_user_from_actor(
+  conn: &PgConnection,
+  actor_id: &str,
+  actor: &Value,
+) -> Result<User_, Error> {
+  let domain = url_host(actor_id).ok_or_else(|| format_err!("Invalid actor {}", actor_id))?;
+  let username = actor["preferredUsername"]
+    .as_str()
+    .or_else(|| actor_id.trim_end_matches('/').rsplit('/').next())
+    .unwrap_or_default();
+  let user = User_::create_remote(conn, actor_id, &domain, username)?;
+  info!("Created remote user {} for {}", user.name, actor_id);
+  Ok(user)
+}
+
+/// Fetches a remote user's actor again, if the copy here is older than
+/// `federation.remote_actor_max_age` or a retry of a failed refresh is due. When fetching it fails
+/// the copy here is still returned, and the refresh is tried again later, after a minute and then
+/// twice as long each time, up to a day.
+pub fn refresh_remote_user(conn: &PgConnection, user: User_) -> User_ {
+  let actor_id = match &user.actor_id {
+    Some(actor_id) if refresh_due(&user) => actor_id.to_owned(),
+    _ => return user,
+  };
+  match fetch_remote_object(&actor_id) {
+    Ok(actor) => {
+      let display_name = actor["name"]
+        .as_str()
+        .map(|name| name.chars().take(20).collect::<String>())
+        .filter(|name| !name.trim().is_empty());
+      let icon = actor["icon"]["url"]
+        .as_str()
+        .or_else(|| actor["icon"].as_str())
+        .map(str::to_owned);
+      User_::update_refreshed(conn, user.id, display_name, icon).unwrap_or(user)
+    }
+    Err(e) => {
+      let failures = user.refresh_failures + 1;
+      let retry_at = naive_now() + retry_delay(failures);
+      warn!(
+        "Couldn't refresh {}, retrying at {}: {}",
+        actor_id, retry_at, e
+      );
+      User_::update_refresh_failed(conn, user.id, failures, retry_at).unwrap_or(user)
+    }
+  }
+}
+
+fn refresh_due(user: &User_) -> bool {
+  let now = naive_now();
+  match (user.refresh_retry_at, user.last_refreshed) {
+    (Some(retry_at), _) => retry_at <= now,
+    (None, Some(last_refreshed)) => {
+      now - last_refreshed > Duration::seconds(Settings::get().federation.remote_actor_max_age)
+    }
+    (None, None) => true,
+  }
+}
+
+/// How long to wait before trying a refresh again, after it failed `failures` times in a row.
+fn retry_delay(failures: i32) -> Duration {
+  let minutes = 1i64 << (failures - 1).max(0).min(11);
+  Duration::minutes(minutes.min(24 * 60))
+}
+
+/// Retries the failed refreshes that are due, a few at a time.
+pub fn retry_refreshes(conn: &PgConnection) -> Result<usize, Error> {
+  let users = User_::list_refresh_retries(conn, 20)?;
+  let retried = users.len();
+  for user in users {
+    refresh_remote_user(conn, user);
+  }
+  Ok(retried)
 }
 
 #[derive(Deserialize)]
@@ -61,14 +178,29 @@ pub struct UserQuery {
   user_name: String,
 }
 
-pub async fn get_apub_user(info: Path<UserQuery>) -> HttpResponse<Body> {
-  let connection = establish_unpooled_connection();
+pub async fn get_apub_user(
+  req: HttpRequest,
+  info: Path<UserQuery>,
+  db: web::Data<Pool<ConnectionManager<PgConnection>>>,
+) -> HttpResponse<Body> {
+  cached_apub_response(&req, || {
+    let conn = db.get().ok()?;
+    let user = User_::find_by_email_or_username(&conn, &info.user_name).ok()?;
+    Some(serde_json::to_string(&user.as_person_json()).unwrap())
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::retry_delay;
+  use chrono::Duration;
 
-  if let Ok(user) = User_::find_by_email_or_username(&connection, &info.user_name) {
-    HttpResponse::Ok()
-      .content_type("application/activity+json")
-      .body(serde_json::to_string(&user.as_person()).unwrap())
-  } else {
-    HttpResponse::NotFound().finish()
+  #[test]
+  fn test_retry_delay() {
+    assert_eq!(Duration::minutes(1), retry_delay(1));
+    assert_eq!(Duration::minutes(2), retry_delay(2));
+    assert_eq!(Duration::minutes(512), retry_delay(10));
+    assert_eq!(Duration::days(1), retry_delay(12));
+    assert_eq!(Duration::days(1), retry_delay(500));
   }
 }
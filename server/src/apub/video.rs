@@ -0,0 +1,289 @@
+//! Videos from PeerTube. They arrive in the shared inbox as `Create` activities, and become posts
+//! in the local communities they're addressed to, with the video embedded.
+//!
+//! The activities aren't verified, so the video and its account are fetched from where their ids
+//! say they're from, and the account has to be the one the video is attributed to.
+
+use crate::apub::post::parse_duration;
+use crate::apub::user::apub_user;
+use crate::apub::{fetch_remote_object, is_federated, make_apub_endpoint, url_host};
+use crate::db::community::Community;
+use crate::db::ids::PostId;
+use crate::db::post::{Post, PostForm};
+use crate::db::post_attachment::{PostAttachment, PostAttachmentForm};
+use crate::db::post_view::PostView;
+use crate::db::{transaction_with_retry, Crud, PostAttachmentKind};
+use crate::routes::index::escape;
+use crate::slur_check;
+use diesel::PgConnection;
+use failure::Error;
+use log::info;
+use serde_json::Value;
+
+/// Post titles are at most 200 characters.
+const MAX_NAME_LENGTH: usize = 200;
+
+/// Creates the post of a `Create` activity of a video, if the video is addressed to a federated
+/// local community. Videos that were already received are left alone.
+pub fn receive_create_video(
+  conn: &PgConnection,
+  activity: &Value,
+) -> Result<Option<PostView>, Error> {
+  let actor_id = match &activity["actor"] {
+    Value::String(id) => id.to_owned(),
+    actor => actor["id"]
+      .as_str()
+      .ok_or_else(|| format_err!("No actor"))?
+      .to_owned(),
+  };
+  let video_id = match &activity["object"] {
+    Value::String(id) => id.to_owned(),
+    object => object["id"]
+      .as_str()
+      .ok_or_else(|| format_err!("No video id"))?
+      .to_owned(),
+  };
+  if url_host(&video_id) != url_host(&actor_id) {
+    return Err(format_err!("{} isn't from {}", video_id, actor_id));
+  }
+  if Post::read_from_ap_id(conn, &video_id).is_ok() {
+    return Ok(None);
+  }
+
+  let video = fetch_remote_object(&video_id)?;
+  if video["type"] != "Video" {
+    return Err(format_err!("{} isn't a video", video_id));
+  }
+  if !attributed_to(&video).iter().any(|id| *id == actor_id) {
+    return Err(format_err!("{} isn't by {}", video_id, actor_id));
+  }
+
+  let community = match addressed_community(conn, &[activity, &video]) {
+    Some(community) => community,
+    None => return Ok(None),
+  };
+  let creator = apub_user(conn, &actor_id)?;
+  if creator.banned {
+    return Ok(None);
+  }
+
+  let form = match video_post_form(&video, creator.id, community.id) {
+    Some(form) => form,
+    None => return Ok(None),
+  };
+  let attachment = video_attachment(&video);
+  let post = transaction_with_retry(conn, || -> Result<Post, diesel::result::Error> {
+    let post = Post::create(conn, &form)?;
+    let post = Post::update_ap_id(conn, post.id, &video_id)?;
+    if let Some(attachment) = &attachment {
+      let attachment = PostAttachmentForm {
+        post_id: post.id,
+        ..attachment.to_owned()
+      };
+      PostAttachment::replace_for_post(conn, post.id, &[attachment])?;
+    }
+    Ok(post)
+  })?;
+  info!("Received video {} as post {}", video_id, post.id);
+
+  Ok(Some(PostView::read(conn, PostId(post.id), None)?))
+}
+
+/// The post of a video: its title, description, watch page and embedded player.
+pub fn video_post_form(video: &Value, creator_id: i32, community_id: i32) -> Option<PostForm> {
+  let name = video["name"]
+    .as_str()?
+    .chars()
+    .take(MAX_NAME_LENGTH)
+    .collect::<String>();
+  let description = video["content"].as_str().map(str::to_owned);
+  if slur_check(&name).is_err()
+    || description
+      .as_deref()
+      .map_or(false, |d| slur_check(d).is_err())
+  {
+    return None;
+  }
+
+  let links = links(&video["url"]);
+  let watch_page = links
+    .iter()
+    .find(|link| link["mediaType"] == "text/html")
+    .and_then(|link| link["href"].as_str())
+    .or_else(|| video["id"].as_str())?
+    .to_owned();
+  // PeerTube's player is at /videos/embed/{uuid}
+  let embed_html = match (url_host(&watch_page), video["uuid"].as_str()) {
+    (Some(host), Some(uuid)) => Some(format!(
+      "<iframe src=\"https://{}/videos/embed/{}\" title=\"{}\" allowfullscreen sandbox=\"allow-same-origin allow-scripts allow-popups\" width=\"560\" height=\"315\" frameborder=\"0\"></iframe>",
+      escape(&host),
+      escape(uuid),
+      escape(&name),
+    )),
+    _ => None,
+  };
+  // The largest thumbnail
+  let thumbnail_url = links_of(&video["icon"])
+    .into_iter()
+    .max_by_key(|icon| icon["width"].as_i64().unwrap_or(0))
+    .and_then(|icon| icon["url"].as_str())
+    .map(str::to_owned);
+
+  Some(PostForm {
+    name: name.to_owned(),
+    url: Some(watch_page),
+    body: description.to_owned(),
+    creator_id,
+    community_id,
+    removed: None,
+    deleted: None,
+    locked: None,
+    stickied: None,
+    updated: None,
+    nsfw: video["sensitive"].as_bool().unwrap_or(false),
+    embed_title: Some(name),
+    embed_description: description,
+    embed_html,
+    thumbnail_url,
+    alt_text: None,
+    visibility: None,
+    generated: None,
+    language_id: None,
+    scheduled_publish_time: None,
+  })
+}
+
+/// The video file of a video, with its length, for clients that play it themselves. The post id is
+/// filled in once there is one.
+fn video_attachment(video: &Value) -> Option<PostAttachmentForm> {
+  let file = links(&video["url"])
+    .into_iter()
+    .find(|link| link["mediaType"] == "video/mp4")?;
+  let duration = parse_duration(&video["duration"]);
+  Some(PostAttachmentForm {
+    post_id: 0,
+    position: 0,
+    kind: PostAttachmentKind::Video as i16,
+    url: file["href"].as_str()?.to_owned(),
+    mime_type: Some("video/mp4".into()),
+    name: video["name"].as_str().map(str::to_owned),
+    duration,
+  })
+}
+
+/// The links in a `url`, including those nested in a link's `tag`, which is where PeerTube puts the
+/// files of each resolution.
+fn links(url: &Value) -> Vec<&Value> {
+  links_of(url)
+    .into_iter()
+    .flat_map(|link| {
+      let mut all = vec![link];
+      all.extend(links_of(&link["tag"]));
+      all
+    })
+    .collect()
+}
+
+fn links_of(value: &Value) -> Vec<&Value> {
+  match value {
+    Value::Array(values) => values.iter().filter(|v| v.is_object()).collect(),
+    Value::Object(_) => vec![value],
+    _ => vec![],
+  }
+}
+
+/// The ids of the accounts and channels a video is attributed to.
+fn attributed_to(video: &Value) -> Vec<&str> {
+  match &video["attributedTo"] {
+    Value::String(id) => vec![id.as_str()],
+    Value::Array(values) => values
+      .iter()
+      .filter_map(|v| v.as_str().or_else(|| v["id"].as_str()))
+      .collect(),
+    value => value["id"].as_str().into_iter().collect(),
+  }
+}
+
+/// The first federated local community any of the objects are addressed to, by its actor or
+/// followers collection.
+fn addressed_community(conn: &PgConnection, objects: &[&Value]) -> Option<Community> {
+  let community_prefix = make_apub_endpoint("c", "");
+  objects
+    .iter()
+    .flat_map(|object| {
+      ["to", "cc", "audience"]
+        .iter()
+        .flat_map(move |field| match &object[*field] {
+          Value::String(id) => vec![id.as_str()],
+          Value::Array(ids) => ids.iter().filter_map(Value::as_str).collect(),
+          _ => vec![],
+        })
+    })
+    .filter(|id| id.starts_with(&community_prefix))
+    .filter_map(|id| {
+      let name = id[community_prefix.len()..].trim_end_matches("/followers");
+      Community::read_from_name(conn, name.to_owned()).ok()
+    })
+    .find(|community| is_federated(community) && !community.removed && !community.deleted)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn test_video_post_form() {
+    let video = json!({
+      "type": "Video",
+      "id": "https://videos.example/videos/watch/9c9de5e8",
+      "uuid": "9c9de5e8",
+      "name": "A <video>",
+      "content": "All about **cats**",
+      "sensitive": false,
+      "duration": "PT93S",
+      "icon": [
+        {"type": "Image", "url": "https://videos.example/small.jpg", "width": 280},
+        {"type": "Image", "url": "https://videos.example/large.jpg", "width": 850},
+      ],
+      "url": [
+        {"type": "Link", "mediaType": "text/html", "href": "https://videos.example/w/9c9de5e8"},
+        {
+          "type": "Link",
+          "mediaType": "application/x-mpegURL",
+          "href": "https://videos.example/master.m3u8",
+          "tag": [{"type": "Link", "mediaType": "video/mp4", "href": "https://videos.example/720.mp4"}],
+        },
+      ],
+      "attributedTo": [
+        {"type": "Person", "id": "https://videos.example/accounts/thom"},
+        "https://videos.example/video-channels/cats",
+      ],
+    });
+
+    let form = video_post_form(&video, 1, 2).unwrap();
+    assert_eq!("A <video>", form.name);
+    assert_eq!(Some("https://videos.example/w/9c9de5e8".into()), form.url);
+    assert_eq!(Some("All about **cats**".into()), form.body);
+    assert_eq!(
+      Some("https://videos.example/large.jpg".into()),
+      form.thumbnail_url
+    );
+    let embed_html = form.embed_html.unwrap();
+    assert!(embed_html.contains("src=\"https://videos.example/videos/embed/9c9de5e8\""));
+    assert!(embed_html.contains("title=\"A &lt;video&gt;\""));
+
+    let attachment = video_attachment(&video).unwrap();
+    assert_eq!("https://videos.example/720.mp4", attachment.url);
+    assert_eq!(Some(93), attachment.duration);
+
+    assert_eq!(
+      vec![
+        "https://videos.example/accounts/thom",
+        "https://videos.example/video-channels/cats"
+      ],
+      attributed_to(&video)
+    );
+    assert!(video_post_form(&json!({"type": "Video"}), 1, 2).is_none());
+  }
+}
@@ -0,0 +1,386 @@
+//! Votes on posts and comments. Each one has a `likes` and a `dislikes` collection of who voted on
+//! it, with the count of them. Voters who hide their activity are counted, but left out of the
+//! items, and there's no count when the site hides scores.
+//!
+//! Remote users vote with `Like` and `Dislike` activities in the shared inbox, and take their vote
+//! back with an `Undo` of one. The activities aren't verified, so each one is fetched again from
+//! its actor's instance, along with the vote an `Undo` undoes when it only has its id. Receiving
+//! one twice changes nothing, an `Undo` only takes back a vote that's still the one it undoes, and
+//! a vote replaces the other one, so the scores always come out the same as the votes.
+
+use crate::apub::user::apub_user;
+use crate::apub::{federated_community, fetch_remote_object, local_object, make_apub_endpoint};
+use crate::apub::{fetchable_post, object_id, url_host, LocalObject};
+use crate::db::comment::{Comment, CommentLike, CommentLikeForm};
+use crate::db::community_view::CommunityUserBanView;
+use crate::db::post::{Post, PostLike, PostLikeForm};
+use crate::db::site::Site;
+use crate::db::site_view::SiteView;
+use crate::db::user::User_;
+use crate::db::{transaction_with_retry, Crud, Likeable};
+use activitypub::collection::OrderedCollection;
+use activitypub::context;
+use diesel::PgConnection;
+use failure::Error;
+use log::info;
+use serde_json::Value;
+
+/// The score of the votes in a collection, from its name.
+pub fn collection_score(collection: &str) -> Option<i16> {
+  match collection {
+    "likes" => Some(1),
+    "dislikes" => Some(-1),
+    _ => None,
+  }
+}
+
+/// Adds the `likes` and `dislikes` collections to a page or note.
+pub fn with_vote_collections(mut object: Value) -> Value {
+  if let Some(id) = object["id"].as_str().map(str::to_owned) {
+    object["likes"] = Value::String(format!("{}/likes", id));
+    object["dislikes"] = Value::String(format!("{}/dislikes", id));
+  }
+  object
+}
+
+/// The voters of a collection, by their activitypub ids, newest vote first.
+pub fn votes_as_collection(id: String, voters: &[User_], hide_scores: bool) -> OrderedCollection {
+  let mut collection = OrderedCollection::default();
+  collection.object_props.set_context_object(context()).ok();
+  collection.object_props.set_id_string(id).ok();
+
+  let items = if hide_scores {
+    vec![]
+  } else {
+    collection
+      .collection_props
+      .set_total_items_u64(voters.len() as u64)
+      .ok();
+    voters
+      .iter()
+      .filter(|voter| !voter.hide_activity)
+      .map(User_::apub_id)
+      .collect()
+  };
+  collection
+    .collection_props
+    .set_items_string_vec(items)
+    .unwrap();
+  collection
+}
+
+/// The likes or dislikes of a post, unless it's followers only or in a community that isn't
+/// federated.
+pub fn post_votes(
+  conn: &PgConnection,
+  post_id: i32,
+  collection: &str,
+) -> Option<OrderedCollection> {
+  let score = collection_score(collection)?;
+  let (post, _) = fetchable_post(conn, post_id)?;
+  let voters = PostLike::voters(conn, post.id, score).ok()?;
+  let hide_scores = Site::hides_scores_from(conn, None).ok()?;
+  let id = format!("{}/{}", make_apub_endpoint("post", post.id), collection);
+  Some(votes_as_collection(id, &voters, hide_scores))
+}
+
+/// The likes or dislikes of a comment, like `post_votes`.
+pub fn comment_votes(
+  conn: &PgConnection,
+  comment_id: i32,
+  collection: &str,
+) -> Option<OrderedCollection> {
+  let score = collection_score(collection)?;
+  let comment = Comment::read(conn, comment_id).ok()?;
+  fetchable_post(conn, comment.post_id)?;
+  let voters = CommentLike::voters(conn, comment.id, score).ok()?;
+  let hide_scores = Site::hides_scores_from(conn, None).ok()?;
+  let id = format!(
+    "{}/{}",
+    make_apub_endpoint("comment", comment.id),
+    collection
+  );
+  Some(votes_as_collection(id, &voters, hide_scores))
+}
+
+/// Whether an activity is a vote, or the `Undo` of one, from what it looks like before it's fetched
+/// again. An `Undo` with only the id of what it undoes might be of a vote, which is found out once
+/// that's fetched.
+pub fn is_vote(activity: &Value) -> bool {
+  match activity["type"].as_str() {
+    Some("Like") | Some("Dislike") => true,
+    Some("Undo") => match &activity["object"] {
+      Value::String(_) => true,
+      object => match object["type"].as_str() {
+        Some("Like") | Some("Dislike") => true,
+        _ => false,
+      },
+    },
+    _ => false,
+  }
+}
+
+/// A vote, or the `Undo` of one.
+#[derive(Debug, PartialEq)]
+pub struct Vote<'a> {
+  pub actor_id: &'a str,
+  pub object_id: &'a str,
+  pub score: i16,
+  pub undo: bool,
+}
+
+/// The vote of a `Like`, `Dislike`, or `Undo` of one. The vote of an `Undo` has to be in it, by the
+/// same actor.
+pub fn vote_of(activity: &Value) -> Option<Vote> {
+  let actor_id = object_id(&activity["actor"])?;
+  let (vote, undo) = match activity["type"].as_str()? {
+    "Undo" => (&activity["object"], true),
+    _ => (activity, false),
+  };
+  let score = match vote["type"].as_str()? {
+    "Like" => 1,
+    "Dislike" => -1,
+    _ => return None,
+  };
+  if undo && object_id(&vote["actor"])? != actor_id {
+    return None;
+  }
+  Some(Vote {
+    actor_id,
+    object_id: object_id(&vote["object"])?,
+    score,
+    undo,
+  })
+}
+
+pub fn receive_vote(conn: &PgConnection, activity: &Value) -> Result<(), Error> {
+  let activity_id = activity["id"]
+    .as_str()
+    .ok_or_else(|| format_err!("No activity id"))?;
+  let mut activity = fetch_remote_object(activity_id)?;
+  if activity["type"] == "Undo" {
+    if let Some(undone_id) = activity["object"].as_str().map(str::to_owned) {
+      activity["object"] = fetch_remote_object(&undone_id)?;
+    }
+    // Undos of follows and the rest are left alone
+    if !is_vote(&activity) {
+      return Ok(());
+    }
+  }
+  let vote = vote_of(&activity).ok_or_else(|| format_err!("{} isn't a vote", activity_id))?;
+  if url_host(vote.actor_id) != url_host(activity_id) {
+    return Err(format_err!("{} isn't from {}", activity_id, vote.actor_id));
+  }
+
+  let (post, comment) = match local_object(vote.object_id) {
+    Some(LocalObject::Comment(comment_id)) => {
+      let comment = Comment::read(conn, comment_id)?;
+      (Post::read(conn, comment.post_id)?, Some(comment))
+    }
+    Some(LocalObject::Post(post_id)) => (Post::read(conn, post_id)?, None),
+    None => (Post::read_from_ap_id(conn, vote.object_id)?, None),
+  };
+  if federated_community(conn, post.community_id).is_none() {
+    return Err(format_err!("{} isn't federated", vote.object_id));
+  }
+
+  let voter = apub_user(conn, vote.actor_id)?;
+  // Votes can always be taken back, but not added while they couldn't be here
+  if !vote.undo
+    && (voter.banned
+      || CommunityUserBanView::get(conn, voter.id, post.community_id).is_ok()
+      || (vote.score == -1 && !SiteView::read(conn)?.enable_downvotes))
+  {
+    return Ok(());
+  }
+
+  let changed = transaction_with_retry(conn, || -> Result<bool, diesel::result::Error> {
+    match &comment {
+      Some(comment) => {
+        let form = CommentLikeForm {
+          user_id: voter.id,
+          comment_id: comment.id,
+          post_id: comment.post_id,
+          score: vote.score,
+        };
+        let current = CommentLike::user_score(conn, comment.id, voter.id)?;
+        apply_vote(
+          current,
+          &vote,
+          || CommentLike::remove(conn, &form),
+          || CommentLike::like(conn, &form).map(|_| ()),
+        )
+      }
+      None => {
+        let form = PostLikeForm {
+          post_id: post.id,
+          user_id: voter.id,
+          score: vote.score,
+        };
+        let current = PostLike::user_score(conn, post.id, voter.id)?;
+        apply_vote(
+          current,
+          &vote,
+          || PostLike::remove(conn, &form),
+          || PostLike::like(conn, &form).map(|_| ()),
+        )
+      }
+    }
+  })?;
+  if changed {
+    info!(
+      "{} {} {}",
+      voter.name,
+      match (vote.undo, vote.score) {
+        (true, _) => "took back their vote on",
+        (false, 1) => "liked",
+        _ => "disliked",
+      },
+      vote.object_id
+    );
+  }
+  Ok(())
+}
+
+/// Changes a user's current vote to what a vote says, and whether that changed anything.
+fn apply_vote<R, L>(
+  current: Option<i16>,
+  vote: &Vote,
+  remove: R,
+  like: L,
+) -> Result<bool, diesel::result::Error>
+where
+  R: FnOnce() -> Result<usize, diesel::result::Error>,
+  L: FnOnce() -> Result<(), diesel::result::Error>,
+{
+  match (vote.undo, current == Some(vote.score)) {
+    (true, true) => {
+      remove()?;
+      Ok(true)
+    }
+    (false, false) => {
+      remove()?;
+      like()?;
+      Ok(true)
+    }
+    _ => Ok(false),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+  use std::cell::RefCell;
+
+  #[test]
+  fn test_vote_of() {
+    let like = json!({
+      "id": "https://remote.example/activities/like/1",
+      "type": "Like",
+      "actor": "https://remote.example/u/thom",
+      "object": "https://local.example/federation/comment/7",
+    });
+    assert!(is_vote(&like));
+    assert_eq!(
+      Some(Vote {
+        actor_id: "https://remote.example/u/thom",
+        object_id: "https://local.example/federation/comment/7",
+        score: 1,
+        undo: false,
+      }),
+      vote_of(&like)
+    );
+
+    let undo = json!({
+      "id": "https://remote.example/activities/undo/1",
+      "type": "Undo",
+      "actor": {"id": "https://remote.example/u/thom"},
+      "object": {
+        "type": "Dislike",
+        "actor": "https://remote.example/u/thom",
+        "object": {"id": "https://local.example/federation/post/62"},
+      },
+    });
+    assert!(is_vote(&undo));
+    assert_eq!(
+      Some(Vote {
+        actor_id: "https://remote.example/u/thom",
+        object_id: "https://local.example/federation/post/62",
+        score: -1,
+        undo: true,
+      }),
+      vote_of(&undo)
+    );
+
+    // Only the voter can take a vote back
+    let mut someone_elses = undo.clone();
+    someone_elses["object"]["actor"] = json!("https://remote.example/u/other");
+    assert_eq!(None, vote_of(&someone_elses));
+
+    let undo_follow = json!({"type": "Undo", "object": {"type": "Follow"}});
+    assert!(!is_vote(&undo_follow));
+    // Fetched to find out what it undoes
+    let undo_by_id = json!({"type": "Undo", "object": "https://remote.example/activities/like/1"});
+    assert!(is_vote(&undo_by_id));
+    assert_eq!(None, vote_of(&undo_by_id));
+    assert!(!is_vote(&json!({"type": "Announce"})));
+  }
+
+  #[test]
+  fn test_apply_vote() {
+    let vote = |score, undo| Vote {
+      actor_id: "https://remote.example/u/thom",
+      object_id: "https://local.example/federation/post/62",
+      score,
+      undo,
+    };
+    let applied = |current, vote: Vote| {
+      let calls = RefCell::new(vec![]);
+      let changed = apply_vote(
+        current,
+        &vote,
+        || {
+          calls.borrow_mut().push("remove");
+          Ok(1)
+        },
+        || {
+          calls.borrow_mut().push("like");
+          Ok(())
+        },
+      )
+      .unwrap();
+      (changed, calls.into_inner())
+    };
+
+    assert_eq!(
+      (true, vec!["remove", "like"]),
+      applied(None, vote(1, false))
+    );
+    assert_eq!((false, vec![]), applied(Some(1), vote(1, false)));
+    // A like replaces a dislike
+    assert_eq!(
+      (true, vec!["remove", "like"]),
+      applied(Some(-1), vote(1, false))
+    );
+    assert_eq!((true, vec!["remove"]), applied(Some(1), vote(1, true)));
+    assert_eq!((false, vec![]), applied(None, vote(1, true)));
+    // Undoing a like doesn't take back the dislike that replaced it
+    assert_eq!((false, vec![]), applied(Some(-1), vote(1, true)));
+  }
+
+  #[test]
+  fn test_with_vote_collections() {
+    let note = with_vote_collections(json!({"id": "https://local.example/federation/comment/7"}));
+    assert_eq!(
+      "https://local.example/federation/comment/7/likes",
+      note["likes"]
+    );
+    assert_eq!(
+      "https://local.example/federation/comment/7/dislikes",
+      note["dislikes"]
+    );
+    assert_eq!(Some(-1), collection_score("dislikes"));
+    assert_eq!(None, collection_score("shares"));
+  }
+}
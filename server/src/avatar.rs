@@ -0,0 +1,125 @@
+//! The generated avatars of users and communities without one of their own.
+//!
+//! An avatar is an identicon made from the hash of the user or community name: a symmetric 5x5
+//! pattern in one color, so that the same name always gets the same picture. They're served as
+//! svg from `/avatars/{kind}/{name}.svg`, and the views have the url of the generated one in place
+//! of a missing avatar, or of a community's icon.
+
+use crate::settings::Settings;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sha2::{Digest, Sha256};
+
+/// What the generated avatars are for, the first part of their path
+pub const AVATAR_KINDS: [&str; 2] = ["user", "community"];
+
+/// How many cells across and down the pattern is
+const GRID_SIZE: usize = 5;
+
+/// The characters of names that are kept as they are in the path
+const NAME: &AsciiSet = &NON_ALPHANUMERIC.remove(b'_').remove(b'-').remove(b'.');
+
+/// The url of a user's generated avatar.
+pub fn default_user_avatar(name: &str) -> String {
+  default_avatar("user", name)
+}
+
+/// The url of a community's generated avatar.
+pub fn default_community_avatar(name: &str) -> String {
+  default_avatar("community", name)
+}
+
+fn default_avatar(kind: &str, name: &str) -> String {
+  format!(
+    "https://{}/avatars/{}/{}.svg",
+    Settings::get().hostname,
+    kind,
+    utf8_percent_encode(name, NAME)
+  )
+}
+
+/// Gives an avatar that's missing the url of the user's generated one.
+pub fn fill_default_avatar(avatar: &mut Option<String>, name: &str) {
+  if avatar.is_none() {
+    *avatar = Some(default_user_avatar(name));
+  }
+}
+
+/// The svg of the identicon of a user or community name.
+pub fn identicon(kind: &str, name: &str) -> String {
+  let mut hasher = Sha256::new();
+  hasher.input(kind.as_bytes());
+  hasher.input(b":");
+  hasher.input(name.as_bytes());
+  let hash = hasher.result();
+
+  let hue = u16::from_be_bytes([hash[0], hash[1]]) % 360;
+  let saturation = 45 + hash[2] % 30;
+  let lightness = 45 + hash[3] % 15;
+
+  // The left columns and the middle one are picked from the hash, the right ones mirror them
+  let mut cells = String::new();
+  let columns = (GRID_SIZE + 1) / 2;
+  for row in 0..GRID_SIZE {
+    for column in 0..columns {
+      let bit = row * columns + column;
+      if hash[4 + bit / 8] & (1 << (bit % 8)) == 0 {
+        continue;
+      }
+      cells.push_str(&cell(column, row));
+      let mirrored = GRID_SIZE - 1 - column;
+      if mirrored != column {
+        cells.push_str(&cell(mirrored, row));
+      }
+    }
+  }
+
+  format!(
+    "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {size} {size}\" \
+     width=\"120\" height=\"120\" shape-rendering=\"crispEdges\">\
+     <rect width=\"{size}\" height=\"{size}\" fill=\"#f0f0f0\"/>\
+     <g fill=\"hsl({}, {}%, {}%)\">{}</g></svg>",
+    hue,
+    saturation,
+    lightness,
+    cells,
+    size = GRID_SIZE + 1,
+  )
+}
+
+/// A cell of the pattern, which has half a cell of margin around it.
+fn cell(column: usize, row: usize) -> String {
+  format!(
+    "<rect x=\"{}.5\" y=\"{}.5\" width=\"1\" height=\"1\"/>",
+    column, row
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_identicon() {
+    let avatar = identicon("user", "thommy");
+    assert_eq!(avatar, identicon("user", "thommy"));
+    assert_ne!(avatar, identicon("user", "thommy_2"));
+    assert_ne!(avatar, identicon("community", "thommy"));
+    assert!(avatar.starts_with("<svg ") && avatar.ends_with("</svg>"));
+
+    let hostname = Settings::get().hostname;
+    assert_eq!(
+      format!("https://{}/avatars/user/a_b%20c.svg", hostname),
+      default_user_avatar("a_b c")
+    );
+    assert_eq!(
+      format!("https://{}/avatars/community/main.svg", hostname),
+      default_community_avatar("main")
+    );
+    let mut avatar = None;
+    fill_default_avatar(&mut avatar, "thommy");
+    assert_eq!(Some(default_user_avatar("thommy")), avatar);
+    let mut avatar = Some("https://example.com/me.png".to_string());
+    fill_default_avatar(&mut avatar, "thommy");
+    assert_eq!(Some("https://example.com/me.png".to_string()), avatar);
+  }
+}
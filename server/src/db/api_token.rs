@@ -0,0 +1,211 @@
+use super::*;
+use crate::naive_now;
+use crate::schema::api_token;
+use crate::schema::api_token::dsl::*;
+
+/// What an api token may be used for. Account and site settings, and the tokens themselves, can
+/// only be changed by logging in.
+#[derive(EnumString, ToString, Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+  /// Fetching anything, including the user's own inbox
+  Read,
+  /// Posting, commenting, voting, saving and messaging
+  Write,
+  /// Removing, locking, stickying and banning, where the user is a mod
+  Moderate,
+}
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "api_token"]
+pub struct ApiToken {
+  pub id: i32,
+  pub user_id: i32,
+  pub name: String,
+  pub scopes: Vec<String>,
+  pub rate_limit_multiplier: f32,
+  pub last_used: Option<chrono::NaiveDateTime>,
+  pub published: chrono::NaiveDateTime,
+  /// The app the token was given to, for tokens from the oauth flow
+  pub oauth_client_id: Option<i32>,
+  /// The admin viewing the site as the user, for impersonation tokens
+  pub impersonator_id: Option<i32>,
+  pub expires: Option<chrono::NaiveDateTime>,
+  /// What the device the token is used on is called, like "Work laptop"
+  pub device_name: Option<String>,
+  /// The client the token is used with, like "Lemmy Android 0.3"
+  pub client_app: Option<String>,
+  /// The network of the IP the token was last used from, like `203.0.113.0/24`
+  pub last_ip_network: Option<String>,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "api_token"]
+pub struct ApiTokenForm {
+  pub user_id: i32,
+  pub name: String,
+  pub scopes: Vec<String>,
+  pub rate_limit_multiplier: f32,
+  pub oauth_client_id: Option<i32>,
+  pub impersonator_id: Option<i32>,
+  pub expires: Option<chrono::NaiveDateTime>,
+  pub device_name: Option<String>,
+  pub client_app: Option<String>,
+}
+
+impl Crud<ApiTokenForm> for ApiToken {
+  fn read(conn: &PgConnection, api_token_id: i32) -> Result<Self, Error> {
+    api_token.find(api_token_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, api_token_id: i32) -> Result<usize, Error> {
+    diesel::delete(api_token.find(api_token_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &ApiTokenForm) -> Result<Self, Error> {
+    insert_into(api_token).values(form).get_result::<Self>(conn)
+  }
+
+  fn update(conn: &PgConnection, api_token_id: i32, form: &ApiTokenForm) -> Result<Self, Error> {
+    diesel::update(api_token.find(api_token_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl ApiToken {
+  /// The user's tokens, leaving out the ones admins are viewing the site as them with.
+  pub fn list_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    api_token
+      .filter(user_id.eq(for_user_id))
+      .filter(impersonator_id.is_null())
+      .order_by(published.desc())
+      .load::<Self>(conn)
+  }
+
+  /// Marks a token used now, from `ip_network` if the request's IP is known.
+  pub fn mark_used(
+    conn: &PgConnection,
+    api_token_id: i32,
+    ip_network: Option<String>,
+  ) -> Result<usize, Error> {
+    let token = api_token.find(api_token_id);
+    match ip_network {
+      Some(ip_network) => diesel::update(token)
+        .set((last_used.eq(naive_now()), last_ip_network.eq(ip_network)))
+        .execute(conn),
+      None => diesel::update(token)
+        .set(last_used.eq(naive_now()))
+        .execute(conn),
+    }
+  }
+
+  pub fn update_rate_limit_multiplier(
+    conn: &PgConnection,
+    api_token_id: i32,
+    multiplier: f32,
+  ) -> Result<Self, Error> {
+    diesel::update(api_token.find(api_token_id))
+      .set(rate_limit_multiplier.eq(multiplier))
+      .get_result::<Self>(conn)
+  }
+
+  /// The unexpired impersonation tokens.
+  pub fn list_impersonations(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    api_token
+      .filter(impersonator_id.is_not_null())
+      .filter(expires.gt(naive_now()))
+      .order_by(published.desc())
+      .load::<Self>(conn)
+  }
+
+  pub fn delete_expired(conn: &PgConnection) -> Result<usize, Error> {
+    diesel::delete(api_token.filter(expires.le(naive_now()))).execute(conn)
+  }
+
+  pub fn is_expired(&self) -> bool {
+    self.expires.map_or(false, |e| e <= naive_now())
+  }
+
+  pub fn has_scope(&self, scope: TokenScope) -> bool {
+    self.scopes.iter().any(|s| *s == scope.to_string())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "token_bot".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let form = ApiTokenForm {
+      user_id: inserted_user.id,
+      name: "feed bot".into(),
+      scopes: vec![TokenScope::Read.to_string(), TokenScope::Write.to_string()],
+      rate_limit_multiplier: 1.0,
+      oauth_client_id: None,
+      impersonator_id: None,
+      expires: None,
+      device_name: Some("Work laptop".into()),
+      client_app: None,
+    };
+    let inserted_token = ApiToken::create(&conn, &form).unwrap();
+    assert_eq!(vec!["read", "write"], inserted_token.scopes);
+    assert!(inserted_token.has_scope(TokenScope::Write));
+    assert!(!inserted_token.has_scope(TokenScope::Moderate));
+    assert!(inserted_token.last_used.is_none());
+
+    ApiToken::mark_used(&conn, inserted_token.id, Some("203.0.113.0/24".into())).unwrap();
+    // Requests without a known IP keep the last one
+    ApiToken::mark_used(&conn, inserted_token.id, None).unwrap();
+    let doubled = ApiToken::update_rate_limit_multiplier(&conn, inserted_token.id, 2.0).unwrap();
+    assert!(doubled.last_used.is_some());
+    assert_eq!(Some("203.0.113.0/24".into()), doubled.last_ip_network);
+    assert_eq!(Some("Work laptop".into()), doubled.device_name);
+    assert!((doubled.rate_limit_multiplier - 2.0).abs() < f32::EPSILON);
+
+    let listed = ApiToken::list_for_user(&conn, inserted_user.id).unwrap();
+    assert_eq!(vec![doubled], listed);
+
+    // The token's jwt names it
+    let jwt = inserted_user.api_token_jwt(inserted_token.id);
+    let claims = Claims::decode(&jwt).unwrap().claims;
+    assert_eq!(Some(inserted_token.id), claims.token_id);
+    assert_eq!(
+      None,
+      Claims::decode(&inserted_user.jwt())
+        .unwrap()
+        .claims
+        .token_id
+    );
+
+    let num_deleted = ApiToken::delete(&conn, inserted_token.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+    assert_eq!(1, num_deleted);
+  }
+}
@@ -1,6 +1,8 @@
 use super::post::Post;
+use super::user::User_;
 use super::*;
 use crate::schema::{comment, comment_like, comment_saved};
+use diesel::sql_types::{Array, BigInt, Bool, Integer, Nullable};
 
 // WITH RECURSIVE MyTree AS (
 //     SELECT * FROM comment WHERE parent_id IS NULL
@@ -23,6 +25,10 @@ pub struct Comment {
   pub published: chrono::NaiveDateTime,
   pub updated: Option<chrono::NaiveDateTime>,
   pub deleted: bool,
+  /// Made by a bot or generated by AI
+  pub generated: bool,
+  /// The `Language` it's in
+  pub language_id: Option<i32>,
 }
 
 #[derive(Insertable, AsChangeset, Clone)]
@@ -36,6 +42,8 @@ pub struct CommentForm {
   pub read: Option<bool>,
   pub updated: Option<chrono::NaiveDateTime>,
   pub deleted: Option<bool>,
+  pub generated: Option<bool>,
+  pub language_id: Option<i32>,
 }
 
 impl Crud<CommentForm> for Comment {
@@ -68,6 +76,160 @@ impl Crud<CommentForm> for Comment {
   }
 }
 
+/// How many replies a comment has, at any depth.
+#[derive(QueryableByName, PartialEq, Debug, Serialize, Deserialize)]
+pub struct CommentChildCount {
+  #[sql_type = "Integer"]
+  pub comment_id: i32,
+  #[sql_type = "BigInt"]
+  pub child_count: i64,
+}
+
+/// A comment in a subtree, from `Comment::tree`.
+#[derive(QueryableByName, PartialEq, Debug)]
+pub struct CommentTreeNode {
+  #[sql_type = "Integer"]
+  pub id: i32,
+  /// How far under the subtree's parent it is, with 0 for its direct replies
+  #[sql_type = "Integer"]
+  pub depth: i32,
+  /// Whether it has replies that were left out of the subtree
+  #[sql_type = "Bool"]
+  pub has_more_children: bool,
+}
+
+impl Comment {
+  /// The replies to a comment of a post, or its top level comments without one, down to
+  /// `max_depth` levels, and at most `max_children` of each comment's replies, the hottest ones.
+  /// They're in the order they're shown in: each comment right before its replies.
+  pub fn tree(
+    conn: &PgConnection,
+    post_id: i32,
+    parent_id: Option<i32>,
+    max_depth: i32,
+    max_children: i64,
+  ) -> Result<Vec<CommentTreeNode>, Error> {
+    // The aggregates don't have the comments made since they were last refreshed yet, which are
+    // the newest, so those go first
+    sql_query(
+      "with recursive ranked as (
+        select c.id, c.parent_id, row_number() over (
+          partition by c.parent_id order by ca.hot_rank desc nulls first, c.published desc
+        ) as rank
+        from comment c left join comment_aggregates_mview ca on ca.id = c.id
+        where c.post_id = $1
+      ), tree(id, depth, path) as (
+        select r.id, 0, array[r.rank] from ranked r
+        where r.parent_id is not distinct from $2 and r.rank <= $4
+        union all
+        select r.id, t.depth + 1, t.path || r.rank from ranked r join tree t on r.parent_id = t.id
+        where t.depth + 1 < $3 and r.rank <= $4
+      )
+      select t.id, t.depth, exists (
+        select 1 from ranked r where r.parent_id = t.id and (t.depth + 1 >= $3 or r.rank > $4)
+      ) as has_more_children
+      from tree t order by t.path",
+    )
+    .bind::<Integer, _>(post_id)
+    .bind::<Nullable<Integer>, _>(parent_id)
+    .bind::<Integer, _>(max_depth)
+    .bind::<BigInt, _>(max_children)
+    .load::<CommentTreeNode>(conn)
+  }
+
+  /// The reply counts of the comments. Comments without replies are left out.
+  pub fn child_counts(
+    conn: &PgConnection,
+    comment_ids: &[i32],
+  ) -> Result<Vec<CommentChildCount>, Error> {
+    sql_query(
+      "with recursive tree(root_id, id) as (
+        select c.parent_id, c.id from comment c where c.parent_id = any($1)
+        union all
+        select t.root_id, c.id from comment c join tree t on c.parent_id = t.id
+      )
+      select root_id as comment_id, count(*) as child_count from tree group by root_id",
+    )
+    .bind::<Array<Integer>, _>(comment_ids)
+    .load::<CommentChildCount>(conn)
+  }
+
+  /// The ids of a comment, the comments it's a reply to, and all its replies.
+  pub fn context_ids(conn: &PgConnection, comment_id: i32) -> Result<Vec<i32>, Error> {
+    #[derive(QueryableByName)]
+    struct ContextId {
+      #[sql_type = "Integer"]
+      id: i32,
+    }
+
+    let ids = sql_query(
+      "with recursive ancestor(id, parent_id) as (
+        select c.id, c.parent_id from comment c where c.id = $1
+        union all
+        select c.id, c.parent_id from comment c join ancestor a on c.id = a.parent_id
+      ), descendant(id) as (
+        select c.id from comment c where c.parent_id = $1
+        union all
+        select c.id from comment c join descendant d on c.parent_id = d.id
+      )
+      select id from ancestor union select id from descendant",
+    )
+    .bind::<Integer, _>(comment_id)
+    .load::<ContextId>(conn)?;
+    Ok(ids.into_iter().map(|c| c.id).collect())
+  }
+
+  pub fn update_removed(
+    conn: &PgConnection,
+    comment_id: i32,
+    new_removed: bool,
+  ) -> Result<Self, Error> {
+    use crate::schema::comment::dsl::*;
+    diesel::update(comment.find(comment_id))
+      .set(removed.eq(new_removed))
+      .get_result::<Self>(conn)
+  }
+
+  /// Marks the replies to a user as read, or unread: the ones in `reply_ids`, or all of them.
+  /// Comments that aren't replies to the user are left alone.
+  pub fn update_replies_read(
+    conn: &PgConnection,
+    for_recipient_id: i32,
+    reply_ids: Option<&[i32]>,
+    new_read: bool,
+  ) -> Result<usize, Error> {
+    use crate::schema::comment::dsl::*;
+    use crate::schema::post;
+
+    let to_recipient = parent_id
+      .eq_any(
+        comment
+          .filter(creator_id.eq(for_recipient_id))
+          .select(id.nullable()),
+      )
+      .or(
+        parent_id.is_null().and(
+          post_id.eq_any(
+            post::table
+              .filter(post::creator_id.eq(for_recipient_id))
+              .select(post::id),
+          ),
+        ),
+      );
+    let replies = comment
+      .filter(creator_id.ne(for_recipient_id))
+      .filter(read.ne(new_read))
+      .filter(to_recipient);
+
+    match reply_ids {
+      Some(reply_ids) => diesel::update(replies.filter(id.eq_any(reply_ids)))
+        .set(read.eq(new_read))
+        .execute(conn),
+      None => diesel::update(replies).set(read.eq(new_read)).execute(conn),
+    }
+  }
+}
+
 #[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Clone)]
 #[belongs_to(Comment)]
 #[table_name = "comment_like"]
@@ -115,6 +277,37 @@ impl Likeable<CommentLikeForm> for CommentLike {
 }
 
 impl CommentLike {
+  /// The score of a user's vote on a comment, if they voted on it.
+  pub fn user_score(
+    conn: &PgConnection,
+    for_comment_id: i32,
+    for_user_id: i32,
+  ) -> Result<Option<i16>, Error> {
+    use crate::schema::comment_like::dsl::*;
+    comment_like
+      .filter(comment_id.eq(for_comment_id))
+      .filter(user_id.eq(for_user_id))
+      .select(score)
+      .first::<i16>(conn)
+      .optional()
+  }
+
+  /// The users who voted on a comment with a score, newest vote first.
+  pub fn voters(
+    conn: &PgConnection,
+    for_comment_id: i32,
+    for_score: i16,
+  ) -> Result<Vec<User_>, Error> {
+    use crate::schema::user_;
+    comment_like::table
+      .inner_join(user_::table)
+      .filter(comment_like::comment_id.eq(for_comment_id))
+      .filter(comment_like::score.eq(for_score))
+      .order_by(comment_like::published.desc())
+      .select(user_::all_columns)
+      .load::<User_>(conn)
+  }
+
   pub fn from_post(conn: &PgConnection, post_id_from: i32) -> Result<Vec<Self>, Error> {
     use crate::schema::comment_like::dsl::*;
     comment_like
@@ -131,6 +324,8 @@ pub struct CommentSaved {
   pub comment_id: i32,
   pub user_id: i32,
   pub published: chrono::NaiveDateTime,
+  /// The `SavedFolder` it's in
+  pub folder_id: Option<i32>,
 }
 
 #[derive(Insertable, AsChangeset, Clone)]
@@ -138,13 +333,18 @@ pub struct CommentSaved {
 pub struct CommentSavedForm {
   pub comment_id: i32,
   pub user_id: i32,
+  pub folder_id: Option<i32>,
 }
 
 impl Saveable<CommentSavedForm> for CommentSaved {
+  /// Saving a comment that's already saved moves it to the folder.
   fn save(conn: &PgConnection, comment_saved_form: &CommentSavedForm) -> Result<Self, Error> {
     use crate::schema::comment_saved::dsl::*;
     insert_into(comment_saved)
       .values(comment_saved_form)
+      .on_conflict((comment_id, user_id))
+      .do_update()
+      .set(folder_id.eq(comment_saved_form.folder_id))
       .get_result::<Self>(conn)
   }
   fn unsave(conn: &PgConnection, comment_saved_form: &CommentSavedForm) -> Result<usize, Error> {
@@ -220,6 +420,11 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
     };
 
     let inserted_post = Post::create(&conn, &new_post).unwrap();
@@ -233,6 +438,8 @@ mod tests {
       read: None,
       parent_id: None,
       updated: None,
+      generated: None,
+      language_id: None,
     };
 
     let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
@@ -248,6 +455,8 @@ mod tests {
       parent_id: None,
       published: inserted_comment.published,
       updated: None,
+      generated: false,
+      language_id: None,
     };
 
     let child_comment_form = CommentForm {
@@ -259,10 +468,16 @@ mod tests {
       deleted: None,
       read: None,
       updated: None,
+      generated: None,
+      language_id: None,
     };
 
     let inserted_child_comment = Comment::create(&conn, &child_comment_form).unwrap();
 
+    let shallow_tree = Comment::tree(&conn, inserted_post.id, None, 1, 10).unwrap();
+    let deep_tree = Comment::tree(&conn, inserted_post.id, None, 2, 10).unwrap();
+    let subtree = Comment::tree(&conn, inserted_post.id, Some(inserted_comment.id), 1, 10).unwrap();
+
     // Comment Like
     let comment_like_form = CommentLikeForm {
       comment_id: inserted_comment.id,
@@ -286,6 +501,7 @@ mod tests {
     let comment_saved_form = CommentSavedForm {
       comment_id: inserted_comment.id,
       user_id: inserted_user.id,
+      folder_id: None,
     };
 
     let inserted_comment_saved = CommentSaved::save(&conn, &comment_saved_form).unwrap();
@@ -295,6 +511,7 @@ mod tests {
       comment_id: inserted_comment.id,
       user_id: inserted_user.id,
       published: inserted_comment_saved.published,
+      folder_id: None,
     };
 
     let read_comment = Comment::read(&conn, inserted_comment.id).unwrap();
@@ -316,6 +533,37 @@ mod tests {
       expected_comment.id,
       inserted_child_comment.parent_id.unwrap()
     );
+    assert_eq!(
+      vec![CommentTreeNode {
+        id: inserted_comment.id,
+        depth: 0,
+        has_more_children: true,
+      }],
+      shallow_tree
+    );
+    assert_eq!(
+      vec![
+        CommentTreeNode {
+          id: inserted_comment.id,
+          depth: 0,
+          has_more_children: false,
+        },
+        CommentTreeNode {
+          id: inserted_child_comment.id,
+          depth: 1,
+          has_more_children: false,
+        },
+      ],
+      deep_tree
+    );
+    assert_eq!(
+      vec![CommentTreeNode {
+        id: inserted_child_comment.id,
+        depth: 0,
+        has_more_children: false,
+      }],
+      subtree
+    );
     assert_eq!(1, like_removed);
     assert_eq!(1, saved_removed);
     assert_eq!(1, num_deleted);
@@ -1,5 +1,12 @@
+use super::ids::{CommentId, PostId, UserId};
+use super::user_instance_block::instance_domain;
 use super::*;
+use crate::avatar::fill_default_avatar;
+use crate::schema::{
+  comment, community, community_block, post, user_, user_block, user_instance_block, user_language,
+};
 use diesel::pg::Pg;
+use std::collections::HashMap;
 
 // The faked schema since diesel doesn't do views
 table! {
@@ -20,9 +27,9 @@ table! {
     banned_from_community -> Bool,
     creator_name -> Varchar,
     creator_avatar -> Nullable<Text>,
-    score -> BigInt,
-    upvotes -> BigInt,
-    downvotes -> BigInt,
+    score -> Nullable<BigInt>,
+    upvotes -> Nullable<BigInt>,
+    downvotes -> Nullable<BigInt>,
     hot_rank -> Int4,
     user_id -> Nullable<Int4>,
     my_vote -> Nullable<Int4>,
@@ -49,9 +56,9 @@ table! {
     banned_from_community -> Bool,
     creator_name -> Varchar,
     creator_avatar -> Nullable<Text>,
-    score -> BigInt,
-    upvotes -> BigInt,
-    downvotes -> BigInt,
+    score -> Nullable<BigInt>,
+    upvotes -> Nullable<BigInt>,
+    downvotes -> Nullable<BigInt>,
     hot_rank -> Int4,
     user_id -> Nullable<Int4>,
     my_vote -> Nullable<Int4>,
@@ -81,9 +88,9 @@ pub struct CommentView {
   pub banned_from_community: bool,
   pub creator_name: String,
   pub creator_avatar: Option<String>,
-  pub score: i64,
-  pub upvotes: i64,
-  pub downvotes: i64,
+  pub score: Option<i64>,
+  pub upvotes: Option<i64>,
+  pub downvotes: Option<i64>,
   pub hot_rank: i32,
   pub user_id: Option<i32>,
   pub my_vote: Option<i32>,
@@ -94,16 +101,17 @@ pub struct CommentView {
 pub struct CommentQueryBuilder<'a> {
   conn: &'a PgConnection,
   query: super::comment_view::comment_mview::BoxedQuery<'a, Pg>,
-  listing_type: ListingType,
-  sort: &'a SortType,
-  for_community_id: Option<i32>,
-  for_post_id: Option<i32>,
-  for_creator_id: Option<i32>,
+  listing: ListingParams<'a>,
+  viewer: ViewerParams,
+  for_post_id: Option<PostId>,
+  for_creator_id: Option<UserId>,
   search_term: Option<String>,
-  my_user_id: Option<i32>,
-  saved_only: bool,
-  page: Option<i64>,
-  limit: Option<i64>,
+  for_comment_ids: Option<Vec<i32>>,
+  top_level_only: bool,
+  published_after: Option<chrono::NaiveDateTime>,
+  published_before: Option<chrono::NaiveDateTime>,
+  removed_only: bool,
+  hide_generated: bool,
 }
 
 impl<'a> CommentQueryBuilder<'a> {
@@ -115,111 +123,209 @@ impl<'a> CommentQueryBuilder<'a> {
     CommentQueryBuilder {
       conn,
       query,
-      listing_type: ListingType::All,
-      sort: &SortType::New,
-      for_community_id: None,
+      listing: ListingParams::new(&SortType::New),
+      viewer: ViewerParams::default(),
       for_post_id: None,
       for_creator_id: None,
       search_term: None,
-      my_user_id: None,
-      saved_only: false,
-      page: None,
-      limit: None,
+      for_comment_ids: None,
+      top_level_only: false,
+      published_after: None,
+      published_before: None,
+      removed_only: false,
+      hide_generated: false,
     }
   }
 
-  pub fn listing_type(mut self, listing_type: ListingType) -> Self {
-    self.listing_type = listing_type;
-    self
-  }
-
-  pub fn sort(mut self, sort: &'a SortType) -> Self {
-    self.sort = sort;
+  pub fn for_post_id<T: MaybeOptional<PostId>>(mut self, for_post_id: T) -> Self {
+    self.for_post_id = for_post_id.get_optional();
     self
   }
 
-  pub fn for_post_id<T: MaybeOptional<i32>>(mut self, for_post_id: T) -> Self {
-    self.for_post_id = for_post_id.get_optional();
+  pub fn for_creator_id<T: MaybeOptional<UserId>>(mut self, for_creator_id: T) -> Self {
+    self.for_creator_id = for_creator_id.get_optional();
     self
   }
 
-  pub fn for_creator_id<T: MaybeOptional<i32>>(mut self, for_creator_id: T) -> Self {
-    self.for_creator_id = for_creator_id.get_optional();
+  pub fn search_term<T: MaybeOptional<String>>(mut self, search_term: T) -> Self {
+    self.search_term = search_term.get_optional();
     self
   }
 
-  pub fn for_community_id<T: MaybeOptional<i32>>(mut self, for_community_id: T) -> Self {
-    self.for_community_id = for_community_id.get_optional();
+  pub fn for_comment_ids(mut self, for_comment_ids: Vec<i32>) -> Self {
+    self.for_comment_ids = Some(for_comment_ids);
     self
   }
 
-  pub fn search_term<T: MaybeOptional<String>>(mut self, search_term: T) -> Self {
-    self.search_term = search_term.get_optional();
+  /// Only the comments that aren't replies to other comments.
+  pub fn top_level_only(mut self, top_level_only: bool) -> Self {
+    self.top_level_only = top_level_only;
     self
   }
 
-  pub fn my_user_id<T: MaybeOptional<i32>>(mut self, my_user_id: T) -> Self {
-    self.my_user_id = my_user_id.get_optional();
+  pub fn published_after<T: MaybeOptional<chrono::NaiveDateTime>>(
+    mut self,
+    published_after: T,
+  ) -> Self {
+    self.published_after = published_after.get_optional();
     self
   }
 
-  pub fn saved_only(mut self, saved_only: bool) -> Self {
-    self.saved_only = saved_only;
+  pub fn published_before<T: MaybeOptional<chrono::NaiveDateTime>>(
+    mut self,
+    published_before: T,
+  ) -> Self {
+    self.published_before = published_before.get_optional();
     self
   }
 
-  pub fn page<T: MaybeOptional<i64>>(mut self, page: T) -> Self {
-    self.page = page.get_optional();
+  /// Only the comments mods removed.
+  pub fn removed_only(mut self, removed_only: bool) -> Self {
+    self.removed_only = removed_only;
     self
   }
 
-  pub fn limit<T: MaybeOptional<i64>>(mut self, limit: T) -> Self {
-    self.limit = limit.get_optional();
+  /// Leaves out the comments made by bots or generated by AI.
+  pub fn hide_generated(mut self, hide_generated: bool) -> Self {
+    self.hide_generated = hide_generated;
     self
   }
 
-  pub fn list(self) -> Result<Vec<CommentView>, Error> {
+  /// The query `list` runs, without running it.
+  pub fn build(self) -> super::comment_view::comment_mview::BoxedQuery<'a, Pg> {
     use super::comment_view::comment_mview::dsl::*;
 
-    let mut query = self.query;
-
-    // The view lets you pass a null user_id, if you're not logged in
-    if let Some(my_user_id) = self.my_user_id {
-      query = query.filter(user_id.eq(my_user_id));
-    } else {
-      query = query.filter(user_id.is_null());
-    }
+    let mut query = filter_for_viewer!(self.query, &self.viewer);
 
     if let Some(for_creator_id) = self.for_creator_id {
       query = query.filter(creator_id.eq(for_creator_id));
     };
 
-    if let Some(for_community_id) = self.for_community_id {
-      query = query.filter(community_id.eq(for_community_id));
-    }
+    if let Some(for_comment_ids) = self.for_comment_ids {
+      query = query.filter(id.eq_any(for_comment_ids));
+    };
 
-    if let Some(for_post_id) = self.for_post_id {
-      query = query.filter(post_id.eq(for_post_id));
+    if self.top_level_only {
+      query = query.filter(parent_id.is_null());
     };
 
-    if let Some(search_term) = self.search_term {
-      query = query.filter(content.ilike(fuzzy_search(&search_term)));
+    if let Some(published_after) = self.published_after {
+      query = query.filter(published.ge(published_after));
+    };
+
+    if let Some(published_before) = self.published_before {
+      query = query.filter(published.lt(published_before));
+    };
+
+    if self.removed_only {
+      query = query.filter(removed.eq(true));
     };
 
-    if let ListingType::Subscribed = self.listing_type {
-      query = query.filter(subscribed.eq(true));
+    if self.hide_generated {
+      let generated_comments = comment::table
+        .filter(comment::generated.eq(true))
+        .select(comment::id);
+      query = query.filter(id.ne_all(generated_comments));
+    };
+
+    // Comments don't have their own ids from other instances, so the ones from instances the
+    // viewer blocked are those on their posts, in their communities, or by their users
+    if let Some(my_user_id) = self.viewer.my_user_id {
+      let blocked_domains = || {
+        user_instance_block::table
+          .filter(user_instance_block::user_id.eq(my_user_id))
+          .select(user_instance_block::domain)
+      };
+      let blocked_posts = post::table
+        .filter(instance_domain(post::ap_id).eq_any(blocked_domains()))
+        .select(post::id);
+      let blocked_communities = community::table
+        .filter(instance_domain(community::actor_id).eq_any(blocked_domains()))
+        .select(community::id);
+      let blocked_creators = user_::table
+        .filter(instance_domain(user_::actor_id).eq_any(blocked_domains()))
+        .select(user_::id);
+      query = query
+        .filter(post_id.ne_all(blocked_posts))
+        .filter(community_id.ne_all(blocked_communities))
+        .filter(creator_id.ne_all(blocked_creators));
     }
 
-    if self.saved_only {
-      query = query.filter(saved.eq(true));
+    // Local only communities' comments are only listed for users who are logged in
+    if self.viewer.my_user_id.is_none() {
+      let local_only_communities = community::table
+        .filter(community::local_only.eq(true))
+        .select(community::id);
+      query = query.filter(community_id.ne_all(local_only_communities));
     }
 
-    query = match self.sort {
-      SortType::Hot => query
-        .order_by(hot_rank.desc())
+    // Leave out the comments in the communities the viewer blocked, unless they're looking at one
+    // of their posts or the community itself
+    let for_one = self.for_post_id.is_some() || self.viewer.for_community_id.is_some();
+    if let (Some(my_user_id), false) = (self.viewer.my_user_id, for_one) {
+      let blocked_communities = community_block::table
+        .filter(community_block::user_id.eq(my_user_id))
+        .select(community_block::community_id);
+      query = query.filter(community_id.ne_all(blocked_communities));
+    }
+
+    // Leave out the comments with the words the viewer muted, except their own
+    if let Some(my_user_id) = self.viewer.my_user_id {
+      let muted = keyword_muted!("comment", "comment_mview", "t.content", my_user_id);
+      query = query.filter(creator_id.eq(my_user_id).or(not(muted)));
+    }
+
+    // Only the comments in the viewer's languages, if they picked any, and those without one
+    if let Some(my_user_id) = self.viewer.my_user_id {
+      let languages = || {
+        user_language::table
+          .filter(user_language::user_id.eq(my_user_id))
+          .select(user_language::language_id.nullable())
+      };
+      let other_language_comments = comment::table
+        .filter(exists(languages()))
+        .filter(comment::language_id.ne_all(languages()))
+        .select(comment::id);
+      query = query.filter(id.ne_all(other_language_comments));
+    }
+
+    if let Some(for_post_id) = self.for_post_id {
+      query = query.filter(post_id.eq(for_post_id));
+    };
+
+    // Searches go through the comment table's indexes, rather than scanning the view. Full text
+    // searches are ranked by how well the comments match, ahead of the sorts that aren't by time
+    // or score.
+    let mut search_rank = None;
+    if let Some(search_term) = self.search_term {
+      let mut matches = comment::table.select(comment::id).into_boxed();
+      if let Some(for_post_id) = self.for_post_id {
+        matches = matches.filter(comment::post_id.eq(for_post_id));
+      }
+      if is_full_text_search(&search_term) {
+        matches = matches.filter(full_text_match!("comment", search_term.to_owned()));
+        search_rank = Some(search_term);
+      } else {
+        matches = matches.filter(comment::content.ilike(fuzzy_search(&search_term)));
+      }
+      query = query.filter(id.eq_any(matches));
+    };
+
+    query = match self.listing.sort {
+      SortType::Hot | SortType::Rising => match search_rank {
+        Some(search_rank) => query
+          .order_by(full_text_rank!("comment", "comment_mview", search_rank).desc())
+          .then_order_by(hot_rank.desc())
+          .then_order_by(published.desc()),
+        None => query
+          .order_by(hot_rank.desc())
+          .then_order_by(published.desc()),
+      },
+      SortType::Controversial => query
+        .order_by(controversy_rank(upvotes, downvotes).desc())
         .then_order_by(published.desc()),
       SortType::New => query.order_by(published.desc()),
-      SortType::TopAll => query.order_by(score.desc()),
+      SortType::TopAll | SortType::TopViews => query.order_by(score.desc()),
       SortType::TopYear => query
         .filter(published.gt(now - 1.years()))
         .order_by(score.desc()),
@@ -235,21 +341,117 @@ impl<'a> CommentQueryBuilder<'a> {
       // _ => query.order_by(published.desc()),
     };
 
-    let (limit, offset) = limit_and_offset(self.page, self.limit);
+    let (limit, offset) = self.listing.limit_and_offset();
 
     // Note: deleted and removed comments are done on the front side
-    query
-      .limit(limit)
-      .offset(offset)
-      .load::<CommentView>(self.conn)
+    query.limit(limit).offset(offset)
+  }
+}
+
+impl<'a> ListingQueryBuilder<'a> for CommentQueryBuilder<'a> {
+  type View = CommentView;
+
+  fn listing_params(&mut self) -> &mut ListingParams<'a> {
+    &mut self.listing
+  }
+
+  fn list(self) -> Result<Vec<CommentView>, Error> {
+    let conn = self.conn;
+    self
+      .build()
+      .load::<CommentView>(conn)
+      .map(DefaultAvatars::with_default_avatars)
+  }
+}
+
+impl<'a> ViewerQueryBuilder<'a> for CommentQueryBuilder<'a> {
+  fn viewer_params(&mut self) -> &mut ViewerParams {
+    &mut self.viewer
+  }
+}
+
+impl HideScores for CommentView {
+  fn hide_scores(&mut self) {
+    self.score = None;
+    self.upvotes = None;
+    self.downvotes = None;
+  }
+}
+
+impl DefaultAvatars for CommentView {
+  fn default_avatars(&mut self) {
+    fill_default_avatar(&mut self.creator_avatar, &self.creator_name);
+  }
+}
+
+/// A comment of a subtree, with how deep it is and whether some of its replies were left out, so
+/// clients can show a link to load them.
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct CommentTreeView {
+  pub comment: CommentView,
+  pub depth: i32,
+  pub has_more_children: bool,
+}
+
+impl HideScores for CommentTreeView {
+  fn hide_scores(&mut self) {
+    self.comment.hide_scores();
+  }
+}
+
+/// A comment with the post it's on, for users going through their own comments.
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct CommentWithPostView {
+  pub comment: CommentView,
+  pub post_name: String,
+  pub post_url: Option<String>,
+}
+
+impl CommentWithPostView {
+  /// Adds their posts to comments, keeping their order.
+  pub fn from_comments(
+    conn: &PgConnection,
+    comments: Vec<CommentView>,
+  ) -> Result<Vec<Self>, Error> {
+    let post_ids = comments.iter().map(|c| c.post_id).collect::<Vec<i32>>();
+    let posts = post::table
+      .filter(post::id.eq_any(post_ids))
+      .select((post::id, post::name, post::url))
+      .load::<(i32, String, Option<String>)>(conn)?
+      .into_iter()
+      .map(|(id, name, url)| (id, (name, url)))
+      .collect::<HashMap<_, _>>();
+
+    Ok(
+      comments
+        .into_iter()
+        .map(|comment| {
+          let (post_name, post_url) = match posts.get(&comment.post_id) {
+            Some((name, url)) => (name.to_owned(), url.to_owned()),
+            None => (String::new(), None),
+          };
+          CommentWithPostView {
+            comment,
+            post_name,
+            post_url,
+          }
+        })
+        .collect(),
+    )
+  }
+}
+
+impl HideScores for CommentWithPostView {
+  fn hide_scores(&mut self) {
+    self.comment.hide_scores();
   }
 }
 
 impl CommentView {
   pub fn read(
     conn: &PgConnection,
-    from_comment_id: i32,
-    my_user_id: Option<i32>,
+    from_comment_id: CommentId,
+    my_user_id: Option<UserId>,
   ) -> Result<Self, Error> {
     use super::comment_view::comment_mview::dsl::*;
     let mut query = comment_mview.into_boxed();
@@ -265,7 +467,7 @@ impl CommentView {
       .filter(id.eq(from_comment_id))
       .order_by(published.desc());
 
-    query.first::<Self>(conn)
+    query.first::<Self>(conn).map(Self::with_default_avatars)
   }
 }
 
@@ -288,9 +490,9 @@ table! {
     banned_from_community -> Bool,
     creator_name -> Varchar,
     creator_avatar -> Nullable<Text>,
-    score -> BigInt,
-    upvotes -> BigInt,
-    downvotes -> BigInt,
+    score -> Nullable<BigInt>,
+    upvotes -> Nullable<BigInt>,
+    downvotes -> Nullable<BigInt>,
     hot_rank -> Int4,
     user_id -> Nullable<Int4>,
     my_vote -> Nullable<Int4>,
@@ -321,9 +523,9 @@ pub struct ReplyView {
   pub banned_from_community: bool,
   pub creator_name: String,
   pub creator_avatar: Option<String>,
-  pub score: i64,
-  pub upvotes: i64,
-  pub downvotes: i64,
+  pub score: Option<i64>,
+  pub upvotes: Option<i64>,
+  pub downvotes: Option<i64>,
   pub hot_rank: i32,
   pub user_id: Option<i32>,
   pub my_vote: Option<i32>,
@@ -332,6 +534,20 @@ pub struct ReplyView {
   pub recipient_id: i32,
 }
 
+impl HideScores for ReplyView {
+  fn hide_scores(&mut self) {
+    self.score = None;
+    self.upvotes = None;
+    self.downvotes = None;
+  }
+}
+
+impl DefaultAvatars for ReplyView {
+  fn default_avatars(&mut self) {
+    fill_default_avatar(&mut self.creator_avatar, &self.creator_name);
+  }
+}
+
 pub struct ReplyQueryBuilder<'a> {
   conn: &'a PgConnection,
   query: super::comment_view::reply_view::BoxedQuery<'a, Pg>,
@@ -390,14 +606,26 @@ impl<'a> ReplyQueryBuilder<'a> {
       .filter(deleted.eq(false))
       .filter(removed.eq(false));
 
+    // Not from the users they blocked
+    query = query.filter(
+      creator_id.ne_all(
+        user_block::table
+          .filter(user_block::user_id.eq(self.for_user_id))
+          .select(user_block::target_id),
+      ),
+    );
+
     if self.unread_only {
       query = query.filter(read.eq(false));
     }
 
     query = match self.sort {
       // SortType::Hot => query.order_by(hot_rank.desc()),
+      SortType::Controversial => query
+        .order_by(controversy_rank(upvotes, downvotes).desc())
+        .then_order_by(published.desc()),
       SortType::New => query.order_by(published.desc()),
-      SortType::TopAll => query.order_by(score.desc()),
+      SortType::TopAll | SortType::TopViews => query.order_by(score.desc()),
       SortType::TopYear => query
         .filter(published.gt(now - 1.years()))
         .order_by(score.desc()),
@@ -418,6 +646,7 @@ impl<'a> ReplyQueryBuilder<'a> {
       .limit(limit)
       .offset(offset)
       .load::<ReplyView>(self.conn)
+      .map(DefaultAvatars::with_default_avatars)
   }
 }
 
@@ -426,8 +655,10 @@ mod tests {
   use super::super::comment::*;
   use super::super::community::*;
   use super::super::post::*;
+  use super::super::query_plan::assert_no_seq_scan;
   use super::super::user::*;
   use super::*;
+  use crate::avatar::default_user_avatar;
   #[test]
   fn test_crud() {
     let conn = establish_unpooled_connection();
@@ -484,6 +715,11 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
     };
 
     let inserted_post = Post::create(&conn, &new_post).unwrap();
@@ -497,6 +733,8 @@ mod tests {
       deleted: None,
       read: None,
       updated: None,
+      generated: None,
+      language_id: None,
     };
 
     let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
@@ -526,11 +764,11 @@ mod tests {
       published: inserted_comment.published,
       updated: None,
       creator_name: inserted_user.name.to_owned(),
-      creator_avatar: None,
-      score: 1,
-      downvotes: 0,
+      creator_avatar: Some(default_user_avatar(&inserted_user.name)),
+      score: Some(1),
+      downvotes: Some(0),
       hot_rank: 0,
-      upvotes: 1,
+      upvotes: Some(1),
       user_id: None,
       my_vote: None,
       subscribed: None,
@@ -553,11 +791,11 @@ mod tests {
       published: inserted_comment.published,
       updated: None,
       creator_name: inserted_user.name.to_owned(),
-      creator_avatar: None,
-      score: 1,
-      downvotes: 0,
+      creator_avatar: Some(default_user_avatar(&inserted_user.name)),
+      score: Some(1),
+      downvotes: Some(0),
       hot_rank: 0,
-      upvotes: 1,
+      upvotes: Some(1),
       user_id: Some(inserted_user.id),
       my_vote: Some(1),
       subscribed: None,
@@ -565,18 +803,30 @@ mod tests {
     };
 
     let mut read_comment_views_no_user = CommentQueryBuilder::create(&conn)
-      .for_post_id(inserted_post.id)
+      .for_post_id(PostId(inserted_post.id))
       .list()
       .unwrap();
     read_comment_views_no_user[0].hot_rank = 0;
 
     let mut read_comment_views_with_user = CommentQueryBuilder::create(&conn)
-      .for_post_id(inserted_post.id)
-      .my_user_id(inserted_user.id)
+      .for_post_id(PostId(inserted_post.id))
+      .my_user_id(UserId(inserted_user.id))
       .list()
       .unwrap();
     read_comment_views_with_user[0].hot_rank = 0;
 
+    let my_comments = CommentQueryBuilder::create(&conn)
+      .for_creator_id(UserId(inserted_user.id))
+      .published_after(inserted_comment.published)
+      .list()
+      .unwrap();
+    let my_comments_with_posts = CommentWithPostView::from_comments(&conn, my_comments).unwrap();
+    let removed_comments = CommentQueryBuilder::create(&conn)
+      .for_creator_id(UserId(inserted_user.id))
+      .removed_only(true)
+      .list()
+      .unwrap();
+
     let like_removed = CommentLike::remove(&conn, &comment_like_form).unwrap();
     let num_deleted = Comment::delete(&conn, inserted_comment.id).unwrap();
     Post::delete(&conn, inserted_post.id).unwrap();
@@ -588,7 +838,36 @@ mod tests {
       expected_comment_view_with_user,
       read_comment_views_with_user[0]
     );
+    assert_eq!(1, my_comments_with_posts.len());
+    assert_eq!(inserted_post.name, my_comments_with_posts[0].post_name);
+    assert!(removed_comments.is_empty());
     assert_eq!(1, num_deleted);
     assert_eq!(1, like_removed);
   }
+
+  #[test]
+  fn test_query_plans() {
+    let conn = establish_unpooled_connection();
+
+    assert_no_seq_scan(
+      &conn,
+      CommentQueryBuilder::create(&conn)
+        .for_post_id(PostId(1))
+        .my_user_id(UserId(1))
+        .limit(9999)
+        .build(),
+    );
+    assert_no_seq_scan(
+      &conn,
+      CommentQueryBuilder::create(&conn)
+        .sort(&SortType::New)
+        .build(),
+    );
+    assert_no_seq_scan(
+      &conn,
+      CommentQueryBuilder::create(&conn)
+        .search_term("borrow checker".to_string())
+        .build(),
+    );
+  }
 }
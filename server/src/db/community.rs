@@ -1,5 +1,9 @@
 use super::*;
-use crate::schema::{community, community_follower, community_moderator, community_user_ban};
+use crate::naive_now;
+use crate::schema::{
+  community, community_alias, community_follower, community_moderator, community_rules_ack,
+  community_user_ban,
+};
 
 #[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
 #[table_name = "community"]
@@ -15,6 +19,19 @@ pub struct Community {
   pub updated: Option<chrono::NaiveDateTime>,
   pub deleted: bool,
   pub nsfw: bool,
+  pub rules: Option<String>,
+  /// Whether users have to agree to the rules before they first post or comment
+  pub require_rules_ack: bool,
+  /// Whether image posts need alt text
+  pub require_alt_text: bool,
+  /// Whether the community, and everything in it, is kept off of activitypub
+  pub local_only: bool,
+  /// Whether the community is left out of the listings, so it's only found by its link
+  pub hidden: bool,
+  /// The activitypub id from before the community was renamed, which it keeps
+  pub actor_id: Option<String>,
+  /// How many users reporting a post or comment removes it, if set
+  pub report_auto_remove_threshold: Option<i32>,
 }
 
 #[derive(Insertable, AsChangeset, Clone, Serialize, Deserialize)]
@@ -62,11 +79,111 @@ impl Crud<CommunityForm> for Community {
 }
 
 impl Community {
+  /// Reads a community by its name, or by one of the names it had before it was renamed.
   pub fn read_from_name(conn: &PgConnection, community_name: String) -> Result<Self, Error> {
     use crate::schema::community::dsl::*;
     community
-      .filter(name.eq(community_name))
+      .filter(name.eq(&community_name))
       .first::<Self>(conn)
+      .or_else(|_| {
+        community
+          .filter(
+            id.eq_any(
+              community_alias::table
+                .filter(community_alias::name.eq(&community_name))
+                .select(community_alias::community_id),
+            ),
+          )
+          .first::<Self>(conn)
+      })
+  }
+
+  /// Renames a community. The old name becomes an alias, and the community keeps `actor_id` as its
+  /// activitypub id.
+  pub fn rename(
+    conn: &PgConnection,
+    community_id: i32,
+    new_name: &str,
+    new_actor_id: &str,
+  ) -> Result<Self, Error> {
+    use crate::schema::community::dsl::*;
+    let old = Self::read(conn, community_id)?;
+    // Going back to an old name takes it from the aliases
+    diesel::delete(
+      community_alias::table
+        .filter(community_alias::community_id.eq(community_id))
+        .filter(community_alias::name.eq(new_name)),
+    )
+    .execute(conn)?;
+    let form = CommunityAliasForm {
+      community_id,
+      name: old.name,
+    };
+    insert_into(community_alias::table)
+      .values(&form)
+      .execute(conn)?;
+    diesel::update(community.find(community_id))
+      .set((name.eq(new_name), actor_id.eq(new_actor_id)))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_rules(
+    conn: &PgConnection,
+    community_id: i32,
+    new_rules: Option<String>,
+    new_require_rules_ack: bool,
+  ) -> Result<Self, Error> {
+    use crate::schema::community::dsl::*;
+    diesel::update(community.find(community_id))
+      .set((
+        rules.eq(new_rules),
+        require_rules_ack.eq(new_require_rules_ack),
+      ))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_require_alt_text(
+    conn: &PgConnection,
+    community_id: i32,
+    new_require_alt_text: bool,
+  ) -> Result<Self, Error> {
+    use crate::schema::community::dsl::*;
+    diesel::update(community.find(community_id))
+      .set(require_alt_text.eq(new_require_alt_text))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_local_only(
+    conn: &PgConnection,
+    community_id: i32,
+    new_local_only: bool,
+  ) -> Result<Self, Error> {
+    use crate::schema::community::dsl::*;
+    diesel::update(community.find(community_id))
+      .set(local_only.eq(new_local_only))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_report_auto_remove_threshold(
+    conn: &PgConnection,
+    community_id: i32,
+    threshold: Option<i32>,
+  ) -> Result<Self, Error> {
+    use crate::schema::community::dsl::*;
+    diesel::update(community.find(community_id))
+      .set(report_auto_remove_threshold.eq(threshold))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_hidden(
+    conn: &PgConnection,
+    community_id: i32,
+    new_hidden: bool,
+  ) -> Result<Self, Error> {
+    use crate::schema::community::dsl::*;
+    diesel::update(community.find(community_id))
+      .set(hidden.eq(new_hidden))
+      .get_result::<Self>(conn)
   }
 
   pub fn get_url(&self) -> String {
@@ -74,6 +191,34 @@ impl Community {
   }
 }
 
+/// A name a community had before it was renamed.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Deserialize)]
+#[belongs_to(Community)]
+#[table_name = "community_alias"]
+pub struct CommunityAlias {
+  pub id: i32,
+  pub community_id: i32,
+  pub name: String,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "community_alias"]
+pub struct CommunityAliasForm {
+  pub community_id: i32,
+  pub name: String,
+}
+
+impl CommunityAlias {
+  pub fn for_community(conn: &PgConnection, for_community_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::community_alias::dsl::*;
+    community_alias
+      .filter(community_id.eq(for_community_id))
+      .order_by(published)
+      .load::<Self>(conn)
+  }
+}
+
 #[derive(Identifiable, Queryable, Associations, PartialEq, Debug)]
 #[belongs_to(Community)]
 #[table_name = "community_moderator"]
@@ -131,6 +276,8 @@ pub struct CommunityUserBan {
   pub community_id: i32,
   pub user_id: i32,
   pub published: chrono::NaiveDateTime,
+  /// When the ban is lifted, for temporary bans
+  pub expires: Option<chrono::NaiveDateTime>,
 }
 
 #[derive(Insertable, AsChangeset, Clone)]
@@ -138,6 +285,7 @@ pub struct CommunityUserBan {
 pub struct CommunityUserBanForm {
   pub community_id: i32,
   pub user_id: i32,
+  pub expires: Option<chrono::NaiveDateTime>,
 }
 
 impl Bannable<CommunityUserBanForm> for CommunityUserBan {
@@ -165,6 +313,14 @@ impl Bannable<CommunityUserBanForm> for CommunityUserBan {
   }
 }
 
+impl CommunityUserBan {
+  /// Lifts the temporary bans that have expired, returning how many there were.
+  pub fn lift_expired(conn: &PgConnection) -> Result<usize, Error> {
+    use crate::schema::community_user_ban::dsl::*;
+    diesel::delete(community_user_ban.filter(expires.le(naive_now()))).execute(conn)
+  }
+}
+
 #[derive(Identifiable, Queryable, Associations, PartialEq, Debug)]
 #[belongs_to(Community)]
 #[table_name = "community_follower"]
@@ -206,9 +362,70 @@ impl Followable<CommunityFollowerForm> for CommunityFollower {
   }
 }
 
+/// A user having agreed to a community's rules.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug)]
+#[belongs_to(Community)]
+#[table_name = "community_rules_ack"]
+pub struct CommunityRulesAck {
+  pub id: i32,
+  pub community_id: i32,
+  pub user_id: i32,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "community_rules_ack"]
+pub struct CommunityRulesAckForm {
+  pub community_id: i32,
+  pub user_id: i32,
+}
+
+impl CommunityRulesAck {
+  pub fn acknowledge(conn: &PgConnection, form: &CommunityRulesAckForm) -> Result<usize, Error> {
+    use crate::schema::community_rules_ack::dsl::*;
+    insert_into(community_rules_ack)
+      .values(form)
+      .on_conflict_do_nothing()
+      .execute(conn)
+  }
+
+  pub fn has_acknowledged(
+    conn: &PgConnection,
+    for_community_id: i32,
+    for_user_id: i32,
+  ) -> Result<bool, Error> {
+    use crate::schema::community_rules_ack::dsl::*;
+    use diesel::dsl::exists;
+    diesel::select(exists(
+      community_rules_ack
+        .filter(community_id.eq(for_community_id))
+        .filter(user_id.eq(for_user_id)),
+    ))
+    .get_result(conn)
+  }
+}
+
+/// A community named `name`, for the tests.
+#[cfg(test)]
+pub fn community_form(name: &str, UserId(creator_id): UserId) -> CommunityForm {
+  CommunityForm {
+    name: name.into(),
+    title: "nada".to_owned(),
+    description: None,
+    category_id: 1,
+    creator_id,
+    removed: None,
+    updated: None,
+    deleted: None,
+    nsfw: false,
+  }
+}
+
 #[cfg(test)]
 mod tests {
+  use super::super::community_view::CommunityUserBanView;
   use super::super::user::*;
+  use super::super::user_view::UserView;
   use super::*;
   #[test]
   fn test_crud() {
@@ -262,6 +479,13 @@ mod tests {
       deleted: false,
       published: inserted_community.published,
       updated: None,
+      rules: None,
+      require_rules_ack: false,
+      require_alt_text: false,
+      local_only: false,
+      hidden: false,
+      actor_id: None,
+      report_auto_remove_threshold: None,
     };
 
     let community_follower_form = CommunityFollowerForm {
@@ -296,6 +520,7 @@ mod tests {
     let community_user_ban_form = CommunityUserBanForm {
       community_id: inserted_community.id,
       user_id: inserted_user.id,
+      expires: None,
     };
 
     let inserted_community_user_ban =
@@ -306,11 +531,37 @@ mod tests {
       community_id: inserted_community.id,
       user_id: inserted_user.id,
       published: inserted_community_user_ban.published,
+      expires: None,
+    };
+
+    let rules_ack_form = CommunityRulesAckForm {
+      community_id: inserted_community.id,
+      user_id: inserted_user.id,
     };
+    let unacknowledged =
+      CommunityRulesAck::has_acknowledged(&conn, inserted_community.id, inserted_user.id).unwrap();
+    CommunityRulesAck::acknowledge(&conn, &rules_ack_form).unwrap();
+    // Agreeing twice is fine
+    CommunityRulesAck::acknowledge(&conn, &rules_ack_form).unwrap();
+    let acknowledged =
+      CommunityRulesAck::has_acknowledged(&conn, inserted_community.id, inserted_user.id).unwrap();
 
     let read_community = Community::read(&conn, inserted_community.id).unwrap();
     let updated_community =
       Community::update(&conn, inserted_community.id, &new_community).unwrap();
+    let renamed_community = Community::rename(
+      &conn,
+      inserted_community.id,
+      "TodayILearned",
+      "https://a/c/TIL",
+    )
+    .unwrap();
+    let read_from_old_name = Community::read_from_name(&conn, "TIL".into()).unwrap();
+    let aliases = CommunityAlias::for_community(&conn, inserted_community.id).unwrap();
+    let renamed_back =
+      Community::rename(&conn, inserted_community.id, "TIL", "https://a/c/TIL").unwrap();
+    let aliases_after_renaming_back =
+      CommunityAlias::for_community(&conn, inserted_community.id).unwrap();
     let ignored_community = CommunityFollower::ignore(&conn, &community_follower_form).unwrap();
     let left_community = CommunityModerator::leave(&conn, &community_user_form).unwrap();
     let unban = CommunityUserBan::unban(&conn, &community_user_ban_form).unwrap();
@@ -320,13 +571,107 @@ mod tests {
     assert_eq!(expected_community, read_community);
     assert_eq!(expected_community, inserted_community);
     assert_eq!(expected_community, updated_community);
+    assert_eq!("TodayILearned", renamed_community.name);
+    assert_eq!(Some("https://a/c/TIL".into()), renamed_community.actor_id);
+    assert_eq!(inserted_community.id, read_from_old_name.id);
+    assert_eq!(
+      vec!["TIL"],
+      aliases.iter().map(|a| a.name.as_str()).collect::<Vec<_>>()
+    );
+    assert_eq!("TIL", renamed_back.name);
+    assert_eq!(
+      vec!["TodayILearned"],
+      aliases_after_renaming_back
+        .iter()
+        .map(|a| a.name.as_str())
+        .collect::<Vec<_>>()
+    );
     assert_eq!(expected_community_follower, inserted_community_follower);
     assert_eq!(expected_community_user, inserted_community_user);
     assert_eq!(expected_community_user_ban, inserted_community_user_ban);
     assert_eq!(1, ignored_community);
     assert_eq!(1, left_community);
     assert_eq!(1, unban);
+    assert!(!unacknowledged);
+    assert!(acknowledged);
     // assert_eq!(2, loaded_count);
     assert_eq!(1, num_deleted);
   }
+
+  #[test]
+  fn test_ban_expiry() {
+    let conn = establish_unpooled_connection();
+
+    let moderator = User_::create(&conn, &user_form("ban_expiry_mod")).unwrap();
+    let banned_user = User_::create(
+      &conn,
+      &UserForm {
+        banned: true,
+        ..user_form("ban_expiry_user")
+      },
+    )
+    .unwrap();
+    let community = Community::create(
+      &conn,
+      &community_form("ban_expiry_comm", UserId(moderator.id)),
+    )
+    .unwrap();
+    let in_an_hour = naive_now() + chrono::Duration::hours(1);
+    User_::update_ban_expires(&conn, banned_user.id, Some(in_an_hour)).unwrap();
+    let ban_form = CommunityUserBanForm {
+      community_id: community.id,
+      user_id: banned_user.id,
+      expires: Some(in_an_hour),
+    };
+    CommunityUserBan::ban(&conn, &ban_form).unwrap();
+
+    // The site and community bans, which are what keep them from creating, editing and voting on
+    // posts there
+    let blocked = || {
+      (
+        UserView::read(&conn, banned_user.id).unwrap().banned,
+        CommunityUserBanView::get(&conn, banned_user.id, community.id).is_ok(),
+      )
+    };
+
+    let before_expiry = blocked();
+    User_::lift_expired_bans(&conn).unwrap();
+    CommunityUserBan::lift_expired(&conn).unwrap();
+    let lifted_before_expiry = blocked();
+
+    // The hour passes
+    let an_hour_ago = naive_now() - chrono::Duration::hours(1);
+    User_::update_ban_expires(&conn, banned_user.id, Some(an_hour_ago)).unwrap();
+    diesel::update(
+      community_user_ban::table
+        .filter(community_user_ban::community_id.eq(community.id))
+        .filter(community_user_ban::user_id.eq(banned_user.id)),
+    )
+    .set(community_user_ban::expires.eq(an_hour_ago))
+    .execute(&conn)
+    .unwrap();
+    // An expired community ban doesn't count, even before it's lifted
+    let community_ban_after_expiry = blocked().1;
+    User_::lift_expired_bans(&conn).unwrap();
+    CommunityUserBan::lift_expired(&conn).unwrap();
+    let after_expiry = blocked();
+    let lifted_user = User_::read(&conn, banned_user.id).unwrap();
+    let community_bans: i64 = community_user_ban::table
+      .filter(community_user_ban::user_id.eq(banned_user.id))
+      .count()
+      .get_result(&conn)
+      .unwrap();
+
+    Community::delete(&conn, community.id).unwrap();
+    User_::delete(&conn, banned_user.id).unwrap();
+    User_::delete(&conn, moderator.id).unwrap();
+
+    assert_eq!((true, true), before_expiry);
+    assert_eq!((true, true), lifted_before_expiry);
+    assert!(!community_ban_after_expiry);
+    assert_eq!((false, false), after_expiry);
+    assert!(!lifted_user.banned);
+    assert_eq!(None, lifted_user.ban_expires);
+    assert_eq!(0, community_bans);
+  }
 }
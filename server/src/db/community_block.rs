@@ -0,0 +1,120 @@
+use super::*;
+use crate::schema::community_block;
+use crate::schema::community_block::dsl::*;
+
+/// A community that a user blocked. Its posts and comments are left out of the user's listings.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "community_block"]
+pub struct CommunityBlock {
+  pub id: i32,
+  pub user_id: i32,
+  pub community_id: i32,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "community_block"]
+pub struct CommunityBlockForm {
+  pub user_id: i32,
+  pub community_id: i32,
+}
+
+impl CommunityBlock {
+  /// Blocks a community, which does nothing if it already is.
+  pub fn block(conn: &PgConnection, form: &CommunityBlockForm) -> Result<usize, Error> {
+    insert_into(community_block)
+      .values(form)
+      .on_conflict((user_id, community_id))
+      .do_nothing()
+      .execute(conn)
+  }
+
+  pub fn unblock(conn: &PgConnection, form: &CommunityBlockForm) -> Result<usize, Error> {
+    diesel::delete(
+      community_block
+        .filter(user_id.eq(form.user_id))
+        .filter(community_id.eq(form.community_id)),
+    )
+    .execute(conn)
+  }
+
+  /// The ids of the communities a user blocked, most recently blocked first.
+  pub fn community_ids_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<i32>, Error> {
+    community_block
+      .filter(user_id.eq(for_user_id))
+      .order_by(published.desc())
+      .select(community_id)
+      .load::<i32>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::community::*;
+  use super::super::community_view::*;
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_block() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "community_blocker".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+    let blocker = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "blocked_community".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: blocker.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let form = CommunityBlockForm {
+      user_id: blocker.id,
+      community_id: inserted_community.id,
+    };
+    let inserted = CommunityBlock::block(&conn, &form).unwrap();
+    let inserted_again = CommunityBlock::block(&conn, &form).unwrap();
+    let blocked = CommunityBlock::community_ids_for_user(&conn, blocker.id).unwrap();
+    let blocked_views = CommunityView::read_many(&conn, &blocked, Some(blocker.id)).unwrap();
+    let unblocked = CommunityBlock::unblock(&conn, &form).unwrap();
+    let blocked_after = CommunityBlock::community_ids_for_user(&conn, blocker.id).unwrap();
+
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, blocker.id).unwrap();
+
+    assert_eq!(1, inserted);
+    assert_eq!(0, inserted_again);
+    assert_eq!(vec![inserted_community.id], blocked);
+    assert_eq!(
+      vec![inserted_community.id],
+      blocked_views.iter().map(|c| c.id).collect::<Vec<i32>>()
+    );
+    assert_eq!(1, unblocked);
+    assert!(blocked_after.is_empty());
+  }
+}
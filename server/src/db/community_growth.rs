@@ -0,0 +1,206 @@
+use super::*;
+use crate::schema::community_growth;
+use crate::schema::community_growth::dsl::*;
+use diesel::sql_types::{BigInt, Integer, Varchar};
+
+/// How many subscriptions in a day, at least, count as a spike
+pub const SPIKE_MIN_SUBSCRIPTIONS: i64 = 20;
+
+/// How many times the usual daily subscriptions count as a spike
+pub const SPIKE_FACTOR: i64 = 5;
+
+/// How many days before the last one the usual daily subscriptions are averaged over
+pub const SPIKE_BASELINE_DAYS: i64 = 28;
+
+/// The last milestones of a community that its moderators were told about, and when they were last
+/// told about a spike in subscriptions.
+#[derive(Queryable, Insertable, AsChangeset, PartialEq, Debug, Clone)]
+#[table_name = "community_growth"]
+pub struct CommunityGrowth {
+  pub community_id: i32,
+  pub subscriber_milestone: i64,
+  /// Of the posts and comments in it
+  pub activity_milestone: i64,
+  pub spike_notified: Option<chrono::NaiveDateTime>,
+}
+
+/// How big a local community is, and how many subscribed to it lately.
+#[derive(QueryableByName, PartialEq, Debug)]
+pub struct CommunityGrowthCounts {
+  #[sql_type = "Integer"]
+  pub community_id: i32,
+  #[sql_type = "Varchar"]
+  pub community_name: String,
+  #[sql_type = "BigInt"]
+  pub subscribers: i64,
+  /// Its posts and comments
+  #[sql_type = "BigInt"]
+  pub activity: i64,
+  /// The subscriptions of the last day
+  #[sql_type = "BigInt"]
+  pub new_subscriptions: i64,
+  /// The subscriptions of the `SPIKE_BASELINE_DAYS` before the last day
+  #[sql_type = "BigInt"]
+  pub earlier_subscriptions: i64,
+}
+
+impl CommunityGrowth {
+  pub fn read(conn: &PgConnection, for_community_id: i32) -> Result<Self, Error> {
+    community_growth.find(for_community_id).first::<Self>(conn)
+  }
+
+  pub fn save(conn: &PgConnection, growth: &Self) -> Result<Self, Error> {
+    insert_into(community_growth)
+      .values(growth)
+      .on_conflict(community_id)
+      .do_update()
+      .set(growth)
+      .get_result::<Self>(conn)
+  }
+
+  /// The counts of the local communities that aren't removed or deleted.
+  pub fn counts(conn: &PgConnection) -> Result<Vec<CommunityGrowthCounts>, Error> {
+    sql_query(format!(
+      "select ca.id as community_id, ca.name as community_name,
+      ca.number_of_subscribers as subscribers,
+      ca.number_of_posts + ca.number_of_comments as activity,
+      (select count(*) from community_follower cf where cf.community_id = ca.id
+        and cf.published > now()::timestamp - interval '1 day') as new_subscriptions,
+      (select count(*) from community_follower cf where cf.community_id = ca.id
+        and cf.published <= now()::timestamp - interval '1 day'
+        and cf.published > now()::timestamp - interval '{} days') as earlier_subscriptions
+      from community_aggregates_mview ca
+      where ca.actor_id is null and not (ca.removed or ca.deleted)
+      order by ca.id",
+      SPIKE_BASELINE_DAYS + 1
+    ))
+    .load::<CommunityGrowthCounts>(conn)
+  }
+}
+
+/// The highest of 10, 50, 100, 500, 1000 and so on that a count reached, or 0.
+pub fn milestone(count: i64) -> i64 {
+  let mut reached = 0;
+  let mut power = 10;
+  while count >= power {
+    reached = if count >= power * 5 { power * 5 } else { power };
+    power *= 10;
+  }
+  reached
+}
+
+/// Whether the subscriptions of the last day are many more than usual, which could be a brigade.
+pub fn is_subscription_spike(new_subscriptions: i64, earlier_subscriptions: i64) -> bool {
+  new_subscriptions >= SPIKE_MIN_SUBSCRIPTIONS
+    && new_subscriptions * SPIKE_BASELINE_DAYS >= SPIKE_FACTOR * earlier_subscriptions
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::community::*;
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_milestones() {
+    assert_eq!(0, milestone(0));
+    assert_eq!(0, milestone(9));
+    assert_eq!(10, milestone(10));
+    assert_eq!(10, milestone(49));
+    assert_eq!(50, milestone(50));
+    assert_eq!(100, milestone(499));
+    assert_eq!(5000, milestone(9999));
+    assert_eq!(10000, milestone(10000));
+
+    assert!(!is_subscription_spike(19, 0));
+    assert!(is_subscription_spike(20, 0));
+    // 4 a day for the last 4 weeks
+    assert!(is_subscription_spike(20, 112));
+    assert!(!is_subscription_spike(20, 113));
+  }
+
+  #[test]
+  fn test_save() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "growth_watcher".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "growth_community".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let follower_form = CommunityFollowerForm {
+      community_id: inserted_community.id,
+      user_id: inserted_user.id,
+    };
+    CommunityFollower::follow(&conn, &follower_form).unwrap();
+
+    let counts = CommunityGrowth::counts(&conn).unwrap();
+    let growth = CommunityGrowth {
+      community_id: inserted_community.id,
+      subscriber_milestone: 10,
+      activity_milestone: 0,
+      spike_notified: None,
+    };
+    CommunityGrowth::save(&conn, &growth).unwrap();
+    let saved = CommunityGrowth::save(
+      &conn,
+      &CommunityGrowth {
+        activity_milestone: 50,
+        ..growth.clone()
+      },
+    )
+    .unwrap();
+    let read_growth = CommunityGrowth::read(&conn, inserted_community.id).unwrap();
+
+    CommunityFollower::ignore(&conn, &follower_form).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    let community_counts = counts
+      .iter()
+      .find(|c| c.community_id == inserted_community.id)
+      .unwrap();
+    assert_eq!(
+      &CommunityGrowthCounts {
+        community_id: inserted_community.id,
+        community_name: "growth_community".into(),
+        subscribers: 1,
+        activity: 0,
+        new_subscriptions: 1,
+        earlier_subscriptions: 0,
+      },
+      community_counts
+    );
+    assert_eq!(50, saved.activity_milestone);
+    assert_eq!(saved, read_growth);
+  }
+}
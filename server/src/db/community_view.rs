@@ -1,5 +1,9 @@
 use super::community_view::community_mview::BoxedQuery;
+use super::ids::UserId;
+use super::user_instance_block::instance_domain;
 use super::*;
+use crate::avatar::{default_community_avatar, fill_default_avatar};
+use crate::schema::user_instance_block;
 use diesel::pg::Pg;
 
 table! {
@@ -15,8 +19,15 @@ table! {
     updated -> Nullable<Timestamp>,
     deleted -> Bool,
     nsfw -> Bool,
+    rules -> Nullable<Text>,
+    require_rules_ack -> Bool,
+    require_alt_text -> Bool,
+    local_only -> Bool,
+    hidden -> Bool,
+    actor_id -> Nullable<Text>,
     creator_name -> Varchar,
     creator_avatar -> Nullable<Text>,
+    icon -> Nullable<Text>,
     category_name -> Varchar,
     number_of_subscribers -> BigInt,
     number_of_posts -> BigInt,
@@ -40,8 +51,15 @@ table! {
     updated -> Nullable<Timestamp>,
     deleted -> Bool,
     nsfw -> Bool,
+    rules -> Nullable<Text>,
+    require_rules_ack -> Bool,
+    require_alt_text -> Bool,
+    local_only -> Bool,
+    hidden -> Bool,
+    actor_id -> Nullable<Text>,
     creator_name -> Varchar,
     creator_avatar -> Nullable<Text>,
+    icon -> Nullable<Text>,
     category_name -> Varchar,
     number_of_subscribers -> BigInt,
     number_of_posts -> BigInt,
@@ -82,6 +100,7 @@ table! {
     community_id -> Int4,
     user_id -> Int4,
     published -> Timestamp,
+    expires -> Nullable<Timestamp>,
     user_name -> Varchar,
     avatar -> Nullable<Text>,
     community_name -> Varchar,
@@ -104,8 +123,15 @@ pub struct CommunityView {
   pub updated: Option<chrono::NaiveDateTime>,
   pub deleted: bool,
   pub nsfw: bool,
+  pub rules: Option<String>,
+  pub require_rules_ack: bool,
+  pub require_alt_text: bool,
+  pub local_only: bool,
+  pub hidden: bool,
+  pub actor_id: Option<String>,
   pub creator_name: String,
   pub creator_avatar: Option<String>,
+  pub icon: Option<String>,
   pub category_name: String,
   pub number_of_subscribers: i64,
   pub number_of_posts: i64,
@@ -118,12 +144,10 @@ pub struct CommunityView {
 pub struct CommunityQueryBuilder<'a> {
   conn: &'a PgConnection,
   query: BoxedQuery<'a, Pg>,
-  sort: &'a SortType,
-  from_user_id: Option<i32>,
+  listing: ListingParams<'a>,
+  from_user_id: Option<UserId>,
   show_nsfw: bool,
   search_term: Option<String>,
-  page: Option<i64>,
-  limit: Option<i64>,
 }
 
 impl<'a> CommunityQueryBuilder<'a> {
@@ -135,21 +159,14 @@ impl<'a> CommunityQueryBuilder<'a> {
     CommunityQueryBuilder {
       conn,
       query,
-      sort: &SortType::Hot,
+      listing: ListingParams::new(&SortType::Hot),
       from_user_id: None,
       show_nsfw: true,
       search_term: None,
-      page: None,
-      limit: None,
     }
   }
 
-  pub fn sort(mut self, sort: &'a SortType) -> Self {
-    self.sort = sort;
-    self
-  }
-
-  pub fn for_user<T: MaybeOptional<i32>>(mut self, from_user_id: T) -> Self {
+  pub fn for_user<T: MaybeOptional<UserId>>(mut self, from_user_id: T) -> Self {
     self.from_user_id = from_user_id.get_optional();
     self
   }
@@ -163,18 +180,16 @@ impl<'a> CommunityQueryBuilder<'a> {
     self.search_term = search_term.get_optional();
     self
   }
+}
 
-  pub fn page<T: MaybeOptional<i64>>(mut self, page: T) -> Self {
-    self.page = page.get_optional();
-    self
-  }
+impl<'a> ListingQueryBuilder<'a> for CommunityQueryBuilder<'a> {
+  type View = CommunityView;
 
-  pub fn limit<T: MaybeOptional<i64>>(mut self, limit: T) -> Self {
-    self.limit = limit.get_optional();
-    self
+  fn listing_params(&mut self) -> &mut ListingParams<'a> {
+    &mut self.listing
   }
 
-  pub fn list(self) -> Result<Vec<CommunityView>, Error> {
+  fn list(self) -> Result<Vec<CommunityView>, Error> {
     use super::community_view::community_mview::dsl::*;
 
     let mut query = self.query;
@@ -188,15 +203,15 @@ impl<'a> CommunityQueryBuilder<'a> {
     };
 
     // The view lets you pass a null user_id, if you're not logged in
-    match self.sort {
-      SortType::Hot => {
+    match self.listing.sort {
+      SortType::Hot | SortType::Rising | SortType::Controversial => {
         query = query
           .order_by(hot_rank.desc())
           .then_order_by(number_of_subscribers.desc())
           .filter(user_id.is_null())
       }
       SortType::New => query = query.order_by(published.desc()).filter(user_id.is_null()),
-      SortType::TopAll => match self.from_user_id {
+      SortType::TopAll | SortType::TopViews => match self.from_user_id {
         Some(from_user_id) => {
           query = query
             .filter(user_id.eq(from_user_id))
@@ -215,13 +230,38 @@ impl<'a> CommunityQueryBuilder<'a> {
       query = query.filter(nsfw.eq(false));
     };
 
-    let (limit, offset) = limit_and_offset(self.page, self.limit);
+    // Hidden communities are only listed for their subscribers
+    query = query.filter(hidden.eq(false).or(subscribed.eq(true)));
+
+    // Local only communities are only listed for users who are logged in
+    if self.from_user_id.is_none() {
+      query = query.filter(local_only.eq(false));
+    }
+
+    if let Some(from_user_id) = self.from_user_id {
+      let blocked_domains = user_instance_block::table
+        .filter(user_instance_block::user_id.eq(from_user_id))
+        .select(user_instance_block::domain);
+      query = query.filter(instance_domain(actor_id).ne_all(blocked_domains));
+    }
+
+    let (limit, offset) = self.listing.limit_and_offset();
     query
       .limit(limit)
       .offset(offset)
       .filter(removed.eq(false))
       .filter(deleted.eq(false))
       .load::<CommunityView>(self.conn)
+      .map(DefaultAvatars::with_default_avatars)
+  }
+}
+
+impl DefaultAvatars for CommunityView {
+  fn default_avatars(&mut self) {
+    fill_default_avatar(&mut self.creator_avatar, &self.creator_name);
+    if self.icon.is_none() {
+      self.icon = Some(default_community_avatar(&self.name));
+    }
   }
 }
 
@@ -244,7 +284,32 @@ impl CommunityView {
       query = query.filter(user_id.is_null());
     };
 
-    query.first::<Self>(conn)
+    query.first::<Self>(conn).map(Self::with_default_avatars)
+  }
+
+  /// The communities with these ids, in the same order, in one query.
+  pub fn read_many(
+    conn: &PgConnection,
+    community_ids: &[i32],
+    from_user_id: Option<i32>,
+  ) -> Result<Vec<Self>, Error> {
+    use super::community_view::community_mview::dsl::*;
+
+    let mut query = community_mview
+      .filter(id.eq_any(community_ids.to_vec()))
+      .into_boxed();
+
+    if let Some(from_user_id) = from_user_id {
+      query = query.filter(user_id.eq(from_user_id));
+    } else {
+      query = query.filter(user_id.is_null());
+    };
+
+    let mut communities = query
+      .load::<Self>(conn)
+      .map(DefaultAvatars::with_default_avatars)?;
+    communities.sort_by_key(|c| community_ids.iter().position(|&i| i == c.id));
+    Ok(communities)
   }
 }
 
@@ -262,12 +327,19 @@ pub struct CommunityModeratorView {
   pub community_name: String,
 }
 
+impl DefaultAvatars for CommunityModeratorView {
+  fn default_avatars(&mut self) {
+    fill_default_avatar(&mut self.avatar, &self.user_name);
+  }
+}
+
 impl CommunityModeratorView {
   pub fn for_community(conn: &PgConnection, from_community_id: i32) -> Result<Vec<Self>, Error> {
     use super::community_view::community_moderator_view::dsl::*;
     community_moderator_view
       .filter(community_id.eq(from_community_id))
       .load::<Self>(conn)
+      .map(DefaultAvatars::with_default_avatars)
   }
 
   pub fn for_user(conn: &PgConnection, from_user_id: i32) -> Result<Vec<Self>, Error> {
@@ -275,6 +347,7 @@ impl CommunityModeratorView {
     community_moderator_view
       .filter(user_id.eq(from_user_id))
       .load::<Self>(conn)
+      .map(DefaultAvatars::with_default_avatars)
   }
 }
 
@@ -292,12 +365,19 @@ pub struct CommunityFollowerView {
   pub community_name: String,
 }
 
+impl DefaultAvatars for CommunityFollowerView {
+  fn default_avatars(&mut self) {
+    fill_default_avatar(&mut self.avatar, &self.user_name);
+  }
+}
+
 impl CommunityFollowerView {
   pub fn for_community(conn: &PgConnection, from_community_id: i32) -> Result<Vec<Self>, Error> {
     use super::community_view::community_follower_view::dsl::*;
     community_follower_view
       .filter(community_id.eq(from_community_id))
       .load::<Self>(conn)
+      .map(DefaultAvatars::with_default_avatars)
   }
 
   pub fn for_user(conn: &PgConnection, from_user_id: i32) -> Result<Vec<Self>, Error> {
@@ -305,6 +385,7 @@ impl CommunityFollowerView {
     community_follower_view
       .filter(user_id.eq(from_user_id))
       .load::<Self>(conn)
+      .map(DefaultAvatars::with_default_avatars)
   }
 }
 
@@ -317,17 +398,26 @@ pub struct CommunityUserBanView {
   pub community_id: i32,
   pub user_id: i32,
   pub published: chrono::NaiveDateTime,
+  /// When the ban is lifted, for temporary bans. Expired bans aren't in the view.
+  pub expires: Option<chrono::NaiveDateTime>,
   pub user_name: String,
   pub avatar: Option<String>,
   pub community_name: String,
 }
 
+impl DefaultAvatars for CommunityUserBanView {
+  fn default_avatars(&mut self) {
+    fill_default_avatar(&mut self.avatar, &self.user_name);
+  }
+}
+
 impl CommunityUserBanView {
   pub fn for_community(conn: &PgConnection, from_community_id: i32) -> Result<Vec<Self>, Error> {
     use super::community_view::community_user_ban_view::dsl::*;
     community_user_ban_view
       .filter(community_id.eq(from_community_id))
       .load::<Self>(conn)
+      .map(DefaultAvatars::with_default_avatars)
   }
 
   pub fn for_user(conn: &PgConnection, from_user_id: i32) -> Result<Vec<Self>, Error> {
@@ -335,6 +425,7 @@ impl CommunityUserBanView {
     community_user_ban_view
       .filter(user_id.eq(from_user_id))
       .load::<Self>(conn)
+      .map(DefaultAvatars::with_default_avatars)
   }
 
   pub fn get(
@@ -347,5 +438,6 @@ impl CommunityUserBanView {
       .filter(user_id.eq(from_user_id))
       .filter(community_id.eq(from_community_id))
       .first::<Self>(conn)
+      .map(Self::with_default_avatars)
   }
 }
@@ -0,0 +1,286 @@
+use super::user_activity::ACTIVITY_COUNTS;
+use super::*;
+use crate::naive_now;
+use crate::schema::consistency_check;
+use crate::schema::consistency_check::dsl::*;
+use diesel::sql_types::BigInt;
+
+/// The result of one of the checks of a consistency audit. They look for what the foreign keys
+/// and triggers should rule out, but which a trigger bug or manual surgery on the database can
+/// still leave behind.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "consistency_check"]
+pub struct ConsistencyCheck {
+  pub id: i32,
+  pub name: String,
+  /// How many inconsistent rows it found
+  pub found: i64,
+  pub repaired: bool,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "consistency_check"]
+pub struct ConsistencyCheckForm {
+  pub name: String,
+  pub found: i64,
+  pub repaired: bool,
+  pub published: chrono::NaiveDateTime,
+}
+
+/// A query for the inconsistent rows, and a statement repairing them.
+struct Check {
+  name: String,
+  find: String,
+  repair: String,
+}
+
+#[derive(QueryableByName)]
+struct Inconsistent {
+  #[sql_type = "BigInt"]
+  found: i64,
+}
+
+/// The materialized views of the aggregates: their name in the checks, the table they're of, the
+/// view and its materialized view, and the counts they keep.
+const AGGREGATES: [(&str, &str, &str, &str, &[&str]); 4] = [
+  (
+    "post",
+    "post",
+    "post_aggregates_view",
+    "post_aggregates_mview",
+    &["number_of_comments", "score", "upvotes", "downvotes"],
+  ),
+  (
+    "comment",
+    "comment",
+    "comment_aggregates_view",
+    "comment_aggregates_mview",
+    &["score", "upvotes", "downvotes"],
+  ),
+  (
+    "community",
+    "community",
+    "community_aggregates_view",
+    "community_aggregates_mview",
+    &[
+      "number_of_subscribers",
+      "number_of_posts",
+      "number_of_comments",
+    ],
+  ),
+  (
+    "user",
+    "user_",
+    "user_view",
+    "user_mview",
+    &[
+      "number_of_posts",
+      "post_score",
+      "number_of_comments",
+      "comment_score",
+    ],
+  ),
+];
+
+fn checks() -> Vec<Check> {
+  let mut checks = vec![
+    deleting_check(
+      "comments_on_missing_posts",
+      "comment",
+      "not exists (select 1 from post p where p.id = t.post_id)",
+    ),
+    deleting_check(
+      "followers_of_deleted_communities",
+      "community_follower",
+      "not exists (select 1 from community c where c.id = t.community_id and not c.deleted)",
+    ),
+  ];
+
+  for (aggregate, table, view, mview, counts) in AGGREGATES.iter() {
+    let refresh = format!("refresh materialized view concurrently {}", mview);
+    checks.push(Check {
+      name: format!("orphaned_{}_aggregates", aggregate),
+      find: format!(
+        "select m.id from {mview} m where not exists (select 1 from {table} t where t.id = m.id)
+        union all
+        select t.id from {table} t where not exists (select 1 from {mview} m where m.id = t.id)",
+        table = table,
+        mview = mview
+      ),
+      repair: refresh.clone(),
+    });
+    checks.push(Check {
+      name: format!("mismatched_{}_counts", aggregate),
+      find: format!(
+        "select m.id from {} m join {} v on v.id = m.id where ({}) is distinct from ({})",
+        mview,
+        view,
+        prefixed("m", counts),
+        prefixed("v", counts)
+      ),
+      repair: refresh,
+    });
+  }
+
+  checks.push(Check {
+    name: "mismatched_activity_counts".into(),
+    find: format!(
+      "select user_id from ({}) actual
+      full join (
+        select * from user_activity_day where day > now() - interval '1 year'
+      ) stored using (user_id, day)
+      where coalesce(actual.posts, 0) <> coalesce(stored.posts, 0)
+      or coalesce(actual.comments, 0) <> coalesce(stored.comments, 0)",
+      ACTIVITY_COUNTS
+    ),
+    repair: format!(
+      "with actual as ({}),
+      stale as (
+        delete from user_activity_day d where d.day > now() - interval '1 year'
+        and not exists (select 1 from actual a where a.user_id = d.user_id and a.day = d.day)
+      )
+      insert into user_activity_day (user_id, day, posts, comments)
+      select user_id, day, posts, comments from actual
+      on conflict (user_id, day) do update
+      set posts = excluded.posts, comments = excluded.comments",
+      ACTIVITY_COUNTS
+    ),
+  });
+
+  checks
+}
+
+/// A check for rows of `table` (as `t`) matching `condition`, which are repaired by deleting them.
+fn deleting_check(check_name: &str, table: &str, condition: &str) -> Check {
+  Check {
+    name: check_name.to_owned(),
+    find: format!("select t.id from {} t where {}", table, condition),
+    repair: format!("delete from {} t where {}", table, condition),
+  }
+}
+
+fn prefixed(alias: &str, columns: &[&str]) -> String {
+  columns
+    .iter()
+    .map(|column| format!("{}.{}", alias, column))
+    .collect::<Vec<String>>()
+    .join(", ")
+}
+
+impl ConsistencyCheck {
+  /// Runs every check, repairing what they find if `repair`, and saves the results as an audit.
+  pub fn run_all(conn: &PgConnection, repair: bool) -> Result<Vec<Self>, Error> {
+    let audited = naive_now();
+    checks()
+      .iter()
+      .map(|check| {
+        let inconsistent = sql_query(format!(
+          "select count(*) as found from ({}) inconsistent",
+          check.find
+        ))
+        .get_result::<Inconsistent>(conn)?
+        .found;
+        let repairing = repair && inconsistent > 0;
+        if repairing {
+          sql_query(check.repair.as_str()).execute(conn)?;
+        }
+
+        let form = ConsistencyCheckForm {
+          name: check.name.to_owned(),
+          found: inconsistent,
+          repaired: repairing,
+          published: audited,
+        };
+        insert_into(consistency_check)
+          .values(&form)
+          .get_result::<Self>(conn)
+      })
+      .collect()
+  }
+
+  /// The results of the latest audit, or nothing if there hasn't been one yet.
+  pub fn latest(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    let last_audited = consistency_check
+      .select(max(published))
+      .first::<Option<chrono::NaiveDateTime>>(conn)?;
+    match last_audited {
+      Some(last_audited) => consistency_check
+        .filter(published.eq(last_audited))
+        .order_by(id)
+        .load::<Self>(conn),
+      None => Ok(Vec::new()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::community::*;
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_run_all() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "auditor".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "audited_community".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: Some(true),
+      updated: None,
+      nsfw: false,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let follower_form = CommunityFollowerForm {
+      community_id: inserted_community.id,
+      user_id: inserted_user.id,
+    };
+    CommunityFollower::follow(&conn, &follower_form).unwrap();
+
+    let audit = ConsistencyCheck::run_all(&conn, true).unwrap();
+    let latest = ConsistencyCheck::latest(&conn).unwrap();
+    // Following again only works if the follow was deleted
+    let followed_again = CommunityFollower::follow(&conn, &follower_form);
+
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    let followers = audit
+      .iter()
+      .find(|check| check.name == "followers_of_deleted_communities")
+      .unwrap();
+    assert!(followers.found >= 1);
+    assert!(followers.repaired);
+    assert_eq!(audit, latest);
+    assert!(followed_again.is_ok());
+  }
+}
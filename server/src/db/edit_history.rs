@@ -0,0 +1,214 @@
+use super::*;
+use crate::schema::{comment_edit, post_edit};
+
+/// What a post was before an edit.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "post_edit"]
+pub struct PostEdit {
+  pub id: i32,
+  pub post_id: i32,
+  /// Who made the edit, the creator or a mod or admin
+  pub editor_id: i32,
+  pub name: String,
+  pub url: Option<String>,
+  pub body: Option<String>,
+  /// When it was edited
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "post_edit"]
+pub struct PostEditForm {
+  pub post_id: i32,
+  pub editor_id: i32,
+  pub name: String,
+  pub url: Option<String>,
+  pub body: Option<String>,
+}
+
+/// What a comment was before an edit.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "comment_edit"]
+pub struct CommentEdit {
+  pub id: i32,
+  pub comment_id: i32,
+  /// Who made the edit, the creator or a mod or admin
+  pub editor_id: i32,
+  pub content: String,
+  /// When it was edited
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "comment_edit"]
+pub struct CommentEditForm {
+  pub comment_id: i32,
+  pub editor_id: i32,
+  pub content: String,
+}
+
+impl PostEdit {
+  pub fn create(conn: &PgConnection, form: &PostEditForm) -> Result<Self, Error> {
+    insert_into(post_edit::table)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  /// A post's earlier versions, the latest first.
+  pub fn list_for_post(conn: &PgConnection, for_post_id: i32) -> Result<Vec<Self>, Error> {
+    post_edit::table
+      .filter(post_edit::post_id.eq(for_post_id))
+      .order_by(post_edit::id.desc())
+      .load::<Self>(conn)
+  }
+
+  /// Forgets a post's earlier versions, for when its content is purged.
+  pub fn delete_for_post(conn: &PgConnection, for_post_id: i32) -> Result<usize, Error> {
+    diesel::delete(post_edit::table.filter(post_edit::post_id.eq(for_post_id))).execute(conn)
+  }
+}
+
+impl CommentEdit {
+  pub fn create(conn: &PgConnection, form: &CommentEditForm) -> Result<Self, Error> {
+    insert_into(comment_edit::table)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  /// A comment's earlier versions, the latest first.
+  pub fn list_for_comment(conn: &PgConnection, for_comment_id: i32) -> Result<Vec<Self>, Error> {
+    comment_edit::table
+      .filter(comment_edit::comment_id.eq(for_comment_id))
+      .order_by(comment_edit::id.desc())
+      .load::<Self>(conn)
+  }
+
+  /// Forgets a comment's earlier versions, for when its content is purged.
+  pub fn delete_for_comment(conn: &PgConnection, for_comment_id: i32) -> Result<usize, Error> {
+    diesel::delete(comment_edit::table.filter(comment_edit::comment_id.eq(for_comment_id)))
+      .execute(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::comment::*;
+  use super::super::community::*;
+  use super::super::post::*;
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_edit_history() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "stealth_editor".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_edit_history".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "An edited post".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
+    };
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let comment_form = CommentForm {
+      content: "What I said first".into(),
+      creator_id: inserted_user.id,
+      post_id: inserted_post.id,
+      removed: None,
+      deleted: None,
+      read: None,
+      parent_id: None,
+      updated: None,
+      generated: None,
+      language_id: None,
+    };
+    let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
+
+    let post_edit = |body: &str| PostEditForm {
+      post_id: inserted_post.id,
+      editor_id: inserted_user.id,
+      name: "An edited post".into(),
+      url: None,
+      body: Some(body.into()),
+    };
+    PostEdit::create(&conn, &post_edit("The first body")).unwrap();
+    PostEdit::create(&conn, &post_edit("The second body")).unwrap();
+    let post_edits = PostEdit::list_for_post(&conn, inserted_post.id).unwrap();
+
+    let comment_edit_form = CommentEditForm {
+      comment_id: inserted_comment.id,
+      editor_id: inserted_user.id,
+      content: "What I said first".into(),
+    };
+    let inserted_comment_edit = CommentEdit::create(&conn, &comment_edit_form).unwrap();
+    let comment_edits = CommentEdit::list_for_comment(&conn, inserted_comment.id).unwrap();
+
+    let posts_forgotten = PostEdit::delete_for_post(&conn, inserted_post.id).unwrap();
+    let comments_forgotten = CommentEdit::delete_for_comment(&conn, inserted_comment.id).unwrap();
+
+    Comment::delete(&conn, inserted_comment.id).unwrap();
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    // The latest first
+    let bodies = post_edits
+      .iter()
+      .map(|e| e.body.to_owned().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(vec!["The second body", "The first body"], bodies);
+    assert_eq!(vec![inserted_comment_edit], comment_edits);
+    assert_eq!(2, posts_forgotten);
+    assert_eq!(1, comments_forgotten);
+  }
+}
@@ -0,0 +1,235 @@
+//! Optional encryption at rest for private message content.
+//!
+//! Encrypted content is stored as `enc:v1:<key id>:<base64 of nonce, ciphertext and tag>`, using
+//! AES-256-GCM, with the row's `encrypted` flag set. Rows without it are plaintext, whatever they
+//! look like, so encryption can be turned on without touching existing rows, and
+//! `lemmy_server admin reencrypt` brings them up to the current key.
+
+use crate::settings::{EncryptionConfig, Settings};
+use failure::Error;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashMap;
+
+const PREFIX: &str = "enc:v1:";
+
+pub struct Keyring {
+  /// The id of the key to encrypt with, if encryption is on.
+  current_key: Option<String>,
+  keys: HashMap<String, LessSafeKey>,
+  rng: SystemRandom,
+}
+
+lazy_static! {
+  static ref KEYRING: Keyring =
+    match Keyring::from_config(Settings::get().private_message_encryption.as_ref()) {
+      Ok(keyring) => keyring,
+      Err(e) => panic!("{}", e),
+    };
+}
+
+impl Keyring {
+  pub fn from_config(config: Option<&EncryptionConfig>) -> Result<Self, Error> {
+    let mut keyring = Keyring {
+      current_key: None,
+      keys: HashMap::new(),
+      rng: SystemRandom::new(),
+    };
+
+    let config = match config {
+      Some(config) => config,
+      None => return Ok(keyring),
+    };
+
+    for (key_id, key) in &config.keys {
+      if key_id.contains(':') {
+        return Err(format_err!(
+          "encryption key id {:?} can't contain ':'",
+          key_id
+        ));
+      }
+      let bytes = base64::decode(key)
+        .map_err(|_| format_err!("encryption key {:?} isn't valid base64", key_id))?;
+      let key = UnboundKey::new(&AES_256_GCM, &bytes)
+        .map_err(|_| format_err!("encryption key {:?} must be 32 bytes", key_id))?;
+      keyring
+        .keys
+        .insert(key_id.to_owned(), LessSafeKey::new(key));
+    }
+
+    if !keyring.keys.contains_key(&config.current_key) {
+      return Err(format_err!(
+        "the current encryption key {:?} isn't one of the keys",
+        config.current_key
+      ));
+    }
+    keyring.current_key = Some(config.current_key.to_owned());
+
+    Ok(keyring)
+  }
+
+  /// `plaintext` as it's stored, and whether it's encrypted: with the current key, or not if
+  /// encryption is off.
+  pub fn encrypt(&self, plaintext: &str) -> Result<(String, bool), Error> {
+    let key_id = match &self.current_key {
+      Some(key_id) => key_id,
+      None => return Ok((plaintext.to_owned(), false)),
+    };
+
+    let mut nonce = [0u8; NONCE_LEN];
+    self
+      .rng
+      .fill(&mut nonce)
+      .map_err(|_| format_err!("couldn't generate a nonce"))?;
+
+    let mut sealed = plaintext.as_bytes().to_vec();
+    self.keys[key_id]
+      .seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::empty(),
+        &mut sealed,
+      )
+      .map_err(|_| format_err!("couldn't encrypt"))?;
+
+    let mut stored = nonce.to_vec();
+    stored.append(&mut sealed);
+    let stored = format!("{}{}:{}", PREFIX, key_id, base64::encode(&stored));
+    Ok((stored, true))
+  }
+
+  /// The plaintext of stored content, whichever key it was encrypted with, if it's `encrypted`.
+  pub fn decrypt(&self, stored: &str, encrypted: bool) -> Result<String, Error> {
+    if !encrypted {
+      return Ok(stored.to_owned());
+    }
+    let (key_id, encoded) = split_stored(stored).ok_or_else(|| format_err!("couldn't decode"))?;
+
+    let key = self
+      .keys
+      .get(key_id)
+      .ok_or_else(|| format_err!("no encryption key {:?} to decrypt with", key_id))?;
+
+    let mut sealed = base64::decode(encoded).map_err(|_| format_err!("couldn't decode"))?;
+    if sealed.len() < NONCE_LEN {
+      return Err(format_err!("couldn't decrypt"));
+    }
+    let mut in_out = sealed.split_off(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(&sealed).map_err(|_| format_err!("bad nonce"))?;
+
+    let plaintext = key
+      .open_in_place(nonce, Aad::empty(), &mut in_out)
+      .map_err(|_| format_err!("couldn't decrypt"))?;
+    Ok(String::from_utf8(plaintext.to_vec())?)
+  }
+
+  /// Whether stored content isn't encrypted the way new content would be, ie it's plaintext or
+  /// uses an older key, or it's encrypted but encryption has since been turned off.
+  pub fn needs_reencryption(&self, stored: &str, encrypted: bool) -> bool {
+    let key_id = if encrypted {
+      split_stored(stored).map(|(key_id, _)| key_id)
+    } else {
+      None
+    };
+    key_id != self.current_key.as_deref()
+  }
+}
+
+fn split_stored(stored: &str) -> Option<(&str, &str)> {
+  if !stored.starts_with(PREFIX) {
+    return None;
+  }
+  let mut parts = stored[PREFIX.len()..].splitn(2, ':');
+  match (parts.next(), parts.next()) {
+    (Some(key_id), Some(encoded)) => Some((key_id, encoded)),
+    _ => None,
+  }
+}
+
+/// The keyring from the settings.
+pub fn keyring() -> &'static Keyring {
+  &KEYRING
+}
+
+/// Encrypts content for storage, with the keyring from the settings. Returns it as it's stored, and
+/// whether it's encrypted.
+pub fn encrypt(plaintext: &str) -> Result<(String, bool), diesel::result::Error> {
+  KEYRING
+    .encrypt(plaintext)
+    .map_err(|e| diesel::result::Error::SerializationError(e.to_string().into()))
+}
+
+/// Decrypts stored content, with the keyring from the settings.
+pub fn decrypt(stored: &str, encrypted: bool) -> Result<String, diesel::result::Error> {
+  KEYRING
+    .decrypt(stored, encrypted)
+    .map_err(|e| diesel::result::Error::DeserializationError(e.to_string().into()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn keyring(current_key: &str, keys: &[(&str, &str)]) -> Keyring {
+    let config = EncryptionConfig {
+      current_key: current_key.to_owned(),
+      keys: keys
+        .iter()
+        .map(|(id, key)| (id.to_string(), key.to_string()))
+        .collect(),
+    };
+    Keyring::from_config(Some(&config)).unwrap()
+  }
+
+  const KEY_1: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+  const KEY_2: &str = "Hx4dHBsaGRgXFhUUExIREA8ODQwLCgkIBwYFBAMCAQA=";
+
+  #[test]
+  fn test_encrypt_and_rotate() {
+    let plaintext = "a private message";
+
+    let old = keyring("k1", &[("k1", KEY_1)]);
+    let (stored, encrypted) = old.encrypt(plaintext).unwrap();
+    assert!(encrypted);
+    assert!(stored.starts_with("enc:v1:k1:"));
+    assert!(!stored.contains(plaintext));
+    assert_eq!(plaintext, old.decrypt(&stored, true).unwrap());
+    assert!(!old.needs_reencryption(&stored, true));
+
+    // After rotating, old content still decrypts, but is due for re-encryption
+    let rotated = keyring("k2", &[("k1", KEY_1), ("k2", KEY_2)]);
+    assert_eq!(plaintext, rotated.decrypt(&stored, true).unwrap());
+    assert!(rotated.needs_reencryption(&stored, true));
+
+    // Without the old key, it can't be read
+    let dropped = keyring("k2", &[("k2", KEY_2)]);
+    assert!(dropped.decrypt(&stored, true).is_err());
+
+    // Plaintext passes through
+    let off = Keyring::from_config(None).unwrap();
+    assert_eq!(
+      (plaintext.to_owned(), false),
+      off.encrypt(plaintext).unwrap()
+    );
+    assert_eq!(plaintext, rotated.decrypt(plaintext, false).unwrap());
+    assert!(rotated.needs_reencryption(plaintext, false));
+    assert!(!off.needs_reencryption(plaintext, false));
+  }
+
+  #[test]
+  fn test_plaintext_that_looks_encrypted() {
+    let plaintext = "enc:v1:x:y";
+
+    // It's stored as it is, and read back as it is, whether or not encryption is on
+    let off = Keyring::from_config(None).unwrap();
+    let (stored, encrypted) = off.encrypt(plaintext).unwrap();
+    assert!(!encrypted);
+    assert_eq!(plaintext, off.decrypt(&stored, encrypted).unwrap());
+    assert!(!off.needs_reencryption(&stored, encrypted));
+
+    let on = keyring("k1", &[("k1", KEY_1)]);
+    assert_eq!(plaintext, on.decrypt(&stored, encrypted).unwrap());
+    assert!(on.needs_reencryption(&stored, encrypted));
+    let (stored, encrypted) = on.encrypt(plaintext).unwrap();
+    assert_eq!(plaintext, on.decrypt(&stored, encrypted).unwrap());
+  }
+}
@@ -0,0 +1,197 @@
+use super::*;
+use crate::schema::featured;
+use crate::schema::featured::dsl::*;
+
+/// A post or comment that a user pinned to their profile, where it's shown before the rest.
+/// Exactly one of `post_id` and `comment_id` is set.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "featured"]
+pub struct Featured {
+  pub id: i32,
+  pub user_id: i32,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub position: i32,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "featured"]
+pub struct FeaturedForm {
+  pub user_id: i32,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub position: i32,
+}
+
+impl Featured {
+  pub fn pin(conn: &PgConnection, form: &FeaturedForm) -> Result<Self, Error> {
+    insert_into(featured).values(form).get_result::<Self>(conn)
+  }
+
+  pub fn unpin(conn: &PgConnection, featured_id: i32) -> Result<usize, Error> {
+    diesel::delete(featured.find(featured_id)).execute(conn)
+  }
+
+  /// A user's pins, in the order they're shown in.
+  pub fn list_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    featured
+      .filter(user_id.eq(for_user_id))
+      .order_by((position, id))
+      .load::<Self>(conn)
+  }
+
+  /// Puts a user's pins in the order of `pin_ids`.
+  pub fn reorder(
+    conn: &PgConnection,
+    for_user_id: i32,
+    pin_ids: &[i32],
+  ) -> Result<Vec<Self>, Error> {
+    conn.transaction(|| {
+      for (new_position, pin_id) in pin_ids.iter().enumerate() {
+        diesel::update(
+          featured
+            .filter(id.eq(pin_id))
+            .filter(user_id.eq(for_user_id)),
+        )
+        .set(position.eq(new_position as i32))
+        .execute(conn)?;
+      }
+      Self::list_for_user(conn, for_user_id)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::comment::*;
+  use super::super::community::*;
+  use super::super::post::*;
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_pins() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "pinner".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_pins".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A pinned post".into(),
+      creator_id: inserted_user.id,
+      url: None,
+      body: None,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
+    };
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let comment_form = CommentForm {
+      content: "A pinned comment".into(),
+      creator_id: inserted_user.id,
+      post_id: inserted_post.id,
+      parent_id: None,
+      removed: None,
+      deleted: None,
+      read: None,
+      updated: None,
+      generated: None,
+      language_id: None,
+    };
+    let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
+
+    let post_pin = Featured::pin(
+      &conn,
+      &FeaturedForm {
+        user_id: inserted_user.id,
+        post_id: Some(inserted_post.id),
+        comment_id: None,
+        position: 0,
+      },
+    )
+    .unwrap();
+    let comment_pin = Featured::pin(
+      &conn,
+      &FeaturedForm {
+        user_id: inserted_user.id,
+        post_id: None,
+        comment_id: Some(inserted_comment.id),
+        position: 1,
+      },
+    )
+    .unwrap();
+    let pinned_twice = Featured::pin(
+      &conn,
+      &FeaturedForm {
+        user_id: inserted_user.id,
+        post_id: Some(inserted_post.id),
+        comment_id: None,
+        position: 2,
+      },
+    );
+    let reordered = Featured::reorder(&conn, inserted_user.id, &[comment_pin.id, post_pin.id])
+      .unwrap()
+      .iter()
+      .map(|pin| pin.id)
+      .collect::<Vec<i32>>();
+    let unpinned = Featured::unpin(&conn, post_pin.id).unwrap();
+    let left = Featured::list_for_user(&conn, inserted_user.id).unwrap();
+
+    Comment::delete(&conn, inserted_comment.id).unwrap();
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert!(pinned_twice.is_err());
+    assert_eq!(vec![comment_pin.id, post_pin.id], reordered);
+    assert_eq!(1, unpinned);
+    assert_eq!(1, left.len());
+    assert_eq!(Some(inserted_comment.id), left[0].comment_id);
+  }
+}
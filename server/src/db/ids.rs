@@ -0,0 +1,87 @@
+//! Typed ids, so that a user id can't be passed where a community id was expected.
+//!
+//! They serialize as plain numbers, and map to `int4` columns, so they can be compared against
+//! the schema's id columns directly. There's deliberately no `From<i32>`, so an `i32` only becomes
+//! one by naming the type, like `PostId(id)`, and never through a stray `.into()`.
+//!
+//! They're taken by the view reads and query builders, the helpers that take more than one kind
+//! of id, like `check_mod_or_admin`, and the edit history and apub id requests. The table rows and
+//! the rest of the requests still have plain `i32` ids, which are wrapped where they're passed to
+//! one of those.
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Integer;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Write;
+
+macro_rules! id_type {
+  ($(#[$meta:meta])* $name:ident) => {
+    $(#[$meta])*
+    #[derive(
+      Debug,
+      Clone,
+      Copy,
+      PartialEq,
+      Eq,
+      Hash,
+      PartialOrd,
+      Ord,
+      Serialize,
+      Deserialize,
+      AsExpression,
+      FromSqlRow,
+    )]
+    #[serde(transparent)]
+    #[sql_type = "Integer"]
+    pub struct $name(pub i32);
+
+    impl ToSql<Integer, Pg> for $name {
+      fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<Integer, Pg>::to_sql(&self.0, out)
+      }
+    }
+
+    impl FromSql<Integer, Pg> for $name {
+      fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        <i32 as FromSql<Integer, Pg>>::from_sql(bytes).map($name)
+      }
+    }
+
+    impl fmt::Display for $name {
+      fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+      }
+    }
+  };
+}
+
+id_type!(
+  /// The id of a `post` row.
+  PostId
+);
+id_type!(
+  /// The id of a `comment` row.
+  CommentId
+);
+id_type!(
+  /// The id of a `user_` row.
+  UserId
+);
+id_type!(
+  /// The id of a `community` row.
+  CommunityId
+);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_serialize_as_number() {
+    assert_eq!("42", serde_json::to_string(&PostId(42)).unwrap());
+    assert_eq!(UserId(7), serde_json::from_str("7").unwrap());
+  }
+}
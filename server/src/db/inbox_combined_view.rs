@@ -0,0 +1,325 @@
+use super::encryption;
+use super::*;
+use crate::avatar::fill_default_avatar;
+use crate::schema::user_block;
+use diesel::pg::Pg;
+
+// The faked schema since diesel doesn't do views
+table! {
+  inbox_combined_view (kind, id) {
+    kind -> Int2,
+    id -> Int4,
+    creator_id -> Int4,
+    recipient_id -> Int4,
+    post_id -> Nullable<Int4>,
+    comment_id -> Nullable<Int4>,
+    content -> Text,
+    read -> Bool,
+    deleted -> Bool,
+    published -> Timestamp,
+    updated -> Nullable<Timestamp>,
+    creator_name -> Varchar,
+    creator_avatar -> Nullable<Text>,
+    post_name -> Nullable<Varchar>,
+    encrypted -> Bool,
+  }
+}
+
+/// Which of a user's notifications an inbox item is. Stored as its `i16`.
+#[derive(EnumString, ToString, Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum InboxItemKind {
+  Reply,
+  Mention,
+  PrivateMessage,
+}
+
+/// A reply, mention or private message to a user.
+#[derive(Queryable, PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct InboxCombinedView {
+  pub kind: i16,
+  /// The id of the reply's comment, the user mention, or the private message
+  pub id: i32,
+  pub creator_id: i32,
+  pub recipient_id: i32,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub content: String,
+  pub read: bool,
+  /// Deleted, or removed by a mod
+  pub deleted: bool,
+  pub published: chrono::NaiveDateTime,
+  pub updated: Option<chrono::NaiveDateTime>,
+  pub creator_name: String,
+  pub creator_avatar: Option<String>,
+  pub post_name: Option<String>,
+  /// Whether a private message's content is encrypted at rest. It's decrypted when it's read.
+  #[serde(skip)]
+  pub encrypted: bool,
+}
+
+pub struct InboxQueryBuilder<'a> {
+  conn: &'a PgConnection,
+  query: super::inbox_combined_view::inbox_combined_view::BoxedQuery<'a, Pg>,
+  for_recipient_id: i32,
+  unread_only: bool,
+  page: Option<i64>,
+  limit: Option<i64>,
+}
+
+impl<'a> InboxQueryBuilder<'a> {
+  pub fn create(conn: &'a PgConnection, for_recipient_id: i32) -> Self {
+    use super::inbox_combined_view::inbox_combined_view::dsl::*;
+
+    let query = inbox_combined_view.into_boxed();
+
+    InboxQueryBuilder {
+      conn,
+      query,
+      for_recipient_id,
+      unread_only: false,
+      page: None,
+      limit: None,
+    }
+  }
+
+  pub fn unread_only(mut self, unread_only: bool) -> Self {
+    self.unread_only = unread_only;
+    self
+  }
+
+  pub fn page<T: MaybeOptional<i64>>(mut self, page: T) -> Self {
+    self.page = page.get_optional();
+    self
+  }
+
+  pub fn limit<T: MaybeOptional<i64>>(mut self, limit: T) -> Self {
+    self.limit = limit.get_optional();
+    self
+  }
+
+  /// The items, newest first.
+  pub fn list(self) -> Result<Vec<InboxCombinedView>, Error> {
+    use super::inbox_combined_view::inbox_combined_view::dsl::*;
+
+    let mut query = self
+      .query
+      .filter(recipient_id.eq(self.for_recipient_id))
+      .filter(deleted.eq(false));
+
+    // Not from the users they blocked
+    query = query.filter(
+      creator_id.ne_all(
+        user_block::table
+          .filter(user_block::user_id.eq(self.for_recipient_id))
+          .select(user_block::target_id),
+      ),
+    );
+
+    if self.unread_only {
+      query = query.filter(read.eq(false));
+    }
+
+    let (limit, offset) = limit_and_offset(self.page, self.limit);
+    query
+      .order_by(published.desc())
+      .then_order_by(kind.asc())
+      .then_order_by(id.desc())
+      .limit(limit)
+      .offset(offset)
+      .load::<InboxCombinedView>(self.conn)?
+      .into_iter()
+      .map(|item| item.with_default_avatars().decrypted())
+      .collect()
+  }
+}
+
+impl DefaultAvatars for InboxCombinedView {
+  fn default_avatars(&mut self) {
+    fill_default_avatar(&mut self.creator_avatar, &self.creator_name);
+  }
+}
+
+impl InboxCombinedView {
+  fn decrypted(mut self) -> Result<Self, Error> {
+    self.content = encryption::decrypt(&self.content, self.encrypted)?;
+    self.encrypted = false;
+    Ok(self)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::comment::*;
+  use super::super::community::*;
+  use super::super::post::*;
+  use super::super::private_message::*;
+  use super::super::user::*;
+  use super::super::user_mention::*;
+  use super::super::user_unread_counts_view::*;
+  use super::*;
+
+  #[test]
+  fn test_inbox() {
+    let conn = establish_unpooled_connection();
+
+    let inserted_user = User_::create(&conn, &user_form("inbox_owner")).unwrap();
+    let inserted_sender = User_::create(&conn, &user_form("inbox_sender")).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_inbox_community".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "An inbox test post".into(),
+      creator_id: inserted_user.id,
+      url: None,
+      body: None,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    // A reply to the post, which also mentions its creator
+    let comment_form = CommentForm {
+      content: "A reply to the post".into(),
+      creator_id: inserted_sender.id,
+      post_id: inserted_post.id,
+      removed: None,
+      deleted: None,
+      read: None,
+      parent_id: None,
+      updated: None,
+      generated: None,
+      language_id: None,
+    };
+
+    let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
+
+    let user_mention_form = UserMentionForm {
+      recipient_id: inserted_user.id,
+      comment_id: inserted_comment.id,
+      read: Some(true),
+    };
+
+    let inserted_mention = UserMention::create(&conn, &user_mention_form).unwrap();
+
+    let private_message_form = PrivateMessageForm {
+      creator_id: inserted_sender.id,
+      recipient_id: inserted_user.id,
+      content: Some("A private message".into()),
+      deleted: None,
+      read: None,
+      updated: None,
+    };
+
+    let inserted_private_message = PrivateMessage::create(&conn, &private_message_form).unwrap();
+
+    let inbox = InboxQueryBuilder::create(&conn, inserted_user.id)
+      .list()
+      .unwrap();
+    let unread = InboxQueryBuilder::create(&conn, inserted_user.id)
+      .unread_only(true)
+      .list()
+      .unwrap();
+    let senders_inbox = InboxQueryBuilder::create(&conn, inserted_sender.id)
+      .list()
+      .unwrap();
+    let unread_counts = UserUnreadCountsView::read(&conn, inserted_user.id).unwrap();
+
+    let replies_marked = Comment::update_replies_read(
+      &conn,
+      inserted_user.id,
+      Some(&[inserted_comment.id][..]),
+      true,
+    )
+    .unwrap();
+    let replies_marked_again = Comment::update_replies_read(
+      &conn,
+      inserted_user.id,
+      Some(&[inserted_comment.id][..]),
+      true,
+    )
+    .unwrap();
+    // Only the recipient can mark a message
+    let marked_by_sender = PrivateMessage::update_read(
+      &conn,
+      inserted_sender.id,
+      Some(&[inserted_private_message.id][..]),
+      true,
+    )
+    .unwrap();
+    let messages_marked = PrivateMessage::update_read(&conn, inserted_user.id, None, true).unwrap();
+    let unread_after_marking = InboxQueryBuilder::create(&conn, inserted_user.id)
+      .unread_only(true)
+      .list()
+      .unwrap();
+    let unread_counts_after_marking = UserUnreadCountsView::read(&conn, inserted_user.id).unwrap();
+
+    PrivateMessage::delete(&conn, inserted_private_message.id).unwrap();
+    UserMention::delete(&conn, inserted_mention.id).unwrap();
+    Comment::delete(&conn, inserted_comment.id).unwrap();
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+    User_::delete(&conn, inserted_sender.id).unwrap();
+
+    let kinds: Vec<i16> = inbox.iter().map(|item| item.kind).collect();
+    assert_eq!(
+      vec![
+        InboxItemKind::PrivateMessage as i16,
+        InboxItemKind::Reply as i16,
+        InboxItemKind::Mention as i16,
+      ],
+      kinds
+    );
+    assert_eq!("A private message", inbox[0].content);
+    assert_eq!(Some("An inbox test post".to_string()), inbox[1].post_name);
+    assert_eq!(inserted_mention.id, inbox[2].id);
+
+    // The mention was already read
+    assert_eq!(2, unread.len());
+    assert!(senders_inbox.is_empty());
+    assert_eq!(
+      UserUnreadCountsView {
+        user_id: inserted_user.id,
+        unread_replies: 1,
+        unread_mentions: 0,
+        unread_private_messages: 1,
+      },
+      unread_counts
+    );
+
+    assert_eq!(1, replies_marked);
+    assert_eq!(0, replies_marked_again);
+    assert_eq!(0, marked_by_sender);
+    assert_eq!(1, messages_marked);
+    assert!(unread_after_marking.is_empty());
+    assert_eq!(0, unread_counts_after_marking.unread_replies);
+    assert_eq!(0, unread_counts_after_marking.unread_private_messages);
+  }
+}
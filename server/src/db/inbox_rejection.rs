@@ -0,0 +1,83 @@
+use super::*;
+use crate::naive_now;
+use crate::schema::inbox_rejection;
+use crate::schema::inbox_rejection::dsl::*;
+
+/// How many activities from an instance the shared inbox rejected for one reason.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "inbox_rejection"]
+pub struct InboxRejection {
+  pub id: i32,
+  pub domain: String,
+  pub reason: String,
+  pub count: i32,
+  pub last_rejected: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "inbox_rejection"]
+pub struct InboxRejectionForm {
+  pub domain: String,
+  pub reason: String,
+}
+
+impl InboxRejection {
+  /// Counts one more rejection of an activity from the instance.
+  pub fn record(conn: &PgConnection, form: &InboxRejectionForm) -> Result<Self, Error> {
+    insert_into(inbox_rejection)
+      .values(form)
+      .on_conflict((domain, reason))
+      .do_update()
+      .set((count.eq(count + 1), last_rejected.eq(naive_now())))
+      .get_result::<Self>(conn)
+  }
+
+  /// Every instance's rejections, most recent first.
+  pub fn list(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    inbox_rejection
+      .order_by(last_rejected.desc())
+      .load::<Self>(conn)
+  }
+
+  pub fn delete_for_domain(conn: &PgConnection, for_domain: &str) -> Result<usize, Error> {
+    diesel::delete(inbox_rejection.filter(domain.eq(for_domain))).execute(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_record() {
+    let conn = establish_unpooled_connection();
+    let form = InboxRejectionForm {
+      domain: "rejected.example".into(),
+      reason: "too_deep".into(),
+    };
+
+    let first = InboxRejection::record(&conn, &form).unwrap();
+    let second = InboxRejection::record(&conn, &form).unwrap();
+    let other = InboxRejection::record(
+      &conn,
+      &InboxRejectionForm {
+        reason: "invalid_json".into(),
+        ..form.clone()
+      },
+    )
+    .unwrap();
+    let listed = InboxRejection::list(&conn)
+      .unwrap()
+      .into_iter()
+      .filter(|r| r.domain == form.domain)
+      .count();
+    let deleted = InboxRejection::delete_for_domain(&conn, &form.domain).unwrap();
+
+    assert_eq!(1, first.count);
+    assert_eq!(first.id, second.id);
+    assert_eq!(2, second.count);
+    assert_eq!(1, other.count);
+    assert_eq!(2, listed);
+    assert_eq!(2, deleted);
+  }
+}
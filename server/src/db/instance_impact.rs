@@ -0,0 +1,178 @@
+use super::*;
+use diesel::sql_types::{BigInt, Text};
+
+/// What defederating from an instance would cut off, counted from its users' `fedi_name`.
+/// Communities are counted as the instance's when their creator is from it.
+#[derive(QueryableByName, PartialEq, Debug, Serialize, Deserialize)]
+pub struct InstanceImpact {
+  #[sql_type = "BigInt"]
+  pub users: i64,
+  #[sql_type = "BigInt"]
+  pub communities: i64,
+  /// Local users following its communities
+  #[sql_type = "BigInt"]
+  pub local_followers: i64,
+  #[sql_type = "BigInt"]
+  pub posts: i64,
+  #[sql_type = "BigInt"]
+  pub comments: i64,
+  /// Pairs of a local user and one of its users who have sent each other private messages
+  #[sql_type = "BigInt"]
+  pub private_message_relationships: i64,
+}
+
+impl InstanceImpact {
+  pub fn for_domain(conn: &PgConnection, domain: &str) -> Result<Self, Error> {
+    sql_query(
+      "with remote_user as (select id from user_ where fedi_name = $1),
+      local_user as (select id from user_ where fedi_name = $2),
+      remote_community as (
+        select id from community where creator_id in (select id from remote_user)
+      )
+      select
+      (select count(*) from remote_user) as users,
+      (select count(*) from remote_community) as communities,
+      (
+        select count(distinct cf.user_id) from community_follower cf
+        where cf.community_id in (select id from remote_community)
+        and cf.user_id in (select id from local_user)
+      ) as local_followers,
+      (select count(*) from post where creator_id in (select id from remote_user)) as posts,
+      (select count(*) from comment where creator_id in (select id from remote_user)) as comments,
+      (
+        select count(*) from (
+          select distinct least(pm.creator_id, pm.recipient_id), greatest(pm.creator_id, pm.recipient_id)
+          from private_message pm
+          where (pm.creator_id in (select id from remote_user) and pm.recipient_id in (select id from local_user))
+          or (pm.creator_id in (select id from local_user) and pm.recipient_id in (select id from remote_user))
+        ) pairs
+      ) as private_message_relationships",
+    )
+    .bind::<Text, _>(domain)
+    .bind::<Text, _>(Settings::get().actor_domain())
+    .get_result::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::community::*;
+  use super::super::post::*;
+  use super::super::private_message::*;
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_for_domain() {
+    let conn = establish_unpooled_connection();
+    let domain = "impact.example";
+
+    let remote_user = User_::create(
+      &conn,
+      &UserForm {
+        fedi_name: domain.into(),
+        ..user_form("impact_remote")
+      },
+    )
+    .unwrap();
+    let local_user = User_::create(
+      &conn,
+      &UserForm {
+        fedi_name: Settings::get().actor_domain(),
+        ..user_form("impact_local")
+      },
+    )
+    .unwrap();
+
+    let community = Community::create(
+      &conn,
+      &CommunityForm {
+        name: "impact_community".into(),
+        title: "nada".into(),
+        description: None,
+        category_id: 1,
+        creator_id: remote_user.id,
+        removed: None,
+        deleted: None,
+        updated: None,
+        nsfw: false,
+      },
+    )
+    .unwrap();
+    CommunityFollower::follow(
+      &conn,
+      &CommunityFollowerForm {
+        community_id: community.id,
+        user_id: local_user.id,
+      },
+    )
+    .unwrap();
+
+    Post::create(
+      &conn,
+      &PostForm {
+        name: "A remote post".into(),
+        url: None,
+        body: None,
+        creator_id: remote_user.id,
+        community_id: community.id,
+        removed: None,
+        deleted: None,
+        locked: None,
+        stickied: None,
+        updated: None,
+        nsfw: false,
+        embed_title: None,
+        embed_description: None,
+        embed_html: None,
+        thumbnail_url: None,
+        alt_text: None,
+        visibility: None,
+        generated: None,
+        language_id: None,
+        scheduled_publish_time: None,
+      },
+    )
+    .unwrap();
+
+    // Both ways count as one relationship
+    for (creator_id, recipient_id) in &[
+      (remote_user.id, local_user.id),
+      (local_user.id, remote_user.id),
+    ] {
+      PrivateMessage::create(
+        &conn,
+        &PrivateMessageForm {
+          content: Some("hi".into()),
+          deleted: None,
+          read: None,
+          updated: None,
+          creator_id: *creator_id,
+          recipient_id: *recipient_id,
+        },
+      )
+      .unwrap();
+    }
+
+    let impact = InstanceImpact::for_domain(&conn, domain).unwrap();
+    let no_impact = InstanceImpact::for_domain(&conn, "nowhere.example").unwrap();
+
+    Community::delete(&conn, community.id).unwrap();
+    User_::delete(&conn, remote_user.id).unwrap();
+    User_::delete(&conn, local_user.id).unwrap();
+
+    assert_eq!(
+      InstanceImpact {
+        users: 1,
+        communities: 1,
+        local_followers: 1,
+        posts: 1,
+        comments: 0,
+        private_message_relationships: 1,
+      },
+      impact
+    );
+    assert_eq!(0, no_impact.users);
+    assert_eq!(0, no_impact.posts);
+  }
+}
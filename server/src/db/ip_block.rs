@@ -0,0 +1,189 @@
+use super::*;
+use crate::naive_now;
+use crate::schema::ip_block;
+use crate::schema::ip_block::dsl::*;
+use diesel::dsl::sql;
+use diesel::sql_types::{Bool, Text};
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "ip_block"]
+pub struct IpBlock {
+  pub id: i32,
+  pub cidr: Option<String>,
+  pub asn: Option<i32>,
+  pub reason: Option<String>,
+  pub block_posting: bool,
+  pub hits: i32,
+  pub last_hit: Option<chrono::NaiveDateTime>,
+  pub creator_id: i32,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone, Serialize, Deserialize)]
+#[table_name = "ip_block"]
+pub struct IpBlockForm {
+  pub cidr: Option<String>,
+  pub asn: Option<i32>,
+  pub reason: Option<String>,
+  pub block_posting: bool,
+  pub creator_id: i32,
+}
+
+/// Rows per insert when importing a list, well under postgres' limit on bind parameters
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+impl Crud<IpBlockForm> for IpBlock {
+  fn read(conn: &PgConnection, ip_block_id: i32) -> Result<Self, Error> {
+    ip_block.find(ip_block_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, ip_block_id: i32) -> Result<usize, Error> {
+    diesel::delete(ip_block.find(ip_block_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &IpBlockForm) -> Result<Self, Error> {
+    insert_into(ip_block).values(form).get_result::<Self>(conn)
+  }
+
+  fn update(conn: &PgConnection, ip_block_id: i32, form: &IpBlockForm) -> Result<Self, Error> {
+    diesel::update(ip_block.find(ip_block_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl IpBlock {
+  pub fn list_all(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    ip_block.order_by(published.desc()).load::<Self>(conn)
+  }
+
+  /// Adds blocks, skipping networks which are already blocked. Returns how many were added.
+  pub fn create_many(conn: &PgConnection, forms: &[IpBlockForm]) -> Result<usize, Error> {
+    let mut added = 0;
+    for batch in forms.chunks(IMPORT_BATCH_SIZE) {
+      added += insert_into(ip_block)
+        .values(batch)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    }
+    Ok(added)
+  }
+
+  /// The ids of the blocks covering an address, or the autonomous system it belongs to.
+  pub fn matching(
+    conn: &PgConnection,
+    ip: &str,
+    ip_asn: Option<i32>,
+    posting: bool,
+  ) -> Result<Vec<i32>, Error> {
+    let covers_ip = sql::<Bool>("cidr::cidr >>= ")
+      .bind::<Text, _>(ip)
+      .sql("::inet");
+    let mut query = ip_block
+      .select(id)
+      .filter(covers_ip.or(asn.eq(ip_asn)))
+      .into_boxed();
+
+    if posting {
+      query = query.filter(block_posting.eq(true));
+    }
+
+    query.load::<i32>(conn)
+  }
+
+  pub fn record_hits(conn: &PgConnection, ip_block_ids: &[i32]) -> Result<usize, Error> {
+    diesel::update(ip_block.filter(id.eq_any(ip_block_ids)))
+      .set((hits.eq(hits + 1), last_hit.eq(naive_now())))
+      .execute(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_matching() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "ip_block_admin".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: true,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let form = |network_cidr: Option<&str>, network_asn: Option<i32>, posting: bool| IpBlockForm {
+      cidr: network_cidr.map(String::from),
+      asn: network_asn,
+      reason: None,
+      block_posting: posting,
+      creator_id: inserted_user.id,
+    };
+    let forms = vec![
+      form(Some("192.0.2.0/24"), None, false),
+      form(Some("2001:db8::/32"), None, true),
+      form(None, Some(64496), true),
+      form(Some("192.0.2.0/24"), None, true),
+    ];
+    // The duplicate network is skipped
+    assert_eq!(3, IpBlock::create_many(&conn, &forms).unwrap());
+    let blocks = IpBlock::list_all(&conn)
+      .unwrap()
+      .into_iter()
+      .filter(|b| b.creator_id == inserted_user.id)
+      .collect::<Vec<_>>();
+    let id_of = |network_cidr: Option<&str>| {
+      blocks
+        .iter()
+        .find(|b| b.cidr.as_deref() == network_cidr)
+        .unwrap()
+        .id
+    };
+
+    let matching = |ip: &str, ip_asn: Option<i32>, posting: bool| {
+      IpBlock::matching(&conn, ip, ip_asn, posting).unwrap()
+    };
+    assert_eq!(
+      vec![id_of(Some("192.0.2.0/24"))],
+      matching("192.0.2.7", None, false)
+    );
+    assert!(matching("192.0.2.7", None, true).is_empty());
+    assert!(matching("192.0.3.7", None, false).is_empty());
+    assert_eq!(
+      vec![id_of(Some("2001:db8::/32"))],
+      matching("2001:db8::1", None, true)
+    );
+    assert_eq!(
+      vec![id_of(None)],
+      matching("198.51.100.1", Some(64496), true)
+    );
+
+    let hit = id_of(Some("192.0.2.0/24"));
+    IpBlock::record_hits(&conn, &[hit]).unwrap();
+    let read_block = IpBlock::read(&conn, hit).unwrap();
+    assert_eq!(1, read_block.hits);
+    assert!(read_block.last_hit.is_some());
+
+    User_::delete(&conn, inserted_user.id).unwrap();
+    assert!(IpBlock::list_all(&conn)
+      .unwrap()
+      .iter()
+      .all(|b| b.creator_id != inserted_user.id));
+  }
+}
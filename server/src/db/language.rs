@@ -0,0 +1,124 @@
+use super::*;
+use crate::schema::language::dsl::*;
+use crate::schema::{language, user_language};
+
+/// A language that posts and comments can be in.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[table_name = "language"]
+pub struct Language {
+  pub id: i32,
+  /// Its ISO 639 code
+  pub code: String,
+  pub name: String,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "user_language"]
+pub struct UserLanguageForm {
+  pub user_id: i32,
+  pub language_id: i32,
+}
+
+impl Language {
+  pub fn read(conn: &PgConnection, language_id: i32) -> Result<Self, Error> {
+    language.find(language_id).first::<Self>(conn)
+  }
+
+  /// All of them, by name.
+  pub fn list_all(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    language.order_by(name).load::<Self>(conn)
+  }
+
+  /// How many of these ids are languages.
+  pub fn count_existing(conn: &PgConnection, language_ids: &[i32]) -> Result<i64, Error> {
+    language
+      .filter(id.eq_any(language_ids))
+      .count()
+      .get_result::<i64>(conn)
+  }
+
+  /// The languages a user wants to see. None of them means all of them.
+  pub fn ids_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<i32>, Error> {
+    user_language::table
+      .filter(user_language::user_id.eq(for_user_id))
+      .select(user_language::language_id)
+      .order_by(user_language::language_id)
+      .load::<i32>(conn)
+  }
+
+  /// Replaces all of a user's languages with these.
+  pub fn replace_for_user(
+    conn: &PgConnection,
+    for_user_id: i32,
+    language_ids: &[i32],
+  ) -> Result<usize, Error> {
+    let forms = language_ids
+      .iter()
+      .map(|&language_id| UserLanguageForm {
+        user_id: for_user_id,
+        language_id,
+      })
+      .collect::<Vec<_>>();
+    transaction_with_retry(conn, || -> Result<usize, Error> {
+      diesel::delete(user_language::table.filter(user_language::user_id.eq(for_user_id)))
+        .execute(conn)?;
+      insert_into(user_language::table)
+        .values(&forms)
+        .on_conflict_do_nothing()
+        .execute(conn)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_user_languages() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "polyglot".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let languages = Language::list_all(&conn).unwrap();
+    let english = languages.iter().find(|l| l.code == "en").unwrap().id;
+    let german = languages.iter().find(|l| l.code == "de").unwrap().id;
+
+    let none_picked = Language::ids_for_user(&conn, inserted_user.id).unwrap();
+    Language::replace_for_user(&conn, inserted_user.id, &[german, english, english]).unwrap();
+    let picked = Language::ids_for_user(&conn, inserted_user.id).unwrap();
+    Language::replace_for_user(&conn, inserted_user.id, &[german]).unwrap();
+    let replaced = Language::ids_for_user(&conn, inserted_user.id).unwrap();
+    let existing = Language::count_existing(&conn, &[english, -1]).unwrap();
+
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!("English", Language::read(&conn, english).unwrap().name);
+    assert!(none_picked.is_empty());
+    let mut expected = vec![english, german];
+    expected.sort();
+    assert_eq!(expected, picked);
+    assert_eq!(vec![german], replaced);
+    assert_eq!(1, existing);
+  }
+}
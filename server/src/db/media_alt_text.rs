@@ -0,0 +1,95 @@
+use super::*;
+use crate::naive_now;
+use crate::schema::media_alt_text;
+use crate::schema::media_alt_text::dsl::*;
+
+/// The alt text of an uploaded image, by its url, which is used for posts of that image.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "media_alt_text"]
+pub struct MediaAltText {
+  pub id: i32,
+  pub url: String,
+  pub alt_text: String,
+  pub creator_id: i32,
+  pub published: chrono::NaiveDateTime,
+  pub updated: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "media_alt_text"]
+pub struct MediaAltTextForm {
+  pub url: String,
+  pub alt_text: String,
+  pub creator_id: i32,
+}
+
+impl MediaAltText {
+  pub fn read_for_url(conn: &PgConnection, for_url: &str) -> Result<Self, Error> {
+    media_alt_text.filter(url.eq(for_url)).first::<Self>(conn)
+  }
+
+  /// Saves the alt text of an image, replacing the one it had.
+  pub fn save(conn: &PgConnection, form: &MediaAltTextForm) -> Result<Self, Error> {
+    insert_into(media_alt_text)
+      .values(form)
+      .on_conflict(url)
+      .do_update()
+      .set((alt_text.eq(&form.alt_text), updated.eq(naive_now())))
+      .get_result::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "alt_text_user".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let form = MediaAltTextForm {
+      url: "https://example.com/pictshare/cat.png".into(),
+      alt_text: "A cat".into(),
+      creator_id: inserted_user.id,
+    };
+    let saved = MediaAltText::save(&conn, &form).unwrap();
+    let resaved = MediaAltText::save(
+      &conn,
+      &MediaAltTextForm {
+        alt_text: "A sleeping cat".into(),
+        ..form.clone()
+      },
+    )
+    .unwrap();
+    let read = MediaAltText::read_for_url(&conn, &form.url).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!("A cat", saved.alt_text);
+    assert_eq!(saved.id, resaved.id);
+    assert_eq!("A sleeping cat", read.alt_text);
+    assert!(read.updated.is_some());
+  }
+}
@@ -0,0 +1,72 @@
+use super::*;
+use crate::schema::media_variant;
+use crate::schema::media_variant::dsl::*;
+
+/// The image that's served for an uploaded one, and the original, if it was kept.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "media_variant"]
+pub struct MediaVariant {
+  pub id: i32,
+  pub url: String,
+  pub original_url: Option<String>,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "media_variant"]
+pub struct MediaVariantForm {
+  pub url: String,
+  pub original_url: Option<String>,
+}
+
+impl MediaVariant {
+  pub fn read_for_url(conn: &PgConnection, for_url: &str) -> Result<Self, Error> {
+    media_variant.filter(url.eq(for_url)).first::<Self>(conn)
+  }
+
+  /// Saves a served image, replacing the original it had.
+  pub fn save(conn: &PgConnection, form: &MediaVariantForm) -> Result<Self, Error> {
+    insert_into(media_variant)
+      .values(form)
+      .on_conflict(url)
+      .do_update()
+      .set(original_url.eq(&form.original_url))
+      .get_result::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_save() {
+    let conn = establish_unpooled_connection();
+
+    let form = MediaVariantForm {
+      url: "https://my_domain/pictshare/1920x1920/webp/variant.jpg".into(),
+      original_url: None,
+    };
+    let inserted = MediaVariant::save(&conn, &form).unwrap();
+    let saved = MediaVariant::save(
+      &conn,
+      &MediaVariantForm {
+        original_url: Some("https://my_domain/pictshare/variant.jpg".into()),
+        ..form.clone()
+      },
+    )
+    .unwrap();
+    let read = MediaVariant::read_for_url(&conn, &form.url).unwrap();
+    diesel::delete(media_variant.find(inserted.id))
+      .execute(&conn)
+      .unwrap();
+
+    assert_eq!(None, inserted.original_url);
+    assert_eq!(inserted.id, saved.id);
+    assert_eq!(
+      Some("https://my_domain/pictshare/variant.jpg".into()),
+      saved.original_url
+    );
+    assert_eq!(saved, read);
+  }
+}
@@ -1,26 +1,160 @@
+use crate::db::ids::{CommunityId, UserId};
 use crate::settings::Settings;
 use diesel::dsl::*;
-use diesel::result::Error;
+use diesel::result::{DatabaseErrorKind, Error};
 use diesel::*;
 use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::Duration;
 
+sql_function!(fn controversy_rank(upvotes: diesel::sql_types::Nullable<diesel::sql_types::BigInt>, downvotes: diesel::sql_types::Nullable<diesel::sql_types::BigInt>) -> diesel::sql_types::Double);
+
+/// Applies a builder's `ViewerParams` to a query on a view with `user_id`, `creator_id`,
+/// `community_id`, `subscribed` and `saved` columns. The view's dsl has to be in scope.
+macro_rules! filter_for_viewer {
+  ($query:expr, $viewer:expr) => {{
+    let viewer = $viewer;
+    let mut query = $query;
+
+    // The view lets you pass a null user_id, if you're not logged in
+    query = if let Some(my_user_id) = viewer.my_user_id {
+      query.filter(user_id.eq(my_user_id))
+    } else {
+      query.filter(user_id.is_null())
+    };
+
+    // Leave out what the users the viewer blocked made, or only show that for their block list
+    if let Some(my_user_id) = viewer.my_user_id {
+      let blocked = crate::schema::user_block::table
+        .filter(crate::schema::user_block::user_id.eq(my_user_id))
+        .select(crate::schema::user_block::target_id);
+      query = if viewer.blocked_only {
+        query.filter(creator_id.eq_any(blocked))
+      } else {
+        query.filter(creator_id.ne_all(blocked))
+      };
+    }
+
+    if let ListingType::Subscribed = viewer.listing_type {
+      query = query.filter(subscribed.eq(true));
+    }
+
+    if let Some(for_community_id) = viewer.for_community_id {
+      query = query.filter(community_id.eq(for_community_id));
+    }
+
+    // TODO these are wrong, bc they'll only show saved for your logged in user, not theirs
+    if viewer.saved_only {
+      query = query.filter(saved.eq(true));
+    }
+
+    query
+  }};
+}
+
+/// Matches the rows of `$table` whose `search_vector` has the words of the search term `$q`, which
+/// can use the operators of web searches, like quotes and `-`.
+macro_rules! full_text_match {
+  ($table:expr, $q:expr) => {
+    diesel::dsl::sql::<diesel::sql_types::Bool>(concat!(
+      $table,
+      ".search_vector @@ websearch_to_tsquery('english', "
+    ))
+    .bind::<diesel::sql_types::Text, _>($q)
+    .sql(")")
+  };
+}
+
+/// Whether the `$table` row of each row of `$view` has a word the user muted, or its `$text`
+/// matches one of their regular expressions.
+macro_rules! keyword_muted {
+  ($table:expr, $view:expr, $text:expr, $user_id:expr) => {
+    diesel::dsl::sql::<diesel::sql_types::Bool>(concat!(
+      "exists (select 1 from user_keyword_filter f join ",
+      $table,
+      " t on t.id = ",
+      $view,
+      ".id where f.user_id = "
+    ))
+    .bind::<diesel::sql_types::Integer, _>($user_id)
+    .sql(concat!(
+      " and (t.search_vector @@ f.query or (f.regex and ",
+      $text,
+      " ~* f.keyword)))"
+    ))
+  };
+}
+
+/// How well the `$table` row of each row of `$view` matches the search term `$q`, for ordering by.
+macro_rules! full_text_rank {
+  ($table:expr, $view:expr, $q:expr) => {
+    diesel::dsl::sql::<diesel::sql_types::Float>(
+      "(select ts_rank(t.search_vector, websearch_to_tsquery('english', ",
+    )
+    .bind::<diesel::sql_types::Text, _>($q)
+    .sql(concat!(
+      ")) from ",
+      $table,
+      " t where t.id = ",
+      $view,
+      ".id)"
+    ))
+  };
+}
+
+pub mod api_token;
 pub mod category;
 pub mod comment;
 pub mod comment_view;
 pub mod community;
+pub mod community_block;
+pub mod community_growth;
 pub mod community_view;
+pub mod consistency;
+pub mod edit_history;
+pub mod encryption;
+pub mod featured;
+pub mod ids;
+pub mod inbox_combined_view;
+pub mod inbox_rejection;
+pub mod instance_impact;
+pub mod ip_block;
+pub mod language;
+pub mod media_alt_text;
+pub mod media_variant;
+pub mod mod_digest;
+pub mod mod_note;
+pub mod moderation_notice;
 pub mod moderator;
 pub mod moderator_views;
+pub mod oauth;
 pub mod password_reset_request;
 pub mod post;
+pub mod post_activity_hour;
+pub mod post_attachment;
 pub mod post_view;
+pub mod post_view_count;
 pub mod private_message;
 pub mod private_message_view;
+#[cfg(test)]
+pub mod query_plan;
+pub mod report;
+pub mod reserved_community_name;
+pub mod saved_folder;
+pub mod saved_items_view;
+pub mod scheduled_post;
 pub mod site;
 pub mod site_view;
+pub mod sitemap;
+pub mod translation;
 pub mod user;
+pub mod user_activity;
+pub mod user_block;
+pub mod user_instance_block;
+pub mod user_keyword_filter;
 pub mod user_mention;
 pub mod user_mention_view;
+pub mod user_unread_counts_view;
 pub mod user_view;
 
 pub trait Crud<T> {
@@ -111,6 +245,57 @@ impl<T> MaybeOptional<T> for Option<T> {
   }
 }
 
+/// How many times `transaction_with_retry` runs a transaction before giving up.
+const TRANSACTION_ATTEMPTS: u64 = 3;
+
+/// Errors which might only mean the transaction lost a race with another one, so that running it
+/// again could succeed.
+pub trait Retryable {
+  fn is_retryable(&self) -> bool;
+}
+
+impl Retryable for Error {
+  fn is_retryable(&self) -> bool {
+    match self {
+      Error::DatabaseError(DatabaseErrorKind::SerializationFailure, _) => true,
+      // Diesel doesn't have a kind for deadlocks
+      Error::DatabaseError(_, info) => info.message().starts_with("deadlock detected"),
+      _ => false,
+    }
+  }
+}
+
+impl Retryable for failure::Error {
+  fn is_retryable(&self) -> bool {
+    self
+      .downcast_ref::<Error>()
+      .map(Retryable::is_retryable)
+      .unwrap_or(false)
+  }
+}
+
+/// Runs `f` in a transaction, running it again from the start if it fails with a serialization
+/// failure or a deadlock.
+///
+/// `f` can run more than once, so it shouldn't do anything outside of the database, like sending
+/// emails.
+pub fn transaction_with_retry<T, E, F>(conn: &PgConnection, mut f: F) -> Result<T, E>
+where
+  E: From<Error> + Retryable,
+  F: FnMut() -> Result<T, E>,
+{
+  let mut attempt = 1;
+  loop {
+    match conn.transaction(|| f()) {
+      Err(e) if e.is_retryable() && attempt < TRANSACTION_ATTEMPTS => {
+        thread::sleep(Duration::from_millis(10 * attempt));
+        attempt += 1;
+      }
+      result => return result,
+    }
+  }
+}
+
 pub fn establish_unpooled_connection() -> PgConnection {
   let db_url = Settings::get().get_database_url();
   PgConnection::establish(&db_url).unwrap_or_else(|_| panic!("Error connecting to {}", db_url))
@@ -125,6 +310,11 @@ pub enum SortType {
   TopMonth,
   TopYear,
   TopAll,
+  Rising,
+  /// Lots of votes, split close to evenly between up and down.
+  Controversial,
+  /// The most viewed posts of all time. Only for posts, the others treat it as `TopAll`.
+  TopViews,
 }
 
 #[derive(EnumString, ToString, Debug, Serialize, Deserialize)]
@@ -132,6 +322,9 @@ pub enum ListingType {
   All,
   Subscribed,
   Community,
+  /// The subscribed posts, with a percentage of others mixed in. Only for posts, the other
+  /// listings treat it as `All`.
+  Blended,
 }
 
 #[derive(EnumString, ToString, Debug, Serialize, Deserialize)]
@@ -144,6 +337,125 @@ pub enum SearchType {
   Url,
 }
 
+/// Who can see a post, stored as its `i16`.
+#[derive(EnumString, ToString, Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum PostVisibility {
+  /// In the listings, for everyone
+  Public,
+  /// Left out of the listings, but anyone with the link can see it
+  Unlisted,
+  /// Only for the community's subscribers
+  FollowersOnly,
+}
+
+impl PostVisibility {
+  pub fn from_i16(visibility: i16) -> Self {
+    match visibility {
+      1 => PostVisibility::Unlisted,
+      2 => PostVisibility::FollowersOnly,
+      _ => PostVisibility::Public,
+    }
+  }
+}
+
+/// What a post attachment is, stored as its `i16`.
+#[derive(EnumString, ToString, Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum PostAttachmentKind {
+  Image,
+  Video,
+  Audio,
+}
+
+impl PostAttachmentKind {
+  pub fn from_i16(kind: i16) -> Self {
+    match kind {
+      1 => PostAttachmentKind::Video,
+      2 => PostAttachmentKind::Audio,
+      _ => PostAttachmentKind::Image,
+    }
+  }
+}
+
+/// How far a removal of remote content, or a ban of a remote user, goes. Stored as its `i16` in the
+/// modlog.
+#[derive(EnumString, ToString, Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum RemoteModerationScope {
+  /// Only on this instance
+  Local,
+  /// Also reported to the content's own instance with a `Flag`
+  Flag,
+}
+
+impl RemoteModerationScope {
+  pub fn from_i16(scope: i16) -> Self {
+    match scope {
+      1 => RemoteModerationScope::Flag,
+      _ => RemoteModerationScope::Local,
+    }
+  }
+}
+
+/// Who sees the vote scores of posts, comments and users. Stored as its `i16` on the site.
+#[derive(EnumString, ToString, Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum ScoreVisibility {
+  /// Everyone but the users who turned them off
+  UserChoice,
+  Shown,
+  Hidden,
+}
+
+/// Views with vote scores in them, which are left out for the users who don't see them.
+pub trait HideScores {
+  fn hide_scores(&mut self);
+}
+
+impl<T: HideScores> HideScores for Vec<T> {
+  fn hide_scores(&mut self) {
+    for view in self.iter_mut() {
+      view.hide_scores();
+    }
+  }
+}
+
+/// Views with the avatars of users in them, which have the url of a user's generated avatar when
+/// they don't have one.
+pub trait DefaultAvatars {
+  fn default_avatars(&mut self);
+
+  fn with_default_avatars(mut self) -> Self
+  where
+    Self: Sized,
+  {
+    self.default_avatars();
+    self
+  }
+}
+
+impl<T: DefaultAvatars> DefaultAvatars for Vec<T> {
+  fn default_avatars(&mut self) {
+    for view in self.iter_mut() {
+      view.default_avatars();
+    }
+  }
+}
+
+impl ScoreVisibility {
+  pub fn from_i16(visibility: i16) -> Option<Self> {
+    match visibility {
+      0 => Some(ScoreVisibility::UserChoice),
+      1 => Some(ScoreVisibility::Shown),
+      2 => Some(ScoreVisibility::Hidden),
+      _ => None,
+    }
+  }
+}
+
+/// Whether a search term is matched as words, with the full text search, or as a substring, for
+/// terms too short to be words.
+pub fn is_full_text_search(q: &str) -> bool {
+  q.trim().chars().count() >= Settings::get().search.substring_below_length
+}
+
 pub fn fuzzy_search(q: &str) -> String {
   let replaced = q.replace(" ", "%");
   format!("%{}%", replaced)
@@ -155,12 +467,150 @@ pub fn limit_and_offset(page: Option<i64>, limit: Option<i64>) -> (i64, i64) {
   let offset = limit * (page - 1);
   (limit, offset)
 }
+
+/// The paging and sorting every listing has.
+pub struct ListingParams<'a> {
+  pub sort: &'a SortType,
+  pub page: Option<i64>,
+  pub limit: Option<i64>,
+}
+
+impl<'a> ListingParams<'a> {
+  pub fn new(sort: &'a SortType) -> Self {
+    ListingParams {
+      sort,
+      page: None,
+      limit: None,
+    }
+  }
+
+  pub fn limit_and_offset(&self) -> (i64, i64) {
+    limit_and_offset(self.page, self.limit)
+  }
+}
+
+/// Who's looking at a listing, and which of their communities and saves it's limited to.
+pub struct ViewerParams {
+  pub listing_type: ListingType,
+  pub my_user_id: Option<UserId>,
+  pub for_community_id: Option<CommunityId>,
+  pub saved_only: bool,
+  pub blocked_only: bool,
+}
+
+impl Default for ViewerParams {
+  fn default() -> Self {
+    ViewerParams {
+      listing_type: ListingType::All,
+      my_user_id: None,
+      for_community_id: None,
+      saved_only: false,
+      blocked_only: false,
+    }
+  }
+}
+
+/// A query builder for one of the views, with the setters they all share.
+pub trait ListingQueryBuilder<'a>: Sized {
+  type View;
+
+  fn listing_params(&mut self) -> &mut ListingParams<'a>;
+
+  fn list(self) -> Result<Vec<Self::View>, Error>;
+
+  fn sort(mut self, sort: &'a SortType) -> Self {
+    self.listing_params().sort = sort;
+    self
+  }
+
+  fn page<T: MaybeOptional<i64>>(mut self, page: T) -> Self {
+    self.listing_params().page = page.get_optional();
+    self
+  }
+
+  fn limit<T: MaybeOptional<i64>>(mut self, limit: T) -> Self {
+    self.listing_params().limit = limit.get_optional();
+    self
+  }
+}
+
+/// A query builder for a view that joins in the logged in user's votes, subscriptions and saves.
+///
+/// The params are applied with `filter_for_viewer!`, so they mean the same thing for every view.
+pub trait ViewerQueryBuilder<'a>: ListingQueryBuilder<'a> {
+  fn viewer_params(&mut self) -> &mut ViewerParams;
+
+  fn listing_type(mut self, listing_type: ListingType) -> Self {
+    self.viewer_params().listing_type = listing_type;
+    self
+  }
+
+  fn my_user_id<T: MaybeOptional<UserId>>(mut self, my_user_id: T) -> Self {
+    self.viewer_params().my_user_id = my_user_id.get_optional();
+    self
+  }
+
+  fn for_community_id<T: MaybeOptional<CommunityId>>(mut self, for_community_id: T) -> Self {
+    self.viewer_params().for_community_id = for_community_id.get_optional();
+    self
+  }
+
+  fn saved_only(mut self, saved_only: bool) -> Self {
+    self.viewer_params().saved_only = saved_only;
+    self
+  }
+
+  /// Only what the users the viewer blocked made, which is otherwise left out.
+  fn blocked_only(mut self, blocked_only: bool) -> Self {
+    self.viewer_params().blocked_only = blocked_only;
+    self
+  }
+}
+
 #[cfg(test)]
 mod tests {
-  use super::fuzzy_search;
+  use super::*;
   #[test]
   fn test_fuzzy_search() {
     let test = "This is a fuzzy search";
     assert_eq!(fuzzy_search(test), "%This%is%a%fuzzy%search%".to_string());
   }
+
+  #[test]
+  fn test_transaction_with_retry() {
+    let conn = establish_unpooled_connection();
+    let serialization_failure = || {
+      Error::DatabaseError(
+        DatabaseErrorKind::SerializationFailure,
+        Box::new("could not serialize access due to concurrent update".to_string()),
+      )
+    };
+
+    let mut attempts = 0;
+    let retried: Result<u64, Error> = transaction_with_retry(&conn, || {
+      attempts += 1;
+      if attempts < TRANSACTION_ATTEMPTS {
+        Err(serialization_failure())
+      } else {
+        Ok(attempts)
+      }
+    });
+    assert_eq!(TRANSACTION_ATTEMPTS, retried.unwrap());
+
+    let mut attempts = 0;
+    let gave_up: Result<(), Error> = transaction_with_retry(&conn, || {
+      attempts += 1;
+      Err(serialization_failure())
+    });
+    assert!(gave_up.unwrap_err().is_retryable());
+    assert_eq!(TRANSACTION_ATTEMPTS, attempts);
+
+    let mut attempts = 0;
+    let not_retried: Result<(), Error> = transaction_with_retry(&conn, || {
+      attempts += 1;
+      Err(Error::NotFound)
+    });
+    assert!(not_retried.is_err());
+    assert_eq!(1, attempts);
+  }
 }
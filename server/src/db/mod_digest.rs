@@ -0,0 +1,144 @@
+use super::*;
+use crate::naive_now;
+use crate::schema::mod_digest;
+use crate::schema::mod_digest::dsl::*;
+
+/// A moderator getting a digest of a community's most reported and fastest growing posts every
+/// few days, as a moderation notice.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "mod_digest"]
+pub struct ModDigest {
+  pub id: i32,
+  pub user_id: i32,
+  pub community_id: i32,
+  pub interval_days: i16,
+  pub next_send: chrono::NaiveDateTime,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "mod_digest"]
+pub struct ModDigestForm {
+  pub user_id: i32,
+  pub community_id: i32,
+  pub interval_days: i16,
+  pub next_send: chrono::NaiveDateTime,
+}
+
+impl ModDigest {
+  /// Subscribes a moderator to a community's digest, or changes how often they get it.
+  pub fn save(conn: &PgConnection, form: &ModDigestForm) -> Result<Self, Error> {
+    insert_into(mod_digest)
+      .values(form)
+      .on_conflict((user_id, community_id))
+      .do_update()
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+
+  pub fn remove(
+    conn: &PgConnection,
+    for_user_id: i32,
+    for_community_id: i32,
+  ) -> Result<usize, Error> {
+    diesel::delete(
+      mod_digest
+        .filter(user_id.eq(for_user_id))
+        .filter(community_id.eq(for_community_id)),
+    )
+    .execute(conn)
+  }
+
+  pub fn list_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    mod_digest
+      .filter(user_id.eq(for_user_id))
+      .order_by(community_id)
+      .load::<Self>(conn)
+  }
+
+  /// The digests that are due to be sent, the longest overdue first.
+  pub fn list_due(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    mod_digest
+      .filter(next_send.le(naive_now()))
+      .order_by(next_send)
+      .load::<Self>(conn)
+  }
+
+  /// Moves a digest on to its next time, a whole interval from now.
+  pub fn record_sent(conn: &PgConnection, digest: &Self) -> Result<Self, Error> {
+    let new_next_send = naive_now() + chrono::Duration::days(digest.interval_days.into());
+    diesel::update(mod_digest.find(digest.id))
+      .set(next_send.eq(new_next_send))
+      .get_result::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::community::*;
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_save() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "digest_reader".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "digest_community".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let form = |days: i16| ModDigestForm {
+      user_id: inserted_user.id,
+      community_id: inserted_community.id,
+      interval_days: days,
+      next_send: naive_now(),
+    };
+    ModDigest::save(&conn, &form(1)).unwrap();
+    let changed = ModDigest::save(&conn, &form(7)).unwrap();
+    let due = ModDigest::list_due(&conn).unwrap();
+    let sent = ModDigest::record_sent(&conn, &changed).unwrap();
+    let due_after_send = ModDigest::list_due(&conn).unwrap();
+    let digests = ModDigest::list_for_user(&conn, inserted_user.id).unwrap();
+    let removed = ModDigest::remove(&conn, inserted_user.id, inserted_community.id).unwrap();
+
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(7, changed.interval_days);
+    assert!(due.iter().any(|d| d.id == changed.id));
+    assert!(sent.next_send > naive_now() + chrono::Duration::days(6));
+    assert!(!due_after_send.iter().any(|d| d.id == changed.id));
+    assert_eq!(1, digests.len());
+    assert_eq!(1, removed);
+  }
+}
@@ -0,0 +1,282 @@
+use super::*;
+use crate::schema::{mod_note, mod_note_revision};
+
+/// A note a community's moderators keep about a user. Only they and the admins see them.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "mod_note"]
+pub struct ModNote {
+  pub id: i32,
+  pub community_id: i32,
+  /// The user the note is about
+  pub user_id: i32,
+  pub creator_id: i32,
+  pub content: String,
+  pub deleted: bool,
+  pub published: chrono::NaiveDateTime,
+  pub updated: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "mod_note"]
+pub struct ModNoteForm {
+  pub community_id: i32,
+  pub user_id: i32,
+  pub creator_id: i32,
+  pub content: String,
+  pub deleted: Option<bool>,
+  pub updated: Option<chrono::NaiveDateTime>,
+}
+
+impl Crud<ModNoteForm> for ModNote {
+  fn read(conn: &PgConnection, note_id: i32) -> Result<Self, Error> {
+    use crate::schema::mod_note::dsl::*;
+    mod_note.find(note_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, note_id: i32) -> Result<usize, Error> {
+    use crate::schema::mod_note::dsl::*;
+    diesel::delete(mod_note.find(note_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &ModNoteForm) -> Result<Self, Error> {
+    use crate::schema::mod_note::dsl::*;
+    insert_into(mod_note).values(form).get_result::<Self>(conn)
+  }
+
+  fn update(conn: &PgConnection, note_id: i32, form: &ModNoteForm) -> Result<Self, Error> {
+    use crate::schema::mod_note::dsl::*;
+    diesel::update(mod_note.find(note_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl ModNote {
+  /// Creates or edits a note, recording the new version and its editor as a revision.
+  pub fn save(
+    conn: &PgConnection,
+    note_id: Option<i32>,
+    form: &ModNoteForm,
+    editor_id: i32,
+  ) -> Result<Self, Error> {
+    conn.transaction(|| {
+      let note = match note_id {
+        Some(note_id) => Self::update(conn, note_id, form)?,
+        None => Self::create(conn, form)?,
+      };
+      ModNoteRevision::record(conn, &note, editor_id)?;
+      Ok(note)
+    })
+  }
+}
+
+/// A version of a note, kept so that edits and deletes can be audited.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "mod_note_revision"]
+pub struct ModNoteRevision {
+  pub id: i32,
+  pub mod_note_id: i32,
+  pub editor_id: i32,
+  pub content: String,
+  pub deleted: bool,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "mod_note_revision"]
+pub struct ModNoteRevisionForm {
+  pub mod_note_id: i32,
+  pub editor_id: i32,
+  pub content: String,
+  pub deleted: bool,
+}
+
+impl ModNoteRevision {
+  pub fn record(conn: &PgConnection, note: &ModNote, editor_id: i32) -> Result<Self, Error> {
+    use crate::schema::mod_note_revision::dsl::*;
+    let form = ModNoteRevisionForm {
+      mod_note_id: note.id,
+      editor_id,
+      content: note.content.to_owned(),
+      deleted: note.deleted,
+    };
+    insert_into(mod_note_revision)
+      .values(&form)
+      .get_result::<Self>(conn)
+  }
+}
+
+// The faked schema since diesel doesn't do views
+table! {
+  mod_note_view (id) {
+    id -> Int4,
+    community_id -> Int4,
+    user_id -> Int4,
+    creator_id -> Int4,
+    content -> Text,
+    deleted -> Bool,
+    published -> Timestamp,
+    updated -> Nullable<Timestamp>,
+    user_name -> Varchar,
+    creator_name -> Varchar,
+    community_name -> Varchar,
+  }
+}
+
+table! {
+  mod_note_revision_view (id) {
+    id -> Int4,
+    mod_note_id -> Int4,
+    editor_id -> Int4,
+    content -> Text,
+    deleted -> Bool,
+    published -> Timestamp,
+    editor_name -> Varchar,
+  }
+}
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[table_name = "mod_note_view"]
+pub struct ModNoteView {
+  pub id: i32,
+  pub community_id: i32,
+  pub user_id: i32,
+  pub creator_id: i32,
+  pub content: String,
+  pub deleted: bool,
+  pub published: chrono::NaiveDateTime,
+  pub updated: Option<chrono::NaiveDateTime>,
+  pub user_name: String,
+  pub creator_name: String,
+  pub community_name: String,
+}
+
+impl ModNoteView {
+  pub fn read(conn: &PgConnection, note_id: i32) -> Result<Self, Error> {
+    use super::mod_note::mod_note_view::dsl::*;
+    mod_note_view.find(note_id).first::<Self>(conn)
+  }
+
+  /// The notes, newest first, of the given communities (or all of them if `None`), and about the
+  /// given user if there is one.
+  pub fn list(
+    conn: &PgConnection,
+    for_community_ids: Option<Vec<i32>>,
+    for_user_id: Option<i32>,
+    include_deleted: bool,
+  ) -> Result<Vec<Self>, Error> {
+    use super::mod_note::mod_note_view::dsl::*;
+    let mut query = mod_note_view.into_boxed();
+
+    if !include_deleted {
+      query = query.filter(deleted.eq(false));
+    }
+
+    if let Some(for_community_ids) = for_community_ids {
+      query = query.filter(community_id.eq_any(for_community_ids));
+    }
+
+    if let Some(for_user_id) = for_user_id {
+      query = query.filter(user_id.eq(for_user_id));
+    }
+
+    query.order_by(published.desc()).load::<Self>(conn)
+  }
+}
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[table_name = "mod_note_revision_view"]
+pub struct ModNoteRevisionView {
+  pub id: i32,
+  pub mod_note_id: i32,
+  pub editor_id: i32,
+  pub content: String,
+  pub deleted: bool,
+  pub published: chrono::NaiveDateTime,
+  pub editor_name: String,
+}
+
+impl ModNoteRevisionView {
+  /// Every revision of the notes, oldest first.
+  pub fn for_notes(conn: &PgConnection, note_ids: Vec<i32>) -> Result<Vec<Self>, Error> {
+    use super::mod_note::mod_note_revision_view::dsl::*;
+    mod_note_revision_view
+      .filter(mod_note_id.eq_any(note_ids))
+      .order_by(id)
+      .load::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::community::*;
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_save() {
+    let conn = establish_unpooled_connection();
+
+    let inserted_mod = User_::create(&conn, &user_form("note_taker")).unwrap();
+    let inserted_user = User_::create(&conn, &user_form("noted")).unwrap();
+
+    let new_community = CommunityForm {
+      name: "noted_community".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_mod.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let note_form = ModNoteForm {
+      community_id: inserted_community.id,
+      user_id: inserted_user.id,
+      creator_id: inserted_mod.id,
+      content: "Second warning about spam".into(),
+      deleted: None,
+      updated: None,
+    };
+
+    let inserted_note = ModNote::save(&conn, None, &note_form, inserted_mod.id).unwrap();
+    let notes = ModNoteView::list(
+      &conn,
+      Some(vec![inserted_community.id]),
+      Some(inserted_user.id),
+      false,
+    )
+    .unwrap();
+
+    let deleted_form = ModNoteForm {
+      deleted: Some(true),
+      ..note_form.clone()
+    };
+    ModNote::save(
+      &conn,
+      Some(inserted_note.id),
+      &deleted_form,
+      inserted_mod.id,
+    )
+    .unwrap();
+    let notes_after_delete = ModNoteView::list(&conn, None, Some(inserted_user.id), false).unwrap();
+    let revisions = ModNoteRevisionView::for_notes(&conn, vec![inserted_note.id]).unwrap();
+
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+    User_::delete(&conn, inserted_mod.id).unwrap();
+
+    assert_eq!(1, notes.len());
+    assert_eq!("noted", notes[0].user_name);
+    assert_eq!("note_taker", notes[0].creator_name);
+    assert!(notes_after_delete.is_empty());
+    assert_eq!(2, revisions.len());
+    assert!(!revisions[0].deleted);
+    assert!(revisions[1].deleted);
+    assert_eq!(note_form.content, revisions[1].content);
+  }
+}
@@ -0,0 +1,177 @@
+use super::*;
+use crate::schema::moderation_notice;
+use crate::schema::moderation_notice::dsl::*;
+
+/// The text of the notices when the site doesn't have its own.
+pub const DEFAULT_MODERATION_NOTICE_TEMPLATE: &str = "{action}.\n\nReason: {reason}";
+
+/// Tells a user that a moderator removed their post or comment, or banned them.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[table_name = "moderation_notice"]
+pub struct ModerationNotice {
+  pub id: i32,
+  pub recipient_id: i32,
+  pub mod_user_id: i32,
+  pub kind: i16,
+  pub community_id: Option<i32>,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub reason: Option<String>,
+  pub content: String,
+  pub read: bool,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "moderation_notice"]
+pub struct ModerationNoticeForm {
+  pub recipient_id: i32,
+  pub mod_user_id: i32,
+  pub kind: i16,
+  pub community_id: Option<i32>,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub reason: Option<String>,
+  pub content: String,
+}
+
+/// What a notice is about. Stored as its `i16`.
+#[derive(EnumString, ToString, Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum ModerationNoticeKind {
+  PostRemoved,
+  CommentRemoved,
+  BannedFromCommunity,
+  Banned,
+  /// A digest of a community for one of its moderators.
+  ModDigest,
+  /// A community the user moderates reached a number of subscribers, or of posts and comments.
+  CommunityMilestone,
+  /// A community the user moderates got many more subscriptions than usual in a day.
+  SubscriptionSpike,
+}
+
+impl ModerationNotice {
+  pub fn create(conn: &PgConnection, form: &ModerationNoticeForm) -> Result<Self, Error> {
+    insert_into(moderation_notice)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  /// The user's notices, newest first.
+  pub fn list(
+    conn: &PgConnection,
+    for_recipient_id: i32,
+    unread_only: bool,
+    page: Option<i64>,
+    limit: Option<i64>,
+  ) -> Result<Vec<Self>, Error> {
+    let mut query = moderation_notice
+      .filter(recipient_id.eq(for_recipient_id))
+      .into_boxed();
+
+    if unread_only {
+      query = query.filter(read.eq(false));
+    }
+
+    let (limit, offset) = limit_and_offset(page, limit);
+
+    query
+      .order_by(published.desc())
+      .limit(limit)
+      .offset(offset)
+      .load::<Self>(conn)
+  }
+
+  pub fn mark_all_as_read(conn: &PgConnection, for_recipient_id: i32) -> Result<usize, Error> {
+    diesel::update(
+      moderation_notice
+        .filter(recipient_id.eq(for_recipient_id))
+        .filter(read.eq(false)),
+    )
+    .set(read.eq(true))
+    .execute(conn)
+  }
+}
+
+/// Fills in a notice template. Without a reason it says none was given, and the default template
+/// only mentions appealing if the site has somewhere to appeal.
+pub fn render_moderation_notice(
+  template: Option<&str>,
+  action: &str,
+  notice_reason: Option<&str>,
+  appeal_url: Option<&str>,
+) -> String {
+  let template = match template {
+    Some(template) => template.to_owned(),
+    None if appeal_url.is_some() => format!(
+      "{}\n\nIf you think this was a mistake, you can appeal at {{appeal_url}}",
+      DEFAULT_MODERATION_NOTICE_TEMPLATE
+    ),
+    None => DEFAULT_MODERATION_NOTICE_TEMPLATE.to_owned(),
+  };
+
+  template
+    .replace("{action}", action)
+    .replace("{reason}", notice_reason.unwrap_or("No reason was given"))
+    .replace("{appeal_url}", appeal_url.unwrap_or(""))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_render() {
+    let action = "Your post \"Hello\" was removed from test";
+
+    assert_eq!(
+      "Your post \"Hello\" was removed from test.\n\nReason: spam",
+      render_moderation_notice(None, action, Some("spam"), None)
+    );
+    assert_eq!(
+      "Your post \"Hello\" was removed from test.\n\nReason: No reason was given\n\n\
+       If you think this was a mistake, you can appeal at https://example.com/appeal",
+      render_moderation_notice(None, action, None, Some("https://example.com/appeal"))
+    );
+    assert_eq!(
+      "Your post \"Hello\" was removed from test (spam)",
+      render_moderation_notice(Some("{action} ({reason})"), action, Some("spam"), None)
+    );
+  }
+
+  #[test]
+  fn test_mark_all_as_read() {
+    let conn = establish_unpooled_connection();
+
+    let inserted_mod = User_::create(&conn, &user_form("notice_mod")).unwrap();
+    let inserted_user = User_::create(&conn, &user_form("noticed")).unwrap();
+
+    let notice_form = ModerationNoticeForm {
+      recipient_id: inserted_user.id,
+      mod_user_id: inserted_mod.id,
+      kind: ModerationNoticeKind::Banned as i16,
+      community_id: None,
+      post_id: None,
+      comment_id: None,
+      reason: Some("spam".into()),
+      content: "You were banned.\n\nReason: spam".into(),
+    };
+
+    let inserted_notice = ModerationNotice::create(&conn, &notice_form).unwrap();
+    let unread = ModerationNotice::list(&conn, inserted_user.id, true, None, None).unwrap();
+    let marked = ModerationNotice::mark_all_as_read(&conn, inserted_user.id).unwrap();
+    let unread_after_marking =
+      ModerationNotice::list(&conn, inserted_user.id, true, None, None).unwrap();
+    let all = ModerationNotice::list(&conn, inserted_user.id, false, None, None).unwrap();
+
+    User_::delete(&conn, inserted_user.id).unwrap();
+    User_::delete(&conn, inserted_mod.id).unwrap();
+
+    assert_eq!(vec![inserted_notice], unread);
+    assert_eq!(1, marked);
+    assert!(unread_after_marking.is_empty());
+    assert_eq!(1, all.len());
+    assert!(all[0].read);
+  }
+}
@@ -1,7 +1,7 @@
 use super::*;
 use crate::schema::{
-  mod_add, mod_add_community, mod_ban, mod_ban_from_community, mod_lock_post, mod_remove_comment,
-  mod_remove_community, mod_remove_post, mod_sticky_post,
+  mod_add, mod_add_community, mod_ban, mod_ban_from_community, mod_impersonate, mod_legal_hold,
+  mod_lock_post, mod_remove_comment, mod_remove_community, mod_remove_post, mod_sticky_post,
 };
 
 #[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
@@ -13,6 +13,7 @@ pub struct ModRemovePost {
   pub reason: Option<String>,
   pub removed: Option<bool>,
   pub when_: chrono::NaiveDateTime,
+  pub remote_scope: Option<i16>,
 }
 
 #[derive(Insertable, AsChangeset, Clone, Serialize, Deserialize)]
@@ -22,6 +23,8 @@ pub struct ModRemovePostForm {
   pub post_id: i32,
   pub reason: Option<String>,
   pub removed: Option<bool>,
+  /// A `RemoteModerationScope`, for remote content
+  pub remote_scope: Option<i16>,
 }
 
 impl Crud<ModRemovePostForm> for ModRemovePost {
@@ -147,6 +150,7 @@ pub struct ModRemoveComment {
   pub reason: Option<String>,
   pub removed: Option<bool>,
   pub when_: chrono::NaiveDateTime,
+  pub remote_scope: Option<i16>,
 }
 
 #[derive(Insertable, AsChangeset, Clone, Serialize, Deserialize)]
@@ -156,6 +160,8 @@ pub struct ModRemoveCommentForm {
   pub comment_id: i32,
   pub reason: Option<String>,
   pub removed: Option<bool>,
+  /// A `RemoteModerationScope`, for remote content
+  pub remote_scope: Option<i16>,
 }
 
 impl Crud<ModRemoveCommentForm> for ModRemoveComment {
@@ -300,6 +306,7 @@ pub struct ModBan {
   pub banned: Option<bool>,
   pub expires: Option<chrono::NaiveDateTime>,
   pub when_: chrono::NaiveDateTime,
+  pub remote_scope: Option<i16>,
 }
 
 #[derive(Insertable, AsChangeset, Clone, Serialize, Deserialize)]
@@ -310,6 +317,8 @@ pub struct ModBanForm {
   pub reason: Option<String>,
   pub banned: Option<bool>,
   pub expires: Option<chrono::NaiveDateTime>,
+  /// A `RemoteModerationScope`, for remote content
+  pub remote_scope: Option<i16>,
 }
 
 impl Crud<ModBanForm> for ModBan {
@@ -424,10 +433,122 @@ impl Crud<ModAddForm> for ModAdd {
   }
 }
 
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "mod_legal_hold"]
+pub struct ModLegalHold {
+  pub id: i32,
+  pub mod_user_id: i32,
+  pub other_user_id: Option<i32>,
+  pub post_id: Option<i32>,
+  pub reason: Option<String>,
+  pub held: Option<bool>,
+  pub when_: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone, Serialize, Deserialize)]
+#[table_name = "mod_legal_hold"]
+pub struct ModLegalHoldForm {
+  pub mod_user_id: i32,
+  pub other_user_id: Option<i32>,
+  pub post_id: Option<i32>,
+  pub reason: Option<String>,
+  pub held: Option<bool>,
+}
+
+impl Crud<ModLegalHoldForm> for ModLegalHold {
+  fn read(conn: &PgConnection, from_id: i32) -> Result<Self, Error> {
+    use crate::schema::mod_legal_hold::dsl::*;
+    mod_legal_hold.find(from_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, from_id: i32) -> Result<usize, Error> {
+    use crate::schema::mod_legal_hold::dsl::*;
+    diesel::delete(mod_legal_hold.find(from_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &ModLegalHoldForm) -> Result<Self, Error> {
+    use crate::schema::mod_legal_hold::dsl::*;
+    insert_into(mod_legal_hold)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(conn: &PgConnection, from_id: i32, form: &ModLegalHoldForm) -> Result<Self, Error> {
+    use crate::schema::mod_legal_hold::dsl::*;
+    diesel::update(mod_legal_hold.find(from_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+/// An admin viewing the site as a user, or revoking that.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "mod_impersonate"]
+pub struct ModImpersonate {
+  pub id: i32,
+  pub mod_user_id: i32,
+  pub other_user_id: i32,
+  pub reason: Option<String>,
+  pub revoked: bool,
+  pub when_: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone, Serialize, Deserialize)]
+#[table_name = "mod_impersonate"]
+pub struct ModImpersonateForm {
+  pub mod_user_id: i32,
+  pub other_user_id: i32,
+  pub reason: Option<String>,
+  pub revoked: bool,
+}
+
+impl Crud<ModImpersonateForm> for ModImpersonate {
+  fn read(conn: &PgConnection, from_id: i32) -> Result<Self, Error> {
+    use crate::schema::mod_impersonate::dsl::*;
+    mod_impersonate.find(from_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, from_id: i32) -> Result<usize, Error> {
+    use crate::schema::mod_impersonate::dsl::*;
+    diesel::delete(mod_impersonate.find(from_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &ModImpersonateForm) -> Result<Self, Error> {
+    use crate::schema::mod_impersonate::dsl::*;
+    insert_into(mod_impersonate)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(conn: &PgConnection, from_id: i32, form: &ModImpersonateForm) -> Result<Self, Error> {
+    use crate::schema::mod_impersonate::dsl::*;
+    diesel::update(mod_impersonate.find(from_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl ModImpersonate {
+  pub fn list(
+    conn: &PgConnection,
+    page: Option<i64>,
+    limit: Option<i64>,
+  ) -> Result<Vec<Self>, Error> {
+    use crate::schema::mod_impersonate::dsl::*;
+    let (limit, offset) = limit_and_offset(page, limit);
+    mod_impersonate
+      .order_by(when_.desc())
+      .limit(limit)
+      .offset(offset)
+      .load::<Self>(conn)
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::super::comment::*;
   use super::super::community::*;
+  use super::super::moderator_views::{ModlogActionKind, ModlogQueryBuilder};
   use super::super::post::*;
   use super::super::user::*;
   use super::*;
@@ -510,6 +631,11 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
     };
 
     let inserted_post = Post::create(&conn, &new_post).unwrap();
@@ -523,6 +649,8 @@ mod tests {
       read: None,
       parent_id: None,
       updated: None,
+      generated: None,
+      language_id: None,
     };
 
     let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
@@ -535,6 +663,7 @@ mod tests {
       post_id: inserted_post.id,
       reason: None,
       removed: None,
+      remote_scope: None,
     };
     let inserted_mod_remove_post = ModRemovePost::create(&conn, &mod_remove_post_form).unwrap();
     let read_mod_remove_post = ModRemovePost::read(&conn, inserted_mod_remove_post.id).unwrap();
@@ -545,6 +674,7 @@ mod tests {
       reason: None,
       removed: Some(true),
       when_: inserted_mod_remove_post.when_,
+      remote_scope: None,
     };
 
     // lock post
@@ -588,6 +718,7 @@ mod tests {
       comment_id: inserted_comment.id,
       reason: None,
       removed: None,
+      remote_scope: None,
     };
     let inserted_mod_remove_comment =
       ModRemoveComment::create(&conn, &mod_remove_comment_form).unwrap();
@@ -600,6 +731,7 @@ mod tests {
       reason: None,
       removed: Some(true),
       when_: inserted_mod_remove_comment.when_,
+      remote_scope: None,
     };
 
     // community
@@ -658,6 +790,7 @@ mod tests {
       reason: None,
       banned: None,
       expires: None,
+      remote_scope: None,
     };
     let inserted_mod_ban = ModBan::create(&conn, &mod_ban_form).unwrap();
     let read_mod_ban = ModBan::read(&conn, inserted_mod_ban.id).unwrap();
@@ -669,6 +802,7 @@ mod tests {
       banned: Some(true),
       expires: None,
       when_: inserted_mod_ban.when_,
+      remote_scope: None,
     };
 
     // mod add community
@@ -709,6 +843,57 @@ mod tests {
       when_: inserted_mod_add.when_,
     };
 
+    // legal hold
+
+    let mod_legal_hold_form = ModLegalHoldForm {
+      mod_user_id: inserted_mod.id,
+      other_user_id: None,
+      post_id: Some(inserted_post.id),
+      reason: None,
+      held: None,
+    };
+    let inserted_mod_legal_hold = ModLegalHold::create(&conn, &mod_legal_hold_form).unwrap();
+    let read_mod_legal_hold = ModLegalHold::read(&conn, inserted_mod_legal_hold.id).unwrap();
+    let expected_mod_legal_hold = ModLegalHold {
+      id: inserted_mod_legal_hold.id,
+      mod_user_id: inserted_mod.id,
+      other_user_id: None,
+      post_id: Some(inserted_post.id),
+      reason: None,
+      held: Some(true),
+      when_: inserted_mod_legal_hold.when_,
+    };
+
+    // impersonate
+
+    let mod_impersonate_form = ModImpersonateForm {
+      mod_user_id: inserted_mod.id,
+      other_user_id: inserted_user.id,
+      reason: Some("A report".into()),
+      revoked: false,
+    };
+    let inserted_mod_impersonate = ModImpersonate::create(&conn, &mod_impersonate_form).unwrap();
+    let read_mod_impersonate = ModImpersonate::read(&conn, inserted_mod_impersonate.id).unwrap();
+    let expected_mod_impersonate = ModImpersonate {
+      id: inserted_mod_impersonate.id,
+      mod_user_id: inserted_mod.id,
+      other_user_id: inserted_user.id,
+      reason: Some("A report".into()),
+      revoked: false,
+      when_: inserted_mod_impersonate.when_,
+    };
+
+    let modlog = ModlogQueryBuilder::create(&conn)
+      .for_mod_user_id(inserted_mod.id)
+      .limit(20)
+      .list()
+      .unwrap();
+    let bans_of_user = ModlogQueryBuilder::create(&conn)
+      .for_other_user_id(inserted_user.id)
+      .kind(ModlogActionKind::Ban)
+      .list()
+      .unwrap();
+
     ModRemovePost::delete(&conn, inserted_mod_remove_post.id).unwrap();
     ModLockPost::delete(&conn, inserted_mod_lock_post.id).unwrap();
     ModStickyPost::delete(&conn, inserted_mod_sticky_post.id).unwrap();
@@ -718,6 +903,8 @@ mod tests {
     ModBan::delete(&conn, inserted_mod_ban.id).unwrap();
     ModAddCommunity::delete(&conn, inserted_mod_add_community.id).unwrap();
     ModAdd::delete(&conn, inserted_mod_add.id).unwrap();
+    ModLegalHold::delete(&conn, inserted_mod_legal_hold.id).unwrap();
+    ModImpersonate::delete(&conn, inserted_mod_impersonate.id).unwrap();
 
     Comment::delete(&conn, inserted_comment.id).unwrap();
     Post::delete(&conn, inserted_post.id).unwrap();
@@ -734,5 +921,12 @@ mod tests {
     assert_eq!(expected_mod_ban, read_mod_ban);
     assert_eq!(expected_mod_add_community, read_mod_add_community);
     assert_eq!(expected_mod_add, read_mod_add);
+    assert_eq!(expected_mod_legal_hold, read_mod_legal_hold);
+    assert_eq!(expected_mod_impersonate, read_mod_impersonate);
+    // Every action but the legal hold and impersonation, which aren't in the modlog
+    assert_eq!(9, modlog.len());
+    assert_eq!(1, bans_of_user.len());
+    assert_eq!(inserted_mod_ban.id, bans_of_user[0].id);
+    assert_eq!(Some("jim2".to_string()), bans_of_user[0].other_user_name);
   }
 }
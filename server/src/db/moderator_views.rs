@@ -1,4 +1,6 @@
 use super::*;
+use diesel::pg::Pg;
+use strum::IntoEnumIterator;
 
 table! {
   mod_remove_post_view (id) {
@@ -8,6 +10,7 @@ table! {
     reason -> Nullable<Text>,
     removed -> Nullable<Bool>,
     when_ -> Timestamp,
+    remote_scope -> Nullable<Int2>,
     mod_user_name -> Varchar,
     post_name -> Varchar,
     community_id -> Int4,
@@ -26,6 +29,7 @@ pub struct ModRemovePostView {
   pub reason: Option<String>,
   pub removed: Option<bool>,
   pub when_: chrono::NaiveDateTime,
+  pub remote_scope: Option<i16>,
   pub mod_user_name: String,
   pub post_name: String,
   pub community_id: i32,
@@ -187,6 +191,7 @@ table! {
     reason -> Nullable<Text>,
     removed -> Nullable<Bool>,
     when_ -> Timestamp,
+    remote_scope -> Nullable<Int2>,
     mod_user_name -> Varchar,
     comment_user_id -> Int4,
     comment_user_name -> Varchar,
@@ -209,6 +214,7 @@ pub struct ModRemoveCommentView {
   pub reason: Option<String>,
   pub removed: Option<bool>,
   pub when_: chrono::NaiveDateTime,
+  pub remote_scope: Option<i16>,
   pub mod_user_name: String,
   pub comment_user_id: i32,
   pub comment_user_name: String,
@@ -374,6 +380,7 @@ table! {
     banned -> Nullable<Bool>,
     expires -> Nullable<Timestamp>,
     when_ -> Timestamp,
+    remote_scope -> Nullable<Int2>,
     mod_user_name -> Varchar,
     other_user_name -> Varchar,
   }
@@ -391,6 +398,7 @@ pub struct ModBanView {
   pub banned: Option<bool>,
   pub expires: Option<chrono::NaiveDateTime>,
   pub when_: chrono::NaiveDateTime,
+  pub remote_scope: Option<i16>,
   pub mod_user_name: String,
   pub other_user_name: String,
 }
@@ -527,3 +535,185 @@ impl ModAddView {
       .load::<Self>(conn)
   }
 }
+
+table! {
+  modlog_view (kind, id) {
+    kind -> Int2,
+    id -> Int4,
+    mod_user_id -> Int4,
+    other_user_id -> Nullable<Int4>,
+    community_id -> Nullable<Int4>,
+    post_id -> Nullable<Int4>,
+    comment_id -> Nullable<Int4>,
+    reason -> Nullable<Text>,
+    applied -> Bool,
+    expires -> Nullable<Timestamp>,
+    remote_scope -> Nullable<Int2>,
+    when_ -> Timestamp,
+    mod_user_name -> Varchar,
+    other_user_name -> Nullable<Varchar>,
+    community_name -> Nullable<Varchar>,
+    post_name -> Nullable<Varchar>,
+  }
+}
+
+/// Which of the mod tables a modlog action is from. Stored as its `i16`.
+#[derive(EnumString, ToString, EnumIter, Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum ModlogActionKind {
+  RemovePost,
+  LockPost,
+  StickyPost,
+  RemoveComment,
+  RemoveCommunity,
+  BanFromCommunity,
+  Ban,
+  AddModToCommunity,
+  AddAdmin,
+}
+
+impl ModlogActionKind {
+  pub fn from_i16(kind: i16) -> Option<Self> {
+    ModlogActionKind::iter().nth(kind as usize)
+  }
+}
+
+/// A moderator action from any of the mod tables.
+#[derive(Queryable, PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct ModlogView {
+  pub kind: i16,
+  /// The id in the mod table it's from
+  pub id: i32,
+  pub mod_user_id: i32,
+  /// The user it was done to, or whose post, comment or community it was done to
+  pub other_user_id: Option<i32>,
+  pub community_id: Option<i32>,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub reason: Option<String>,
+  /// False if it undid the action, like restoring a post or removing a mod
+  pub applied: bool,
+  pub expires: Option<chrono::NaiveDateTime>,
+  pub remote_scope: Option<i16>,
+  pub when_: chrono::NaiveDateTime,
+  pub mod_user_name: String,
+  pub other_user_name: Option<String>,
+  pub community_name: Option<String>,
+  pub post_name: Option<String>,
+}
+
+pub struct ModlogQueryBuilder<'a> {
+  conn: &'a PgConnection,
+  query: super::moderator_views::modlog_view::BoxedQuery<'a, Pg>,
+  for_community_id: Option<i32>,
+  for_mod_user_id: Option<i32>,
+  for_other_user_id: Option<i32>,
+  kind: Option<ModlogActionKind>,
+  after: Option<chrono::NaiveDateTime>,
+  before: Option<chrono::NaiveDateTime>,
+  page: Option<i64>,
+  limit: Option<i64>,
+}
+
+impl<'a> ModlogQueryBuilder<'a> {
+  pub fn create(conn: &'a PgConnection) -> Self {
+    use super::moderator_views::modlog_view::dsl::*;
+
+    let query = modlog_view.into_boxed();
+
+    ModlogQueryBuilder {
+      conn,
+      query,
+      for_community_id: None,
+      for_mod_user_id: None,
+      for_other_user_id: None,
+      kind: None,
+      after: None,
+      before: None,
+      page: None,
+      limit: None,
+    }
+  }
+
+  /// Only the actions in a community. The site bans and admins aren't in any.
+  pub fn for_community_id<T: MaybeOptional<i32>>(mut self, for_community_id: T) -> Self {
+    self.for_community_id = for_community_id.get_optional();
+    self
+  }
+
+  pub fn for_mod_user_id<T: MaybeOptional<i32>>(mut self, for_mod_user_id: T) -> Self {
+    self.for_mod_user_id = for_mod_user_id.get_optional();
+    self
+  }
+
+  /// Only the actions done to a user, or to their posts, comments and communities.
+  pub fn for_other_user_id<T: MaybeOptional<i32>>(mut self, for_other_user_id: T) -> Self {
+    self.for_other_user_id = for_other_user_id.get_optional();
+    self
+  }
+
+  pub fn kind<T: MaybeOptional<ModlogActionKind>>(mut self, kind: T) -> Self {
+    self.kind = kind.get_optional();
+    self
+  }
+
+  /// Only the actions from this time on.
+  pub fn after<T: MaybeOptional<chrono::NaiveDateTime>>(mut self, after: T) -> Self {
+    self.after = after.get_optional();
+    self
+  }
+
+  /// Only the actions from before this time.
+  pub fn before<T: MaybeOptional<chrono::NaiveDateTime>>(mut self, before: T) -> Self {
+    self.before = before.get_optional();
+    self
+  }
+
+  pub fn page<T: MaybeOptional<i64>>(mut self, page: T) -> Self {
+    self.page = page.get_optional();
+    self
+  }
+
+  pub fn limit<T: MaybeOptional<i64>>(mut self, limit: T) -> Self {
+    self.limit = limit.get_optional();
+    self
+  }
+
+  /// The actions, newest first.
+  pub fn list(self) -> Result<Vec<ModlogView>, Error> {
+    use super::moderator_views::modlog_view::dsl::*;
+
+    let mut query = self.query;
+
+    if let Some(for_community_id) = self.for_community_id {
+      query = query.filter(community_id.eq(for_community_id));
+    }
+
+    if let Some(for_mod_user_id) = self.for_mod_user_id {
+      query = query.filter(mod_user_id.eq(for_mod_user_id));
+    }
+
+    if let Some(for_other_user_id) = self.for_other_user_id {
+      query = query.filter(other_user_id.eq(for_other_user_id));
+    }
+
+    if let Some(for_kind) = self.kind {
+      query = query.filter(kind.eq(for_kind as i16));
+    }
+
+    if let Some(after) = self.after {
+      query = query.filter(when_.ge(after));
+    }
+
+    if let Some(before) = self.before {
+      query = query.filter(when_.lt(before));
+    }
+
+    let (limit, offset) = limit_and_offset(self.page, self.limit);
+    query
+      .order_by(when_.desc())
+      .then_order_by(id.desc())
+      .limit(limit)
+      .offset(offset)
+      .load::<ModlogView>(self.conn)
+  }
+}
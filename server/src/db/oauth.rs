@@ -0,0 +1,198 @@
+use super::*;
+use crate::naive_now;
+use crate::schema::{oauth_client, oauth_code};
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "oauth_client"]
+pub struct OAuthClient {
+  pub id: i32,
+  pub client_id: String,
+  #[serde(skip_serializing)]
+  pub client_secret_hash: String,
+  pub name: String,
+  pub redirect_uris: Vec<String>,
+  pub creator_id: i32,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "oauth_client"]
+pub struct OAuthClientForm {
+  pub client_id: String,
+  pub client_secret_hash: String,
+  pub name: String,
+  pub redirect_uris: Vec<String>,
+  pub creator_id: i32,
+}
+
+impl Crud<OAuthClientForm> for OAuthClient {
+  fn read(conn: &PgConnection, oauth_client_id: i32) -> Result<Self, Error> {
+    use crate::schema::oauth_client::dsl::*;
+    oauth_client.find(oauth_client_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, oauth_client_id: i32) -> Result<usize, Error> {
+    use crate::schema::oauth_client::dsl::*;
+    diesel::delete(oauth_client.find(oauth_client_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &OAuthClientForm) -> Result<Self, Error> {
+    use crate::schema::oauth_client::dsl::*;
+    insert_into(oauth_client)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(
+    conn: &PgConnection,
+    oauth_client_id: i32,
+    form: &OAuthClientForm,
+  ) -> Result<Self, Error> {
+    use crate::schema::oauth_client::dsl::*;
+    diesel::update(oauth_client.find(oauth_client_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl OAuthClient {
+  pub fn read_from_client_id(conn: &PgConnection, from_client_id: &str) -> Result<Self, Error> {
+    use crate::schema::oauth_client::dsl::*;
+    oauth_client
+      .filter(client_id.eq(from_client_id))
+      .first::<Self>(conn)
+  }
+
+  pub fn list_for_creator(conn: &PgConnection, for_creator_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::oauth_client::dsl::*;
+    oauth_client
+      .filter(creator_id.eq(for_creator_id))
+      .order_by(published.desc())
+      .load::<Self>(conn)
+  }
+}
+
+/// An authorization code, which the app exchanges for a token.
+#[derive(Queryable, Identifiable, PartialEq, Debug)]
+#[table_name = "oauth_code"]
+pub struct OAuthCode {
+  pub id: i32,
+  pub code_hash: String,
+  pub oauth_client_id: i32,
+  pub user_id: i32,
+  pub scopes: Vec<String>,
+  pub redirect_uri: String,
+  /// The PKCE challenge, which public clients like mobile apps use instead of a secret
+  pub code_challenge: Option<String>,
+  pub expires: chrono::NaiveDateTime,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "oauth_code"]
+pub struct OAuthCodeForm {
+  pub code_hash: String,
+  pub oauth_client_id: i32,
+  pub user_id: i32,
+  pub scopes: Vec<String>,
+  pub redirect_uri: String,
+  pub code_challenge: Option<String>,
+  pub expires: chrono::NaiveDateTime,
+}
+
+impl OAuthCode {
+  pub fn create(conn: &PgConnection, form: &OAuthCodeForm) -> Result<Self, Error> {
+    use crate::schema::oauth_code::dsl::*;
+    insert_into(oauth_code)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  /// Reads and deletes an unexpired code, so that it can only be used once.
+  pub fn take(conn: &PgConnection, from_code_hash: &str) -> Result<Self, Error> {
+    use crate::schema::oauth_code::dsl::*;
+    diesel::delete(
+      oauth_code
+        .filter(code_hash.eq(from_code_hash))
+        .filter(expires.gt(naive_now())),
+    )
+    .get_result::<Self>(conn)
+  }
+
+  /// Drops the codes which expired without being used.
+  pub fn delete_expired(conn: &PgConnection) -> Result<usize, Error> {
+    use crate::schema::oauth_code::dsl::*;
+    diesel::delete(oauth_code.filter(expires.le(naive_now()))).execute(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_take_code() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "oauth_user".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let client_form = OAuthClientForm {
+      client_id: "test_client_id".into(),
+      client_secret_hash: "hash".into(),
+      name: "Test app".into(),
+      redirect_uris: vec!["app://callback".into()],
+      creator_id: inserted_user.id,
+    };
+    let inserted_client = OAuthClient::create(&conn, &client_form).unwrap();
+    assert_eq!(
+      inserted_client,
+      OAuthClient::read_from_client_id(&conn, "test_client_id").unwrap()
+    );
+
+    let code_form = |hash: &str, expires: chrono::NaiveDateTime| OAuthCodeForm {
+      code_hash: hash.into(),
+      oauth_client_id: inserted_client.id,
+      user_id: inserted_user.id,
+      scopes: vec!["read".into()],
+      redirect_uri: "app://callback".into(),
+      code_challenge: None,
+      expires,
+    };
+    let hour = chrono::Duration::hours(1);
+    OAuthCode::create(&conn, &code_form("fresh", naive_now() + hour)).unwrap();
+    OAuthCode::create(&conn, &code_form("stale", naive_now() - hour)).unwrap();
+
+    // Codes only work once, and not after they expire
+    assert_eq!(
+      inserted_user.id,
+      OAuthCode::take(&conn, "fresh").unwrap().user_id
+    );
+    assert!(OAuthCode::take(&conn, "fresh").is_err());
+    assert!(OAuthCode::take(&conn, "stale").is_err());
+    assert!(OAuthCode::delete_expired(&conn).unwrap() >= 1);
+
+    let num_deleted = OAuthClient::delete(&conn, inserted_client.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+    assert_eq!(1, num_deleted);
+  }
+}
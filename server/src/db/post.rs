@@ -1,5 +1,13 @@
+use super::user::User_;
 use super::*;
-use crate::schema::{post, post_like, post_read, post_saved};
+use crate::naive_now;
+use crate::schema::{post, post_like, post_read, post_saved, post_slug_history};
+use crate::settings::Settings;
+use crate::slugify;
+use diesel::sql_types::{Array, Integer, SmallInt, Varchar};
+
+/// The most characters of a post's excerpt.
+pub const EXCERPT_LENGTH: usize = 300;
 
 #[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
 #[table_name = "post"]
@@ -21,6 +29,22 @@ pub struct Post {
   pub embed_description: Option<String>,
   pub embed_html: Option<String>,
   pub thumbnail_url: Option<String>,
+  pub legal_hold: bool,
+  pub slug: String,
+  /// A plain text preview of the body, for listings
+  pub excerpt: Option<String>,
+  /// Describes the image, for image posts
+  pub alt_text: Option<String>,
+  /// A `PostVisibility`
+  pub visibility: i16,
+  /// The id of the remote object it came from, for federated posts
+  pub ap_id: Option<String>,
+  /// Made by a bot or generated by AI
+  pub generated: bool,
+  /// The `Language` it's in
+  pub language_id: Option<i32>,
+  /// When it's published, until which nobody but its creator sees it
+  pub scheduled_publish_time: Option<chrono::NaiveDateTime>,
 }
 
 #[derive(Insertable, AsChangeset, Clone)]
@@ -41,6 +65,11 @@ pub struct PostForm {
   pub embed_description: Option<String>,
   pub embed_html: Option<String>,
   pub thumbnail_url: Option<String>,
+  pub alt_text: Option<String>,
+  pub visibility: Option<i16>,
+  pub generated: Option<bool>,
+  pub language_id: Option<i32>,
+  pub scheduled_publish_time: Option<chrono::NaiveDateTime>,
 }
 
 impl Crud<PostForm> for Post {
@@ -56,15 +85,187 @@ impl Crud<PostForm> for Post {
 
   fn create(conn: &PgConnection, new_post: &PostForm) -> Result<Self, Error> {
     use crate::schema::post::dsl::*;
-    insert_into(post).values(new_post).get_result::<Self>(conn)
+    insert_into(post)
+      .values((
+        new_post,
+        slug.eq(post_slug(&new_post.name)),
+        excerpt.eq(post_excerpt(&new_post.body)),
+      ))
+      .get_result::<Self>(conn)
   }
 
+  /// Regenerates the slug from the title, keeping the old one in the history if it changed, and
+  /// the excerpt from the body.
   fn update(conn: &PgConnection, post_id: i32, new_post: &PostForm) -> Result<Self, Error> {
+    use crate::schema::post::dsl::*;
+    let new_slug = post_slug(&new_post.name);
+    let old_slug = post.find(post_id).select(slug).first::<String>(conn)?;
+    if old_slug != new_slug && !old_slug.is_empty() {
+      insert_into(post_slug_history::table)
+        .values((
+          post_slug_history::post_id.eq(post_id),
+          post_slug_history::slug.eq(old_slug),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    }
+
+    diesel::update(post.find(post_id))
+      .set((
+        new_post,
+        slug.eq(new_slug),
+        excerpt.eq(post_excerpt(&new_post.body)),
+      ))
+      .get_result::<Self>(conn)
+  }
+}
+
+fn post_slug(title: &str) -> String {
+  slugify(title, Settings::get().post_slug.max_length)
+}
+
+pub fn post_excerpt(body: &Option<String>) -> Option<String> {
+  body
+    .as_ref()
+    .and_then(|body| crate::excerpt(body, EXCERPT_LENGTH))
+}
+
+/// Another post of the same link as a post of a listing that collapsed them into it.
+#[derive(QueryableByName, PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct Crosspost {
+  /// The post in the listing
+  #[sql_type = "Integer"]
+  pub post_id: i32,
+  #[sql_type = "Integer"]
+  pub id: i32,
+  #[sql_type = "Integer"]
+  pub community_id: i32,
+  #[sql_type = "Varchar"]
+  pub community_name: String,
+}
+
+impl Post {
+  /// The other public posts of the same links as the posts, the oldest first. Links match when
+  /// they only differ by what doesn't change where they go, like tracking parameters.
+  pub fn crossposts(conn: &PgConnection, post_ids: &[i32]) -> Result<Vec<Crosspost>, Error> {
+    sql_query(
+      "select p.id as post_id, o.id, o.community_id, c.name as community_name
+      from post p
+      join post o on normalize_url(o.url) = normalize_url(p.url) and o.id <> p.id
+      join community c on c.id = o.community_id
+      where p.id = any($1) and p.url is not null and o.url is not null
+      and o.visibility = $2 and o.scheduled_publish_time is null and not (o.removed or o.deleted or c.removed or c.deleted)
+      order by p.id, o.published",
+    )
+    .bind::<Array<Integer>, _>(post_ids)
+    .bind::<SmallInt, _>(PostVisibility::Public as i16)
+    .load::<Crosspost>(conn)
+  }
+
+  /// Whether the post used to have this slug, before its title was edited.
+  pub fn had_slug(conn: &PgConnection, for_post_id: i32, old_slug: &str) -> Result<bool, Error> {
+    use crate::schema::post_slug_history::dsl::*;
+    select(diesel::dsl::exists(
+      post_slug_history
+        .filter(post_id.eq(for_post_id))
+        .filter(slug.eq(old_slug)),
+    ))
+    .get_result::<bool>(conn)
+  }
+
+  /// Drops the old slugs, for when the title itself is purged.
+  pub fn forget_slug_history(conn: &PgConnection, for_post_id: i32) -> Result<usize, Error> {
+    use crate::schema::post_slug_history::dsl::*;
+    diesel::delete(post_slug_history.filter(post_id.eq(for_post_id))).execute(conn)
+  }
+
+  pub fn update_legal_hold(conn: &PgConnection, post_id: i32, hold: bool) -> Result<Self, Error> {
+    use crate::schema::post::dsl::*;
+    diesel::update(post.find(post_id))
+      .set(legal_hold.eq(hold))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn read_from_ap_id(conn: &PgConnection, object_id: &str) -> Result<Self, Error> {
+    use crate::schema::post::dsl::*;
+    post.filter(ap_id.eq(object_id)).first::<Self>(conn)
+  }
+
+  pub fn update_ap_id(conn: &PgConnection, post_id: i32, object_id: &str) -> Result<Self, Error> {
+    use crate::schema::post::dsl::*;
+    diesel::update(post.find(post_id))
+      .set(ap_id.eq(object_id))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_removed(
+    conn: &PgConnection,
+    post_id: i32,
+    new_removed: bool,
+  ) -> Result<Self, Error> {
+    use crate::schema::post::dsl::*;
+    diesel::update(post.find(post_id))
+      .set(removed.eq(new_removed))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_locked(conn: &PgConnection, post_id: i32, new_locked: bool) -> Result<Self, Error> {
     use crate::schema::post::dsl::*;
     diesel::update(post.find(post_id))
-      .set(new_post)
+      .set(locked.eq(new_locked))
       .get_result::<Self>(conn)
   }
+
+  pub fn update_stickied(
+    conn: &PgConnection,
+    post_id: i32,
+    new_stickied: bool,
+  ) -> Result<Self, Error> {
+    use crate::schema::post::dsl::*;
+    diesel::update(post.find(post_id))
+      .set(stickied.eq(new_stickied))
+      .get_result::<Self>(conn)
+  }
+
+  /// The scheduled posts whose time has come, the longest overdue first.
+  pub fn list_due_scheduled(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    use crate::schema::post::dsl::*;
+    post
+      .filter(scheduled_publish_time.le(naive_now()))
+      .order_by(scheduled_publish_time)
+      .load::<Self>(conn)
+  }
+
+  /// Publishes a scheduled post, as if it was just made. Gives `None` if it isn't scheduled
+  /// anymore.
+  pub fn publish_scheduled(conn: &PgConnection, post_id: i32) -> Result<Option<Self>, Error> {
+    use crate::schema::post::dsl::*;
+    diesel::update(
+      post
+        .find(post_id)
+        .filter(scheduled_publish_time.is_not_null()),
+    )
+    .set((
+      scheduled_publish_time.eq(None::<chrono::NaiveDateTime>),
+      published.eq(naive_now()),
+    ))
+    .get_result::<Self>(conn)
+    .optional()
+  }
+
+  /// The community's stickied posts that other instances can see, newest first.
+  pub fn list_featured(conn: &PgConnection, for_community_id: i32) -> Result<Vec<Self>, Error> {
+    use crate::schema::post::dsl::*;
+    post
+      .filter(community_id.eq(for_community_id))
+      .filter(stickied.eq(true))
+      .filter(removed.eq(false))
+      .filter(deleted.eq(false))
+      .filter(visibility.ne(PostVisibility::FollowersOnly as i16))
+      .filter(scheduled_publish_time.is_null())
+      .order_by(published.desc())
+      .load::<Self>(conn)
+  }
 }
 
 #[derive(Identifiable, Queryable, Associations, PartialEq, Debug)]
@@ -110,6 +311,39 @@ impl Likeable<PostLikeForm> for PostLike {
   }
 }
 
+impl PostLike {
+  /// The score of a user's vote on a post, if they voted on it.
+  pub fn user_score(
+    conn: &PgConnection,
+    for_post_id: i32,
+    for_user_id: i32,
+  ) -> Result<Option<i16>, Error> {
+    use crate::schema::post_like::dsl::*;
+    post_like
+      .filter(post_id.eq(for_post_id))
+      .filter(user_id.eq(for_user_id))
+      .select(score)
+      .first::<i16>(conn)
+      .optional()
+  }
+
+  /// The users who voted on a post with a score, newest vote first.
+  pub fn voters(
+    conn: &PgConnection,
+    for_post_id: i32,
+    for_score: i16,
+  ) -> Result<Vec<User_>, Error> {
+    use crate::schema::user_;
+    post_like::table
+      .inner_join(user_::table)
+      .filter(post_like::post_id.eq(for_post_id))
+      .filter(post_like::score.eq(for_score))
+      .order_by(post_like::published.desc())
+      .select(user_::all_columns)
+      .load::<User_>(conn)
+  }
+}
+
 #[derive(Identifiable, Queryable, Associations, PartialEq, Debug)]
 #[belongs_to(Post)]
 #[table_name = "post_saved"]
@@ -118,6 +352,8 @@ pub struct PostSaved {
   pub post_id: i32,
   pub user_id: i32,
   pub published: chrono::NaiveDateTime,
+  /// The `SavedFolder` it's in
+  pub folder_id: Option<i32>,
 }
 
 #[derive(Insertable, AsChangeset, Clone)]
@@ -125,13 +361,18 @@ pub struct PostSaved {
 pub struct PostSavedForm {
   pub post_id: i32,
   pub user_id: i32,
+  pub folder_id: Option<i32>,
 }
 
 impl Saveable<PostSavedForm> for PostSaved {
+  /// Saving a post that's already saved moves it to the folder.
   fn save(conn: &PgConnection, post_saved_form: &PostSavedForm) -> Result<Self, Error> {
     use crate::schema::post_saved::dsl::*;
     insert_into(post_saved)
       .values(post_saved_form)
+      .on_conflict((post_id, user_id))
+      .do_update()
+      .set(folder_id.eq(post_saved_form.folder_id))
       .get_result::<Self>(conn)
   }
   fn unsave(conn: &PgConnection, post_saved_form: &PostSavedForm) -> Result<usize, Error> {
@@ -180,6 +421,37 @@ impl Readable<PostReadForm> for PostRead {
   }
 }
 
+/// A public text post named `name`, for the tests.
+#[cfg(test)]
+pub fn post_form(
+  name: &str,
+  UserId(creator_id): UserId,
+  CommunityId(community_id): CommunityId,
+) -> PostForm {
+  PostForm {
+    name: name.into(),
+    url: None,
+    body: None,
+    creator_id,
+    community_id,
+    removed: None,
+    locked: None,
+    updated: None,
+    deleted: None,
+    nsfw: false,
+    stickied: None,
+    embed_title: None,
+    embed_description: None,
+    embed_html: None,
+    thumbnail_url: None,
+    alt_text: None,
+    visibility: None,
+    generated: None,
+    language_id: None,
+    scheduled_publish_time: None,
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::super::community::*;
@@ -241,6 +513,11 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
     };
 
     let inserted_post = Post::create(&conn, &new_post).unwrap();
@@ -263,6 +540,15 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      legal_hold: false,
+      slug: "a-test-post".into(),
+      excerpt: None,
+      alt_text: None,
+      visibility: PostVisibility::Public as i16,
+      ap_id: None,
+      generated: false,
+      language_id: None,
+      scheduled_publish_time: None,
     };
 
     // Post Like
@@ -286,6 +572,7 @@ mod tests {
     let post_saved_form = PostSavedForm {
       post_id: inserted_post.id,
       user_id: inserted_user.id,
+      folder_id: None,
     };
 
     let inserted_post_saved = PostSaved::save(&conn, &post_saved_form).unwrap();
@@ -295,6 +582,7 @@ mod tests {
       post_id: inserted_post.id,
       user_id: inserted_user.id,
       published: inserted_post_saved.published,
+      folder_id: None,
     };
 
     // Post Read
@@ -317,6 +605,19 @@ mod tests {
     let like_removed = PostLike::remove(&conn, &post_like_form).unwrap();
     let saved_removed = PostSaved::unsave(&conn, &post_saved_form).unwrap();
     let read_removed = PostRead::mark_as_unread(&conn, &post_read_form).unwrap();
+
+    // Renaming keeps the old slug in the history
+    let renamed_form = PostForm {
+      name: "A renamed post".into(),
+      ..new_post.clone()
+    };
+    let renamed_post = Post::update(&conn, inserted_post.id, &renamed_form).unwrap();
+    assert_eq!("a-renamed-post", renamed_post.slug);
+    assert!(Post::had_slug(&conn, inserted_post.id, "a-test-post").unwrap());
+    assert!(!Post::had_slug(&conn, inserted_post.id, "another-post").unwrap());
+    Post::forget_slug_history(&conn, inserted_post.id).unwrap();
+    assert!(!Post::had_slug(&conn, inserted_post.id, "a-test-post").unwrap());
+
     let num_deleted = Post::delete(&conn, inserted_post.id).unwrap();
     Community::delete(&conn, inserted_community.id).unwrap();
     User_::delete(&conn, inserted_user.id).unwrap();
@@ -0,0 +1,167 @@
+use super::post_view::rising_rank;
+use super::*;
+use crate::schema::post_activity_hour;
+use crate::schema::post_activity_hour::dsl::*;
+
+/// The score a post gained and the comments it got in an hour, which the Rising sort ranks by.
+/// The rows are kept up to date by triggers on post_like and comment.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[primary_key(post_id, hour)]
+#[table_name = "post_activity_hour"]
+pub struct PostActivityHour {
+  pub post_id: i32,
+  pub hour: chrono::NaiveDateTime,
+  pub score: i32,
+  pub comments: i32,
+}
+
+impl PostActivityHour {
+  pub fn for_post(conn: &PgConnection, for_post_id: i32) -> Result<Vec<Self>, Error> {
+    post_activity_hour
+      .filter(post_id.eq(for_post_id))
+      .order_by(hour)
+      .load::<Self>(conn)
+  }
+
+  /// The community's posts picking up votes and comments the fastest, ranked like the Rising
+  /// sort, as their ids, names and ranks. Only the posts with activity in the last 6 hours count.
+  pub fn rising_in_community(
+    conn: &PgConnection,
+    for_community_id: i32,
+    limit: i64,
+  ) -> Result<Vec<(i32, String, f64)>, Error> {
+    use crate::schema::post;
+    let active = post_activity_hour
+      .filter(hour.gt(now - 6.hours()))
+      .select(post_id);
+    post::table
+      .filter(post::community_id.eq(for_community_id))
+      .filter(post::removed.eq(false))
+      .filter(post::deleted.eq(false))
+      .filter(post::id.eq_any(active))
+      .select((post::id, post::name, rising_rank(post::id)))
+      .order_by(rising_rank(post::id).desc())
+      .limit(limit)
+      .load::<(i32, String, f64)>(conn)
+  }
+
+  /// Deletes the hours older than a day, which no longer count for anything. Returns how many
+  /// were deleted.
+  pub fn prune(conn: &PgConnection) -> Result<usize, Error> {
+    diesel::delete(post_activity_hour.filter(hour.lt(now - 1.days()))).execute(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::comment::*;
+  use super::super::community::*;
+  use super::super::post::*;
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_for_post() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "riser".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "rising_community".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A rising post".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let post_like_form = PostLikeForm {
+      post_id: inserted_post.id,
+      user_id: inserted_user.id,
+      score: 1,
+    };
+
+    PostLike::like(&conn, &post_like_form).unwrap();
+
+    let comment_form = CommentForm {
+      content: "A comment".into(),
+      creator_id: inserted_user.id,
+      post_id: inserted_post.id,
+      removed: None,
+      deleted: None,
+      read: None,
+      parent_id: None,
+      updated: None,
+      generated: None,
+      language_id: None,
+    };
+
+    Comment::create(&conn, &comment_form).unwrap();
+
+    let activity = PostActivityHour::for_post(&conn, inserted_post.id).unwrap();
+    PostActivityHour::prune(&conn).unwrap();
+    let activity_after_prune = PostActivityHour::for_post(&conn, inserted_post.id).unwrap();
+    let rising = PostActivityHour::rising_in_community(&conn, inserted_community.id, 5).unwrap();
+
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(1, activity.len());
+    assert_eq!(1, activity[0].score);
+    assert_eq!(1, activity[0].comments);
+    assert_eq!(activity, activity_after_prune);
+    assert_eq!(1, rising.len());
+    assert_eq!(inserted_post.id, rising[0].0);
+    assert!(rising[0].2 > 0.0);
+  }
+}
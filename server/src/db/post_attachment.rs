@@ -0,0 +1,219 @@
+use super::*;
+use crate::schema::post_attachment::dsl::*;
+use crate::schema::{post, post_attachment};
+
+/// An image, video or audio of a post, with its caption as the name.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[table_name = "post_attachment"]
+pub struct PostAttachment {
+  pub id: i32,
+  pub post_id: i32,
+  pub position: i32,
+  /// A `PostAttachmentKind`
+  pub kind: i16,
+  pub url: String,
+  pub mime_type: Option<String>,
+  pub name: Option<String>,
+  pub published: chrono::NaiveDateTime,
+  /// In seconds, for audio and video
+  pub duration: Option<i32>,
+}
+
+#[derive(Insertable, Clone, PartialEq, Debug)]
+#[table_name = "post_attachment"]
+pub struct PostAttachmentForm {
+  pub post_id: i32,
+  pub position: i32,
+  pub kind: i16,
+  pub url: String,
+  pub mime_type: Option<String>,
+  pub name: Option<String>,
+  pub duration: Option<i32>,
+}
+
+impl PostAttachment {
+  pub fn list_for_post(conn: &PgConnection, for_post_id: i32) -> Result<Vec<Self>, Error> {
+    post_attachment
+      .filter(post_id.eq(for_post_id))
+      .order_by(position)
+      .load::<Self>(conn)
+  }
+
+  /// How many attachments someone has added to their posts since then, leaving out those of
+  /// `except_post_id`, which is being edited.
+  pub fn count_for_creator_since(
+    conn: &PgConnection,
+    for_creator_id: i32,
+    since: chrono::NaiveDateTime,
+    except_post_id: Option<i32>,
+  ) -> Result<i64, Error> {
+    let mut query = post_attachment
+      .inner_join(post::table)
+      .filter(post::creator_id.eq(for_creator_id))
+      .filter(published.gt(since))
+      .into_boxed();
+    if let Some(except_post_id) = except_post_id {
+      query = query.filter(post_id.ne(except_post_id));
+    }
+    query.count().get_result(conn)
+  }
+
+  /// Replaces all of a post's attachments with these.
+  pub fn replace_for_post(
+    conn: &PgConnection,
+    for_post_id: i32,
+    forms: &[PostAttachmentForm],
+  ) -> Result<Vec<Self>, Error> {
+    conn.transaction(|| {
+      diesel::delete(post_attachment.filter(post_id.eq(for_post_id))).execute(conn)?;
+      insert_into(post_attachment)
+        .values(forms)
+        .get_results::<Self>(conn)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::community::*;
+  use super::super::ids::PostId;
+  use super::super::post::*;
+  use super::super::post_view::*;
+  use super::super::user::*;
+  use super::*;
+  use crate::naive_now;
+
+  #[test]
+  fn test_crud() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "attachment_user".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "attachment_community".into(),
+      title: "nada".into(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A gallery".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
+    };
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let image = PostAttachmentForm {
+      post_id: inserted_post.id,
+      position: 0,
+      kind: PostAttachmentKind::Image as i16,
+      url: "https://example.com/pictshare/cat.png".into(),
+      mime_type: Some("image/png".into()),
+      name: Some("A cat".into()),
+      duration: None,
+    };
+    let video = PostAttachmentForm {
+      position: 1,
+      kind: PostAttachmentKind::Video as i16,
+      url: "https://example.com/cat.mp4".into(),
+      mime_type: None,
+      name: None,
+      duration: Some(93),
+      ..image.clone()
+    };
+    PostAttachment::replace_for_post(&conn, inserted_post.id, &[video.clone()]).unwrap();
+    // Replacing them again leaves only the new ones
+    PostAttachment::replace_for_post(
+      &conn,
+      inserted_post.id,
+      &[PostAttachmentForm {
+        position: 0,
+        ..video.clone()
+      }],
+    )
+    .unwrap();
+    PostAttachment::replace_for_post(&conn, inserted_post.id, &[video, image]).unwrap();
+
+    let attachments = PostAttachment::list_for_post(&conn, inserted_post.id).unwrap();
+    let day_ago = naive_now() - chrono::Duration::days(1);
+    let recent =
+      PostAttachment::count_for_creator_since(&conn, inserted_user.id, day_ago, None).unwrap();
+    let recent_elsewhere = PostAttachment::count_for_creator_since(
+      &conn,
+      inserted_user.id,
+      day_ago,
+      Some(inserted_post.id),
+    )
+    .unwrap();
+    let post_view = PostView::read(&conn, PostId(inserted_post.id), None).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(2, attachments.len());
+    assert_eq!(2, recent);
+    assert_eq!(0, recent_elsewhere);
+    assert_eq!(Some("A cat".into()), attachments[0].name);
+    assert_eq!(PostAttachmentKind::Video as i16, attachments[1].kind);
+    assert_eq!(
+      serde_json::json!([
+        {
+          "kind": 0,
+          "url": "https://example.com/pictshare/cat.png",
+          "mime_type": "image/png",
+          "name": "A cat",
+          "duration": null,
+        },
+        {
+          "kind": 1,
+          "url": "https://example.com/cat.mp4",
+          "mime_type": null,
+          "name": null,
+          "duration": 93,
+        },
+      ]),
+      post_view.attachments
+    );
+  }
+}
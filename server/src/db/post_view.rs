@@ -1,7 +1,13 @@
+use super::ids::{PostId, UserId};
 use super::post_view::post_mview::BoxedQuery;
+use super::user_instance_block::instance_domain;
 use super::*;
+use crate::avatar::fill_default_avatar;
+use crate::schema::{community, community_block, post, user_, user_instance_block, user_language};
 use diesel::pg::Pg;
 
+sql_function!(fn rising_rank(x: diesel::sql_types::Integer) -> diesel::sql_types::Double);
+
 // The faked schema since diesel doesn't do views
 table! {
   post_view (id) {
@@ -22,6 +28,12 @@ table! {
     embed_description -> Nullable<Text>,
     embed_html -> Nullable<Text>,
     thumbnail_url -> Nullable<Text>,
+    legal_hold -> Bool,
+    slug -> Text,
+    excerpt -> Nullable<Text>,
+    alt_text -> Nullable<Text>,
+    visibility -> Int2,
+    ap_id -> Nullable<Text>,
     banned -> Bool,
     banned_from_community -> Bool,
     creator_name -> Varchar,
@@ -30,12 +42,16 @@ table! {
     community_removed -> Bool,
     community_deleted -> Bool,
     community_nsfw -> Bool,
+    community_hidden -> Bool,
+    attachments -> Jsonb,
     number_of_comments -> BigInt,
-    score -> BigInt,
-    upvotes -> BigInt,
-    downvotes -> BigInt,
+    score -> Nullable<BigInt>,
+    upvotes -> Nullable<BigInt>,
+    downvotes -> Nullable<BigInt>,
     hot_rank -> Int4,
     newest_activity_time -> Timestamp,
+    views -> BigInt,
+    scheduled_publish_time -> Nullable<Timestamp>,
     user_id -> Nullable<Int4>,
     my_vote -> Nullable<Int4>,
     subscribed -> Nullable<Bool>,
@@ -63,6 +79,12 @@ table! {
     embed_description -> Nullable<Text>,
     embed_html -> Nullable<Text>,
     thumbnail_url -> Nullable<Text>,
+    legal_hold -> Bool,
+    slug -> Text,
+    excerpt -> Nullable<Text>,
+    alt_text -> Nullable<Text>,
+    visibility -> Int2,
+    ap_id -> Nullable<Text>,
     banned -> Bool,
     banned_from_community -> Bool,
     creator_name -> Varchar,
@@ -71,12 +93,16 @@ table! {
     community_removed -> Bool,
     community_deleted -> Bool,
     community_nsfw -> Bool,
+    community_hidden -> Bool,
+    attachments -> Jsonb,
     number_of_comments -> BigInt,
-    score -> BigInt,
-    upvotes -> BigInt,
-    downvotes -> BigInt,
+    score -> Nullable<BigInt>,
+    upvotes -> Nullable<BigInt>,
+    downvotes -> Nullable<BigInt>,
     hot_rank -> Int4,
     newest_activity_time -> Timestamp,
+    views -> BigInt,
+    scheduled_publish_time -> Nullable<Timestamp>,
     user_id -> Nullable<Int4>,
     my_vote -> Nullable<Int4>,
     subscribed -> Nullable<Bool>,
@@ -107,6 +133,16 @@ pub struct PostView {
   pub embed_description: Option<String>,
   pub embed_html: Option<String>,
   pub thumbnail_url: Option<String>,
+  /// Only for admins, so it isn't sent
+  #[serde(skip_serializing, default)]
+  pub legal_hold: bool,
+  pub slug: String,
+  /// A plain text preview of the body
+  pub excerpt: Option<String>,
+  pub alt_text: Option<String>,
+  pub visibility: i16,
+  /// The id of the remote object it came from, for federated posts
+  pub ap_id: Option<String>,
   pub banned: bool,
   pub banned_from_community: bool,
   pub creator_name: String,
@@ -115,12 +151,19 @@ pub struct PostView {
   pub community_removed: bool,
   pub community_deleted: bool,
   pub community_nsfw: bool,
+  pub community_hidden: bool,
+  /// The post's `PostAttachment`s in order, as a list of `{kind, url, mime_type, name, duration}`
+  pub attachments: serde_json::Value,
   pub number_of_comments: i64,
-  pub score: i64,
-  pub upvotes: i64,
-  pub downvotes: i64,
+  pub score: Option<i64>,
+  pub upvotes: Option<i64>,
+  pub downvotes: Option<i64>,
   pub hot_rank: i32,
   pub newest_activity_time: chrono::NaiveDateTime,
+  /// How many times it was seen, counted a batch at a time
+  pub views: i64,
+  /// When it's published, for its creator's scheduled posts
+  pub scheduled_publish_time: Option<chrono::NaiveDateTime>,
   pub user_id: Option<i32>,
   pub my_vote: Option<i32>,
   pub subscribed: Option<bool>,
@@ -131,18 +174,18 @@ pub struct PostView {
 pub struct PostQueryBuilder<'a> {
   conn: &'a PgConnection,
   query: BoxedQuery<'a, Pg>,
-  listing_type: ListingType,
-  sort: &'a SortType,
-  my_user_id: Option<i32>,
-  for_creator_id: Option<i32>,
-  for_community_id: Option<i32>,
+  listing: ListingParams<'a>,
+  viewer: ViewerParams,
+  for_creator_id: Option<UserId>,
+  for_post_ids: Option<Vec<i32>>,
   search_term: Option<String>,
   url_search: Option<String>,
   show_nsfw: bool,
-  saved_only: bool,
+  hide_generated: bool,
   unread_only: bool,
-  page: Option<i64>,
-  limit: Option<i64>,
+  discovery_percent: i16,
+  discovery_local_only: bool,
+  collapse_crossposts: bool,
 }
 
 impl<'a> PostQueryBuilder<'a> {
@@ -154,38 +197,28 @@ impl<'a> PostQueryBuilder<'a> {
     PostQueryBuilder {
       conn,
       query,
-      listing_type: ListingType::All,
-      sort: &SortType::Hot,
-      my_user_id: None,
+      listing: ListingParams::new(&SortType::Hot),
+      viewer: ViewerParams::default(),
       for_creator_id: None,
-      for_community_id: None,
+      for_post_ids: None,
       search_term: None,
       url_search: None,
       show_nsfw: true,
-      saved_only: false,
+      hide_generated: false,
       unread_only: false,
-      page: None,
-      limit: None,
+      discovery_percent: 20,
+      discovery_local_only: false,
+      collapse_crossposts: false,
     }
   }
 
-  pub fn listing_type(mut self, listing_type: ListingType) -> Self {
-    self.listing_type = listing_type;
-    self
-  }
-
-  pub fn sort(mut self, sort: &'a SortType) -> Self {
-    self.sort = sort;
-    self
-  }
-
-  pub fn for_community_id<T: MaybeOptional<i32>>(mut self, for_community_id: T) -> Self {
-    self.for_community_id = for_community_id.get_optional();
+  pub fn for_creator_id<T: MaybeOptional<UserId>>(mut self, for_creator_id: T) -> Self {
+    self.for_creator_id = for_creator_id.get_optional();
     self
   }
 
-  pub fn for_creator_id<T: MaybeOptional<i32>>(mut self, for_creator_id: T) -> Self {
-    self.for_creator_id = for_creator_id.get_optional();
+  pub fn for_post_ids(mut self, for_post_ids: Vec<i32>) -> Self {
+    self.for_post_ids = Some(for_post_ids);
     self
   }
 
@@ -199,18 +232,14 @@ impl<'a> PostQueryBuilder<'a> {
     self
   }
 
-  pub fn my_user_id<T: MaybeOptional<i32>>(mut self, my_user_id: T) -> Self {
-    self.my_user_id = my_user_id.get_optional();
-    self
-  }
-
   pub fn show_nsfw(mut self, show_nsfw: bool) -> Self {
     self.show_nsfw = show_nsfw;
     self
   }
 
-  pub fn saved_only(mut self, saved_only: bool) -> Self {
-    self.saved_only = saved_only;
+  /// Leaves out the posts made by bots or generated by AI.
+  pub fn hide_generated(mut self, hide_generated: bool) -> Self {
+    self.hide_generated = hide_generated;
     self
   }
 
@@ -219,47 +248,175 @@ impl<'a> PostQueryBuilder<'a> {
     self
   }
 
-  pub fn page<T: MaybeOptional<i64>>(mut self, page: T) -> Self {
-    self.page = page.get_optional();
+  /// For the Blended listing, the percentage of it (from 0 to 100) made up of posts from outside
+  /// the viewer's subscriptions, and whether those are only local posts.
+  pub fn blended_discovery(mut self, percent: i16, local_only: bool) -> Self {
+    self.discovery_percent = percent;
+    self.discovery_local_only = local_only;
     self
   }
 
-  pub fn limit<T: MaybeOptional<i64>>(mut self, limit: T) -> Self {
-    self.limit = limit.get_optional();
+  /// For the All and Subscribed listings, only the highest ranked of the posts of the same link
+  /// in them, which `Post::crossposts` has the others of.
+  pub fn collapse_crossposts(mut self, collapse_crossposts: bool) -> Self {
+    self.collapse_crossposts = collapse_crossposts;
     self
   }
 
-  pub fn list(self) -> Result<Vec<PostView>, Error> {
+  /// The query `list` runs, without running it.
+  pub fn build(mut self) -> BoxedQuery<'a, Pg> {
     use super::post_view::post_mview::dsl::*;
 
-    let mut query = self.query;
+    let query = std::mem::replace(&mut self.query, post_mview.into_boxed());
+    let mut query = self.filtered(query);
 
-    if let ListingType::Subscribed = self.listing_type {
-      query = query.filter(subscribed.eq(true));
+    let collapses = match self.viewer.listing_type {
+      ListingType::All | ListingType::Subscribed => self.collapse_crossposts,
+      _ => false,
+    };
+    if collapses && self.viewer.for_community_id.is_none() {
+      // Window functions can't be filtered by, so the first post of each link is looked up among
+      // the same posts in a subquery
+      let first_of_link = format!(
+        "first_value(id) over (partition by coalesce(normalize_url(url), id::text) order by {})",
+        sort_order_sql(self.listing.sort)
+      );
+      let first_of_links = self
+        .filtered(post_mview.into_boxed())
+        .select(sql::<diesel::sql_types::Integer>(&first_of_link));
+      query = query.filter(id.eq_any(first_of_links));
     }
 
-    if let Some(for_community_id) = self.for_community_id {
-      query = query.filter(community_id.eq(for_community_id));
+    let (limit, offset) = self.listing.limit_and_offset();
+    query.limit(limit).offset(offset)
+  }
+
+  /// The listing's posts in order, without its page.
+  fn filtered(&self, query: BoxedQuery<'a, Pg>) -> BoxedQuery<'a, Pg> {
+    use super::post_view::post_mview::dsl::*;
+
+    let mut query = filter_for_viewer!(query, &self.viewer);
+
+    if self.viewer.for_community_id.is_some() {
       query = query.then_order_by(stickied.desc());
     }
 
-    if let Some(url_search) = self.url_search {
-      query = query.filter(url.eq(url_search));
+    if let Some(for_post_ids) = &self.for_post_ids {
+      query = query.filter(id.eq_any(for_post_ids.to_owned()));
+    }
+
+    if let Some(url_search) = &self.url_search {
+      query = query.filter(url.eq(url_search.to_owned()));
+    }
+
+    // Full text searches are ranked by how well the posts match, ahead of the sorts that aren't
+    // by time or score
+    let mut search_rank = None;
+    if let Some(search_term) = &self.search_term {
+      if is_full_text_search(search_term) {
+        let matches = post::table
+          .select(post::id)
+          .filter(full_text_match!("post", search_term.to_owned()));
+        query = query.filter(id.eq_any(matches));
+        search_rank = Some(search_term.to_owned());
+      } else {
+        let searcher = fuzzy_search(search_term);
+        query = query.filter(name.ilike(searcher.to_owned()).or(body.ilike(searcher)));
+      }
     }
 
-    if let Some(search_term) = self.search_term {
-      let searcher = fuzzy_search(&search_term);
+    if let ListingType::Blended = self.viewer.listing_type {
+      let subscribed_percent = 100 - self.discovery_percent;
+      // Each post's place among the subscribed or the other posts, stretched by the share of the
+      // listing they get, so that ordering by it interleaves them. A share of 0 puts them last.
+      query = query.then_order_by(sql::<diesel::sql_types::Double>(&format!(
+        "row_number() over (partition by coalesce(subscribed, false) order by {}) \
+        / nullif(case when coalesce(subscribed, false) then {} else {} end, 0)::float8",
+        sort_order_sql(self.listing.sort),
+        subscribed_percent,
+        self.discovery_percent
+      )));
+      if self.discovery_local_only {
+        query = query.filter(subscribed.eq(true).or(ap_id.is_null()));
+      }
+    }
+
+    // Leave out the posts from the instances the viewer blocked, and those in their communities or
+    // by their users, which can have ids from other instances
+    if let Some(my_user_id) = self.viewer.my_user_id {
+      let blocked_domains = || {
+        user_instance_block::table
+          .filter(user_instance_block::user_id.eq(my_user_id))
+          .select(user_instance_block::domain)
+      };
+      let blocked_communities = community::table
+        .filter(instance_domain(community::actor_id).eq_any(blocked_domains()))
+        .select(community::id);
+      let blocked_creators = user_::table
+        .filter(instance_domain(user_::actor_id).eq_any(blocked_domains()))
+        .select(user_::id);
       query = query
-        .filter(name.ilike(searcher.to_owned()))
-        .or_filter(body.ilike(searcher));
+        .filter(instance_domain(ap_id).ne_all(blocked_domains()))
+        .filter(community_id.ne_all(blocked_communities))
+        .filter(creator_id.ne_all(blocked_creators));
+    }
+
+    // Leave out the posts in the communities the viewer blocked, unless they're looking at one
+    if let (Some(my_user_id), None) = (self.viewer.my_user_id, self.viewer.for_community_id) {
+      let blocked_communities = community_block::table
+        .filter(community_block::user_id.eq(my_user_id))
+        .select(community_block::community_id);
+      query = query.filter(community_id.ne_all(blocked_communities));
+    }
+
+    // Leave out the posts with the words the viewer muted, except their own
+    if let Some(my_user_id) = self.viewer.my_user_id {
+      let muted = keyword_muted!(
+        "post",
+        "post_mview",
+        "t.name || ' ' || coalesce(t.body, '')",
+        my_user_id
+      );
+      query = query.filter(creator_id.eq(my_user_id).or(not(muted)));
+    }
+
+    // Only the posts in the viewer's languages, if they picked any, and those without one
+    if let Some(my_user_id) = self.viewer.my_user_id {
+      let languages = || {
+        user_language::table
+          .filter(user_language::user_id.eq(my_user_id))
+          .select(user_language::language_id.nullable())
+      };
+      let other_language_posts = post::table
+        .filter(exists(languages()))
+        .filter(post::language_id.ne_all(languages()))
+        .select(post::id);
+      query = query.filter(id.ne_all(other_language_posts));
+    }
+
+    if let (Some(search_rank), SortType::Hot) | (Some(search_rank), SortType::Rising) =
+      (search_rank, self.listing.sort)
+    {
+      query = query.then_order_by(full_text_rank!("post", "post_mview", search_rank).desc());
     }
 
-    query = match self.sort {
+    query = match self.listing.sort {
       SortType::Hot => query
         .then_order_by(hot_rank.desc())
         .then_order_by(published.desc()),
+      // Only posts of the last day can have rising, the rest have little activity left to rank by
+      SortType::Rising => query
+        .filter(published.gt(now - 1.days()))
+        .then_order_by(rising_rank(id).desc())
+        .then_order_by(published.desc()),
+      SortType::Controversial => query
+        .then_order_by(controversy_rank(upvotes, downvotes).desc())
+        .then_order_by(published.desc()),
       SortType::New => query.then_order_by(published.desc()),
       SortType::TopAll => query.then_order_by(score.desc()),
+      SortType::TopViews => query
+        .then_order_by(views.desc())
+        .then_order_by(published.desc()),
       SortType::TopYear => query
         .filter(published.gt(now - 1.years()))
         .then_order_by(score.desc()),
@@ -274,18 +431,13 @@ impl<'a> PostQueryBuilder<'a> {
         .then_order_by(score.desc()),
     };
 
-    // The view lets you pass a null user_id, if you're not logged in
-    query = if let Some(my_user_id) = self.my_user_id {
-      query.filter(user_id.eq(my_user_id))
-    } else {
-      query.filter(user_id.is_null())
-    };
-
-    // If its for a specific user, show the removed / deleted
+    // If its for a specific user, show the removed / deleted, and the scheduled posts, which only
+    // the creator gets
     if let Some(for_creator_id) = self.for_creator_id {
       query = query.filter(creator_id.eq(for_creator_id));
     } else {
       query = query
+        .filter(scheduled_publish_time.is_null())
         .filter(removed.eq(false))
         .filter(deleted.eq(false))
         .filter(community_removed.eq(false))
@@ -298,33 +450,113 @@ impl<'a> PostQueryBuilder<'a> {
         .filter(community_nsfw.eq(false));
     };
 
-    // TODO these are wrong, bc they'll only show saved for your logged in user, not theirs
-    if self.saved_only {
-      query = query.filter(saved.eq(true));
+    if self.hide_generated {
+      let generated_posts = post::table
+        .filter(post::generated.eq(true))
+        .select(post::id);
+      query = query.filter(id.ne_all(generated_posts));
     };
 
     if self.unread_only {
       query = query.filter(read.eq(false));
     };
 
-    let (limit, offset) = limit_and_offset(self.page, self.limit);
-    query = query
-      .limit(limit)
-      .offset(offset)
+    // Unlisted posts are only seen by their link, and followers only ones by the community's
+    // subscribers. Creators always see their own.
+    let listed = visibility.eq(PostVisibility::Public as i16).or(
+      visibility
+        .eq(PostVisibility::FollowersOnly as i16)
+        .and(subscribed.eq(true)),
+    );
+    query = match self.viewer.my_user_id {
+      Some(my_user_id) => query.filter(listed.or(creator_id.eq(my_user_id))),
+      None => query.filter(listed),
+    };
+
+    // Local only communities' posts are only listed for users who are logged in
+    if self.viewer.my_user_id.is_none() {
+      let local_only_communities = community::table
+        .filter(community::local_only.eq(true))
+        .select(community::id);
+      query = query.filter(community_id.ne_all(local_only_communities));
+    }
+
+    // Hidden communities' posts are only in their own listing, and their subscribers' front pages
+    if self.viewer.for_community_id.is_none() {
+      match self.viewer.listing_type {
+        ListingType::All => query = query.filter(community_hidden.eq(false)),
+        ListingType::Blended => {
+          query = query.filter(subscribed.eq(true).or(community_hidden.eq(false)))
+        }
+        _ => {}
+      }
+    }
+
+    query
       .filter(removed.eq(false))
       .filter(deleted.eq(false))
       .filter(community_removed.eq(false))
-      .filter(community_deleted.eq(false));
+      .filter(community_deleted.eq(false))
+  }
+}
+
+/// The order of a sort, as sql for a window function.
+fn sort_order_sql(sort: &SortType) -> &'static str {
+  match sort {
+    SortType::Hot => "hot_rank desc, published desc",
+    SortType::Rising => "rising_rank(id) desc, published desc",
+    SortType::Controversial => "controversy_rank(upvotes, downvotes) desc, published desc",
+    SortType::New => "published desc",
+    SortType::TopDay
+    | SortType::TopWeek
+    | SortType::TopMonth
+    | SortType::TopYear
+    | SortType::TopAll => "score desc",
+    SortType::TopViews => "views desc, published desc",
+  }
+}
+
+impl<'a> ListingQueryBuilder<'a> for PostQueryBuilder<'a> {
+  type View = PostView;
 
-    query.load::<PostView>(self.conn)
+  fn listing_params(&mut self) -> &mut ListingParams<'a> {
+    &mut self.listing
+  }
+
+  fn list(self) -> Result<Vec<PostView>, Error> {
+    let conn = self.conn;
+    self
+      .build()
+      .load::<PostView>(conn)
+      .map(DefaultAvatars::with_default_avatars)
+  }
+}
+
+impl<'a> ViewerQueryBuilder<'a> for PostQueryBuilder<'a> {
+  fn viewer_params(&mut self) -> &mut ViewerParams {
+    &mut self.viewer
+  }
+}
+
+impl HideScores for PostView {
+  fn hide_scores(&mut self) {
+    self.score = None;
+    self.upvotes = None;
+    self.downvotes = None;
+  }
+}
+
+impl DefaultAvatars for PostView {
+  fn default_avatars(&mut self) {
+    fill_default_avatar(&mut self.creator_avatar, &self.creator_name);
   }
 }
 
 impl PostView {
   pub fn read(
     conn: &PgConnection,
-    from_post_id: i32,
-    my_user_id: Option<i32>,
+    from_post_id: PostId,
+    my_user_id: Option<UserId>,
   ) -> Result<Self, Error> {
     use super::post_view::post_mview::dsl::*;
     use diesel::prelude::*;
@@ -339,16 +571,36 @@ impl PostView {
       query = query.filter(user_id.is_null());
     };
 
-    query.first::<Self>(conn)
+    query.first::<Self>(conn).map(Self::with_default_avatars)
   }
 }
 
 #[cfg(test)]
 mod tests {
+  use super::super::comment::*;
+  use super::super::comment_view::CommentQueryBuilder;
   use super::super::community::*;
+  use super::super::community_view::CommunityQueryBuilder;
   use super::super::post::*;
+  use super::super::query_plan::assert_no_seq_scan;
   use super::super::user::*;
   use super::*;
+  use crate::api::post::publish_scheduled_posts;
+  use crate::apub::vote::{comment_votes, post_votes};
+  use crate::apub::{federated_community, fetchable_post};
+  use crate::avatar::default_user_avatar;
+  use crate::naive_now;
+  use crate::routes::index::{match_slug, post_path, SlugMatch};
+
+  /// Whether a listing has a post, out of the posts with `post_ids`.
+  fn lists(builder: PostQueryBuilder, post_ids: &[i32], post_id: i32) -> bool {
+    builder
+      .for_post_ids(post_ids.to_vec())
+      .list()
+      .unwrap()
+      .iter()
+      .any(|p| p.id == post_id)
+  }
   #[test]
   fn test_crud() {
     let conn = establish_unpooled_connection();
@@ -409,6 +661,11 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
     };
 
     let inserted_post = Post::create(&conn, &new_post).unwrap();
@@ -445,7 +702,7 @@ mod tests {
       body: None,
       creator_id: inserted_user.id,
       creator_name: user_name.to_owned(),
-      creator_avatar: None,
+      creator_avatar: Some(default_user_avatar(&user_name)),
       banned: false,
       banned_from_community: false,
       community_id: inserted_community.id,
@@ -457,13 +714,17 @@ mod tests {
       community_removed: false,
       community_deleted: false,
       community_nsfw: false,
+      community_hidden: false,
+      attachments: serde_json::json!([]),
       number_of_comments: 0,
-      score: 1,
-      upvotes: 1,
-      downvotes: 0,
+      score: Some(1),
+      upvotes: Some(1),
+      downvotes: Some(0),
       hot_rank: 1728,
       published: inserted_post.published,
       newest_activity_time: inserted_post.published,
+      views: 0,
+      scheduled_publish_time: None,
       updated: None,
       subscribed: None,
       read: None,
@@ -473,6 +734,12 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      legal_hold: false,
+      slug: inserted_post.slug.to_owned(),
+      excerpt: None,
+      alt_text: None,
+      visibility: PostVisibility::Public as i16,
+      ap_id: None,
     };
 
     let expected_post_listing_with_user = PostView {
@@ -487,8 +754,8 @@ mod tests {
       locked: false,
       stickied: false,
       creator_id: inserted_user.id,
+      creator_avatar: Some(default_user_avatar(&user_name)),
       creator_name: user_name,
-      creator_avatar: None,
       banned: false,
       banned_from_community: false,
       community_id: inserted_community.id,
@@ -496,13 +763,17 @@ mod tests {
       community_removed: false,
       community_deleted: false,
       community_nsfw: false,
+      community_hidden: false,
+      attachments: serde_json::json!([]),
       number_of_comments: 0,
-      score: 1,
-      upvotes: 1,
-      downvotes: 0,
+      score: Some(1),
+      upvotes: Some(1),
+      downvotes: Some(0),
       hot_rank: 1728,
       published: inserted_post.published,
       newest_activity_time: inserted_post.published,
+      views: 0,
+      scheduled_publish_time: None,
       updated: None,
       subscribed: None,
       read: None,
@@ -512,26 +783,104 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      legal_hold: false,
+      slug: inserted_post.slug.to_owned(),
+      excerpt: None,
+      alt_text: None,
+      visibility: PostVisibility::Public as i16,
+      ap_id: None,
     };
 
     let read_post_listings_with_user = PostQueryBuilder::create(&conn)
       .listing_type(ListingType::Community)
       .sort(&SortType::New)
-      .for_community_id(inserted_community.id)
-      .my_user_id(inserted_user.id)
+      .for_community_id(CommunityId(inserted_community.id))
+      .my_user_id(UserId(inserted_user.id))
       .list()
       .unwrap();
 
     let read_post_listings_no_user = PostQueryBuilder::create(&conn)
       .listing_type(ListingType::Community)
       .sort(&SortType::New)
-      .for_community_id(inserted_community.id)
+      .for_community_id(CommunityId(inserted_community.id))
+      .list()
+      .unwrap();
+
+    let read_post_listing_no_user = PostView::read(&conn, PostId(inserted_post.id), None).unwrap();
+    let read_post_listing_with_user = PostView::read(
+      &conn,
+      PostId(inserted_post.id),
+      Some(UserId(inserted_user.id)),
+    )
+    .unwrap();
+
+    // Other posts are mixed into the blended listing, only local ones if asked
+    let blended_listings = PostQueryBuilder::create(&conn)
+      .listing_type(ListingType::Blended)
+      .blended_discovery(50, true)
+      .my_user_id(UserId(inserted_user.id))
+      .limit(std::i64::MAX)
+      .list()
+      .unwrap();
+
+    // Hidden communities' posts are only left out of the listing of all posts, and the others
+    // mixed into the blended one
+    Community::update_hidden(&conn, inserted_community.id, true).unwrap();
+    let hidden_all_listings = PostQueryBuilder::create(&conn)
+      .listing_type(ListingType::All)
+      .limit(std::i64::MAX)
+      .list()
+      .unwrap();
+    let hidden_blended_listings = PostQueryBuilder::create(&conn)
+      .listing_type(ListingType::Blended)
+      .my_user_id(UserId(inserted_user.id))
+      .limit(std::i64::MAX)
       .list()
       .unwrap();
+    let hidden_community_listings = PostQueryBuilder::create(&conn)
+      .listing_type(ListingType::Community)
+      .for_community_id(CommunityId(inserted_community.id))
+      .list()
+      .unwrap();
+    Community::update_hidden(&conn, inserted_community.id, false).unwrap();
 
-    let read_post_listing_no_user = PostView::read(&conn, inserted_post.id, None).unwrap();
-    let read_post_listing_with_user =
-      PostView::read(&conn, inserted_post.id, Some(inserted_user.id)).unwrap();
+    // Unlisted posts are left out of the listings, except for their creator's
+    let unlisted_form = PostForm {
+      visibility: Some(PostVisibility::Unlisted as i16),
+      ..new_post.clone()
+    };
+    Post::update(&conn, inserted_post.id, &unlisted_form).unwrap();
+    let unlisted_listings_no_user = PostQueryBuilder::create(&conn)
+      .listing_type(ListingType::Community)
+      .for_community_id(CommunityId(inserted_community.id))
+      .list()
+      .unwrap();
+    let unlisted_listings_with_user = PostQueryBuilder::create(&conn)
+      .listing_type(ListingType::Community)
+      .for_community_id(CommunityId(inserted_community.id))
+      .my_user_id(UserId(inserted_user.id))
+      .list()
+      .unwrap();
+    let unlisted_post = PostView::read(&conn, PostId(inserted_post.id), None).unwrap();
+
+    // Generated posts are only left out for the users who hide them
+    let generated_form = PostForm {
+      visibility: Some(PostVisibility::Public as i16),
+      generated: Some(true),
+      ..new_post.clone()
+    };
+    Post::update(&conn, inserted_post.id, &generated_form).unwrap();
+    let generated_listings = PostQueryBuilder::create(&conn)
+      .listing_type(ListingType::Community)
+      .for_community_id(CommunityId(inserted_community.id))
+      .list()
+      .unwrap();
+    let generated_hidden_listings = PostQueryBuilder::create(&conn)
+      .listing_type(ListingType::Community)
+      .for_community_id(CommunityId(inserted_community.id))
+      .hide_generated(true)
+      .list()
+      .unwrap();
 
     let like_removed = PostLike::remove(&conn, &post_like_form).unwrap();
     let num_deleted = Post::delete(&conn, inserted_post.id).unwrap();
@@ -551,10 +900,473 @@ mod tests {
     assert_eq!(expected_post_listing_no_user, read_post_listing_no_user);
     assert_eq!(1, read_post_listings_no_user.len());
 
+    assert!(blended_listings.iter().any(|p| p.id == inserted_post.id));
+    assert!(!hidden_all_listings.iter().any(|p| p.id == inserted_post.id));
+    assert!(!hidden_blended_listings
+      .iter()
+      .any(|p| p.id == inserted_post.id));
+    assert_eq!(1, hidden_community_listings.len());
+    assert!(unlisted_listings_no_user.is_empty());
+    assert_eq!(1, unlisted_listings_with_user.len());
+    assert_eq!(PostVisibility::Unlisted as i16, unlisted_post.visibility);
+    assert_eq!(1, generated_listings.len());
+    assert!(generated_hidden_listings.is_empty());
+
     // assert_eq!(expected_post, inserted_post);
     // assert_eq!(expected_post, updated_post);
     assert_eq!(expected_post_like, inserted_post_like);
     assert_eq!(1, like_removed);
     assert_eq!(1, num_deleted);
   }
+
+  #[test]
+  fn test_collapse_crossposts() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "crossposter".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      updated: None,
+      admin: false,
+      banned: false,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_crosspost_community".into(),
+      title: "nada".to_owned(),
+      description: None,
+      creator_id: inserted_user.id,
+      category_id: 1,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let link_post = |name: &str, url: &str| PostForm {
+      url: Some(url.into()),
+      ..post_form(
+        name,
+        UserId(inserted_user.id),
+        CommunityId(inserted_community.id),
+      )
+    };
+
+    // The same link, but for the tracking and the trailing slash
+    let first_post = Post::create(
+      &conn,
+      &link_post("crosspost 1", "https://crosspost.example/story/"),
+    )
+    .unwrap();
+    let second_post = Post::create(
+      &conn,
+      &link_post(
+        "crosspost 2",
+        "http://www.crosspost.example/story?utm_source=feed",
+      ),
+    )
+    .unwrap();
+    let other_post = Post::create(
+      &conn,
+      &link_post("crosspost 3", "https://crosspost.example/other"),
+    )
+    .unwrap();
+    let post_ids = vec![first_post.id, second_post.id, other_post.id];
+
+    let listing = |collapse: bool| {
+      PostQueryBuilder::create(&conn)
+        .listing_type(ListingType::All)
+        .sort(&SortType::New)
+        .for_post_ids(post_ids.to_owned())
+        .collapse_crossposts(collapse)
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|p| p.id)
+        .collect::<Vec<i32>>()
+    };
+    let listed = listing(false);
+    let collapsed = listing(true);
+    let crossposts = Post::crossposts(&conn, &collapsed).unwrap();
+
+    for post_id in &post_ids {
+      Post::delete(&conn, *post_id).unwrap();
+    }
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(vec![other_post.id, second_post.id, first_post.id], listed);
+    // Only the newest of the two is left
+    assert_eq!(vec![other_post.id, second_post.id], collapsed);
+    assert_eq!(
+      vec![Crosspost {
+        post_id: second_post.id,
+        id: first_post.id,
+        community_id: inserted_community.id,
+        community_name: "test_crosspost_community".into(),
+      }],
+      crossposts
+    );
+  }
+
+  #[test]
+  fn test_scheduled_post() {
+    let conn = establish_unpooled_connection();
+
+    let creator = User_::create(&conn, &user_form("scheduled_creator")).unwrap();
+    let other = User_::create(&conn, &user_form("scheduled_other")).unwrap();
+    let community = Community::create(
+      &conn,
+      &community_form("scheduled_community", UserId(creator.id)),
+    )
+    .unwrap();
+    let scheduled_post = Post::create(
+      &conn,
+      &PostForm {
+        scheduled_publish_time: Some(naive_now() - chrono::Duration::minutes(1)),
+        ..post_form(
+          "A scheduled quasar",
+          UserId(creator.id),
+          CommunityId(community.id),
+        )
+      },
+    )
+    .unwrap();
+    let comment = Comment::create(
+      &conn,
+      &CommentForm {
+        creator_id: creator.id,
+        post_id: scheduled_post.id,
+        parent_id: None,
+        content: "Ready for later".into(),
+        removed: None,
+        read: None,
+        updated: None,
+        deleted: None,
+        generated: None,
+        language_id: None,
+      },
+    )
+    .unwrap();
+    let post_ids = [scheduled_post.id];
+
+    // Whether a user, or nobody logged in, sees it in the All listing, searches, its creator's
+    // posts, and on its own
+    let seen_by = |user_id: Option<i32>| {
+      let viewer = || {
+        PostQueryBuilder::create(&conn)
+          .listing_type(ListingType::All)
+          .my_user_id(user_id.map(UserId))
+      };
+      vec![
+        lists(viewer(), &post_ids, scheduled_post.id),
+        lists(
+          viewer().search_term("quasar".to_string()),
+          &post_ids,
+          scheduled_post.id,
+        ),
+        lists(
+          viewer().for_creator_id(UserId(creator.id)),
+          &post_ids,
+          scheduled_post.id,
+        ),
+        PostView::read(&conn, PostId(scheduled_post.id), user_id.map(UserId)).is_ok(),
+      ]
+    };
+    // Whether other instances can fetch it, which goes for its comments too, and their votes
+    let fetchable = || {
+      vec![
+        fetchable_post(&conn, scheduled_post.id).is_some(),
+        post_votes(&conn, scheduled_post.id, "likes").is_some(),
+        comment_votes(&conn, comment.id, "likes").is_some(),
+      ]
+    };
+
+    let seen_by_creator = seen_by(Some(creator.id));
+    let seen_by_other = seen_by(Some(other.id));
+    let seen_by_nobody = seen_by(None);
+    let fetchable_before = fetchable();
+    // Its bare url isn't redirected to the one with its title until it's published
+    let slug_before = match_slug(&conn, scheduled_post.id, None).unwrap();
+    let due = Post::list_due_scheduled(&conn).unwrap();
+
+    let published = publish_scheduled_posts(&conn).unwrap();
+    let published_post = Post::read(&conn, scheduled_post.id).unwrap();
+    let published_again = Post::publish_scheduled(&conn, scheduled_post.id).unwrap();
+    let seen_by_other_after = seen_by(Some(other.id));
+    let seen_by_nobody_after = seen_by(None);
+    let fetchable_after = fetchable();
+    let slug_after = match_slug(&conn, scheduled_post.id, None).unwrap();
+
+    Comment::delete(&conn, comment.id).unwrap();
+    Post::delete(&conn, scheduled_post.id).unwrap();
+    Community::delete(&conn, community.id).unwrap();
+    User_::delete(&conn, creator.id).unwrap();
+    User_::delete(&conn, other.id).unwrap();
+
+    assert_eq!(vec![true; 4], seen_by_creator);
+    assert_eq!(vec![false; 4], seen_by_other);
+    assert_eq!(vec![false; 4], seen_by_nobody);
+    assert_eq!(vec![false; 3], fetchable_before);
+    assert_eq!(SlugMatch::Current, slug_before);
+    assert!(due.iter().any(|p| p.id == scheduled_post.id));
+
+    assert!(published >= 1);
+    assert_eq!(None, published_post.scheduled_publish_time);
+    assert_eq!(None, published_again);
+    assert_eq!(vec![true; 4], seen_by_other_after);
+    assert_eq!(vec![true; 4], seen_by_nobody_after);
+    assert_eq!(vec![true; 3], fetchable_after);
+    assert_eq!(
+      SlugMatch::Moved(format!(
+        "/{}",
+        post_path(published_post.id, &published_post.slug)
+      )),
+      slug_after
+    );
+  }
+
+  #[test]
+  fn test_post_visibility() {
+    let conn = establish_unpooled_connection();
+
+    let creator = User_::create(&conn, &user_form("visibility_creator")).unwrap();
+    let follower = User_::create(&conn, &user_form("visibility_follower")).unwrap();
+    let other = User_::create(&conn, &user_form("visibility_other")).unwrap();
+    let community = Community::create(
+      &conn,
+      &community_form("visibility_community", UserId(creator.id)),
+    )
+    .unwrap();
+    CommunityFollower::follow(
+      &conn,
+      &CommunityFollowerForm {
+        community_id: community.id,
+        user_id: follower.id,
+      },
+    )
+    .unwrap();
+    let create_post = |name: &str, visibility: PostVisibility| {
+      Post::create(
+        &conn,
+        &PostForm {
+          visibility: Some(visibility as i16),
+          ..post_form(name, UserId(creator.id), CommunityId(community.id))
+        },
+      )
+      .unwrap()
+    };
+    let followers_only_post = create_post("A followers only nebula", PostVisibility::FollowersOnly);
+    let unlisted_post = create_post("An unlisted nebula", PostVisibility::Unlisted);
+    let post_ids = [followers_only_post.id, unlisted_post.id];
+
+    // Whether a user, or nobody logged in, sees a post in the All and community listings, and
+    // searches
+    let seen_by = |user_id: Option<i32>, post_id: i32| {
+      let viewer = |listing_type: ListingType| {
+        PostQueryBuilder::create(&conn)
+          .listing_type(listing_type)
+          .my_user_id(user_id.map(UserId))
+      };
+      vec![
+        lists(viewer(ListingType::All), &post_ids, post_id),
+        lists(
+          viewer(ListingType::Community).for_community_id(CommunityId(community.id)),
+          &post_ids,
+          post_id,
+        ),
+        lists(
+          viewer(ListingType::All).search_term("nebula".to_string()),
+          &post_ids,
+          post_id,
+        ),
+      ]
+    };
+
+    let followers_only_seen = vec![
+      seen_by(Some(creator.id), followers_only_post.id),
+      seen_by(Some(follower.id), followers_only_post.id),
+      seen_by(Some(other.id), followers_only_post.id),
+      seen_by(None, followers_only_post.id),
+    ];
+    let unlisted_seen = vec![
+      seen_by(Some(creator.id), unlisted_post.id),
+      seen_by(Some(follower.id), unlisted_post.id),
+      seen_by(Some(other.id), unlisted_post.id),
+      seen_by(None, unlisted_post.id),
+    ];
+    // Anyone with the link can still see an unlisted post
+    let unlisted_read = PostView::read(&conn, PostId(unlisted_post.id), None).is_ok();
+    // Only the unlisted one's bare url is redirected to the one with its title
+    let followers_only_slug = match_slug(&conn, followers_only_post.id, None).unwrap();
+    let unlisted_slug = match_slug(&conn, unlisted_post.id, None).unwrap();
+
+    Post::delete(&conn, followers_only_post.id).unwrap();
+    Post::delete(&conn, unlisted_post.id).unwrap();
+    Community::delete(&conn, community.id).unwrap();
+    User_::delete(&conn, creator.id).unwrap();
+    User_::delete(&conn, follower.id).unwrap();
+    User_::delete(&conn, other.id).unwrap();
+
+    assert_eq!(
+      vec![vec![true; 3], vec![true; 3], vec![false; 3], vec![false; 3]],
+      followers_only_seen
+    );
+    assert_eq!(
+      vec![
+        vec![true; 3],
+        vec![false; 3],
+        vec![false; 3],
+        vec![false; 3]
+      ],
+      unlisted_seen
+    );
+    assert!(unlisted_read);
+    assert_eq!(SlugMatch::Current, followers_only_slug);
+    assert_eq!(
+      SlugMatch::Moved(format!(
+        "/{}",
+        post_path(unlisted_post.id, &unlisted_post.slug)
+      )),
+      unlisted_slug
+    );
+  }
+
+  #[test]
+  fn test_local_only() {
+    let conn = establish_unpooled_connection();
+
+    let creator = User_::create(&conn, &user_form("local_only_creator")).unwrap();
+    let community = Community::create(
+      &conn,
+      &community_form("local_only_community", UserId(creator.id)),
+    )
+    .unwrap();
+    let post = Post::create(
+      &conn,
+      &post_form(
+        "A local only pulsar",
+        UserId(creator.id),
+        CommunityId(community.id),
+      ),
+    )
+    .unwrap();
+    let comment = Comment::create(
+      &conn,
+      &CommentForm {
+        creator_id: creator.id,
+        post_id: post.id,
+        parent_id: None,
+        content: "Kept at home".into(),
+        removed: None,
+        read: None,
+        updated: None,
+        deleted: None,
+        generated: None,
+        language_id: None,
+      },
+    )
+    .unwrap();
+    let post_ids = [post.id];
+
+    // Whether a user, or nobody logged in, sees the post, the comment and the community in their
+    // listings
+    let seen_by = |user_id: Option<i32>| {
+      vec![
+        lists(
+          PostQueryBuilder::create(&conn)
+            .listing_type(ListingType::All)
+            .my_user_id(user_id.map(UserId)),
+          &post_ids,
+          post.id,
+        ),
+        CommentQueryBuilder::create(&conn)
+          .for_post_id(PostId(post.id))
+          .my_user_id(user_id.map(UserId))
+          .list()
+          .unwrap()
+          .iter()
+          .any(|c| c.id == comment.id),
+        CommunityQueryBuilder::create(&conn)
+          .search_term("local_only_community".to_string())
+          .for_user(user_id.map(UserId))
+          .list()
+          .unwrap()
+          .iter()
+          .any(|c| c.id == community.id),
+      ]
+    };
+    // Whether other instances can fetch the community, the post, the comment and their votes
+    let fetchable = || {
+      vec![
+        federated_community(&conn, community.id).is_some(),
+        fetchable_post(&conn, post.id).is_some(),
+        post_votes(&conn, post.id, "likes").is_some(),
+        comment_votes(&conn, comment.id, "likes").is_some(),
+      ]
+    };
+
+    let seen_by_nobody_before = seen_by(None);
+    let fetchable_before = fetchable();
+
+    Community::update_local_only(&conn, community.id, true).unwrap();
+    let seen_by_creator = seen_by(Some(creator.id));
+    let seen_by_nobody = seen_by(None);
+    let fetchable_after = fetchable();
+
+    Comment::delete(&conn, comment.id).unwrap();
+    Post::delete(&conn, post.id).unwrap();
+    Community::delete(&conn, community.id).unwrap();
+    User_::delete(&conn, creator.id).unwrap();
+
+    assert_eq!(vec![true; 3], seen_by_nobody_before);
+    assert_eq!(vec![true; 4], fetchable_before);
+    assert_eq!(vec![true; 3], seen_by_creator);
+    assert_eq!(vec![false; 3], seen_by_nobody);
+    assert_eq!(vec![false; 4], fetchable_after);
+  }
+
+  #[test]
+  fn test_query_plans() {
+    let conn = establish_unpooled_connection();
+
+    assert_no_seq_scan(&conn, PostQueryBuilder::create(&conn).build());
+    assert_no_seq_scan(
+      &conn,
+      PostQueryBuilder::create(&conn)
+        .listing_type(ListingType::Subscribed)
+        .my_user_id(UserId(1))
+        .build(),
+    );
+    assert_no_seq_scan(
+      &conn,
+      PostQueryBuilder::create(&conn)
+        .listing_type(ListingType::Community)
+        .for_community_id(CommunityId(1))
+        .sort(&SortType::New)
+        .build(),
+    );
+    assert_no_seq_scan(
+      &conn,
+      PostQueryBuilder::create(&conn)
+        .search_term("memory safety".to_string())
+        .build(),
+    );
+  }
 }
@@ -0,0 +1,128 @@
+use super::*;
+use crate::schema::post_view_count::dsl::*;
+use crate::schema::{post, post_view_count};
+use diesel::upsert::excluded;
+
+/// How many times a post was seen.
+#[derive(Queryable, Insertable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "post_view_count"]
+pub struct PostViewCount {
+  pub post_id: i32,
+  pub views: i64,
+}
+
+impl PostViewCount {
+  pub fn read(conn: &PgConnection, for_post_id: i32) -> Result<Self, Error> {
+    post_view_count.find(for_post_id).first::<Self>(conn)
+  }
+
+  /// Adds the views to the posts' counts, in one statement so the post aggregates are only
+  /// refreshed once. The posts which were deleted since are left out.
+  pub fn add(conn: &PgConnection, counts: &[PostViewCount]) -> Result<usize, Error> {
+    let existing_posts = post::table
+      .filter(post::id.eq_any(counts.iter().map(|c| c.post_id).collect::<Vec<_>>()))
+      .select(post::id)
+      .load::<i32>(conn)?;
+    let counts = counts
+      .iter()
+      .filter(|c| existing_posts.contains(&c.post_id))
+      .map(|c| PostViewCount {
+        post_id: c.post_id,
+        views: c.views,
+      })
+      .collect::<Vec<_>>();
+    if counts.is_empty() {
+      return Ok(0);
+    }
+    insert_into(post_view_count)
+      .values(&counts)
+      .on_conflict(post_id)
+      .do_update()
+      .set(views.eq(views + excluded(views)))
+      .execute(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::community::*;
+  use super::super::post::*;
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_add_views() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "post_viewer".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_post_views".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A viewed post".into(),
+      url: None,
+      body: None,
+      creator_id: inserted_user.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
+    };
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let count = |post_id, views| PostViewCount { post_id, views };
+    let added = PostViewCount::add(&conn, &[count(inserted_post.id, 1), count(-1, 10)]).unwrap();
+    PostViewCount::add(&conn, &[count(inserted_post.id, 10)]).unwrap();
+    let read_count = PostViewCount::read(&conn, inserted_post.id).unwrap();
+
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    // The post that doesn't exist is left out
+    assert_eq!(1, added);
+    assert_eq!(count(inserted_post.id, 11), read_count);
+  }
+}
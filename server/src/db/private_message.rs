@@ -1,3 +1,4 @@
+use super::encryption;
 use super::*;
 use crate::schema::private_message;
 
@@ -12,6 +13,9 @@ pub struct PrivateMessage {
   pub read: bool,
   pub published: chrono::NaiveDateTime,
   pub updated: Option<chrono::NaiveDateTime>,
+  /// Whether `content` is encrypted at rest. It's decrypted when it's read, so this is false.
+  #[serde(skip)]
+  pub encrypted: bool,
 }
 
 #[derive(Insertable, AsChangeset, Clone)]
@@ -25,10 +29,26 @@ pub struct PrivateMessageForm {
   pub updated: Option<chrono::NaiveDateTime>,
 }
 
+/// A `PrivateMessageForm` as it's stored, with its content encrypted if encryption is on.
+#[derive(Insertable, AsChangeset)]
+#[table_name = "private_message"]
+struct StoredPrivateMessageForm {
+  creator_id: i32,
+  recipient_id: i32,
+  content: Option<String>,
+  encrypted: Option<bool>,
+  deleted: Option<bool>,
+  read: Option<bool>,
+  updated: Option<chrono::NaiveDateTime>,
+}
+
 impl Crud<PrivateMessageForm> for PrivateMessage {
   fn read(conn: &PgConnection, private_message_id: i32) -> Result<Self, Error> {
     use crate::schema::private_message::dsl::*;
-    private_message.find(private_message_id).first::<Self>(conn)
+    private_message
+      .find(private_message_id)
+      .first::<Self>(conn)?
+      .decrypted()
   }
 
   fn delete(conn: &PgConnection, private_message_id: i32) -> Result<usize, Error> {
@@ -39,8 +59,9 @@ impl Crud<PrivateMessageForm> for PrivateMessage {
   fn create(conn: &PgConnection, private_message_form: &PrivateMessageForm) -> Result<Self, Error> {
     use crate::schema::private_message::dsl::*;
     insert_into(private_message)
-      .values(private_message_form)
-      .get_result::<Self>(conn)
+      .values(&private_message_form.encrypted()?)
+      .get_result::<Self>(conn)?
+      .decrypted()
   }
 
   fn update(
@@ -50,13 +71,67 @@ impl Crud<PrivateMessageForm> for PrivateMessage {
   ) -> Result<Self, Error> {
     use crate::schema::private_message::dsl::*;
     diesel::update(private_message.find(private_message_id))
-      .set(private_message_form)
-      .get_result::<Self>(conn)
+      .set(&private_message_form.encrypted()?)
+      .get_result::<Self>(conn)?
+      .decrypted()
+  }
+}
+
+impl PrivateMessage {
+  /// Marks the private messages to a user as read, or unread: the ones in `private_message_ids`,
+  /// or all of them. The ones the user sent are left alone.
+  pub fn update_read(
+    conn: &PgConnection,
+    for_recipient_id: i32,
+    private_message_ids: Option<&[i32]>,
+    new_read: bool,
+  ) -> Result<usize, Error> {
+    use crate::schema::private_message::dsl::*;
+    let messages = private_message
+      .filter(recipient_id.eq(for_recipient_id))
+      .filter(read.ne(new_read));
+
+    match private_message_ids {
+      Some(private_message_ids) => diesel::update(messages.filter(id.eq_any(private_message_ids)))
+        .set(read.eq(new_read))
+        .execute(conn),
+      None => diesel::update(messages)
+        .set(read.eq(new_read))
+        .execute(conn),
+    }
+  }
+
+  fn decrypted(mut self) -> Result<Self, Error> {
+    self.content = encryption::decrypt(&self.content, self.encrypted)?;
+    self.encrypted = false;
+    Ok(self)
+  }
+}
+
+impl PrivateMessageForm {
+  fn encrypted(&self) -> Result<StoredPrivateMessageForm, Error> {
+    let (content, encrypted) = match &self.content {
+      Some(content) => {
+        let (content, encrypted) = encryption::encrypt(content)?;
+        (Some(content), Some(encrypted))
+      }
+      None => (None, None),
+    };
+    Ok(StoredPrivateMessageForm {
+      creator_id: self.creator_id,
+      recipient_id: self.recipient_id,
+      content,
+      encrypted,
+      deleted: self.deleted,
+      read: self.read,
+      updated: self.updated,
+    })
   }
 }
 
 #[cfg(test)]
 mod tests {
+  use super::super::private_message_view::*;
   use super::super::user::*;
   use super::*;
   #[test]
@@ -127,12 +202,24 @@ mod tests {
       read: false,
       updated: None,
       published: inserted_private_message.published,
+      encrypted: false,
     };
 
     let read_private_message = PrivateMessage::read(&conn, inserted_private_message.id).unwrap();
     let updated_private_message =
       PrivateMessage::update(&conn, inserted_private_message.id, &private_message_form).unwrap();
     let num_deleted = PrivateMessage::delete(&conn, inserted_private_message.id).unwrap();
+
+    // Plaintext that looks like encrypted content is read back as it was written
+    let lookalike_form = PrivateMessageForm {
+      content: Some("enc:v1:x:y".into()),
+      ..private_message_form
+    };
+    let lookalike = PrivateMessage::create(&conn, &lookalike_form).unwrap();
+    let read_lookalike = PrivateMessage::read(&conn, lookalike.id).unwrap();
+    let read_lookalike_view = PrivateMessageView::read(&conn, lookalike.id).unwrap();
+    PrivateMessage::delete(&conn, lookalike.id).unwrap();
+
     User_::delete(&conn, inserted_creator.id).unwrap();
     User_::delete(&conn, inserted_recipient.id).unwrap();
 
@@ -140,5 +227,8 @@ mod tests {
     assert_eq!(expected_private_message, updated_private_message);
     assert_eq!(expected_private_message, inserted_private_message);
     assert_eq!(1, num_deleted);
+    assert_eq!("enc:v1:x:y", lookalike.content);
+    assert_eq!("enc:v1:x:y", read_lookalike.content);
+    assert_eq!("enc:v1:x:y", read_lookalike_view.content);
   }
 }
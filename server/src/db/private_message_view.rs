@@ -1,4 +1,7 @@
+use super::encryption;
 use super::*;
+use crate::avatar::fill_default_avatar;
+use crate::schema::user_block;
 use diesel::pg::Pg;
 
 // The faked schema since diesel doesn't do views
@@ -16,6 +19,7 @@ table! {
     creator_avatar -> Nullable<Text>,
     recipient_name -> Varchar,
     recipient_avatar -> Nullable<Text>,
+    encrypted -> Bool,
   }
 }
 
@@ -33,6 +37,7 @@ table! {
     creator_avatar -> Nullable<Text>,
     recipient_name -> Varchar,
     recipient_avatar -> Nullable<Text>,
+    encrypted -> Bool,
   }
 }
 
@@ -53,6 +58,9 @@ pub struct PrivateMessageView {
   pub creator_avatar: Option<String>,
   pub recipient_name: String,
   pub recipient_avatar: Option<String>,
+  /// Whether `content` is encrypted at rest, until it's decrypted
+  #[serde(skip)]
+  pub encrypted: bool,
 }
 
 pub struct PrivateMessageQueryBuilder<'a> {
@@ -100,6 +108,15 @@ impl<'a> PrivateMessageQueryBuilder<'a> {
 
     let mut query = self.query.filter(deleted.eq(false));
 
+    // Not from the users they blocked
+    query = query.filter(
+      creator_id.ne_all(
+        user_block::table
+          .filter(user_block::user_id.eq(self.for_recipient_id))
+          .select(user_block::target_id),
+      ),
+    );
+
     // If its unread, I only want the ones to me
     if self.unread_only {
       query = query
@@ -121,7 +138,17 @@ impl<'a> PrivateMessageQueryBuilder<'a> {
       .limit(limit)
       .offset(offset)
       .order_by(published.desc())
-      .load::<PrivateMessageView>(self.conn)
+      .load::<PrivateMessageView>(self.conn)?
+      .into_iter()
+      .map(|message| message.with_default_avatars().decrypted())
+      .collect()
+  }
+}
+
+impl DefaultAvatars for PrivateMessageView {
+  fn default_avatars(&mut self) {
+    fill_default_avatar(&mut self.creator_avatar, &self.creator_name);
+    fill_default_avatar(&mut self.recipient_avatar, &self.recipient_name);
   }
 }
 
@@ -135,6 +162,15 @@ impl PrivateMessageView {
       .filter(id.eq(from_private_message_id))
       .order_by(published.desc());
 
-    query.first::<Self>(conn)
+    query
+      .first::<Self>(conn)?
+      .with_default_avatars()
+      .decrypted()
+  }
+
+  fn decrypted(mut self) -> Result<Self, Error> {
+    self.content = encryption::decrypt(&self.content, self.encrypted)?;
+    self.encrypted = false;
+    Ok(self)
   }
 }
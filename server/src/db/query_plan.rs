@@ -0,0 +1,78 @@
+//! Test helpers for checking that the listing queries can use indexes.
+//!
+//! The test database is nearly empty, so postgres would happily seq scan everything. Plans are
+//! made with `enable_seqscan` off instead: a seq scan that's still in the plan then means there's
+//! no index it could have used.
+//!
+//! The aggregate materialized views are always read whole by the views built on them, so only the
+//! plain tables are checked.
+
+use diesel::pg::Pg;
+use diesel::query_builder::{AstPass, Query, QueryFragment, QueryId};
+use diesel::sql_types::Text;
+use diesel::*;
+
+/// The tables that grow with the site, and so should never be scanned in full by a listing.
+pub const LARGE_TABLES: &[&str] = &[
+  "comment",
+  "comment_like",
+  "comment_saved",
+  "community_follower",
+  "post",
+  "post_like",
+  "post_read",
+  "post_saved",
+];
+
+/// `EXPLAIN`s a query, rather than running it.
+pub struct Explain<Q>(pub Q);
+
+impl<Q: QueryFragment<Pg>> QueryFragment<Pg> for Explain<Q> {
+  fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+    out.push_sql("EXPLAIN ");
+    self.0.walk_ast(out.reborrow())
+  }
+}
+
+impl<Q> QueryId for Explain<Q> {
+  type QueryId = ();
+  const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<Q> Query for Explain<Q> {
+  type SqlType = Text;
+}
+
+impl<Q> RunQueryDsl<PgConnection> for Explain<Q> {}
+
+/// The plan postgres picks for a query, one line per node.
+pub fn explain<Q: QueryFragment<Pg>>(conn: &PgConnection, query: Q) -> Vec<String> {
+  sql_query("set enable_seqscan = off").execute(conn).unwrap();
+  let plan = Explain(query).load::<String>(conn).unwrap();
+  sql_query("reset enable_seqscan").execute(conn).unwrap();
+  plan
+}
+
+/// Panics if the query's plan seq scans any of `LARGE_TABLES`.
+pub fn assert_no_seq_scan<Q: QueryFragment<Pg>>(conn: &PgConnection, query: Q) {
+  let plan = explain(conn, query);
+  for line in &plan {
+    for table in LARGE_TABLES {
+      let seq_scan = format!("Seq Scan on {} ", table);
+      assert!(
+        !line.contains(&seq_scan),
+        "query seq scans {}:\n{}",
+        table,
+        plan.join("\n")
+      );
+    }
+  }
+}
+
+#[test]
+#[should_panic(expected = "query seq scans post")]
+fn test_unindexed_filter() {
+  use crate::schema::post::dsl::*;
+  let conn = super::establish_unpooled_connection();
+  assert_no_seq_scan(&conn, post.filter(name.eq("not indexed")));
+}
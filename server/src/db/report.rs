@@ -0,0 +1,416 @@
+use super::*;
+use crate::naive_now;
+use crate::schema::report;
+use crate::schema::report::dsl::*;
+use diesel::pg::Pg;
+
+/// A user reporting a post or comment to the community's moderators.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "report"]
+pub struct Report {
+  pub id: i32,
+  pub creator_id: i32,
+  pub community_id: i32,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub reason: String,
+  pub resolved: bool,
+  pub resolver_id: Option<i32>,
+  pub published: chrono::NaiveDateTime,
+  pub updated: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "report"]
+pub struct ReportForm {
+  pub creator_id: i32,
+  pub community_id: i32,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub reason: String,
+}
+
+/// What a report is of.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ReportedObject {
+  Post(i32),
+  Comment(i32),
+}
+
+impl ReportedObject {
+  pub fn of(report_post_id: Option<i32>, report_comment_id: Option<i32>) -> Option<Self> {
+    match (report_post_id, report_comment_id) {
+      (Some(report_post_id), None) => Some(ReportedObject::Post(report_post_id)),
+      (None, Some(report_comment_id)) => Some(ReportedObject::Comment(report_comment_id)),
+      _ => None,
+    }
+  }
+}
+
+impl Report {
+  pub fn create(conn: &PgConnection, form: &ReportForm) -> Result<Self, Error> {
+    insert_into(report).values(form).get_result::<Self>(conn)
+  }
+
+  pub fn read(conn: &PgConnection, report_id: i32) -> Result<Self, Error> {
+    report.find(report_id).first::<Self>(conn)
+  }
+
+  /// How many users have an open report of the object.
+  pub fn count_open(conn: &PgConnection, object: ReportedObject) -> Result<i64, Error> {
+    let open = report.filter(resolved.eq(false));
+    match object {
+      ReportedObject::Post(for_post_id) => open
+        .filter(post_id.eq(for_post_id))
+        .count()
+        .get_result(conn),
+      ReportedObject::Comment(for_comment_id) => open
+        .filter(comment_id.eq(for_comment_id))
+        .count()
+        .get_result(conn),
+    }
+  }
+
+  /// Resolves every open report of the object. Returns how many there were.
+  pub fn resolve_all(
+    conn: &PgConnection,
+    object: ReportedObject,
+    for_resolver_id: i32,
+  ) -> Result<usize, Error> {
+    let open = report.filter(resolved.eq(false));
+    let resolve = (
+      resolved.eq(true),
+      resolver_id.eq(for_resolver_id),
+      updated.eq(naive_now()),
+    );
+    match object {
+      ReportedObject::Post(for_post_id) => diesel::update(open.filter(post_id.eq(for_post_id)))
+        .set(resolve)
+        .execute(conn),
+      ReportedObject::Comment(for_comment_id) => {
+        diesel::update(open.filter(comment_id.eq(for_comment_id)))
+          .set(resolve)
+          .execute(conn)
+      }
+    }
+  }
+}
+
+// The faked schema since diesel doesn't do views
+table! {
+  report_view (id) {
+    id -> Int4,
+    creator_id -> Int4,
+    community_id -> Int4,
+    post_id -> Nullable<Int4>,
+    comment_id -> Nullable<Int4>,
+    reason -> Text,
+    resolved -> Bool,
+    resolver_id -> Nullable<Int4>,
+    published -> Timestamp,
+    updated -> Nullable<Timestamp>,
+    creator_name -> Varchar,
+    community_name -> Varchar,
+    post_name -> Nullable<Varchar>,
+    comment_content -> Nullable<Text>,
+  }
+}
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[table_name = "report_view"]
+pub struct ReportView {
+  pub id: i32,
+  pub creator_id: i32,
+  pub community_id: i32,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub reason: String,
+  pub resolved: bool,
+  pub resolver_id: Option<i32>,
+  pub published: chrono::NaiveDateTime,
+  pub updated: Option<chrono::NaiveDateTime>,
+  pub creator_name: String,
+  pub community_name: String,
+  pub post_name: Option<String>,
+  pub comment_content: Option<String>,
+}
+
+impl ReportView {
+  pub fn read(conn: &PgConnection, report_id: i32) -> Result<Self, Error> {
+    use super::report::report_view::dsl::*;
+    report_view.find(report_id).first::<Self>(conn)
+  }
+}
+
+/// What kind of reports to list.
+#[derive(EnumString, ToString, Debug, Serialize, Deserialize)]
+pub enum ReportType {
+  All,
+  Posts,
+  Comments,
+}
+
+pub struct ReportQueryBuilder<'a> {
+  conn: &'a PgConnection,
+  query: super::report::report_view::BoxedQuery<'a, Pg>,
+  for_community_ids: Option<Vec<i32>>,
+  for_creator_id: Option<i32>,
+  type_: &'a ReportType,
+  resolved: bool,
+  page: Option<i64>,
+  limit: Option<i64>,
+}
+
+impl<'a> ReportQueryBuilder<'a> {
+  pub fn create(conn: &'a PgConnection) -> Self {
+    use super::report::report_view::dsl::*;
+
+    let query = report_view.into_boxed();
+
+    ReportQueryBuilder {
+      conn,
+      query,
+      for_community_ids: None,
+      for_creator_id: None,
+      type_: &ReportType::All,
+      resolved: false,
+      page: None,
+      limit: None,
+    }
+  }
+
+  /// The reports of the given communities, or of all of them if `None`.
+  pub fn for_community_ids<T: MaybeOptional<Vec<i32>>>(mut self, for_community_ids: T) -> Self {
+    self.for_community_ids = for_community_ids.get_optional();
+    self
+  }
+
+  /// Only the reports a user made.
+  pub fn for_creator_id<T: MaybeOptional<i32>>(mut self, for_creator_id: T) -> Self {
+    self.for_creator_id = for_creator_id.get_optional();
+    self
+  }
+
+  pub fn type_(mut self, type_: &'a ReportType) -> Self {
+    self.type_ = type_;
+    self
+  }
+
+  /// The resolved reports instead of the open ones.
+  pub fn resolved(mut self, resolved: bool) -> Self {
+    self.resolved = resolved;
+    self
+  }
+
+  pub fn page<T: MaybeOptional<i64>>(mut self, page: T) -> Self {
+    self.page = page.get_optional();
+    self
+  }
+
+  pub fn limit<T: MaybeOptional<i64>>(mut self, limit: T) -> Self {
+    self.limit = limit.get_optional();
+    self
+  }
+
+  /// Open reports come oldest first, and resolved ones the most recently resolved first.
+  pub fn list(self) -> Result<Vec<ReportView>, Error> {
+    use super::report::report_view::dsl::*;
+
+    let mut query = self.query.filter(resolved.eq(self.resolved));
+
+    if let Some(for_community_ids) = self.for_community_ids {
+      query = query.filter(community_id.eq_any(for_community_ids));
+    }
+
+    if let Some(for_creator_id) = self.for_creator_id {
+      query = query.filter(creator_id.eq(for_creator_id));
+    }
+
+    query = match self.type_ {
+      ReportType::All => query,
+      ReportType::Posts => query.filter(comment_id.is_null()),
+      ReportType::Comments => query.filter(comment_id.is_not_null()),
+    };
+
+    query = if self.resolved {
+      query.order_by(updated.desc()).then_order_by(published)
+    } else {
+      query.order_by(published)
+    };
+
+    let (limit, offset) = limit_and_offset(self.page, self.limit);
+    query
+      .limit(limit)
+      .offset(offset)
+      .load::<ReportView>(self.conn)
+  }
+}
+
+/// The open reports of one post or comment, as moderators see them.
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct ReportGroup {
+  pub community_id: i32,
+  pub community_name: String,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub post_name: Option<String>,
+  pub comment_content: Option<String>,
+  /// Any of them resolves them all
+  pub report_ids: Vec<i32>,
+  pub count: i64,
+  pub reporters: Vec<String>,
+  pub reasons: Vec<String>,
+  pub first_reported: chrono::NaiveDateTime,
+  pub last_reported: chrono::NaiveDateTime,
+}
+
+impl ReportGroup {
+  /// Groups the reports by what they're of, the most reported first.
+  pub fn group(reports: Vec<ReportView>) -> Vec<Self> {
+    let mut groups: Vec<Self> = Vec::new();
+    for reported in reports {
+      let existing = groups
+        .iter_mut()
+        .find(|g| g.post_id == reported.post_id && g.comment_id == reported.comment_id);
+      match existing {
+        Some(group) => {
+          group.report_ids.push(reported.id);
+          group.count += 1;
+          group.reporters.push(reported.creator_name);
+          group.reasons.push(reported.reason);
+          group.first_reported = group.first_reported.min(reported.published);
+          group.last_reported = group.last_reported.max(reported.published);
+        }
+        None => groups.push(ReportGroup {
+          community_id: reported.community_id,
+          community_name: reported.community_name,
+          post_id: reported.post_id,
+          comment_id: reported.comment_id,
+          post_name: reported.post_name,
+          comment_content: reported.comment_content,
+          report_ids: vec![reported.id],
+          count: 1,
+          reporters: vec![reported.creator_name],
+          reasons: vec![reported.reason],
+          first_reported: reported.published,
+          last_reported: reported.published,
+        }),
+      }
+    }
+    groups.sort_by(|a, b| b.count.cmp(&a.count));
+    groups
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::community::*;
+  use super::super::post::*;
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_resolve_all() {
+    let conn = establish_unpooled_connection();
+
+    let first_reporter = User_::create(&conn, &user_form("reporter_one")).unwrap();
+    let second_reporter = User_::create(&conn, &user_form("reporter_two")).unwrap();
+
+    let new_community = CommunityForm {
+      name: "reported_community".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: first_reporter.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A reported post".into(),
+      url: None,
+      body: None,
+      creator_id: first_reporter.id,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let report_form = |reporter_id: i32| ReportForm {
+      creator_id: reporter_id,
+      community_id: inserted_community.id,
+      post_id: Some(inserted_post.id),
+      comment_id: None,
+      reason: "spam".into(),
+    };
+
+    Report::create(&conn, &report_form(first_reporter.id)).unwrap();
+    // Someone can only have one open report of something
+    let reported_again = Report::create(&conn, &report_form(first_reporter.id));
+    Report::create(&conn, &report_form(second_reporter.id)).unwrap();
+
+    let object = ReportedObject::Post(inserted_post.id);
+    let open = Report::count_open(&conn, object).unwrap();
+    let groups = ReportGroup::group(
+      ReportQueryBuilder::create(&conn)
+        .for_community_ids(vec![inserted_community.id])
+        .list()
+        .unwrap(),
+    );
+    let others_reports = ReportQueryBuilder::create(&conn)
+      .for_community_ids(vec![inserted_community.id])
+      .for_creator_id(second_reporter.id)
+      .list()
+      .unwrap();
+    let comment_reports = ReportQueryBuilder::create(&conn)
+      .for_community_ids(vec![inserted_community.id])
+      .type_(&ReportType::Comments)
+      .list()
+      .unwrap();
+    let resolved_count = Report::resolve_all(&conn, object, second_reporter.id).unwrap();
+    let open_after_resolve = Report::count_open(&conn, object).unwrap();
+    let resolved_reports = ReportQueryBuilder::create(&conn)
+      .for_community_ids(vec![inserted_community.id])
+      .resolved(true)
+      .list()
+      .unwrap();
+
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, first_reporter.id).unwrap();
+    User_::delete(&conn, second_reporter.id).unwrap();
+
+    assert!(reported_again.is_err());
+    assert_eq!(2, open);
+    assert_eq!(1, groups.len());
+    assert_eq!(2, groups[0].count);
+    assert_eq!(
+      vec!["reporter_one".to_string(), "reporter_two".to_string()],
+      groups[0].reporters
+    );
+    assert_eq!(1, others_reports.len());
+    assert_eq!("reporter_two", others_reports[0].creator_name);
+    assert!(comment_reports.is_empty());
+    assert_eq!(2, resolved_count);
+    assert_eq!(2, resolved_reports.len());
+    assert_eq!(0, open_after_resolve);
+  }
+}
@@ -0,0 +1,118 @@
+use super::*;
+use crate::schema::reserved_community_name;
+use crate::schema::reserved_community_name::dsl::*;
+
+sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
+
+/// A community name that only admins can create a community with.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "reserved_community_name"]
+pub struct ReservedCommunityName {
+  pub id: i32,
+  pub name: String,
+  pub reason: Option<String>,
+  pub creator_id: i32,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone, Serialize, Deserialize)]
+#[table_name = "reserved_community_name"]
+pub struct ReservedCommunityNameForm {
+  pub name: String,
+  pub reason: Option<String>,
+  pub creator_id: i32,
+}
+
+impl Crud<ReservedCommunityNameForm> for ReservedCommunityName {
+  fn read(conn: &PgConnection, reserved_id: i32) -> Result<Self, Error> {
+    reserved_community_name
+      .find(reserved_id)
+      .first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, reserved_id: i32) -> Result<usize, Error> {
+    diesel::delete(reserved_community_name.find(reserved_id)).execute(conn)
+  }
+
+  /// Names are stored lowercase, since they're reserved whatever their case.
+  fn create(conn: &PgConnection, form: &ReservedCommunityNameForm) -> Result<Self, Error> {
+    let form = ReservedCommunityNameForm {
+      name: form.name.to_lowercase(),
+      ..form.to_owned()
+    };
+    insert_into(reserved_community_name)
+      .values(&form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(
+    conn: &PgConnection,
+    reserved_id: i32,
+    form: &ReservedCommunityNameForm,
+  ) -> Result<Self, Error> {
+    diesel::update(reserved_community_name.find(reserved_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl ReservedCommunityName {
+  pub fn list_all(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    reserved_community_name.order_by(name).load::<Self>(conn)
+  }
+
+  pub fn is_reserved(conn: &PgConnection, community_name: &str) -> Result<bool, Error> {
+    select(exists(
+      reserved_community_name.filter(name.eq(lower(community_name))),
+    ))
+    .get_result(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_is_reserved() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "reserving_admin".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: true,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let form = ReservedCommunityNameForm {
+      name: "Announcements".into(),
+      reason: Some("For the admins".into()),
+      creator_id: inserted_user.id,
+    };
+    let reserved = ReservedCommunityName::create(&conn, &form).unwrap();
+    let duplicate = ReservedCommunityName::create(&conn, &form);
+
+    assert_eq!("announcements", reserved.name);
+    assert!(duplicate.is_err());
+    assert!(ReservedCommunityName::is_reserved(&conn, "ANNOUNCEMENTS").unwrap());
+    assert!(!ReservedCommunityName::is_reserved(&conn, "announcement").unwrap());
+
+    User_::delete(&conn, inserted_user.id).unwrap();
+    assert!(!ReservedCommunityName::is_reserved(&conn, "announcements").unwrap());
+  }
+}
@@ -0,0 +1,57 @@
+use super::*;
+use crate::schema::saved_folder;
+use crate::schema::saved_folder::dsl::*;
+
+/// A folder that a user sorts their saved posts and comments into.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "saved_folder"]
+pub struct SavedFolder {
+  pub id: i32,
+  pub user_id: i32,
+  pub name: String,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "saved_folder"]
+pub struct SavedFolderForm {
+  pub user_id: i32,
+  pub name: String,
+}
+
+impl Crud<SavedFolderForm> for SavedFolder {
+  fn read(conn: &PgConnection, saved_folder_id: i32) -> Result<Self, Error> {
+    saved_folder.find(saved_folder_id).first::<Self>(conn)
+  }
+
+  /// The folder's items stay saved, without a folder.
+  fn delete(conn: &PgConnection, saved_folder_id: i32) -> Result<usize, Error> {
+    diesel::delete(saved_folder.find(saved_folder_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &SavedFolderForm) -> Result<Self, Error> {
+    insert_into(saved_folder)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(
+    conn: &PgConnection,
+    saved_folder_id: i32,
+    form: &SavedFolderForm,
+  ) -> Result<Self, Error> {
+    diesel::update(saved_folder.find(saved_folder_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl SavedFolder {
+  /// A user's folders, by name.
+  pub fn list_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    saved_folder
+      .filter(user_id.eq(for_user_id))
+      .order_by(name)
+      .load::<Self>(conn)
+  }
+}
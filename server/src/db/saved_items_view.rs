@@ -0,0 +1,281 @@
+use super::*;
+use diesel::pg::Pg;
+
+// The faked schema since diesel doesn't do views
+table! {
+  saved_items_view (kind, id) {
+    kind -> Int2,
+    id -> Int4,
+    user_id -> Int4,
+    folder_id -> Nullable<Int4>,
+    saved -> Timestamp,
+    post_id -> Int4,
+    comment_id -> Nullable<Int4>,
+    post_name -> Varchar,
+    post_url -> Nullable<Text>,
+    content -> Nullable<Text>,
+    creator_id -> Int4,
+    creator_name -> Varchar,
+    community_id -> Int4,
+    community_name -> Varchar,
+    published -> Timestamp,
+    deleted -> Bool,
+  }
+}
+
+/// Whether a saved item is a post or a comment. Stored as its `i16`.
+#[derive(EnumString, ToString, Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum SavedItemKind {
+  Post,
+  Comment,
+}
+
+/// A post or comment that a user saved.
+#[derive(Queryable, PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct SavedItemsView {
+  pub kind: i16,
+  /// The id of the saved post or saved comment
+  pub id: i32,
+  pub user_id: i32,
+  pub folder_id: Option<i32>,
+  /// When it was saved
+  pub saved: chrono::NaiveDateTime,
+  pub post_id: i32,
+  pub comment_id: Option<i32>,
+  pub post_name: String,
+  pub post_url: Option<String>,
+  /// The comment's content
+  pub content: Option<String>,
+  pub creator_id: i32,
+  pub creator_name: String,
+  pub community_id: i32,
+  pub community_name: String,
+  pub published: chrono::NaiveDateTime,
+  /// Deleted, or removed by a mod
+  pub deleted: bool,
+}
+
+pub struct SavedItemsQueryBuilder<'a> {
+  conn: &'a PgConnection,
+  query: super::saved_items_view::saved_items_view::BoxedQuery<'a, Pg>,
+  for_user_id: i32,
+  for_folder_id: Option<i32>,
+  page: Option<i64>,
+  limit: Option<i64>,
+}
+
+impl<'a> SavedItemsQueryBuilder<'a> {
+  pub fn create(conn: &'a PgConnection, for_user_id: i32) -> Self {
+    use super::saved_items_view::saved_items_view::dsl::*;
+
+    let query = saved_items_view.into_boxed();
+
+    SavedItemsQueryBuilder {
+      conn,
+      query,
+      for_user_id,
+      for_folder_id: None,
+      page: None,
+      limit: None,
+    }
+  }
+
+  /// Only the items in a folder, all of them if `None`.
+  pub fn folder_id<T: MaybeOptional<i32>>(mut self, for_folder_id: T) -> Self {
+    self.for_folder_id = for_folder_id.get_optional();
+    self
+  }
+
+  pub fn page<T: MaybeOptional<i64>>(mut self, page: T) -> Self {
+    self.page = page.get_optional();
+    self
+  }
+
+  pub fn limit<T: MaybeOptional<i64>>(mut self, limit: T) -> Self {
+    self.limit = limit.get_optional();
+    self
+  }
+
+  /// The items, the last saved first.
+  pub fn list(self) -> Result<Vec<SavedItemsView>, Error> {
+    use super::saved_items_view::saved_items_view::dsl::*;
+
+    let mut query = self.query.filter(user_id.eq(self.for_user_id));
+
+    if let Some(for_folder_id) = self.for_folder_id {
+      query = query.filter(folder_id.eq(for_folder_id));
+    }
+
+    let (limit, offset) = limit_and_offset(self.page, self.limit);
+    query
+      .order_by(saved.desc())
+      .then_order_by(kind.asc())
+      .then_order_by(id.desc())
+      .limit(limit)
+      .offset(offset)
+      .load::<SavedItemsView>(self.conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::comment::*;
+  use super::super::community::*;
+  use super::super::post::*;
+  use super::super::saved_folder::*;
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_saved_items() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "saved_items_user".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_saved_items_community".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A saved post".into(),
+      creator_id: inserted_user.id,
+      url: None,
+      body: None,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let comment_form = CommentForm {
+      content: "A saved comment".into(),
+      creator_id: inserted_user.id,
+      post_id: inserted_post.id,
+      removed: None,
+      deleted: None,
+      read: None,
+      parent_id: None,
+      updated: None,
+      generated: None,
+      language_id: None,
+    };
+
+    let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
+
+    let folder_form = SavedFolderForm {
+      user_id: inserted_user.id,
+      name: "Recipes".into(),
+    };
+
+    let inserted_folder = SavedFolder::create(&conn, &folder_form).unwrap();
+    let duplicate_folder = SavedFolder::create(&conn, &folder_form);
+
+    let post_saved_form = PostSavedForm {
+      post_id: inserted_post.id,
+      user_id: inserted_user.id,
+      folder_id: None,
+    };
+    PostSaved::save(&conn, &post_saved_form).unwrap();
+
+    // Saving it again moves it to the folder
+    let comment_saved_form = CommentSavedForm {
+      comment_id: inserted_comment.id,
+      user_id: inserted_user.id,
+      folder_id: None,
+    };
+    CommentSaved::save(&conn, &comment_saved_form).unwrap();
+    let moved_comment = CommentSaved::save(
+      &conn,
+      &CommentSavedForm {
+        folder_id: Some(inserted_folder.id),
+        ..comment_saved_form.clone()
+      },
+    )
+    .unwrap();
+
+    let items = SavedItemsQueryBuilder::create(&conn, inserted_user.id)
+      .list()
+      .unwrap();
+    let in_folder = SavedItemsQueryBuilder::create(&conn, inserted_user.id)
+      .folder_id(inserted_folder.id)
+      .list()
+      .unwrap();
+
+    // Its items stay saved
+    SavedFolder::delete(&conn, inserted_folder.id).unwrap();
+    let items_after_delete = SavedItemsQueryBuilder::create(&conn, inserted_user.id)
+      .list()
+      .unwrap();
+
+    CommentSaved::unsave(&conn, &comment_saved_form).unwrap();
+    PostSaved::unsave(&conn, &post_saved_form).unwrap();
+    Comment::delete(&conn, inserted_comment.id).unwrap();
+    Post::delete(&conn, inserted_post.id).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert!(duplicate_folder.is_err());
+    assert_eq!(Some(inserted_folder.id), moved_comment.folder_id);
+
+    let kinds: Vec<i16> = items.iter().map(|item| item.kind).collect();
+    assert_eq!(
+      vec![SavedItemKind::Comment as i16, SavedItemKind::Post as i16],
+      kinds
+    );
+    assert_eq!(Some("A saved comment".to_string()), items[0].content);
+    assert_eq!("A saved post", items[1].post_name);
+    assert_eq!("test_saved_items_community", items[1].community_name);
+
+    assert_eq!(1, in_folder.len());
+    assert_eq!(Some(inserted_comment.id), in_folder[0].comment_id);
+
+    assert_eq!(2, items_after_delete.len());
+    assert!(items_after_delete
+      .iter()
+      .all(|item| item.folder_id.is_none()));
+  }
+}
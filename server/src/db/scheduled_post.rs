@@ -0,0 +1,172 @@
+use super::*;
+use crate::naive_now;
+use crate::schema::scheduled_post;
+use crate::schema::scheduled_post::dsl::*;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+
+/// A post that gets made in a community every week or every day, like a weekly discussion
+/// thread. Each one is stickied, and the one before it unstickied.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "scheduled_post"]
+pub struct ScheduledPost {
+  pub id: i32,
+  pub community_id: i32,
+  /// The moderator the posts are made as
+  pub creator_id: i32,
+  /// The title of the posts, where `{date}` is the day it's made
+  pub name: String,
+  pub body: Option<String>,
+  /// 0 is Monday, every day if `None`
+  pub weekday: Option<i16>,
+  /// The hour of the day, in UTC
+  pub hour: i16,
+  pub last_post_id: Option<i32>,
+  pub next_run: NaiveDateTime,
+  pub published: NaiveDateTime,
+  pub updated: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "scheduled_post"]
+pub struct ScheduledPostForm {
+  pub community_id: i32,
+  pub creator_id: i32,
+  pub name: String,
+  pub body: Option<String>,
+  pub weekday: Option<i16>,
+  pub hour: i16,
+  pub next_run: NaiveDateTime,
+  pub updated: Option<NaiveDateTime>,
+}
+
+impl Crud<ScheduledPostForm> for ScheduledPost {
+  fn read(conn: &PgConnection, scheduled_post_id: i32) -> Result<Self, Error> {
+    scheduled_post.find(scheduled_post_id).first::<Self>(conn)
+  }
+
+  fn delete(conn: &PgConnection, scheduled_post_id: i32) -> Result<usize, Error> {
+    diesel::delete(scheduled_post.find(scheduled_post_id)).execute(conn)
+  }
+
+  fn create(conn: &PgConnection, form: &ScheduledPostForm) -> Result<Self, Error> {
+    insert_into(scheduled_post)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  fn update(
+    conn: &PgConnection,
+    scheduled_post_id: i32,
+    form: &ScheduledPostForm,
+  ) -> Result<Self, Error> {
+    diesel::update(scheduled_post.find(scheduled_post_id))
+      .set(form)
+      .get_result::<Self>(conn)
+  }
+}
+
+impl ScheduledPost {
+  /// A community's scheduled posts, the next to be made first.
+  pub fn list_for_community(
+    conn: &PgConnection,
+    for_community_id: i32,
+  ) -> Result<Vec<Self>, Error> {
+    scheduled_post
+      .filter(community_id.eq(for_community_id))
+      .order_by(next_run)
+      .load::<Self>(conn)
+  }
+
+  /// The scheduled posts that are due to be made, the longest overdue first.
+  pub fn list_due(conn: &PgConnection) -> Result<Vec<Self>, Error> {
+    scheduled_post
+      .filter(next_run.le(naive_now()))
+      .order_by(next_run)
+      .load::<Self>(conn)
+  }
+
+  /// Locks a scheduled post until the end of the transaction, if it's still due. Another run
+  /// that got to it first will have moved its `next_run` on.
+  pub fn lock_if_due(conn: &PgConnection, scheduled_post_id: i32) -> Result<Option<Self>, Error> {
+    scheduled_post
+      .find(scheduled_post_id)
+      .filter(next_run.le(naive_now()))
+      .for_update()
+      .first::<Self>(conn)
+      .optional()
+  }
+
+  /// Records that a post was made, or skipped if `None`, and when the next one is due.
+  pub fn record_run(
+    conn: &PgConnection,
+    scheduled_post_id: i32,
+    post_id: Option<i32>,
+    new_next_run: NaiveDateTime,
+  ) -> Result<Self, Error> {
+    let target = scheduled_post.find(scheduled_post_id);
+    match post_id {
+      Some(post_id) => diesel::update(target)
+        .set((last_post_id.eq(post_id), next_run.eq(new_next_run)))
+        .get_result::<Self>(conn),
+      None => diesel::update(target)
+        .set(next_run.eq(new_next_run))
+        .get_result::<Self>(conn),
+    }
+  }
+
+  /// The title of the post made on the day.
+  pub fn title(&self, day: NaiveDate) -> String {
+    self
+      .name
+      .replace("{date}", &day.format("%Y-%m-%d").to_string())
+  }
+}
+
+/// The first time after `after` on the hour, and on the weekday if there is one.
+pub fn next_run_after(
+  run_weekday: Option<i16>,
+  run_hour: i16,
+  after: NaiveDateTime,
+) -> NaiveDateTime {
+  let mut candidate = after.date().and_hms(run_hour as u32, 0, 0);
+  while candidate <= after
+    || run_weekday.map_or(false, |w| {
+      candidate.weekday().num_days_from_monday() != w as u32
+    })
+  {
+    candidate += Duration::days(1);
+  }
+  candidate
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_next_run_after() {
+    // A Wednesday
+    let after = NaiveDate::from_ymd(2020, 4, 22).and_hms(10, 30, 0);
+
+    assert_eq!(
+      NaiveDate::from_ymd(2020, 4, 22).and_hms(12, 0, 0),
+      next_run_after(None, 12, after)
+    );
+    assert_eq!(
+      NaiveDate::from_ymd(2020, 4, 23).and_hms(9, 0, 0),
+      next_run_after(None, 9, after)
+    );
+    assert_eq!(
+      NaiveDate::from_ymd(2020, 4, 27).and_hms(9, 0, 0),
+      next_run_after(Some(0), 9, after)
+    );
+    assert_eq!(
+      NaiveDate::from_ymd(2020, 4, 29).and_hms(10, 0, 0),
+      next_run_after(Some(2), 10, after)
+    );
+    assert_eq!(
+      NaiveDate::from_ymd(2020, 4, 22).and_hms(11, 0, 0),
+      next_run_after(Some(2), 11, after)
+    );
+  }
+}
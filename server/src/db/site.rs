@@ -1,3 +1,4 @@
+use super::user::User_;
 use super::*;
 use crate::schema::site;
 
@@ -13,6 +14,41 @@ pub struct Site {
   pub enable_downvotes: bool,
   pub open_registration: bool,
   pub enable_nsfw: bool,
+  pub noindex: bool,
+  pub robots_txt: Option<String>,
+  /// Whether image posts need alt text
+  pub require_alt_text: bool,
+  /// Only admins can create communities
+  pub community_creation_admin_only: bool,
+  /// How old accounts have to be to create communities, 0 for any age
+  pub community_creation_min_account_age_days: i32,
+  /// How much karma users need to create communities, 0 for none
+  pub community_creation_min_karma: i32,
+  /// Votes from accounts that are newer or have less karma than the minimums count for
+  /// `vote_weighting_weight` in the hot ranks
+  pub vote_weighting: bool,
+  pub vote_weighting_min_account_age_days: i32,
+  pub vote_weighting_min_karma: i32,
+  pub vote_weighting_weight: f32,
+  /// Users get a notice when a moderator removes their post or comment, or bans them
+  pub moderation_notices: bool,
+  /// The notices are also sent as a private message from the moderator
+  pub moderation_notice_pm: bool,
+  /// The text of the notices, with `{action}`, `{reason}` and `{appeal_url}` filled in
+  pub moderation_notice_template: Option<String>,
+  pub moderation_appeal_url: Option<String>,
+  /// Votes on your own posts and comments don't count towards your karma
+  pub karma_exclude_self_votes: bool,
+  /// The most karma a post or comment counts for either way, 0 for no cap
+  pub karma_cap_per_item: i32,
+  /// Votes count half as much towards karma every so many days, 0 for no decay
+  pub karma_decay_half_life_days: i32,
+  /// A `ScoreVisibility`
+  pub score_visibility: i16,
+  /// Whether post views are counted
+  pub track_post_views: bool,
+  /// Whether everyone can see the edit history of posts and comments, or only mods and admins
+  pub public_edit_history: bool,
 }
 
 #[derive(Insertable, AsChangeset, Clone, Serialize, Deserialize)]
@@ -25,6 +61,26 @@ pub struct SiteForm {
   pub enable_downvotes: bool,
   pub open_registration: bool,
   pub enable_nsfw: bool,
+  pub noindex: bool,
+  pub robots_txt: Option<String>,
+  pub require_alt_text: bool,
+  pub community_creation_admin_only: bool,
+  pub community_creation_min_account_age_days: i32,
+  pub community_creation_min_karma: i32,
+  pub vote_weighting: bool,
+  pub vote_weighting_min_account_age_days: i32,
+  pub vote_weighting_min_karma: i32,
+  pub vote_weighting_weight: f32,
+  pub moderation_notices: bool,
+  pub moderation_notice_pm: bool,
+  pub moderation_notice_template: Option<String>,
+  pub moderation_appeal_url: Option<String>,
+  pub karma_exclude_self_votes: bool,
+  pub karma_cap_per_item: i32,
+  pub karma_decay_half_life_days: i32,
+  pub score_visibility: i16,
+  pub track_post_views: bool,
+  pub public_edit_history: bool,
 }
 
 impl Crud<SiteForm> for Site {
@@ -50,3 +106,20 @@ impl Crud<SiteForm> for Site {
       .get_result::<Self>(conn)
   }
 }
+
+impl Site {
+  /// Whether the user, or someone who isn't logged in if `None`, has vote scores hidden from them.
+  pub fn hides_scores_from(conn: &PgConnection, user_id: Option<i32>) -> Result<bool, Error> {
+    let visibility = match Site::read(conn, 1).optional()? {
+      Some(site) => ScoreVisibility::from_i16(site.score_visibility),
+      None => return Ok(false),
+    };
+    match (visibility, user_id) {
+      (Some(ScoreVisibility::Hidden), _) => Ok(true),
+      (Some(ScoreVisibility::UserChoice), Some(user_id)) => {
+        Ok(!User_::read(conn, user_id)?.show_scores)
+      }
+      _ => Ok(false),
+    }
+  }
+}
@@ -1,4 +1,5 @@
 use super::*;
+use crate::avatar::fill_default_avatar;
 
 table! {
   site_view (id) {
@@ -11,6 +12,26 @@ table! {
     enable_downvotes -> Bool,
     open_registration -> Bool,
     enable_nsfw -> Bool,
+    noindex -> Bool,
+    robots_txt -> Nullable<Text>,
+    require_alt_text -> Bool,
+    community_creation_admin_only -> Bool,
+    community_creation_min_account_age_days -> Int4,
+    community_creation_min_karma -> Int4,
+    vote_weighting -> Bool,
+    vote_weighting_min_account_age_days -> Int4,
+    vote_weighting_min_karma -> Int4,
+    vote_weighting_weight -> Float4,
+    moderation_notices -> Bool,
+    moderation_notice_pm -> Bool,
+    moderation_notice_template -> Nullable<Text>,
+    moderation_appeal_url -> Nullable<Text>,
+    karma_exclude_self_votes -> Bool,
+    karma_cap_per_item -> Int4,
+    karma_decay_half_life_days -> Int4,
+    score_visibility -> Int2,
+    track_post_views -> Bool,
+    public_edit_history -> Bool,
     creator_name -> Varchar,
     creator_avatar -> Nullable<Text>,
     number_of_users -> BigInt,
@@ -34,6 +55,26 @@ pub struct SiteView {
   pub enable_downvotes: bool,
   pub open_registration: bool,
   pub enable_nsfw: bool,
+  pub noindex: bool,
+  pub robots_txt: Option<String>,
+  pub require_alt_text: bool,
+  pub community_creation_admin_only: bool,
+  pub community_creation_min_account_age_days: i32,
+  pub community_creation_min_karma: i32,
+  pub vote_weighting: bool,
+  pub vote_weighting_min_account_age_days: i32,
+  pub vote_weighting_min_karma: i32,
+  pub vote_weighting_weight: f32,
+  pub moderation_notices: bool,
+  pub moderation_notice_pm: bool,
+  pub moderation_notice_template: Option<String>,
+  pub moderation_appeal_url: Option<String>,
+  pub karma_exclude_self_votes: bool,
+  pub karma_cap_per_item: i32,
+  pub karma_decay_half_life_days: i32,
+  pub score_visibility: i16,
+  pub track_post_views: bool,
+  pub public_edit_history: bool,
   pub creator_name: String,
   pub creator_avatar: Option<String>,
   pub number_of_users: i64,
@@ -42,9 +83,17 @@ pub struct SiteView {
   pub number_of_communities: i64,
 }
 
+impl DefaultAvatars for SiteView {
+  fn default_avatars(&mut self) {
+    fill_default_avatar(&mut self.creator_avatar, &self.creator_name);
+  }
+}
+
 impl SiteView {
   pub fn read(conn: &PgConnection) -> Result<Self, Error> {
     use super::site_view::site_view::dsl::*;
-    site_view.first::<Self>(conn)
+    site_view
+      .first::<Self>(conn)
+      .map(Self::with_default_avatars)
   }
 }
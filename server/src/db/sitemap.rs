@@ -0,0 +1,39 @@
+use super::*;
+
+table! {
+  sitemap_entry (kind, id) {
+    kind -> Text,
+    id -> Int4,
+    name -> Nullable<Text>,
+    lastmod -> Timestamp,
+  }
+}
+
+/// A public post or community, as listed in the sitemap.
+#[derive(Queryable, PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct SitemapEntry {
+  /// `community` or `post`
+  pub kind: String,
+  pub id: i32,
+  /// The name of a community, or the slug of a post
+  pub name: Option<String>,
+  pub lastmod: chrono::NaiveDateTime,
+}
+
+impl SitemapEntry {
+  pub fn count(conn: &PgConnection) -> Result<i64, Error> {
+    use super::sitemap::sitemap_entry::dsl::*;
+    sitemap_entry.count().get_result::<i64>(conn)
+  }
+
+  /// The `page`th chunk of `limit` entries, starting at 0. Posts come first, by id rather than
+  /// time, so that new posts only change the last chunks.
+  pub fn chunk(conn: &PgConnection, page: i64, limit: i64) -> Result<Vec<Self>, Error> {
+    use super::sitemap::sitemap_entry::dsl::*;
+    sitemap_entry
+      .order_by((kind.desc(), id))
+      .limit(limit)
+      .offset(page * limit)
+      .load::<Self>(conn)
+  }
+}
@@ -0,0 +1,71 @@
+use super::*;
+use crate::schema::translation;
+use crate::schema::translation::dsl::*;
+
+/// A post or comment translated to a language, which is replaced when the original is edited.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "translation"]
+pub struct Translation {
+  pub id: i32,
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub lang: String,
+  /// The post's title
+  pub name: Option<String>,
+  /// The post's body, or the comment
+  pub content: Option<String>,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[table_name = "translation"]
+pub struct TranslationForm {
+  pub post_id: Option<i32>,
+  pub comment_id: Option<i32>,
+  pub lang: String,
+  pub name: Option<String>,
+  pub content: Option<String>,
+}
+
+impl Translation {
+  pub fn read_for_post(
+    conn: &PgConnection,
+    for_post_id: i32,
+    for_lang: &str,
+  ) -> Result<Self, Error> {
+    translation
+      .filter(post_id.eq(for_post_id))
+      .filter(lang.eq(for_lang))
+      .first::<Self>(conn)
+  }
+
+  pub fn read_for_comment(
+    conn: &PgConnection,
+    for_comment_id: i32,
+    for_lang: &str,
+  ) -> Result<Self, Error> {
+    translation
+      .filter(comment_id.eq(for_comment_id))
+      .filter(lang.eq(for_lang))
+      .first::<Self>(conn)
+  }
+
+  /// Saves a translation, replacing any older one of the same object and language.
+  pub fn replace(conn: &PgConnection, form: &TranslationForm) -> Result<Self, Error> {
+    conn.transaction(|| {
+      let old = translation.filter(lang.eq(&form.lang));
+      match (form.post_id, form.comment_id) {
+        (Some(for_post_id), _) => {
+          diesel::delete(old.filter(post_id.eq(for_post_id))).execute(conn)?
+        }
+        (_, Some(for_comment_id)) => {
+          diesel::delete(old.filter(comment_id.eq(for_comment_id))).execute(conn)?
+        }
+        (None, None) => 0,
+      };
+      insert_into(translation)
+        .values(form)
+        .get_result::<Self>(conn)
+    })
+  }
+}
@@ -1,9 +1,12 @@
 use super::*;
 use crate::schema::user_;
 use crate::schema::user_::dsl::*;
-use crate::{is_email_regex, Settings};
+use crate::{is_email_regex, naive_now, Settings};
 use bcrypt::{hash, DEFAULT_COST};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use std::collections::BTreeMap;
+
+sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
 
 #[derive(Queryable, Identifiable, PartialEq, Debug)]
 #[table_name = "user_"]
@@ -27,6 +30,32 @@ pub struct User_ {
   pub show_avatars: bool,
   pub send_notifications_to_email: bool,
   pub matrix_user_id: Option<String>,
+  pub settings: serde_json::Value,
+  pub legal_hold: bool,
+  pub hide_profile: bool,
+  /// The activitypub id of a remote user
+  pub actor_id: Option<String>,
+  pub hide_activity: bool,
+  /// When a remote user was last fetched from their instance
+  pub last_refreshed: Option<chrono::NaiveDateTime>,
+  /// How many times in a row refreshing a remote user has failed
+  pub refresh_failures: i32,
+  /// When the next try at refreshing a remote user is, after a failed one
+  pub refresh_retry_at: Option<chrono::NaiveDateTime>,
+  /// The percentage of the Blended listing made up of posts from outside their subscriptions
+  pub blended_discovery_percent: i16,
+  /// Whether the Blended listing only mixes in local posts
+  pub blended_discovery_local: bool,
+  /// Whether they see vote scores, if the site leaves it up to them
+  pub show_scores: bool,
+  /// Whether the account is run by a bot, so everything it makes has to be marked generated
+  pub bot_account: bool,
+  /// Whether they see posts and comments marked generated
+  pub show_generated: bool,
+  /// When their ban is lifted, for temporary bans
+  pub ban_expires: Option<chrono::NaiveDateTime>,
+  /// Whether the All and Subscribed listings collapse the posts of the same link into one
+  pub collapse_crossposts: bool,
 }
 
 #[derive(Insertable, AsChangeset, Clone)]
@@ -93,6 +122,237 @@ impl User_ {
   pub fn read_from_name(conn: &PgConnection, from_user_name: String) -> Result<Self, Error> {
     user_.filter(name.eq(from_user_name)).first::<Self>(conn)
   }
+
+  pub fn update_legal_hold(conn: &PgConnection, user_id: i32, hold: bool) -> Result<Self, Error> {
+    diesel::update(user_.find(user_id))
+      .set(legal_hold.eq(hold))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_hide_profile(conn: &PgConnection, user_id: i32, hide: bool) -> Result<Self, Error> {
+    diesel::update(user_.find(user_id))
+      .set(hide_profile.eq(hide))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_hide_activity(
+    conn: &PgConnection,
+    user_id: i32,
+    hide: bool,
+  ) -> Result<Self, Error> {
+    diesel::update(user_.find(user_id))
+      .set(hide_activity.eq(hide))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_blended_discovery(
+    conn: &PgConnection,
+    user_id: i32,
+    percent: i16,
+    local: bool,
+  ) -> Result<Self, Error> {
+    diesel::update(user_.find(user_id))
+      .set((
+        blended_discovery_percent.eq(percent),
+        blended_discovery_local.eq(local),
+      ))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_show_scores(conn: &PgConnection, user_id: i32, show: bool) -> Result<Self, Error> {
+    diesel::update(user_.find(user_id))
+      .set(show_scores.eq(show))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_generated_settings(
+    conn: &PgConnection,
+    user_id: i32,
+    bot: bool,
+    show: bool,
+  ) -> Result<Self, Error> {
+    diesel::update(user_.find(user_id))
+      .set((bot_account.eq(bot), show_generated.eq(show)))
+      .get_result::<Self>(conn)
+  }
+
+  pub fn update_collapse_crossposts(
+    conn: &PgConnection,
+    user_id: i32,
+    collapse: bool,
+  ) -> Result<Self, Error> {
+    diesel::update(user_.find(user_id))
+      .set(collapse_crossposts.eq(collapse))
+      .get_result::<Self>(conn)
+  }
+
+  /// Whether the user has generated posts and comments hidden from them. They're shown to
+  /// everyone who isn't logged in.
+  pub fn hides_generated(conn: &PgConnection, user_id: Option<i32>) -> Result<bool, Error> {
+    match user_id {
+      Some(user_id) => Ok(!User_::read(conn, user_id)?.show_generated),
+      None => Ok(false),
+    }
+  }
+
+  pub fn update_ban_expires(
+    conn: &PgConnection,
+    user_id: i32,
+    expires: Option<chrono::NaiveDateTime>,
+  ) -> Result<Self, Error> {
+    diesel::update(user_.find(user_id))
+      .set(ban_expires.eq(expires))
+      .get_result::<Self>(conn)
+  }
+
+  /// Lifts the temporary bans that have expired, returning how many there were.
+  pub fn lift_expired_bans(conn: &PgConnection) -> Result<usize, Error> {
+    diesel::update(
+      user_
+        .filter(banned.eq(true))
+        .filter(ban_expires.le(naive_now())),
+    )
+    .set((
+      banned.eq(false),
+      ban_expires.eq(None::<chrono::NaiveDateTime>),
+    ))
+    .execute(conn)
+  }
+
+  pub fn read_from_actor_id(conn: &PgConnection, from_actor_id: &str) -> Result<Self, Error> {
+    user_.filter(actor_id.eq(from_actor_id)).first::<Self>(conn)
+  }
+
+  /// Creates the local copy of a remote user, who can't log in. Names are unique across instances,
+  /// so the name is `username` if it's free, or else it with a number.
+  pub fn create_remote(
+    conn: &PgConnection,
+    remote_actor_id: &str,
+    domain: &str,
+    username: &str,
+  ) -> Result<Self, Error> {
+    let base = remote_user_name(username);
+    let mut candidate = base.to_owned();
+    let mut n = 1;
+    while user_
+      .filter(lower(name).eq(candidate.to_lowercase()))
+      .first::<Self>(conn)
+      .is_ok()
+    {
+      let suffix = format!("_{}", n);
+      let keep = base.chars().count().min(20 - suffix.len());
+      candidate = format!("{}{}", base.chars().take(keep).collect::<String>(), suffix);
+      n += 1;
+    }
+
+    let form = UserForm {
+      name: candidate,
+      fedi_name: domain.to_owned(),
+      preferred_username: None,
+      // Not a password hash, so no password matches it
+      password_encrypted: String::new(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+    conn.transaction(|| {
+      let user = User_::create(conn, &form)?;
+      diesel::update(user_.find(user.id))
+        .set((actor_id.eq(remote_actor_id), last_refreshed.eq(naive_now())))
+        .get_result::<Self>(conn)
+    })
+  }
+
+  /// Updates a remote user from a fresh copy of their actor.
+  pub fn update_refreshed(
+    conn: &PgConnection,
+    user_id: i32,
+    display_name: Option<String>,
+    icon: Option<String>,
+  ) -> Result<Self, Error> {
+    diesel::update(user_.find(user_id))
+      .set((
+        preferred_username.eq(display_name),
+        avatar.eq(icon),
+        last_refreshed.eq(naive_now()),
+        refresh_failures.eq(0),
+        refresh_retry_at.eq(None::<chrono::NaiveDateTime>),
+      ))
+      .get_result::<Self>(conn)
+  }
+
+  /// Records another failed refresh of a remote user, and when to try again.
+  pub fn update_refresh_failed(
+    conn: &PgConnection,
+    user_id: i32,
+    failures: i32,
+    retry_at: chrono::NaiveDateTime,
+  ) -> Result<Self, Error> {
+    diesel::update(user_.find(user_id))
+      .set((refresh_failures.eq(failures), refresh_retry_at.eq(retry_at)))
+      .get_result::<Self>(conn)
+  }
+
+  /// The remote users whose refresh failed, and are due to be tried again, longest waiting first.
+  pub fn list_refresh_retries(conn: &PgConnection, limit: i64) -> Result<Vec<Self>, Error> {
+    user_
+      .filter(refresh_retry_at.le(naive_now()))
+      .order_by(refresh_retry_at)
+      .limit(limit)
+      .load::<Self>(conn)
+  }
+
+  /// The user's settings, with the defaults for any that can't be read.
+  pub fn settings(&self) -> UserSettings {
+    serde_json::from_value(self.settings.clone()).unwrap_or_default()
+  }
+
+  pub fn update_settings(
+    conn: &PgConnection,
+    user_id: i32,
+    new_settings: &UserSettings,
+  ) -> Result<Self, Error> {
+    let value = serde_json::to_value(new_settings).expect("Couldn't serialize settings");
+
+    diesel::update(user_.find(user_id))
+      .set(settings.eq(value))
+      .get_result::<Self>(conn)
+  }
+}
+
+/// Client preferences which are stored as a json blob, rather than getting a column each.
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct UserSettings {
+  /// The ids of the categories collapsed in the community list.
+  pub collapsed_categories: Vec<i32>,
+  /// Free-form hints for clients, like the last used editor mode.
+  pub client_hints: BTreeMap<String, String>,
+}
+
+/// A remote username as a local one: only letters, numbers and underscores, and at most 20
+/// characters.
+fn remote_user_name(username: &str) -> String {
+  let name = username
+    .chars()
+    .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+    .take(20)
+    .collect::<String>();
+  if name.is_empty() {
+    "remote_user".into()
+  } else {
+    name
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -107,6 +367,9 @@ pub struct Claims {
   pub lang: String,
   pub avatar: Option<String>,
   pub show_avatars: bool,
+  /// Set for api tokens, which are checked against their row
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub token_id: Option<i32>,
 }
 
 impl Claims {
@@ -126,6 +389,15 @@ impl Claims {
 type Jwt = String;
 impl User_ {
   pub fn jwt(&self) -> Jwt {
+    self.encode_claims(None)
+  }
+
+  /// The jwt for an api token, which only works while the token's row exists.
+  pub fn api_token_jwt(&self, token_id: i32) -> Jwt {
+    self.encode_claims(Some(token_id))
+  }
+
+  fn encode_claims(&self, token_id: Option<i32>) -> Jwt {
     let my_claims = Claims {
       id: self.id,
       username: self.name.to_owned(),
@@ -137,6 +409,7 @@ impl User_ {
       lang: self.lang.to_owned(),
       avatar: self.avatar.to_owned(),
       show_avatars: self.show_avatars.to_owned(),
+      token_id,
     };
     encode(
       &Header::default(),
@@ -175,6 +448,30 @@ impl User_ {
   }
 }
 
+/// A local user named `name`, with a new account's settings, for the tests.
+#[cfg(test)]
+pub fn user_form(name: &str) -> UserForm {
+  UserForm {
+    name: name.into(),
+    fedi_name: "rrf".into(),
+    preferred_username: None,
+    password_encrypted: "nope".into(),
+    email: None,
+    matrix_user_id: None,
+    avatar: None,
+    admin: false,
+    banned: false,
+    updated: None,
+    show_nsfw: false,
+    theme: "darkly".into(),
+    default_sort_type: SortType::Hot as i16,
+    default_listing_type: ListingType::Subscribed as i16,
+    lang: "browser".into(),
+    show_avatars: true,
+    send_notifications_to_email: false,
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::User_;
@@ -226,15 +523,72 @@ mod tests {
       lang: "browser".into(),
       show_avatars: true,
       send_notifications_to_email: false,
+      settings: serde_json::json!({}),
+      legal_hold: false,
+      hide_profile: false,
+      actor_id: None,
+      hide_activity: false,
+      last_refreshed: None,
+      refresh_failures: 0,
+      refresh_retry_at: None,
+      blended_discovery_percent: 20,
+      blended_discovery_local: false,
+      show_scores: true,
+      bot_account: false,
+      show_generated: true,
+      ban_expires: None,
+      collapse_crossposts: false,
     };
 
     let read_user = User_::read(&conn, inserted_user.id).unwrap();
     let updated_user = User_::update(&conn, inserted_user.id, &new_user).unwrap();
+
+    let mut new_settings = UserSettings::default();
+    new_settings.collapsed_categories.push(1);
+    new_settings
+      .client_hints
+      .insert("editor".into(), "markdown".into());
+    let user_with_settings =
+      User_::update_settings(&conn, inserted_user.id, &new_settings).unwrap();
+
     let num_deleted = User_::delete(&conn, inserted_user.id).unwrap();
 
     assert_eq!(expected_user, read_user);
     assert_eq!(expected_user, inserted_user);
     assert_eq!(expected_user, updated_user);
+    assert_eq!(UserSettings::default(), read_user.settings());
+    assert_eq!(new_settings, user_with_settings.settings());
     assert_eq!(1, num_deleted);
   }
+
+  #[test]
+  fn test_create_remote() {
+    let conn = establish_unpooled_connection();
+
+    let first = User_::create_remote(
+      &conn,
+      "https://videos.example/accounts/remote_thom",
+      "videos.example",
+      "remote_thom",
+    )
+    .unwrap();
+    // Another instance's user by the same name gets a number
+    let second = User_::create_remote(
+      &conn,
+      "https://other.example/accounts/Remote_Thom",
+      "other.example",
+      "Remote_Thom",
+    )
+    .unwrap();
+    let read = User_::read_from_actor_id(&conn, "https://other.example/accounts/Remote_Thom");
+    User_::delete(&conn, first.id).unwrap();
+    User_::delete(&conn, second.id).unwrap();
+
+    assert_eq!("remote_thom", first.name);
+    assert_eq!("videos.example", first.fedi_name);
+    assert_eq!("Remote_Thom_1", second.name);
+    assert_eq!(second, read.unwrap());
+    assert_eq!("remote_user", remote_user_name("ø.ø"));
+    assert_eq!(20, remote_user_name("a_very_long_remote_username").len());
+  }
 }
@@ -0,0 +1,182 @@
+use super::*;
+use crate::schema::user_activity_day;
+use crate::schema::user_activity_day::dsl::*;
+use diesel::sql_types::{Array, Integer};
+
+/// How many posts and comments a user made on a day. The rows are kept up to date by triggers on
+/// post and comment.
+#[derive(Queryable, PartialEq, Debug, Serialize, Deserialize)]
+pub struct UserActivityDay {
+  pub user_id: i32,
+  pub day: chrono::NaiveDate,
+  pub posts: i32,
+  pub comments: i32,
+}
+
+/// The posts and comments per user and day of the last year, counted again, which is as far back
+/// as `user_activity_day` goes.
+pub const ACTIVITY_COUNTS: &str = "select user_id, day,
+  sum(posts)::int as posts, sum(comments)::int as comments
+  from (
+    select creator_id as user_id, published::date as day, 1 as posts, 0 as comments from post
+    union all
+    select creator_id as user_id, published::date as day, 0 as posts, 1 as comments from comment
+  ) activity
+  where day > now() - interval '1 year'
+  group by user_id, day";
+
+impl UserActivityDay {
+  /// The days since `since` that the user posted or commented on, oldest first.
+  pub fn for_user(
+    conn: &PgConnection,
+    for_user_id: i32,
+    since: chrono::NaiveDate,
+  ) -> Result<Vec<Self>, Error> {
+    user_activity_day
+      .filter(user_id.eq(for_user_id))
+      .filter(day.ge(since))
+      .filter(posts.gt(0).or(comments.gt(0)))
+      .order_by(day)
+      .load::<Self>(conn)
+  }
+
+  /// Counts the last year of the users' activity again, from their posts and comments, for after
+  /// a trigger bug or manual changes to the database. Returns how many days were recounted.
+  pub fn recount(conn: &PgConnection, user_ids: &[i32]) -> Result<usize, Error> {
+    sql_query(format!(
+      "with actual as (select * from ({}) counts where user_id = any($1)),
+      stale as (
+        delete from user_activity_day d
+        where d.user_id = any($1) and d.day > now() - interval '1 year'
+        and not exists (select 1 from actual a where a.user_id = d.user_id and a.day = d.day)
+      )
+      insert into user_activity_day (user_id, day, posts, comments)
+      select user_id, day, posts, comments from actual
+      on conflict (user_id, day) do update
+      set posts = excluded.posts, comments = excluded.comments",
+      ACTIVITY_COUNTS
+    ))
+    .bind::<Array<Integer>, _>(user_ids)
+    .execute(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::comment::*;
+  use super::super::community::*;
+  use super::super::post::*;
+  use super::super::user::*;
+  use super::*;
+  use crate::naive_now;
+
+  #[test]
+  fn test_for_user() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "heatmap".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+
+    let inserted_user = User_::create(&conn, &new_user).unwrap();
+
+    let new_community = CommunityForm {
+      name: "heatmap_community".to_string(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: inserted_user.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let new_post = PostForm {
+      name: "A test post".into(),
+      creator_id: inserted_user.id,
+      url: None,
+      body: None,
+      community_id: inserted_community.id,
+      removed: None,
+      deleted: None,
+      locked: None,
+      stickied: None,
+      updated: None,
+      nsfw: false,
+      embed_title: None,
+      embed_description: None,
+      embed_html: None,
+      thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
+    };
+
+    let inserted_post = Post::create(&conn, &new_post).unwrap();
+
+    let comment_form = CommentForm {
+      content: "A test comment".into(),
+      creator_id: inserted_user.id,
+      post_id: inserted_post.id,
+      removed: None,
+      deleted: None,
+      read: None,
+      parent_id: None,
+      updated: None,
+      generated: None,
+      language_id: None,
+    };
+
+    let first_comment = Comment::create(&conn, &comment_form).unwrap();
+    Comment::create(&conn, &comment_form).unwrap();
+
+    let today = naive_now().date();
+    let after_creating = UserActivityDay::for_user(&conn, inserted_user.id, today).unwrap();
+    diesel::update(user_activity_day.filter(user_id.eq(inserted_user.id)))
+      .set(posts.eq(7))
+      .execute(&conn)
+      .unwrap();
+    UserActivityDay::recount(&conn, &[inserted_user.id]).unwrap();
+    let after_recounting = UserActivityDay::for_user(&conn, inserted_user.id, today).unwrap();
+    Comment::delete(&conn, first_comment.id).unwrap();
+    let after_deleting = UserActivityDay::for_user(&conn, inserted_user.id, today).unwrap();
+    Post::delete(&conn, inserted_post.id).unwrap();
+    let after_deleting_all = UserActivityDay::for_user(&conn, inserted_user.id, today).unwrap();
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, inserted_user.id).unwrap();
+
+    assert_eq!(
+      vec![UserActivityDay {
+        user_id: inserted_user.id,
+        day: today,
+        posts: 1,
+        comments: 2,
+      }],
+      after_creating
+    );
+    assert_eq!(after_creating, after_recounting);
+    assert_eq!(1, after_deleting[0].comments);
+    assert!(after_deleting_all.is_empty());
+  }
+}
@@ -0,0 +1,83 @@
+use super::*;
+use crate::schema::user_block;
+use crate::schema::user_block::dsl::*;
+
+/// A user that a user blocked. Their posts, comments, mentions and private messages are left out
+/// of what the user sees.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "user_block"]
+pub struct UserBlock {
+  pub id: i32,
+  pub user_id: i32,
+  pub target_id: i32,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "user_block"]
+pub struct UserBlockForm {
+  pub user_id: i32,
+  pub target_id: i32,
+}
+
+impl UserBlock {
+  /// Blocks a user, which does nothing if they already are.
+  pub fn block(conn: &PgConnection, form: &UserBlockForm) -> Result<usize, Error> {
+    insert_into(user_block)
+      .values(form)
+      .on_conflict((user_id, target_id))
+      .do_nothing()
+      .execute(conn)
+  }
+
+  pub fn unblock(conn: &PgConnection, form: &UserBlockForm) -> Result<usize, Error> {
+    diesel::delete(
+      user_block
+        .filter(user_id.eq(form.user_id))
+        .filter(target_id.eq(form.target_id)),
+    )
+    .execute(conn)
+  }
+
+  /// The ids of the users a user blocked, most recently blocked first.
+  pub fn blocked_ids(conn: &PgConnection, for_user_id: i32) -> Result<Vec<i32>, Error> {
+    user_block
+      .filter(user_id.eq(for_user_id))
+      .order_by(published.desc())
+      .select(target_id)
+      .load::<i32>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_block() {
+    let conn = establish_unpooled_connection();
+
+    let blocker = User_::create(&conn, &user_form("blocker")).unwrap();
+    let blocked = User_::create(&conn, &user_form("blocked")).unwrap();
+
+    let form = UserBlockForm {
+      user_id: blocker.id,
+      target_id: blocked.id,
+    };
+    let inserted = UserBlock::block(&conn, &form).unwrap();
+    let inserted_again = UserBlock::block(&conn, &form).unwrap();
+    let blocked_ids = UserBlock::blocked_ids(&conn, blocker.id).unwrap();
+    let unblocked = UserBlock::unblock(&conn, &form).unwrap();
+    let blocked_ids_after = UserBlock::blocked_ids(&conn, blocker.id).unwrap();
+
+    User_::delete(&conn, blocker.id).unwrap();
+    User_::delete(&conn, blocked.id).unwrap();
+
+    assert_eq!(1, inserted);
+    assert_eq!(0, inserted_again);
+    assert_eq!(vec![blocked.id], blocked_ids);
+    assert_eq!(1, unblocked);
+    assert!(blocked_ids_after.is_empty());
+  }
+}
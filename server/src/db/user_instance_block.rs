@@ -0,0 +1,225 @@
+use super::*;
+use crate::schema::user_instance_block;
+use crate::schema::user_instance_block::dsl::*;
+
+// The host of an activitypub id, or '' for local objects
+sql_function!(fn instance_domain(x: diesel::sql_types::Nullable<diesel::sql_types::Text>) -> diesel::sql_types::Text);
+
+/// A remote instance that a user blocked. The posts, comments and communities from it are left
+/// out of what the user sees.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "user_instance_block"]
+pub struct UserInstanceBlock {
+  pub id: i32,
+  pub user_id: i32,
+  pub domain: String,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "user_instance_block"]
+pub struct UserInstanceBlockForm {
+  pub user_id: i32,
+  pub domain: String,
+}
+
+impl UserInstanceBlock {
+  /// Blocks an instance, which does nothing if it already is.
+  pub fn block(conn: &PgConnection, form: &UserInstanceBlockForm) -> Result<usize, Error> {
+    insert_into(user_instance_block)
+      .values(form)
+      .on_conflict((user_id, domain))
+      .do_nothing()
+      .execute(conn)
+  }
+
+  pub fn unblock(conn: &PgConnection, form: &UserInstanceBlockForm) -> Result<usize, Error> {
+    diesel::delete(
+      user_instance_block
+        .filter(user_id.eq(form.user_id))
+        .filter(domain.eq(&form.domain)),
+    )
+    .execute(conn)
+  }
+
+  /// The instances a user blocked, most recently blocked first.
+  pub fn list_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    user_instance_block
+      .filter(user_id.eq(for_user_id))
+      .order_by(published.desc())
+      .load::<Self>(conn)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::comment::*;
+  use super::super::comment_view::*;
+  use super::super::community::*;
+  use super::super::community_view::*;
+  use super::super::ids::{CommunityId, PostId, UserId};
+  use super::super::post::*;
+  use super::super::user::*;
+  use super::super::user_view::*;
+  use super::*;
+  use crate::schema::{community, user_};
+
+  #[test]
+  fn test_block() {
+    let conn = establish_unpooled_connection();
+
+    let new_user = UserForm {
+      name: "instance_blocker".into(),
+      fedi_name: "rrf".into(),
+      preferred_username: None,
+      password_encrypted: "nope".into(),
+      email: None,
+      matrix_user_id: None,
+      avatar: None,
+      admin: false,
+      banned: false,
+      updated: None,
+      show_nsfw: false,
+      theme: "darkly".into(),
+      default_sort_type: SortType::Hot as i16,
+      default_listing_type: ListingType::Subscribed as i16,
+      lang: "browser".into(),
+      show_avatars: true,
+      send_notifications_to_email: false,
+    };
+    let blocker = User_::create(&conn, &new_user).unwrap();
+
+    let form = UserInstanceBlockForm {
+      user_id: blocker.id,
+      domain: "remote.example".into(),
+    };
+    let inserted = UserInstanceBlock::block(&conn, &form).unwrap();
+    let inserted_again = UserInstanceBlock::block(&conn, &form).unwrap();
+    let blocked = UserInstanceBlock::list_for_user(&conn, blocker.id).unwrap();
+    let unblocked = UserInstanceBlock::unblock(&conn, &form).unwrap();
+    let blocked_after = UserInstanceBlock::list_for_user(&conn, blocker.id).unwrap();
+
+    let domains = diesel::select((
+      instance_domain(Some("https://Remote.Example:8443/post/1")),
+      instance_domain(None::<String>),
+    ))
+    .get_result::<(String, String)>(&conn)
+    .unwrap();
+
+    User_::delete(&conn, blocker.id).unwrap();
+
+    assert_eq!(1, inserted);
+    assert_eq!(0, inserted_again);
+    assert_eq!(
+      vec!["remote.example"],
+      blocked
+        .iter()
+        .map(|b| b.domain.as_str())
+        .collect::<Vec<_>>()
+    );
+    assert_eq!(1, unblocked);
+    assert!(blocked_after.is_empty());
+    assert_eq!(("remote.example".into(), "".into()), domains);
+  }
+
+  #[test]
+  fn test_blocked_listings() {
+    let conn = establish_unpooled_connection();
+
+    let blocker = User_::create(&conn, &user_form("instance_blocker_2")).unwrap();
+    let remote_user = User_::create(&conn, &user_form("blockedremoteuser")).unwrap();
+    diesel::update(user_::table.find(remote_user.id))
+      .set(user_::actor_id.eq("https://blocked.example/u/blockedremoteuser"))
+      .execute(&conn)
+      .unwrap();
+    let remote_community = Community::create(
+      &conn,
+      &community_form("blockedremotecomm", UserId(blocker.id)),
+    )
+    .unwrap();
+    diesel::update(community::table.find(remote_community.id))
+      .set(community::actor_id.eq("https://blocked.example/c/blockedremotecomm"))
+      .execute(&conn)
+      .unwrap();
+    let post = Post::create(
+      &conn,
+      &post_form(
+        "local post",
+        UserId(blocker.id),
+        CommunityId(remote_community.id),
+      ),
+    )
+    .unwrap();
+    let comment = Comment::create(
+      &conn,
+      &CommentForm {
+        creator_id: blocker.id,
+        post_id: post.id,
+        parent_id: None,
+        content: "local comment".into(),
+        removed: None,
+        read: None,
+        updated: None,
+        deleted: None,
+        generated: None,
+        language_id: None,
+      },
+    )
+    .unwrap();
+
+    let lists_comment = |my_user_id: Option<UserId>| {
+      CommentQueryBuilder::create(&conn)
+        .for_post_id(PostId(post.id))
+        .my_user_id(my_user_id)
+        .list()
+        .unwrap()
+        .iter()
+        .any(|c| c.id == comment.id)
+    };
+    let lists_community = |from_user_id: Option<UserId>| {
+      CommunityQueryBuilder::create(&conn)
+        .search_term("blockedremotecomm".to_string())
+        .for_user(from_user_id)
+        .list()
+        .unwrap()
+        .iter()
+        .any(|c| c.id == remote_community.id)
+    };
+    let lists_user = |from_user_id: Option<UserId>| {
+      UserQueryBuilder::create(&conn)
+        .search_term("blockedremoteuser".to_string())
+        .for_user(from_user_id)
+        .list()
+        .unwrap()
+        .iter()
+        .any(|u| u.id == remote_user.id)
+    };
+
+    let blocker_id = Some(UserId(blocker.id));
+    let before = (
+      lists_comment(blocker_id),
+      lists_community(blocker_id),
+      lists_user(blocker_id),
+    );
+
+    let form = UserInstanceBlockForm {
+      user_id: blocker.id,
+      domain: "blocked.example".into(),
+    };
+    UserInstanceBlock::block(&conn, &form).unwrap();
+    let blocked = (
+      lists_comment(blocker_id),
+      lists_community(blocker_id),
+      lists_user(blocker_id),
+    );
+    let anonymous = (lists_comment(None), lists_community(None), lists_user(None));
+
+    Community::delete(&conn, remote_community.id).unwrap();
+    User_::delete(&conn, remote_user.id).unwrap();
+    User_::delete(&conn, blocker.id).unwrap();
+
+    assert_eq!((true, true, true), before);
+    assert_eq!((false, false, false), blocked);
+    assert_eq!((true, true, true), anonymous);
+  }
+}
@@ -0,0 +1,251 @@
+use super::*;
+use crate::schema::user_keyword_filter;
+use crate::schema::user_keyword_filter::dsl::*;
+
+/// The longest a muted regular expression can be.
+const MAX_REGEX_LENGTH: usize = 50;
+
+/// A word or regular expression that a user muted. The posts and comments with it are left out of
+/// what the user sees, except their own.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "user_keyword_filter"]
+pub struct UserKeywordFilter {
+  pub id: i32,
+  pub user_id: i32,
+  /// A word or phrase, which also mutes its other forms, like plurals
+  pub keyword: String,
+  /// Whether the keyword is a case insensitive regular expression instead
+  pub regex: bool,
+  pub published: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[table_name = "user_keyword_filter"]
+pub struct UserKeywordFilterForm {
+  pub user_id: i32,
+  pub keyword: String,
+  pub regex: bool,
+}
+
+impl UserKeywordFilter {
+  pub fn create(conn: &PgConnection, form: &UserKeywordFilterForm) -> Result<Self, Error> {
+    insert_into(user_keyword_filter)
+      .values(form)
+      .get_result::<Self>(conn)
+  }
+
+  pub fn read(conn: &PgConnection, filter_id: i32) -> Result<Self, Error> {
+    user_keyword_filter.find(filter_id).first::<Self>(conn)
+  }
+
+  pub fn delete(conn: &PgConnection, filter_id: i32) -> Result<usize, Error> {
+    diesel::delete(user_keyword_filter.find(filter_id)).execute(conn)
+  }
+
+  /// The words a user muted, in the order they were muted.
+  pub fn list_for_user(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    user_keyword_filter
+      .filter(user_id.eq(for_user_id))
+      .order_by(published)
+      .then_order_by(id)
+      .load::<Self>(conn)
+  }
+
+  /// Whether a regular expression is cheap enough to match every post and comment a user sees
+  /// against: it's short, has no back references, and doesn't repeat something that repeats,
+  /// which is what makes matching take exponential time.
+  pub fn is_simple_regex(pattern: &str) -> bool {
+    if pattern.chars().count() > MAX_REGEX_LENGTH {
+      return false;
+    }
+
+    // Whether each open group, and the one at the top level, has a repetition in it
+    let mut groups = vec![false];
+    let mut last_group_repeats = false;
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+      let closed_group_repeats = last_group_repeats;
+      last_group_repeats = false;
+      match c {
+        '\\' => {
+          if chars
+            .next()
+            .map_or(false, |d| d.is_ascii_digit() && d != '0')
+          {
+            return false;
+          }
+        }
+        '[' => {
+          // A bracket expression is one character, and a `]` right after its start is in it
+          if chars.peek() == Some(&'^') {
+            chars.next();
+          }
+          if chars.peek() == Some(&']') {
+            chars.next();
+          }
+          chars.find(|&d| d == ']');
+        }
+        '(' => groups.push(false),
+        ')' => {
+          let repeats = groups.pop().unwrap_or(false);
+          if groups.is_empty() {
+            return false;
+          }
+          last_group_repeats = repeats;
+          if repeats {
+            *groups.last_mut().unwrap() = true;
+          }
+        }
+        '*' | '+' | '?' | '{' => {
+          if closed_group_repeats && c != '?' {
+            return false;
+          }
+          if c == '{' {
+            chars.find(|&d| d == '}');
+          }
+          *groups.last_mut().unwrap() = true;
+        }
+        _ => {}
+      }
+    }
+    true
+  }
+
+  /// Whether the database can match with the regular expression, whose syntax differs from the
+  /// one of the `regex` crate.
+  pub fn is_valid_regex(conn: &PgConnection, pattern: &str) -> bool {
+    select(sql::<diesel::sql_types::Bool>("'' ~* ").bind::<diesel::sql_types::Text, _>(pattern))
+      .get_result::<bool>(conn)
+      .is_ok()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::community::*;
+  use super::super::ids::{CommunityId, UserId};
+  use super::super::post::*;
+  use super::super::post_view::*;
+  use super::super::user::*;
+  use super::*;
+
+  #[test]
+  fn test_keyword_filter() {
+    let conn = establish_unpooled_connection();
+
+    let muter = User_::create(&conn, &user_form("keyword_muter")).unwrap();
+    let poster = User_::create(&conn, &user_form("keyword_poster")).unwrap();
+
+    let new_community = CommunityForm {
+      name: "test_keyword_community".into(),
+      title: "nada".to_owned(),
+      description: None,
+      category_id: 1,
+      creator_id: poster.id,
+      removed: None,
+      deleted: None,
+      updated: None,
+      nsfw: false,
+    };
+
+    let inserted_community = Community::create(&conn, &new_community).unwrap();
+
+    let text_post = |creator_id: i32, name: &str, body: Option<&str>| PostForm {
+      body: body.map(String::from),
+      ..post_form(name, UserId(creator_id), CommunityId(inserted_community.id))
+    };
+
+    let posts = vec![
+      Post::create(
+        &conn,
+        &text_post(poster.id, "Spoilers for the finale", None),
+      )
+      .unwrap(),
+      Post::create(
+        &conn,
+        &text_post(poster.id, "A new drop", Some("Get your NFT today")),
+      )
+      .unwrap(),
+      Post::create(&conn, &text_post(poster.id, "Gardening tips", None)).unwrap(),
+      // Their own posts stay
+      Post::create(&conn, &text_post(muter.id, "My spoiler", None)).unwrap(),
+    ];
+    let post_ids: Vec<i32> = posts.iter().map(|p| p.id).collect();
+
+    let word = UserKeywordFilter::create(
+      &conn,
+      &UserKeywordFilterForm {
+        user_id: muter.id,
+        keyword: "spoiler".into(),
+        regex: false,
+      },
+    )
+    .unwrap();
+    let regex = UserKeywordFilter::create(
+      &conn,
+      &UserKeywordFilterForm {
+        user_id: muter.id,
+        keyword: "\\m(nft|web3)\\M".into(),
+        regex: true,
+      },
+    )
+    .unwrap();
+    let filters = UserKeywordFilter::list_for_user(&conn, muter.id).unwrap();
+
+    let listing = |user_id: i32| {
+      PostQueryBuilder::create(&conn)
+        .listing_type(ListingType::All)
+        .sort(&SortType::New)
+        .for_post_ids(post_ids.to_owned())
+        .my_user_id(UserId(user_id))
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|p| p.name)
+        .collect::<Vec<String>>()
+    };
+    let muters_listing = listing(muter.id);
+    let posters_listing = listing(poster.id);
+
+    let valid = UserKeywordFilter::is_valid_regex(&conn, "colou?r");
+    let invalid = UserKeywordFilter::is_valid_regex(&conn, "(unbalanced");
+
+    UserKeywordFilter::delete(&conn, word.id).unwrap();
+    UserKeywordFilter::delete(&conn, regex.id).unwrap();
+    for post_id in &post_ids {
+      Post::delete(&conn, *post_id).unwrap();
+    }
+    Community::delete(&conn, inserted_community.id).unwrap();
+    User_::delete(&conn, muter.id).unwrap();
+    User_::delete(&conn, poster.id).unwrap();
+
+    assert_eq!(
+      vec![word.id, regex.id],
+      filters.iter().map(|f| f.id).collect::<Vec<_>>()
+    );
+    assert_eq!(vec!["My spoiler", "Gardening tips"], muters_listing);
+    assert_eq!(4, posters_listing.len());
+    assert!(valid);
+    assert!(!invalid);
+  }
+
+  #[test]
+  fn test_is_simple_regex() {
+    assert!(UserKeywordFilter::is_simple_regex("colou?r"));
+    assert!(UserKeywordFilter::is_simple_regex("spoil(er|ers)?"));
+    assert!(UserKeywordFilter::is_simple_regex(
+      "[a-z]+ (season|episode) [0-9]{1,2}"
+    ));
+    assert!(UserKeywordFilter::is_simple_regex("[]()+*]+"));
+    assert!(UserKeywordFilter::is_simple_regex("\\(a+\\)+"));
+
+    // Repeated repetitions, even with groups in between, and back references
+    assert!(!UserKeywordFilter::is_simple_regex("(a+)+b"));
+    assert!(!UserKeywordFilter::is_simple_regex("(a*)*"));
+    assert!(!UserKeywordFilter::is_simple_regex("((a|b)+c)*"));
+    assert!(!UserKeywordFilter::is_simple_regex("(a{2,})+"));
+    assert!(!UserKeywordFilter::is_simple_regex("(a)\\1"));
+    assert!(!UserKeywordFilter::is_simple_regex("unbalanced)"));
+    assert!(!UserKeywordFilter::is_simple_regex(&"a".repeat(51)));
+  }
+}
@@ -51,6 +51,30 @@ impl Crud<UserMentionForm> for UserMention {
   }
 }
 
+impl UserMention {
+  /// Marks a user's mentions as read, or unread: the ones in `user_mention_ids`, or all of them.
+  pub fn update_read(
+    conn: &PgConnection,
+    for_recipient_id: i32,
+    user_mention_ids: Option<&[i32]>,
+    new_read: bool,
+  ) -> Result<usize, Error> {
+    use crate::schema::user_mention::dsl::*;
+    let mentions = user_mention
+      .filter(recipient_id.eq(for_recipient_id))
+      .filter(read.ne(new_read));
+
+    match user_mention_ids {
+      Some(user_mention_ids) => diesel::update(mentions.filter(id.eq_any(user_mention_ids)))
+        .set(read.eq(new_read))
+        .execute(conn),
+      None => diesel::update(mentions)
+        .set(read.eq(new_read))
+        .execute(conn),
+    }
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::super::comment::*;
@@ -136,6 +160,11 @@ mod tests {
       embed_description: None,
       embed_html: None,
       thumbnail_url: None,
+      alt_text: None,
+      visibility: None,
+      generated: None,
+      language_id: None,
+      scheduled_publish_time: None,
     };
 
     let inserted_post = Post::create(&conn, &new_post).unwrap();
@@ -149,6 +178,8 @@ mod tests {
       read: None,
       parent_id: None,
       updated: None,
+      generated: None,
+      language_id: None,
     };
 
     let inserted_comment = Comment::create(&conn, &comment_form).unwrap();
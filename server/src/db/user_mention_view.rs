@@ -1,4 +1,6 @@
 use super::*;
+use crate::avatar::fill_default_avatar;
+use crate::schema::user_block;
 use diesel::pg::Pg;
 
 // The faked schema since diesel doesn't do views
@@ -21,9 +23,9 @@ table! {
     banned_from_community -> Bool,
     creator_name -> Varchar,
     creator_avatar -> Nullable<Text>,
-    score -> BigInt,
-    upvotes -> BigInt,
-    downvotes -> BigInt,
+    score -> Nullable<BigInt>,
+    upvotes -> Nullable<BigInt>,
+    downvotes -> Nullable<BigInt>,
     hot_rank -> Int4,
     user_id -> Nullable<Int4>,
     my_vote -> Nullable<Int4>,
@@ -51,9 +53,9 @@ table! {
     banned_from_community -> Bool,
     creator_name -> Varchar,
     creator_avatar -> Nullable<Text>,
-    score -> BigInt,
-    upvotes -> BigInt,
-    downvotes -> BigInt,
+    score -> Nullable<BigInt>,
+    upvotes -> Nullable<BigInt>,
+    downvotes -> Nullable<BigInt>,
     hot_rank -> Int4,
     user_id -> Nullable<Int4>,
     my_vote -> Nullable<Int4>,
@@ -84,9 +86,9 @@ pub struct UserMentionView {
   pub banned_from_community: bool,
   pub creator_name: String,
   pub creator_avatar: Option<String>,
-  pub score: i64,
-  pub upvotes: i64,
-  pub downvotes: i64,
+  pub score: Option<i64>,
+  pub upvotes: Option<i64>,
+  pub downvotes: Option<i64>,
   pub hot_rank: i32,
   pub user_id: Option<i32>,
   pub my_vote: Option<i32>,
@@ -154,12 +156,24 @@ impl<'a> UserMentionQueryBuilder<'a> {
       .filter(user_id.eq(self.for_user_id))
       .filter(recipient_id.eq(self.for_user_id));
 
+    // Not from the users they blocked
+    query = query.filter(
+      creator_id.ne_all(
+        user_block::table
+          .filter(user_block::user_id.eq(self.for_user_id))
+          .select(user_block::target_id),
+      ),
+    );
+
     query = match self.sort {
-      SortType::Hot => query
+      SortType::Hot | SortType::Rising => query
         .order_by(hot_rank.desc())
         .then_order_by(published.desc()),
+      SortType::Controversial => query
+        .order_by(controversy_rank(upvotes, downvotes).desc())
+        .then_order_by(published.desc()),
       SortType::New => query.order_by(published.desc()),
-      SortType::TopAll => query.order_by(score.desc()),
+      SortType::TopAll | SortType::TopViews => query.order_by(score.desc()),
       SortType::TopYear => query
         .filter(published.gt(now - 1.years()))
         .order_by(score.desc()),
@@ -180,6 +194,21 @@ impl<'a> UserMentionQueryBuilder<'a> {
       .limit(limit)
       .offset(offset)
       .load::<UserMentionView>(self.conn)
+      .map(DefaultAvatars::with_default_avatars)
+  }
+}
+
+impl HideScores for UserMentionView {
+  fn hide_scores(&mut self) {
+    self.score = None;
+    self.upvotes = None;
+    self.downvotes = None;
+  }
+}
+
+impl DefaultAvatars for UserMentionView {
+  fn default_avatars(&mut self) {
+    fill_default_avatar(&mut self.creator_avatar, &self.creator_name);
   }
 }
 
@@ -195,5 +224,6 @@ impl UserMentionView {
       .filter(user_mention_id.eq(from_user_mention_id))
       .filter(user_id.eq(from_recipient_id))
       .first::<Self>(conn)
+      .map(Self::with_default_avatars)
   }
 }
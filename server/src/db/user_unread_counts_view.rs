@@ -0,0 +1,35 @@
+use super::*;
+
+// The faked schema since diesel doesn't do views
+table! {
+  user_unread_counts_view (user_id) {
+    user_id -> Int4,
+    unread_replies -> BigInt,
+    unread_mentions -> BigInt,
+    unread_private_messages -> BigInt,
+  }
+}
+
+/// How many unread items a user has in their inbox, of each kind.
+#[derive(Queryable, PartialEq, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UserUnreadCountsView {
+  pub user_id: i32,
+  pub unread_replies: i64,
+  pub unread_mentions: i64,
+  pub unread_private_messages: i64,
+}
+
+impl UserUnreadCountsView {
+  /// The counts are all 0 when the user has nothing unread.
+  pub fn read(conn: &PgConnection, for_user_id: i32) -> Result<Self, Error> {
+    use super::user_unread_counts_view::user_unread_counts_view::dsl::*;
+    let counts = user_unread_counts_view
+      .find(for_user_id)
+      .first::<Self>(conn)
+      .optional()?;
+    Ok(counts.unwrap_or(UserUnreadCountsView {
+      user_id: for_user_id,
+      ..Default::default()
+    }))
+  }
+}
@@ -1,5 +1,9 @@
+use super::ids::UserId;
+use super::user_instance_block::instance_domain;
 use super::user_view::user_mview::BoxedQuery;
 use super::*;
+use crate::avatar::fill_default_avatar;
+use crate::schema::{user_, user_instance_block};
 use diesel::pg::Pg;
 
 table! {
@@ -15,10 +19,12 @@ table! {
     show_avatars -> Bool,
     send_notifications_to_email -> Bool,
     published -> Timestamp,
+    last_refreshed -> Nullable<Timestamp>,
+    refresh_failures -> Int4,
     number_of_posts -> BigInt,
-    post_score -> BigInt,
+    post_score -> Nullable<BigInt>,
     number_of_comments -> BigInt,
-    comment_score -> BigInt,
+    comment_score -> Nullable<BigInt>,
   }
 }
 
@@ -35,10 +41,12 @@ table! {
     show_avatars -> Bool,
     send_notifications_to_email -> Bool,
     published -> Timestamp,
+    last_refreshed -> Nullable<Timestamp>,
+    refresh_failures -> Int4,
     number_of_posts -> BigInt,
-    post_score -> BigInt,
+    post_score -> Nullable<BigInt>,
     number_of_comments -> BigInt,
-    comment_score -> BigInt,
+    comment_score -> Nullable<BigInt>,
   }
 }
 
@@ -58,20 +66,27 @@ pub struct UserView {
   pub show_avatars: bool,
   pub send_notifications_to_email: bool,
   pub published: chrono::NaiveDateTime,
+  /// When a remote user was last fetched from their instance
+  pub last_refreshed: Option<chrono::NaiveDateTime>,
+  /// How many times in a row refreshing a remote user has failed, so more than 0 means what's
+  /// shown of them may be out of date
+  pub refresh_failures: i32,
   pub number_of_posts: i64,
-  pub post_score: i64,
+  pub post_score: Option<i64>,
   pub number_of_comments: i64,
-  pub comment_score: i64,
+  pub comment_score: Option<i64>,
 }
 
 pub struct UserQueryBuilder<'a> {
   conn: &'a PgConnection,
   query: BoxedQuery<'a, Pg>,
-  sort: &'a SortType,
-  page: Option<i64>,
-  limit: Option<i64>,
+  listing: ListingParams<'a>,
+  logged_in: bool,
+  from_user_id: Option<UserId>,
 }
 
+allow_tables_to_appear_in_same_query!(user_mview, user_);
+
 impl<'a> UserQueryBuilder<'a> {
   pub fn create(conn: &'a PgConnection) -> Self {
     use super::user_view::user_mview::dsl::*;
@@ -81,14 +96,21 @@ impl<'a> UserQueryBuilder<'a> {
     UserQueryBuilder {
       conn,
       query,
-      sort: &SortType::Hot,
-      page: None,
-      limit: None,
+      listing: ListingParams::new(&SortType::Hot),
+      logged_in: false,
+      from_user_id: None,
     }
   }
 
-  pub fn sort(mut self, sort: &'a SortType) -> Self {
-    self.sort = sort;
+  /// Hidden profiles are only listed for logged in users.
+  pub fn logged_in(mut self, logged_in: bool) -> Self {
+    self.logged_in = logged_in;
+    self
+  }
+
+  /// Leaves out the users from the instances this user blocked.
+  pub fn for_user<T: MaybeOptional<UserId>>(mut self, from_user_id: T) -> Self {
+    self.from_user_id = from_user_id.get_optional();
     self
   }
 
@@ -99,28 +121,43 @@ impl<'a> UserQueryBuilder<'a> {
     }
     self
   }
+}
 
-  pub fn page<T: MaybeOptional<i64>>(mut self, page: T) -> Self {
-    self.page = page.get_optional();
-    self
-  }
+impl<'a> ListingQueryBuilder<'a> for UserQueryBuilder<'a> {
+  type View = UserView;
 
-  pub fn limit<T: MaybeOptional<i64>>(mut self, limit: T) -> Self {
-    self.limit = limit.get_optional();
-    self
+  fn listing_params(&mut self) -> &mut ListingParams<'a> {
+    &mut self.listing
   }
 
-  pub fn list(self) -> Result<Vec<UserView>, Error> {
+  fn list(self) -> Result<Vec<UserView>, Error> {
     use super::user_view::user_mview::dsl::*;
 
     let mut query = self.query;
 
-    query = match self.sort {
-      SortType::Hot => query
+    if !self.logged_in {
+      let hidden = user_::table
+        .select(user_::id)
+        .filter(user_::hide_profile.eq(true));
+      query = query.filter(id.ne_all(hidden));
+    }
+
+    if let Some(from_user_id) = self.from_user_id {
+      let blocked_domains = user_instance_block::table
+        .filter(user_instance_block::user_id.eq(from_user_id))
+        .select(user_instance_block::domain);
+      let blocked = user_::table
+        .select(user_::id)
+        .filter(instance_domain(user_::actor_id).eq_any(blocked_domains));
+      query = query.filter(id.ne_all(blocked));
+    }
+
+    query = match self.listing.sort {
+      SortType::Hot | SortType::Rising | SortType::Controversial => query
         .order_by(comment_score.desc())
         .then_order_by(published.desc()),
       SortType::New => query.order_by(published.desc()),
-      SortType::TopAll => query.order_by(comment_score.desc()),
+      SortType::TopAll | SortType::TopViews => query.order_by(comment_score.desc()),
       SortType::TopYear => query
         .filter(published.gt(now - 1.years()))
         .order_by(comment_score.desc()),
@@ -135,26 +172,74 @@ impl<'a> UserQueryBuilder<'a> {
         .order_by(comment_score.desc()),
     };
 
-    let (limit, offset) = limit_and_offset(self.page, self.limit);
+    let (limit, offset) = self.listing.limit_and_offset();
     query = query.limit(limit).offset(offset);
 
-    query.load::<UserView>(self.conn)
+    query
+      .load::<UserView>(self.conn)
+      .map(DefaultAvatars::with_default_avatars)
+  }
+}
+
+impl HideScores for UserView {
+  fn hide_scores(&mut self) {
+    self.post_score = None;
+    self.comment_score = None;
+  }
+}
+
+impl DefaultAvatars for UserView {
+  fn default_avatars(&mut self) {
+    fill_default_avatar(&mut self.avatar, &self.name);
   }
 }
 
 impl UserView {
   pub fn read(conn: &PgConnection, from_user_id: i32) -> Result<Self, Error> {
     use super::user_view::user_mview::dsl::*;
-    user_mview.find(from_user_id).first::<Self>(conn)
+    user_mview
+      .find(from_user_id)
+      .first::<Self>(conn)
+      .map(Self::with_default_avatars)
   }
 
   pub fn admins(conn: &PgConnection) -> Result<Vec<Self>, Error> {
     use super::user_view::user_mview::dsl::*;
-    user_mview.filter(admin.eq(true)).load::<Self>(conn)
+    user_mview
+      .filter(admin.eq(true))
+      .load::<Self>(conn)
+      .map(DefaultAvatars::with_default_avatars)
   }
 
   pub fn banned(conn: &PgConnection) -> Result<Vec<Self>, Error> {
     use super::user_view::user_mview::dsl::*;
-    user_mview.filter(banned.eq(true)).load::<Self>(conn)
+    user_mview
+      .filter(banned.eq(true))
+      .load::<Self>(conn)
+      .map(DefaultAvatars::with_default_avatars)
+  }
+
+  /// The users a user blocked.
+  pub fn blocked_by(conn: &PgConnection, for_user_id: i32) -> Result<Vec<Self>, Error> {
+    use super::user_view::user_mview::dsl::*;
+    use crate::schema::user_block;
+    user_mview
+      .filter(
+        id.eq_any(
+          user_block::table
+            .filter(user_block::user_id.eq(for_user_id))
+            .select(user_block::target_id),
+        ),
+      )
+      .order_by(name)
+      .load::<Self>(conn)
+      .map(DefaultAvatars::with_default_avatars)
+  }
+
+  /// Counts the users' posts, comments and karma again, which the triggers otherwise only do when
+  /// they change.
+  pub fn refresh(conn: &PgConnection) -> Result<(), Error> {
+    sql_query("refresh materialized view concurrently user_mview").execute(conn)?;
+    Ok(())
   }
 }
@@ -0,0 +1,125 @@
+//! An in-process bus for the things that happen on the site.
+//!
+//! Handlers publish an `Event` once their changes are committed, and the side effects that only
+//! need to know it happened, like notification emails, are subscribers instead of being inlined
+//! into every handler. Only the events something subscribes to are published.
+
+pub mod notifications;
+
+use crate::db::comment_view::CommentView;
+use crate::db::moderation_notice::ModerationNotice;
+use crate::db::user::User_;
+use std::sync::RwLock;
+
+pub enum Event {
+  CommentCreated {
+    comment: CommentView,
+    /// The users mentioned in the comment, other than its creator.
+    mentioned: Vec<User_>,
+    /// The creator of the parent comment, or of the post for top level comments, unless that's the
+    /// comment's creator.
+    replied_to: Option<User_>,
+  },
+  ModerationNoticeSent {
+    notice: ModerationNotice,
+    recipient: User_,
+    mod_name: String,
+  },
+}
+
+pub trait Subscriber: Send + Sync {
+  /// Called on the publishing thread, so anything slow should be handed off.
+  fn handle(&self, event: &Event);
+}
+
+/// The subscribers events are handed to.
+pub struct EventBus {
+  subscribers: RwLock<Vec<Box<dyn Subscriber>>>,
+}
+
+impl EventBus {
+  pub fn new(subscribers: Vec<Box<dyn Subscriber>>) -> Self {
+    EventBus {
+      subscribers: RwLock::new(subscribers),
+    }
+  }
+
+  /// Adds a subscriber, which gets every event published after this.
+  pub fn subscribe(&self, subscriber: Box<dyn Subscriber>) {
+    self.subscribers.write().unwrap().push(subscriber);
+  }
+
+  /// Hands `event` to every subscriber, in the order they subscribed.
+  pub fn publish(&self, event: Event) {
+    for subscriber in self.subscribers.read().unwrap().iter() {
+      subscriber.handle(&event);
+    }
+  }
+}
+
+lazy_static! {
+  static ref BUS: EventBus = EventBus::new(vec![Box::new(notifications::EmailNotifications)]);
+}
+
+/// Adds a subscriber to the site's bus.
+pub fn subscribe(subscriber: Box<dyn Subscriber>) {
+  BUS.subscribe(subscriber);
+}
+
+/// Publishes an event on the site's bus.
+pub fn publish(event: Event) {
+  BUS.publish(event);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::db::establish_unpooled_connection;
+  use crate::db::user::user_form;
+  use crate::db::Crud;
+  use crate::naive_now;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::Arc;
+
+  struct CountNotices(Arc<AtomicUsize>);
+
+  impl Subscriber for CountNotices {
+    fn handle(&self, event: &Event) {
+      if let Event::ModerationNoticeSent { .. } = event {
+        self.0.fetch_add(1, Ordering::SeqCst);
+      }
+    }
+  }
+
+  #[test]
+  fn test_publish() {
+    let conn = establish_unpooled_connection();
+    let recipient = User_::create(&conn, &user_form("event_bus_user")).unwrap();
+    User_::delete(&conn, recipient.id).unwrap();
+
+    // A bus of its own, so the subscriber doesn't see the other tests' events
+    let bus = EventBus::new(Vec::new());
+    let notices = Arc::new(AtomicUsize::new(0));
+    bus.subscribe(Box::new(CountNotices(notices.clone())));
+
+    bus.publish(Event::ModerationNoticeSent {
+      notice: ModerationNotice {
+        id: 1,
+        recipient_id: recipient.id,
+        mod_user_id: 1,
+        kind: 0,
+        community_id: None,
+        post_id: None,
+        comment_id: None,
+        reason: None,
+        content: "Your post was removed".into(),
+        read: false,
+        published: naive_now(),
+      },
+      recipient,
+      mod_name: "a_mod".into(),
+    });
+
+    assert_eq!(1, notices.load(Ordering::SeqCst));
+  }
+}
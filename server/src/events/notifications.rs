@@ -0,0 +1,77 @@
+use super::{Event, Subscriber};
+use crate::db::user::User_;
+use crate::{send_email, Settings};
+use log::error;
+
+/// Emails the users who have notifications on about mentions, replies and moderation notices.
+pub struct EmailNotifications;
+
+impl Subscriber for EmailNotifications {
+  fn handle(&self, event: &Event) {
+    if let Event::ModerationNoticeSent {
+      notice,
+      recipient,
+      mod_name,
+    } = event
+    {
+      notify(
+        recipient,
+        "Moderation notice",
+        "Moderation Notice",
+        mod_name,
+        &notice.content,
+      );
+    }
+
+    if let Event::CommentCreated {
+      comment,
+      mentioned,
+      replied_to,
+    } = event
+    {
+      for mention_user in mentioned {
+        notify(
+          mention_user,
+          &format!("Mentioned by {}", comment.creator_name),
+          "User Mention",
+          &comment.creator_name,
+          &comment.content,
+        );
+      }
+
+      if let Some(parent_user) = replied_to {
+        let heading = if comment.parent_id.is_some() {
+          "Comment Reply"
+        } else {
+          "Post Reply"
+        };
+        notify(
+          parent_user,
+          &format!("Reply from {}", comment.creator_name),
+          heading,
+          &comment.creator_name,
+          &comment.content,
+        );
+      }
+    }
+  }
+}
+
+fn notify(user: &User_, subject: &str, heading: &str, username: &str, content: &str) {
+  if !user.send_notifications_to_email {
+    return;
+  }
+
+  if let Some(email) = &user.email {
+    let hostname = &Settings::get().hostname;
+    let subject = &format!("{} - {}", hostname, subject);
+    let html = &format!(
+      "<h1>{}</h1><br><div>{} - {}</div><br><a href=https://{}/inbox>inbox</a>",
+      heading, username, content, hostname
+    );
+    match send_email(subject, email, &user.name, html) {
+      Ok(_o) => _o,
+      Err(e) => error!("{}", e),
+    };
+  }
+}
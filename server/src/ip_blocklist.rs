@@ -0,0 +1,211 @@
+//! Blocking registrations, and optionally posts and comments, from abusive networks.
+//!
+//! Admins block either a network by CIDR, or a whole autonomous system by number. Matching IPs to
+//! autonomous systems needs an IP to ASN database in the tab separated format of
+//! <https://iptoasn.com>, set as `asn_database` in the settings. Without it, only the CIDR blocks
+//! apply.
+
+use crate::api::APIError;
+use crate::db::ip_block::IpBlock;
+use crate::settings::Settings;
+use diesel::PgConnection;
+use failure::Error;
+use log::{error, warn};
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// One entry of a blocklist.
+#[derive(Debug, PartialEq)]
+pub enum Network {
+  /// A CIDR, with its host bits cleared, ie `192.0.2.0/24`
+  Cidr(String),
+  /// An autonomous system number
+  Asn(i32),
+}
+
+/// Parses a CIDR, a single address, or an autonomous system number like `AS64496`.
+pub fn parse_network(entry: &str) -> Option<Network> {
+  let entry = entry.trim();
+  if entry
+    .get(..2)
+    .map_or(false, |p| p.eq_ignore_ascii_case("as"))
+  {
+    return entry[2..]
+      .parse::<u32>()
+      .ok()
+      .filter(|asn| *asn <= i32::max_value() as u32)
+      .map(|asn| Network::Asn(asn as i32));
+  }
+
+  let (address, prefix) = match entry.find('/') {
+    Some(slash) => (&entry[..slash], Some(&entry[slash + 1..])),
+    None => (entry, None),
+  };
+  let address = address.parse::<IpAddr>().ok()?;
+  let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+  let prefix = match prefix {
+    Some(prefix) => prefix.parse::<u32>().ok().filter(|p| *p <= max_prefix)?,
+    None => max_prefix,
+  };
+
+  // Postgres' cidr type rejects set host bits, and lists often have them
+  let network = match address {
+    IpAddr::V4(v4) => {
+      let mask = u32::max_value().checked_shl(32 - prefix).unwrap_or(0);
+      IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+    }
+    IpAddr::V6(v6) => {
+      let mask = u128::max_value().checked_shl(128 - prefix).unwrap_or(0);
+      IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+    }
+  };
+  Some(Network::Cidr(format!("{}/{}", network, prefix)))
+}
+
+/// Parses a blocklist in the common plain text formats, like Spamhaus DROP or FireHOL netsets: one
+/// network per line, with anything after a `;` or `#` a comment. Returns the networks, and the
+/// lines which couldn't be parsed.
+pub fn parse_list(list: &str) -> (Vec<Network>, Vec<String>) {
+  let mut networks = Vec::new();
+  let mut invalid = Vec::new();
+
+  for line in list.lines() {
+    let entry = line.split(|c| c == ';' || c == '#').next().unwrap_or("");
+    let entry = entry.split_whitespace().next().unwrap_or("");
+    if entry.is_empty() {
+      continue;
+    }
+    match parse_network(entry) {
+      Some(network) => networks.push(network),
+      None => invalid.push(line.trim().to_string()),
+    }
+  }
+
+  (networks, invalid)
+}
+
+/// Address ranges and the autonomous systems they belong to, sorted by the start of the range.
+pub struct AsnDatabase {
+  ranges: Vec<(IpAddr, IpAddr, i32)>,
+}
+
+lazy_static! {
+  static ref ASN_DATABASE: Option<AsnDatabase> =
+    Settings::get()
+      .asn_database
+      .and_then(|path| match fs::read_to_string(&path) {
+        Ok(tsv) => Some(AsnDatabase::parse(&tsv)),
+        Err(e) => {
+          error!("Couldn't read the ASN database {}: {}", path, e);
+          None
+        }
+      });
+}
+
+impl AsnDatabase {
+  /// Parses `range_start range_end AS_number ...` lines. Ranges which aren't routed (AS 0) are
+  /// skipped.
+  pub fn parse(tsv: &str) -> Self {
+    let mut ranges = Vec::new();
+    for line in tsv.lines() {
+      let mut fields = line.split('\t');
+      let range = (fields.next(), fields.next(), fields.next());
+      if let (Some(start), Some(end), Some(asn)) = range {
+        match (start.parse(), end.parse(), asn.parse::<i32>()) {
+          (Ok(start), Ok(end), Ok(asn)) if asn != 0 => ranges.push((start, end, asn)),
+          (Ok(_), Ok(_), Ok(_)) => (),
+          _ => warn!("Skipping invalid ASN database line: {}", line),
+        }
+      }
+    }
+    ranges.sort();
+    AsnDatabase { ranges }
+  }
+
+  /// The autonomous system an address belongs to.
+  pub fn lookup(&self, ip: IpAddr) -> Option<i32> {
+    // The last range starting at or before the address
+    let index = match self.ranges.binary_search_by(|(start, _, _)| start.cmp(&ip)) {
+      Ok(index) => index,
+      Err(0) => return None,
+      Err(index) => index - 1,
+    };
+    let (_, end, asn) = self.ranges[index];
+    if ip <= end {
+      Some(asn)
+    } else {
+      None
+    }
+  }
+}
+
+/// Errors with `ip_blocked` if `ip` is on the blocklist, counting the hit. Only blocks which
+/// apply to posting are checked when `posting`, otherwise all of them are.
+pub fn check(conn: &PgConnection, ip: Option<&str>, posting: bool) -> Result<(), Error> {
+  // Requests which didn't come from a client, and addresses which can't be parsed
+  let ip = match ip.and_then(|ip| ip.parse::<IpAddr>().ok()) {
+    Some(ip) => ip,
+    None => return Ok(()),
+  };
+  let asn = ASN_DATABASE
+    .as_ref()
+    .and_then(|database| database.lookup(ip));
+
+  let blocks = IpBlock::matching(conn, &ip.to_string(), asn, posting)?;
+  if blocks.is_empty() {
+    return Ok(());
+  }
+
+  IpBlock::record_hits(conn, &blocks)?;
+  Err(APIError::err("ip_blocked").into())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_list() {
+    let list = "\
+; Spamhaus DROP List
+192.0.2.0/24 ; SBL123456
+198.51.100.17/28
+2001:db8:85a3::8a2e/48 # documentation
+203.0.113.5
+AS64496
+
+not a network
+10.0.0.0/33
+";
+    let (networks, invalid) = parse_list(list);
+    assert_eq!(
+      vec![
+        Network::Cidr("192.0.2.0/24".into()),
+        Network::Cidr("198.51.100.16/28".into()),
+        Network::Cidr("2001:db8:85a3::/48".into()),
+        Network::Cidr("203.0.113.5/32".into()),
+        Network::Asn(64496),
+      ],
+      networks
+    );
+    assert_eq!(vec!["not a network", "10.0.0.0/33"], invalid);
+  }
+
+  #[test]
+  fn test_asn_lookup() {
+    let database = AsnDatabase::parse(
+      "1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET\n\
+       1.0.1.0\t1.0.3.255\t0\tNone\tNot routed\n\
+       1.0.4.0\t1.0.7.255\t38803\tAU\tGTELECOM-AUSTRALIA\n\
+       2001:db8::\t2001:db8:ffff:ffff:ffff:ffff:ffff:ffff\t64496\tZZ\tDOCUMENTATION\n",
+    );
+
+    let lookup = |ip: &str| database.lookup(ip.parse().unwrap());
+    assert_eq!(Some(13335), lookup("1.0.0.1"));
+    assert_eq!(None, lookup("1.0.2.1"));
+    assert_eq!(Some(38803), lookup("1.0.7.255"));
+    assert_eq!(None, lookup("1.0.8.0"));
+    assert_eq!(None, lookup("0.255.255.255"));
+    assert_eq!(Some(64496), lookup("2001:db8::1"));
+  }
+}
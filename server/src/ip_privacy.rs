@@ -0,0 +1,102 @@
+//! What's kept of user IPs, according to the `ip_privacy` settings.
+//!
+//! IPs are only held in memory, by the websocket server's rate limiting, and are otherwise only
+//! written to the logs. Rate limiting needs the whole address, so only the logs are anonymized.
+//! The one thing stored is the network an api token was last used from, for its user to tell
+//! their sessions apart, which never has the whole address whatever the settings.
+
+use crate::settings::{IpAnonymization, Settings};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+
+/// How an IP shows up in the logs.
+pub fn for_log(ip: &str) -> String {
+  let config = Settings::get().ip_privacy;
+  if config.log_ips {
+    anonymize(ip, config.anonymize, &Settings::get().jwt_secret)
+  } else {
+    "[ip not logged]".to_string()
+  }
+}
+
+/// The network of an IP that's kept with the api token it used, or `None` if it isn't one.
+pub fn network(ip: &str) -> Option<String> {
+  if ip.parse::<IpAddr>().is_err() {
+    return None;
+  }
+  Some(anonymize(ip, IpAnonymization::Truncate, ""))
+}
+
+/// Anonymizes an IP, salting hashes with `salt` so that they can't be reversed by hashing every
+/// address.
+pub fn anonymize(ip: &str, anonymization: IpAnonymization, salt: &str) -> String {
+  match anonymization {
+    IpAnonymization::None => ip.to_string(),
+    IpAnonymization::Truncate => match ip.parse::<IpAddr>() {
+      Ok(IpAddr::V4(v4)) => {
+        let o = v4.octets();
+        format!("{}.{}.{}.0/24", o[0], o[1], o[2])
+      }
+      Ok(IpAddr::V6(v6)) => {
+        let s = v6.segments();
+        format!("{:x}:{:x}:{:x}::/48", s[0], s[1], s[2])
+      }
+      // Not an address, so nothing to keep of it
+      Err(_) => "[invalid ip]".to_string(),
+    },
+    IpAnonymization::Hash => {
+      let mut hasher = Sha256::new();
+      hasher.input(salt.as_bytes());
+      hasher.input(ip.as_bytes());
+      let hash = hasher.result();
+      hash[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_anonymize() {
+    assert_eq!(
+      "203.0.113.7",
+      anonymize("203.0.113.7", IpAnonymization::None, "salt")
+    );
+    assert_eq!(
+      "203.0.113.0/24",
+      anonymize("203.0.113.7", IpAnonymization::Truncate, "salt")
+    );
+    assert_eq!(
+      "2001:db8:85a3::/48",
+      anonymize(
+        "2001:db8:85a3::8a2e:370:7334",
+        IpAnonymization::Truncate,
+        "salt"
+      )
+    );
+
+    let hashed = anonymize("203.0.113.7", IpAnonymization::Hash, "salt");
+    assert_eq!(16, hashed.len());
+    assert!(!hashed.contains("203"));
+    assert_eq!(
+      hashed,
+      anonymize("203.0.113.7", IpAnonymization::Hash, "salt")
+    );
+    assert_ne!(
+      hashed,
+      anonymize("203.0.113.8", IpAnonymization::Hash, "salt")
+    );
+    assert_ne!(
+      hashed,
+      anonymize("203.0.113.7", IpAnonymization::Hash, "other salt")
+    );
+  }
+
+  #[test]
+  fn test_network() {
+    assert_eq!(Some("203.0.113.0/24".into()), network("203.0.113.7"));
+    assert_eq!(None, network("unknown"));
+  }
+}
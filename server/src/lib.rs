@@ -24,12 +24,20 @@ pub extern crate serde_json;
 pub extern crate sha2;
 pub extern crate strum;
 
+pub mod admin;
 pub mod api;
 pub mod apub;
+pub mod avatar;
 pub mod db;
+pub mod events;
+pub mod ip_blocklist;
+pub mod ip_privacy;
+pub mod media;
+pub mod post_views;
 pub mod routes;
 pub mod schema;
 pub mod settings;
+pub mod translation;
 pub mod version;
 pub mod websocket;
 
@@ -102,6 +110,70 @@ pub fn extract_usernames(test: &str) -> Vec<&str> {
   matches.iter().map(|t| &t[3..]).collect()
 }
 
+/// The readable part of a url, like `my-post-title`: lowercased, with runs of anything but letters
+/// and digits turned into dashes, and cut down to `max_length` characters at a word boundary if
+/// there is one.
+pub fn slugify(text: &str, max_length: usize) -> String {
+  let mut slug = String::new();
+  for c in text.chars() {
+    if c.is_alphanumeric() {
+      slug.extend(c.to_lowercase());
+    } else if !slug.is_empty() && !slug.ends_with('-') {
+      slug.push('-');
+    }
+  }
+
+  if slug.chars().count() > max_length {
+    slug = slug.chars().take(max_length + 1).collect();
+    let cut = match slug.rfind('-') {
+      Some(dash) if dash > 0 => dash,
+      _ => slug
+        .char_indices()
+        .nth(max_length)
+        .map_or(slug.len(), |(i, _)| i),
+    };
+    slug.truncate(cut);
+  }
+
+  slug.trim_end_matches('-').to_string()
+}
+
+/// A plain text preview of markdown, for listings: rendered, without images, link urls or
+/// formatting, and cut down to `max_length` characters at a word boundary. `None` if there's no
+/// text left.
+pub fn excerpt(markdown: &str, max_length: usize) -> Option<String> {
+  let html = markdown_to_html(markdown);
+  let html = HTML_BLOCK_END_REGEX.replace_all(&html, " ");
+  let html = HTML_TAG_REGEX.replace_all(&html, "");
+  let text = htmlescape::decode_html(&html).unwrap_or_else(|_| html.to_string());
+  let text = BARE_URL_REGEX.replace_all(&text, "");
+  let mut text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+  if text.chars().count() > max_length {
+    let end = text
+      .char_indices()
+      .nth(max_length)
+      .map_or(text.len(), |(i, _)| i);
+    let cut = match text[..end].rfind(' ') {
+      Some(space) if space > 0 => space,
+      _ => end,
+    };
+    text.truncate(cut);
+    text.push('…');
+  }
+
+  if text.is_empty() {
+    None
+  } else {
+    Some(text)
+  }
+}
+
+/// Whether a url looks like it's for an image, going by its extension.
+pub fn is_image_url(url: &str) -> bool {
+  IMAGE_URL_REGEX.is_match(url)
+}
+
 pub fn generate_random_string() -> String {
   thread_rng().sample_iter(&Alphanumeric).take(30).collect()
 }
@@ -226,7 +298,10 @@ pub fn markdown_to_html(text: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-  use crate::{extract_usernames, is_email_regex, remove_slurs, slur_check, slurs_vec_to_str};
+  use crate::{
+    excerpt, extract_usernames, is_email_regex, is_image_url, remove_slurs, slugify, slur_check,
+    slurs_vec_to_str,
+  };
 
   #[test]
   fn test_email() {
@@ -269,6 +344,44 @@ mod tests {
     assert_eq!(usernames, expected);
   }
 
+  #[test]
+  fn test_slugify() {
+    assert_eq!("my-post-title", slugify("My post title!", 60));
+    assert_eq!("what-s-new-in-0-6", slugify("  What's new in 0.6?", 60));
+    assert_eq!("über-straße", slugify("Über -- Straße", 60));
+    assert_eq!("a-long", slugify("A long post title", 8));
+    assert_eq!("abcdefgh", slugify("Abcdefghijkl", 8));
+    assert_eq!("", slugify("???", 60));
+  }
+
+  #[test]
+  fn test_excerpt() {
+    assert_eq!(
+      Some("A title Some bold text and a link. Next paragraph".to_string()),
+      excerpt(
+        "# A title\n\nSome **bold** text ![a cat](https://example.com/cat.png)and [a link](https://example.com).\n\nNext paragraph https://example.com/bare",
+        100
+      )
+    );
+    assert_eq!(
+      Some("Fish & chips".to_string()),
+      excerpt("Fish & chips", 100)
+    );
+    assert_eq!(Some("A long…".to_string()), excerpt("A long post body", 8));
+    assert_eq!(
+      None,
+      excerpt("![only an image](https://example.com/a.png)", 100)
+    );
+  }
+
+  #[test]
+  fn test_is_image_url() {
+    assert!(is_image_url("https://example.com/pictshare/cat.png"));
+    assert!(is_image_url("https://example.com/Cat.JPEG?size=large"));
+    assert!(!is_image_url("https://example.com/cat.png/comments"));
+    assert!(!is_image_url("https://example.com/article"));
+  }
+
   // These helped with testing
   // #[test]
   // fn test_iframely() {
@@ -295,4 +408,8 @@ lazy_static! {
   static ref EMAIL_REGEX: Regex = Regex::new(r"^[a-zA-Z0-9.!#$%&’*+/=?^_`{|}~-]+@[a-zA-Z0-9-]+(?:\.[a-zA-Z0-9-]+)*$").unwrap();
   static ref SLUR_REGEX: Regex = RegexBuilder::new(r"(fag(g|got|tard)?|maricos?|cock\s?sucker(s|ing)?|nig(\b|g?(a|er)?(s|z)?)\b|dindu(s?)|mudslime?s?|kikes?|mongoloids?|towel\s*heads?|\bspi(c|k)s?\b|\bchinks?|niglets?|beaners?|\bnips?\b|\bcoons?\b|jungle\s*bunn(y|ies?)|jigg?aboo?s?|\bpakis?\b|rag\s*heads?|gooks?|cunts?|bitch(es|ing|y)?|puss(y|ies?)|twats?|feminazis?|whor(es?|ing)|\bslut(s|t?y)?|\btrann?(y|ies?)|ladyboy(s?)|\b(b|re|r)tard(ed)?s?)").case_insensitive(true).build().unwrap();
   static ref USERNAME_MATCHES_REGEX: Regex = Regex::new(r"/u/[a-zA-Z][0-9a-zA-Z_]*").unwrap();
+  static ref HTML_BLOCK_END_REGEX: Regex = Regex::new(r"(?i)</(p|h[1-6]|li|blockquote|pre|td|th)>|<(br|hr)\s*/?>").unwrap();
+  static ref HTML_TAG_REGEX: Regex = Regex::new(r"<!--.*?-->|<[^>]*>").unwrap();
+  static ref BARE_URL_REGEX: Regex = Regex::new(r"https?://\S+").unwrap();
+  static ref IMAGE_URL_REGEX: Regex = Regex::new(r"(?i)\.(png|jpe?g|gif|webp|svg)([?#].*)?$").unwrap();
 }
@@ -6,10 +6,14 @@ use actix::prelude::*;
 use actix_web::*;
 use diesel::r2d2::{ConnectionManager, Pool};
 use diesel::PgConnection;
-use lemmy_server::routes::{api, federation, feeds, index, nodeinfo, webfinger, websocket};
-use lemmy_server::settings::Settings;
+use lemmy_server::admin;
+use lemmy_server::routes::{
+  api, avatar, federation, feeds, html, index, nodeinfo, oauth, robots, sitemap, webfinger,
+  websocket,
+};
+use lemmy_server::settings::{IpAnonymization, Settings};
 use lemmy_server::websocket::server::*;
-use std::io;
+use std::{env, io, process};
 
 embed_migrations!();
 
@@ -29,6 +33,16 @@ async fn main() -> io::Result<()> {
   let conn = pool.get().unwrap();
   embedded_migrations::run(&conn).unwrap();
 
+  // `lemmy_server admin <command>` runs a maintenance command instead of the server
+  let args: Vec<String> = env::args().collect();
+  if args.get(1).map(String::as_str) == Some("admin") {
+    if let Err(e) = admin::run(&conn, &args[2..]) {
+      eprintln!("{}", e);
+      process::exit(1);
+    }
+    return Ok(());
+  }
+
   // Set up websocket server
   let server = ChatServer::startup(pool.clone()).start();
 
@@ -41,15 +55,21 @@ async fn main() -> io::Result<()> {
   HttpServer::new(move || {
     let settings = Settings::get();
     App::new()
-      .wrap(middleware::Logger::default())
+      .wrap(access_logger(&settings))
+      .wrap(middleware::Compress::default())
       .data(pool.clone())
       .data(server.clone())
       // The routes
       .configure(api::config)
+      .configure(avatar::config)
       .configure(federation::config)
       .configure(feeds::config)
+      .configure(html::config)
       .configure(index::config)
       .configure(nodeinfo::config)
+      .configure(oauth::config)
+      .configure(robots::config)
+      .configure(sitemap::config)
       .configure(webfinger::config)
       .configure(websocket::config)
       // static files
@@ -66,3 +86,12 @@ async fn main() -> io::Result<()> {
   .run()
   .await
 }
+
+/// The default access log, minus the client IP unless IPs are logged in full.
+fn access_logger(settings: &Settings) -> middleware::Logger {
+  if settings.ip_privacy.log_ips && settings.ip_privacy.anonymize == IpAnonymization::None {
+    middleware::Logger::default()
+  } else {
+    middleware::Logger::new(r#""%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
+  }
+}
@@ -0,0 +1,221 @@
+//! Uploaded images, which posts use for their link, gallery and thumbnail.
+//!
+//! Instead of the uploaded original, posts link to a variant that pictshare resizes to fit
+//! the configured dimensions and re-encodes, as webp if configured. Pictshare makes the variant
+//! the first time it's asked for, and re-encoding leaves out the exif metadata. The served url
+//! is stored in `media_variant`, with the original's if the instance keeps them.
+//!
+//! Since a location in the metadata of a photo can give away where someone lives, a variant is
+//! fetched and checked for metadata before it's first used, and isn't if it still has any. Images
+//! from other sites, like the thumbnails of links, go through pictshare and the same check.
+
+use crate::db::media_variant::{MediaVariant, MediaVariantForm};
+use crate::settings::{ImageConfig, Settings};
+use diesel::PgConnection;
+use failure::Error;
+use isahc::http::header::CONTENT_LENGTH;
+use log::error;
+use std::io::Read;
+
+/// The file name of an image uploaded here, if the url is one.
+pub fn uploaded_file<'a>(url: &'a str, hostname: &str) -> Option<&'a str> {
+  let prefix = format!("https://{}/pictshare/", hostname);
+  if !url.starts_with(&prefix) {
+    return None;
+  }
+  let file = url[prefix.len()..].rsplit('/').next()?;
+  if file.is_empty() || file.contains(|c| c == '?' || c == '#') {
+    None
+  } else {
+    Some(file)
+  }
+}
+
+/// The path of the resized and re-encoded variant of an uploaded file, in pictshare.
+fn variant_path(file: &str, config: &ImageConfig) -> String {
+  format!(
+    "{}x{}/{}{}",
+    config.max_width,
+    config.max_height,
+    if config.convert_to_webp { "webp/" } else { "" },
+    file
+  )
+}
+
+/// The url of the resized and re-encoded variant of an uploaded file.
+pub fn variant_url(file: &str, hostname: &str, config: &ImageConfig) -> String {
+  format!(
+    "https://{}/pictshare/{}",
+    hostname,
+    variant_path(file, config)
+  )
+}
+
+/// Whether an uploaded image is bigger than the configured `max_size`. Images that aren't
+/// uploaded here, or whose size can't be found out, aren't.
+pub fn is_too_large(url: &str) -> bool {
+  let settings = Settings::get();
+  let file = match uploaded_file(url, &settings.hostname) {
+    Some(file) => file,
+    None => return false,
+  };
+  let size = isahc::head(format!("http://pictshare/{}", file)).map(|res| {
+    res
+      .headers()
+      .get(CONTENT_LENGTH)
+      .and_then(|length| length.to_str().ok())
+      .and_then(|length| length.parse::<u64>().ok())
+  });
+  match size {
+    Ok(size) => size.map_or(false, |size| size > settings.image.max_size),
+    Err(e) => {
+      error!("pictshare err: {}", e);
+      false
+    }
+  }
+}
+
+/// The url that a post serves for an image. Uploaded images are swapped for their variant, which is
+/// checked for metadata and recorded, and other urls, or the url of a variant, are kept.
+pub fn ingest(conn: &PgConnection, url: &str) -> Result<String, Error> {
+  let settings = Settings::get();
+  let file = match uploaded_file(url, &settings.hostname) {
+    Some(file) => file,
+    None => return Ok(url.to_owned()),
+  };
+  if MediaVariant::read_for_url(conn, url).is_ok() {
+    return Ok(url.to_owned());
+  }
+
+  let path = variant_path(file, &settings.image);
+  let mut res = isahc::get(format!("http://pictshare/{}", path))?;
+  if !res.status().is_success() {
+    return Err(format_err!("pictshare gave {} for {}", res.status(), path));
+  }
+  let mut image = Vec::new();
+  res.body_mut().read_to_end(&mut image)?;
+  if has_metadata(&image) {
+    return Err(format_err!("{} still has metadata", path));
+  }
+
+  let form = MediaVariantForm {
+    url: variant_url(file, &settings.hostname, &settings.image),
+    original_url: if settings.image.keep_originals {
+      Some(url.to_owned())
+    } else {
+      None
+    },
+  };
+  Ok(MediaVariant::save(conn, &form)?.url)
+}
+
+/// Whether a jpeg, png or webp image has exif, xmp or iptc metadata, or png text, which can have
+/// the time and place a photo was taken. Other formats, and images that can't be made sense of,
+/// are taken to have none.
+pub fn has_metadata(image: &[u8]) -> bool {
+  if image.starts_with(b"\xff\xd8") {
+    jpeg_has_metadata(image)
+  } else if image.starts_with(b"\x89PNG\r\n\x1a\n") {
+    png_has_metadata(image)
+  } else if image.len() >= 12 && &image[..4] == b"RIFF" && &image[8..12] == b"WEBP" {
+    webp_has_metadata(image)
+  } else {
+    false
+  }
+}
+
+/// Looks through the segments before the image data for APP1 (exif and xmp) and APP13 (iptc).
+fn jpeg_has_metadata(image: &[u8]) -> bool {
+  let mut i = 2;
+  while i + 4 <= image.len() && image[i] == 0xff {
+    let marker = image[i + 1];
+    // Start of scan, the image data follows
+    if marker == 0xda {
+      break;
+    }
+    let length = u16::from_be_bytes([image[i + 2], image[i + 3]]) as usize;
+    if length < 2 {
+      break;
+    }
+    let segment = &image[i + 4..(i + 2 + length).min(image.len())];
+    let is_exif = marker == 0xe1 && segment.starts_with(b"Exif\0");
+    let is_xmp = marker == 0xe1 && segment.starts_with(b"http://ns.adobe.com/xap/");
+    if is_exif || is_xmp || marker == 0xed {
+      return true;
+    }
+    i += 2 + length;
+  }
+  false
+}
+
+fn png_has_metadata(image: &[u8]) -> bool {
+  let mut i = 8;
+  while i + 8 <= image.len() {
+    let length = u32::from_be_bytes([image[i], image[i + 1], image[i + 2], image[i + 3]]) as usize;
+    match &image[i + 4..i + 8] {
+      b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" => return true,
+      b"IEND" => break,
+      _ => {}
+    }
+    i += 12 + length;
+  }
+  false
+}
+
+fn webp_has_metadata(image: &[u8]) -> bool {
+  let mut i = 12;
+  while i + 8 <= image.len() {
+    let length =
+      u32::from_le_bytes([image[i + 4], image[i + 5], image[i + 6], image[i + 7]]) as usize;
+    let fourcc = &image[i..i + 4];
+    if fourcc == b"EXIF" || fourcc == b"XMP " {
+      return true;
+    }
+    // Chunks are padded to an even length
+    i += 8 + length + (length & 1);
+  }
+  false
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_variant_url() {
+    let mut config = ImageConfig {
+      max_width: 1920,
+      max_height: 1080,
+      max_size: 1024,
+      convert_to_webp: true,
+      keep_originals: false,
+    };
+    let file = uploaded_file("https://my_domain/pictshare/abc.jpg", "my_domain").unwrap();
+    assert_eq!(
+      "https://my_domain/pictshare/1920x1080/webp/abc.jpg",
+      variant_url(file, "my_domain", &config)
+    );
+    config.convert_to_webp = false;
+    assert_eq!(
+      "https://my_domain/pictshare/1920x1080/abc.jpg",
+      variant_url(file, "my_domain", &config)
+    );
+
+    assert_eq!(
+      Some("abc.jpg"),
+      uploaded_file("https://my_domain/pictshare/100x100/abc.jpg", "my_domain")
+    );
+    assert_eq!(
+      None,
+      uploaded_file("https://example.com/pictshare/abc.jpg", "my_domain")
+    );
+    assert_eq!(
+      None,
+      uploaded_file("https://my_domain/pictshare/abc.jpg?x=1", "my_domain")
+    );
+    assert_eq!(
+      None,
+      uploaded_file("https://my_domain/pictshare/", "my_domain")
+    );
+  }
+}
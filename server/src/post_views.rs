@@ -0,0 +1,82 @@
+//! Counting how many times posts are seen.
+//!
+//! Views are added up in memory and written a batch at a time by the websocket server, since every
+//! write refreshes the post aggregates. Views by people who aren't logged in are only counted one
+//! in `ANONYMOUS_SAMPLE_RATE` times, as that many views each, so that crawlers and refreshes add
+//! little work. Nothing is written while the site has `track_post_views` off.
+
+use crate::db::post_view_count::PostViewCount;
+use crate::db::site::Site;
+use crate::db::Crud;
+use diesel::{OptionalExtension, PgConnection};
+use failure::Error;
+use rand::{thread_rng, Rng};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// One in how many views by people who aren't logged in are counted
+pub const ANONYMOUS_SAMPLE_RATE: i64 = 10;
+
+lazy_static! {
+  static ref PENDING_VIEWS: Mutex<HashMap<i32, i64>> = Mutex::new(HashMap::new());
+}
+
+/// How many views a view counts for: every one by a logged in user, and a sample of the others.
+pub fn sampled_views<R: Rng>(logged_in: bool, rng: &mut R) -> i64 {
+  if logged_in {
+    1
+  } else if rng.gen_range(0, ANONYMOUS_SAMPLE_RATE) == 0 {
+    ANONYMOUS_SAMPLE_RATE
+  } else {
+    0
+  }
+}
+
+/// Counts a view of a post, which is written with the next batch.
+pub fn record_view(post_id: i32, logged_in: bool) {
+  let views = sampled_views(logged_in, &mut thread_rng());
+  if views > 0 {
+    *PENDING_VIEWS.lock().unwrap().entry(post_id).or_insert(0) += views;
+  }
+}
+
+/// Writes the views counted since the last batch. Gives how many posts' counts were added to.
+pub fn flush_views(conn: &PgConnection) -> Result<usize, Error> {
+  let pending = PENDING_VIEWS
+    .lock()
+    .unwrap()
+    .drain()
+    .map(|(post_id, views)| PostViewCount { post_id, views })
+    .collect::<Vec<_>>();
+  if pending.is_empty() {
+    return Ok(0);
+  }
+  let tracked = Site::read(conn, 1)
+    .optional()?
+    .map_or(false, |site| site.track_post_views);
+  if !tracked {
+    return Ok(0);
+  }
+  Ok(PostViewCount::add(conn, &pending)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_sampled_views() {
+    let mut rng = thread_rng();
+    assert!((0..100).all(|_| sampled_views(true, &mut rng) == 1));
+
+    let anonymous = (0..10_000)
+      .map(|_| sampled_views(false, &mut rng))
+      .collect::<Vec<_>>();
+    assert!(anonymous
+      .iter()
+      .all(|&views| views == 0 || views == ANONYMOUS_SAMPLE_RATE));
+    // Close to the actual number of views, on average
+    let total: i64 = anonymous.iter().sum();
+    assert!(total > 5_000 && total < 15_000, "{} views", total);
+  }
+}
@@ -1,16 +1,154 @@
+use crate::api::api_token::*;
 use crate::api::comment::*;
 use crate::api::community::*;
+use crate::api::oauth::*;
 use crate::api::post::*;
+use crate::api::report::*;
 use crate::api::site::*;
+use crate::api::translation::*;
 use crate::api::user::*;
-use crate::api::{Oper, Perform};
-use actix_web::{web, HttpResponse};
+use crate::api::{etag_matches, APIError, ETag, Oper, Operation, Perform};
+use crate::db::api_token::ApiToken;
+use crate::routes::client_ip;
+use crate::websocket::server::{ChatServer, CheckRateLimit, RateLimitState, RateLimitType};
+use crate::websocket::UserOperation;
+use actix::Addr;
+use actix_web::dev::HttpResponseBuilder;
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
 use diesel::r2d2::{ConnectionManager, Pool};
 use diesel::PgConnection;
 use failure::Error;
+use futures::stream;
 use serde::Serialize;
+use std::sync::Arc;
 
 type DbParam = web::Data<Pool<ConnectionManager<PgConnection>>>;
+type ChatParam = web::Data<Addr<ChatServer>>;
+
+macro_rules! operations {
+  ($($op:ident),* $(,)?) => {
+    $(impl Operation for $op {
+      const OP: UserOperation = UserOperation::$op;
+    })*
+  };
+}
+
+operations!(
+  GetSite,
+  ListCategories,
+  ListLanguages,
+  GetModlog,
+  GetModlogFeed,
+  Search,
+  ResolveObject,
+  PrefetchActors,
+  GetApubId,
+  TranslateContent,
+  CreateCommunity,
+  GetCommunity,
+  EditCommunity,
+  ListCommunities,
+  FollowCommunity,
+  CreatePost,
+  EditPost,
+  GetPost,
+  GetPostEdits,
+  GetPosts,
+  CreatePostLike,
+  SavePost,
+  SaveMediaAltText,
+  CreateComment,
+  EditComment,
+  GetComments,
+  GetCommentContext,
+  GetCommentTree,
+  GetCommentEdits,
+  CreateCommentLike,
+  SaveComment,
+  GetUserDetails,
+  GetUserActivity,
+  GetUserMentions,
+  GetInbox,
+  GetUnreadCount,
+  CreateSavedFolder,
+  EditSavedFolder,
+  DeleteSavedFolder,
+  GetSavedFolders,
+  GetSavedItems,
+  CreateKeywordFilter,
+  DeleteKeywordFilter,
+  GetKeywordFilters,
+  BlockUser,
+  GetBlockedUsers,
+  PinToProfile,
+  ReorderProfilePins,
+  BlockInstance,
+  GetBlockedInstances,
+  GetMyContent,
+  EditUserMention,
+  GetModerationNotices,
+  GetReplies,
+  GetFollowedCommunities,
+  BlockCommunity,
+  GetBlockedCommunities,
+  TransferCommunity,
+  BanFromCommunity,
+  AddModToCommunity,
+  CreateModNote,
+  EditModNote,
+  GetModNotes,
+  CreateScheduledPost,
+  DeleteScheduledPost,
+  GetScheduledPosts,
+  SaveModDigest,
+  GetModDigests,
+  CreateReport,
+  ListReports,
+  ResolveReport,
+  CreateSite,
+  EditSite,
+  TransferSite,
+  GetSiteConfig,
+  SaveSiteConfig,
+  AddAdmin,
+  BanUser,
+  LegalHold,
+  ImportIpBlocks,
+  GetInstanceImpact,
+  ListInboxRejections,
+  GetFederationMetrics,
+  GetOperationMetrics,
+  ListIpBlocks,
+  DeleteIpBlock,
+  ListReservedCommunityNames,
+  ReserveCommunityName,
+  DeleteReservedCommunityName,
+  GetConsistencyReport,
+  RunConsistencyCheck,
+  Login,
+  Register,
+  DeleteAccount,
+  PasswordReset,
+  PasswordChange,
+  MarkAllAsRead,
+  MarkAsRead,
+  SaveUserSettings,
+  CreateApiToken,
+  ListApiTokens,
+  ListMyTokens,
+  DeleteApiToken,
+  SetApiTokenRateLimit,
+  RegisterOAuthClient,
+  ListOAuthClients,
+  DeleteOAuthClient,
+  GetOAuthConsent,
+  AuthorizeOAuthClient,
+  ImpersonateUser,
+  ListImpersonations,
+  RevokeImpersonation,
+);
 
 #[rustfmt::skip]
 pub fn config(cfg: &mut web::ServiceConfig) {
@@ -18,36 +156,82 @@ pub fn config(cfg: &mut web::ServiceConfig) {
     // Site
     .route("/api/v1/site", web::get().to(route_get::<GetSite, GetSiteResponse>))
     .route("/api/v1/categories", web::get().to(route_get::<ListCategories, ListCategoriesResponse>))
+    .route("/api/v1/languages", web::get().to(route_get::<ListLanguages, ListLanguagesResponse>))
     .route("/api/v1/modlog", web::get().to(route_get::<GetModlog, GetModlogResponse>))
+    .route("/api/v1/modlog/feed", web::get().to(route_get::<GetModlogFeed, GetModlogFeedResponse>))
+    .route("/api/v1/modlog/feed/export", web::get().to(route_export::<GetModlogFeed>))
     .route("/api/v1/search", web::get().to(route_get::<Search, SearchResponse>))
+    .route("/api/v1/resolve_object", web::get().to(route_get::<ResolveObject, ResolveObjectResponse>))
+    .route("/api/v1/prefetch_actors", web::post().to(route_post::<PrefetchActors, PrefetchActorsResponse>))
+    .route("/api/v1/apub_id", web::get().to(route_get::<GetApubId, GetApubIdResponse>))
+    .route("/api/v1/translate", web::get().to(route_get::<TranslateContent, TranslateContentResponse>))
     // Community
     .route("/api/v1/community", web::post().to(route_post::<CreateCommunity, CommunityResponse>))
-    .route("/api/v1/community", web::get().to(route_get::<GetCommunity, GetCommunityResponse>))
+    .route("/api/v1/community", web::get().to(route_get_etag::<GetCommunity, GetCommunityResponse>))
     .route("/api/v1/community", web::put().to(route_post::<EditCommunity, CommunityResponse>))
     .route("/api/v1/community/list", web::get().to(route_get::<ListCommunities, ListCommunitiesResponse>))
     .route("/api/v1/community/follow", web::post().to(route_post::<FollowCommunity, CommunityResponse>))
+    .route("/api/v1/community/block", web::post().to(route_post::<BlockCommunity, BlockCommunityResponse>))
+    .route("/api/v1/community/block", web::get().to(route_get::<GetBlockedCommunities, GetBlockedCommunitiesResponse>))
     // Post
     .route("/api/v1/post", web::post().to(route_post::<CreatePost, PostResponse>))
     .route("/api/v1/post", web::put().to(route_post::<EditPost, PostResponse>))
-    .route("/api/v1/post", web::get().to(route_get::<GetPost, GetPostResponse>))
+    .route("/api/v1/post", web::get().to(route_get_etag::<GetPost, GetPostResponse>))
+    .route("/api/v1/post/edits", web::get().to(route_get::<GetPostEdits, GetPostEditsResponse>))
     .route("/api/v1/post/list", web::get().to(route_get::<GetPosts, GetPostsResponse>))
     .route("/api/v1/post/like", web::post().to(route_post::<CreatePostLike, PostResponse>))
     .route("/api/v1/post/save", web::put().to(route_post::<SavePost, PostResponse>))
+    .route("/api/v1/post/alt_text", web::put().to(route_post::<SaveMediaAltText, MediaAltTextResponse>))
     // Comment
     .route("/api/v1/comment", web::post().to(route_post::<CreateComment, CommentResponse>))
     .route("/api/v1/comment", web::put().to(route_post::<EditComment, CommentResponse>))
+    .route("/api/v1/comment/list", web::get().to(route_get_etag::<GetComments, GetCommentsResponse>))
+    .route("/api/v1/comment/context", web::get().to(route_get::<GetCommentContext, GetCommentContextResponse>))
+    .route("/api/v1/comment/tree", web::get().to(route_get::<GetCommentTree, GetCommentTreeResponse>))
+    .route("/api/v1/comment/edits", web::get().to(route_get::<GetCommentEdits, GetCommentEditsResponse>))
     .route("/api/v1/comment/like", web::post().to(route_post::<CreateCommentLike, CommentResponse>))
     .route("/api/v1/comment/save", web::put().to(route_post::<SaveComment, CommentResponse>))
     // User
     .route("/api/v1/user", web::get().to(route_get::<GetUserDetails, GetUserDetailsResponse>))
+    .route("/api/v1/user/activity", web::get().to(route_get::<GetUserActivity, GetUserActivityResponse>))
     .route("/api/v1/user/mention", web::get().to(route_get::<GetUserMentions, GetUserMentionsResponse>))
+    .route("/api/v1/user/inbox", web::get().to(route_get::<GetInbox, GetInboxResponse>))
+    .route("/api/v1/user/unread_count", web::get().to(route_get::<GetUnreadCount, GetUnreadCountResponse>))
+    .route("/api/v1/user/saved_folder", web::get().to(route_get::<GetSavedFolders, GetSavedFoldersResponse>))
+    .route("/api/v1/user/saved_folder", web::post().to(route_post::<CreateSavedFolder, GetSavedFoldersResponse>))
+    .route("/api/v1/user/saved_folder", web::put().to(route_post::<EditSavedFolder, GetSavedFoldersResponse>))
+    .route("/api/v1/user/saved_folder/delete", web::post().to(route_post::<DeleteSavedFolder, GetSavedFoldersResponse>))
+    .route("/api/v1/user/saved", web::get().to(route_get::<GetSavedItems, GetSavedItemsResponse>))
+    .route("/api/v1/user/keyword_filter", web::get().to(route_get::<GetKeywordFilters, GetKeywordFiltersResponse>))
+    .route("/api/v1/user/keyword_filter", web::post().to(route_post::<CreateKeywordFilter, GetKeywordFiltersResponse>))
+    .route("/api/v1/user/keyword_filter/delete", web::post().to(route_post::<DeleteKeywordFilter, GetKeywordFiltersResponse>))
     .route("/api/v1/user/mention", web::put().to(route_post::<EditUserMention, UserMentionResponse>))
+    .route("/api/v1/user/moderation_notices", web::get().to(route_get::<GetModerationNotices, GetModerationNoticesResponse>))
     .route("/api/v1/user/replies", web::get().to(route_get::<GetReplies, GetRepliesResponse>))
     .route("/api/v1/user/followed_communities", web::get().to(route_get::<GetFollowedCommunities, GetFollowedCommunitiesResponse>))
+    .route("/api/v1/user/block", web::post().to(route_post::<BlockUser, BlockUserResponse>))
+    .route("/api/v1/user/block", web::get().to(route_get::<GetBlockedUsers, GetBlockedUsersResponse>))
+    .route("/api/v1/user/pin", web::post().to(route_post::<PinToProfile, ProfilePinsResponse>))
+    .route("/api/v1/user/pin/reorder", web::post().to(route_post::<ReorderProfilePins, ProfilePinsResponse>))
+    .route("/api/v1/user/block_instance", web::post().to(route_post::<BlockInstance, BlockInstanceResponse>))
+    .route("/api/v1/user/block_instance", web::get().to(route_get::<GetBlockedInstances, GetBlockedInstancesResponse>))
+    .route("/api/v1/user/content", web::get().to(route_get::<GetMyContent, GetMyContentResponse>))
+    .route("/api/v1/user/content/export", web::get().to(route_export::<GetMyContent>))
     // Mod actions
     .route("/api/v1/community/transfer", web::post().to(route_post::<TransferCommunity, GetCommunityResponse>))
     .route("/api/v1/community/ban_user", web::post().to(route_post::<BanFromCommunity, BanFromCommunityResponse>))
     .route("/api/v1/community/mod", web::post().to(route_post::<AddModToCommunity, AddModToCommunityResponse>))
+    .route("/api/v1/community/mod_note", web::get().to(route_get::<GetModNotes, GetModNotesResponse>))
+    .route("/api/v1/community/mod_note", web::post().to(route_post::<CreateModNote, ModNoteResponse>))
+    .route("/api/v1/community/mod_note", web::put().to(route_post::<EditModNote, ModNoteResponse>))
+    .route("/api/v1/community/scheduled_post", web::get().to(route_get::<GetScheduledPosts, GetScheduledPostsResponse>))
+    .route("/api/v1/community/scheduled_post", web::post().to(route_post::<CreateScheduledPost, GetScheduledPostsResponse>))
+    .route("/api/v1/community/mod_digest", web::get().to(route_get::<GetModDigests, GetModDigestsResponse>))
+    .route("/api/v1/community/mod_digest", web::post().to(route_post::<SaveModDigest, GetModDigestsResponse>))
+    .route("/api/v1/community/scheduled_post/delete", web::post().to(route_post::<DeleteScheduledPost, GetScheduledPostsResponse>))
+    .route("/api/v1/report", web::post().to(route_post::<CreateReport, ReportResponse>))
+    .route("/api/v1/report/list", web::get().to(route_get::<ListReports, ListReportsResponse>))
+    .route("/api/v1/report/resolve", web::post().to(route_post::<ResolveReport, ResolveReportResponse>))
     // Admin actions
     .route("/api/v1/site", web::post().to(route_post::<CreateSite, SiteResponse>))
     .route("/api/v1/site", web::put().to(route_post::<EditSite, SiteResponse>))
@@ -56,6 +240,23 @@ pub fn config(cfg: &mut web::ServiceConfig) {
     .route("/api/v1/site/config", web::put().to(route_post::<SaveSiteConfig, GetSiteConfigResponse>))
     .route("/api/v1/admin/add", web::post().to(route_post::<AddAdmin, AddAdminResponse>))
     .route("/api/v1/user/ban", web::post().to(route_post::<BanUser, BanUserResponse>))
+    .route("/api/v1/admin/legal_hold", web::post().to(route_post::<LegalHold, LegalHoldResponse>))
+    .route("/api/v1/admin/ip_block/import", web::post().to(route_post::<ImportIpBlocks, ImportIpBlocksResponse>))
+    .route("/api/v1/admin/ip_block/list", web::get().to(route_get::<ListIpBlocks, ListIpBlocksResponse>))
+    .route("/api/v1/admin/ip_block/delete", web::post().to(route_post::<DeleteIpBlock, IpBlockResponse>))
+    .route("/api/v1/admin/reserved_community_name/list", web::get().to(route_get::<ListReservedCommunityNames, ListReservedCommunityNamesResponse>))
+    .route("/api/v1/admin/reserved_community_name", web::post().to(route_post::<ReserveCommunityName, ReservedCommunityNameResponse>))
+    .route("/api/v1/admin/reserved_community_name/delete", web::post().to(route_post::<DeleteReservedCommunityName, ReservedCommunityNameResponse>))
+    .route("/api/v1/admin/consistency_check", web::get().to(route_get::<GetConsistencyReport, ConsistencyReportResponse>))
+    .route("/api/v1/admin/consistency_check", web::post().to(route_post::<RunConsistencyCheck, ConsistencyReportResponse>))
+    .route("/api/v1/admin/instance_impact", web::get().to(route_get::<GetInstanceImpact, GetInstanceImpactResponse>))
+    .route("/api/v1/admin/inbox_rejections", web::get().to(route_get::<ListInboxRejections, ListInboxRejectionsResponse>))
+    .route("/api/v1/admin/federation_metrics", web::get().to(route_get::<GetFederationMetrics, GetFederationMetricsResponse>))
+    .route("/api/v1/admin/operation_metrics", web::get().to(route_get::<GetOperationMetrics, GetOperationMetricsResponse>))
+    .route("/api/v1/admin/api_token/rate_limit", web::post().to(route_post::<SetApiTokenRateLimit, ApiTokenResponse>))
+    .route("/api/v1/admin/impersonate", web::post().to(route_post::<ImpersonateUser, CreateApiTokenResponse>))
+    .route("/api/v1/admin/impersonate/list", web::get().to(route_get::<ListImpersonations, ListImpersonationsResponse>))
+    .route("/api/v1/admin/impersonate/revoke", web::post().to(route_post::<RevokeImpersonation, ListImpersonationsResponse>))
     // User account actions
     .route("/api/v1/user/login", web::post().to(route_post::<Login, LoginResponse>))
     .route("/api/v1/user/register", web::post().to(route_post::<Register, LoginResponse>))
@@ -63,11 +264,29 @@ pub fn config(cfg: &mut web::ServiceConfig) {
     .route("/api/v1/user/password_reset", web::post().to(route_post::<PasswordReset, PasswordResetResponse>))
     .route("/api/v1/user/password_change", web::post().to(route_post::<PasswordChange, LoginResponse>))
     .route("/api/v1/user/mark_all_as_read", web::post().to(route_post::<MarkAllAsRead, GetRepliesResponse>))
-    .route("/api/v1/user/save_user_settings", web::put().to(route_post::<SaveUserSettings, LoginResponse>));
+    .route("/api/v1/user/mark_as_read", web::post().to(route_post::<MarkAsRead, MarkAsReadResponse>))
+    .route("/api/v1/user/save_user_settings", web::put().to(route_post::<SaveUserSettings, LoginResponse>))
+    // Api tokens
+    .route("/api/v1/user/api_token", web::post().to(route_post::<CreateApiToken, CreateApiTokenResponse>))
+    .route("/api/v1/user/api_token/list", web::get().to(route_get::<ListApiTokens, ListApiTokensResponse>))
+    .route("/api/v1/user/api_token/mine", web::get().to(route_get::<ListMyTokens, ListMyTokensResponse>))
+    .route("/api/v1/user/api_token/delete", web::post().to(route_post::<DeleteApiToken, ListApiTokensResponse>))
+    // Oauth apps
+    .route("/api/v1/oauth/client", web::post().to(route_post::<RegisterOAuthClient, RegisterOAuthClientResponse>))
+    .route("/api/v1/oauth/client/list", web::get().to(route_get::<ListOAuthClients, ListOAuthClientsResponse>))
+    .route("/api/v1/oauth/client/delete", web::post().to(route_post::<DeleteOAuthClient, ListOAuthClientsResponse>))
+    .route("/api/v1/oauth/consent", web::get().to(route_get::<GetOAuthConsent, GetOAuthConsentResponse>))
+    .route("/api/v1/oauth/authorize", web::post().to(route_post::<AuthorizeOAuthClient, AuthorizeOAuthClientResponse>));
 }
 
-fn perform<Request, Response>(data: Request, db: DbParam) -> Result<HttpResponse, Error>
+async fn perform<Request, Response>(
+  data: Request,
+  ip: String,
+  db: DbParam,
+  chat: ChatParam,
+) -> Result<HttpResponse, Error>
 where
+  Request: Serialize + Operation,
   Response: Serialize,
   Oper<Request>: Perform<Response>,
 {
@@ -75,31 +294,292 @@ where
     Ok(c) => c,
     Err(e) => return Err(format_err!("{}", e)),
   };
-  let oper: Oper<Request> = Oper::new(data);
-  let response = oper.perform(&conn);
-  Ok(HttpResponse::Ok().json(response?))
+  let api_token = check_api_token(
+    &conn,
+    &Request::OP,
+    &serde_json::to_value(&data)?,
+    Some(ip.as_str()),
+  )?;
+  let api_token = api_token.as_ref();
+  let rate_limit = match check_rate_limit(Request::OP, &ip, api_token, &chat).await? {
+    Ok(rate_limit) => rate_limit,
+    Err(limited) => return Ok(limited),
+  };
+  // Like over websocket, registering, creating a community and posting are checked against their
+  // own bucket too, which they only take from once they've succeeded.
+  let op_rate_limit = RateLimitType::of(&Request::OP);
+  if let Some(type_) = op_rate_limit {
+    let checked = take_rate_limit(Request::OP, type_, &ip, api_token, true, &chat).await?;
+    if let Err(rate_limit) = checked {
+      return Ok(too_many_requests(&rate_limit));
+    }
+  }
+  let oper: Oper<Request> = Oper::new(data).with_ip(ip.to_owned());
+  let response = oper.perform(&conn)?;
+  let rate_limit = match op_rate_limit {
+    Some(type_) => take_rate_limit(Request::OP, type_, &ip, api_token, false, &chat)
+      .await?
+      .unwrap_or_else(|rate_limit| rate_limit),
+    None => rate_limit,
+  };
+  Ok(
+    rate_limit_headers(&mut HttpResponse::Ok(), &rate_limit)
+      .content_type("application/json")
+      .body(to_json_body(&response)?),
+  )
+}
+
+/// Checks the message rate limit of a request's IP, which http requests share with its websocket
+/// sessions. Gives what's left of it, or the `429 Too Many Requests` response when nothing is.
+async fn check_rate_limit(
+  op: UserOperation,
+  ip: &str,
+  api_token: Option<&ApiToken>,
+  chat: &ChatParam,
+) -> Result<Result<RateLimitState, HttpResponse>, Error> {
+  let checked = take_rate_limit(op, RateLimitType::Message, ip, api_token, false, chat).await?;
+  Ok(checked.map_err(|rate_limit| too_many_requests(&rate_limit)))
+}
+
+/// Takes from one of the rate limit buckets of a request's IP, or only checks it could.
+async fn take_rate_limit(
+  op: UserOperation,
+  type_: RateLimitType,
+  ip: &str,
+  api_token: Option<&ApiToken>,
+  check_only: bool,
+  chat: &ChatParam,
+) -> Result<Result<RateLimitState, RateLimitState>, Error> {
+  chat
+    .send(CheckRateLimit {
+      op,
+      type_,
+      ip: ip.to_owned(),
+      rate_limit_multiplier: api_token.map_or(1.0, |token| f64::from(token.rate_limit_multiplier)),
+      check_only,
+    })
+    .await
+    .map_err(|e| format_err!("{}", e))
+}
+
+fn too_many_requests(rate_limit: &RateLimitState) -> HttpResponse {
+  rate_limit_headers(&mut HttpResponse::TooManyRequests(), rate_limit)
+    .header(header::RETRY_AFTER, rate_limit.reset.to_string())
+    .content_type("application/json")
+    .body(APIError::err("rate_limited").to_string())
+}
+
+/// The `RateLimit-*` headers, so clients can slow down before they're limited.
+fn rate_limit_headers<'a>(
+  response: &'a mut HttpResponseBuilder,
+  rate_limit: &RateLimitState,
+) -> &'a mut HttpResponseBuilder {
+  response
+    .header("RateLimit-Limit", rate_limit.limit.to_string())
+    .header("RateLimit-Remaining", rate_limit.remaining.to_string())
+    .header("RateLimit-Reset", rate_limit.reset.to_string())
+}
+
+/// Most responses are a page of posts or comments, so start the buffer big enough that
+/// serializing one doesn't keep reallocating.
+const JSON_BUFFER_CAPACITY: usize = 16 * 1024;
+
+pub fn to_json_body<Response: Serialize>(response: &Response) -> Result<Vec<u8>, Error> {
+  let mut body = Vec::with_capacity(JSON_BUFFER_CAPACITY);
+  serde_json::to_writer(&mut body, response)?;
+  Ok(body)
 }
 
 async fn route_get<Data, Response>(
+  req: HttpRequest,
   data: web::Query<Data>,
   db: DbParam,
+  chat: ChatParam,
 ) -> Result<HttpResponse, Error>
 where
-  Data: Serialize,
+  Data: Serialize + Operation,
   Response: Serialize,
   Oper<Data>: Perform<Response>,
 {
-  perform::<Data, Response>(data.0, db)
+  perform::<Data, Response>(data.0, client_ip(&req), db, chat).await
+}
+
+/// Like `route_get`, but answers with `304 Not Modified` when the client's `If-None-Match` already
+/// names the response's entity tag.
+async fn route_get_etag<Data, Response>(
+  req: HttpRequest,
+  data: web::Query<Data>,
+  db: DbParam,
+  chat: ChatParam,
+) -> Result<HttpResponse, Error>
+where
+  Data: Serialize + Operation,
+  Response: Serialize + ETag,
+  Oper<Data>: Perform<Response>,
+{
+  let conn = match db.get() {
+    Ok(c) => c,
+    Err(e) => return Err(format_err!("{}", e)),
+  };
+  let ip = client_ip(&req);
+  let api_token = check_api_token(
+    &conn,
+    &Data::OP,
+    &serde_json::to_value(&data.0)?,
+    Some(ip.as_str()),
+  )?;
+  let rate_limit = match check_rate_limit(Data::OP, &ip, api_token.as_ref(), &chat).await? {
+    Ok(rate_limit) => rate_limit,
+    Err(limited) => return Ok(limited),
+  };
+  let oper: Oper<Data> = Oper::new(data.0);
+  let response = oper.perform(&conn)?;
+  let etag = response.etag();
+
+  let if_none_match = req
+    .headers()
+    .get(header::IF_NONE_MATCH)
+    .and_then(|h| h.to_str().ok());
+
+  match if_none_match {
+    Some(tags) if etag_matches(tags, &etag) => Ok(
+      rate_limit_headers(&mut HttpResponse::NotModified(), &rate_limit)
+        .header(header::ETAG, etag)
+        .finish(),
+    ),
+    _ => Ok(
+      rate_limit_headers(&mut HttpResponse::Ok(), &rate_limit)
+        .header(header::ETAG, etag)
+        .content_type("application/json")
+        .body(to_json_body(&response)?),
+    ),
+  }
 }
 
 async fn route_post<Data, Response>(
+  req: HttpRequest,
   data: web::Json<Data>,
   db: DbParam,
+  chat: ChatParam,
 ) -> Result<HttpResponse, Error>
 where
-  Data: Serialize,
+  Data: Serialize + Operation,
   Response: Serialize,
   Oper<Data>: Perform<Response>,
 {
-  perform::<Data, Response>(data.0, db)
+  perform::<Data, Response>(data.0, client_ip(&req), db, chat).await
+}
+
+/// A request whose result can be downloaded whole, a page at a time.
+trait Export: Serialize + Operation + Send + Sync + 'static {
+  /// The name of the file, without its extension
+  const FILE_NAME: &'static str;
+
+  /// Checks the request can make the export, and gives its format.
+  fn format(&mut self, conn: &PgConnection) -> Result<ExportFormat, Error>;
+
+  /// A page of the file, and whether it was the end.
+  fn chunk(
+    &self,
+    conn: &PgConnection,
+    format: ExportFormat,
+    page: i64,
+  ) -> Result<(Vec<u8>, bool), Error>;
+}
+
+impl Export for GetMyContent {
+  const FILE_NAME: &'static str = "comments";
+
+  fn format(&mut self, _conn: &PgConnection) -> Result<ExportFormat, Error> {
+    self.export_format()
+  }
+
+  fn chunk(
+    &self,
+    conn: &PgConnection,
+    format: ExportFormat,
+    page: i64,
+  ) -> Result<(Vec<u8>, bool), Error> {
+    self.export_page(conn, format, page)
+  }
+}
+
+impl Export for GetModlogFeed {
+  const FILE_NAME: &'static str = "modlog";
+
+  fn format(&mut self, conn: &PgConnection) -> Result<ExportFormat, Error> {
+    self.export_format(conn)
+  }
+
+  fn chunk(
+    &self,
+    conn: &PgConnection,
+    format: ExportFormat,
+    page: i64,
+  ) -> Result<(Vec<u8>, bool), Error> {
+    self.export_page(conn, format, page)
+  }
+}
+
+/// Streams a whole export as a file, a page at a time, so a long history doesn't have to be held
+/// in memory.
+async fn route_export<Data: Export>(
+  req: HttpRequest,
+  data: web::Query<Data>,
+  db: DbParam,
+  chat: ChatParam,
+) -> Result<HttpResponse, Error> {
+  let mut data = data.into_inner();
+  let ip = client_ip(&req);
+  let (api_token, format) = {
+    let conn = match db.get() {
+      Ok(c) => c,
+      Err(e) => return Err(format_err!("{}", e)),
+    };
+    let api_token = check_api_token(
+      &conn,
+      &Data::OP,
+      &serde_json::to_value(&data)?,
+      Some(ip.as_str()),
+    )?;
+    (api_token, data.format(&conn)?)
+  };
+  let rate_limit = match check_rate_limit(Data::OP, &ip, api_token.as_ref(), &chat).await? {
+    Ok(rate_limit) => rate_limit,
+    Err(limited) => return Ok(limited),
+  };
+
+  let data = Arc::new(data);
+  let pages = stream::unfold(Some(1), move |page| {
+    let data = data.clone();
+    let db = db.clone();
+    async move {
+      let page = page?;
+      let chunk = web::block(move || -> Result<(Vec<u8>, bool), Error> {
+        data.chunk(&db.get()?, format, page)
+      })
+      .await;
+      Some(match chunk {
+        Ok((bytes, end)) => (
+          Ok(web::Bytes::from(bytes)),
+          if end { None } else { Some(page + 1) },
+        ),
+        Err(e) => (Err(ErrorInternalServerError(e)), None),
+      })
+    }
+  });
+
+  Ok(
+    rate_limit_headers(&mut HttpResponse::Ok(), &rate_limit)
+      .content_type(format.content_type())
+      .header(
+        header::CONTENT_DISPOSITION,
+        format!(
+          "attachment; filename=\"{}.{}\"",
+          Data::FILE_NAME,
+          format.extension()
+        ),
+      )
+      .streaming(pages),
+  )
 }
@@ -0,0 +1,69 @@
+use crate::api::etag_matches;
+use crate::apub::cache::ApubCache;
+use crate::avatar::{identicon, AVATAR_KINDS};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The avatars of the users on the pages being looked at, which are asked for again and again
+const AVATAR_CACHE_SIZE: usize = 1000;
+
+/// A generated avatar never changes, so browsers can keep it for a week
+const AVATAR_MAX_AGE: u64 = 7 * 24 * 60 * 60;
+
+/// Longer than any user or community name
+const MAX_NAME_LENGTH: usize = 100;
+
+lazy_static! {
+  static ref AVATAR_CACHE: Mutex<ApubCache> = Mutex::new(ApubCache::new(
+    AVATAR_CACHE_SIZE,
+    Duration::from_secs(AVATAR_MAX_AGE)
+  ));
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+  cfg.route("/avatars/{kind}/{name}.svg", web::get().to(get_avatar));
+}
+
+async fn get_avatar(
+  req: HttpRequest,
+  path: web::Path<(String, String)>,
+) -> Result<HttpResponse, actix_web::Error> {
+  let (kind, name) = path.into_inner();
+  if !AVATAR_KINDS.contains(&kind.as_str()) || name.is_empty() || name.len() > MAX_NAME_LENGTH {
+    return Ok(HttpResponse::NotFound().finish());
+  }
+
+  let key = format!("{}/{}", kind, name);
+  let cached = AVATAR_CACHE.lock().unwrap().get(&key);
+  let avatar = match cached {
+    Some(avatar) => avatar,
+    None => AVATAR_CACHE
+      .lock()
+      .unwrap()
+      .insert(&key, identicon(&kind, &name)),
+  };
+
+  let cache_control = format!("public, max-age={}", AVATAR_MAX_AGE);
+  let if_none_match = req
+    .headers()
+    .get(header::IF_NONE_MATCH)
+    .and_then(|h| h.to_str().ok());
+
+  match if_none_match {
+    Some(tags) if etag_matches(tags, &avatar.etag) => Ok(
+      HttpResponse::NotModified()
+        .header(header::ETAG, avatar.etag)
+        .header(header::CACHE_CONTROL, cache_control)
+        .finish(),
+    ),
+    _ => Ok(
+      HttpResponse::Ok()
+        .header(header::ETAG, avatar.etag)
+        .header(header::CACHE_CONTROL, cache_control)
+        .content_type("image/svg+xml")
+        .body(avatar.body),
+    ),
+  }
+}
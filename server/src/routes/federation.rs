@@ -1,4 +1,5 @@
 use crate::apub;
+use crate::Settings;
 use actix_web::web;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
@@ -11,8 +12,42 @@ pub fn config(cfg: &mut web::ServiceConfig) {
       "/federation/c/{community_name}/followers",
       web::get().to(apub::community::get_apub_community_followers),
     )
+    .route(
+      "/federation/c/{community_name}/moderators",
+      web::get().to(apub::community::get_apub_community_moderators),
+    )
+    .route(
+      "/federation/c/{community_name}/featured",
+      web::get().to(apub::community::get_apub_community_featured),
+    )
     .route(
       "/federation/u/{user_name}",
       web::get().to(apub::user::get_apub_user),
+    )
+    .route(
+      "/federation/post/{post_id}",
+      web::get().to(apub::post::get_apub_post),
+    )
+    .route(
+      "/federation/post/{post_id}/{collection}",
+      web::get().to(apub::post::get_apub_post_votes),
+    )
+    .route(
+      "/federation/comment/{comment_id}",
+      web::get().to(apub::comment::get_apub_comment),
+    )
+    .route(
+      "/federation/comment/{comment_id}/{collection}",
+      web::get().to(apub::comment::get_apub_comment_votes),
+    );
+
+  if Settings::get().federation_enabled {
+    cfg.service(
+      web::resource("/federation/inbox")
+        .app_data(web::PayloadConfig::new(
+          Settings::get().federation.inbox.max_payload_size,
+        ))
+        .route(web::post().to(apub::inbox::shared_inbox)),
     );
+  }
 }
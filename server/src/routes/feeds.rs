@@ -1,19 +1,26 @@
 use super::*;
 use crate::db::comment_view::{ReplyQueryBuilder, ReplyView};
 use crate::db::community::Community;
+use crate::db::ids::{CommunityId, UserId};
 use crate::db::post_view::{PostQueryBuilder, PostView};
+use crate::db::site::Site;
 use crate::db::site_view::SiteView;
 use crate::db::user::{Claims, User_};
 use crate::db::user_mention_view::{UserMentionQueryBuilder, UserMentionView};
-use crate::db::{ListingType, SortType};
+use crate::db::{
+  HideScores, ListingQueryBuilder, ListingType, PostAttachmentKind, SortType, ViewerQueryBuilder,
+};
 use crate::{markdown_to_html, Settings};
 use actix_web::{web, HttpResponse, Result};
 use chrono::{DateTime, NaiveDateTime, Utc};
 use diesel::r2d2::{ConnectionManager, Pool};
 use diesel::PgConnection;
 use failure::Error;
-use rss::{CategoryBuilder, ChannelBuilder, GuidBuilder, Item, ItemBuilder};
+use rss::{
+  CategoryBuilder, ChannelBuilder, Enclosure, EnclosureBuilder, GuidBuilder, Item, ItemBuilder,
+};
 use serde::Deserialize;
+use serde_json::Value;
 use std::str::FromStr;
 use strum::ParseError;
 
@@ -40,6 +47,9 @@ async fn get_all_feed(
   db: web::Data<Pool<ConnectionManager<PgConnection>>>,
 ) -> Result<HttpResponse, actix_web::Error> {
   let res = web::block(move || {
+    if Settings::get().private_instance {
+      return Err(format_err!("not_logged_in"));
+    }
     let conn = db.get()?;
     get_feed_all_data(&conn, &get_sort_type(info)?)
   })
@@ -56,11 +66,15 @@ async fn get_all_feed(
 fn get_feed_all_data(conn: &PgConnection, sort_type: &SortType) -> Result<String, failure::Error> {
   let site_view = SiteView::read(&conn)?;
 
-  let posts = PostQueryBuilder::create(&conn)
+  let mut posts = PostQueryBuilder::create(&conn)
     .listing_type(ListingType::All)
     .sort(sort_type)
     .list()?;
 
+  if Site::hides_scores_from(&conn, None)? {
+    posts.hide_scores();
+  }
+
   let items = create_post_items(posts);
 
   let mut channel_builder = ChannelBuilder::default();
@@ -96,6 +110,15 @@ async fn get_feed(
 
     let param = path.1.to_owned();
 
+    // The front page and inbox feeds are the only ones with a login
+    let logged_out = match request_type {
+      RequestType::User | RequestType::Community => true,
+      RequestType::Front | RequestType::Inbox => false,
+    };
+    if logged_out && Settings::get().private_instance {
+      return Err(format_err!("not_logged_in"));
+    }
+
     match request_type {
       RequestType::User => get_feed_user(&conn, &sort_type, param),
       RequestType::Community => get_feed_community(&conn, &sort_type, param),
@@ -129,14 +152,22 @@ fn get_feed_user(
 ) -> Result<ChannelBuilder, Error> {
   let site_view = SiteView::read(&conn)?;
   let user = User_::find_by_username(&conn, &user_name)?;
+  // Feeds are always logged out
+  if user.hide_profile {
+    return Err(format_err!("profile_hidden"));
+  }
   let user_url = user.get_profile_url();
 
-  let posts = PostQueryBuilder::create(&conn)
+  let mut posts = PostQueryBuilder::create(&conn)
     .listing_type(ListingType::All)
     .sort(sort_type)
-    .for_creator_id(user.id)
+    .for_creator_id(UserId(user.id))
     .list()?;
 
+  if Site::hides_scores_from(&conn, None)? {
+    posts.hide_scores();
+  }
+
   let items = create_post_items(posts);
 
   let mut channel_builder = ChannelBuilder::default();
@@ -157,12 +188,16 @@ fn get_feed_community(
   let community = Community::read_from_name(&conn, community_name)?;
   let community_url = community.get_url();
 
-  let posts = PostQueryBuilder::create(&conn)
+  let mut posts = PostQueryBuilder::create(&conn)
     .listing_type(ListingType::All)
     .sort(sort_type)
-    .for_community_id(community.id)
+    .for_community_id(CommunityId(community.id))
     .list()?;
 
+  if Site::hides_scores_from(&conn, None)? {
+    posts.hide_scores();
+  }
+
   let items = create_post_items(posts);
 
   let mut channel_builder = ChannelBuilder::default();
@@ -186,12 +221,16 @@ fn get_feed_front(
   let site_view = SiteView::read(&conn)?;
   let user_id = Claims::decode(&jwt)?.claims.id;
 
-  let posts = PostQueryBuilder::create(&conn)
+  let mut posts = PostQueryBuilder::create(&conn)
     .listing_type(ListingType::Subscribed)
     .sort(sort_type)
-    .my_user_id(user_id)
+    .my_user_id(UserId(user_id))
     .list()?;
 
+  if Site::hides_scores_from(&conn, Some(user_id))? {
+    posts.hide_scores();
+  }
+
   let items = create_post_items(posts);
 
   let mut channel_builder = ChannelBuilder::default();
@@ -334,13 +373,17 @@ fn create_post_items(posts: Vec<PostView>) -> Vec<Item> {
       i.link(url);
     }
 
+    if let Some(enclosure) = audio_enclosure(&p.attachments) {
+      i.enclosure(enclosure);
+    }
+
     // TODO add images
-    let mut description = format!("submitted by <a href=\"{}\">{}</a> to <a href=\"{}\">{}</a><br>{} points | <a href=\"{}\">{} comments</a>",
+    let mut description = format!("submitted by <a href=\"{}\">{}</a> to <a href=\"{}\">{}</a><br>{}<a href=\"{}\">{} comments</a>",
     author_url,
     p.creator_name,
     community_url,
     p.community_name,
-    p.score,
+    p.score.map(|score| format!("{} points | ", score)).unwrap_or_default(),
     post_url,
     p.number_of_comments);
 
@@ -356,3 +399,18 @@ fn create_post_items(posts: Vec<PostView>) -> Vec<Item> {
 
   items
 }
+
+/// The enclosure of a podcast episode, which is the post's first audio. Rss only has one per item,
+/// and its length in bytes isn't known, which podcast apps allow as 0.
+fn audio_enclosure(attachments: &Value) -> Option<Enclosure> {
+  let audio = attachments
+    .as_array()?
+    .iter()
+    .find(|attachment| attachment["kind"] == PostAttachmentKind::Audio as i16)?;
+  EnclosureBuilder::default()
+    .url(audio["url"].as_str()?)
+    .length("0")
+    .mime_type(audio["mime_type"].as_str().unwrap_or("audio/mpeg"))
+    .build()
+    .ok()
+}
@@ -0,0 +1,411 @@
+//! A read only, plain html version of the site under `/html`, for browsers without javascript,
+//! screen readers and crawlers. It's rendered from the same views and query builders as the api.
+
+use crate::db::comment_view::{CommentQueryBuilder, CommentView};
+use crate::db::community::Community;
+use crate::db::community_view::CommunityView;
+use crate::db::ids::{CommunityId, PostId};
+use crate::db::post_view::{PostQueryBuilder, PostView};
+use crate::db::site::Site;
+use crate::db::site_view::SiteView;
+use crate::db::{
+  HideScores, ListingQueryBuilder, ListingType, PostAttachmentKind, PostVisibility, SortType,
+  ViewerQueryBuilder,
+};
+use crate::post_views::record_view;
+use crate::routes::index::escape;
+use crate::{is_image_url, markdown_to_html, Settings};
+use actix_web::{web, HttpResponse};
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use serde::Deserialize;
+use serde_json::Value;
+
+type DbParam = web::Data<Pool<ConnectionManager<PgConnection>>>;
+
+const POSTS_PER_PAGE: i64 = 20;
+
+#[derive(Deserialize)]
+pub struct PageParams {
+  page: Option<i64>,
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+  if !Settings::get().plain_html {
+    return;
+  }
+  cfg
+    .route("/html", web::get().to(front_page))
+    .route("/html/c/{name}", web::get().to(community_page))
+    .route("/html/post/{id}", web::get().to(post_page));
+}
+
+async fn front_page(info: web::Query<PageParams>, db: DbParam) -> HttpResponse {
+  let page = info.page.unwrap_or(1).max(1);
+  html_response(db, move |conn, site_name| {
+    let mut posts = PostQueryBuilder::create(conn)
+      .listing_type(ListingType::All)
+      .sort(&SortType::Hot)
+      .show_nsfw(false)
+      .page(page)
+      .limit(POSTS_PER_PAGE)
+      .list()?;
+    if Site::hides_scores_from(conn, None)? {
+      posts.hide_scores();
+    }
+
+    let body = format!(
+      "<h1>{}</h1>\n{}",
+      escape(site_name),
+      post_list(&posts, "/html", page)
+    );
+    Ok(Some(layout(site_name, site_name, &body)))
+  })
+  .await
+}
+
+async fn community_page(
+  name: web::Path<String>,
+  info: web::Query<PageParams>,
+  db: DbParam,
+) -> HttpResponse {
+  let name = name.into_inner();
+  let page = info.page.unwrap_or(1).max(1);
+  html_response(db, move |conn, site_name| {
+    let community_id = match Community::read_from_name(conn, name) {
+      Ok(community) => community.id,
+      Err(_) => return Ok(None),
+    };
+    let community = CommunityView::read(conn, community_id, None)?;
+    if community.removed || community.deleted || community.nsfw {
+      return Ok(None);
+    }
+
+    let mut posts = PostQueryBuilder::create(conn)
+      .listing_type(ListingType::Community)
+      .for_community_id(CommunityId(community.id))
+      .sort(&SortType::Hot)
+      .show_nsfw(false)
+      .page(page)
+      .limit(POSTS_PER_PAGE)
+      .list()?;
+    if Site::hides_scores_from(conn, None)? {
+      posts.hide_scores();
+    }
+
+    let description = community
+      .description
+      .as_ref()
+      .map(|d| markdown_to_html(d))
+      .unwrap_or_default();
+    let body = format!(
+      "<h1>{}</h1>\n<p>/c/{} · {} subscribers</p>\n{}\n{}",
+      escape(&community.title),
+      escape(&community.name),
+      community.number_of_subscribers,
+      description,
+      post_list(&posts, &format!("/html/c/{}", community.name), page),
+    );
+    Ok(Some(layout(site_name, &community.title, &body)))
+  })
+  .await
+}
+
+async fn post_page(id: web::Path<i32>, db: DbParam) -> HttpResponse {
+  let post_id = id.into_inner();
+  html_response(db, move |conn, site_name| {
+    let mut post = match PostView::read(conn, PostId(post_id), None) {
+      Ok(post) => post,
+      Err(_) => return Ok(None),
+    };
+    if post.removed
+      || post.deleted
+      || post.community_removed
+      || post.community_deleted
+      || post.nsfw
+      || post.community_nsfw
+      || PostVisibility::from_i16(post.visibility) == PostVisibility::FollowersOnly
+    {
+      return Ok(None);
+    }
+    record_view(post_id, false);
+
+    let mut comments = CommentQueryBuilder::create(conn)
+      .for_post_id(PostId(post_id))
+      .sort(&SortType::Hot)
+      .limit(9999)
+      .list()?;
+    if Site::hides_scores_from(conn, None)? {
+      post.hide_scores();
+      comments.hide_scores();
+    }
+
+    let mut body = format!("<article>\n<h1>{}</h1>\n", escape(&post.name));
+    body.push_str(&post_byline(&post));
+    if let Some(url) = &post.url {
+      if is_image_url(url) {
+        body.push_str(&format!(
+          "<p><img src=\"{}\" alt=\"{}\" /></p>\n",
+          escape(url),
+          escape(post.alt_text.as_deref().unwrap_or(""))
+        ));
+      }
+      body.push_str(&format!(
+        "<p><a href=\"{}\">{}</a></p>\n",
+        escape(url),
+        escape(url)
+      ));
+    }
+    body.push_str(&attachment_list(&post.attachments));
+    if let Some(post_body) = &post.body {
+      body.push_str(&markdown_to_html(post_body));
+    }
+    body.push_str("</article>\n");
+    body.push_str(&format!(
+      "<section aria-labelledby=\"comments\">\n<h2 id=\"comments\">{} comments</h2>\n{}</section>",
+      post.number_of_comments,
+      comment_tree(&comments, None)
+    ));
+    Ok(Some(layout(site_name, &post.name, &body)))
+  })
+  .await
+}
+
+/// Renders a page on the blocking thread pool, or a 404 if there's nothing to show. Nothing is
+/// shown on a private instance.
+async fn html_response<F>(db: DbParam, render: F) -> HttpResponse
+where
+  F: FnOnce(&PgConnection, &str) -> Result<Option<String>, failure::Error> + Send + 'static,
+{
+  let res = web::block(move || -> Result<Option<String>, failure::Error> {
+    if Settings::get().private_instance {
+      return Ok(None);
+    }
+    let conn = db.get()?;
+    let site_name = SiteView::read(&conn)
+      .map(|site| site.name)
+      .unwrap_or_else(|_| "Lemmy".into());
+    render(&conn, &site_name)
+  })
+  .await;
+
+  match res {
+    Ok(Some(html)) => HttpResponse::Ok()
+      .content_type("text/html; charset=utf-8")
+      .body(html),
+    Ok(None) => HttpResponse::NotFound().finish(),
+    Err(_) => HttpResponse::InternalServerError().finish(),
+  }
+}
+
+fn layout(site_name: &str, title: &str, body: &str) -> String {
+  format!(
+    "<!DOCTYPE html>\n\
+     <html lang=\"en\">\n\
+     <head>\n\
+     <meta charset=\"utf-8\" />\n\
+     <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\" />\n\
+     <title>{title} - {site_name}</title>\n\
+     </head>\n\
+     <body>\n\
+     <a href=\"#main\">Skip to content</a>\n\
+     <header><nav aria-label=\"Site\"><a href=\"/html\">{site_name}</a> · <a href=\"/\">Full site</a></nav></header>\n\
+     <main id=\"main\">\n{body}\n</main>\n\
+     </body>\n\
+     </html>\n",
+    title = escape(title),
+    site_name = escape(site_name),
+    body = body,
+  )
+}
+
+fn post_byline(post: &PostView) -> String {
+  format!(
+    "<p>{}by {} in <a href=\"/html/c/{}\">/c/{}</a> · <time datetime=\"{}Z\">{}</time></p>\n",
+    post
+      .score
+      .map(|score| format!("{} points · ", score))
+      .unwrap_or_default(),
+    escape(&post.creator_name),
+    escape(&post.community_name),
+    escape(&post.community_name),
+    post.published.format("%Y-%m-%dT%H:%M:%S"),
+    post.published.format("%Y-%m-%d"),
+  )
+}
+
+/// A page of posts, with a link to the next one if this one is full.
+fn post_list(posts: &[PostView], path: &str, page: i64) -> String {
+  if posts.is_empty() {
+    return "<p>No posts.</p>".into();
+  }
+  let items = posts
+    .iter()
+    .map(|post| {
+      format!(
+        "<li><article><h2><a href=\"/html/post/{}\">{}</a></h2>\n{}<p><a href=\"/html/post/{}#comments\">{} comments</a></p></article></li>\n",
+        post.id,
+        escape(&post.name),
+        post_byline(post),
+        post.id,
+        post.number_of_comments,
+      )
+    })
+    .collect::<String>();
+  let mut pages = Vec::new();
+  if page > 1 {
+    pages.push(format!(
+      "<a href=\"{}?page={}\">Previous page</a>",
+      path,
+      page - 1
+    ));
+  }
+  if posts.len() as i64 == POSTS_PER_PAGE {
+    pages.push(format!(
+      "<a href=\"{}?page={}\">Next page</a>",
+      path,
+      page + 1
+    ));
+  }
+  format!(
+    "<ol>\n{}</ol>\n<nav aria-label=\"Pages\">{}</nav>",
+    items,
+    pages.join(" · ")
+  )
+}
+
+/// A post's gallery of images, videos and audio, from its `PostView::attachments`.
+fn attachment_list(attachments: &Value) -> String {
+  let attachments = match attachments.as_array() {
+    Some(attachments) if !attachments.is_empty() => attachments,
+    _ => return String::new(),
+  };
+  let items = attachments
+    .iter()
+    .filter_map(|attachment| {
+      let url = escape(attachment["url"].as_str()?);
+      let name = escape(attachment["name"].as_str().unwrap_or(""));
+      let media = match PostAttachmentKind::from_i16(attachment["kind"].as_i64()? as i16) {
+        PostAttachmentKind::Image => format!("<img src=\"{}\" alt=\"{}\" />", url, name),
+        PostAttachmentKind::Video => format!(
+          "<video src=\"{}\" controls aria-label=\"{}\"></video>",
+          url, name
+        ),
+        PostAttachmentKind::Audio => format!(
+          "<audio src=\"{}\" controls aria-label=\"{}\"></audio>",
+          url, name
+        ),
+      };
+      Some(format!("<li>{}</li>\n", media))
+    })
+    .collect::<String>();
+  format!("<ul aria-label=\"Gallery\">\n{}</ul>\n", items)
+}
+
+/// The replies to `parent_id` as nested lists, in the order they were loaded.
+fn comment_tree(comments: &[CommentView], parent_id: Option<i32>) -> String {
+  let items = comments
+    .iter()
+    .filter(|comment| comment.parent_id == parent_id)
+    .map(|comment| {
+      let content = if comment.removed {
+        "<p>[removed]</p>".into()
+      } else if comment.deleted {
+        "<p>[deleted]</p>".into()
+      } else {
+        markdown_to_html(&comment.content)
+      };
+      format!(
+        "<li><article id=\"comment-{}\"><p>{}{}</p>\n{}{}</article></li>\n",
+        comment.id,
+        escape(&comment.creator_name),
+        comment
+          .score
+          .map(|score| format!(" · {} points", score))
+          .unwrap_or_default(),
+        content,
+        comment_tree(comments, Some(comment.id)),
+      )
+    })
+    .collect::<String>();
+  if items.is_empty() {
+    String::new()
+  } else {
+    format!("<ul>\n{}</ul>\n", items)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::naive_now;
+
+  fn comment(id: i32, parent_id: Option<i32>, content: &str) -> CommentView {
+    CommentView {
+      id,
+      creator_id: 1,
+      post_id: 1,
+      parent_id,
+      content: content.into(),
+      removed: false,
+      read: false,
+      published: naive_now(),
+      updated: None,
+      deleted: false,
+      community_id: 1,
+      community_name: "main".into(),
+      banned: false,
+      banned_from_community: false,
+      creator_name: "<someone>".into(),
+      creator_avatar: None,
+      score: Some(1),
+      upvotes: Some(1),
+      downvotes: Some(0),
+      hot_rank: 0,
+      user_id: None,
+      my_vote: None,
+      subscribed: None,
+      saved: None,
+    }
+  }
+
+  #[test]
+  fn test_comment_tree() {
+    let comments = vec![
+      comment(1, None, "First"),
+      comment(2, Some(1), "A **reply**"),
+      comment(3, None, "Second"),
+    ];
+    let html = comment_tree(&comments, None);
+
+    assert!(html.starts_with("<ul>\n<li><article id=\"comment-1\">"));
+    assert!(html.contains("&lt;someone&gt;"));
+    // The reply is nested inside the first comment
+    let reply = html.find("<strong>reply</strong>").unwrap();
+    assert!(html.find("First").unwrap() < reply);
+    assert!(reply < html.find("Second").unwrap());
+    assert_eq!(2, html.matches("<ul>").count());
+    assert_eq!("", comment_tree(&comments, Some(3)));
+  }
+
+  #[test]
+  fn test_attachment_list() {
+    let html = attachment_list(&serde_json::json!([
+      {"kind": 0, "url": "https://example.com/pictshare/cat.png", "mime_type": null, "name": "A \"cat\""},
+      {"kind": 1, "url": "https://example.com/cat.mp4", "mime_type": null, "name": null},
+      {"kind": 2, "url": "https://example.com/purr.mp3", "mime_type": null, "name": "Purring"},
+    ]));
+    assert!(html
+      .contains("<img src=\"https://example.com/pictshare/cat.png\" alt=\"A &quot;cat&quot;\" />"));
+    assert!(html.contains("<video src=\"https://example.com/cat.mp4\" controls"));
+    assert!(html.contains("<audio src=\"https://example.com/purr.mp3\" controls"));
+    assert_eq!("", attachment_list(&serde_json::json!([])));
+  }
+
+  #[test]
+  fn test_layout() {
+    let html = layout("Example", "A <b>title</b>", "<p>Body</p>");
+    assert!(html.contains("<title>A &lt;b&gt;title&lt;/b&gt; - Example</title>"));
+    assert!(html.contains("<main id=\"main\">\n<p>Body</p>\n</main>"));
+  }
+}
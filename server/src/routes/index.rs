@@ -1,6 +1,23 @@
+use crate::db::community::Community;
+use crate::db::community_view::CommunityView;
+use crate::db::ids::PostId;
+use crate::db::post::Post;
+use crate::db::post_view::PostView;
+use crate::db::site_view::SiteView;
+use crate::db::user::User_;
+use crate::db::user_view::UserView;
+use crate::db::{Crud, PostAttachmentKind, PostVisibility};
 use crate::settings::Settings;
-use actix_files::NamedFile;
-use actix_web::web;
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use std::fs;
+
+type DbParam = web::Data<Pool<ConnectionManager<PgConnection>>>;
+
+/// The longest a description in the meta tags gets, in characters.
+const DESCRIPTION_LENGTH: usize = 200;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
   cfg
@@ -15,20 +32,21 @@ pub fn config(cfg: &mut web::ServiceConfig) {
     .route("/create_private_message", web::get().to(index))
     .route("/communities/page/{page}", web::get().to(index))
     .route("/communities", web::get().to(index))
-    .route("/post/{id}/comment/{id2}", web::get().to(index))
-    .route("/post/{id}", web::get().to(index))
+    .route("/post/{id}/comment/{id2}", web::get().to(post_page))
+    .route("/post/{id}/{slug}", web::get().to(post_page))
+    .route("/post/{id}", web::get().to(post_page))
     .route(
       "/c/{name}/data_type/{data_type}/sort/{sort}/page/{page}",
-      web::get().to(index),
+      web::get().to(community_page),
     )
-    .route("/c/{name}", web::get().to(index))
-    .route("/community/{id}", web::get().to(index))
+    .route("/c/{name}", web::get().to(community_page))
+    .route("/community/{id}", web::get().to(community_page))
     .route(
       "/u/{username}/view/{view}/sort/{sort}/page/{page}",
-      web::get().to(index),
+      web::get().to(user_page),
     )
-    .route("/u/{username}", web::get().to(index))
-    .route("/user/{id}", web::get().to(index))
+    .route("/u/{username}", web::get().to(user_page))
+    .route("/user/{id}", web::get().to(user_page))
     .route("/inbox", web::get().to(index))
     .route("/modlog/community/{community_id}", web::get().to(index))
     .route("/modlog", web::get().to(index))
@@ -43,8 +61,430 @@ pub fn config(cfg: &mut web::ServiceConfig) {
     .route("/password_change/{token}", web::get().to(index));
 }
 
-async fn index() -> Result<NamedFile, actix_web::error::Error> {
-  Ok(NamedFile::open(
-    Settings::get().front_end_dir + "/index.html",
-  )?)
+async fn index(db: DbParam) -> Result<HttpResponse, actix_web::Error> {
+  page_with_meta(db, |_| Ok(PageObject::Unknown)).await
+}
+
+/// What a page is about, for link previews (OpenGraph and Twitter cards) and microformats.
+struct Meta {
+  og_type: &'static str,
+  title: String,
+  description: Option<String>,
+  image: Option<String>,
+  /// The canonical url of the page
+  url: String,
+  /// Microformats markup describing the object, ie an `h-entry` or `h-card`
+  microformat: String,
+}
+
+impl Meta {
+  fn head(&self, site_name: Option<&str>) -> String {
+    let mut tags = vec![
+      format!(r#"<link rel="canonical" href="{}" />"#, escape(&self.url)),
+      property("og:type", self.og_type),
+      property("og:title", &self.title),
+      property("og:url", &self.url),
+      name("twitter:title", &self.title),
+    ];
+    if let Some(site_name) = site_name {
+      tags.push(property("og:site_name", site_name));
+    }
+    if let Some(description) = &self.description {
+      tags.push(property("og:description", description));
+      tags.push(name("twitter:description", description));
+    }
+    match &self.image {
+      Some(image) => {
+        tags.push(property("og:image", image));
+        tags.push(name("twitter:image", image));
+        tags.push(name("twitter:card", "summary_large_image"));
+      }
+      None => tags.push(name("twitter:card", "summary")),
+    }
+    tags.join("\n    ")
+  }
+
+  /// The front end's `index.html`, with the tags and microformats added.
+  fn insert_into(&self, index: &str, site_name: Option<&str>) -> String {
+    let head = format!("    {}\n  </head>", self.head(site_name));
+    let body = format!("<body>\n    <div hidden>{}</div>", self.microformat);
+    index
+      .replacen("</head>", &head, 1)
+      .replacen("<body>", &body, 1)
+  }
+}
+
+fn property(property: &str, content: &str) -> String {
+  format!(
+    r#"<meta property="{}" content="{}" />"#,
+    property,
+    escape(content)
+  )
+}
+
+fn name(name: &str, content: &str) -> String {
+  format!(r#"<meta name="{}" content="{}" />"#, name, escape(content))
+}
+
+pub fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&#39;")
+}
+
+/// Markdown or plain text shortened to a one line description.
+fn description(text: &str) -> Option<String> {
+  let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+  if text.is_empty() {
+    return None;
+  }
+  if text.chars().count() <= DESCRIPTION_LENGTH {
+    return Some(text);
+  }
+  let shortened = text
+    .chars()
+    .take(DESCRIPTION_LENGTH - 1)
+    .collect::<String>();
+  Some(format!("{}…", shortened.trim_end()))
+}
+
+/// The path of a post's page, with its slug if slugs are on and it has one.
+pub fn post_path(post_id: i32, slug: &str) -> String {
+  if Settings::get().post_slug.enabled && !slug.is_empty() {
+    format!("post/{}/{}", post_id, slug)
+  } else {
+    format!("post/{}", post_id)
+  }
+}
+
+fn post_meta(conn: &PgConnection, post_id: i32) -> Result<PageObject, failure::Error> {
+  let post = PostView::read(conn, PostId(post_id), None)?;
+  if post.removed
+    || post.deleted
+    || post.community_removed
+    || post.community_deleted
+    || PostVisibility::from_i16(post.visibility) == PostVisibility::FollowersOnly
+  {
+    return Ok(PageObject::Unknown);
+  }
+
+  let hostname = Settings::get().hostname;
+  let slug = Post::read(conn, post_id)?.slug;
+  let url = format!("https://{}/{}", hostname, post_path(post.id, &slug));
+  let author_url = format!("https://{}/u/{}", hostname, post.creator_name);
+  // Don't put nsfw content in previews
+  let nsfw = post.nsfw || post.community_nsfw;
+  let body = post
+    .body
+    .as_ref()
+    .or_else(|| post.embed_description.as_ref());
+  // A gallery's first image, for posts without a thumbnail
+  let first_image = post
+    .attachments
+    .get(0)
+    .filter(|attachment| attachment["kind"] == PostAttachmentKind::Image as i16)
+    .and_then(|attachment| attachment["url"].as_str())
+    .map(String::from);
+
+  Ok(PageObject::Found(Meta {
+    og_type: "article",
+    title: post.name.to_owned(),
+    description: body.filter(|_| !nsfw).and_then(|b| description(b)),
+    image: post
+      .thumbnail_url
+      .to_owned()
+      .or(first_image)
+      .filter(|_| !nsfw),
+    microformat: format!(
+      r#"<article class="h-entry"><a class="u-url p-name" href="{}">{}</a> <a class="p-author h-card" href="{}">{}</a> <time class="dt-published" datetime="{}Z"></time></article>"#,
+      escape(&url),
+      escape(&post.name),
+      escape(&author_url),
+      escape(&post.creator_name),
+      post.published.format("%Y-%m-%dT%H:%M:%S"),
+    ),
+    url,
+  }))
+}
+
+fn community_meta(conn: &PgConnection, community_id: i32) -> Result<PageObject, failure::Error> {
+  let community = CommunityView::read(conn, community_id, None)?;
+  if community.removed || community.deleted {
+    return Ok(PageObject::Unknown);
+  }
+
+  let url = format!("https://{}/c/{}", Settings::get().hostname, community.name);
+  let title = format!("{} (/c/{})", community.title, community.name);
+
+  Ok(PageObject::Found(Meta {
+    og_type: "website",
+    description: community
+      .description
+      .as_ref()
+      .filter(|_| !community.nsfw)
+      .and_then(|d| description(d)),
+    image: None,
+    microformat: format!(
+      r#"<div class="h-card"><a class="u-url p-name" href="{}">{}</a> <span class="p-nickname">{}</span></div>"#,
+      escape(&url),
+      escape(&community.title),
+      escape(&community.name),
+    ),
+    title,
+    url,
+  }))
+}
+
+fn user_meta(conn: &PgConnection, user_id: i32) -> Result<PageObject, failure::Error> {
+  let user = User_::read(conn, user_id)?;
+  if user.hide_profile {
+    return Ok(PageObject::Hidden);
+  }
+  let user_view = UserView::read(conn, user_id)?;
+
+  let url = format!("https://{}/u/{}", Settings::get().hostname, user.name);
+  let display_name = user.preferred_username.as_ref().unwrap_or(&user.name);
+  let photo = match &user.avatar {
+    Some(avatar) => format!(
+      r#" <img class="u-photo" src="{}" alt="" />"#,
+      escape(avatar)
+    ),
+    None => String::new(),
+  };
+
+  Ok(PageObject::Found(Meta {
+    og_type: "profile",
+    title: format!("{} (/u/{})", display_name, user.name),
+    description: Some(format!(
+      "{} posts, {} comments",
+      user_view.number_of_posts, user_view.number_of_comments
+    )),
+    image: user.avatar.to_owned(),
+    microformat: format!(
+      r#"<div class="h-card"><a class="u-url p-name" href="{}">{}</a> <span class="p-nickname">{}</span>{}</div>"#,
+      escape(&url),
+      escape(display_name),
+      escape(&user.name),
+      photo,
+    ),
+    url,
+  }))
+}
+
+/// What's known about the object a page shows.
+enum PageObject {
+  Found(Meta),
+  /// Only for logged in users, so nothing about it goes in the page, and crawlers are kept off it
+  Hidden,
+  /// No object, or one which couldn't be read
+  Unknown,
+}
+
+/// Serves `index.html`, with the meta tags for the page's object if it can be read. Pages of
+/// missing objects still load, for the front end to show the error.
+///
+/// In the site's noindex mode, and for hidden objects, pages carry an `X-Robots-Tag` too, for the
+/// crawlers which don't read robots.txt.
+async fn page_with_meta<F>(db: DbParam, meta: F) -> Result<HttpResponse, actix_web::Error>
+where
+  F: FnOnce(&PgConnection) -> Result<PageObject, failure::Error> + Send + 'static,
+{
+  let (html, noindex) = web::block(move || -> Result<(String, bool), failure::Error> {
+    let index = fs::read_to_string(Settings::get().front_end_dir + "/index.html")?;
+    let conn = db.get()?;
+    let site = SiteView::read(&conn).ok();
+    let site_name = site.as_ref().map(|site| site.name.as_str());
+    let mut noindex = site.as_ref().map(|site| site.noindex).unwrap_or(false);
+    // Everything on a private instance is hidden from logged out users
+    let object = if Settings::get().private_instance {
+      PageObject::Hidden
+    } else {
+      meta(&conn).unwrap_or(PageObject::Unknown)
+    };
+    let html = match object {
+      PageObject::Found(meta) => meta.insert_into(&index, site_name),
+      PageObject::Hidden => {
+        noindex = true;
+        index
+      }
+      PageObject::Unknown => index,
+    };
+    Ok((html, noindex))
+  })
+  .await
+  .map_err(|_| HttpResponse::InternalServerError())?;
+
+  let mut res = HttpResponse::Ok();
+  if noindex {
+    res.header("X-Robots-Tag", "noindex, nofollow");
+  }
+  Ok(res.content_type("text/html; charset=utf-8").body(html))
+}
+
+/// Whether a post page's url has the post's current slug.
+#[derive(Debug, PartialEq)]
+pub enum SlugMatch {
+  Current,
+  /// A bare id or an old slug, which redirects to the current url
+  Moved(String),
+  /// A slug the post never had
+  Unknown,
+}
+
+/// Read as someone who isn't logged in, so that the posts they can't see, like scheduled ones,
+/// aren't redirected to a url with their title.
+pub fn match_slug(
+  conn: &PgConnection,
+  post_id: i32,
+  slug: Option<&str>,
+) -> Result<SlugMatch, failure::Error> {
+  let post = match PostView::read(conn, PostId(post_id), None) {
+    Ok(post) => post,
+    Err(_e) => return Ok(SlugMatch::Current),
+  };
+  // Removed and followers only posts keep their urls, so as not to give away the title
+  if post.removed
+    || post.deleted
+    || PostVisibility::from_i16(post.visibility) == PostVisibility::FollowersOnly
+    || slug == Some(post.slug.as_str())
+  {
+    return Ok(SlugMatch::Current);
+  }
+  let current = format!("/{}", post_path(post.id, &post.slug));
+
+  match slug {
+    None if post.slug.is_empty() => Ok(SlugMatch::Current),
+    None => Ok(SlugMatch::Moved(current)),
+    Some(slug) if Post::had_slug(conn, post.id, slug)? => Ok(SlugMatch::Moved(current)),
+    Some(_) => Ok(SlugMatch::Unknown),
+  }
+}
+
+async fn post_page(req: HttpRequest, db: DbParam) -> Result<HttpResponse, actix_web::Error> {
+  let post_id = req.match_info().query("id").parse::<i32>().ok();
+  let slug = req.match_info().get("slug").map(String::from);
+  let is_comment_page = req.match_info().get("id2").is_some();
+
+  // Comment links keep the bare post id, and a private instance doesn't give titles away
+  let settings = Settings::get();
+  if let Some(post_id) =
+    post_id.filter(|_| settings.post_slug.enabled && !settings.private_instance && !is_comment_page)
+  {
+    let db = db.clone();
+    let slug_match = web::block(move || -> Result<SlugMatch, failure::Error> {
+      let conn = db.get()?;
+      match_slug(&conn, post_id, slug.as_deref())
+    })
+    .await
+    .unwrap_or(SlugMatch::Current);
+    match slug_match {
+      SlugMatch::Current => (),
+      SlugMatch::Moved(url) => {
+        return Ok(
+          HttpResponse::MovedPermanently()
+            .header(header::LOCATION, url)
+            .finish(),
+        )
+      }
+      SlugMatch::Unknown => return Ok(HttpResponse::NotFound().finish()),
+    }
+  }
+
+  page_with_meta(db, move |conn| match post_id {
+    Some(post_id) => post_meta(conn, post_id),
+    None => Ok(PageObject::Unknown),
+  })
+  .await
+}
+
+async fn community_page(req: HttpRequest, db: DbParam) -> Result<HttpResponse, actix_web::Error> {
+  let community_id = req.match_info().query("id").parse::<i32>().ok();
+  let community_name = req.match_info().query("name").to_owned();
+
+  // The old names of renamed communities redirect to the new one
+  if community_id.is_none() && !Settings::get().private_instance {
+    let db = db.clone();
+    let old_name = community_name.to_owned();
+    let current_name = web::block(move || -> Result<String, failure::Error> {
+      Ok(Community::read_from_name(&db.get()?, old_name)?.name)
+    })
+    .await
+    .ok()
+    .filter(|name| *name != community_name);
+    if let Some(current_name) = current_name {
+      // Keeping the rest of the path, ie the sort and page
+      let prefix = format!("/c/{}", community_name);
+      let rest = if req.path().starts_with(&prefix) {
+        &req.path()[prefix.len()..]
+      } else {
+        ""
+      };
+      let url = format!("/c/{}{}", current_name, rest);
+      return Ok(
+        HttpResponse::MovedPermanently()
+          .header(header::LOCATION, url)
+          .finish(),
+      );
+    }
+  }
+
+  page_with_meta(db, move |conn| {
+    let community_id = match community_id {
+      Some(community_id) => community_id,
+      None => Community::read_from_name(conn, community_name)?.id,
+    };
+    community_meta(conn, community_id)
+  })
+  .await
+}
+
+async fn user_page(req: HttpRequest, db: DbParam) -> Result<HttpResponse, actix_web::Error> {
+  let user_id = req.match_info().query("id").parse::<i32>().ok();
+  let username = req.match_info().query("username").to_owned();
+  page_with_meta(db, move |conn| {
+    let user_id = match user_id {
+      Some(user_id) => user_id,
+      None => User_::read_from_name(conn, username)?.id,
+    };
+    user_meta(conn, user_id)
+  })
+  .await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_insert_meta() {
+    let meta = Meta {
+      og_type: "article",
+      title: "A \"quoted\" <title>".into(),
+      description: description("Some\n\nmarkdown   body"),
+      image: None,
+      url: "https://example.com/post/1".into(),
+      microformat: r#"<article class="h-entry"></article>"#.into(),
+    };
+    let index = "<html><head><title>Lemmy</title></head><body><div id=\"app\"></div></body></html>";
+    let html = meta.insert_into(index, Some("Example"));
+
+    assert!(html.contains(r#"<link rel="canonical" href="https://example.com/post/1" />"#));
+    assert!(
+      html.contains(r#"<meta property="og:title" content="A &quot;quoted&quot; &lt;title&gt;" />"#)
+    );
+    assert!(html.contains(r#"<meta property="og:description" content="Some markdown body" />"#));
+    assert!(html.contains(r#"<meta property="og:site_name" content="Example" />"#));
+    assert!(html.contains(r#"<meta name="twitter:card" content="summary" />"#));
+    assert!(html.contains(r#"<div hidden><article class="h-entry"></article></div>"#));
+    assert!(html.find("og:title").unwrap() < html.find("</head>").unwrap());
+
+    let long = "word ".repeat(100);
+    assert_eq!(
+      DESCRIPTION_LENGTH,
+      description(&long).unwrap().chars().count()
+    );
+  }
 }
@@ -1,7 +1,30 @@
 pub mod api;
+pub mod avatar;
 pub mod federation;
 pub mod feeds;
+pub mod html;
 pub mod index;
 pub mod nodeinfo;
+pub mod oauth;
+pub mod robots;
+pub mod sitemap;
 pub mod webfinger;
 pub mod websocket;
+
+use actix_web::HttpRequest;
+use std::net::{IpAddr, SocketAddr};
+
+/// The client's IP, from the forwarding headers if there are any.
+pub fn client_ip(req: &HttpRequest) -> String {
+  let remote = req
+    .connection_info()
+    .remote()
+    .unwrap_or("127.0.0.1")
+    .to_string();
+  remote
+    .parse::<SocketAddr>()
+    .map(|addr| addr.ip())
+    .or_else(|_| remote.parse::<IpAddr>())
+    .map(|ip| ip.to_string())
+    .unwrap_or(remote)
+}
@@ -15,6 +15,10 @@ pub fn config(cfg: &mut web::ServiceConfig) {
 }
 
 async fn node_info_well_known() -> HttpResponse<Body> {
+  // Instance listings find instances through nodeinfo
+  if Settings::get().private_instance {
+    return HttpResponse::NotFound().finish();
+  }
   let node_info = NodeInfoWellKnown {
     links: NodeInfoWellKnownLinks {
       rel: "http://nodeinfo.diaspora.software/ns/schema/2.0".to_string(),
@@ -28,6 +32,9 @@ async fn node_info(
   db: web::Data<Pool<ConnectionManager<PgConnection>>>,
 ) -> Result<HttpResponse, actix_web::Error> {
   let res = web::block(move || {
+    if Settings::get().private_instance {
+      return Err(format_err!("not_found"));
+    }
     let conn = db.get()?;
     let site_view = match SiteView::read(&conn) {
       Ok(site_view) => site_view,
@@ -0,0 +1,118 @@
+use crate::api::oauth::{exchange_code, revoke_token};
+use crate::api::APIError;
+use actix_web::http::header;
+use actix_web::{web, HttpResponse};
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use serde::{Deserialize, Serialize};
+
+type DbParam = web::Data<Pool<ConnectionManager<PgConnection>>>;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+  cfg
+    .route("/oauth/token", web::post().to(token))
+    .route("/oauth/revoke", web::post().to(revoke));
+}
+
+#[derive(Deserialize)]
+pub struct TokenRequest {
+  grant_type: String,
+  code: String,
+  redirect_uri: String,
+  client_id: String,
+  client_secret: Option<String>,
+  code_verifier: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TokenResponse {
+  access_token: String,
+  token_type: String,
+  scope: String,
+}
+
+#[derive(Deserialize)]
+pub struct RevokeRequest {
+  token: String,
+  client_id: String,
+  client_secret: Option<String>,
+}
+
+/// The oauth2 error response, with the `APIError`'s message as the error code.
+fn oauth_error(e: failure::Error) -> HttpResponse {
+  let error = match e.downcast_ref::<APIError>() {
+    Some(api_error) => api_error.message.to_owned(),
+    None => "server_error".to_string(),
+  };
+  let mut response = match error.as_str() {
+    "invalid_client" => HttpResponse::Unauthorized(),
+    "server_error" => HttpResponse::InternalServerError(),
+    _ => HttpResponse::BadRequest(),
+  };
+  response
+    .header(header::CACHE_CONTROL, "no-store")
+    .json(serde_json::json!({ "error": error }))
+}
+
+async fn token(
+  form: web::Form<TokenRequest>,
+  db: DbParam,
+) -> Result<HttpResponse, actix_web::Error> {
+  let form = form.into_inner();
+  if form.grant_type != "authorization_code" {
+    return Ok(oauth_error(APIError::err("unsupported_grant_type").into()));
+  }
+
+  let res = web::block(move || {
+    let conn = db.get()?;
+    exchange_code(
+      &conn,
+      &form.code,
+      &form.client_id,
+      form.client_secret.as_deref(),
+      &form.redirect_uri,
+      form.code_verifier.as_deref(),
+    )
+  })
+  .await;
+
+  Ok(match res {
+    Ok((access_token, scopes)) => HttpResponse::Ok()
+      .header(header::CACHE_CONTROL, "no-store")
+      .json(TokenResponse {
+        access_token,
+        token_type: "bearer".to_string(),
+        scope: scopes.join(" "),
+      }),
+    Err(actix_web::error::BlockingError::Error(e)) => oauth_error(e),
+    Err(actix_web::error::BlockingError::Canceled) => {
+      oauth_error(APIError::err("server_error").into())
+    }
+  })
+}
+
+/// Revokes a token, per RFC 7009: unknown tokens still get a 200.
+async fn revoke(
+  form: web::Form<RevokeRequest>,
+  db: DbParam,
+) -> Result<HttpResponse, actix_web::Error> {
+  let form = form.into_inner();
+  let res = web::block(move || {
+    let conn = db.get()?;
+    revoke_token(
+      &conn,
+      &form.token,
+      &form.client_id,
+      form.client_secret.as_deref(),
+    )
+  })
+  .await;
+
+  Ok(match res {
+    Ok(()) => HttpResponse::Ok().finish(),
+    Err(actix_web::error::BlockingError::Error(e)) => oauth_error(e),
+    Err(actix_web::error::BlockingError::Canceled) => {
+      oauth_error(APIError::err("server_error").into())
+    }
+  })
+}
@@ -0,0 +1,58 @@
+use crate::db::site_view::SiteView;
+use crate::Settings;
+use actix_web::{web, HttpResponse};
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+
+type DbParam = web::Data<Pool<ConnectionManager<PgConnection>>>;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+  cfg.route("/robots.txt", web::get().to(get_robots_txt));
+}
+
+async fn get_robots_txt(db: DbParam) -> Result<HttpResponse, actix_web::Error> {
+  let robots_txt = web::block(move || -> Result<String, failure::Error> {
+    let conn = db.get()?;
+    let site = SiteView::read(&conn).ok();
+    Ok(robots_txt(site.as_ref(), &Settings::get().hostname))
+  })
+  .await
+  .map_err(|_| HttpResponse::InternalServerError())?;
+
+  Ok(
+    HttpResponse::Ok()
+      .content_type("text/plain; charset=utf-8")
+      .body(robots_txt),
+  )
+}
+
+/// The site's own robots.txt if it has one, otherwise rules which let crawlers index posts and
+/// communities, but not user profiles or the pages only logged in users see.
+fn robots_txt(site: Option<&SiteView>, hostname: &str) -> String {
+  let noindex = site.map(|site| site.noindex).unwrap_or(false);
+  if noindex || Settings::get().private_instance {
+    return "User-agent: *\nDisallow: /\n".to_string();
+  }
+  if let Some(site) = site {
+    if let Some(robots_txt) = &site.robots_txt {
+      return robots_txt.to_owned();
+    }
+  }
+
+  format!(
+    "User-agent: *\n\
+     Allow: /post/\n\
+     Allow: /c/\n\
+     Disallow: /u/\n\
+     Disallow: /user/\n\
+     Disallow: /api/\n\
+     Disallow: /inbox\n\
+     Disallow: /search\n\
+     Disallow: /login\n\
+     Disallow: /create_\n\
+     Disallow: /password_change/\n\
+     \n\
+     Sitemap: https://{}/sitemap.xml\n",
+    hostname
+  )
+}
@@ -0,0 +1,194 @@
+use crate::apub::cache::ApubCache;
+use crate::db::site_view::SiteView;
+use crate::db::sitemap::SitemapEntry;
+use crate::routes::index::{escape, post_path};
+use crate::Settings;
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use std::sync::Mutex;
+use std::time::Duration;
+
+type DbParam = web::Data<Pool<ConnectionManager<PgConnection>>>;
+
+/// The most urls the sitemap protocol allows in one file
+const URLS_PER_SITEMAP: i64 = 50_000;
+
+/// The index and the most requested chunks
+const SITEMAP_CACHE_SIZE: usize = 16;
+
+lazy_static! {
+  static ref SITEMAP_CACHE: Mutex<ApubCache> = Mutex::new(ApubCache::new(
+    SITEMAP_CACHE_SIZE,
+    Duration::from_secs(Settings::get().sitemap.cache_max_age),
+  ));
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+  cfg
+    .route("/sitemap.xml", web::get().to(get_sitemap_index))
+    .route("/sitemap/{page}.xml", web::get().to(get_sitemap));
+}
+
+async fn get_sitemap_index(
+  req: HttpRequest,
+  db: DbParam,
+) -> Result<HttpResponse, actix_web::Error> {
+  cached_sitemap(req.path().to_owned(), db, |conn| {
+    let chunks = chunks(SitemapEntry::count(conn)?);
+    Ok(Some(sitemap_index(&Settings::get().hostname, chunks)))
+  })
+  .await
+}
+
+async fn get_sitemap(
+  req: HttpRequest,
+  page: web::Path<i64>,
+  db: DbParam,
+) -> Result<HttpResponse, actix_web::Error> {
+  let page = page.into_inner();
+  cached_sitemap(req.path().to_owned(), db, move |conn| {
+    if page < 1 || page > chunks(SitemapEntry::count(conn)?) {
+      return Ok(None);
+    }
+    let entries = SitemapEntry::chunk(conn, page - 1, URLS_PER_SITEMAP)?;
+    Ok(Some(urlset(&Settings::get().hostname, &entries)))
+  })
+  .await
+}
+
+/// How many sitemap files `count` entries take. There's always at least one, so that the index
+/// never points nowhere.
+fn chunks(count: i64) -> i64 {
+  ((count + URLS_PER_SITEMAP - 1) / URLS_PER_SITEMAP).max(1)
+}
+
+fn sitemap_index(hostname: &str, chunks: i64) -> String {
+  let sitemaps = (1..=chunks)
+    .map(|page| {
+      format!(
+        "  <sitemap><loc>https://{}/sitemap/{}.xml</loc></sitemap>\n",
+        escape(hostname),
+        page
+      )
+    })
+    .collect::<String>();
+  format!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+     <sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n\
+     {}</sitemapindex>\n",
+    sitemaps
+  )
+}
+
+fn urlset(hostname: &str, entries: &[SitemapEntry]) -> String {
+  let urls = entries
+    .iter()
+    .map(|entry| {
+      let name = entry.name.as_deref().unwrap_or("");
+      let path = match entry.kind.as_str() {
+        "community" => format!("c/{}", name),
+        _ => post_path(entry.id, name),
+      };
+      format!(
+        "  <url><loc>https://{}/{}</loc><lastmod>{}</lastmod></url>\n",
+        escape(hostname),
+        escape(&path),
+        entry.lastmod.format("%Y-%m-%dT%H:%M:%SZ")
+      )
+    })
+    .collect::<String>();
+  format!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+     <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n\
+     {}</urlset>\n",
+    urls
+  )
+}
+
+/// Serves a sitemap file from the cache, only calling `build` on a miss. `build` returning `None`
+/// means a 404, as does the site being in noindex mode or a private instance.
+async fn cached_sitemap<F>(
+  key: String,
+  db: DbParam,
+  build: F,
+) -> Result<HttpResponse, actix_web::Error>
+where
+  F: FnOnce(&PgConnection) -> Result<Option<String>, failure::Error> + Send + 'static,
+{
+  let body = web::block(move || -> Result<Option<String>, failure::Error> {
+    let conn = db.get()?;
+    // No sitemap at all in noindex mode, or for a private instance
+    let noindex = SiteView::read(&conn)
+      .map(|site| site.noindex)
+      .unwrap_or(false);
+    if noindex || Settings::get().private_instance {
+      return Ok(None);
+    }
+    if let Some(cached) = SITEMAP_CACHE.lock().unwrap().get(&key) {
+      return Ok(Some(cached.body));
+    }
+    Ok(build(&conn)?.map(|body| SITEMAP_CACHE.lock().unwrap().insert(&key, body).body))
+  })
+  .await
+  .map_err(|_| HttpResponse::InternalServerError())?;
+
+  match body {
+    Some(body) => Ok(
+      HttpResponse::Ok()
+        .header(
+          header::CACHE_CONTROL,
+          format!("public, max-age={}", Settings::get().sitemap.cache_max_age),
+        )
+        .content_type("application/xml")
+        .body(body),
+    ),
+    None => Ok(HttpResponse::NotFound().finish()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::NaiveDate;
+
+  #[test]
+  fn test_sitemap() {
+    assert_eq!(1, chunks(0));
+    assert_eq!(1, chunks(URLS_PER_SITEMAP));
+    assert_eq!(2, chunks(URLS_PER_SITEMAP + 1));
+
+    let index = sitemap_index("example.com", 2);
+    assert!(index.contains("<loc>https://example.com/sitemap/1.xml</loc>"));
+    assert!(index.contains("<loc>https://example.com/sitemap/2.xml</loc>"));
+
+    let lastmod = NaiveDate::from_ymd(2020, 3, 14).and_hms(12, 4, 31);
+    let entries = vec![
+      SitemapEntry {
+        kind: "post".into(),
+        id: 7,
+        name: Some("".into()),
+        lastmod,
+      },
+      SitemapEntry {
+        kind: "post".into(),
+        id: 8,
+        name: Some("a-post".into()),
+        lastmod,
+      },
+      SitemapEntry {
+        kind: "community".into(),
+        id: 2,
+        name: Some("main".into()),
+        lastmod,
+      },
+    ];
+    let urls = urlset("example.com", &entries);
+    assert!(urls.contains(
+      "<url><loc>https://example.com/post/7</loc><lastmod>2020-03-14T12:04:31Z</lastmod></url>"
+    ));
+    assert!(urls.contains("<loc>https://example.com/post/8/a-post</loc>"));
+    assert!(urls.contains("<loc>https://example.com/c/main</loc>"));
+  }
+}
@@ -1,3 +1,4 @@
+use crate::apub::is_federated;
 use crate::db::community::Community;
 use crate::Settings;
 use actix_web::web;
@@ -26,7 +27,7 @@ pub fn config(cfg: &mut web::ServiceConfig) {
 lazy_static! {
   static ref WEBFINGER_COMMUNITY_REGEX: Regex = Regex::new(&format!(
     "^group:([a-z0-9_]{{3, 20}})@{}$",
-    Settings::get().hostname
+    regex::escape(&Settings::get().actor_domain())
   ))
   .unwrap();
 }
@@ -42,6 +43,9 @@ async fn get_webfinger_response(
   db: web::Data<Pool<ConnectionManager<PgConnection>>>,
 ) -> Result<HttpResponse, actix_web::Error> {
   let res = web::block(move || {
+    if Settings::get().private_instance {
+      return Err(format_err!("not_found"));
+    }
     let conn = db.get()?;
 
     let regex_parsed = WEBFINGER_COMMUNITY_REGEX
@@ -57,18 +61,21 @@ async fn get_webfinger_response(
       None => return Err(format_err!("not_found")),
     };
 
-    // Make sure the requested community exists.
+    // Make sure the requested community exists, and is federated.
     let community = match Community::read_from_name(&conn, community_name.to_string()) {
-      Ok(o) => o,
-      Err(_) => return Err(format_err!("not_found")),
+      Ok(o) if is_federated(&o) => o,
+      _ => return Err(format_err!("not_found")),
     };
 
+    // The page is on this hostname, the actor on the actor domain
     let community_url = community.get_url();
+    let actor_id = community.apub_id();
 
     Ok(json!({
     "subject": info.resource,
     "aliases": [
       community_url,
+      actor_id,
     ],
     "links": [
     {
@@ -79,8 +86,7 @@ async fn get_webfinger_response(
     {
       "rel": "self",
       "type": "application/activity+json",
-      // Yes this is correct, this link doesn't include the `.json` extension
-      "href": community_url
+      "href": actor_id
     }
     // TODO: this also needs to return the subscribe link once that's implemented
     //{
@@ -1,3 +1,4 @@
+use crate::routes::client_ip;
 use crate::websocket::server::*;
 use actix::prelude::*;
 use actix_web::web;
@@ -27,14 +28,7 @@ async fn chat_route(
       cs_addr: chat_server.get_ref().to_owned(),
       id: 0,
       hb: Instant::now(),
-      ip: req
-        .connection_info()
-        .remote()
-        .unwrap_or("127.0.0.1:12345")
-        .split(':')
-        .next()
-        .unwrap_or("127.0.0.1")
-        .to_string(),
+      ip: client_ip(&req),
     },
     &req,
     stream,
@@ -1,3 +1,21 @@
+table! {
+    api_token (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Varchar,
+        scopes -> Array<Text>,
+        rate_limit_multiplier -> Float4,
+        last_used -> Nullable<Timestamp>,
+        published -> Timestamp,
+        oauth_client_id -> Nullable<Int4>,
+        impersonator_id -> Nullable<Int4>,
+        expires -> Nullable<Timestamp>,
+        device_name -> Nullable<Varchar>,
+        client_app -> Nullable<Varchar>,
+        last_ip_network -> Nullable<Text>,
+    }
+}
+
 table! {
     category (id) {
         id -> Int4,
@@ -17,6 +35,18 @@ table! {
         published -> Timestamp,
         updated -> Nullable<Timestamp>,
         deleted -> Bool,
+        generated -> Bool,
+        language_id -> Nullable<Int4>,
+    }
+}
+
+table! {
+    comment_edit (id) {
+        id -> Int4,
+        comment_id -> Int4,
+        editor_id -> Int4,
+        content -> Text,
+        published -> Timestamp,
     }
 }
 
@@ -37,6 +67,7 @@ table! {
         comment_id -> Int4,
         user_id -> Int4,
         published -> Timestamp,
+        folder_id -> Nullable<Int4>,
     }
 }
 
@@ -53,6 +84,40 @@ table! {
         updated -> Nullable<Timestamp>,
         deleted -> Bool,
         nsfw -> Bool,
+        rules -> Nullable<Text>,
+        require_rules_ack -> Bool,
+        require_alt_text -> Bool,
+        local_only -> Bool,
+        hidden -> Bool,
+        actor_id -> Nullable<Text>,
+        report_auto_remove_threshold -> Nullable<Int4>,
+    }
+}
+
+table! {
+    community_alias (id) {
+        id -> Int4,
+        community_id -> Int4,
+        name -> Varchar,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    community_block (id) {
+        id -> Int4,
+        user_id -> Int4,
+        community_id -> Int4,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    community_growth (community_id) {
+        community_id -> Int4,
+        subscriber_milestone -> Int8,
+        activity_milestone -> Int8,
+        spike_notified -> Nullable<Timestamp>,
     }
 }
 
@@ -74,12 +139,86 @@ table! {
     }
 }
 
+table! {
+    community_rules_ack (id) {
+        id -> Int4,
+        community_id -> Int4,
+        user_id -> Int4,
+        published -> Timestamp,
+    }
+}
+
 table! {
     community_user_ban (id) {
         id -> Int4,
         community_id -> Int4,
         user_id -> Int4,
         published -> Timestamp,
+        expires -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    consistency_check (id) {
+        id -> Int4,
+        name -> Text,
+        found -> Int8,
+        repaired -> Bool,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    featured (id) {
+        id -> Int4,
+        user_id -> Int4,
+        post_id -> Nullable<Int4>,
+        comment_id -> Nullable<Int4>,
+        position -> Int4,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    inbox_rejection (id) {
+        id -> Int4,
+        domain -> Text,
+        reason -> Text,
+        count -> Int4,
+        last_rejected -> Timestamp,
+    }
+}
+
+table! {
+    ip_block (id) {
+        id -> Int4,
+        cidr -> Nullable<Text>,
+        asn -> Nullable<Int4>,
+        reason -> Nullable<Text>,
+        block_posting -> Bool,
+        hits -> Int4,
+        last_hit -> Nullable<Timestamp>,
+        creator_id -> Int4,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    media_alt_text (id) {
+        id -> Int4,
+        url -> Text,
+        alt_text -> Text,
+        creator_id -> Int4,
+        published -> Timestamp,
+        updated -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    language (id) {
+        id -> Int4,
+        code -> Varchar,
+        name -> Varchar,
     }
 }
 
@@ -93,6 +232,15 @@ table! {
     }
 }
 
+table! {
+    media_variant (id) {
+        id -> Int4,
+        url -> Text,
+        original_url -> Nullable<Text>,
+        published -> Timestamp,
+    }
+}
+
 table! {
     mod_add_community (id) {
         id -> Int4,
@@ -113,6 +261,7 @@ table! {
         banned -> Nullable<Bool>,
         expires -> Nullable<Timestamp>,
         when_ -> Timestamp,
+        remote_scope -> Nullable<Int2>,
     }
 }
 
@@ -129,6 +278,40 @@ table! {
     }
 }
 
+table! {
+    mod_impersonate (id) {
+        id -> Int4,
+        mod_user_id -> Int4,
+        other_user_id -> Int4,
+        reason -> Nullable<Text>,
+        revoked -> Bool,
+        when_ -> Timestamp,
+    }
+}
+
+table! {
+    mod_legal_hold (id) {
+        id -> Int4,
+        mod_user_id -> Int4,
+        other_user_id -> Nullable<Int4>,
+        post_id -> Nullable<Int4>,
+        reason -> Nullable<Text>,
+        held -> Nullable<Bool>,
+        when_ -> Timestamp,
+    }
+}
+
+table! {
+    mod_digest (id) {
+        id -> Int4,
+        user_id -> Int4,
+        community_id -> Int4,
+        interval_days -> Int2,
+        next_send -> Timestamp,
+        published -> Timestamp,
+    }
+}
+
 table! {
     mod_lock_post (id) {
         id -> Int4,
@@ -139,6 +322,30 @@ table! {
     }
 }
 
+table! {
+    mod_note (id) {
+        id -> Int4,
+        community_id -> Int4,
+        user_id -> Int4,
+        creator_id -> Int4,
+        content -> Text,
+        deleted -> Bool,
+        published -> Timestamp,
+        updated -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    mod_note_revision (id) {
+        id -> Int4,
+        mod_note_id -> Int4,
+        editor_id -> Int4,
+        content -> Text,
+        deleted -> Bool,
+        published -> Timestamp,
+    }
+}
+
 table! {
     mod_remove_comment (id) {
         id -> Int4,
@@ -147,6 +354,7 @@ table! {
         reason -> Nullable<Text>,
         removed -> Nullable<Bool>,
         when_ -> Timestamp,
+        remote_scope -> Nullable<Int2>,
     }
 }
 
@@ -170,6 +378,7 @@ table! {
         reason -> Nullable<Text>,
         removed -> Nullable<Bool>,
         when_ -> Timestamp,
+        remote_scope -> Nullable<Int2>,
     }
 }
 
@@ -183,6 +392,48 @@ table! {
     }
 }
 
+table! {
+    moderation_notice (id) {
+        id -> Int4,
+        recipient_id -> Int4,
+        mod_user_id -> Int4,
+        kind -> Int2,
+        community_id -> Nullable<Int4>,
+        post_id -> Nullable<Int4>,
+        comment_id -> Nullable<Int4>,
+        reason -> Nullable<Text>,
+        content -> Text,
+        read -> Bool,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    oauth_client (id) {
+        id -> Int4,
+        client_id -> Text,
+        client_secret_hash -> Text,
+        name -> Varchar,
+        redirect_uris -> Array<Text>,
+        creator_id -> Int4,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    oauth_code (id) {
+        id -> Int4,
+        code_hash -> Text,
+        oauth_client_id -> Int4,
+        user_id -> Int4,
+        scopes -> Array<Text>,
+        redirect_uri -> Text,
+        code_challenge -> Nullable<Text>,
+        expires -> Timestamp,
+        published -> Timestamp,
+    }
+}
+
 table! {
     password_reset_request (id) {
         id -> Int4,
@@ -211,6 +462,50 @@ table! {
         embed_description -> Nullable<Text>,
         embed_html -> Nullable<Text>,
         thumbnail_url -> Nullable<Text>,
+        legal_hold -> Bool,
+        slug -> Text,
+        excerpt -> Nullable<Text>,
+        alt_text -> Nullable<Text>,
+        visibility -> Int2,
+        ap_id -> Nullable<Text>,
+        generated -> Bool,
+        language_id -> Nullable<Int4>,
+        scheduled_publish_time -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    post_activity_hour (post_id, hour) {
+        post_id -> Int4,
+        hour -> Timestamp,
+        score -> Int4,
+        comments -> Int4,
+    }
+}
+
+table! {
+    post_attachment (id) {
+        id -> Int4,
+        post_id -> Int4,
+        position -> Int4,
+        kind -> Int2,
+        url -> Text,
+        mime_type -> Nullable<Text>,
+        name -> Nullable<Text>,
+        published -> Timestamp,
+        duration -> Nullable<Int4>,
+    }
+}
+
+table! {
+    post_edit (id) {
+        id -> Int4,
+        post_id -> Int4,
+        editor_id -> Int4,
+        name -> Varchar,
+        url -> Nullable<Text>,
+        body -> Nullable<Text>,
+        published -> Timestamp,
     }
 }
 
@@ -233,12 +528,29 @@ table! {
     }
 }
 
+table! {
+    post_slug_history (id) {
+        id -> Int4,
+        post_id -> Int4,
+        slug -> Text,
+        published -> Timestamp,
+    }
+}
+
 table! {
     post_saved (id) {
         id -> Int4,
         post_id -> Int4,
         user_id -> Int4,
         published -> Timestamp,
+        folder_id -> Nullable<Int4>,
+    }
+}
+
+table! {
+    post_view_count (post_id) {
+        post_id -> Int4,
+        views -> Int8,
     }
 }
 
@@ -252,6 +564,57 @@ table! {
         read -> Bool,
         published -> Timestamp,
         updated -> Nullable<Timestamp>,
+        encrypted -> Bool,
+    }
+}
+
+table! {
+    report (id) {
+        id -> Int4,
+        creator_id -> Int4,
+        community_id -> Int4,
+        post_id -> Nullable<Int4>,
+        comment_id -> Nullable<Int4>,
+        reason -> Text,
+        resolved -> Bool,
+        resolver_id -> Nullable<Int4>,
+        published -> Timestamp,
+        updated -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    reserved_community_name (id) {
+        id -> Int4,
+        name -> Varchar,
+        reason -> Nullable<Text>,
+        creator_id -> Int4,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    saved_folder (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Varchar,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    scheduled_post (id) {
+        id -> Int4,
+        community_id -> Int4,
+        creator_id -> Int4,
+        name -> Varchar,
+        body -> Nullable<Text>,
+        weekday -> Nullable<Int2>,
+        hour -> Int2,
+        last_post_id -> Nullable<Int4>,
+        next_run -> Timestamp,
+        published -> Timestamp,
+        updated -> Nullable<Timestamp>,
     }
 }
 
@@ -266,6 +629,38 @@ table! {
         enable_downvotes -> Bool,
         open_registration -> Bool,
         enable_nsfw -> Bool,
+        noindex -> Bool,
+        robots_txt -> Nullable<Text>,
+        require_alt_text -> Bool,
+        community_creation_admin_only -> Bool,
+        community_creation_min_account_age_days -> Int4,
+        community_creation_min_karma -> Int4,
+        vote_weighting -> Bool,
+        vote_weighting_min_account_age_days -> Int4,
+        vote_weighting_min_karma -> Int4,
+        vote_weighting_weight -> Float4,
+        moderation_notices -> Bool,
+        moderation_notice_pm -> Bool,
+        moderation_notice_template -> Nullable<Text>,
+        moderation_appeal_url -> Nullable<Text>,
+        karma_exclude_self_votes -> Bool,
+        karma_cap_per_item -> Int4,
+        karma_decay_half_life_days -> Int4,
+        score_visibility -> Int2,
+        track_post_views -> Bool,
+        public_edit_history -> Bool,
+    }
+}
+
+table! {
+    translation (id) {
+        id -> Int4,
+        post_id -> Nullable<Int4>,
+        comment_id -> Nullable<Int4>,
+        lang -> Varchar,
+        name -> Nullable<Text>,
+        content -> Nullable<Text>,
+        published -> Timestamp,
     }
 }
 
@@ -290,6 +685,30 @@ table! {
         show_avatars -> Bool,
         send_notifications_to_email -> Bool,
         matrix_user_id -> Nullable<Text>,
+        settings -> Jsonb,
+        legal_hold -> Bool,
+        hide_profile -> Bool,
+        actor_id -> Nullable<Text>,
+        hide_activity -> Bool,
+        last_refreshed -> Nullable<Timestamp>,
+        refresh_failures -> Int4,
+        refresh_retry_at -> Nullable<Timestamp>,
+        blended_discovery_percent -> Int2,
+        blended_discovery_local -> Bool,
+        show_scores -> Bool,
+        bot_account -> Bool,
+        show_generated -> Bool,
+        ban_expires -> Nullable<Timestamp>,
+        collapse_crossposts -> Bool,
+    }
+}
+
+table! {
+    user_activity_day (user_id, day) {
+        user_id -> Int4,
+        day -> Date,
+        posts -> Int4,
+        comments -> Int4,
     }
 }
 
@@ -301,6 +720,42 @@ table! {
     }
 }
 
+table! {
+    user_block (id) {
+        id -> Int4,
+        user_id -> Int4,
+        target_id -> Int4,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    user_instance_block (id) {
+        id -> Int4,
+        user_id -> Int4,
+        domain -> Text,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    user_keyword_filter (id) {
+        id -> Int4,
+        user_id -> Int4,
+        keyword -> Varchar,
+        regex -> Bool,
+        published -> Timestamp,
+    }
+}
+
+table! {
+    user_language (id) {
+        id -> Int4,
+        user_id -> Int4,
+        language_id -> Int4,
+    }
+}
+
 table! {
     user_mention (id) {
         id -> Int4,
@@ -311,25 +766,47 @@ table! {
     }
 }
 
+joinable!(api_token -> oauth_client (oauth_client_id));
+joinable!(comment -> language (language_id));
 joinable!(comment -> post (post_id));
 joinable!(comment -> user_ (creator_id));
+joinable!(comment_edit -> comment (comment_id));
+joinable!(comment_edit -> user_ (editor_id));
 joinable!(comment_like -> comment (comment_id));
 joinable!(comment_like -> post (post_id));
 joinable!(comment_like -> user_ (user_id));
 joinable!(comment_saved -> comment (comment_id));
+joinable!(comment_saved -> saved_folder (folder_id));
 joinable!(comment_saved -> user_ (user_id));
 joinable!(community -> category (category_id));
 joinable!(community -> user_ (creator_id));
+joinable!(community_alias -> community (community_id));
+joinable!(community_block -> community (community_id));
+joinable!(community_block -> user_ (user_id));
 joinable!(community_follower -> community (community_id));
+joinable!(community_growth -> community (community_id));
 joinable!(community_follower -> user_ (user_id));
 joinable!(community_moderator -> community (community_id));
 joinable!(community_moderator -> user_ (user_id));
+joinable!(community_rules_ack -> community (community_id));
+joinable!(community_rules_ack -> user_ (user_id));
 joinable!(community_user_ban -> community (community_id));
 joinable!(community_user_ban -> user_ (user_id));
+joinable!(featured -> comment (comment_id));
+joinable!(featured -> post (post_id));
+joinable!(featured -> user_ (user_id));
+joinable!(media_alt_text -> user_ (creator_id));
 joinable!(mod_add_community -> community (community_id));
 joinable!(mod_ban_from_community -> community (community_id));
+joinable!(ip_block -> user_ (creator_id));
+joinable!(mod_legal_hold -> post (post_id));
+joinable!(mod_digest -> community (community_id));
+joinable!(mod_digest -> user_ (user_id));
 joinable!(mod_lock_post -> post (post_id));
 joinable!(mod_lock_post -> user_ (mod_user_id));
+joinable!(mod_note -> community (community_id));
+joinable!(mod_note_revision -> mod_note (mod_note_id));
+joinable!(mod_note_revision -> user_ (editor_id));
 joinable!(mod_remove_comment -> comment (comment_id));
 joinable!(mod_remove_comment -> user_ (mod_user_id));
 joinable!(mod_remove_community -> community (community_id));
@@ -338,46 +815,112 @@ joinable!(mod_remove_post -> post (post_id));
 joinable!(mod_remove_post -> user_ (mod_user_id));
 joinable!(mod_sticky_post -> post (post_id));
 joinable!(mod_sticky_post -> user_ (mod_user_id));
+joinable!(moderation_notice -> comment (comment_id));
+joinable!(moderation_notice -> community (community_id));
+joinable!(moderation_notice -> post (post_id));
+joinable!(oauth_client -> user_ (creator_id));
+joinable!(oauth_code -> oauth_client (oauth_client_id));
+joinable!(oauth_code -> user_ (user_id));
 joinable!(password_reset_request -> user_ (user_id));
 joinable!(post -> community (community_id));
+joinable!(post -> language (language_id));
 joinable!(post -> user_ (creator_id));
+joinable!(post_activity_hour -> post (post_id));
+joinable!(post_attachment -> post (post_id));
+joinable!(post_edit -> post (post_id));
+joinable!(post_edit -> user_ (editor_id));
 joinable!(post_like -> post (post_id));
 joinable!(post_like -> user_ (user_id));
 joinable!(post_read -> post (post_id));
 joinable!(post_read -> user_ (user_id));
 joinable!(post_saved -> post (post_id));
+joinable!(post_saved -> saved_folder (folder_id));
 joinable!(post_saved -> user_ (user_id));
+joinable!(post_view_count -> post (post_id));
+joinable!(report -> comment (comment_id));
+joinable!(report -> community (community_id));
+joinable!(report -> post (post_id));
+joinable!(post_slug_history -> post (post_id));
+joinable!(reserved_community_name -> user_ (creator_id));
+joinable!(saved_folder -> user_ (user_id));
+joinable!(scheduled_post -> community (community_id));
+joinable!(scheduled_post -> post (last_post_id));
+joinable!(scheduled_post -> user_ (creator_id));
 joinable!(site -> user_ (creator_id));
+joinable!(translation -> comment (comment_id));
+joinable!(translation -> post (post_id));
+joinable!(user_activity_day -> user_ (user_id));
 joinable!(user_ban -> user_ (user_id));
+joinable!(user_block -> user_ (user_id));
+joinable!(user_instance_block -> user_ (user_id));
+joinable!(user_keyword_filter -> user_ (user_id));
+joinable!(user_language -> language (language_id));
+joinable!(user_language -> user_ (user_id));
 joinable!(user_mention -> comment (comment_id));
 joinable!(user_mention -> user_ (recipient_id));
 
 allow_tables_to_appear_in_same_query!(
+  api_token,
   category,
   comment,
+  comment_edit,
   comment_like,
   comment_saved,
   community,
+  community_alias,
+  community_block,
   community_follower,
+  community_growth,
   community_moderator,
+  community_rules_ack,
   community_user_ban,
+  consistency_check,
+  featured,
+  inbox_rejection,
+  ip_block,
+  language,
+  media_alt_text,
+  media_variant,
   mod_add,
   mod_add_community,
   mod_ban,
   mod_ban_from_community,
+  mod_impersonate,
+  mod_legal_hold,
+  mod_digest,
   mod_lock_post,
+  mod_note,
+  mod_note_revision,
   mod_remove_comment,
   mod_remove_community,
   mod_remove_post,
   mod_sticky_post,
+  moderation_notice,
+  oauth_client,
+  oauth_code,
   password_reset_request,
   post,
+  post_activity_hour,
+  post_attachment,
+  post_edit,
   post_like,
   post_read,
   post_saved,
+  post_view_count,
+  post_slug_history,
+  reserved_community_name,
   private_message,
+  report,
+  saved_folder,
+  scheduled_post,
   site,
+  translation,
   user_,
+  user_activity_day,
   user_ban,
+  user_block,
+  user_instance_block,
+  user_keyword_filter,
+  user_language,
   user_mention,
 );
@@ -1,6 +1,7 @@
 use config::{Config, ConfigError, Environment, File};
 use failure::Error;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::net::IpAddr;
@@ -19,8 +20,23 @@ pub struct Settings {
   pub jwt_secret: String,
   pub front_end_dir: String,
   pub rate_limit: RateLimitConfig,
+  pub ip_privacy: IpPrivacyConfig,
   pub email: Option<EmailConfig>,
   pub federation_enabled: bool,
+  pub private_instance: bool,
+  pub plain_html: bool,
+  pub public_modlog_export: bool,
+  pub federation: FederationConfig,
+  pub sitemap: SitemapConfig,
+  pub post_slug: PostSlugConfig,
+  pub gallery: GalleryConfig,
+  pub image: ImageConfig,
+  pub megathread: MegathreadConfig,
+  pub search: SearchConfig,
+  pub consistency_check: ConsistencyCheckConfig,
+  pub private_message_encryption: Option<EncryptionConfig>,
+  pub asn_database: Option<String>,
+  pub translation: Option<TranslationConfig>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -41,6 +57,112 @@ pub struct RateLimitConfig {
   pub register_per_second: i32,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct IpPrivacyConfig {
+  pub log_ips: bool,
+  pub anonymize: IpAnonymization,
+  pub retention_seconds: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IpAnonymization {
+  /// The whole address
+  None,
+  /// Only the /24 of an IPv4 address, or the /48 of an IPv6 one
+  Truncate,
+  /// A salted hash of the address, so the same address can still be told apart from others
+  Hash,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FederationConfig {
+  pub apub_cache_size: usize,
+  pub apub_cache_max_age: u64,
+  pub remote_actor_max_age: i64,
+  pub actor_domain: Option<String>,
+  pub inbox: InboxConfig,
+  pub limits: FederationLimitsConfig,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct InboxConfig {
+  pub max_payload_size: usize,
+  pub max_depth: usize,
+  pub accepted_activity_types: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FederationLimitsConfig {
+  pub max_concurrent_deliveries: usize,
+  pub max_concurrent_fetches: usize,
+  pub instance_requests_per_second: f64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SitemapConfig {
+  pub cache_max_age: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PostSlugConfig {
+  pub enabled: bool,
+  pub max_length: usize,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct GalleryConfig {
+  pub max_images: usize,
+  pub images_per_day: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ImageConfig {
+  pub max_width: u32,
+  pub max_height: u32,
+  pub max_size: u64,
+  pub convert_to_webp: bool,
+  pub keep_originals: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MegathreadConfig {
+  pub comment_threshold: i64,
+  pub page_size: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SearchConfig {
+  /// Terms shorter than this are matched anywhere in the text, rather than as words
+  pub substring_below_length: usize,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConsistencyCheckConfig {
+  pub interval_hours: u64,
+  pub repair: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EncryptionConfig {
+  pub current_key: String,
+  pub keys: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TranslationConfig {
+  pub backend: TranslationBackend,
+  pub endpoint: String,
+  pub api_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TranslationBackend {
+  LibreTranslate,
+  DeepL,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct EmailConfig {
   pub smtp_server: String,
@@ -94,6 +216,12 @@ impl Settings {
     SETTINGS.read().unwrap().to_owned()
   }
 
+  /// Replaces the config, until it's set again or reloaded from the files. The integration tests
+  /// use it to run instances with their own hostnames in one process.
+  pub fn set(settings: Settings) {
+    *SETTINGS.write().unwrap() = settings;
+  }
+
   /// Returns the postgres connection url. If LEMMY_DATABASE_URL is set, that is used,
   /// otherwise the connection url is generated from the config.
   pub fn get_database_url(&self) -> String {
@@ -110,6 +238,15 @@ impl Settings {
     }
   }
 
+  /// The domain in activitypub ids and webfinger addresses, which is the hostname unless the
+  /// instance federates under a different domain than it's served on.
+  pub fn actor_domain(&self) -> String {
+    match &self.federation.actor_domain {
+      Some(actor_domain) => actor_domain.to_owned(),
+      None => self.hostname.to_owned(),
+    }
+  }
+
   pub fn api_endpoint(&self) -> String {
     format!("{}/api/v1", self.hostname)
   }
@@ -0,0 +1,149 @@
+//! Translating posts and comments with a LibreTranslate or DeepL server, set as `translation` in
+//! the settings. The server's api key stays on the instance; clients only see the translations,
+//! which are cached in the `translation` table.
+
+use crate::settings::{TranslationBackend, TranslationConfig};
+use failure::Error;
+use isahc::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Deserialize)]
+struct LibreTranslateResponse {
+  #[serde(rename = "translatedText")]
+  translated_text: String,
+}
+
+#[derive(Deserialize)]
+struct DeepLResponse {
+  translations: Vec<DeepLTranslation>,
+}
+
+#[derive(Deserialize)]
+struct DeepLTranslation {
+  text: String,
+}
+
+/// Whether `lang` looks like a language code, ie `de` or `pt-BR`.
+pub fn valid_lang(lang: &str) -> bool {
+  let mut parts = lang.splitn(2, '-');
+  let language = parts.next().unwrap_or("");
+  let region = parts.next();
+  (2..=3).contains(&language.len())
+    && language.chars().all(|c| c.is_ascii_alphabetic())
+    && region.map_or(true, |r| {
+      (2..=4).contains(&r.len()) && r.chars().all(|c| c.is_ascii_alphanumeric())
+    })
+}
+
+/// The url and json body of a request translating `text` to `lang`.
+fn build_request(config: &TranslationConfig, text: &str, lang: &str) -> (String, String) {
+  let endpoint = config.endpoint.trim_end_matches('/');
+  match config.backend {
+    TranslationBackend::LibreTranslate => {
+      let body = json!({
+        "q": text,
+        "source": "auto",
+        "target": lang.to_lowercase(),
+        "format": "text",
+        "api_key": config.api_key,
+      });
+      (format!("{}/translate", endpoint), body.to_string())
+    }
+    TranslationBackend::DeepL => {
+      let body = json!({
+        "text": [text],
+        "target_lang": lang.to_uppercase(),
+      });
+      (format!("{}/v2/translate", endpoint), body.to_string())
+    }
+  }
+}
+
+fn parse_response(backend: &TranslationBackend, text: &str) -> Result<String, Error> {
+  match backend {
+    TranslationBackend::LibreTranslate => {
+      Ok(serde_json::from_str::<LibreTranslateResponse>(text)?.translated_text)
+    }
+    TranslationBackend::DeepL => serde_json::from_str::<DeepLResponse>(text)?
+      .translations
+      .into_iter()
+      .next()
+      .map(|t| t.text)
+      .ok_or_else(|| format_err!("No translation in the response")),
+  }
+}
+
+/// Translates `text` to `lang`, with the source language detected by the server.
+pub fn translate(config: &TranslationConfig, text: &str, lang: &str) -> Result<String, Error> {
+  let (url, body) = build_request(config, text, lang);
+  let mut request = Request::post(url)
+    .header("Content-Type", "application/json")
+    .timeout(TIMEOUT);
+  if let (TranslationBackend::DeepL, Some(api_key)) = (&config.backend, &config.api_key) {
+    request = request.header("Authorization", format!("DeepL-Auth-Key {}", api_key));
+  }
+
+  let mut response = request.body(body)?.send()?;
+  let text = response.text()?;
+  if !response.status().is_success() {
+    return Err(format_err!(
+      "Translation failed with {}: {}",
+      response.status(),
+      text
+    ));
+  }
+  parse_response(&config.backend, &text)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_translation_requests() {
+    assert!(valid_lang("de"));
+    assert!(valid_lang("pt-BR"));
+    assert!(valid_lang("zh-Hant"));
+    assert!(!valid_lang("d"));
+    assert!(!valid_lang("de/../x"));
+    assert!(!valid_lang("pt-"));
+
+    let libre = TranslationConfig {
+      backend: TranslationBackend::LibreTranslate,
+      endpoint: "https://translate.example.com/".into(),
+      api_key: None,
+    };
+    let (url, body) = build_request(&libre, "Hello", "DE");
+    assert_eq!("https://translate.example.com/translate", url);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!("de", body["target"]);
+    assert_eq!(
+      "Hallo",
+      parse_response(&libre.backend, r#"{"translatedText":"Hallo"}"#).unwrap()
+    );
+
+    let deepl = TranslationConfig {
+      backend: TranslationBackend::DeepL,
+      endpoint: "https://api-free.deepl.com".into(),
+      api_key: Some("key".into()),
+    };
+    let (url, body) = build_request(&deepl, "Hello", "pt-br");
+    assert_eq!("https://api-free.deepl.com/v2/translate", url);
+    assert!(!body.contains("key"));
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!("PT-BR", body["target_lang"]);
+    assert_eq!(
+      "Hallo",
+      parse_response(
+        &deepl.backend,
+        r#"{"translations":[{"detected_source_language":"EN","text":"Hallo"}]}"#
+      )
+      .unwrap()
+    );
+    assert!(parse_response(&deepl.backend, r#"{"translations":[]}"#).is_err());
+  }
+}
@@ -8,6 +8,7 @@ pub enum UserOperation {
   CreatePost,
   ListCommunities,
   ListCategories,
+  ListLanguages,
   GetPost,
   GetCommunity,
   CreateComment,
@@ -21,11 +22,31 @@ pub enum UserOperation {
   EditCommunity,
   FollowCommunity,
   GetFollowedCommunities,
+  BlockCommunity,
+  GetBlockedCommunities,
   GetUserDetails,
   GetReplies,
   GetUserMentions,
+  GetInbox,
+  GetUnreadCount,
+  CreateSavedFolder,
+  EditSavedFolder,
+  DeleteSavedFolder,
+  GetSavedFolders,
+  GetSavedItems,
+  CreateKeywordFilter,
+  DeleteKeywordFilter,
+  GetKeywordFilters,
+  BlockUser,
+  GetBlockedUsers,
+  PinToProfile,
+  ReorderProfilePins,
+  BlockInstance,
+  GetBlockedInstances,
+  GetMyContent,
   EditUserMention,
   GetModlog,
+  GetModlogFeed,
   BanFromCommunity,
   AddModToCommunity,
   CreateSite,
@@ -35,6 +56,7 @@ pub enum UserOperation {
   BanUser,
   Search,
   MarkAllAsRead,
+  MarkAsRead,
   SaveUserSettings,
   TransferCommunity,
   TransferSite,
@@ -48,4 +70,52 @@ pub enum UserOperation {
   GetComments,
   GetSiteConfig,
   SaveSiteConfig,
+  LegalHold,
+  ImportIpBlocks,
+  ListIpBlocks,
+  DeleteIpBlock,
+  CreateApiToken,
+  ListApiTokens,
+  ListMyTokens,
+  DeleteApiToken,
+  SetApiTokenRateLimit,
+  RegisterOAuthClient,
+  ListOAuthClients,
+  DeleteOAuthClient,
+  GetOAuthConsent,
+  AuthorizeOAuthClient,
+  ImpersonateUser,
+  ListImpersonations,
+  RevokeImpersonation,
+  TranslateContent,
+  SaveMediaAltText,
+  ResolveObject,
+  PrefetchActors,
+  GetApubId,
+  GetInstanceImpact,
+  ListInboxRejections,
+  GetFederationMetrics,
+  GetOperationMetrics,
+  GetCommentContext,
+  GetCommentTree,
+  GetUserActivity,
+  ListReservedCommunityNames,
+  ReserveCommunityName,
+  DeleteReservedCommunityName,
+  GetConsistencyReport,
+  RunConsistencyCheck,
+  CreateModNote,
+  EditModNote,
+  GetModNotes,
+  CreateScheduledPost,
+  DeleteScheduledPost,
+  GetScheduledPosts,
+  SaveModDigest,
+  GetModDigests,
+  CreateReport,
+  ListReports,
+  ResolveReport,
+  GetModerationNotices,
+  GetPostEdits,
+  GetCommentEdits,
 }
@@ -12,15 +12,34 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
-use std::time::SystemTime;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime};
 use strum::IntoEnumIterator;
 
+use crate::api::api_token::*;
 use crate::api::comment::*;
 use crate::api::community::*;
+use crate::api::oauth::*;
 use crate::api::post::*;
+use crate::api::report::*;
 use crate::api::site::*;
+use crate::api::translation::*;
 use crate::api::user::*;
 use crate::api::*;
+use crate::apub::user::retry_refreshes;
+use crate::db::comment_view::CommentView;
+use crate::db::community::CommunityUserBan;
+use crate::db::consistency::ConsistencyCheck;
+use crate::db::ids;
+use crate::db::post_activity_hour::PostActivityHour;
+use crate::db::post_view::PostView;
+use crate::db::site::Site;
+use crate::db::user::User_;
+use crate::db::user_view::UserView;
+use crate::db::{Crud, HideScores, PostVisibility};
+use crate::ip_privacy;
+use crate::post_views::flush_views;
 use crate::websocket::UserOperation;
 use crate::Settings;
 
@@ -30,6 +49,40 @@ type CommunityId = i32;
 type UserId = i32;
 type IPAddr = String;
 
+/// How often IPs past their retention are dropped from the rate limiting state
+const PRUNE_INTERVAL: Duration = Duration::from_secs(600);
+
+/// How often the failed refreshes of remote users that are due are tried again
+const REFRESH_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the post activity that no longer counts for the Rising sort is deleted
+const POST_ACTIVITY_PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// How often karma is counted again when it decays, which it does by the day
+const KARMA_DECAY_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// How often the scheduled posts that are due are made and published
+const SCHEDULED_POST_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the moderators' digests that are due are sent
+const MOD_DIGEST_INTERVAL: Duration = Duration::from_secs(600);
+
+/// How often the temporary bans that have expired are lifted
+const BAN_EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the communities are checked for the milestones and subscription spikes their
+/// moderators are told about
+const COMMUNITY_GROWTH_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
+/// How often the post views counted in memory are written
+const POST_VIEW_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the posts and comments whose votes were only sent as deltas are sent whole
+const SCORE_RECONCILE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The op of the vote count deltas, which isn't a user operation since nobody sends it
+const SCORE_DELTA_OP: &str = "ScoreDelta";
+
 /// Chat server sends this messages to session
 #[derive(Message)]
 #[rtype(result = "()")]
@@ -53,6 +106,14 @@ pub struct Disconnect {
   pub ip: IPAddr,
 }
 
+/// The posts and comments whose scores were reconciled, with the messages to send their rooms
+#[derive(Message)]
+#[rtype(result = "()")]
+struct ReconciledScores {
+  posts: Vec<(PostView, String)>,
+  comments: Vec<(CommentView, String)>,
+}
+
 #[derive(Serialize, Deserialize, Message)]
 #[rtype(String)]
 pub struct StandardMessage {
@@ -68,9 +129,148 @@ pub struct RateLimitBucket {
   allowance: f64,
 }
 
+impl RateLimitBucket {
+  /// Adds to the allowance for the time since it was last checked, then takes a request from it
+  /// unless `check_only`. `Err` is when there was no request left to take.
+  #[allow(clippy::float_cmp)]
+  fn take(
+    &mut self,
+    now: SystemTime,
+    rate: i32,
+    per: i32,
+    check_only: bool,
+  ) -> Result<RateLimitState, RateLimitState> {
+    let time_passed = now
+      .duration_since(self.last_checked)
+      .map(|passed| passed.as_secs())
+      .unwrap_or(0) as f64;
+
+    // The initial value
+    if self.allowance == -2f64 {
+      self.allowance = rate as f64;
+    };
+
+    self.last_checked = now;
+    self.allowance += time_passed * (rate as f64 / per as f64);
+    if !check_only && self.allowance > rate as f64 {
+      self.allowance = rate as f64;
+    }
+
+    if self.allowance < 1.0 {
+      Err(self.state(rate, per))
+    } else {
+      if !check_only {
+        self.allowance -= 1.0;
+      }
+      Ok(self.state(rate, per))
+    }
+  }
+
+  fn state(&self, rate: i32, per: i32) -> RateLimitState {
+    let missing = (rate as f64 - self.allowance).max(0.0);
+    let reset = if rate > 0 {
+      (missing * per as f64 / rate as f64).ceil() as i64
+    } else {
+      0
+    };
+    RateLimitState {
+      limit: rate,
+      remaining: (self.allowance.max(0.0) as i32).min(rate),
+      reset,
+    }
+  }
+}
+
+/// What's left of a rate limit, so clients can slow down before they hit it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RateLimitState {
+  /// How many requests can be made at once
+  pub limit: i32,
+  /// How many of them can be made right now
+  pub remaining: i32,
+  /// Seconds until all of them can be made again
+  pub reset: i64,
+}
+
+/// How many requests of an operation have been made since the server started, over http and
+/// websockets, and how many of those were rate limited.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct OperationMetrics {
+  pub op: String,
+  pub requests: usize,
+  pub rate_limited: usize,
+}
+
+lazy_static! {
+  static ref OPERATION_METRICS: Mutex<HashMap<String, OperationMetrics>> =
+    Mutex::new(HashMap::new());
+}
+
+fn count_operation(op: &UserOperation, rate_limited: bool) {
+  let op = op.to_string();
+  let mut metrics = OPERATION_METRICS.lock().unwrap();
+  let counts = metrics
+    .entry(op.to_owned())
+    .or_insert_with(|| OperationMetrics {
+      op,
+      ..OperationMetrics::default()
+    });
+  counts.requests += 1;
+  if rate_limited {
+    counts.rate_limited += 1;
+  }
+}
+
+/// The counts of every operation that has been requested, by name.
+pub fn operation_metrics() -> Vec<OperationMetrics> {
+  let mut metrics: Vec<OperationMetrics> = OPERATION_METRICS
+    .lock()
+    .unwrap()
+    .values()
+    .cloned()
+    .collect();
+  metrics.sort_by(|a, b| a.op.cmp(&b.op));
+  metrics
+}
+
+/// Checks a rate limit of an http request, which has no session, so is limited by its IP alone.
+/// The state is returned either way, for the response's headers.
+#[derive(Message)]
+#[rtype(result = "Result<RateLimitState, RateLimitState>")]
+pub struct CheckRateLimit {
+  pub op: UserOperation,
+  pub type_: RateLimitType,
+  pub ip: IPAddr,
+  pub rate_limit_multiplier: f64,
+  pub check_only: bool,
+}
+
 pub struct SessionInfo {
   pub addr: Recipient<WSMessage>,
   pub ip: IPAddr,
+  /// Raises the rate limits, for sessions using an api token which has been given more
+  pub rate_limit_multiplier: f64,
+}
+
+/// The new vote counts of a post, or of a comment if `comment_id` is given, which is all a vote
+/// sends to the rooms. Only the voter gets the whole post or comment back.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ScoreDelta {
+  pub post_id: PostId,
+  pub comment_id: Option<i32>,
+  pub score: Option<i64>,
+  pub upvotes: Option<i64>,
+  pub downvotes: Option<i64>,
+}
+
+impl ScoreDelta {
+  fn to_json_string(&self) -> Result<String, Error> {
+    let response = WebsocketResponse {
+      op: SCORE_DELTA_OP.to_string(),
+      data: self,
+    };
+    Ok(serde_json::to_string(&response)?)
+  }
 }
 
 #[derive(Eq, PartialEq, Hash, Debug, EnumIter, Copy, Clone)]
@@ -80,6 +280,27 @@ pub enum RateLimitType {
   Post,
 }
 
+impl RateLimitType {
+  /// The bucket an operation takes from once it succeeds, on top of the message one, if any.
+  pub fn of(op: &UserOperation) -> Option<RateLimitType> {
+    match op {
+      UserOperation::Register | UserOperation::CreateCommunity => Some(RateLimitType::Register),
+      UserOperation::CreatePost => Some(RateLimitType::Post),
+      _ => None,
+    }
+  }
+
+  /// How many of it are allowed, and per how many seconds.
+  fn limit(self) -> (i32, i32) {
+    let rate_limit = Settings::get().rate_limit;
+    match self {
+      RateLimitType::Message => (rate_limit.message, rate_limit.message_per_second),
+      RateLimitType::Register => (rate_limit.register, rate_limit.register_per_second),
+      RateLimitType::Post => (rate_limit.post, rate_limit.post_per_second),
+    }
+  }
+}
+
 /// `ChatServer` manages chat rooms and responsible for coordinating chat
 /// session.
 pub struct ChatServer {
@@ -99,6 +320,11 @@ pub struct ChatServer {
   /// Rate limiting based on rate type and IP addr
   rate_limit_buckets: HashMap<RateLimitType, HashMap<IPAddr, RateLimitBucket>>,
 
+  /// The posts and comments voted on since the last reconciliation, which the rooms only got the
+  /// vote counts of
+  unreconciled_posts: HashSet<PostId>,
+  unreconciled_comments: HashSet<i32>,
+
   rng: ThreadRng,
   db: Pool<ConnectionManager<PgConnection>>,
 }
@@ -111,6 +337,8 @@ impl ChatServer {
       post_rooms: HashMap::new(),
       community_rooms: HashMap::new(),
       user_rooms: HashMap::new(),
+      unreconciled_posts: HashSet::new(),
+      unreconciled_comments: HashSet::new(),
       rng: rand::thread_rng(),
       db,
     }
@@ -174,10 +402,10 @@ impl ChatServer {
     self.user_rooms.get_mut(&user_id).unwrap().insert(id);
   }
 
-  fn send_post_room_message(&self, post_id: PostId, message: &str, skip_id: ConnectionId) {
+  fn send_post_room_message(&self, post_id: PostId, message: &str, skip_id: Option<ConnectionId>) {
     if let Some(sessions) = self.post_rooms.get(&post_id) {
       for id in sessions {
-        if *id != skip_id {
+        if Some(*id) != skip_id {
           if let Some(info) = self.sessions.get(id) {
             let _ = info.addr.do_send(WSMessage(message.to_owned()));
           }
@@ -190,11 +418,11 @@ impl ChatServer {
     &self,
     community_id: CommunityId,
     message: &str,
-    skip_id: ConnectionId,
+    skip_id: Option<ConnectionId>,
   ) {
     if let Some(sessions) = self.community_rooms.get(&community_id) {
       for id in sessions {
-        if *id != skip_id {
+        if Some(*id) != skip_id {
           if let Some(info) = self.sessions.get(id) {
             let _ = info.addr.do_send(WSMessage(message.to_owned()));
           }
@@ -225,15 +453,37 @@ impl ChatServer {
     }
   }
 
+  /// Sends a post to /c/all and its community, unless it's left out of the listings, and to its
+  /// post room.
+  fn send_post_to_rooms(&self, post: &PostView, message: &str, skip_id: Option<ConnectionId>) {
+    if post.visibility == PostVisibility::Public as i16 {
+      self.send_community_room_message(0, message, skip_id);
+      self.send_community_room_message(post.community_id, message, skip_id);
+    }
+    self.send_post_room_message(post.id, message, skip_id);
+  }
+
+  /// Sends a comment to its post room, /c/all and its community.
+  fn send_comment_to_rooms(
+    &self,
+    comment: &CommentView,
+    message: &str,
+    skip_id: Option<ConnectionId>,
+  ) {
+    self.send_post_room_message(comment.post_id, message, skip_id);
+    self.send_community_room_message(0, message, skip_id);
+    self.send_community_room_message(comment.community_id, message, skip_id);
+  }
+
   fn comment_sends(
     &self,
+    conn: &PgConnection,
     user_operation: UserOperation,
     comment: CommentResponse,
     id: ConnectionId,
   ) -> Result<String, Error> {
     let mut comment_reply_sent = comment.clone();
-    comment_reply_sent.comment.my_vote = None;
-    comment_reply_sent.comment.user_id = None;
+    comment_reply_sent.comment = comment_for_others(conn, &comment.comment)?;
 
     // For the post room ones, and the directs back to the user
     // strip out the recipient_ids, so that
@@ -248,46 +498,91 @@ impl ChatServer {
     let comment_post_sent_str = to_json_string(&user_operation, &comment_post_sent)?;
     let comment_user_sent_str = to_json_string(&user_operation, &comment_user_sent)?;
 
-    // Send it to the post room
-    self.send_post_room_message(comment.comment.post_id, &comment_post_sent_str, id);
-
     // Send it to the recipient(s) including the mentioned users
     for recipient_id in comment_reply_sent.recipient_ids {
       self.send_user_room_message(recipient_id, &comment_reply_sent_str, id);
     }
 
-    // Send it to the community too
-    self.send_community_room_message(0, &comment_post_sent_str, id);
-    self.send_community_room_message(comment.comment.community_id, &comment_post_sent_str, id);
+    self.send_comment_to_rooms(&comment.comment, &comment_post_sent_str, Some(id));
 
     Ok(comment_user_sent_str)
   }
 
   fn post_sends(
     &self,
+    conn: &PgConnection,
     user_operation: UserOperation,
     post: PostResponse,
     id: ConnectionId,
   ) -> Result<String, Error> {
-    let community_id = post.post.community_id;
+    // Scheduled posts are only sent out once they're published
+    if post.post.scheduled_publish_time.is_some() {
+      return to_json_string(&user_operation, post);
+    }
 
-    // Don't send my data with it
-    let mut post_sent = post.clone();
-    post_sent.post.my_vote = None;
-    post_sent.post.user_id = None;
+    let post_sent = PostResponse {
+      post: post_for_others(conn, &post.post)?,
+    };
     let post_sent_str = to_json_string(&user_operation, &post_sent)?;
 
-    // Send it to /c/all and that community
-    self.send_community_room_message(0, &post_sent_str, id);
-    self.send_community_room_message(community_id, &post_sent_str, id);
+    self.send_post_to_rooms(&post_sent.post, &post_sent_str, Some(id));
 
-    // Send it to the post room
-    self.send_post_room_message(post_sent.post.id, &post_sent_str, id);
+    to_json_string(&user_operation, post)
+  }
+
+  /// Sends only the new vote counts of a voted on post to everyone else. It's sent whole at the
+  /// next reconciliation.
+  fn post_score_sends(
+    &mut self,
+    conn: &PgConnection,
+    user_operation: UserOperation,
+    post: PostResponse,
+    id: ConnectionId,
+  ) -> Result<String, Error> {
+    let post_sent = post_for_others(conn, &post.post)?;
+    let delta = ScoreDelta {
+      post_id: post_sent.id,
+      comment_id: None,
+      score: post_sent.score,
+      upvotes: post_sent.upvotes,
+      downvotes: post_sent.downvotes,
+    };
+    self.send_post_to_rooms(&post_sent, &delta.to_json_string()?, Some(id));
+    self.unreconciled_posts.insert(post_sent.id);
 
     to_json_string(&user_operation, post)
   }
 
-  fn check_rate_limit_register(&mut self, id: usize, check_only: bool) -> Result<(), Error> {
+  /// Sends only the new vote counts of a voted on comment to everyone else. It's sent whole at the
+  /// next reconciliation.
+  fn comment_score_sends(
+    &mut self,
+    conn: &PgConnection,
+    user_operation: UserOperation,
+    comment: CommentResponse,
+    id: ConnectionId,
+  ) -> Result<String, Error> {
+    let comment_sent = comment_for_others(conn, &comment.comment)?;
+    let delta = ScoreDelta {
+      post_id: comment_sent.post_id,
+      comment_id: Some(comment_sent.id),
+      score: comment_sent.score,
+      upvotes: comment_sent.upvotes,
+      downvotes: comment_sent.downvotes,
+    };
+    self.send_comment_to_rooms(&comment_sent, &delta.to_json_string()?, Some(id));
+    self.unreconciled_comments.insert(comment_sent.id);
+
+    let mut comment_user_sent = comment;
+    comment_user_sent.recipient_ids = Vec::new();
+    to_json_string(&user_operation, comment_user_sent)
+  }
+
+  fn check_rate_limit_register(
+    &mut self,
+    id: usize,
+    check_only: bool,
+  ) -> Result<Option<RateLimitState>, Error> {
     self.check_rate_limit_full(
       RateLimitType::Register,
       id,
@@ -297,7 +592,11 @@ impl ChatServer {
     )
   }
 
-  fn check_rate_limit_post(&mut self, id: usize, check_only: bool) -> Result<(), Error> {
+  fn check_rate_limit_post(
+    &mut self,
+    id: usize,
+    check_only: bool,
+  ) -> Result<Option<RateLimitState>, Error> {
     self.check_rate_limit_full(
       RateLimitType::Post,
       id,
@@ -307,7 +606,11 @@ impl ChatServer {
     )
   }
 
-  fn check_rate_limit_message(&mut self, id: usize, check_only: bool) -> Result<(), Error> {
+  fn check_rate_limit_message(
+    &mut self,
+    id: usize,
+    check_only: bool,
+  ) -> Result<Option<RateLimitState>, Error> {
     self.check_rate_limit_full(
       RateLimitType::Message,
       id,
@@ -317,7 +620,7 @@ impl ChatServer {
     )
   }
 
-  #[allow(clippy::float_cmp)]
+  /// Checks the rate limit of a session, which has none once it's closed.
   fn check_rate_limit_full(
     &mut self,
     type_: RateLimitType,
@@ -325,50 +628,264 @@ impl ChatServer {
     rate: i32,
     per: i32,
     check_only: bool,
-  ) -> Result<(), Error> {
-    if let Some(info) = self.sessions.get(&id) {
-      if let Some(bucket) = self.rate_limit_buckets.get_mut(&type_) {
-        if let Some(rate_limit) = bucket.get_mut(&info.ip) {
-          let current = SystemTime::now();
-          let time_passed = current.duration_since(rate_limit.last_checked)?.as_secs() as f64;
-
-          // The initial value
-          if rate_limit.allowance == -2f64 {
-            rate_limit.allowance = rate as f64;
-          };
-
-          rate_limit.last_checked = current;
-          rate_limit.allowance += time_passed * (rate as f64 / per as f64);
-          if !check_only && rate_limit.allowance > rate as f64 {
-            rate_limit.allowance = rate as f64;
-          }
-
-          if rate_limit.allowance < 1.0 {
-            warn!(
-              "Rate limited IP: {}, time_passed: {}, allowance: {}",
-              &info.ip, time_passed, rate_limit.allowance
-            );
-            Err(
-              APIError {
-                message: format!("Too many requests. {} per {} seconds", rate, per),
-              }
-              .into(),
-            )
-          } else {
-            if !check_only {
-              rate_limit.allowance -= 1.0;
-            }
-            Ok(())
-          }
-        } else {
-          Ok(())
+  ) -> Result<Option<RateLimitState>, Error> {
+    let (ip, multiplier) = match self.sessions.get(&id) {
+      Some(info) => (info.ip.to_owned(), info.rate_limit_multiplier),
+      None => return Ok(None),
+    };
+    match self.check_ip_rate_limit(type_, &ip, multiplier, rate, per, check_only) {
+      Ok(state) => Ok(Some(state)),
+      Err(_) => Err(
+        APIError {
+          message: format!("Too many requests. {} per {} seconds", rate, per),
         }
-      } else {
-        Ok(())
+        .into(),
+      ),
+    }
+  }
+
+  fn check_ip_rate_limit(
+    &mut self,
+    type_: RateLimitType,
+    ip: &str,
+    multiplier: f64,
+    rate: i32,
+    per: i32,
+    check_only: bool,
+  ) -> Result<RateLimitState, RateLimitState> {
+    let rate = (f64::from(rate) * multiplier) as i32;
+    let rate_limit = self
+      .rate_limit_buckets
+      .entry(type_)
+      .or_insert_with(HashMap::new)
+      .entry(ip.to_owned())
+      .or_insert_with(|| RateLimitBucket {
+        last_checked: SystemTime::now(),
+        allowance: -2f64,
+      });
+
+    let taken = rate_limit.take(SystemTime::now(), rate, per, check_only);
+    if taken.is_err() {
+      warn!(
+        "Rate limited IP: {}, allowance: {}",
+        ip_privacy::for_log(ip),
+        rate_limit.allowance
+      );
+    }
+    taken
+  }
+
+  fn set_rate_limit_multiplier(&mut self, id: ConnectionId, multiplier: f64) {
+    if let Some(info) = self.sessions.get_mut(&id) {
+      info.rate_limit_multiplier = multiplier;
+    }
+  }
+
+  /// The IP of a session, or an empty string for a closed one, which no block matches.
+  fn session_ip(&self, id: ConnectionId) -> IPAddr {
+    self
+      .sessions
+      .get(&id)
+      .map(|info| info.ip.to_owned())
+      .unwrap_or_default()
+  }
+
+  /// Drops the rate limiting state of IPs which have had no connections for longer than
+  /// `ip_privacy.retention_seconds`. Connected IPs are always kept, or they'd be unlimited.
+  fn prune_rate_limit_buckets(&mut self) {
+    let retention = Duration::from_secs(Settings::get().ip_privacy.retention_seconds);
+    let connected: HashSet<&IPAddr> = self.sessions.values().map(|info| &info.ip).collect();
+    let now = SystemTime::now();
+
+    for bucket in self.rate_limit_buckets.values_mut() {
+      bucket.retain(|ip, rate_limit| {
+        connected.contains(ip)
+          || now
+            .duration_since(rate_limit.last_checked)
+            .map(|idle| idle <= retention)
+            .unwrap_or(true)
+      });
+    }
+  }
+
+  /// Runs a job on another thread with a connection from the pool, so the database work doesn't
+  /// hold up the actor. `what` names it in the log if it fails.
+  fn spawn_job<F>(&self, what: &'static str, job: F)
+  where
+    F: FnOnce(&PgConnection) -> Result<(), Error> + Send + 'static,
+  {
+    let pool = self.db.clone();
+    thread::spawn(move || {
+      if let Err(e) = pool.get().map_err(Error::from).and_then(|conn| job(&conn)) {
+        error!("{} failed: {}", what, e);
+      }
+    });
+  }
+
+  /// Tries the failed refreshes of remote users again, since it fetches them from their
+  /// instances.
+  fn retry_user_refreshes(&self) {
+    self.spawn_job("Retrying refreshes", |conn| {
+      let retried = retry_refreshes(conn)?;
+      if retried > 0 {
+        info!("Retried refreshing {} remote users", retried);
+      }
+      Ok(())
+    });
+  }
+
+  /// Deletes the hours of post activity older than a day.
+  fn prune_post_activity(&self) {
+    self.spawn_job("Pruning post activity", |conn| {
+      PostActivityHour::prune(conn)?;
+      Ok(())
+    });
+  }
+
+  /// Counts karma again if the site has it decay, which goes through every vote.
+  fn decay_karma(&self) {
+    self.spawn_job("Decaying karma", |conn| {
+      if Site::read(conn, 1)?.karma_decay_half_life_days > 0 {
+        UserView::refresh(conn)?;
+      }
+      Ok(())
+    });
+  }
+
+  /// Makes the communities' scheduled posts that are due.
+  fn make_scheduled_posts(&self) {
+    self.spawn_job("Making scheduled posts", |conn| {
+      let made = run_scheduled_posts(conn)?;
+      if made > 0 {
+        info!("Made {} scheduled posts", made);
+      }
+      Ok(())
+    });
+  }
+
+  /// Publishes the scheduled posts that are due.
+  fn publish_scheduled_posts(&self) {
+    self.spawn_job("Publishing scheduled posts", |conn| {
+      let published = publish_scheduled_posts(conn)?;
+      if published > 0 {
+        info!("Published {} scheduled posts", published);
+      }
+      Ok(())
+    });
+  }
+
+  /// Sends the moderators' digests that are due.
+  fn send_mod_digests(&self) {
+    self.spawn_job("Sending mod digests", |conn| {
+      let sent = send_mod_digests(conn)?;
+      if sent > 0 {
+        info!("Sent {} mod digests", sent);
+      }
+      Ok(())
+    });
+  }
+
+  /// Tells moderators how their communities grew.
+  fn send_growth_notices(&self) {
+    self.spawn_job("Sending community growth notices", |conn| {
+      let sent = send_growth_notices(conn)?;
+      if sent > 0 {
+        info!("Sent {} community growth notices", sent);
+      }
+      Ok(())
+    });
+  }
+
+  /// Lifts the site and community bans that have expired.
+  fn lift_expired_bans(&self) {
+    self.spawn_job("Lifting expired bans", |conn| {
+      let lifted = User_::lift_expired_bans(conn)? + CommunityUserBan::lift_expired(conn)?;
+      if lifted > 0 {
+        info!("Lifted {} expired bans", lifted);
       }
-    } else {
       Ok(())
+    });
+  }
+
+  /// Writes the post views counted since the last time.
+  fn flush_post_views(&self) {
+    self.spawn_job("Writing post views", |conn| {
+      flush_views(conn)?;
+      Ok(())
+    });
+  }
+
+  /// Sends the posts and comments that were voted on whole to their rooms, in case a client
+  /// missed a delta or can't apply them. They're read on another thread, and handed back to the
+  /// actor to send.
+  fn reconcile_scores(&mut self, addr: Addr<ChatServer>) {
+    if self.unreconciled_posts.is_empty() && self.unreconciled_comments.is_empty() {
+      return;
     }
+    let post_ids: Vec<PostId> = self.unreconciled_posts.drain().collect();
+    let comment_ids: Vec<i32> = self.unreconciled_comments.drain().collect();
+
+    self.spawn_job("Reconciling scores", move |conn| {
+      let mut reconciled = ReconciledScores {
+        posts: Vec::new(),
+        comments: Vec::new(),
+      };
+
+      for post_id in post_ids {
+        let read = PostView::read(conn, ids::PostId(post_id), None)
+          .map_err(Error::from)
+          .and_then(|post| post_for_others(conn, &post))
+          .and_then(|post| {
+            let post_str = to_json_string(
+              &UserOperation::CreatePostLike,
+              PostResponse { post: post.clone() },
+            )?;
+            Ok((post, post_str))
+          });
+        match read {
+          Ok(post) => reconciled.posts.push(post),
+          Err(e) => error!("Reconciling the score of post {} failed: {}", post_id, e),
+        }
+      }
+
+      for comment_id in comment_ids {
+        let read = CommentView::read(conn, ids::CommentId(comment_id), None)
+          .map_err(Error::from)
+          .and_then(|comment| comment_for_others(conn, &comment))
+          .and_then(|comment| {
+            let comment_str = to_json_string(
+              &UserOperation::CreateCommentLike,
+              CommentResponse {
+                comment: comment.clone(),
+                recipient_ids: Vec::new(),
+              },
+            )?;
+            Ok((comment, comment_str))
+          });
+        match read {
+          Ok(comment) => reconciled.comments.push(comment),
+          Err(e) => error!(
+            "Reconciling the score of comment {} failed: {}",
+            comment_id, e
+          ),
+        }
+      }
+
+      addr.do_send(reconciled);
+      Ok(())
+    });
+  }
+
+  /// Runs a consistency audit of the database, which reads through most of it. What it finds is
+  /// logged, and saved for the admins.
+  fn run_consistency_check(&self) {
+    let repair = Settings::get().consistency_check.repair;
+    self.spawn_job("Consistency check", move |conn| {
+      let checks = ConsistencyCheck::run_all(conn, repair)?;
+      let found: i64 = checks.iter().map(|check| check.found).sum();
+      info!("Consistency check found {} inconsistent rows", found);
+      Ok(())
+    });
   }
 }
 
@@ -377,6 +894,34 @@ impl Actor for ChatServer {
   /// We are going to use simple Context, we just need ability to communicate
   /// with other actors.
   type Context = Context<Self>;
+
+  fn started(&mut self, ctx: &mut Self::Context) {
+    ctx.run_interval(PRUNE_INTERVAL, |act, _| act.prune_rate_limit_buckets());
+    ctx.run_interval(REFRESH_RETRY_INTERVAL, |act, _| act.retry_user_refreshes());
+    ctx.run_interval(POST_ACTIVITY_PRUNE_INTERVAL, |act, _| {
+      act.prune_post_activity()
+    });
+    ctx.run_interval(KARMA_DECAY_INTERVAL, |act, _| act.decay_karma());
+    ctx.run_interval(SCORE_RECONCILE_INTERVAL, |act, ctx| {
+      act.reconcile_scores(ctx.address())
+    });
+    ctx.run_interval(SCHEDULED_POST_INTERVAL, |act, _| act.make_scheduled_posts());
+    ctx.run_interval(SCHEDULED_POST_INTERVAL, |act, _| {
+      act.publish_scheduled_posts()
+    });
+    ctx.run_interval(MOD_DIGEST_INTERVAL, |act, _| act.send_mod_digests());
+    ctx.run_interval(BAN_EXPIRY_INTERVAL, |act, _| act.lift_expired_bans());
+    ctx.run_interval(POST_VIEW_FLUSH_INTERVAL, |act, _| act.flush_post_views());
+    ctx.run_interval(COMMUNITY_GROWTH_INTERVAL, |act, _| {
+      act.send_growth_notices()
+    });
+    let interval_hours = Settings::get().consistency_check.interval_hours;
+    if interval_hours > 0 {
+      ctx.run_interval(Duration::from_secs(interval_hours * 3600), |act, _| {
+        act.run_consistency_check()
+      });
+    }
+  }
 }
 
 /// Handler for Connect message.
@@ -388,13 +933,14 @@ impl Handler<Connect> for ChatServer {
   fn handle(&mut self, msg: Connect, _ctx: &mut Context<Self>) -> Self::Result {
     // register session with random id
     let id = self.rng.gen::<usize>();
-    info!("{} joined", &msg.ip);
+    info!("{} joined", ip_privacy::for_log(&msg.ip));
 
     self.sessions.insert(
       id,
       SessionInfo {
         addr: msg.addr,
         ip: msg.ip.to_owned(),
+        rate_limit_multiplier: 1.0,
       },
     );
 
@@ -444,6 +990,41 @@ impl Handler<Disconnect> for ChatServer {
   }
 }
 
+/// Sends the posts and comments read by `reconcile_scores` to their rooms.
+impl Handler<ReconciledScores> for ChatServer {
+  type Result = ();
+
+  fn handle(&mut self, msg: ReconciledScores, _: &mut Context<Self>) {
+    for (post, post_str) in msg.posts {
+      self.send_post_to_rooms(&post, &post_str, None);
+    }
+    for (comment, comment_str) in msg.comments {
+      self.send_comment_to_rooms(&comment, &comment_str, None);
+    }
+  }
+}
+
+/// Handler for CheckRateLimit message.
+impl Handler<CheckRateLimit> for ChatServer {
+  type Result = Result<RateLimitState, RateLimitState>;
+
+  fn handle(&mut self, msg: CheckRateLimit, _: &mut Context<Self>) -> Self::Result {
+    let (rate, per) = msg.type_.limit();
+    let checked = self.check_ip_rate_limit(
+      msg.type_,
+      &msg.ip,
+      msg.rate_limit_multiplier,
+      rate,
+      per,
+      msg.check_only,
+    );
+    if msg.type_ == RateLimitType::Message {
+      count_operation(&msg.op, checked.is_err());
+    }
+    checked
+  }
+}
+
 /// Handler for Message message.
 impl Handler<StandardMessage> for ChatServer {
   type Result = MessageResult<StandardMessage>;
@@ -479,6 +1060,51 @@ where
   Ok(serde_json::to_string(&response)?)
 }
 
+/// Adds what's left of the sender's rate limit to the response they get back. Every response is
+/// a serialized `WebsocketResponse`, so it's added as the object's last field.
+fn with_rate_limit(response: String, rate_limit: Option<RateLimitState>) -> Result<String, Error> {
+  match rate_limit {
+    Some(rate_limit) if response.ends_with('}') => {
+      let mut response = response;
+      response.pop();
+      response.push_str(",\"rate_limit\":");
+      response.push_str(&serde_json::to_string(&rate_limit)?);
+      response.push('}');
+      Ok(response)
+    }
+    _ => Ok(response),
+  }
+}
+
+/// Everyone else's copy of a post, without the sender's vote, and with the scores only following
+/// the site's score visibility, not the sender's own choice.
+fn post_for_others(conn: &PgConnection, post: &PostView) -> Result<PostView, Error> {
+  let mut post_sent = post.clone();
+  post_sent.my_vote = None;
+  post_sent.user_id = None;
+
+  if Site::hides_scores_from(conn, None)? {
+    post_sent.hide_scores();
+  } else if post_sent.score.is_none() {
+    post_sent = PostView::read(conn, ids::PostId(post_sent.id), None)?;
+  }
+  Ok(post_sent)
+}
+
+/// Everyone else's copy of a comment, like `post_for_others`.
+fn comment_for_others(conn: &PgConnection, comment: &CommentView) -> Result<CommentView, Error> {
+  let mut comment_sent = comment.clone();
+  comment_sent.my_vote = None;
+  comment_sent.user_id = None;
+
+  if Site::hides_scores_from(conn, None)? {
+    comment_sent.hide_scores();
+  } else if comment_sent.score.is_none() {
+    comment_sent = CommentView::read(conn, ids::CommentId(comment_sent.id), None)?;
+  }
+  Ok(comment_sent)
+}
+
 fn do_user_operation<'a, Data, Response>(
   op: UserOperation,
   data: &str,
@@ -505,18 +1131,27 @@ fn parse_json_message(chat: &mut ChatServer, msg: StandardMessage) -> Result<Str
 
   let user_operation: UserOperation = UserOperation::from_str(&op)?;
 
+  let ip = chat.sessions.get(&msg.id).map(|info| info.ip.to_owned());
+  let api_token = check_api_token(&conn, &user_operation, &json["data"], ip.as_deref())?;
+  let multiplier = api_token.map_or(1.0, |token| f64::from(token.rate_limit_multiplier));
+  chat.set_rate_limit_multiplier(msg.id, multiplier);
+
   // TODO: none of the chat messages are going to work if stuff is submitted via http api,
   //       need to move that handling elsewhere
 
   // A DDOS check
-  chat.check_rate_limit_message(msg.id, false)?;
+  let rate_limit = chat.check_rate_limit_message(msg.id, false);
+  count_operation(&user_operation, rate_limit.is_err());
+  let rate_limit = rate_limit?;
 
-  match user_operation {
+  let response = match user_operation {
     UserOperation::Login => do_user_operation::<Login, LoginResponse>(user_operation, data, &conn),
     UserOperation::Register => {
       chat.check_rate_limit_register(msg.id, true)?;
       let register: Register = serde_json::from_str(data)?;
-      let res = Oper::new(register).perform(&conn)?;
+      let res = Oper::new(register)
+        .with_ip(chat.session_ip(msg.id))
+        .perform(&conn)?;
       chat.check_rate_limit_register(msg.id, false)?;
       to_json_string(&user_operation, &res)
     }
@@ -540,18 +1175,75 @@ fn parse_json_message(chat: &mut ChatServer, msg: StandardMessage) -> Result<Str
       chat.send_all_message(&res_str, msg.id);
       Ok(res_str)
     }
+    UserOperation::PinToProfile => {
+      do_user_operation::<PinToProfile, ProfilePinsResponse>(user_operation, data, &conn)
+    }
+    UserOperation::ReorderProfilePins => {
+      do_user_operation::<ReorderProfilePins, ProfilePinsResponse>(user_operation, data, &conn)
+    }
+    UserOperation::BlockInstance => {
+      do_user_operation::<BlockInstance, BlockInstanceResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetBlockedInstances => do_user_operation::<
+      GetBlockedInstances,
+      GetBlockedInstancesResponse,
+    >(user_operation, data, &conn),
+    UserOperation::GetMyContent => {
+      do_user_operation::<GetMyContent, GetMyContentResponse>(user_operation, data, &conn)
+    }
     UserOperation::GetReplies => {
       do_user_operation::<GetReplies, GetRepliesResponse>(user_operation, data, &conn)
     }
     UserOperation::GetUserMentions => {
       do_user_operation::<GetUserMentions, GetUserMentionsResponse>(user_operation, data, &conn)
     }
+    UserOperation::GetInbox => {
+      do_user_operation::<GetInbox, GetInboxResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetUnreadCount => {
+      do_user_operation::<GetUnreadCount, GetUnreadCountResponse>(user_operation, data, &conn)
+    }
+    UserOperation::CreateSavedFolder => {
+      do_user_operation::<CreateSavedFolder, GetSavedFoldersResponse>(user_operation, data, &conn)
+    }
+    UserOperation::EditSavedFolder => {
+      do_user_operation::<EditSavedFolder, GetSavedFoldersResponse>(user_operation, data, &conn)
+    }
+    UserOperation::DeleteSavedFolder => {
+      do_user_operation::<DeleteSavedFolder, GetSavedFoldersResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetSavedFolders => {
+      do_user_operation::<GetSavedFolders, GetSavedFoldersResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetSavedItems => {
+      do_user_operation::<GetSavedItems, GetSavedItemsResponse>(user_operation, data, &conn)
+    }
+    UserOperation::CreateKeywordFilter => do_user_operation::<
+      CreateKeywordFilter,
+      GetKeywordFiltersResponse,
+    >(user_operation, data, &conn),
+    UserOperation::DeleteKeywordFilter => do_user_operation::<
+      DeleteKeywordFilter,
+      GetKeywordFiltersResponse,
+    >(user_operation, data, &conn),
+    UserOperation::GetKeywordFilters => {
+      do_user_operation::<GetKeywordFilters, GetKeywordFiltersResponse>(user_operation, data, &conn)
+    }
+    UserOperation::BlockUser => {
+      do_user_operation::<BlockUser, BlockUserResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetBlockedUsers => {
+      do_user_operation::<GetBlockedUsers, GetBlockedUsersResponse>(user_operation, data, &conn)
+    }
     UserOperation::EditUserMention => {
       do_user_operation::<EditUserMention, UserMentionResponse>(user_operation, data, &conn)
     }
     UserOperation::MarkAllAsRead => {
       do_user_operation::<MarkAllAsRead, GetRepliesResponse>(user_operation, data, &conn)
     }
+    UserOperation::MarkAsRead => {
+      do_user_operation::<MarkAsRead, MarkAsReadResponse>(user_operation, data, &conn)
+    }
     UserOperation::GetCommunity => {
       let get_community: GetCommunity = serde_json::from_str(data)?;
       let mut res = Oper::new(get_community).perform(&conn)?;
@@ -584,7 +1276,11 @@ fn parse_json_message(chat: &mut ChatServer, msg: StandardMessage) -> Result<Str
       community_sent.community.user_id = None;
       community_sent.community.subscribed = None;
       let community_sent_str = to_json_string(&user_operation, &community_sent)?;
-      chat.send_community_room_message(community_sent.community.id, &community_sent_str, msg.id);
+      chat.send_community_room_message(
+        community_sent.community.id,
+        &community_sent_str,
+        Some(msg.id),
+      );
       to_json_string(&user_operation, &res)
     }
     UserOperation::FollowCommunity => {
@@ -594,12 +1290,19 @@ fn parse_json_message(chat: &mut ChatServer, msg: StandardMessage) -> Result<Str
       GetFollowedCommunities,
       GetFollowedCommunitiesResponse,
     >(user_operation, data, &conn),
+    UserOperation::BlockCommunity => {
+      do_user_operation::<BlockCommunity, BlockCommunityResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetBlockedCommunities => do_user_operation::<
+      GetBlockedCommunities,
+      GetBlockedCommunitiesResponse,
+    >(user_operation, data, &conn),
     UserOperation::BanFromCommunity => {
       let ban_from_community: BanFromCommunity = serde_json::from_str(data)?;
       let community_id = ban_from_community.community_id;
       let res = Oper::new(ban_from_community).perform(&conn)?;
       let res_str = to_json_string(&user_operation, &res)?;
-      chat.send_community_room_message(community_id, &res_str, msg.id);
+      chat.send_community_room_message(community_id, &res_str, Some(msg.id));
       Ok(res_str)
     }
     UserOperation::AddModToCommunity => {
@@ -607,12 +1310,15 @@ fn parse_json_message(chat: &mut ChatServer, msg: StandardMessage) -> Result<Str
       let community_id = mod_add_to_community.community_id;
       let res = Oper::new(mod_add_to_community).perform(&conn)?;
       let res_str = to_json_string(&user_operation, &res)?;
-      chat.send_community_room_message(community_id, &res_str, msg.id);
+      chat.send_community_room_message(community_id, &res_str, Some(msg.id));
       Ok(res_str)
     }
     UserOperation::ListCategories => {
       do_user_operation::<ListCategories, ListCategoriesResponse>(user_operation, data, &conn)
     }
+    UserOperation::ListLanguages => {
+      do_user_operation::<ListLanguages, ListLanguagesResponse>(user_operation, data, &conn)
+    }
     UserOperation::GetPost => {
       let get_post: GetPost = serde_json::from_str(data)?;
       let post_id = get_post.id;
@@ -648,37 +1354,44 @@ fn parse_json_message(chat: &mut ChatServer, msg: StandardMessage) -> Result<Str
     UserOperation::CreatePost => {
       chat.check_rate_limit_post(msg.id, true)?;
       let create_post: CreatePost = serde_json::from_str(data)?;
-      let res = Oper::new(create_post).perform(&conn)?;
+      let res = Oper::new(create_post)
+        .with_ip(chat.session_ip(msg.id))
+        .perform(&conn)?;
       chat.check_rate_limit_post(msg.id, false)?;
 
-      chat.post_sends(UserOperation::CreatePost, res, msg.id)
+      chat.post_sends(&conn, UserOperation::CreatePost, res, msg.id)
     }
     UserOperation::CreatePostLike => {
       let create_post_like: CreatePostLike = serde_json::from_str(data)?;
       let res = Oper::new(create_post_like).perform(&conn)?;
 
-      chat.post_sends(UserOperation::CreatePostLike, res, msg.id)
+      chat.post_score_sends(&conn, UserOperation::CreatePostLike, res, msg.id)
     }
     UserOperation::EditPost => {
       let edit_post: EditPost = serde_json::from_str(data)?;
       let res = Oper::new(edit_post).perform(&conn)?;
 
-      chat.post_sends(UserOperation::EditPost, res, msg.id)
+      chat.post_sends(&conn, UserOperation::EditPost, res, msg.id)
     }
     UserOperation::SavePost => {
       do_user_operation::<SavePost, PostResponse>(user_operation, data, &conn)
     }
+    UserOperation::SaveMediaAltText => {
+      do_user_operation::<SaveMediaAltText, MediaAltTextResponse>(user_operation, data, &conn)
+    }
     UserOperation::CreateComment => {
       let create_comment: CreateComment = serde_json::from_str(data)?;
-      let res = Oper::new(create_comment).perform(&conn)?;
+      let res = Oper::new(create_comment)
+        .with_ip(chat.session_ip(msg.id))
+        .perform(&conn)?;
 
-      chat.comment_sends(UserOperation::CreateComment, res, msg.id)
+      chat.comment_sends(&conn, UserOperation::CreateComment, res, msg.id)
     }
     UserOperation::EditComment => {
       let edit_comment: EditComment = serde_json::from_str(data)?;
       let res = Oper::new(edit_comment).perform(&conn)?;
 
-      chat.comment_sends(UserOperation::EditComment, res, msg.id)
+      chat.comment_sends(&conn, UserOperation::EditComment, res, msg.id)
     }
     UserOperation::SaveComment => {
       do_user_operation::<SaveComment, CommentResponse>(user_operation, data, &conn)
@@ -687,11 +1400,14 @@ fn parse_json_message(chat: &mut ChatServer, msg: StandardMessage) -> Result<Str
       let create_comment_like: CreateCommentLike = serde_json::from_str(data)?;
       let res = Oper::new(create_comment_like).perform(&conn)?;
 
-      chat.comment_sends(UserOperation::CreateCommentLike, res, msg.id)
+      chat.comment_score_sends(&conn, UserOperation::CreateCommentLike, res, msg.id)
     }
     UserOperation::GetModlog => {
       do_user_operation::<GetModlog, GetModlogResponse>(user_operation, data, &conn)
     }
+    UserOperation::GetModlogFeed => {
+      do_user_operation::<GetModlogFeed, GetModlogFeedResponse>(user_operation, data, &conn)
+    }
     UserOperation::CreateSite => {
       do_user_operation::<CreateSite, SiteResponse>(user_operation, data, &conn)
     }
@@ -721,12 +1437,168 @@ fn parse_json_message(chat: &mut ChatServer, msg: StandardMessage) -> Result<Str
     UserOperation::Search => {
       do_user_operation::<Search, SearchResponse>(user_operation, data, &conn)
     }
+    UserOperation::ResolveObject => {
+      do_user_operation::<ResolveObject, ResolveObjectResponse>(user_operation, data, &conn)
+    }
+    UserOperation::PrefetchActors => {
+      do_user_operation::<PrefetchActors, PrefetchActorsResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetApubId => {
+      do_user_operation::<GetApubId, GetApubIdResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetInstanceImpact => {
+      do_user_operation::<GetInstanceImpact, GetInstanceImpactResponse>(user_operation, data, &conn)
+    }
+    UserOperation::ListInboxRejections => do_user_operation::<
+      ListInboxRejections,
+      ListInboxRejectionsResponse,
+    >(user_operation, data, &conn),
+    UserOperation::GetFederationMetrics => do_user_operation::<
+      GetFederationMetrics,
+      GetFederationMetricsResponse,
+    >(user_operation, data, &conn),
+    UserOperation::GetOperationMetrics => do_user_operation::<
+      GetOperationMetrics,
+      GetOperationMetricsResponse,
+    >(user_operation, data, &conn),
+    UserOperation::GetCommentContext => {
+      do_user_operation::<GetCommentContext, GetCommentContextResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetCommentTree => {
+      do_user_operation::<GetCommentTree, GetCommentTreeResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetPostEdits => {
+      do_user_operation::<GetPostEdits, GetPostEditsResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetCommentEdits => {
+      do_user_operation::<GetCommentEdits, GetCommentEditsResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetUserActivity => {
+      do_user_operation::<GetUserActivity, GetUserActivityResponse>(user_operation, data, &conn)
+    }
+    UserOperation::ListReservedCommunityNames => do_user_operation::<
+      ListReservedCommunityNames,
+      ListReservedCommunityNamesResponse,
+    >(user_operation, data, &conn),
+    UserOperation::ReserveCommunityName => do_user_operation::<
+      ReserveCommunityName,
+      ReservedCommunityNameResponse,
+    >(user_operation, data, &conn),
+    UserOperation::DeleteReservedCommunityName => do_user_operation::<
+      DeleteReservedCommunityName,
+      ReservedCommunityNameResponse,
+    >(user_operation, data, &conn),
+    UserOperation::GetConsistencyReport => do_user_operation::<
+      GetConsistencyReport,
+      ConsistencyReportResponse,
+    >(user_operation, data, &conn),
+    UserOperation::RunConsistencyCheck => do_user_operation::<
+      RunConsistencyCheck,
+      ConsistencyReportResponse,
+    >(user_operation, data, &conn),
+    UserOperation::CreateModNote => {
+      do_user_operation::<CreateModNote, ModNoteResponse>(user_operation, data, &conn)
+    }
+    UserOperation::EditModNote => {
+      do_user_operation::<EditModNote, ModNoteResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetModNotes => {
+      do_user_operation::<GetModNotes, GetModNotesResponse>(user_operation, data, &conn)
+    }
+    UserOperation::CreateScheduledPost => do_user_operation::<
+      CreateScheduledPost,
+      GetScheduledPostsResponse,
+    >(user_operation, data, &conn),
+    UserOperation::DeleteScheduledPost => do_user_operation::<
+      DeleteScheduledPost,
+      GetScheduledPostsResponse,
+    >(user_operation, data, &conn),
+    UserOperation::SaveModDigest => {
+      do_user_operation::<SaveModDigest, GetModDigestsResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetModDigests => {
+      do_user_operation::<GetModDigests, GetModDigestsResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetScheduledPosts => {
+      do_user_operation::<GetScheduledPosts, GetScheduledPostsResponse>(user_operation, data, &conn)
+    }
+    UserOperation::CreateReport => {
+      do_user_operation::<CreateReport, ReportResponse>(user_operation, data, &conn)
+    }
+    UserOperation::ListReports => {
+      do_user_operation::<ListReports, ListReportsResponse>(user_operation, data, &conn)
+    }
+    UserOperation::ResolveReport => {
+      do_user_operation::<ResolveReport, ResolveReportResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetModerationNotices => do_user_operation::<
+      GetModerationNotices,
+      GetModerationNoticesResponse,
+    >(user_operation, data, &conn),
     UserOperation::TransferCommunity => {
       do_user_operation::<TransferCommunity, GetCommunityResponse>(user_operation, data, &conn)
     }
     UserOperation::TransferSite => {
       do_user_operation::<TransferSite, GetSiteResponse>(user_operation, data, &conn)
     }
+    UserOperation::LegalHold => {
+      do_user_operation::<LegalHold, LegalHoldResponse>(user_operation, data, &conn)
+    }
+    UserOperation::ImportIpBlocks => {
+      do_user_operation::<ImportIpBlocks, ImportIpBlocksResponse>(user_operation, data, &conn)
+    }
+    UserOperation::ListIpBlocks => {
+      do_user_operation::<ListIpBlocks, ListIpBlocksResponse>(user_operation, data, &conn)
+    }
+    UserOperation::DeleteIpBlock => {
+      do_user_operation::<DeleteIpBlock, IpBlockResponse>(user_operation, data, &conn)
+    }
+    UserOperation::CreateApiToken => {
+      do_user_operation::<CreateApiToken, CreateApiTokenResponse>(user_operation, data, &conn)
+    }
+    UserOperation::ListApiTokens => {
+      do_user_operation::<ListApiTokens, ListApiTokensResponse>(user_operation, data, &conn)
+    }
+    UserOperation::ListMyTokens => {
+      do_user_operation::<ListMyTokens, ListMyTokensResponse>(user_operation, data, &conn)
+    }
+    UserOperation::DeleteApiToken => {
+      do_user_operation::<DeleteApiToken, ListApiTokensResponse>(user_operation, data, &conn)
+    }
+    UserOperation::SetApiTokenRateLimit => {
+      do_user_operation::<SetApiTokenRateLimit, ApiTokenResponse>(user_operation, data, &conn)
+    }
+    UserOperation::RegisterOAuthClient => do_user_operation::<
+      RegisterOAuthClient,
+      RegisterOAuthClientResponse,
+    >(user_operation, data, &conn),
+    UserOperation::ListOAuthClients => {
+      do_user_operation::<ListOAuthClients, ListOAuthClientsResponse>(user_operation, data, &conn)
+    }
+    UserOperation::DeleteOAuthClient => {
+      do_user_operation::<DeleteOAuthClient, ListOAuthClientsResponse>(user_operation, data, &conn)
+    }
+    UserOperation::GetOAuthConsent => {
+      do_user_operation::<GetOAuthConsent, GetOAuthConsentResponse>(user_operation, data, &conn)
+    }
+    UserOperation::AuthorizeOAuthClient => do_user_operation::<
+      AuthorizeOAuthClient,
+      AuthorizeOAuthClientResponse,
+    >(user_operation, data, &conn),
+    UserOperation::ImpersonateUser => {
+      do_user_operation::<ImpersonateUser, CreateApiTokenResponse>(user_operation, data, &conn)
+    }
+    UserOperation::ListImpersonations => do_user_operation::<
+      ListImpersonations,
+      ListImpersonationsResponse,
+    >(user_operation, data, &conn),
+    UserOperation::RevokeImpersonation => do_user_operation::<
+      RevokeImpersonation,
+      ListImpersonationsResponse,
+    >(user_operation, data, &conn),
+    UserOperation::TranslateContent => {
+      do_user_operation::<TranslateContent, TranslateContentResponse>(user_operation, data, &conn)
+    }
     UserOperation::DeleteAccount => {
       do_user_operation::<DeleteAccount, LoginResponse>(user_operation, data, &conn)
     }
@@ -757,5 +1629,7 @@ fn parse_json_message(chat: &mut ChatServer, msg: StandardMessage) -> Result<Str
       chat.join_user_room(res.user_id, msg.id);
       to_json_string(&user_operation, &res)
     }
-  }
+  }?;
+
+  with_rate_limit(response, rate_limit)
 }
@@ -0,0 +1,282 @@
+//! The activitypub payloads, without a database or network.
+//!
+//! Every type of activity that's sent out is compared with its golden file in `tests/golden`, where
+//! `{actor_domain}` stands for the configured one. Run with `UPDATE_GOLDEN=1` to write the golden
+//! files from what's built instead, and check the diff.
+//!
+//! `tests/corpus` has activities the way Mastodon, Pleroma and kbin send them, which the inbox has
+//! to accept and make the same sense of as ours.
+
+use chrono::{NaiveDate, NaiveDateTime};
+use lemmy_server::apub::community::update_group_activity;
+use lemmy_server::apub::featured::{featured_activity, is_remote_featured};
+use lemmy_server::apub::flag::flag_activity;
+use lemmy_server::apub::follow::{accept_follow_activity, is_follow};
+use lemmy_server::apub::inbox::{is_create_video, validate_activity};
+use lemmy_server::apub::make_apub_endpoint;
+use lemmy_server::apub::moderation::is_moderation;
+use lemmy_server::apub::post::attachments_from_object;
+use lemmy_server::apub::vote::{is_vote, vote_of, Vote};
+use lemmy_server::db::community::Community;
+use lemmy_server::db::post::Post;
+use lemmy_server::db::user::User_;
+use lemmy_server::db::{ListingType, PostAttachmentKind, PostVisibility, SortType};
+use lemmy_server::settings::Settings;
+use serde_json::Value;
+use std::{env, fs};
+
+fn sent() -> NaiveDateTime {
+  NaiveDate::from_ymd(2020, 4, 28).and_hms(12, 0, 0)
+}
+
+fn community() -> Community {
+  Community {
+    id: 42,
+    name: "main".into(),
+    title: "Main".into(),
+    description: Some("The main community".into()),
+    category_id: 1,
+    creator_id: 52,
+    removed: false,
+    published: sent(),
+    updated: None,
+    deleted: false,
+    nsfw: false,
+    rules: None,
+    require_rules_ack: false,
+    require_alt_text: false,
+    local_only: false,
+    hidden: false,
+    actor_id: None,
+    report_auto_remove_threshold: None,
+  }
+}
+
+fn post() -> Post {
+  Post {
+    id: 62,
+    name: "A test post".into(),
+    url: None,
+    body: None,
+    creator_id: 52,
+    community_id: 42,
+    published: sent(),
+    removed: false,
+    locked: false,
+    stickied: true,
+    nsfw: false,
+    deleted: false,
+    updated: None,
+    embed_title: None,
+    embed_description: None,
+    embed_html: None,
+    thumbnail_url: None,
+    legal_hold: false,
+    slug: "a-test-post".into(),
+    excerpt: None,
+    alt_text: None,
+    visibility: PostVisibility::Public as i16,
+    ap_id: None,
+    generated: false,
+    language_id: None,
+    scheduled_publish_time: None,
+  }
+}
+
+fn user() -> User_ {
+  User_ {
+    id: 52,
+    name: "thom".into(),
+    fedi_name: "rrf".into(),
+    preferred_username: None,
+    password_encrypted: "here".into(),
+    email: None,
+    matrix_user_id: None,
+    avatar: None,
+    published: sent(),
+    admin: false,
+    banned: false,
+    updated: None,
+    show_nsfw: false,
+    theme: "darkly".into(),
+    default_sort_type: SortType::Hot as i16,
+    default_listing_type: ListingType::Subscribed as i16,
+    lang: "browser".into(),
+    show_avatars: true,
+    send_notifications_to_email: false,
+    settings: serde_json::json!({}),
+    legal_hold: false,
+    hide_profile: false,
+    actor_id: None,
+    hide_activity: false,
+    last_refreshed: None,
+    refresh_failures: 0,
+    refresh_retry_at: None,
+    blended_discovery_percent: 20,
+    blended_discovery_local: false,
+    show_scores: true,
+    bot_account: false,
+    show_generated: true,
+    ban_expires: None,
+    collapse_crossposts: false,
+  }
+}
+
+fn assert_golden(name: &str, actual: &Value) {
+  let path = format!("{}/tests/golden/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+  let actor_domain = Settings::get().actor_domain();
+  if env::var("UPDATE_GOLDEN").is_ok() {
+    let golden = serde_json::to_string_pretty(actual)
+      .unwrap()
+      .replace(&actor_domain, "{actor_domain}");
+    fs::write(&path, golden + "\n").unwrap();
+    return;
+  }
+  let golden = fs::read_to_string(&path)
+    .unwrap()
+    .replace("{actor_domain}", &actor_domain);
+  let expected: Value = serde_json::from_str(&golden).unwrap();
+  assert_eq!(&expected, actual, "{} doesn't match its golden file", name);
+}
+
+fn corpus(name: &str) -> Value {
+  let path = format!("{}/tests/corpus/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+  let body = fs::read(&path).unwrap();
+  validate_activity(&body, &Settings::get().federation.inbox)
+    .unwrap_or_else(|rejection| panic!("{} was rejected: {:?}", name, rejection))
+}
+
+#[test]
+fn test_outgoing_activities() {
+  let community = community();
+  assert_golden(
+    "add_featured",
+    &featured_activity(&community, &post(), true, sent()),
+  );
+  assert_golden(
+    "remove_featured",
+    &featured_activity(&community, &post(), false, sent()),
+  );
+  assert_golden("update_group", &update_group_activity(&community, sent()));
+  assert_golden(
+    "accept_follow",
+    &accept_follow_activity(&community, &corpus("mastodon/follow"), sent()),
+  );
+  assert_golden(
+    "flag",
+    &flag_activity(
+      &user(),
+      make_apub_endpoint("flag", 1),
+      vec![
+        "https://remote.example/users/spammer".into(),
+        "https://remote.example/users/spammer/statuses/1".into(),
+      ],
+      Some("Spam".into()),
+    ),
+  );
+}
+
+#[test]
+fn test_corpus_votes() {
+  let comment = "https://lemmy.example/federation/comment/7";
+  let post = "https://lemmy.example/federation/post/62";
+  let vote = |actor_id, object_id, score, undo| Vote {
+    actor_id,
+    object_id,
+    score,
+    undo,
+  };
+
+  let like = corpus("mastodon/like");
+  assert!(is_vote(&like));
+  assert_eq!(
+    Some(vote(
+      "https://mastodon.example/users/alice",
+      comment,
+      1,
+      false
+    )),
+    vote_of(&like)
+  );
+  // Mastodon has the like in its undo
+  let undo = corpus("mastodon/undo_like");
+  assert!(is_vote(&undo));
+  assert_eq!(
+    Some(vote(
+      "https://mastodon.example/users/alice",
+      comment,
+      1,
+      true
+    )),
+    vote_of(&undo)
+  );
+
+  let like = corpus("pleroma/like");
+  assert_eq!(
+    Some(vote("https://pleroma.example/users/bob", post, 1, false)),
+    vote_of(&like)
+  );
+  // Pleroma only has its id, so it's fetched
+  let mut undo = corpus("pleroma/undo_like");
+  assert!(is_vote(&undo));
+  assert_eq!(None, vote_of(&undo));
+  assert_eq!(like["id"], undo["object"]);
+  undo["object"] = like;
+  assert_eq!(
+    Some(vote("https://pleroma.example/users/bob", post, 1, true)),
+    vote_of(&undo)
+  );
+
+  assert_eq!(
+    Some(vote("https://kbin.example/u/carol", comment, 1, false)),
+    vote_of(&corpus("kbin/like"))
+  );
+  assert_eq!(
+    Some(vote("https://kbin.example/u/carol", post, -1, false)),
+    vote_of(&corpus("kbin/dislike"))
+  );
+
+  for name in &["mastodon/like", "pleroma/undo_like", "kbin/dislike"] {
+    let activity = corpus(name);
+    assert!(!is_moderation(&activity), "{} isn't moderation", name);
+    assert!(!is_remote_featured(&activity), "{} isn't a sticky", name);
+  }
+}
+
+#[test]
+fn test_corpus_follows() {
+  // Mastodon has the follow in its undo
+  for name in &["mastodon/follow", "mastodon/undo_follow"] {
+    let activity = corpus(name);
+    assert!(is_follow(&activity), "{} is a follow", name);
+    assert!(!is_vote(&activity), "{} isn't a vote", name);
+    assert!(!is_moderation(&activity), "{} isn't moderation", name);
+  }
+  assert!(!is_follow(&corpus("mastodon/undo_like")));
+}
+
+#[test]
+fn test_corpus_attachments() {
+  // Mastodon's image is a Document
+  let create = corpus("mastodon/create_note");
+  assert!(!is_create_video(&create));
+  let attachments = attachments_from_object(62, &create["object"]);
+  assert_eq!(1, attachments.len());
+  assert_eq!(PostAttachmentKind::Image as i16, attachments[0].kind);
+  assert_eq!(Some("image/png".into()), attachments[0].mime_type);
+  assert_eq!(
+    Some("A cat asleep on a keyboard".into()),
+    attachments[0].name
+  );
+
+  let create = corpus("kbin/create_page");
+  assert!(!is_create_video(&create));
+  let attachments = attachments_from_object(62, &create["object"]);
+  assert_eq!(1, attachments.len());
+  assert_eq!(PostAttachmentKind::Image as i16, attachments[0].kind);
+  assert_eq!(
+    "https://kbin.example/media/cc/3f/cc3f.jpg",
+    attachments[0].url
+  );
+  assert_eq!(Some("A cat in a patch of sun".into()), attachments[0].name);
+}
@@ -0,0 +1,368 @@
+//! End-to-end federation tests, against a pair of in-process instances with their own hostnames.
+//!
+//! Something happens on one instance, reaches the other one's shared inbox, and the other one's
+//! API shows what it made of it. Lemmy doesn't send its users' follows, votes and moderation yet, so
+//! those activities are sent the way another instance would, by users of the other instance; the
+//! receiving one still fetches them, and their actors, from there.
+
+#[macro_use]
+extern crate diesel_migrations;
+#[macro_use]
+extern crate lazy_static;
+
+mod support;
+
+use actix_web::http::{header, StatusCode};
+use serde_json::{json, Value};
+use support::TestInstance;
+
+const CONTEXT: &str = "https://www.w3.org/ns/activitystreams";
+
+async fn create_community(instance: &TestInstance, name: &str, jwt: &str) -> i64 {
+  let (status, res) = instance
+    .post(
+      "/api/v1/community",
+      json!({
+        "name": name,
+        "title": name,
+        "description": "Served to other instances",
+        "category_id": 1,
+        "nsfw": false,
+        "auth": jwt,
+      }),
+    )
+    .await;
+  assert_eq!(StatusCode::OK, status, "{}", res);
+  res["community"]["id"].as_i64().unwrap()
+}
+
+async fn create_post(instance: &TestInstance, community_id: i64, jwt: &str) -> Value {
+  let (status, res) = instance
+    .post(
+      "/api/v1/post",
+      json!({
+        "name": "A federated post",
+        "body": "Hello from alpha",
+        "nsfw": false,
+        "community_id": community_id,
+        "auth": jwt,
+      }),
+    )
+    .await;
+  assert_eq!(StatusCode::OK, status, "{}", res);
+  res["post"].to_owned()
+}
+
+async fn get_post(instance: &TestInstance, post_id: i64) -> Value {
+  let (status, res) = instance.get(&format!("/api/v1/post?id={}", post_id)).await;
+  assert_eq!(StatusCode::OK, status, "{}", res);
+  res
+}
+
+async fn subscribers(instance: &TestInstance, name: &str) -> i64 {
+  let (_, res) = instance
+    .get(&format!("/api/v1/community?name={}", name))
+    .await;
+  res["community"]["number_of_subscribers"].as_i64().unwrap()
+}
+
+#[actix_rt::test]
+async fn test_community_and_user_actors() {
+  let (alpha, beta) = TestInstance::pair();
+  let jwt = alpha.register("alpha_user").await;
+  create_community(&alpha, "alpha_community", &jwt).await;
+
+  let (status, group) = alpha.get("/federation/c/alpha_community").await;
+  assert_eq!(StatusCode::OK, status);
+  assert_eq!(alpha.url("/federation/c/alpha_community"), group["id"]);
+  assert_eq!("Served to other instances", group["summary"]);
+
+  let (status, person) = alpha.get("/federation/u/alpha_user").await;
+  assert_eq!(StatusCode::OK, status);
+  assert_eq!(alpha.actor_id("alpha_user"), person["id"]);
+  assert_eq!(
+    alpha.url("/federation/inbox"),
+    person["endpoints"]["sharedInbox"]
+  );
+
+  // Each instance only serves its own actors
+  let (status, _) = beta.get("/federation/c/alpha_community").await;
+  assert_eq!(StatusCode::NOT_FOUND, status);
+  let (status, _) = beta.get("/federation/u/alpha_user").await;
+  assert_eq!(StatusCode::NOT_FOUND, status);
+}
+
+#[actix_rt::test]
+async fn test_follow() {
+  let (alpha, beta) = TestInstance::pair();
+  let jwt = alpha.setup_site("follow_creator").await;
+  let community_id = create_community(&alpha, "followed", &jwt).await;
+  beta.register("beta_follower").await;
+  let community = alpha.url("/federation/c/followed");
+  let subscribers_before = subscribers(&alpha, "followed").await;
+
+  let follow = json!({
+    "@context": CONTEXT,
+    "id": beta.url("/activities/follow/1"),
+    "type": "Follow",
+    "actor": beta.actor_id("beta_follower"),
+    "object": community,
+  });
+  beta.send(&alpha, follow.to_owned()).await;
+
+  // The follower is fetched from beta, and alpha answers with an `Accept` to beta's inbox
+  let accept = beta.delivered("Accept").await;
+  assert_eq!(community, accept["actor"]);
+  assert_eq!(follow["id"], accept["object"]["id"]);
+  assert_eq!(
+    subscribers_before + 1,
+    subscribers(&alpha, "followed").await
+  );
+  let (status, followers) = alpha.get("/federation/c/followed/followers").await;
+  assert_eq!(StatusCode::OK, status);
+  assert!(followers["items"]
+    .as_array()
+    .unwrap()
+    .contains(&json!(beta.actor_id("beta_follower"))));
+
+  // Stickying a post is sent to the instances of the remote followers
+  let post = create_post(&alpha, community_id, &jwt).await;
+  let (status, res) = alpha
+    .put(
+      "/api/v1/post",
+      json!({
+        "edit_id": post["id"],
+        "creator_id": post["creator_id"],
+        "community_id": community_id,
+        "name": post["name"],
+        "body": post["body"],
+        "stickied": true,
+        "nsfw": false,
+        "auth": jwt,
+      }),
+    )
+    .await;
+  assert_eq!(StatusCode::OK, status, "{}", res);
+  let add = beta.delivered("Add").await;
+  assert_eq!(
+    json!(alpha.url(&format!("/federation/post/{}", post["id"]))),
+    add["object"]
+  );
+  assert_eq!(json!(format!("{}/featured", community)), add["target"]);
+
+  beta
+    .send(
+      &alpha,
+      json!({
+        "@context": CONTEXT,
+        "id": beta.url("/activities/follow/1/undo"),
+        "type": "Undo",
+        "actor": beta.actor_id("beta_follower"),
+        "object": follow,
+      }),
+    )
+    .await;
+  assert_eq!(subscribers_before, subscribers(&alpha, "followed").await);
+}
+
+#[actix_rt::test]
+async fn test_post() {
+  let (alpha, beta) = TestInstance::pair();
+  let jwt = alpha.setup_site("post_admin").await;
+  let community_id = create_community(&alpha, "videos", &jwt).await;
+  beta.register("beta_poster").await;
+
+  // Lemmy only takes posts from other instances as PeerTube's videos
+  let video = json!({
+    "@context": CONTEXT,
+    "id": beta.url("/videos/watch/1"),
+    "type": "Video",
+    "name": "A video from beta",
+    "content": "Filmed on beta",
+    "attributedTo": [beta.actor_id("beta_poster")],
+    "to": ["https://www.w3.org/ns/activitystreams#Public"],
+    "cc": [alpha.url("/federation/c/videos")],
+    "url": [{"type": "Link", "mediaType": "text/html", "href": beta.url("/videos/watch/1")}],
+  });
+  beta.publish(&video);
+  beta
+    .send(
+      &alpha,
+      json!({
+        "@context": CONTEXT,
+        "id": beta.url("/videos/watch/1/activity"),
+        "type": "Create",
+        "actor": beta.actor_id("beta_poster"),
+        "to": ["https://www.w3.org/ns/activitystreams#Public"],
+        "cc": [alpha.url("/federation/c/videos")],
+        "object": video["id"],
+      }),
+    )
+    .await;
+
+  let (status, res) = alpha
+    .get(&format!(
+      "/api/v1/post/list?type_=Community&sort=New&community_id={}",
+      community_id
+    ))
+    .await;
+  assert_eq!(StatusCode::OK, status, "{}", res);
+  let posts = res["posts"].as_array().unwrap();
+  assert_eq!(1, posts.len());
+  assert_eq!("A video from beta", posts[0]["name"]);
+  assert_eq!("Filmed on beta", posts[0]["body"]);
+  assert_eq!("beta_poster", posts[0]["creator_name"]);
+}
+
+#[actix_rt::test]
+async fn test_comment_vote_and_remove() {
+  let (alpha, beta) = TestInstance::pair();
+  let jwt = alpha.setup_site("poster").await;
+  let commenter_jwt = alpha.register("commenter").await;
+  beta.register("beta_voter").await;
+  let voter = beta.actor_id("beta_voter");
+
+  // The admin moderates the main community
+  let (_, main) = alpha.get("/api/v1/community?name=main").await;
+  let community_id = main["community"]["id"].as_i64().unwrap();
+  let post = create_post(&alpha, community_id, &jwt).await;
+  let post_id = post["id"].as_i64().unwrap();
+  let (status, res) = alpha
+    .post(
+      "/api/v1/comment",
+      json!({ "content": "A reply", "post_id": post_id, "auth": commenter_jwt }),
+    )
+    .await;
+  assert_eq!(StatusCode::OK, status, "{}", res);
+  let comment_id = res["comment"]["id"].as_i64().unwrap();
+  let post_url = alpha.url(&format!("/federation/post/{}", post_id));
+  let comment_url = alpha.url(&format!("/federation/comment/{}", comment_id));
+
+  // What beta would fetch of them
+  let (status, page) = alpha.get(&format!("/federation/post/{}", post_id)).await;
+  assert_eq!(StatusCode::OK, status);
+  assert_eq!(post_url, page["id"]);
+  assert_eq!("Hello from alpha", page["content"]);
+  let (status, note) = alpha
+    .get(&format!("/federation/comment/{}", comment_id))
+    .await;
+  assert_eq!(StatusCode::OK, status);
+  assert_eq!(comment_url, note["id"]);
+
+  let like = json!({
+    "@context": CONTEXT,
+    "id": beta.url("/activities/like/1"),
+    "type": "Like",
+    "actor": voter,
+    "object": post_url,
+  });
+  beta.send(&alpha, like.to_owned()).await;
+  beta
+    .send(
+      &alpha,
+      json!({
+        "@context": CONTEXT,
+        "id": beta.url("/activities/dislike/2"),
+        "type": "Dislike",
+        "actor": voter,
+        "object": comment_url,
+      }),
+    )
+    .await;
+  let res = get_post(&alpha, post_id).await;
+  // Both include their creator's own upvote
+  assert_eq!(2, res["post"]["score"]);
+  assert_eq!(0, res["comments"][0]["score"]);
+
+  beta
+    .send(
+      &alpha,
+      json!({
+        "@context": CONTEXT,
+        "id": beta.url("/activities/like/1/undo"),
+        "type": "Undo",
+        "actor": voter,
+        "object": like,
+      }),
+    )
+    .await;
+  assert_eq!(1, get_post(&alpha, post_id).await["post"]["score"]);
+
+  // Removing is left to the community's moderators, so the voter is made one first
+  let remove_comment = json!({
+    "@context": CONTEXT,
+    "id": beta.url("/activities/remove/3"),
+    "type": "Remove",
+    "actor": voter,
+    "object": comment_url,
+    "summary": "Off topic",
+  });
+  beta.send(&alpha, remove_comment.to_owned()).await;
+  let (_, modlog) = alpha
+    .get(&format!("/api/v1/modlog?community_id={}", community_id))
+    .await;
+  assert!(modlog["removed_comments"].as_array().unwrap().is_empty());
+
+  let voter_id = alpha.user_id("beta_voter").await;
+  let (status, res) = alpha
+    .post(
+      "/api/v1/community/mod",
+      json!({
+        "community_id": community_id,
+        "user_id": voter_id,
+        "added": true,
+        "auth": jwt,
+      }),
+    )
+    .await;
+  assert_eq!(StatusCode::OK, status, "{}", res);
+
+  let mut remove_comment = remove_comment;
+  remove_comment["id"] = json!(beta.url("/activities/remove/4"));
+  beta.send(&alpha, remove_comment).await;
+  beta
+    .send(
+      &alpha,
+      json!({
+        "@context": CONTEXT,
+        "id": beta.url("/activities/remove/5"),
+        "type": "Remove",
+        "actor": voter,
+        "object": post_url,
+        "summary": "Spam",
+      }),
+    )
+    .await;
+
+  let (_, modlog) = alpha
+    .get(&format!("/api/v1/modlog?community_id={}", community_id))
+    .await;
+  assert_eq!(comment_id, modlog["removed_comments"][0]["comment_id"]);
+  assert_eq!("Off topic", modlog["removed_comments"][0]["reason"]);
+  assert_eq!("beta_voter", modlog["removed_comments"][0]["mod_user_name"]);
+  assert_eq!(post_id, modlog["removed_posts"][0]["post_id"]);
+  assert_eq!("Spam", modlog["removed_posts"][0]["reason"]);
+  assert_eq!(true, get_post(&alpha, post_id).await["post"]["removed"]);
+}
+
+#[actix_rt::test]
+async fn test_conditional_fetch() {
+  let (alpha, _beta) = TestInstance::pair();
+  alpha.register("conditional").await;
+
+  let res = alpha.get_response("/federation/u/conditional", &[]).await;
+  assert_eq!(StatusCode::OK, res.status());
+  assert!(res.headers().contains_key(header::CACHE_CONTROL));
+  let etag = res
+    .headers()
+    .get(header::ETAG)
+    .unwrap()
+    .to_str()
+    .unwrap()
+    .to_owned();
+
+  let res = alpha
+    .get_response("/federation/u/conditional", &[("If-None-Match", &etag)])
+    .await;
+  assert_eq!(StatusCode::NOT_MODIFIED, res.status());
+}
@@ -0,0 +1,43 @@
+//! The check that served images have no metadata, against the images in `tests/images`.
+//!
+//! `gps.jpg` has the location of a photo in its exif, `xmp.jpg` in its xmp, `exif.png` and
+//! `exif.webp` have the same exif, and `text.png` has its creation time as text. The `clean` ones
+//! are the same images without any of that.
+
+use lemmy_server::media::has_metadata;
+use std::fs;
+
+fn image(name: &str) -> Vec<u8> {
+  fs::read(format!(
+    "{}/tests/images/{}",
+    env!("CARGO_MANIFEST_DIR"),
+    name
+  ))
+  .unwrap()
+}
+
+#[test]
+fn test_clean_images() {
+  for name in &["clean.jpg", "clean.png", "clean.webp"] {
+    assert!(!has_metadata(&image(name)), "{} has no metadata", name);
+  }
+}
+
+#[test]
+fn test_images_with_metadata() {
+  for name in &["gps.jpg", "xmp.jpg", "exif.png", "text.png", "exif.webp"] {
+    assert!(has_metadata(&image(name)), "{} has metadata", name);
+  }
+}
+
+#[test]
+fn test_broken_images() {
+  // Cut off anywhere, they're read as far as they go
+  let gps = image("gps.jpg");
+  for length in 0..gps.len() {
+    has_metadata(&gps[..length]);
+  }
+  assert!(has_metadata(&gps[..100]));
+  assert!(!has_metadata(b"GIF89a"));
+  assert!(!has_metadata(&[]));
+}
@@ -0,0 +1,363 @@
+//! Throwaway, in-process lemmy instances for end-to-end tests.
+//!
+//! Every `TestInstance` gets its own freshly migrated database on the postgres server from the
+//! config (the same one the unit tests use), which is dropped again when the instance goes out of
+//! scope, and its own settings, with its hostname and federation enabled. Requests are served
+//! in-process, straight through the real route configs, so nothing listens on a port. What the
+//! instances fetch from and deliver to each other goes through a transport that serves it the same
+//! way, with the other instance's routes.
+//!
+//! Settings are global to the process, so an instance's are put in place while it serves a
+//! request, and the tests of a binary run one at a time: each holds a lock for as long as its pair
+//! of instances is alive.
+
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::{header, StatusCode};
+use actix_web::web::Bytes;
+use actix_web::{test, App};
+use diesel::connection::Connection;
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::{PgConnection, RunQueryDsl};
+use failure::{err_msg, Error};
+use lemmy_server::apub::{set_transport, url_host, Transport};
+use lemmy_server::routes::{api, federation};
+use lemmy_server::settings::Settings;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Once, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+embed_migrations!();
+
+static INSTANCE_COUNT: AtomicUsize = AtomicUsize::new(0);
+static INSTALL_TRANSPORT: Once = Once::new();
+
+lazy_static! {
+  /// The config from the files, which every instance's settings start from.
+  static ref BASE_SETTINGS: Settings = Settings::get();
+  static ref SERIAL: Mutex<()> = Mutex::new(());
+  static ref NODES: Mutex<HashMap<String, Node>> = Mutex::new(HashMap::new());
+  /// Objects served in place of an instance's routes, by their ids. These are the activities
+  /// lemmy doesn't serve, for the tests to send them the way another instance would.
+  static ref PUBLISHED: Mutex<HashMap<String, Value>> = Mutex::new(HashMap::new());
+  /// The activities delivered to the instances, with the inboxes they went to.
+  static ref DELIVERIES: Mutex<Vec<(String, Value)>> = Mutex::new(Vec::new());
+}
+
+/// What serves the requests to an instance: its settings and database.
+#[derive(Clone)]
+struct Node {
+  settings: Settings,
+  pool: Pool<ConnectionManager<PgConnection>>,
+}
+
+impl Node {
+  async fn call(&self, req: test::TestRequest) -> ServiceResponse {
+    Settings::set(self.settings.to_owned());
+    let mut app = test::init_service(
+      App::new()
+        .data(self.pool.to_owned())
+        .configure(api::config)
+        .configure(federation::config),
+    )
+    .await;
+    let req = req
+      .header(header::HOST, self.settings.hostname.as_str())
+      .to_request();
+    app.call(req).await.unwrap()
+  }
+}
+
+/// Serves a request to the instance a url is on, from a thread of its own, so it can be made from
+/// anywhere, even while another instance is waiting for the answer. The settings are put back
+/// afterwards, for the request that's waiting.
+fn serve(url: &str, activity: Option<Value>) -> Result<(StatusCode, Bytes), Error> {
+  let host = url_host(url).ok_or_else(|| err_msg(format!("{} isn't https", url)))?;
+  let node = NODES
+    .lock()
+    .unwrap()
+    .get(&host)
+    .cloned()
+    .ok_or_else(|| err_msg(format!("There's no instance at {}", host)))?;
+  let path = url["https://".len() + host.len()..]
+    .split('#')
+    .next()
+    .unwrap_or_default()
+    .to_owned();
+  thread::spawn(move || {
+    let previous = Settings::get();
+    let served = actix_rt::System::new("federation").block_on(async move {
+      let req = match activity {
+        Some(activity) => test::TestRequest::post().uri(&path).set_json(&activity),
+        None => test::TestRequest::get()
+          .uri(&path)
+          .header(header::ACCEPT, "application/activity+json"),
+      };
+      let res = node.call(req).await;
+      (res.status(), test::read_body(res).await)
+    });
+    Settings::set(previous);
+    served
+  })
+  .join()
+  .map_err(|_| err_msg(format!("Serving {} panicked", url)))
+}
+
+/// Connects the instances of the process to each other.
+struct InProcessTransport;
+
+impl Transport for InProcessTransport {
+  fn get(&self, url: &str) -> Result<String, Error> {
+    if let Some(object) = PUBLISHED.lock().unwrap().get(url) {
+      return Ok(object.to_string());
+    }
+    let (status, body) = serve(url, None)?;
+    if !status.is_success() {
+      return Err(err_msg(format!("Fetching {} failed with {}", url, status)));
+    }
+    Ok(String::from_utf8(body.to_vec())?)
+  }
+
+  fn post(&self, inbox: &str, activity: &Value) -> Result<(), Error> {
+    let (status, _) = serve(inbox, Some(activity.to_owned()))?;
+    DELIVERIES
+      .lock()
+      .unwrap()
+      .push((inbox.to_owned(), activity.to_owned()));
+    if !status.is_success() {
+      return Err(err_msg(format!("{} answered with {}", inbox, status)));
+    }
+    Ok(())
+  }
+}
+
+pub struct TestInstance {
+  pub hostname: String,
+  database_name: String,
+  node: Option<Node>,
+  _serial: Rc<MutexGuard<'static, ()>>,
+}
+
+fn database_url(database_name: &str) -> String {
+  let db = &BASE_SETTINGS.database;
+  format!(
+    "postgres://{}:{}@{}:{}/{}",
+    db.user, db.password, db.host, db.port, database_name
+  )
+}
+
+/// A connection to the configured database, which is only used to create and drop the test ones.
+fn maintenance_connection() -> PgConnection {
+  let url = database_url(&BASE_SETTINGS.database.database);
+  PgConnection::establish(&url).unwrap_or_else(|_| panic!("Error connecting to {}", url))
+}
+
+impl TestInstance {
+  fn new(hostname: &str, serial: Rc<MutexGuard<'static, ()>>) -> Self {
+    INSTALL_TRANSPORT.call_once(|| set_transport(Arc::new(InProcessTransport)));
+
+    let database_name = format!(
+      "lemmy_test_{}_{}_{}",
+      hostname.replace(|c: char| !c.is_ascii_alphanumeric(), "_"),
+      std::process::id(),
+      INSTANCE_COUNT.fetch_add(1, Ordering::SeqCst)
+    );
+
+    diesel::sql_query(format!("create database {}", database_name))
+      .execute(&maintenance_connection())
+      .unwrap();
+
+    let manager = ConnectionManager::<PgConnection>::new(database_url(&database_name));
+    let pool = Pool::builder().max_size(4).build(manager).unwrap();
+    embedded_migrations::run(&pool.get().unwrap()).unwrap();
+
+    let mut settings = BASE_SETTINGS.to_owned();
+    settings.hostname = hostname.to_owned();
+    settings.federation_enabled = true;
+    settings.federation.actor_domain = None;
+    let node = Node { settings, pool };
+    NODES
+      .lock()
+      .unwrap()
+      .insert(hostname.to_owned(), node.to_owned());
+
+    TestInstance {
+      hostname: hostname.to_owned(),
+      database_name,
+      node: Some(node),
+      _serial: serial,
+    }
+  }
+
+  /// Two independent instances, as the two ends of a federation test.
+  pub fn pair() -> (TestInstance, TestInstance) {
+    let serial = Rc::new(SERIAL.lock().unwrap_or_else(PoisonError::into_inner));
+    PUBLISHED.lock().unwrap().clear();
+    DELIVERIES.lock().unwrap().clear();
+    (
+      TestInstance::new("alpha.lemmy.test", serial.to_owned()),
+      TestInstance::new("beta.lemmy.test", serial),
+    )
+  }
+
+  /// The url of a path on this instance.
+  pub fn url(&self, path: &str) -> String {
+    format!("https://{}{}", self.hostname, path)
+  }
+
+  /// The activitypub id of a user of this instance.
+  pub fn actor_id(&self, username: &str) -> String {
+    self.url(&format!("/federation/u/{}", username))
+  }
+
+  async fn call(&self, req: test::TestRequest) -> ServiceResponse {
+    self.node.as_ref().unwrap().call(req).await
+  }
+
+  async fn call_json(&self, req: test::TestRequest) -> (StatusCode, Value) {
+    let res = self.call(req).await;
+    let status = res.status();
+    let body = test::read_body(res).await;
+    let json = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    (status, json)
+  }
+
+  /// A raw GET, for looking at headers.
+  pub async fn get_response(&self, path: &str, headers: &[(&str, &str)]) -> ServiceResponse {
+    let mut req = test::TestRequest::get().uri(path);
+    for (name, value) in headers {
+      req = req.header(*name, *value);
+    }
+    self.call(req).await
+  }
+
+  pub async fn get(&self, path: &str) -> (StatusCode, Value) {
+    self.call_json(test::TestRequest::get().uri(path)).await
+  }
+
+  pub async fn post(&self, path: &str, data: Value) -> (StatusCode, Value) {
+    self
+      .call_json(test::TestRequest::post().uri(path).set_json(&data))
+      .await
+  }
+
+  pub async fn put(&self, path: &str, data: Value) -> (StatusCode, Value) {
+    self
+      .call_json(test::TestRequest::put().uri(path).set_json(&data))
+      .await
+  }
+
+  /// Serves an object at its id, as if this instance did. For the objects lemmy doesn't make, like
+  /// PeerTube's videos.
+  pub fn publish(&self, object: &Value) {
+    let id = object["id"].as_str().unwrap();
+    assert_eq!(Some(self.hostname.to_owned()), url_host(id));
+    PUBLISHED
+      .lock()
+      .unwrap()
+      .insert(id.to_owned(), object.to_owned());
+  }
+
+  /// Delivers an activity to another instance's inbox, the way this instance would, for the
+  /// activities lemmy doesn't send itself. It's served at its id here, where the other instance
+  /// fetches it again.
+  pub async fn send(&self, to: &TestInstance, activity: Value) {
+    self.publish(&activity);
+    let (status, res) = to.post("/federation/inbox", activity).await;
+    assert_eq!(StatusCode::ACCEPTED, status, "{}", res);
+  }
+
+  /// Waits for an activity of a type to be delivered to this instance's inbox, and returns it.
+  pub async fn delivered(&self, kind: &str) -> Value {
+    let inbox = self.url("/federation/inbox");
+    for _ in 0..100 {
+      {
+        let mut deliveries = DELIVERIES.lock().unwrap();
+        if let Some(i) = deliveries
+          .iter()
+          .position(|(to, activity)| *to == inbox && activity["type"] == kind)
+        {
+          return deliveries.remove(i).1;
+        }
+      }
+      actix_rt::time::delay_for(Duration::from_millis(50)).await;
+    }
+    panic!("No {} was delivered to {}", kind, self.hostname);
+  }
+
+  /// Registers a user, returning their auth token.
+  pub async fn register(&self, username: &str) -> String {
+    self.register_user(username, false).await
+  }
+
+  /// Registers the admin and creates the site, like a fresh install's setup does. Returns the
+  /// admin's auth token.
+  pub async fn setup_site(&self, admin_username: &str) -> String {
+    let jwt = self.register_user(admin_username, true).await;
+    let (status, _) = self
+      .post(
+        "/api/v1/site",
+        json!({
+          "name": self.hostname,
+          "enable_downvotes": true,
+          "open_registration": true,
+          "enable_nsfw": true,
+          "auth": jwt,
+        }),
+      )
+      .await;
+    assert_eq!(StatusCode::OK, status);
+    jwt
+  }
+
+  /// The id of a user here, which for remote users is their local copy's.
+  pub async fn user_id(&self, username: &str) -> i64 {
+    let (status, res) = self
+      .get(&format!(
+        "/api/v1/user?username={}&sort=New&saved_only=false",
+        username
+      ))
+      .await;
+    assert_eq!(StatusCode::OK, status, "{}", res);
+    res["user"]["id"].as_i64().unwrap()
+  }
+
+  async fn register_user(&self, username: &str, admin: bool) -> String {
+    let (status, res) = self
+      .post(
+        "/api/v1/user/register",
+        json!({
+          "username": username,
+          "password": "lemmy_test_password",
+          "password_verify": "lemmy_test_password",
+          "admin": admin,
+          "show_nsfw": false,
+        }),
+      )
+      .await;
+    assert_eq!(StatusCode::OK, status);
+    res["jwt"].as_str().unwrap().to_owned()
+  }
+}
+
+impl Drop for TestInstance {
+  fn drop(&mut self) {
+    NODES.lock().unwrap().remove(&self.hostname);
+    // Close the pool's connections first, postgres won't drop a database that's in use
+    self.node.take();
+
+    let conn = maintenance_connection();
+    diesel::sql_query(format!(
+      "select pg_terminate_backend(pid) from pg_stat_activity where datname = '{}'",
+      self.database_name
+    ))
+    .execute(&conn)
+    .ok();
+    diesel::sql_query(format!("drop database if exists {}", self.database_name))
+      .execute(&conn)
+      .ok();
+  }
+}
@@ -58,6 +58,7 @@ class Index extends Component<any, any> {
                   path={`/post/:id/comment/:comment_id`}
                   component={Post}
                 />
+                <Route path={`/post/:id/:slug`} component={Post} />
                 <Route path={`/post/:id`} component={Post} />
                 <Route
                   path={`/c/:name/data_type/:data_type/sort/:sort/page/:page`}